@@ -0,0 +1,67 @@
+//! Redis command handling on the forwarding path
+//!
+//! `fast_path` splits frames and routes from slices of the client's bytes,
+//! as the proxy does for commands sent as arrays of bulk strings.
+//! `full_parse` builds the whole `RespValue` first, as for any other frame.
+use bytes::{Bytes, BytesMut};
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use puerta::modes::redis::resp::{RespEncoder, RespParser};
+use puerta::modes::redis::RedisProtocolApp;
+
+/// Commands in each benchmarked pipeline
+const PIPELINE_DEPTH: usize = 64;
+
+/// A pipeline of SET commands with `value_size` byte values
+fn pipeline(value_size: usize) -> Bytes {
+    let value = "x".repeat(value_size);
+    let mut buf = BytesMut::new();
+    for i in 0..PIPELINE_DEPTH {
+        let command = RespEncoder::create_command("SET", &[&format!("key:{i}"), &value]);
+        RespEncoder::encode_into(&mut buf, &command);
+    }
+    buf.freeze()
+}
+
+fn bench_forwarding(c: &mut Criterion) {
+    let mut group = c.benchmark_group("resp_forwarding");
+    for value_size in [16, 1024, 16 * 1024] {
+        let input = pipeline(value_size);
+        group.throughput(Throughput::Bytes(input.len() as u64));
+
+        group.bench_with_input(
+            BenchmarkId::new("fast_path", value_size),
+            &input,
+            |b, input| {
+                b.iter(|| {
+                    let mut buf = BytesMut::from(&input[..]);
+                    let mut parser = RespParser::new();
+                    while let Some(raw) = parser.split_frame(&mut buf).unwrap() {
+                        let parts = RespParser::command_parts(&raw).unwrap();
+                        let command = RedisProtocolApp::command_from_parts(parts).unwrap();
+                        black_box((command.slot, raw));
+                    }
+                })
+            },
+        );
+
+        group.bench_with_input(
+            BenchmarkId::new("full_parse", value_size),
+            &input,
+            |b, input| {
+                b.iter(|| {
+                    let mut buf = BytesMut::from(&input[..]);
+                    let mut parser = RespParser::new();
+                    while let Some(raw) = parser.split_frame(&mut buf).unwrap() {
+                        let value = RespParser::parse_frame(&raw).unwrap();
+                        let command = RedisProtocolApp::command_from_value(value).unwrap();
+                        black_box((command.slot, raw));
+                    }
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_forwarding);
+criterion_main!(benches);
@@ -0,0 +1,40 @@
+//! Feed arbitrary bytes to one decoder in two reads
+//!
+//! The first byte picks where the input is split. Decoding the pieces as
+//! they arrive must give the same values as parsing the whole input at
+//! once, as it does for a client whose command spans several TCP reads.
+#![no_main]
+
+use bytes::BytesMut;
+use libfuzzer_sys::fuzz_target;
+use puerta::modes::redis::resp::RespParser;
+use tokio_util::codec::Decoder;
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&split, input)) = data.split_first() else {
+        return;
+    };
+    let split = split as usize % (input.len() + 1);
+
+    let mut whole = BytesMut::from(input);
+    let expected = RespParser::parse_commands(&mut whole);
+
+    let mut parser = RespParser::new();
+    let mut buf = BytesMut::new();
+    let mut values = Vec::new();
+    for piece in [&input[..split], &input[split..]] {
+        buf.extend_from_slice(piece);
+        loop {
+            match parser.decode(&mut buf) {
+                Ok(Some(value)) => values.push(value),
+                Ok(None) => break,
+                Err(_) => {
+                    assert!(expected.is_err());
+                    return;
+                }
+            }
+        }
+    }
+    assert_eq!(values, expected.expect("whole input failed to parse"));
+    assert_eq!(buf, whole);
+});
@@ -0,0 +1,22 @@
+//! Parse arbitrary bytes as pipelined RESP values
+//!
+//! The parser must never panic, and every value it accepts must survive an
+//! encode / parse round trip unchanged.
+#![no_main]
+
+use bytes::BytesMut;
+use libfuzzer_sys::fuzz_target;
+use puerta::modes::redis::resp::{RespEncoder, RespParser};
+
+fuzz_target!(|data: &[u8]| {
+    let mut buf = BytesMut::from(data);
+    let Ok(values) = RespParser::parse_commands(&mut buf) else {
+        return;
+    };
+    for value in values {
+        let mut encoded = BytesMut::from(&RespEncoder::encode(&value)[..]);
+        let reparsed = RespParser::parse(&mut encoded).expect("encoded value failed to parse");
+        assert_eq!(reparsed, Some(value));
+        assert!(encoded.is_empty());
+    }
+});
@@ -0,0 +1,247 @@
+/// Client IP access control
+///
+/// Connections are checked against CIDR allow and deny lists as they are
+/// accepted. A deny match always rejects; when the allow list is non-empty a
+/// client must also match it. The lists are swapped atomically when the
+/// configuration is reloaded, so existing connections are not affected.
+use crate::config::{AclConfig, Config};
+use crate::metrics::Counter;
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use tokio::sync::watch;
+
+/// An IPv4 or IPv6 network such as `10.0.0.0/8`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix_len: u8,
+}
+
+impl Cidr {
+    /// Check whether `ip` lies in this network
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.network, ip.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                u32::from(ip) & v4_mask(self.prefix_len) == u32::from(network)
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                u128::from(ip) & v6_mask(self.prefix_len) == u128::from(network)
+            }
+            _ => false,
+        }
+    }
+}
+
+fn v4_mask(prefix_len: u8) -> u32 {
+    u32::MAX.checked_shl(32 - prefix_len as u32).unwrap_or(0)
+}
+
+fn v6_mask(prefix_len: u8) -> u128 {
+    u128::MAX.checked_shl(128 - prefix_len as u32).unwrap_or(0)
+}
+
+impl FromStr for Cidr {
+    type Err = String;
+
+    /// Parse `addr/prefix`, or a bare address as a single host
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix_len) = match s.trim().split_once('/') {
+            Some((addr, prefix_len)) => (addr, Some(prefix_len)),
+            None => (s.trim(), None),
+        };
+        let addr = addr
+            .parse::<IpAddr>()
+            .map_err(|_| format!("invalid address in CIDR {s}"))?
+            .to_canonical();
+        let max_len = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix_len = match prefix_len {
+            Some(prefix_len) => prefix_len
+                .parse::<u8>()
+                .ok()
+                .filter(|len| *len <= max_len)
+                .ok_or_else(|| format!("invalid prefix length in CIDR {s}"))?,
+            None => max_len,
+        };
+
+        // Keep only the network bits so `contains` is a single comparison
+        let network = match addr {
+            IpAddr::V4(addr) => IpAddr::V4((u32::from(addr) & v4_mask(prefix_len)).into()),
+            IpAddr::V6(addr) => IpAddr::V6((u128::from(addr) & v6_mask(prefix_len)).into()),
+        };
+        Ok(Self {
+            network,
+            prefix_len,
+        })
+    }
+}
+
+/// Parsed allow and deny lists
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AclRules {
+    allow: Vec<Cidr>,
+    deny: Vec<Cidr>,
+}
+
+impl AclRules {
+    pub fn from_config(config: &AclConfig) -> Result<Self, String> {
+        let parse = |entries: &[String]| -> Result<Vec<Cidr>, String> {
+            entries.iter().map(|entry| entry.parse()).collect()
+        };
+        Ok(Self {
+            allow: parse(&config.allow)?,
+            deny: parse(&config.deny)?,
+        })
+    }
+
+    /// Whether a client at `ip` may connect
+    pub fn allows(&self, ip: IpAddr) -> bool {
+        if self.deny.iter().any(|cidr| cidr.contains(ip)) {
+            return false;
+        }
+        self.allow.is_empty() || self.allow.iter().any(|cidr| cidr.contains(ip))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.allow.is_empty() && self.deny.is_empty()
+    }
+}
+
+/// Access control shared by every connection of a proxy
+pub struct AccessControl {
+    rules: RwLock<AclRules>,
+    denied: Arc<Counter>,
+}
+
+impl AccessControl {
+    pub fn new(rules: AclRules) -> Self {
+        Self {
+            rules: RwLock::new(rules),
+            denied: crate::metrics::global().counter(
+                "puerta_connections_denied_total",
+                "Client connections rejected by the IP access control lists",
+            ),
+        }
+    }
+
+    /// Build from the `[acl]` section; without one every client is allowed
+    pub fn from_config(config: Option<&AclConfig>) -> Result<Self, String> {
+        let rules = match config {
+            Some(config) => AclRules::from_config(config)?,
+            None => AclRules::default(),
+        };
+        Ok(Self::new(rules))
+    }
+
+    /// Check a client address, counting denied connections
+    ///
+    /// Addresses that cannot be parsed are only allowed while there are no
+    /// rules at all.
+    pub fn check(&self, client_addr: &str) -> bool {
+        let rules = self.rules.read().unwrap();
+        let allowed = match client_addr.parse::<std::net::SocketAddr>() {
+            Ok(addr) => rules.allows(addr.ip()),
+            Err(_) => rules.is_empty(),
+        };
+        if !allowed {
+            self.denied.inc();
+        }
+        allowed
+    }
+
+    /// Replace the allow and deny lists
+    pub fn set_rules(&self, rules: AclRules) {
+        *self.rules.write().unwrap() = rules;
+    }
+
+    /// Number of connections denied so far
+    pub fn denied_count(&self) -> u64 {
+        self.denied.get()
+    }
+
+    /// Apply `[acl]` changes published by the configuration reloader
+    pub fn spawn_reload_watcher(self: &Arc<Self>, mut receiver: watch::Receiver<Arc<Config>>) {
+        let acl = Arc::clone(self);
+        std::thread::spawn(move || {
+            let rt = crate::utils::background_runtime();
+            rt.block_on(async move {
+                while receiver.changed().await.is_ok() {
+                    let config = receiver.borrow_and_update().clone();
+                    let rules = match &config.acl {
+                        Some(acl_config) => AclRules::from_config(acl_config),
+                        None => Ok(AclRules::default()),
+                    };
+                    match rules {
+                        Ok(rules) => {
+                            acl.set_rules(rules);
+                            log::info!("Applied reloaded access control lists");
+                        }
+                        Err(e) => log::error!("Failed to apply reloaded access control lists: {e}"),
+                    }
+                }
+            });
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn acl_config(allow: &[&str], deny: &[&str]) -> AclConfig {
+        AclConfig {
+            allow: allow.iter().map(|s| s.to_string()).collect(),
+            deny: deny.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_cidr_contains() {
+        let cidr: Cidr = "10.1.2.3/8".parse().unwrap();
+        assert!(cidr.contains("10.255.0.1".parse().unwrap()));
+        assert!(!cidr.contains("11.0.0.1".parse().unwrap()));
+        // IPv4-mapped IPv6 clients match IPv4 networks
+        assert!(cidr.contains("::ffff:10.0.0.1".parse().unwrap()));
+
+        let host: Cidr = "192.168.0.7".parse().unwrap();
+        assert!(host.contains("192.168.0.7".parse().unwrap()));
+        assert!(!host.contains("192.168.0.8".parse().unwrap()));
+
+        let v6: Cidr = "2001:db8::/32".parse().unwrap();
+        assert!(v6.contains("2001:db8:1::1".parse().unwrap()));
+        assert!(!v6.contains("10.0.0.1".parse().unwrap()));
+
+        let any: Cidr = "0.0.0.0/0".parse().unwrap();
+        assert!(any.contains("8.8.8.8".parse().unwrap()));
+
+        assert!("10.0.0.0/33".parse::<Cidr>().is_err());
+        assert!("not-an-ip/8".parse::<Cidr>().is_err());
+    }
+
+    #[test]
+    fn test_deny_overrides_allow() {
+        let rules = AclRules::from_config(&acl_config(&["10.0.0.0/8"], &["10.0.0.13/32"])).unwrap();
+        assert!(rules.allows("10.0.0.1".parse().unwrap()));
+        assert!(!rules.allows("10.0.0.13".parse().unwrap()));
+        // A non-empty allow list rejects everything else
+        assert!(!rules.allows("192.168.0.1".parse().unwrap()));
+
+        let deny_only = AclRules::from_config(&acl_config(&[], &["192.168.0.0/16"])).unwrap();
+        assert!(deny_only.allows("10.0.0.1".parse().unwrap()));
+        assert!(!deny_only.allows("192.168.1.1".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_access_control_counts_denials_and_updates() {
+        let acl = AccessControl::from_config(None).unwrap();
+        assert!(acl.check("203.0.113.5:4000"));
+        assert!(acl.check("unknown-1234"));
+
+        let denied = acl.denied_count();
+        acl.set_rules(AclRules::from_config(&acl_config(&["10.0.0.0/8"], &[])).unwrap());
+        assert!(acl.check("10.0.0.1:4000"));
+        assert!(!acl.check("203.0.113.5:4000"));
+        assert!(!acl.check("unknown-1234"));
+        assert!(acl.denied_count() >= denied + 2);
+    }
+}
@@ -0,0 +1,197 @@
+/// Dry-run routing explanations
+///
+/// `POST /explain` tells where a request would be routed without sending
+/// anything or changing any state, to debug "why did my request go there".
+/// Given a Redis `key`, each Redis instance reports its hash tag, slot and
+/// the node serving the slot; given a `client_ip`, each MongoDB instance
+/// reports the affinity rule, read preference route and sessions deciding
+/// the client's mongos.
+use super::sessions::SessionInfo;
+use super::{AdminResponse, AdminRouter};
+use crate::config::BalanceStrategy;
+use crate::core::connections::ConnectionInfo;
+use crate::modes::redis::slots::SlotMigration;
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use std::sync::Arc;
+
+/// What to explain the routing of
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExplainRequest {
+    /// Redis key
+    #[serde(default)]
+    pub key: Option<String>,
+    /// MongoDB client address
+    #[serde(default)]
+    pub client_ip: Option<IpAddr>,
+}
+
+/// Routing of one proxy instance
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InstanceExplanation {
+    pub name: String,
+    /// `mongodb` or `redis`
+    pub mode: String,
+    #[serde(flatten)]
+    pub route: Route,
+}
+
+/// How an instance routes the request
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Route {
+    Key(KeyRoute),
+    Client(ClientRoute),
+}
+
+/// Where a Redis key is routed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KeyRoute {
+    pub key: String,
+    /// The part of the key hashed, when it has a `{...}` hash tag
+    pub hash_tag: Option<String>,
+    pub slot: u16,
+    /// Master serving the slot, `None` while the slot is uncovered
+    pub node: Option<String>,
+    /// Replicas of the master, read from with `read_from_replicas`
+    pub replicas: Vec<String>,
+    /// Resharding of the slot in progress
+    pub migration: Option<SlotMigration>,
+}
+
+/// What decides the mongos of a MongoDB client
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClientRoute {
+    pub client_ip: IpAddr,
+    /// Mongos endpoints of the first affinity rule matching the client
+    pub affinity_rule: Option<Vec<String>>,
+    /// Mongos endpoints of the read preference route the client is kept to
+    pub read_preference_route: Option<Vec<String>>,
+    pub balance_strategy: BalanceStrategy,
+    /// How a new session of the client picks its mongos: `affinity_rule`,
+    /// or `balanced` by the balance strategy
+    pub decision: String,
+    /// Mongos the affinity rule pins the client to
+    pub backend: Option<String>,
+    /// Sessions identified by the client's socket address; those identified
+    /// by their handshake are listed by `GET /mongodb/sessions`
+    pub sessions: Vec<SessionInfo>,
+    /// Connections from the client open now, with their mongos
+    pub connections: Vec<ConnectionInfo>,
+}
+
+/// Explains the routing of one proxy instance, `None` when the request
+/// names nothing it routes by
+pub type ExplainSource =
+    Arc<dyn Fn(ExplainRequest) -> BoxFuture<'static, Option<InstanceExplanation>> + Send + Sync>;
+
+/// Collects the routing explanations of every proxy instance
+#[derive(Default)]
+pub struct Explainer {
+    sources: Vec<ExplainSource>,
+}
+
+impl Explainer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Explain an instance's routing, in the order added
+    pub fn add_source(&mut self, source: ExplainSource) {
+        self.sources.push(source);
+    }
+
+    /// Explanations of every instance routing by what `request` names
+    pub async fn explain(&self, request: ExplainRequest) -> Vec<InstanceExplanation> {
+        futures::future::join_all(self.sources.iter().map(|source| source(request.clone())))
+            .await
+            .into_iter()
+            .flatten()
+            .collect()
+    }
+
+    /// Register the `POST /explain` admin endpoint, taking a JSON object
+    /// with a `key`, a `client_ip` or both; as a dry run, it is served even
+    /// when changes are switched off
+    pub fn register_admin_routes(self: &Arc<Self>, router: AdminRouter) -> AdminRouter {
+        let explainer = Arc::clone(self);
+        router.safe_route("POST", "/explain", move |request| {
+            let explainer = Arc::clone(&explainer);
+            async move {
+                let request = match serde_json::from_slice::<ExplainRequest>(&request.body) {
+                    Ok(request) if request.key.is_some() || request.client_ip.is_some() => request,
+                    Ok(_) => return AdminResponse::error(400, "expected a key or a client_ip"),
+                    Err(e) => return AdminResponse::error(400, format!("invalid explain request: {e}")),
+                };
+                match serde_json::to_value(explainer.explain(request).await) {
+                    Ok(instances) => AdminResponse::ok(serde_json::json!({ "instances": instances })),
+                    Err(e) => AdminResponse::error(500, e.to_string()),
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::admin::AdminRequest;
+
+    fn request(body: &str) -> AdminRequest {
+        AdminRequest {
+            method: "POST".to_string(),
+            path: "/explain".to_string(),
+            body: body.as_bytes().to_vec(),
+            ..AdminRequest::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_explain_endpoint() {
+        let mut explainer = Explainer::new();
+        explainer.add_source(Arc::new(|request: ExplainRequest| {
+            Box::pin(async move {
+                let key = request.key?;
+                Some(InstanceExplanation {
+                    name: "cache".to_string(),
+                    mode: "redis".to_string(),
+                    route: Route::Key(KeyRoute {
+                        key,
+                        hash_tag: None,
+                        slot: 12182,
+                        node: Some("10.0.0.1:7000".to_string()),
+                        replicas: Vec::new(),
+                        migration: None,
+                    }),
+                })
+            })
+        }));
+        let router = Arc::new(explainer).register_admin_routes(AdminRouter::new());
+
+        let response = router.handle(request(r#"{"key": "foo"}"#)).await;
+        assert_eq!(response.status, 200);
+        let instance = &response.body["instances"][0];
+        assert_eq!(instance["name"], "cache");
+        assert_eq!(instance["slot"], 12182);
+        assert_eq!(instance["node"], "10.0.0.1:7000");
+
+        // Instances not routing by what the request names are left out
+        let response = router.handle(request(r#"{"client_ip": "10.1.2.3"}"#)).await;
+        assert_eq!(response.body["instances"], serde_json::json!([]));
+
+        for body in ["", "{}", r#"{"client_ip": "not an ip"}"#] {
+            assert_eq!(router.handle(request(body)).await.status, 400, "{body}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_explain_served_without_token_beyond_loopback() {
+        let router = Arc::new(Explainer::new())
+            .register_admin_routes(AdminRouter::new())
+            .guard_exposure("0.0.0.0:9090");
+
+        let response = router.handle(request(r#"{"key": "foo"}"#)).await;
+        assert_eq!(response.status, 200);
+    }
+}
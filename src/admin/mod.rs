@@ -0,0 +1,627 @@
+/// HTTP admin API
+///
+/// A small HTTP/1.1 server bound to `[admin] listen_addr` for operators and
+/// tooling. Proxy modes register JSON endpoints on an `AdminRouter`; apart
+/// from the Prometheus `/metrics` text, response bodies are JSON and each
+/// connection serves a single request. With `[admin] token` set, requests
+/// without `Authorization: Bearer <token>` are refused with 401. Without a
+/// token, an API listening beyond loopback only serves `GET` requests and
+/// the dry runs registered with `safe_route`.
+pub mod explain;
+pub mod sessions;
+pub mod status;
+
+use futures::future::BoxFuture;
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Largest request head plus body the admin server accepts
+const MAX_REQUEST_SIZE: usize = 64 * 1024;
+
+/// How long a client may take to send a whole request
+const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A parsed admin request
+#[derive(Debug, Clone, Default)]
+pub struct AdminRequest {
+    pub method: String,
+    pub path: String,
+    pub query: HashMap<String, String>,
+    /// Value of the `Authorization` header
+    pub authorization: Option<String>,
+    pub body: Vec<u8>,
+}
+
+impl AdminRequest {
+    /// Parse a query parameter, `None` when absent or malformed
+    pub fn query_param<T: std::str::FromStr>(&self, name: &str) -> Option<T> {
+        self.query.get(name).and_then(|value| value.parse().ok())
+    }
+
+    /// The last path segment, the value of a `{name}` route placeholder
+    pub fn last_segment(&self) -> &str {
+        self.path.rsplit('/').next().unwrap_or_default()
+    }
+}
+
+/// Content type of JSON responses
+const JSON_CONTENT_TYPE: &str = "application/json";
+
+/// An admin response
+#[derive(Debug, Clone, PartialEq)]
+pub struct AdminResponse {
+    pub status: u16,
+    pub content_type: &'static str,
+    /// JSON body, or the raw text for non-JSON content types
+    pub body: Value,
+}
+
+impl AdminResponse {
+    pub fn ok(body: Value) -> Self {
+        Self {
+            status: 200,
+            content_type: JSON_CONTENT_TYPE,
+            body,
+        }
+    }
+
+    /// A 200 response with a plain text body
+    pub fn text(content_type: &'static str, body: String) -> Self {
+        Self {
+            status: 200,
+            content_type,
+            body: Value::String(body),
+        }
+    }
+
+    pub fn error(status: u16, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            content_type: JSON_CONTENT_TYPE,
+            body: json!({ "error": message.into() }),
+        }
+    }
+
+    pub fn not_found() -> Self {
+        Self::error(404, "not found")
+    }
+
+    fn reason(&self) -> &'static str {
+        match self.status {
+            200 => "OK",
+            400 => "Bad Request",
+            401 => "Unauthorized",
+            404 => "Not Found",
+            403 => "Forbidden",
+            405 => "Method Not Allowed",
+            408 => "Request Timeout",
+            409 => "Conflict",
+            413 => "Payload Too Large",
+            503 => "Service Unavailable",
+            _ => "Internal Server Error",
+        }
+    }
+}
+
+type Handler = Arc<dyn Fn(AdminRequest) -> BoxFuture<'static, AdminResponse> + Send + Sync>;
+
+/// Maps `(method, path)` to handlers
+#[derive(Clone, Default)]
+pub struct AdminRouter {
+    routes: HashMap<(String, String), Handler>,
+    /// Routes without side effects whatever their method
+    safe_routes: HashSet<(String, String)>,
+    /// Bearer token requests must carry
+    token: Option<String>,
+    /// Time allowed to read a request, `DEFAULT_REQUEST_TIMEOUT` when unset
+    request_timeout: Option<Duration>,
+    /// Refuse every method but `GET` outside `safe_routes`, set when the API
+    /// is reachable beyond loopback without a token
+    read_only: bool,
+}
+
+impl AdminRouter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a handler for `method` requests to `path`, whose last
+    /// segment may be a `{name}` placeholder matching any single segment
+    pub fn route<F, Fut>(mut self, method: &str, path: &str, handler: F) -> Self
+    where
+        F: Fn(AdminRequest) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = AdminResponse> + Send + 'static,
+    {
+        let handler: Handler = Arc::new(move |request| Box::pin(handler(request)));
+        self.routes
+            .insert((method.to_string(), path.to_string()), handler);
+        self
+    }
+
+    /// Register a handler like `route` for a request without side effects,
+    /// served even when changes are switched off
+    pub fn safe_route<F, Fut>(mut self, method: &str, path: &str, handler: F) -> Self
+    where
+        F: Fn(AdminRequest) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = AdminResponse> + Send + 'static,
+    {
+        self.safe_routes
+            .insert((method.to_string(), path.to_string()));
+        self.route(method, path, handler)
+    }
+
+    /// Refuse requests that do not carry `token` as a bearer token
+    pub fn with_token(mut self, token: Option<String>) -> Self {
+        self.token = token;
+        self
+    }
+
+    /// Answer 408 to clients that take longer than `timeout` to send a
+    /// request
+    pub fn with_request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = Some(timeout);
+        self
+    }
+
+    /// Whether `request` carries the bearer token, if one is required
+    fn authorized(&self, request: &AdminRequest) -> bool {
+        let Some(token) = &self.token else {
+            return true;
+        };
+        let presented = request
+            .authorization
+            .as_deref()
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .unwrap_or_default();
+        crate::utils::constant_time_eq(presented.trim().as_bytes(), token.as_bytes())
+    }
+
+    /// Register `GET /metrics` serving the global metrics registry in the
+    /// Prometheus text format
+    pub fn with_metrics(self) -> Self {
+        self.route("GET", "/metrics", |_| async {
+            AdminResponse::text(
+                "text/plain; version=0.0.4",
+                crate::metrics::global().render_prometheus(),
+            )
+        })
+    }
+
+    /// Dispatch a request to its handler
+    ///
+    /// `GET /` lists the registered routes.
+    pub async fn handle(&self, request: AdminRequest) -> AdminResponse {
+        if !self.authorized(&request) {
+            return AdminResponse::error(401, "missing or invalid bearer token");
+        }
+        let key = (request.method.clone(), request.path.clone());
+        if self.read_only && request.method != "GET" && !self.safe_routes.contains(&key) {
+            return AdminResponse::error(
+                403,
+                "set [admin] token to enable changes over the network",
+            );
+        }
+        if let Some(handler) = self.routes.get(&key) {
+            return handler(request).await;
+        }
+        let placeholder_route = self
+            .routes
+            .iter()
+            .find(|((method, path), _)| *method == request.method && path_matches(path, &request.path));
+        if let Some((_, handler)) = placeholder_route {
+            return handler(request).await;
+        }
+
+        if request.path == "/" && request.method == "GET" {
+            let mut routes: Vec<String> = self
+                .routes
+                .keys()
+                .map(|(method, path)| format!("{method} {path}"))
+                .collect();
+            routes.sort();
+            return AdminResponse::ok(json!({ "routes": routes }));
+        }
+
+        if self.routes.keys().any(|(_, path)| path_matches(path, &request.path)) {
+            return AdminResponse::error(405, "method not allowed");
+        }
+        AdminResponse::not_found()
+    }
+
+    /// Serve the admin API on `listen_addr` from a background thread
+    pub fn spawn(self, listen_addr: String) {
+        let router = self.guard_exposure(&listen_addr);
+        std::thread::spawn(move || {
+            let rt = crate::utils::background_runtime();
+            rt.block_on(async move {
+                let listener = match TcpListener::bind(&listen_addr).await {
+                    Ok(listener) => listener,
+                    Err(e) => {
+                        log::error!("Failed to bind admin API on {listen_addr}: {e}");
+                        return;
+                    }
+                };
+                log::info!("Admin API listening on: {listen_addr}");
+                router.serve(listener).await;
+            });
+        });
+    }
+
+    /// Switch off the routes that change state when the API listens beyond
+    /// loopback on `listen_addr` without a token
+    fn guard_exposure(mut self, listen_addr: &str) -> Self {
+        let loopback = listen_addr
+            .parse::<std::net::SocketAddr>()
+            .is_ok_and(|addr| addr.ip().is_loopback());
+        if self.token.is_none() && !loopback {
+            log::warn!(
+                "Admin API on {listen_addr} has no [admin] token; backend and drain changes are disabled"
+            );
+            self.read_only = true;
+        }
+        self
+    }
+
+    /// Accept and answer admin connections until the listener fails
+    pub async fn serve(self, listener: TcpListener) {
+        let router = Arc::new(self);
+        loop {
+            let (stream, peer) = match listener.accept().await {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    log::error!("Admin API accept failed: {e}");
+                    return;
+                }
+            };
+            let router = Arc::clone(&router);
+            tokio::spawn(async move {
+                if let Err(e) = router.serve_connection(stream).await {
+                    log::debug!("Admin API connection from {peer} failed: {e}");
+                }
+            });
+        }
+    }
+
+    async fn serve_connection(&self, mut stream: TcpStream) -> std::io::Result<()> {
+        let timeout = self.request_timeout.unwrap_or(DEFAULT_REQUEST_TIMEOUT);
+        let response = match tokio::time::timeout(timeout, read_request(&mut stream)).await {
+            Ok(Ok(Ok(request))) => {
+                log::debug!("Admin API {} {}", request.method, request.path);
+                self.handle(request).await
+            }
+            Ok(Ok(Err(response))) => response,
+            Ok(Err(e)) => return Err(e),
+            Err(_) => AdminResponse::error(408, "request timed out"),
+        };
+
+        let body = match &response.body {
+            Value::String(text) if response.content_type != JSON_CONTENT_TYPE => text.clone(),
+            body => body.to_string(),
+        };
+        let challenge = if response.status == 401 { "WWW-Authenticate: Bearer\r\n" } else { "" };
+        let head = format!(
+            "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n{challenge}Connection: close\r\n\r\n",
+            response.status,
+            response.reason(),
+            response.content_type,
+            body.len()
+        );
+        stream.write_all(head.as_bytes()).await?;
+        stream.write_all(body.as_bytes()).await?;
+        stream.shutdown().await
+    }
+}
+
+/// Whether `path` matches the route `pattern`, whose last segment may be a
+/// `{name}` placeholder
+fn path_matches(pattern: &str, path: &str) -> bool {
+    match pattern.strip_suffix('}').and_then(|pattern| pattern.rsplit_once("/{")) {
+        Some((prefix, _)) => path
+            .strip_prefix(prefix)
+            .and_then(|rest| rest.strip_prefix('/'))
+            .is_some_and(|segment| !segment.is_empty() && !segment.contains('/')),
+        None => pattern == path,
+    }
+}
+
+/// Send a request to the admin API at `admin_addr` (`host:port`), with
+/// the bearer `token` if given, and return its JSON body, for the command
+/// line tools
+pub async fn request(admin_addr: &str, token: Option<&str>, method: &str, path: &str) -> Result<Value, String> {
+    let authorization = token.map(|token| format!("Bearer {token}"));
+    let headers: Vec<(&str, &str)> = authorization
+        .iter()
+        .map(|authorization| ("Authorization", authorization.as_str()))
+        .collect();
+    let body = crate::discovery::http::request(
+        &format!("http://{admin_addr}"),
+        method,
+        path,
+        &headers,
+        None,
+    )
+    .await
+    .map_err(|e| format!("Failed to query admin API at {admin_addr}: {e}"))?;
+    serde_json::from_str(&body).map_err(|e| format!("Invalid response from {admin_addr}: {e}"))
+}
+
+/// Read one HTTP request, or the error response to send instead
+async fn read_request(
+    stream: &mut TcpStream,
+) -> std::io::Result<Result<AdminRequest, AdminResponse>> {
+    let mut buf = Vec::with_capacity(1024);
+    let mut chunk = [0u8; 4096];
+
+    let head_end = loop {
+        if let Some(pos) = buf.windows(4).position(|window| window == b"\r\n\r\n") {
+            break pos;
+        }
+        if buf.len() > MAX_REQUEST_SIZE {
+            return Ok(Err(AdminResponse::error(413, "request too large")));
+        }
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    };
+
+    let Some(mut request) = parse_head(&buf[..head_end]) else {
+        return Ok(Err(AdminResponse::error(400, "malformed request")));
+    };
+
+    let content_length = request.1;
+    let request_size = content_length.checked_add(head_end + 4);
+    if request_size.is_none_or(|size| size > MAX_REQUEST_SIZE) {
+        return Ok(Err(AdminResponse::error(413, "request too large")));
+    }
+    let mut body = buf.split_off(head_end + 4);
+    while body.len() < content_length {
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+        body.extend_from_slice(&chunk[..n]);
+    }
+    body.truncate(content_length);
+    request.0.body = body;
+
+    Ok(Ok(request.0))
+}
+
+/// Parse the request line and headers, returning the request and its
+/// `Content-Length`
+fn parse_head(head: &[u8]) -> Option<(AdminRequest, usize)> {
+    let head = std::str::from_utf8(head).ok()?;
+    let mut lines = head.split("\r\n");
+
+    let mut request_line = lines.next()?.split(' ');
+    let method = request_line.next()?.to_uppercase();
+    let target = request_line.next()?;
+
+    let mut content_length = 0;
+    let mut authorization = None;
+    for line in lines {
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().ok()?;
+            } else if name.trim().eq_ignore_ascii_case("authorization") {
+                authorization = Some(value.trim().to_string());
+            }
+        }
+    }
+
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let query = query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (name.to_string(), value.to_string())
+        })
+        .collect();
+
+    Some((
+        AdminRequest {
+            method,
+            path: path.to_string(),
+            query,
+            authorization,
+            body: Vec::new(),
+        },
+        content_length,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(method: &str, path: &str) -> AdminRequest {
+        AdminRequest {
+            method: method.to_string(),
+            path: path.to_string(),
+            ..AdminRequest::default()
+        }
+    }
+
+    #[test]
+    fn test_parse_head() {
+        let (request, content_length) = parse_head(
+            b"POST /redis/slowlog/reset?count=10&verbose HTTP/1.1\r\nHost: localhost\r\nContent-Length: 5",
+        )
+        .unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/redis/slowlog/reset");
+        assert_eq!(request.query_param::<usize>("count"), Some(10));
+        assert_eq!(request.query.get("verbose").map(String::as_str), Some(""));
+        assert_eq!(content_length, 5);
+
+        assert!(parse_head(b"GARBAGE").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_router_dispatch() {
+        let router = AdminRouter::new()
+            .route("GET", "/ping", |_| async { AdminResponse::ok(json!("pong")) });
+
+        assert_eq!(
+            router.handle(request("GET", "/ping")).await,
+            AdminResponse::ok(json!("pong"))
+        );
+        assert_eq!(router.handle(request("POST", "/ping")).await.status, 405);
+        assert_eq!(router.handle(request("GET", "/missing")).await.status, 404);
+
+        let index = router.handle(request("GET", "/")).await;
+        assert_eq!(index.body["routes"], json!(["GET /ping"]));
+    }
+
+    #[tokio::test]
+    async fn test_router_placeholder_segment() {
+        let router = AdminRouter::new().route("DELETE", "/backends/{addr}", |request| async move {
+            AdminResponse::ok(json!(request.last_segment()))
+        });
+
+        let response = router.handle(request("DELETE", "/backends/10.0.1.5:27017")).await;
+        assert_eq!(response, AdminResponse::ok(json!("10.0.1.5:27017")));
+        assert_eq!(router.handle(request("GET", "/backends/10.0.1.5:27017")).await.status, 405);
+        // The placeholder stands for exactly one segment
+        assert_eq!(router.handle(request("DELETE", "/backends")).await.status, 404);
+        assert_eq!(router.handle(request("DELETE", "/backends/")).await.status, 404);
+        assert_eq!(router.handle(request("DELETE", "/backends/a/b")).await.status, 404);
+    }
+
+    #[tokio::test]
+    async fn test_router_requires_token() {
+        let router = AdminRouter::new()
+            .with_token(Some("s3cret".to_string()))
+            .route("GET", "/ping", |_| async { AdminResponse::ok(json!("pong")) });
+        let with_authorization = |authorization: &str| AdminRequest {
+            authorization: Some(authorization.to_string()),
+            ..request("GET", "/ping")
+        };
+
+        assert_eq!(router.handle(request("GET", "/ping")).await.status, 401);
+        assert_eq!(router.handle(with_authorization("Bearer wrong")).await.status, 401);
+        assert_eq!(router.handle(with_authorization("s3cret")).await.status, 401);
+        assert_eq!(router.handle(with_authorization("Bearer s3cret")).await.status, 200);
+        // The index is protected too
+        assert_eq!(router.handle(request("GET", "/")).await.status, 401);
+    }
+
+    #[tokio::test]
+    async fn test_exposed_router_without_token_is_read_only() {
+        let router = AdminRouter::new()
+            .route("GET", "/backends", |_| async { AdminResponse::ok(json!([])) })
+            .route("POST", "/backends", |_| async { AdminResponse::ok(json!("added")) })
+            .safe_route("POST", "/explain", |_| async { AdminResponse::ok(json!("explained")) });
+
+        let exposed = router.clone().guard_exposure("0.0.0.0:9090");
+        assert_eq!(exposed.handle(request("POST", "/backends")).await.status, 403);
+        assert_eq!(exposed.handle(request("GET", "/backends")).await.status, 200);
+        // Dry runs have no side effects
+        assert_eq!(exposed.handle(request("POST", "/explain")).await.status, 200);
+
+        let loopback = router.clone().guard_exposure("127.0.0.1:9090");
+        assert_eq!(loopback.handle(request("POST", "/backends")).await.status, 200);
+
+        let with_token = router
+            .with_token(Some("s3cret".to_string()))
+            .guard_exposure("0.0.0.0:9090");
+        let authorized = AdminRequest {
+            authorization: Some("Bearer s3cret".to_string()),
+            ..request("POST", "/backends")
+        };
+        assert_eq!(with_token.handle(authorized).await.status, 200);
+    }
+
+    #[tokio::test]
+    async fn test_serve_over_http() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let router = AdminRouter::new().route("GET", "/ping", |request| async move {
+            AdminResponse::ok(json!({ "n": request.query_param::<u32>("n") }))
+        });
+        tokio::spawn(router.serve(listener));
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"GET /ping?n=7 HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with("{\"n\":7}"));
+    }
+
+    /// Send `raw` to a fresh admin server and return the response
+    async fn exchange(router: AdminRouter, raw: &[u8]) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(router.serve(listener));
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(raw).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn test_oversized_content_length_is_rejected() {
+        let router = AdminRouter::new()
+            .route("POST", "/ping", |_| async { AdminResponse::ok(json!("pong")) });
+
+        let response = exchange(
+            router.clone(),
+            b"POST /ping HTTP/1.1\r\nContent-Length: 18446744073709551615\r\n\r\n",
+        )
+        .await;
+        assert!(response.starts_with("HTTP/1.1 413 Payload Too Large\r\n"));
+
+        let too_large =
+            format!("POST /ping HTTP/1.1\r\nContent-Length: {MAX_REQUEST_SIZE}\r\n\r\n");
+        let response = exchange(router, too_large.as_bytes()).await;
+        assert!(response.starts_with("HTTP/1.1 413 Payload Too Large\r\n"));
+    }
+
+    #[tokio::test]
+    async fn test_slow_request_times_out() {
+        let router = AdminRouter::new()
+            .with_request_timeout(Duration::from_millis(50))
+            .route("GET", "/ping", |_| async { AdminResponse::ok(json!("pong")) });
+
+        // The head never ends
+        let response = exchange(router, b"GET /ping HTTP/1.1\r\nHost: loc").await;
+        assert!(response.starts_with("HTTP/1.1 408 Request Timeout\r\n"));
+    }
+
+    #[tokio::test]
+    async fn test_metrics_endpoint_serves_text() {
+        crate::metrics::global()
+            .counter("puerta_admin_test_total", "Admin test counter")
+            .inc();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(AdminRouter::new().with_metrics().serve(listener));
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+
+        assert!(response.contains("Content-Type: text/plain; version=0.0.4\r\n"));
+        assert!(response.contains("\r\n\r\n# HELP"));
+        assert!(response.contains("\npuerta_admin_test_total 1\n"));
+    }
+}
@@ -0,0 +1,138 @@
+/// MongoDB session affinity report
+///
+/// `GET /mongodb/sessions` lists the clients pinned to a mongos, with the
+/// session totals and how they spread across backends. The
+/// `puerta sessions` command fetches the report and prints it as a table.
+use crate::modes::mongodb::affinity::{AffinityStatistics, ClientMetadata};
+use serde::{Deserialize, Serialize};
+use std::fmt::Write as _;
+
+/// A client pinned to a mongos
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionInfo {
+    /// Client identifier, e.g. `socket:10.0.0.5:53122`
+    pub client: String,
+    pub backend_id: String,
+    /// Seconds since the client was pinned
+    pub age_secs: u64,
+    /// Seconds since the client's last activity
+    pub idle_secs: u64,
+    /// Connections routed through the session
+    pub connections: u64,
+    /// Application and driver the client's handshake named
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<ClientMetadata>,
+}
+
+/// Active sessions of the first MongoDB instance
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionsReport {
+    pub statistics: AffinityStatistics,
+    /// Sessions, oldest first, restricted to a backend when requested
+    pub sessions: Vec<SessionInfo>,
+}
+
+/// Fetch the sessions from the admin API at `admin_addr` (`host:port`),
+/// only those pinned to `backend` if given
+pub async fn fetch(admin_addr: &str, token: Option<&str>, backend: Option<&str>) -> Result<SessionsReport, String> {
+    let path = match backend {
+        Some(backend) => format!("/mongodb/sessions?backend={backend}"),
+        None => "/mongodb/sessions".to_string(),
+    };
+    let body = super::request(admin_addr, token, "GET", &path).await?;
+    serde_json::from_value(body).map_err(|e| format!("Invalid sessions from {admin_addr}: {e}"))
+}
+
+/// Render a sessions report as human-readable tables
+pub fn render_table(report: &SessionsReport) -> String {
+    let mut out = String::new();
+    let statistics = &report.statistics;
+    let _ = writeln!(
+        out,
+        "{} sessions, {} connections",
+        statistics.total_sessions, statistics.total_connections
+    );
+    let mut distribution: Vec<_> = statistics.backend_distribution.iter().collect();
+    distribution.sort();
+    for (backend, sessions) in distribution {
+        let _ = writeln!(out, "  {backend}: {sessions} sessions");
+    }
+    if report.sessions.is_empty() {
+        return out;
+    }
+
+    let _ = writeln!(out);
+    let rows: Vec<[String; 5]> = report
+        .sessions
+        .iter()
+        .map(|session| {
+            [
+                session.client.clone(),
+                session.backend_id.clone(),
+                super::status::format_uptime(session.age_secs),
+                super::status::format_uptime(session.idle_secs),
+                session.connections.to_string(),
+            ]
+        })
+        .collect();
+    let header = ["CLIENT", "BACKEND", "AGE", "IDLE", "CONNECTIONS"];
+    let widths: Vec<usize> = (0..header.len())
+        .map(|column| {
+            rows.iter()
+                .map(|row| row[column].len())
+                .chain([header[column].len()])
+                .max()
+                .unwrap_or_default()
+        })
+        .collect();
+    for row in std::iter::once(header.map(String::from)).chain(rows) {
+        let line: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{cell:<width$}"))
+            .collect();
+        let _ = writeln!(out, "{}", line.join("  ").trim_end());
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_table() {
+        let report = SessionsReport {
+            statistics: AffinityStatistics {
+                total_sessions: 2,
+                total_connections: 5,
+                backend_distribution: [("mongos-1".to_string(), 1), ("mongos-0".to_string(), 1)]
+                    .into_iter()
+                    .collect(),
+            },
+            sessions: vec![
+                SessionInfo {
+                    client: "socket:10.0.0.5:53122".to_string(),
+                    backend_id: "mongos-0".to_string(),
+                    age_secs: 3725,
+                    idle_secs: 4,
+                    connections: 1,
+                    metadata: None,
+                },
+                SessionInfo {
+                    client: "session:orders".to_string(),
+                    backend_id: "mongos-1".to_string(),
+                    age_secs: 65,
+                    idle_secs: 0,
+                    connections: 4,
+                    metadata: None,
+                },
+            ],
+        };
+        let table = render_table(&report);
+        assert!(table.starts_with("2 sessions, 5 connections\n  mongos-0: 1 sessions\n  mongos-1: 1 sessions\n"));
+        assert!(table.contains("CLIENT                 BACKEND   AGE       IDLE  CONNECTIONS\n"));
+        assert!(table.contains("socket:10.0.0.5:53122  mongos-0  1h 2m 5s  4s    1\n"));
+        assert!(table.contains("session:orders         mongos-1  1m 5s     0s    4\n"));
+    }
+}
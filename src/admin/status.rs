@@ -0,0 +1,273 @@
+/// Proxy status report
+///
+/// `GET /status` gathers backend health, active sessions and Redis slot
+/// coverage from every proxy instance, along with the process uptime. The
+/// `puerta status` command fetches the report and prints it as a table.
+use super::{AdminResponse, AdminRouter};
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use std::fmt::Write as _;
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Number of hash slots in a Redis Cluster
+pub const TOTAL_SLOTS: usize = 16384;
+
+/// Status of the whole process
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProxyStatus {
+    pub version: String,
+    pub uptime_secs: u64,
+    pub instances: Vec<InstanceStatus>,
+}
+
+/// Status of one proxy instance
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InstanceStatus {
+    pub name: String,
+    /// `mongodb` or `redis`
+    pub mode: String,
+    pub listen_addr: String,
+    /// Client connections currently open
+    pub active_sessions: usize,
+    pub backends: Vec<BackendStatus>,
+    /// Slots assigned to a master, Redis mode only
+    pub slot_coverage: Option<SlotCoverageStatus>,
+}
+
+/// Status of one backend
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BackendStatus {
+    pub id: String,
+    pub addr: String,
+    pub healthy: bool,
+    /// Taken out of service by `puerta drain`
+    #[serde(default)]
+    pub draining: bool,
+    /// Client sessions routed to the backend, when tracked
+    pub sessions: Option<usize>,
+    /// Slots the backend serves, Redis mode only
+    pub slots: Option<usize>,
+}
+
+/// Redis slot coverage
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SlotCoverageStatus {
+    pub covered: usize,
+    pub total: usize,
+}
+
+/// Produces the current status of one proxy instance
+pub type StatusSource = Arc<dyn Fn() -> BoxFuture<'static, InstanceStatus> + Send + Sync>;
+
+/// Collects the status of every proxy instance for the admin API
+pub struct StatusReporter {
+    started: Instant,
+    sources: Vec<StatusSource>,
+}
+
+impl StatusReporter {
+    /// Report uptime from now
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            sources: Vec::new(),
+        }
+    }
+
+    /// Report an instance, in the order added
+    pub fn add_source(&mut self, source: StatusSource) {
+        self.sources.push(source);
+    }
+
+    /// Current status of every instance
+    pub async fn status(&self) -> ProxyStatus {
+        let instances =
+            futures::future::join_all(self.sources.iter().map(|source| source())).await;
+        ProxyStatus {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            uptime_secs: self.started.elapsed().as_secs(),
+            instances,
+        }
+    }
+
+    /// Register the `/status` admin endpoint
+    pub fn register_admin_routes(self: &Arc<Self>, router: AdminRouter) -> AdminRouter {
+        let reporter = Arc::clone(self);
+        router.route("GET", "/status", move |_| {
+            let reporter = Arc::clone(&reporter);
+            async move {
+                match serde_json::to_value(reporter.status().await) {
+                    Ok(body) => AdminResponse::ok(body),
+                    Err(e) => AdminResponse::error(500, e.to_string()),
+                }
+            }
+        })
+    }
+}
+
+impl Default for StatusReporter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Fetch the status from the admin API at `admin_addr` (`host:port`)
+pub async fn fetch(admin_addr: &str, token: Option<&str>) -> Result<ProxyStatus, String> {
+    let body = super::request(admin_addr, token, "GET", "/status").await?;
+    serde_json::from_value(body).map_err(|e| format!("Invalid status from {admin_addr}: {e}"))
+}
+
+/// Render a status report as human-readable tables
+pub fn render_table(status: &ProxyStatus) -> String {
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "puerta {}, up {}",
+        status.version,
+        format_uptime(status.uptime_secs)
+    );
+
+    for instance in &status.instances {
+        let healthy = instance.backends.iter().filter(|backend| backend.healthy).count();
+        let _ = writeln!(out);
+        let _ = writeln!(
+            out,
+            "{} ({}) on {}: {} active sessions, {healthy}/{} backends healthy",
+            instance.name,
+            instance.mode,
+            instance.listen_addr,
+            instance.active_sessions,
+            instance.backends.len()
+        );
+        if let Some(coverage) = &instance.slot_coverage {
+            let _ = writeln!(
+                out,
+                "Slot coverage: {}/{} ({:.1}%)",
+                coverage.covered,
+                coverage.total,
+                coverage.covered as f64 * 100.0 / coverage.total as f64
+            );
+        }
+
+        let rows: Vec<[String; 5]> = instance
+            .backends
+            .iter()
+            .map(|backend| {
+                [
+                    backend.id.clone(),
+                    backend.addr.clone(),
+                    match (backend.draining, backend.healthy) {
+                        (true, _) => "draining",
+                        (false, true) => "healthy",
+                        (false, false) => "unhealthy",
+                    }
+                    .to_string(),
+                    backend.sessions.map_or("-".to_string(), |n| n.to_string()),
+                    backend.slots.map_or("-".to_string(), |n| n.to_string()),
+                ]
+            })
+            .collect();
+        let header = ["BACKEND", "ADDRESS", "HEALTH", "SESSIONS", "SLOTS"];
+        let widths: Vec<usize> = (0..header.len())
+            .map(|column| {
+                rows.iter()
+                    .map(|row| row[column].len())
+                    .chain([header[column].len()])
+                    .max()
+                    .unwrap_or_default()
+            })
+            .collect();
+        for row in std::iter::once(header.map(String::from)).chain(rows) {
+            let line: Vec<String> = row
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{cell:<width$}"))
+                .collect();
+            let _ = writeln!(out, "{}", line.join("  ").trim_end());
+        }
+    }
+    out
+}
+
+/// Format seconds as e.g. `3d 4h 5m 6s`
+pub(super) fn format_uptime(secs: u64) -> String {
+    let (days, hours, minutes) = (secs / 86_400, secs / 3600 % 24, secs / 60 % 60);
+    let secs = secs % 60;
+    match (days, hours, minutes) {
+        (0, 0, 0) => format!("{secs}s"),
+        (0, 0, _) => format!("{minutes}m {secs}s"),
+        (0, _, _) => format!("{hours}h {minutes}m {secs}s"),
+        _ => format!("{days}d {hours}h {minutes}m {secs}s"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    fn redis_instance() -> InstanceStatus {
+        InstanceStatus {
+            name: "Redis Cluster Proxy".to_string(),
+            mode: "redis".to_string(),
+            listen_addr: "0.0.0.0:6379".to_string(),
+            active_sessions: 12,
+            backends: vec![
+                BackendStatus {
+                    id: "10.0.0.1:7000".to_string(),
+                    addr: "10.0.0.1:7000".to_string(),
+                    healthy: true,
+                    draining: true,
+                    sessions: None,
+                    slots: Some(8192),
+                },
+                BackendStatus {
+                    id: "10.0.0.2:7000".to_string(),
+                    addr: "10.0.0.2:7000".to_string(),
+                    healthy: false,
+                    draining: false,
+                    sessions: None,
+                    slots: Some(8000),
+                },
+            ],
+            slot_coverage: Some(SlotCoverageStatus {
+                covered: 16192,
+                total: TOTAL_SLOTS,
+            }),
+        }
+    }
+
+    #[test]
+    fn test_render_table() {
+        let status = ProxyStatus {
+            version: "0.1.0".to_string(),
+            uptime_secs: 93_784,
+            instances: vec![redis_instance()],
+        };
+        let table = render_table(&status);
+        assert!(table.starts_with("puerta 0.1.0, up 1d 2h 3m 4s\n"));
+        assert!(table.contains("on 0.0.0.0:6379: 12 active sessions, 1/2 backends healthy\n"));
+        assert!(table.contains("Slot coverage: 16192/16384 (98.8%)\n"));
+        assert!(table.contains("BACKEND        ADDRESS        HEALTH     SESSIONS  SLOTS\n"));
+        assert!(table.contains("10.0.0.1:7000  10.0.0.1:7000  draining   -         8192\n"));
+        assert!(table.contains("10.0.0.2:7000  10.0.0.2:7000  unhealthy  -         8000\n"));
+        assert_eq!(format_uptime(59), "59s");
+        assert_eq!(format_uptime(3600), "1h 0m 0s");
+    }
+
+    #[tokio::test]
+    async fn test_status_endpoint() {
+        let mut reporter = StatusReporter::new();
+        reporter.add_source(Arc::new(|| Box::pin(async { redis_instance() })));
+        let router = Arc::new(reporter).register_admin_routes(AdminRouter::new());
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(router.serve(listener));
+
+        let status = fetch(&addr.to_string(), None).await.unwrap();
+        assert_eq!(status.version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(status.instances, vec![redis_instance()]);
+    }
+}
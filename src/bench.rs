@@ -0,0 +1,508 @@
+/// Load generator behind `puerta bench`
+///
+/// Opens `concurrency` connections to a proxy (or a backend directly) and
+/// sends synthetic requests as fast as replies come back: Redis commands
+/// in RESP, or MongoDB `ping` commands as OP_MSG. Every request's latency
+/// is recorded, and the report gives throughput and latency percentiles.
+///
+/// With `--replay`, the client messages of a capture file are sent instead,
+/// each captured connection on a connection of its own.
+use crate::core::capture::read_capture;
+use crate::modes::mongodb::bson::{DocumentBuilder, Value};
+use crate::modes::mongodb::wire::{more_to_come, op_msg_body, MessageFramer};
+use crate::modes::redis::resp::{RespEncoder, RespParser, RespValue};
+use bytes::{Bytes, BytesMut};
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// Largest MongoDB reply accepted
+const MAX_REPLY_SIZE: usize = 48 * 1024 * 1024;
+
+/// Traffic to generate
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Workload {
+    /// Redis `PING`
+    RedisPing,
+    /// Redis `GET` over the bench keyspace
+    RedisGet,
+    /// Redis `SET` over the bench keyspace
+    RedisSet,
+    /// MongoDB `ping` command
+    MongoDBPing,
+}
+
+impl Workload {
+    /// Workload of `mode` (`redis` or `mongodb`) and `command`, the default
+    /// `ping` when unset
+    pub fn new(mode: &str, command: Option<&str>) -> Result<Self, String> {
+        match (
+            mode,
+            command.unwrap_or("ping").to_ascii_lowercase().as_str(),
+        ) {
+            ("redis", "ping") => Ok(Self::RedisPing),
+            ("redis", "get") => Ok(Self::RedisGet),
+            ("redis", "set") => Ok(Self::RedisSet),
+            ("mongodb", "ping") => Ok(Self::MongoDBPing),
+            ("redis" | "mongodb", command) => {
+                Err(format!("Unsupported {mode} bench command: {command}"))
+            }
+            _ => Err(format!(
+                "Invalid mode: {mode}. Must be 'mongodb' or 'redis'"
+            )),
+        }
+    }
+}
+
+/// Settings of one bench run
+#[derive(Debug, Clone)]
+pub struct BenchConfig {
+    pub workload: Workload,
+    /// Address of the proxy or backend, `host:port`
+    pub target: String,
+    /// Connections sending requests in parallel
+    pub concurrency: usize,
+    /// Requests sent in total
+    pub requests: u64,
+    /// Keys `GET` and `SET` spread over
+    pub keyspace: u64,
+    /// Size of the values `SET` writes
+    pub value_size: usize,
+}
+
+impl BenchConfig {
+    pub fn new(workload: Workload, target: String) -> Self {
+        Self {
+            workload,
+            target,
+            concurrency: 50,
+            requests: 100_000,
+            keyspace: 10_000,
+            value_size: 64,
+        }
+    }
+
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency;
+        self
+    }
+
+    pub fn with_requests(mut self, requests: u64) -> Self {
+        self.requests = requests;
+        self
+    }
+
+    pub fn with_keyspace(mut self, keyspace: u64) -> Self {
+        self.keyspace = keyspace;
+        self
+    }
+
+    pub fn with_value_size(mut self, value_size: usize) -> Self {
+        self.value_size = value_size;
+        self
+    }
+
+    /// Encoded request number `n`
+    fn request(&self, n: u64) -> Bytes {
+        let key = || {
+            Some(Bytes::from(format!(
+                "puerta:bench:{}",
+                n % self.keyspace.max(1)
+            )))
+        };
+        let command = |args: Vec<Option<Bytes>>| {
+            RespEncoder::encode(&RespValue::Array(Some(
+                args.into_iter().map(RespValue::BulkString).collect(),
+            )))
+        };
+        match self.workload {
+            Workload::RedisPing => command(vec![Some(Bytes::from_static(b"PING"))]),
+            Workload::RedisGet => command(vec![Some(Bytes::from_static(b"GET")), key()]),
+            Workload::RedisSet => command(vec![
+                Some(Bytes::from_static(b"SET")),
+                key(),
+                Some(Bytes::from(vec![b'x'; self.value_size])),
+            ]),
+            Workload::MongoDBPing => {
+                let body = DocumentBuilder::new()
+                    .int32("ping", 1)
+                    .string("$db", "admin")
+                    .build();
+                let mut message = ((21 + body.len()) as i32).to_le_bytes().to_vec();
+                // Request id, response to and OP_MSG opcode
+                message.extend_from_slice(&(n as i32).to_le_bytes());
+                message.extend_from_slice(&0i32.to_le_bytes());
+                message.extend_from_slice(&2013i32.to_le_bytes());
+                // No flag bits, then the body section
+                message.extend_from_slice(&[0; 5]);
+                message.extend_from_slice(&body);
+                Bytes::from(message)
+            }
+        }
+    }
+}
+
+/// Results of a bench run
+#[derive(Debug, Clone)]
+pub struct BenchReport {
+    /// Requests answered without an error
+    pub succeeded: u64,
+    /// Requests answered with an error, or lost with their connection
+    pub failed: u64,
+    pub elapsed: Duration,
+    /// Latency of every answered request, sorted
+    latencies: Vec<Duration>,
+}
+
+impl BenchReport {
+    /// Answered requests per second
+    pub fn throughput(&self) -> f64 {
+        self.latencies.len() as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+
+    /// Latency below which `percentile` percent of answered requests fell
+    pub fn percentile(&self, percentile: f64) -> Duration {
+        if self.latencies.is_empty() {
+            return Duration::ZERO;
+        }
+        let rank = (percentile / 100.0 * self.latencies.len() as f64).ceil() as usize;
+        self.latencies[rank.clamp(1, self.latencies.len()) - 1]
+    }
+
+    /// Human-readable summary
+    pub fn render(&self) -> String {
+        let micros = |latency: Duration| latency.as_secs_f64() * 1e6;
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "{} requests in {:.2}s, {} failed",
+            self.succeeded + self.failed,
+            self.elapsed.as_secs_f64(),
+            self.failed
+        );
+        let _ = writeln!(out, "Throughput: {:.0} requests/s", self.throughput());
+        let _ = writeln!(
+            out,
+            "Latency (us): p50 {:.0}, p90 {:.0}, p99 {:.0}, p99.9 {:.0}, max {:.0}",
+            micros(self.percentile(50.0)),
+            micros(self.percentile(90.0)),
+            micros(self.percentile(99.0)),
+            micros(self.percentile(99.9)),
+            micros(self.latencies.last().copied().unwrap_or_default())
+        );
+        out
+    }
+}
+
+/// Latencies and failures seen by one connection
+#[derive(Default)]
+struct WorkerResult {
+    latencies: Vec<Duration>,
+    succeeded: u64,
+    failed: u64,
+}
+
+/// Run the bench until every request is answered or its connection lost
+///
+/// Fails when no connection to the target can be opened.
+pub async fn run(config: BenchConfig) -> Result<BenchReport, String> {
+    let config = Arc::new(config);
+    let mut streams = Vec::with_capacity(config.concurrency);
+    for _ in 0..config.concurrency.max(1) {
+        let stream = TcpStream::connect(&config.target)
+            .await
+            .map_err(|e| format!("Failed to connect to {}: {e}", config.target))?;
+        let _ = stream.set_nodelay(true);
+        streams.push(stream);
+    }
+
+    let next = Arc::new(AtomicU64::new(0));
+    let started = Instant::now();
+    let workers: Vec<_> = streams
+        .into_iter()
+        .map(|stream| tokio::spawn(worker(stream, Arc::clone(&config), Arc::clone(&next))))
+        .collect();
+    let mut report = collect(workers, started).await?;
+
+    // Requests never sent because their connection was lost count as failed
+    let sent = next.load(Ordering::Relaxed).min(config.requests);
+    report.failed += config.requests - sent;
+    Ok(report)
+}
+
+/// Replay the client messages of the `mode` capture file at `path` against
+/// `target`
+///
+/// Captured connections are replayed at once, each on its own connection
+/// sending its messages in order as fast as replies come back. Messages
+/// left out of the capture, such as authentication, are not replayed, so
+/// the target has to accept the commands without them. Fails when the
+/// capture cannot be read or is of another mode.
+pub async fn replay(path: &Path, mode: &str, target: &str) -> Result<BenchReport, String> {
+    let (captured_mode, messages) = read_capture(path)?;
+    if captured_mode != mode {
+        return Err(format!("{} captures {captured_mode} traffic, not {mode}", path.display()));
+    }
+    let mut connections: BTreeMap<u64, Vec<Bytes>> = BTreeMap::new();
+    for captured in messages {
+        connections.entry(captured.connection_id).or_default().push(captured.message);
+    }
+
+    let started = Instant::now();
+    let workers: Vec<_> = connections
+        .into_values()
+        .map(|messages| tokio::spawn(replay_connection(target.to_string(), mode == "mongodb", messages)))
+        .collect();
+    collect(workers, started).await
+}
+
+/// Gather the results of the connections of a run started at `started`
+async fn collect(
+    workers: Vec<tokio::task::JoinHandle<WorkerResult>>,
+    started: Instant,
+) -> Result<BenchReport, String> {
+    let mut latencies = Vec::new();
+    let (mut succeeded, mut failed) = (0, 0);
+    for result in futures::future::join_all(workers).await {
+        let result = result.map_err(|e| format!("Bench connection failed: {e}"))?;
+        latencies.extend(result.latencies);
+        succeeded += result.succeeded;
+        failed += result.failed;
+    }
+    let elapsed = started.elapsed();
+    latencies.sort_unstable();
+    Ok(BenchReport {
+        succeeded,
+        failed,
+        elapsed,
+        latencies,
+    })
+}
+
+/// Send the messages of one captured connection to `target`, counting those
+/// never sent because the connection was lost as failed
+async fn replay_connection(target: String, mongodb: bool, messages: Vec<Bytes>) -> WorkerResult {
+    let mut result = WorkerResult::default();
+    let mut stream = match TcpStream::connect(&target).await {
+        Ok(stream) => stream,
+        Err(e) => {
+            log::warn!("Failed to connect to {target}: {e}");
+            result.failed = messages.len() as u64;
+            return result;
+        }
+    };
+    let _ = stream.set_nodelay(true);
+    let mut buf = BytesMut::with_capacity(16 * 1024);
+    let mut framer = MessageFramer::new(MAX_REPLY_SIZE);
+    for (n, message) in messages.iter().enumerate() {
+        let sent = Instant::now();
+        let reply = match stream.write_all(message).await {
+            // Nothing answers a MongoDB request setting moreToCome
+            Ok(()) if mongodb && more_to_come(message) => {
+                result.succeeded += 1;
+                continue;
+            }
+            Ok(()) if mongodb => read_mongodb_reply(&mut stream, &mut framer).await,
+            Ok(()) => read_redis_reply(&mut stream, &mut buf).await,
+            Err(e) => Err(e.to_string()),
+        };
+        match reply {
+            Ok(ok) => {
+                result.latencies.push(sent.elapsed());
+                if ok {
+                    result.succeeded += 1;
+                } else {
+                    result.failed += 1;
+                }
+            }
+            Err(e) => {
+                log::warn!("Replay connection to {target} failed: {e}");
+                result.failed += (messages.len() - n) as u64;
+                return result;
+            }
+        }
+    }
+    result
+}
+
+/// Send requests one at a time on `stream` until all are taken
+async fn worker(
+    mut stream: TcpStream,
+    config: Arc<BenchConfig>,
+    next: Arc<AtomicU64>,
+) -> WorkerResult {
+    let mut result = WorkerResult::default();
+    let mut buf = BytesMut::with_capacity(16 * 1024);
+    let mut framer = MessageFramer::new(MAX_REPLY_SIZE);
+    loop {
+        let n = next.fetch_add(1, Ordering::Relaxed);
+        if n >= config.requests {
+            return result;
+        }
+        let request = config.request(n);
+        let sent = Instant::now();
+        let reply = match stream.write_all(&request).await {
+            Ok(()) => match config.workload {
+                Workload::MongoDBPing => read_mongodb_reply(&mut stream, &mut framer).await,
+                _ => read_redis_reply(&mut stream, &mut buf).await,
+            },
+            Err(e) => Err(e.to_string()),
+        };
+        match reply {
+            Ok(ok) => {
+                result.latencies.push(sent.elapsed());
+                if ok {
+                    result.succeeded += 1;
+                } else {
+                    result.failed += 1;
+                }
+            }
+            Err(e) => {
+                log::warn!("Bench connection to {} failed: {e}", config.target);
+                result.failed += 1;
+                return result;
+            }
+        }
+    }
+}
+
+/// Read one RESP reply, returning whether it wasn't an error
+async fn read_redis_reply(stream: &mut TcpStream, buf: &mut BytesMut) -> Result<bool, String> {
+    loop {
+        if let Some(reply) = RespParser::parse(buf).map_err(|e| e.to_string())? {
+            return Ok(!matches!(reply, RespValue::Error(_)));
+        }
+        if stream.read_buf(buf).await.map_err(|e| e.to_string())? == 0 {
+            return Err("connection closed".to_string());
+        }
+    }
+}
+
+/// Read one OP_MSG reply, and the replies it announces by setting
+/// `moreToCome`, returning whether the command succeeded
+async fn read_mongodb_reply(
+    stream: &mut TcpStream,
+    framer: &mut MessageFramer,
+) -> Result<bool, String> {
+    let mut chunk = [0u8; 16 * 1024];
+    loop {
+        if let Some((_header, message)) = framer.next_message().map_err(|e| e.to_string())? {
+            if more_to_come(&message) {
+                continue;
+            }
+            return Ok(
+                match op_msg_body(&message).and_then(|body| body.get("ok")) {
+                    Some(Value::Double(ok)) => ok == 1.0,
+                    Some(Value::Int32(ok)) => ok == 1,
+                    Some(Value::Int64(ok)) => ok == 1,
+                    _ => false,
+                },
+            );
+        }
+        let n = stream.read(&mut chunk).await.map_err(|e| e.to_string())?;
+        if n == 0 {
+            return Err("connection closed".to_string());
+        }
+        framer.push(&chunk[..n]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn test_workloads() {
+        assert_eq!(Workload::new("redis", None), Ok(Workload::RedisPing));
+        assert_eq!(Workload::new("redis", Some("SET")), Ok(Workload::RedisSet));
+        assert_eq!(
+            Workload::new("mongodb", Some("ping")),
+            Ok(Workload::MongoDBPing)
+        );
+        assert!(Workload::new("mongodb", Some("get")).is_err());
+        assert!(Workload::new("memcached", None).is_err());
+
+        let config = BenchConfig::new(Workload::RedisSet, "127.0.0.1:6379".to_string())
+            .with_keyspace(10)
+            .with_value_size(3);
+        assert_eq!(
+            config.request(12),
+            Bytes::from_static(b"*3\r\n$3\r\nSET\r\n$14\r\npuerta:bench:2\r\n$3\r\nxxx\r\n")
+        );
+    }
+
+    #[test]
+    fn test_percentiles() {
+        let report = BenchReport {
+            succeeded: 100,
+            failed: 0,
+            elapsed: Duration::from_secs(2),
+            latencies: (1..=100).map(Duration::from_millis).collect(),
+        };
+        assert_eq!(report.throughput(), 50.0);
+        assert_eq!(report.percentile(50.0), Duration::from_millis(50));
+        assert_eq!(report.percentile(99.0), Duration::from_millis(99));
+        assert_eq!(report.percentile(100.0), Duration::from_millis(100));
+        assert!(report.render().contains("p50 50000, p90 90000, p99 99000"));
+    }
+
+    /// Address of a server answering every Redis command with `+PONG`
+    async fn pong_server() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let target = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                tokio::spawn(async move {
+                    let mut buf = BytesMut::new();
+                    while stream.read_buf(&mut buf).await.unwrap_or(0) > 0 {
+                        for _ in RespParser::parse_commands(&mut buf).unwrap() {
+                            stream.write_all(b"+PONG\r\n").await.unwrap();
+                        }
+                    }
+                });
+            }
+        });
+        target
+    }
+
+    #[tokio::test]
+    async fn test_redis_bench() {
+        let target = pong_server().await;
+        let config = BenchConfig::new(Workload::RedisPing, target)
+            .with_concurrency(4)
+            .with_requests(200);
+        let report = run(config).await.unwrap();
+        assert_eq!(report.succeeded, 200);
+        assert_eq!(report.failed, 0);
+        assert_eq!(report.latencies.len(), 200);
+    }
+
+    #[tokio::test]
+    async fn test_replay_capture() {
+        let path = std::env::temp_dir().join(format!("puerta-replay-{}.cap", std::process::id()));
+        let config: crate::config::CaptureConfig =
+            toml::from_str(&format!("path = \"{}\"", path.display())).unwrap();
+        let capture = crate::core::capture::Capture::from_config(Some(&config), "redis", |_| false)
+            .unwrap()
+            .unwrap();
+        let ping = Bytes::from_static(b"*1\r\n$4\r\nPING\r\n");
+        for connection_id in [1, 2, 1] {
+            capture.record(connection_id, &ping);
+        }
+        capture.finish();
+
+        let target = pong_server().await;
+        let report = replay(&path, "redis", &target).await.unwrap();
+        assert_eq!(report.succeeded, 3);
+        assert_eq!(report.failed, 0);
+        assert!(replay(&path, "mongodb", &target).await.is_err());
+        let _ = std::fs::remove_file(&path);
+    }
+}
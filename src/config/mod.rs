@@ -1,8 +1,13 @@
 /// Configuration management for puerta
+pub mod reload;
+pub mod secrets;
+
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::path::Path;
 use crate::error::ConfigError;
+pub use crate::modes::mongodb::affinity::ClientIdentificationStrategy;
 
 /// Main puerta configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -11,10 +16,560 @@ pub struct Config {
     pub server: ServerConfig,
     /// Proxy mode configuration
     pub proxy: ProxyConfig,
+    /// Additional proxy instances served by the same process, each on its
+    /// own listen address
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub proxies: Vec<ProxyInstanceConfig>,
     /// Health check configuration
     pub health: HealthConfig,
     /// Logging configuration
     pub logging: LoggingConfig,
+    /// OpenTelemetry tracing export, disabled when absent
+    #[serde(default)]
+    pub telemetry: Option<TelemetryConfig>,
+    /// StatsD / DogStatsD metrics push, disabled when absent
+    #[serde(default)]
+    pub statsd: Option<StatsdConfig>,
+    /// HTTP admin API, disabled when absent
+    #[serde(default)]
+    pub admin: Option<AdminConfig>,
+    /// Client IP allow and deny lists, every client allowed when absent
+    #[serde(default)]
+    pub acl: Option<AclConfig>,
+    /// Discover backends from DNS, Consul or etcd instead of the static
+    /// endpoint list
+    #[serde(default)]
+    pub discovery: Option<DiscoveryConfig>,
+    /// Fault injection for resilience testing, disabled when absent
+    #[serde(default)]
+    pub chaos: Option<ChaosConfig>,
+    /// Recurring maintenance windows draining backends
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub maintenance: Vec<MaintenanceWindowConfig>,
+}
+
+/// DNS record used for backend discovery
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DnsRecordType {
+    /// SRV records supply host, port and weight
+    #[default]
+    Srv,
+    /// A/AAAA records, e.g. a Kubernetes headless service, on a fixed port
+    A,
+}
+
+/// Source of discovered backends
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiscoveryProvider {
+    /// SRV or A/AAAA records of `dns_name`
+    #[default]
+    Dns,
+    /// Instances of a Consul catalog `service`
+    Consul,
+    /// Keys under an etcd `key_prefix`
+    Etcd,
+}
+
+/// Dynamic backend discovery from DNS, Consul or etcd
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct DiscoveryConfig {
+    #[serde(default, rename = "type")]
+    pub provider: DiscoveryProvider,
+    /// Name to resolve, e.g. `_mongos._tcp.mongos.db.svc.cluster.local`
+    #[serde(default)]
+    pub dns_name: String,
+    #[serde(default)]
+    pub record_type: DnsRecordType,
+    /// Backend port for A records
+    #[serde(default)]
+    pub port: Option<u16>,
+    /// Consul or etcd HTTP API address, e.g. `http://127.0.0.1:8500`
+    #[serde(default)]
+    pub address: Option<String>,
+    /// Consul service name
+    #[serde(default)]
+    pub service: Option<String>,
+    /// etcd key prefix whose values are backend addresses
+    #[serde(default)]
+    pub key_prefix: Option<String>,
+    /// Consul ACL token or etcd auth token
+    #[serde(default)]
+    pub token: Option<String>,
+    /// File holding the token, instead of `token`
+    #[serde(default)]
+    pub token_file: Option<String>,
+    /// Seconds between DNS lookups; Consul and etcd are watched for changes
+    /// and read again at least this often
+    #[serde(default = "default_discovery_interval_sec")]
+    pub interval_sec: u64,
+}
+
+fn default_discovery_interval_sec() -> u64 {
+    30
+}
+
+impl Default for DiscoveryConfig {
+    fn default() -> Self {
+        Self {
+            provider: DiscoveryProvider::default(),
+            dns_name: String::new(),
+            record_type: DnsRecordType::default(),
+            port: None,
+            address: None,
+            service: None,
+            key_prefix: None,
+            token: None,
+            token_file: None,
+            interval_sec: default_discovery_interval_sec(),
+        }
+    }
+}
+
+impl std::fmt::Debug for DiscoveryConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DiscoveryConfig")
+            .field("provider", &self.provider)
+            .field("dns_name", &self.dns_name)
+            .field("record_type", &self.record_type)
+            .field("port", &self.port)
+            .field("address", &self.address)
+            .field("service", &self.service)
+            .field("key_prefix", &self.key_prefix)
+            .field("token", &self.token.as_ref().map(|_| "<redacted>"))
+            .field("token_file", &self.token_file)
+            .field("interval_sec", &self.interval_sec)
+            .finish()
+    }
+}
+
+impl DiscoveryConfig {
+    /// Resolve the token from the environment or `token_file`
+    fn resolve_secrets(&mut self) -> Result<(), ConfigError> {
+        if self.token.is_some() || self.token_file.is_some() {
+            let mut token = self.token.take().unwrap_or_default();
+            secrets::resolve("discovery token", &mut token, self.token_file.as_deref())?;
+            self.token = Some(token);
+        }
+        Ok(())
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        let required = |name: &str, value: &Option<String>, provider: &str| match value {
+            Some(value) if !value.trim().is_empty() => Ok(()),
+            _ => Err(ConfigError::ValidationError(format!(
+                "discovery {name} is required for {provider} discovery"
+            ))),
+        };
+        match self.provider {
+            DiscoveryProvider::Dns => {
+                if self.dns_name.trim().is_empty() {
+                    return Err(ConfigError::ValidationError(
+                        "discovery dns_name cannot be empty".to_string(),
+                    ));
+                }
+                if self.record_type == DnsRecordType::A && self.port.is_none() {
+                    return Err(ConfigError::ValidationError(
+                        "discovery port is required for A records".to_string(),
+                    ));
+                }
+            }
+            DiscoveryProvider::Consul => {
+                required("address", &self.address, "consul")?;
+                required("service", &self.service, "consul")?;
+            }
+            DiscoveryProvider::Etcd => {
+                required("address", &self.address, "etcd")?;
+                required("key_prefix", &self.key_prefix, "etcd")?;
+            }
+        }
+        if let Some(address) = &self.address {
+            if !address.starts_with("http://") {
+                return Err(ConfigError::ValidationError(format!(
+                    "discovery address must be an http:// URL: {address}"
+                )));
+            }
+        }
+        if self.interval_sec == 0 {
+            return Err(ConfigError::ValidationError(
+                "discovery interval_sec must be greater than 0".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Client IP access control lists
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AclConfig {
+    /// Networks allowed to connect, e.g. `10.0.0.0/8`; empty allows all
+    /// clients not denied
+    #[serde(default)]
+    pub allow: Vec<String>,
+    /// Networks refused even if allowed
+    #[serde(default)]
+    pub deny: Vec<String>,
+}
+
+/// Redis command allow and deny lists
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CommandFilterConfig {
+    /// Commands clients may send, e.g. `GET`; empty allows every command
+    /// not denied
+    #[serde(default)]
+    pub allow: Vec<String>,
+    /// Commands refused even if allowed, e.g. `FLUSHALL` or `CONFIG`
+    #[serde(default)]
+    pub deny: Vec<String>,
+}
+
+impl CommandFilterConfig {
+    fn validate(&self) -> Result<(), ConfigError> {
+        for command in self.allow.iter().chain(&self.deny) {
+            if command.is_empty()
+                || !command
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+            {
+                return Err(ConfigError::ValidationError(format!(
+                    "Invalid command in command_filter: {command:?}"
+                )));
+            }
+        }
+        if let Some(command) = self
+            .deny
+            .iter()
+            .find(|denied| self.allow.iter().any(|allowed| allowed.eq_ignore_ascii_case(denied)))
+        {
+            return Err(ConfigError::ValidationError(format!(
+                "command_filter lists {command} as both allowed and denied"
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// How keys are spread over the Redis nodes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RedisSharding {
+    /// The nodes form a Redis Cluster whose topology assigns the slots
+    #[default]
+    Cluster,
+    /// The nodes are independent servers sharing the slots in list order
+    Standalone,
+}
+
+/// How the Redis topology is chosen among the answers of the cluster nodes
+///
+/// A node cut off from the cluster keeps advertising the slot map it last
+/// knew, so trusting the first node to answer may route by a stale map.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TopologyAgreement {
+    /// The first node to answer, asking the nodes in turn
+    #[default]
+    First,
+    /// Every known node is asked, and the slot map with the highest config
+    /// epoch applied
+    HighestEpoch,
+    /// Every known node is asked, and a slot map applied once this many
+    /// nodes answer it; the highest config epoch wins among several
+    Quorum(usize),
+}
+
+/// `CLUSTER SLOTS`, `SHARDS`, `NODES`, `INFO` and `MYID` answered by the
+/// proxy as a single node serving every slot
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ClusterViewConfig {
+    /// `host:port` clients are told to connect to; the address each client
+    /// connected to when absent
+    #[serde(default)]
+    pub announce_addr: Option<String>,
+}
+
+impl ClusterViewConfig {
+    fn validate(&self) -> Result<(), ConfigError> {
+        match &self.announce_addr {
+            Some(addr) if crate::modes::redis::cluster_view::split_host_port(addr).is_none() => {
+                Err(ConfigError::ValidationError(format!(
+                    "cluster_view announce_addr must be host:port, got {addr:?}"
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Snapshot of the Redis cluster topology kept on disk, to route before
+/// the first discovery completes after a restart
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotConfig {
+    /// File the snapshot is written to
+    pub path: String,
+    /// Seconds between snapshots
+    #[serde(default = "default_snapshot_interval_sec")]
+    pub interval_sec: u64,
+    /// Snapshots older than this many seconds are ignored on startup, 0 to
+    /// load a snapshot of any age
+    #[serde(default = "default_snapshot_max_age_sec")]
+    pub max_age_sec: u64,
+}
+
+impl SnapshotConfig {
+    fn validate(&self) -> Result<(), ConfigError> {
+        if self.path.is_empty() {
+            return Err(ConfigError::ValidationError(
+                "snapshot path cannot be empty".to_string(),
+            ));
+        }
+        if self.interval_sec == 0 {
+            return Err(ConfigError::ValidationError(
+                "snapshot interval_sec must be greater than 0".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Large reply detection
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LargePayloadConfig {
+    /// Replies of at least this many bytes are logged and counted
+    pub threshold_bytes: usize,
+    /// Largest offenders (Redis keys or MongoDB operations) reported by the
+    /// admin API
+    #[serde(default = "default_large_payload_top")]
+    pub top: usize,
+}
+
+impl LargePayloadConfig {
+    fn validate(&self) -> Result<(), ConfigError> {
+        if self.threshold_bytes == 0 {
+            return Err(ConfigError::ValidationError(
+                "large_payloads threshold_bytes must be greater than 0".to_string(),
+            ));
+        }
+        if self.top == 0 {
+            return Err(ConfigError::ValidationError(
+                "large_payloads top must be greater than 0".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Shadow traffic: a share of client connections copied to a test backend,
+/// its replies discarded
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MirrorConfig {
+    /// Shadow mongos or Redis node, as `host:port`
+    pub target: String,
+    /// Percentage of client connections mirrored
+    #[serde(default = "default_mirror_percent")]
+    pub percent: f64,
+    /// Messages waiting for the shadow per connection; past this the
+    /// connection is no longer mirrored
+    #[serde(default = "default_mirror_max_queued")]
+    pub max_queued: usize,
+}
+
+impl MirrorConfig {
+    fn validate(&self) -> Result<(), ConfigError> {
+        Endpoint::from(self.target.as_str()).validate("mirror target")?;
+        if !(0.0..=100.0).contains(&self.percent) {
+            return Err(ConfigError::ValidationError(
+                "mirror percent must be between 0 and 100".to_string(),
+            ));
+        }
+        if self.max_queued == 0 {
+            return Err(ConfigError::ValidationError(
+                "mirror max_queued must be greater than 0".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Capture of client messages to rotated files, for offline analysis or
+/// replay
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CaptureConfig {
+    /// File the capture is written to; rotated files get `.1`, `.2`, ...
+    pub path: String,
+    /// Rotate the file once it holds this many bytes
+    #[serde(default = "default_capture_max_bytes")]
+    pub max_bytes: u64,
+    /// Rotate the file after this many seconds, 0 to rotate by size only
+    #[serde(default)]
+    pub max_age_sec: u64,
+    /// Rotated files kept
+    #[serde(default = "default_capture_max_files")]
+    pub max_files: usize,
+    /// Messages waiting to be written; past this they are dropped
+    #[serde(default = "default_capture_max_queued")]
+    pub max_queued: usize,
+    /// Leave out messages carrying credentials: authentication and user
+    /// management commands
+    #[serde(default = "default_true")]
+    pub redact_credentials: bool,
+}
+
+impl CaptureConfig {
+    fn validate(&self) -> Result<(), ConfigError> {
+        if self.path.is_empty() {
+            return Err(ConfigError::ValidationError(
+                "capture path cannot be empty".to_string(),
+            ));
+        }
+        for (name, value) in [
+            ("max_bytes", self.max_bytes),
+            ("max_files", self.max_files as u64),
+            ("max_queued", self.max_queued as u64),
+        ] {
+            if value == 0 {
+                return Err(ConfigError::ValidationError(format!(
+                    "capture {name} must be greater than 0"
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Fault injection on a share of client requests, for testing how client
+/// applications cope with a slow or failing proxy
+///
+/// Each request draws at most one fault, so the percentages add up to at
+/// most 100.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ChaosConfig {
+    /// Inject faults; off unless set, so the section can stay in place
+    #[serde(default)]
+    pub enabled: bool,
+    /// Percentage of requests delayed by `latency_ms`
+    #[serde(default)]
+    pub latency_percent: f64,
+    #[serde(default)]
+    pub latency_ms: u64,
+    /// Random extra delay of up to this many milliseconds
+    #[serde(default)]
+    pub latency_jitter_ms: u64,
+    /// Percentage of requests whose client connection is closed instead
+    #[serde(default)]
+    pub reset_percent: f64,
+    /// Percentage of keyed Redis commands answered with a `MOVED` redirect
+    /// back to the proxy
+    #[serde(default)]
+    pub moved_percent: f64,
+}
+
+impl ChaosConfig {
+    fn validate(&self) -> Result<(), ConfigError> {
+        for (name, percent) in [
+            ("latency_percent", self.latency_percent),
+            ("reset_percent", self.reset_percent),
+            ("moved_percent", self.moved_percent),
+        ] {
+            if !(0.0..=100.0).contains(&percent) {
+                return Err(ConfigError::ValidationError(format!(
+                    "chaos {name} must be between 0 and 100"
+                )));
+            }
+        }
+        if self.latency_percent + self.reset_percent + self.moved_percent > 100.0 {
+            return Err(ConfigError::ValidationError(
+                "chaos percentages must add up to at most 100".to_string(),
+            ));
+        }
+        if self.latency_percent > 0.0 && self.latency_ms == 0 && self.latency_jitter_ms == 0 {
+            return Err(ConfigError::ValidationError(
+                "chaos latency_percent requires latency_ms or latency_jitter_ms".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Longest maintenance window, a week
+pub const MAX_MAINTENANCE_DURATION_MIN: u64 = 7 * 24 * 60;
+
+/// A recurring maintenance window of a backend, during which it is drained
+///
+/// `schedule` is a cron expression in UTC, `minute hour day-of-month month
+/// day-of-week`, giving when the window opens; it closes `duration_min`
+/// minutes later and the backend returns to service.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MaintenanceWindowConfig {
+    /// Backend address, as in the endpoint list
+    pub backend: String,
+    pub schedule: String,
+    pub duration_min: u64,
+    /// Time open sessions get to finish before they are closed, 300 seconds
+    /// when absent
+    #[serde(default)]
+    pub drain_timeout_sec: Option<u64>,
+}
+
+impl MaintenanceWindowConfig {
+    fn validate(&self) -> Result<(), ConfigError> {
+        if self.backend.parse::<std::net::SocketAddr>().is_err() {
+            return Err(ConfigError::ValidationError(format!(
+                "maintenance backend {} must be an IP address",
+                self.backend
+            )));
+        }
+        crate::core::maintenance::Schedule::parse(&self.schedule).map_err(|e| {
+            ConfigError::ValidationError(format!("Invalid maintenance schedule '{}': {e}", self.schedule))
+        })?;
+        if !(1..=MAX_MAINTENANCE_DURATION_MIN).contains(&self.duration_min) {
+            return Err(ConfigError::ValidationError(format!(
+                "maintenance duration_min must be between 1 and {MAX_MAINTENANCE_DURATION_MIN}"
+            )));
+        }
+        if self.drain_timeout_sec == Some(0) {
+            return Err(ConfigError::ValidationError(
+                "maintenance drain_timeout_sec must be greater than 0".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Admin API configuration
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct AdminConfig {
+    /// Address the admin API listens on
+    pub listen_addr: String,
+    /// Bearer token every request must carry
+    #[serde(default)]
+    pub token: Option<String>,
+    /// File holding the token, instead of `token`
+    #[serde(default)]
+    pub token_file: Option<String>,
+}
+
+impl std::fmt::Debug for AdminConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AdminConfig")
+            .field("listen_addr", &self.listen_addr)
+            .field("token", &self.token.as_ref().map(|_| "<redacted>"))
+            .field("token_file", &self.token_file)
+            .finish()
+    }
+}
+
+impl AdminConfig {
+    /// Resolve the token from the environment or `token_file`
+    fn resolve_secrets(&mut self) -> Result<(), ConfigError> {
+        if self.token.is_some() || self.token_file.is_some() {
+            let mut token = self.token.take().unwrap_or_default();
+            secrets::resolve("admin token", &mut token, self.token_file.as_deref())?;
+            self.token = Some(token);
+        }
+        Ok(())
+    }
 }
 
 /// Server configuration
@@ -24,12 +579,99 @@ pub struct ServerConfig {
     pub listen_addr: String,
     /// Maximum number of concurrent connections
     pub max_connections: usize,
+    /// How long a connection past `max_connections` waits for a free slot
+    /// before it is rejected; 0 rejects immediately
+    #[serde(default)]
+    pub connection_queue_timeout_ms: u64,
+    /// Expect a HAProxy PROXY protocol (v1 or v2) header on every client
+    /// connection and use the client address it carries
+    #[serde(default)]
+    pub proxy_protocol: bool,
     /// Connection timeout in seconds
     pub connection_timeout_sec: u64,
-    /// Number of worker threads
+    /// Bytes read from a client or backend socket at a time
+    #[serde(default = "default_buffer_size")]
+    pub buffer_size: usize,
+    /// Bytes queued toward a slow peer at which reads from the other side
+    /// pause; they resume once the queue drains below half of this
+    #[serde(default = "default_write_high_watermark")]
+    pub write_high_watermark: usize,
+    /// Worker threads of each proxy service, 1 when unset
     pub worker_threads: Option<usize>,
+    /// Seconds between lookups of backend endpoints given by hostname
+    #[serde(default = "default_dns_refresh_sec")]
+    pub dns_refresh_sec: u64,
     /// Daemon mode configuration
     pub daemon: Option<DaemonConfig>,
+    /// TLS termination for client connections
+    #[serde(default)]
+    pub tls: Option<TlsConfig>,
+    /// Listening socket options
+    #[serde(default)]
+    pub tuning: ServerTuningConfig,
+}
+
+/// Socket options of client and backend connections
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ServerTuningConfig {
+    /// Set SO_REUSEPORT so the kernel spreads connections over the
+    /// listening sockets (Linux)
+    #[serde(default)]
+    pub reuse_port: bool,
+    /// Probe idle client connections, off when absent
+    #[serde(default)]
+    pub tcp_keepalive: Option<TcpKeepaliveConfig>,
+    /// Probe idle backend connections, off when absent
+    #[serde(default)]
+    pub backend_tcp_keepalive: Option<TcpKeepaliveConfig>,
+    /// Forward MongoDB connections without TLS over io_uring (Linux, `io-uring`
+    /// feature); regular sockets are used where it is unavailable
+    #[serde(default)]
+    pub io_uring: bool,
+    /// Cores the primary listener's worker threads are pinned to, round
+    /// robin (Linux); unpinned when empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cpu_affinity: Vec<usize>,
+}
+
+/// TCP keepalive probing
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TcpKeepaliveConfig {
+    /// Seconds a connection is idle before the first probe
+    pub idle_sec: u64,
+    /// Seconds between probes
+    pub interval_sec: u64,
+    /// Unanswered probes before the connection is dropped
+    pub count: usize,
+    /// Seconds sent data may stay unacknowledged before the connection is
+    /// dropped, TCP_USER_TIMEOUT (Linux); the kernel default when unset
+    #[serde(default)]
+    pub user_timeout_sec: Option<u64>,
+}
+
+impl TcpKeepaliveConfig {
+    fn validate(&self) -> Result<(), ConfigError> {
+        if self.idle_sec == 0 || self.interval_sec == 0 || self.count == 0 {
+            return Err(ConfigError::ValidationError(
+                "tcp_keepalive idle_sec, interval_sec and count must be greater than 0".to_string(),
+            ));
+        }
+        if self.user_timeout_sec == Some(0) {
+            return Err(ConfigError::ValidationError(
+                "tcp_keepalive user_timeout_sec must be greater than 0".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// TLS configuration for the client-facing listener
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TlsConfig {
+    /// Path to the PEM certificate chain
+    pub cert_path: String,
+    /// Path to the PEM private key
+    pub key_path: String,
 }
 
 /// Daemon mode configuration
@@ -49,160 +691,824 @@ pub struct DaemonConfig {
     pub group: Option<String>,
 }
 
+/// An additional proxy instance declared in a `[[proxies]]` entry
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProxyInstanceConfig {
+    /// Name used in logs and as the service name
+    pub name: String,
+    /// Address this instance listens on
+    pub listen_addr: String,
+    /// Cores this instance's worker threads are pinned to, round robin
+    /// (Linux); unpinned when empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cpu_affinity: Vec<usize>,
+    #[serde(flatten)]
+    pub proxy: ProxyConfig,
+}
+
 /// Proxy mode configuration
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(tag = "mode")]
 pub enum ProxyConfig {
     #[serde(rename = "mongodb")]
     MongoDB {
-        /// List of mongos endpoints
-        mongos_endpoints: Vec<String>,
+        /// List of mongos endpoints, optional with discovery
+        #[serde(default)]
+        mongos_endpoints: Vec<Endpoint>,
         /// Enable session affinity
         session_affinity: bool,
         /// Session timeout in seconds
         session_timeout_sec: u64,
+        /// Maximum wire protocol message size in bytes
+        #[serde(default = "default_max_message_size")]
+        max_message_size: usize,
+        /// TLS for connections to mongos
+        #[serde(default)]
+        tls: Option<UpstreamTlsConfig>,
+        /// How new sessions are spread across mongos instances
+        #[serde(default)]
+        balance_strategy: BalanceStrategy,
+        /// How clients are told apart for session affinity
+        #[serde(default)]
+        client_identification: ClientIdentificationStrategy,
+        /// Mongos subsets for clients whose commands carry a read preference
+        #[serde(default)]
+        read_preference_routes: Vec<ReadPreferenceRoute>,
+        /// Client networks pinned to dedicated mongos, ahead of session affinity
+        #[serde(default)]
+        affinity_rules: Vec<AffinityRule>,
+        /// Move idle sessions off mongos holding more than their share
+        #[serde(default)]
+        rebalance: Option<RebalanceConfig>,
+        /// What happens to client connections to a mongos found unhealthy
+        #[serde(default)]
+        on_backend_unhealthy: UnhealthyBackendAction,
+        /// Compressors clients may negotiate with mongos, in order of
+        /// preference; unset passes the client's offer through unchanged
+        #[serde(default)]
+        compressors: Option<Vec<Compressor>>,
+        /// Connections kept open to each healthy mongos ahead of new clients
+        #[serde(default)]
+        spare_connections: usize,
+        /// Other mongos instances tried when connecting to the selected one fails
+        #[serde(default = "default_retry_attempts")]
+        retry_attempts: u32,
+        /// Client connections each mongos takes at once, past which new
+        /// connections go to other mongos; unlimited when unset
+        #[serde(default)]
+        max_connections_per_backend: Option<usize>,
+        /// How long a connection waits for a slot when every mongos is at
+        /// `max_connections_per_backend`; 0 rejects it right away
+        #[serde(default)]
+        backend_queue_timeout_ms: u64,
+        /// Log, count and report replies above a size threshold
+        #[serde(default)]
+        large_payloads: Option<LargePayloadConfig>,
+        /// Copy a share of client connections to a shadow mongos
+        #[serde(default)]
+        mirror: Option<MirrorConfig>,
+        /// Write client messages to capture files
+        #[serde(default)]
+        capture: Option<CaptureConfig>,
+        /// Relay connections needing no message inspection with splice(2),
+        /// without copying through the proxy (Linux)
+        #[serde(default)]
+        zero_copy: bool,
+        /// Write each command's name, database, collection and time to the
+        /// access log
+        #[serde(default)]
+        audit: bool,
+        /// Log and count operations mongos takes at least this many
+        /// milliseconds to answer, off when unset
+        #[serde(default)]
+        slow_operation_threshold_ms: Option<u64>,
+        /// Send new connections of clients with open cursors to the mongos
+        /// holding them
+        #[serde(default)]
+        cursor_affinity: bool,
+        /// Send new connections of clients with an open transaction to the
+        /// mongos running it
+        #[serde(default)]
+        transaction_pinning: bool,
+        /// `host:port` hello replies name as the only member in place of
+        /// the servers' own addresses, off when unset
+        #[serde(default)]
+        announce_addr: Option<String>,
     },
     #[serde(rename = "redis")]
     Redis {
-        /// List of Redis cluster nodes
-        cluster_nodes: Vec<String>,
+        /// List of Redis cluster nodes, optional with discovery
+        #[serde(default)]
+        cluster_nodes: Vec<Endpoint>,
+        /// Whether `cluster_nodes` form a Redis Cluster or are standalone
+        /// servers the proxy shards keys across
+        #[serde(default)]
+        sharding: RedisSharding,
+        /// How many nodes must agree on the topology before it is applied
+        #[serde(default)]
+        topology_agreement: TopologyAgreement,
         /// Slot refresh interval in seconds
         slot_refresh_interval_sec: u64,
         /// Maximum number of redirects to follow
         max_redirects: u8,
         /// Connection timeout in milliseconds
         connection_timeout_ms: u64,
+        /// Maximum pooled upstream connections per cluster node
+        #[serde(default = "default_pool_size")]
+        pool_size: usize,
+        /// Idle timeout for pooled upstream connections in seconds
+        #[serde(default = "default_pool_idle_timeout_sec")]
+        pool_idle_timeout_sec: u64,
+        /// Maximum time to wait for a pooled connection in milliseconds
+        #[serde(default = "default_pool_max_wait_ms")]
+        pool_max_wait_ms: u64,
+        /// Idle connections kept open to each node ahead of demand, opened
+        /// when the node joins the cluster or recovers
+        #[serde(default)]
+        pool_min_idle: usize,
+        /// Other nodes tried when connecting fails for a command any node
+        /// can serve
+        #[serde(default = "default_retry_attempts")]
+        retry_attempts: u32,
+        /// How long a command whose master was lost waits for a replica to
+        /// take over its slot; 0 fails it right away
+        #[serde(default = "default_failover_timeout_ms")]
+        failover_timeout_ms: u64,
+        /// TLS for connections to Redis nodes
+        #[serde(default)]
+        tls: Option<Box<UpstreamTlsConfig>>,
+        /// Credentials used to authenticate to Redis nodes
+        #[serde(default)]
+        auth: Option<RedisAuthConfig>,
+        /// Credentials clients must present to the proxy
+        #[serde(default)]
+        client_auth: Option<RedisAuthConfig>,
+        /// Route read-only commands to replicas of the owning master
+        #[serde(default)]
+        read_from_replicas: bool,
+        /// How replicas and seed nodes are picked: `round_robin` or
+        /// `latency_aware`
+        #[serde(default)]
+        balance_strategy: BalanceStrategy,
+        /// Commands slower than this many microseconds enter the slow log
+        #[serde(default = "default_slowlog_threshold_us")]
+        slowlog_threshold_us: u64,
+        /// Maximum number of slow log entries kept (0 disables the slow log)
+        #[serde(default = "default_slowlog_max_len")]
+        slowlog_max_len: usize,
+        /// Run DBSIZE, FLUSHDB, FLUSHALL, KEYS and INFO on every master and
+        /// aggregate the replies, instead of asking a single node
+        #[serde(default = "default_true")]
+        fan_out_keyless_commands: bool,
+        /// Commands clients may or may not send, every command allowed when absent
+        #[serde(default)]
+        command_filter: Option<CommandFilterConfig>,
+        /// Log, count and report replies above a size threshold
+        #[serde(default)]
+        large_payloads: Option<LargePayloadConfig>,
+        /// Copy a share of client connections to a shadow Redis node
+        #[serde(default)]
+        mirror: Option<MirrorConfig>,
+        /// Write client commands to capture files
+        #[serde(default)]
+        capture: Option<CaptureConfig>,
+        /// Answer CLUSTER topology commands as a single node, hiding the
+        /// cluster from clients
+        #[serde(default)]
+        cluster_view: Option<ClusterViewConfig>,
+        /// Keep the cluster topology on disk and route by it on startup
+        /// until the first discovery completes
+        #[serde(default)]
+        snapshot: Option<SnapshotConfig>,
+        /// Largest command a client may send in bytes; larger ones are
+        /// rejected with a protocol error instead of being buffered
+        #[serde(default = "default_max_resp_size")]
+        max_command_size: usize,
+        /// Largest reply accepted from a node in bytes
+        #[serde(default = "default_max_resp_size")]
+        max_reply_size: usize,
     },
 }
 
-/// Health check configuration
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct HealthConfig {
-    /// Health check interval in seconds
-    pub interval_sec: u64,
-    /// Health check timeout in seconds
-    pub timeout_sec: u64,
-    /// Number of consecutive failures before marking unhealthy
-    pub failure_threshold: u32,
-    /// Number of consecutive successes before marking healthy
-    pub success_threshold: u32,
+/// Backend selection strategy for new MongoDB sessions and keyless Redis
+/// commands
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BalanceStrategy {
+    /// Weighted round-robin
+    #[default]
+    RoundRobin,
+    /// Fewest open connections per unit of weight
+    LeastConnections,
+    /// Ketama consistent hashing on the client IP
+    ConsistentHash,
+    /// Lowest connect and first-byte latency per unit of weight
+    LatencyAware,
 }
 
-/// Logging configuration
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct LoggingConfig {
-    /// Log level (error, warn, info, debug, trace)
-    pub level: String,
-    /// Log format (json, text)
-    pub format: String,
-    /// Log to stdout
-    pub stdout: bool,
-    /// Log file path (optional)
-    pub file: Option<String>,
+/// Handling of client connections open to a mongos that turns unhealthy
+///
+/// Either way the sessions pinned to it are forgotten, so the clients'
+/// new connections go to a healthy mongos.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UnhealthyBackendAction {
+    /// Close the connections, making drivers reconnect
+    #[default]
+    Close,
+    /// Leave the connections open until the client or mongos ends them
+    Drain,
 }
 
-impl Default for Config {
-    fn default() -> Self {
-        Self {
-            server: ServerConfig {
-                listen_addr: "0.0.0.0:8080".to_string(),
-                max_connections: 10000,
-                connection_timeout_sec: 60,
-                worker_threads: None, // Use system default
-                daemon: None, // Daemon mode disabled by default
-            },
-            proxy: ProxyConfig::MongoDB {
-                mongos_endpoints: vec!["127.0.0.1:27017".to_string()],
-                session_affinity: true,
-                session_timeout_sec: 3600,
-            },
-            health: HealthConfig {
-                interval_sec: 10,
-                timeout_sec: 5,
-                failure_threshold: 3,
-                success_threshold: 2,
-            },
-            logging: LoggingConfig {
-                level: "info".to_string(),
-                format: "text".to_string(),
-                stdout: true,
-                file: None,
-            },
+/// MongoDB read preference mode, as sent in a command's `$readPreference`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ReadPreferenceMode {
+    Primary,
+    PrimaryPreferred,
+    Secondary,
+    SecondaryPreferred,
+    Nearest,
+}
+
+impl std::str::FromStr for ReadPreferenceMode {
+    type Err = String;
+
+    fn from_str(mode: &str) -> Result<Self, Self::Err> {
+        match mode {
+            "primary" => Ok(Self::Primary),
+            "primaryPreferred" => Ok(Self::PrimaryPreferred),
+            "secondary" => Ok(Self::Secondary),
+            "secondaryPreferred" => Ok(Self::SecondaryPreferred),
+            "nearest" => Ok(Self::Nearest),
+            other => Err(format!("unknown read preference mode: {other}")),
         }
     }
 }
 
-impl Config {
-    /// Load configuration from TOML file
-    pub fn load_from_file<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
-        let content = fs::read_to_string(path).map_err(|e| ConfigError::IoError(e.to_string()))?;
-
-        let config: Config =
-            toml::from_str(&content).map_err(|e| ConfigError::ParseError(e.to_string()))?;
+/// MongoDB wire protocol compressor
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compressor {
+    Snappy,
+    Zlib,
+    Zstd,
+}
 
-        config.validate()?;
-        Ok(config)
+impl Compressor {
+    /// Name used in the `compression` field of the hello handshake
+    pub fn name(&self) -> &'static str {
+        match self {
+            Compressor::Snappy => "snappy",
+            Compressor::Zlib => "zlib",
+            Compressor::Zstd => "zstd",
+        }
     }
+}
 
-    /// Save configuration to TOML file
-    pub fn save_to_file<P: AsRef<Path>>(&self, path: P) -> Result<(), ConfigError> {
-        let content =
-            toml::to_string_pretty(self).map_err(|e| ConfigError::SerializeError(e.to_string()))?;
+/// Mongos subset serving clients that use one of `modes`
+///
+/// A client's read preference is learned from the commands it sends, so the
+/// route applies to its connections opened after that.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReadPreferenceRoute {
+    pub modes: Vec<ReadPreferenceMode>,
+    /// Mongos addresses (`host:port`) new connections are spread across
+    pub endpoints: Vec<String>,
+}
 
-        fs::write(path, content).map_err(|e| ConfigError::IoError(e.to_string()))?;
+/// Mongos dedicated to clients from some networks, such as a batch job
+/// subnet
+///
+/// Rules are checked in order before session affinity; a client matching
+/// none, or whose rule has no healthy mongos, is balanced as usual.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AffinityRule {
+    /// Client networks, e.g. `10.20.0.0/16`, or single addresses
+    pub clients: Vec<String>,
+    /// Mongos addresses (`host:port`), the first healthy one is used
+    pub endpoints: Vec<String>,
+}
 
-        Ok(())
-    }
+/// Moving of idle sessions off mongos holding more than their share
+///
+/// Every `interval_sec`, when the sessions of the busiest healthy mongos,
+/// per unit of weight, exceed the average by more than `max_skew` times, up
+/// to `max_sessions` sessions idle for `min_idle_sec` are pinned to the
+/// least busy mongos instead, taking effect when their clients reconnect.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RebalanceConfig {
+    /// Ratio of the busiest mongos' sessions to the average tolerated
+    #[serde(default = "default_rebalance_max_skew")]
+    pub max_skew: f64,
+    /// Sessions moved per round at most
+    #[serde(default = "default_rebalance_max_sessions")]
+    pub max_sessions: usize,
+    /// Seconds without traffic before a session may be moved
+    #[serde(default = "default_rebalance_min_idle_sec")]
+    pub min_idle_sec: u64,
+    /// Seconds between rounds
+    #[serde(default = "default_rebalance_interval_sec")]
+    pub interval_sec: u64,
+}
 
-    /// Validate configuration
-    pub fn validate(&self) -> Result<(), ConfigError> {
-        // Validate server config
-        if self.server.max_connections == 0 {
+impl RebalanceConfig {
+    fn validate(&self) -> Result<(), ConfigError> {
+        if self.max_skew.is_nan() || self.max_skew <= 1.0 {
             return Err(ConfigError::ValidationError(
-                "max_connections must be greater than 0".to_string(),
+                "rebalance max_skew must be greater than 1".to_string(),
             ));
         }
-
-        if self.server.connection_timeout_sec == 0 {
+        if self.max_sessions == 0 || self.interval_sec == 0 {
             return Err(ConfigError::ValidationError(
-                "connection_timeout_sec must be greater than 0".to_string(),
+                "rebalance max_sessions and interval_sec must be greater than 0".to_string(),
             ));
         }
+        Ok(())
+    }
+}
 
-        // Validate proxy config
-        match &self.proxy {
+/// How a backend's health is checked
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HealthCheckKind {
+    /// Only open a TCP connection
+    Tcp,
+    /// Run the mode's wire protocol check, e.g. `isMaster` for mongos
+    #[default]
+    Protocol,
+}
+
+/// Health check settings of a single endpoint, overriding the defaults
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EndpointHealthCheck {
+    pub interval_sec: Option<u64>,
+    pub timeout_sec: Option<u64>,
+    #[serde(default)]
+    pub checker: HealthCheckKind,
+}
+
+impl EndpointHealthCheck {
+    fn validate(&self, kind: &str, addr: &str) -> Result<(), ConfigError> {
+        if self.interval_sec == Some(0) || self.timeout_sec == Some(0) {
+            return Err(ConfigError::ValidationError(format!(
+                "{kind} {addr} health check interval_sec and timeout_sec must be greater than 0"
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+/// Backend endpoint with an optional load balancing weight and health
+/// check settings
+///
+/// Written either as a plain `"host:port"` string or as a table
+/// `{ addr = "host:port", weight = 3, health_check = { timeout_sec = 10 } }`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "EndpointRepr", into = "EndpointRepr")]
+pub struct Endpoint {
+    pub addr: String,
+    pub weight: usize,
+    pub health_check: Option<EndpointHealthCheck>,
+}
+
+impl Endpoint {
+    pub fn new(addr: impl Into<String>, weight: usize) -> Self {
+        Self {
+            addr: addr.into(),
+            weight,
+            health_check: None,
+        }
+    }
+
+    /// Check this endpoint with its own settings instead of the defaults
+    pub fn with_health_check(mut self, health_check: EndpointHealthCheck) -> Self {
+        self.health_check = Some(health_check);
+        self
+    }
+
+    /// Addresses of a list of endpoints
+    pub fn addrs(endpoints: &[Endpoint]) -> Vec<String> {
+        endpoints.iter().map(|endpoint| endpoint.addr.clone()).collect()
+    }
+
+    /// Weights of a list of endpoints keyed by address
+    pub fn weights(endpoints: &[Endpoint]) -> HashMap<String, usize> {
+        endpoints
+            .iter()
+            .map(|endpoint| (endpoint.addr.clone(), endpoint.weight))
+            .collect()
+    }
+
+    /// Health check overrides of a list of endpoints keyed by address
+    pub fn health_checks(endpoints: &[Endpoint]) -> HashMap<String, EndpointHealthCheck> {
+        endpoints
+            .iter()
+            .filter_map(|endpoint| {
+                let health_check = endpoint.health_check.clone()?;
+                Some((endpoint.addr.clone(), health_check))
+            })
+            .collect()
+    }
+
+    /// Whether the address names its host rather than an IP address
+    pub fn is_hostname(&self) -> bool {
+        self.addr.parse::<std::net::SocketAddr>().is_err()
+    }
+
+    /// Whether any endpoint of a list is given by hostname
+    pub fn any_hostname(endpoints: &[Endpoint]) -> bool {
+        endpoints.iter().any(Endpoint::is_hostname)
+    }
+
+    pub(crate) fn validate(&self, kind: &str) -> Result<(), ConfigError> {
+        let valid_host = |host: &str| {
+            host.len() <= 253
+                && host.split('.').all(|label| {
+                    !label.is_empty()
+                        && label.len() <= 63
+                        && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+                })
+        };
+        let valid = self.addr.parse::<std::net::SocketAddr>().is_ok()
+            || crate::modes::redis::cluster_view::split_host_port(&self.addr)
+                .is_some_and(|(host, _)| valid_host(&host));
+        if !valid {
+            return Err(ConfigError::ValidationError(format!("Invalid {kind}: {}", self.addr)));
+        }
+
+        if self.weight == 0 {
+            return Err(ConfigError::ValidationError(format!(
+                "{kind} {} weight must be greater than 0",
+                self.addr
+            )));
+        }
+
+        if let Some(health_check) = &self.health_check {
+            health_check.validate(kind, &self.addr)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl From<&str> for Endpoint {
+    fn from(addr: &str) -> Self {
+        Self::new(addr, default_weight())
+    }
+}
+
+impl std::fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.weight == default_weight() {
+            write!(f, "{}", self.addr)
+        } else {
+            write!(f, "{} (weight {})", self.addr, self.weight)
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum EndpointRepr {
+    Addr(String),
+    Weighted {
+        addr: String,
+        #[serde(default = "default_weight")]
+        weight: usize,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        health_check: Option<EndpointHealthCheck>,
+    },
+}
+
+impl From<EndpointRepr> for Endpoint {
+    fn from(repr: EndpointRepr) -> Self {
+        match repr {
+            EndpointRepr::Addr(addr) => Self::new(addr, default_weight()),
+            EndpointRepr::Weighted {
+                addr,
+                weight,
+                health_check,
+            } => Self {
+                addr,
+                weight,
+                health_check,
+            },
+        }
+    }
+}
+
+impl From<Endpoint> for EndpointRepr {
+    fn from(endpoint: Endpoint) -> Self {
+        if endpoint.weight == default_weight() && endpoint.health_check.is_none() {
+            EndpointRepr::Addr(endpoint.addr)
+        } else {
+            EndpointRepr::Weighted {
+                addr: endpoint.addr,
+                weight: endpoint.weight,
+                health_check: endpoint.health_check,
+            }
+        }
+    }
+}
+
+fn default_weight() -> usize {
+    1
+}
+
+/// Redis `AUTH` credentials
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct RedisAuthConfig {
+    /// ACL username, the `default` user when omitted
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: String,
+    /// File holding the password, instead of `password`
+    #[serde(default)]
+    pub password_file: Option<String>,
+}
+
+impl RedisAuthConfig {
+    /// Resolve the username and password from the environment or
+    /// `password_file`; `name` is the config section
+    fn resolve_secrets(&mut self, name: &str) -> Result<(), ConfigError> {
+        if let Some(username) = &self.username {
+            self.username = Some(secrets::interpolate(&format!("{name} username"), username)?);
+        }
+        secrets::resolve(
+            &format!("{name} password"),
+            &mut self.password,
+            self.password_file.as_deref(),
+        )
+    }
+}
+
+impl std::fmt::Debug for RedisAuthConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RedisAuthConfig")
+            .field("username", &self.username)
+            .field("password", &"<redacted>")
+            .field("password_file", &self.password_file)
+            .finish()
+    }
+}
+
+/// TLS configuration for connections to backends
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UpstreamTlsConfig {
+    /// CA bundle used to verify backend certificates
+    pub ca_file: Option<String>,
+    /// Client certificate for mutual TLS
+    pub cert_path: Option<String>,
+    /// Client private key for mutual TLS
+    pub key_path: Option<String>,
+    /// SNI to send instead of the backend host
+    pub sni: Option<String>,
+    /// Verify the backend certificate
+    #[serde(default = "default_true")]
+    pub verify_cert: bool,
+    /// Verify the backend certificate matches the SNI
+    #[serde(default = "default_true")]
+    pub verify_hostname: bool,
+}
+
+impl UpstreamTlsConfig {
+    /// Validate that referenced files exist and client cert/key come in pairs
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.cert_path.is_some() != self.key_path.is_some() {
+            return Err(ConfigError::ValidationError(
+                "upstream tls cert_path and key_path must be set together".to_string(),
+            ));
+        }
+
+        for (name, path) in [
+            ("ca_file", &self.ca_file),
+            ("cert_path", &self.cert_path),
+            ("key_path", &self.key_path),
+        ] {
+            if let Some(path) = path {
+                if !Path::new(path).exists() {
+                    return Err(ConfigError::ValidationError(format!(
+                        "upstream tls {name} does not exist: {path}"
+                    )));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl ProxyConfig {
+    /// Validate the mode settings; endpoint lists may be empty when
+    /// `discovery` supplies the backends
+    fn validate(&self, discovery: bool) -> Result<(), ConfigError> {
+        match self {
             ProxyConfig::MongoDB {
-                mongos_endpoints, ..
+                mongos_endpoints,
+                max_message_size,
+                tls,
+                read_preference_routes,
+                affinity_rules,
+                rebalance,
+                client_identification,
+                session_affinity,
+                compressors,
+                large_payloads,
+                mirror,
+                capture,
+                slow_operation_threshold_ms,
+                announce_addr,
+                max_connections_per_backend,
+                ..
             } => {
-                if mongos_endpoints.is_empty() {
+                if let Some(tls) = tls {
+                    tls.validate()?;
+                }
+
+                if mongos_endpoints.is_empty() && !discovery {
                     return Err(ConfigError::ValidationError(
                         "mongos_endpoints cannot be empty".to_string(),
                     ));
                 }
 
                 for endpoint in mongos_endpoints {
-                    endpoint.parse::<std::net::SocketAddr>().map_err(|_| {
-                        ConfigError::ValidationError(format!(
-                            "Invalid mongos endpoint: {endpoint}"
-                        ))
-                    })?;
+                    endpoint.validate("mongos endpoint")?;
+                }
+
+                if *max_message_size < crate::modes::mongodb::wire::HEADER_LEN {
+                    return Err(ConfigError::ValidationError(
+                        "max_message_size is smaller than a message header".to_string(),
+                    ));
+                }
+
+                if let Some(compressors) = compressors {
+                    let mut seen = HashSet::new();
+                    if let Some(compressor) = compressors.iter().find(|c| !seen.insert(**c)) {
+                        return Err(ConfigError::ValidationError(format!(
+                            "compressor {} is listed more than once",
+                            compressor.name()
+                        )));
+                    }
+                }
+
+                if let Some(large_payloads) = large_payloads {
+                    large_payloads.validate()?;
+                }
+
+                if let Some(mirror) = mirror {
+                    mirror.validate()?;
+                }
+
+                if let Some(capture) = capture {
+                    capture.validate()?;
+                }
+
+                if *slow_operation_threshold_ms == Some(0) {
+                    return Err(ConfigError::ValidationError(
+                        "slow_operation_threshold_ms must be greater than 0".to_string(),
+                    ));
+                }
+
+                if let Some(rebalance) = rebalance {
+                    rebalance.validate()?;
+                    // Sessions by socket address end with their connection
+                    if !*session_affinity || *client_identification == ClientIdentificationStrategy::SocketAddressOnly {
+                        return Err(ConfigError::ValidationError(
+                            "rebalance needs session_affinity and a client_identification other than socket_address_only"
+                                .to_string(),
+                        ));
+                    }
+                }
+
+                if *max_connections_per_backend == Some(0) {
+                    return Err(ConfigError::ValidationError(
+                        "max_connections_per_backend must be greater than 0".to_string(),
+                    ));
+                }
+
+                if let Some(addr) = announce_addr {
+                    if crate::modes::redis::cluster_view::split_host_port(addr).is_none() {
+                        return Err(ConfigError::ValidationError(format!(
+                            "announce_addr must be host:port, got {addr:?}"
+                        )));
+                    }
+                }
+
+                let mut routed_modes = HashSet::new();
+                for route in read_preference_routes {
+                    if route.modes.is_empty() || route.endpoints.is_empty() {
+                        return Err(ConfigError::ValidationError(
+                            "read_preference_routes entries need modes and endpoints".to_string(),
+                        ));
+                    }
+                    if let Some(mode) = route.modes.iter().find(|mode| !routed_modes.insert(**mode)) {
+                        return Err(ConfigError::ValidationError(format!(
+                            "read preference mode {mode:?} is routed more than once"
+                        )));
+                    }
+                    for endpoint in &route.endpoints {
+                        if !discovery && !mongos_endpoints.iter().any(|e| &e.addr == endpoint) {
+                            return Err(ConfigError::ValidationError(format!(
+                                "read preference route endpoint {endpoint} is not a mongos endpoint"
+                            )));
+                        }
+                        // A hostname may stand for several mongos
+                        if Endpoint::from(endpoint.as_str()).is_hostname() {
+                            return Err(ConfigError::ValidationError(format!(
+                                "read preference route endpoint {endpoint} must be an IP address"
+                            )));
+                        }
+                    }
+                }
+
+                for rule in affinity_rules {
+                    if rule.clients.is_empty() || rule.endpoints.is_empty() {
+                        return Err(ConfigError::ValidationError(
+                            "affinity_rules entries need clients and endpoints".to_string(),
+                        ));
+                    }
+                    for client in &rule.clients {
+                        client
+                            .parse::<crate::acl::Cidr>()
+                            .map_err(|e| ConfigError::ValidationError(format!("affinity rule: {e}")))?;
+                    }
+                    for endpoint in &rule.endpoints {
+                        if !discovery && !mongos_endpoints.iter().any(|e| &e.addr == endpoint) {
+                            return Err(ConfigError::ValidationError(format!(
+                                "affinity rule endpoint {endpoint} is not a mongos endpoint"
+                            )));
+                        }
+                        // A hostname may stand for several mongos
+                        if Endpoint::from(endpoint.as_str()).is_hostname() {
+                            return Err(ConfigError::ValidationError(format!(
+                                "affinity rule endpoint {endpoint} must be an IP address"
+                            )));
+                        }
+                    }
                 }
             }
             ProxyConfig::Redis {
                 cluster_nodes,
                 max_redirects,
+                pool_size,
+                pool_min_idle,
+                tls,
+                auth,
+                client_auth,
+                command_filter,
+                large_payloads,
+                mirror,
+                capture,
+                cluster_view,
+                snapshot,
+                max_command_size,
+                max_reply_size,
+                sharding,
+                topology_agreement,
+                read_from_replicas,
+                balance_strategy,
                 ..
             } => {
-                if cluster_nodes.is_empty() {
+                if let Some(tls) = tls {
+                    tls.validate()?;
+                }
+
+                if !matches!(balance_strategy, BalanceStrategy::RoundRobin | BalanceStrategy::LatencyAware) {
+                    return Err(ConfigError::ValidationError(
+                        "Redis balance_strategy must be 'round_robin' or 'latency_aware'".to_string(),
+                    ));
+                }
+
+                if *sharding == RedisSharding::Standalone && *read_from_replicas {
+                    return Err(ConfigError::ValidationError(
+                        "read_from_replicas needs cluster sharding; standalone servers have no known replicas".to_string(),
+                    ));
+                }
+
+                for (name, auth) in [("auth", auth), ("client_auth", client_auth)] {
+                    if auth.as_ref().is_some_and(|auth| auth.password.is_empty()) {
+                        return Err(ConfigError::ValidationError(format!(
+                            "{name} password cannot be empty"
+                        )));
+                    }
+                }
+
+                if cluster_nodes.is_empty() && !discovery {
                     return Err(ConfigError::ValidationError(
                         "cluster_nodes cannot be empty".to_string(),
                     ));
                 }
 
                 for node in cluster_nodes {
-                    node.parse::<std::net::SocketAddr>().map_err(|_| {
-                        ConfigError::ValidationError(format!("Invalid Redis node: {node}"))
-                    })?;
+                    node.validate("Redis node")?;
+                    if node.health_check.is_some() {
+                        return Err(ConfigError::ValidationError(format!(
+                            "Redis node {} health_check is not supported; Redis nodes are not actively checked",
+                            node.addr
+                        )));
+                    }
                 }
 
                 if *max_redirects == 0 {
@@ -210,132 +1516,2558 @@ impl Config {
                         "max_redirects must be greater than 0".to_string(),
                     ));
                 }
-            }
-        }
 
-        // Validate health config
-        if self.health.interval_sec == 0 {
-            return Err(ConfigError::ValidationError(
-                "health check interval_sec must be greater than 0".to_string(),
-            ));
-        }
+                if *pool_size == 0 {
+                    return Err(ConfigError::ValidationError(
+                        "pool_size must be greater than 0".to_string(),
+                    ));
+                }
 
-        if self.health.timeout_sec == 0 {
-            return Err(ConfigError::ValidationError(
-                "health check timeout_sec must be greater than 0".to_string(),
-            ));
-        }
+                if pool_min_idle > pool_size {
+                    return Err(ConfigError::ValidationError(
+                        "pool_min_idle cannot exceed pool_size".to_string(),
+                    ));
+                }
 
-        if self.health.timeout_sec >= self.health.interval_sec {
-            return Err(ConfigError::ValidationError(
-                "health check timeout_sec must be less than interval_sec".to_string(),
-            ));
-        }
+                if let Some(command_filter) = command_filter {
+                    command_filter.validate()?;
+                }
 
-        // Validate logging config
-        match self.logging.level.as_str() {
-            "error" | "warn" | "info" | "debug" | "trace" => {}
-            _ => {
-                return Err(ConfigError::ValidationError(format!(
-                    "Invalid log level: {}",
-                    self.logging.level
-                )))
-            }
-        }
+                if let Some(large_payloads) = large_payloads {
+                    large_payloads.validate()?;
+                }
 
-        match self.logging.format.as_str() {
-            "json" | "text" => {}
-            _ => {
-                return Err(ConfigError::ValidationError(format!(
-                    "Invalid log format: {}",
-                    self.logging.format
-                )))
+                if let Some(mirror) = mirror {
+                    mirror.validate()?;
+                }
+
+                if let Some(capture) = capture {
+                    capture.validate()?;
+                }
+
+                if let Some(cluster_view) = cluster_view {
+                    cluster_view.validate()?;
+                }
+
+                if *topology_agreement != TopologyAgreement::First && *sharding == RedisSharding::Standalone {
+                    return Err(ConfigError::ValidationError(
+                        "topology_agreement needs cluster sharding; standalone servers have no topology to agree on"
+                            .to_string(),
+                    ));
+                }
+                if let TopologyAgreement::Quorum(quorum) = topology_agreement {
+                    if *quorum == 0 || (!discovery && *quorum > cluster_nodes.len()) {
+                        return Err(ConfigError::ValidationError(format!(
+                            "topology_agreement quorum must be between 1 and the {} cluster_nodes, got {quorum}",
+                            cluster_nodes.len()
+                        )));
+                    }
+                }
+
+                if let Some(snapshot) = snapshot {
+                    if *sharding == RedisSharding::Standalone {
+                        return Err(ConfigError::ValidationError(
+                            "snapshot needs cluster sharding; standalone servers have no topology to keep".to_string(),
+                        ));
+                    }
+                    snapshot.validate()?;
+                }
+
+                for (name, size) in [("max_command_size", max_command_size), ("max_reply_size", max_reply_size)] {
+                    if *size == 0 {
+                        return Err(ConfigError::ValidationError(format!(
+                            "{name} must be greater than 0"
+                        )));
+                    }
+                }
             }
         }
 
         Ok(())
     }
+}
 
-    /// Create example configuration file
-    pub fn create_example_config<P: AsRef<Path>>(path: P, mode: &str) -> Result<(), ConfigError> {
-        let config = match mode {
-            "mongodb" => Config {
-                proxy: ProxyConfig::MongoDB {
-                    mongos_endpoints: vec![
-                        "10.0.1.10:27017".to_string(),
-                        "10.0.1.11:27017".to_string(),
-                        "10.0.1.12:27017".to_string(),
-                    ],
-                    session_affinity: true,
-                    session_timeout_sec: 3600,
-                },
-                ..Default::default()
-            },
-            "redis" => Config {
-                proxy: ProxyConfig::Redis {
-                    cluster_nodes: vec![
-                        "10.0.1.20:6379".to_string(),
-                        "10.0.1.21:6379".to_string(),
-                        "10.0.1.22:6379".to_string(),
-                    ],
-                    slot_refresh_interval_sec: 60,
-                    max_redirects: 3,
-                    connection_timeout_ms: 5000,
-                },
-                ..Default::default()
-            },
-            _ => {
-                return Err(ConfigError::ValidationError(
-                    "Mode must be 'mongodb' or 'redis'".to_string(),
-                ))
-            }
-        };
+fn default_true() -> bool {
+    true
+}
 
-        config.save_to_file(path)
-    }
+fn default_buffer_size() -> usize {
+    crate::core::frontend::DEFAULT_BUFFER_SIZE
 }
 
+fn default_dns_refresh_sec() -> u64 {
+    30
+}
 
+fn default_write_high_watermark() -> usize {
+    crate::core::frontend::DEFAULT_WRITE_HIGH_WATERMARK
+}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use tempfile::NamedTempFile;
+fn default_max_message_size() -> usize {
+    crate::modes::mongodb::wire::DEFAULT_MAX_MESSAGE_SIZE
+}
 
-    #[test]
-    fn test_default_config() {
-        let config = Config::default();
-        assert!(config.validate().is_ok());
-    }
+fn default_max_resp_size() -> usize {
+    crate::modes::redis::resp::DEFAULT_MAX_FRAME_SIZE
+}
 
-    #[test]
-    fn test_config_validation() {
-        let mut config = Config::default();
+fn default_passive_failure_threshold() -> u32 {
+    crate::health::DEFAULT_PASSIVE_FAILURE_THRESHOLD
+}
 
-        // Test invalid max_connections
-        config.server.max_connections = 0;
-        assert!(config.validate().is_err());
+fn default_pool_size() -> usize {
+    64
+}
 
-        config.server.max_connections = 1000;
-        assert!(config.validate().is_ok());
-    }
+fn default_pool_idle_timeout_sec() -> u64 {
+    300
+}
 
-    #[test]
-    fn test_config_serialization() {
-        let config = Config::default();
-        let toml_str = toml::to_string(&config).unwrap();
-        let parsed_config: Config = toml::from_str(&toml_str).unwrap();
-        assert!(parsed_config.validate().is_ok());
-    }
+fn default_pool_max_wait_ms() -> u64 {
+    1000
+}
+
+fn default_slowlog_threshold_us() -> u64 {
+    10_000
+}
+
+fn default_slowlog_max_len() -> usize {
+    128
+}
+
+fn default_large_payload_top() -> usize {
+    32
+}
+
+fn default_mirror_percent() -> f64 {
+    100.0
+}
+
+fn default_mirror_max_queued() -> usize {
+    1024
+}
+
+fn default_rebalance_max_skew() -> f64 {
+    1.5
+}
+
+fn default_rebalance_max_sessions() -> usize {
+    10
+}
+
+fn default_rebalance_min_idle_sec() -> u64 {
+    60
+}
+
+fn default_rebalance_interval_sec() -> u64 {
+    60
+}
+
+fn default_snapshot_interval_sec() -> u64 {
+    30
+}
+
+fn default_snapshot_max_age_sec() -> u64 {
+    3600
+}
+
+fn default_capture_max_bytes() -> u64 {
+    100 * 1024 * 1024
+}
+
+fn default_capture_max_files() -> usize {
+    10
+}
+
+fn default_capture_max_queued() -> usize {
+    65536
+}
+
+fn default_retry_attempts() -> u32 {
+    2
+}
+
+fn default_failover_timeout_ms() -> u64 {
+    5000
+}
+
+/// Health check configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HealthConfig {
+    /// Health check interval in seconds
+    pub interval_sec: u64,
+    /// Health check timeout in seconds
+    pub timeout_sec: u64,
+    /// Number of consecutive failures before marking unhealthy
+    pub failure_threshold: u32,
+    /// Number of consecutive successes before marking healthy
+    pub success_threshold: u32,
+    /// Consecutive failures on live traffic before a backend is ejected
+    /// (0 disables passive checking)
+    #[serde(default = "default_passive_failure_threshold")]
+    pub passive_failure_threshold: u32,
+    /// Share of the interval, in percent, each round of checks is spread
+    /// over with random jitter; 0 checks every backend at once
+    #[serde(default)]
+    pub jitter_percent: u32,
+    /// Eject backends whose error rate or latency stands out
+    #[serde(default)]
+    pub outlier_detection: Option<OutlierDetectionConfig>,
+    /// Fail fast on backends whose recent requests mostly failed
+    #[serde(default)]
+    pub circuit_breaker: Option<CircuitBreakerConfig>,
+    /// Push backend health state changes to a webhook
+    #[serde(default)]
+    pub notifications: Option<HealthNotificationsConfig>,
+}
+
+/// Health state change notification settings
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HealthNotificationsConfig {
+    /// `http://host:port/path` each state change is POSTed to as JSON
+    pub webhook_url: String,
+}
+
+impl HealthNotificationsConfig {
+    fn validate(&self) -> Result<(), ConfigError> {
+        let authority = self
+            .webhook_url
+            .strip_prefix("http://")
+            .map(|rest| rest.split('/').next().unwrap_or_default());
+        if authority.is_none_or(str::is_empty) {
+            return Err(ConfigError::ValidationError(format!(
+                "health notifications webhook_url must be an http:// URL: {}",
+                self.webhook_url
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Outlier detection settings
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OutlierDetectionConfig {
+    /// Eject when the error rate EWMA exceeds this fraction
+    #[serde(default = "default_error_rate_threshold")]
+    pub error_rate_threshold: f64,
+    /// Eject when the latency EWMA exceeds this multiple of the other
+    /// backends' average
+    #[serde(default = "default_latency_multiplier")]
+    pub latency_multiplier: f64,
+    /// Requests observed before a backend can be judged
+    #[serde(default = "default_min_requests")]
+    pub min_requests: u64,
+    /// How long an outlier stays ejected
+    #[serde(default = "default_cooldown_sec")]
+    pub cooldown_sec: u64,
+    /// Share of traffic a re-admitted backend starts with
+    #[serde(default = "default_ramp_up_percent")]
+    pub ramp_up_percent: u8,
+    /// Time for a re-admitted backend to grow back to its full share
+    #[serde(default = "default_ramp_up_sec")]
+    pub ramp_up_sec: u64,
+    /// Upper bound on the share of backends ejected at once
+    #[serde(default = "default_max_ejection_percent")]
+    pub max_ejection_percent: u8,
+}
+
+impl Default for OutlierDetectionConfig {
+    fn default() -> Self {
+        Self {
+            error_rate_threshold: default_error_rate_threshold(),
+            latency_multiplier: default_latency_multiplier(),
+            min_requests: default_min_requests(),
+            cooldown_sec: default_cooldown_sec(),
+            ramp_up_percent: default_ramp_up_percent(),
+            ramp_up_sec: default_ramp_up_sec(),
+            max_ejection_percent: default_max_ejection_percent(),
+        }
+    }
+}
+
+impl OutlierDetectionConfig {
+    fn validate(&self) -> Result<(), ConfigError> {
+        if !(self.error_rate_threshold > 0.0 && self.error_rate_threshold <= 1.0) {
+            return Err(ConfigError::ValidationError(
+                "outlier_detection error_rate_threshold must be in (0, 1]".to_string(),
+            ));
+        }
+        if self.latency_multiplier <= 1.0 {
+            return Err(ConfigError::ValidationError(
+                "outlier_detection latency_multiplier must be greater than 1".to_string(),
+            ));
+        }
+        if self.ramp_up_percent == 0 || self.ramp_up_percent > 100 {
+            return Err(ConfigError::ValidationError(
+                "outlier_detection ramp_up_percent must be between 1 and 100".to_string(),
+            ));
+        }
+        if self.max_ejection_percent > 100 {
+            return Err(ConfigError::ValidationError(
+                "outlier_detection max_ejection_percent must be at most 100".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn default_error_rate_threshold() -> f64 {
+    0.5
+}
+
+fn default_latency_multiplier() -> f64 {
+    3.0
+}
+
+fn default_min_requests() -> u64 {
+    20
+}
+
+fn default_cooldown_sec() -> u64 {
+    30
+}
+
+fn default_ramp_up_percent() -> u8 {
+    10
+}
+
+fn default_ramp_up_sec() -> u64 {
+    30
+}
+
+fn default_max_ejection_percent() -> u8 {
+    50
+}
+
+/// Circuit breaker settings
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CircuitBreakerConfig {
+    /// Open the circuit when this fraction of recent requests failed
+    #[serde(default = "default_failure_rate_threshold")]
+    pub failure_rate_threshold: f64,
+    /// Recent requests the failure rate is measured over
+    #[serde(default = "default_window_size")]
+    pub window_size: usize,
+    /// Requests in the window before the failure rate is judged
+    #[serde(default = "default_circuit_min_requests")]
+    pub min_requests: usize,
+    /// How long an open circuit refuses requests before probing
+    #[serde(default = "default_cooldown_sec")]
+    pub cooldown_sec: u64,
+    /// Probe requests let through a half-open circuit; all must succeed
+    /// for it to close
+    #[serde(default = "default_half_open_probes")]
+    pub half_open_probes: u32,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        Self {
+            failure_rate_threshold: default_failure_rate_threshold(),
+            window_size: default_window_size(),
+            min_requests: default_circuit_min_requests(),
+            cooldown_sec: default_cooldown_sec(),
+            half_open_probes: default_half_open_probes(),
+        }
+    }
+}
+
+impl CircuitBreakerConfig {
+    fn validate(&self) -> Result<(), ConfigError> {
+        if !(self.failure_rate_threshold > 0.0 && self.failure_rate_threshold <= 1.0) {
+            return Err(ConfigError::ValidationError(
+                "circuit_breaker failure_rate_threshold must be in (0, 1]".to_string(),
+            ));
+        }
+        if self.min_requests == 0 || self.min_requests > self.window_size {
+            return Err(ConfigError::ValidationError(
+                "circuit_breaker min_requests must be between 1 and window_size".to_string(),
+            ));
+        }
+        if self.cooldown_sec == 0 {
+            return Err(ConfigError::ValidationError(
+                "circuit_breaker cooldown_sec must be greater than 0".to_string(),
+            ));
+        }
+        if self.half_open_probes == 0 {
+            return Err(ConfigError::ValidationError(
+                "circuit_breaker half_open_probes must be greater than 0".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn default_failure_rate_threshold() -> f64 {
+    0.5
+}
+
+fn default_window_size() -> usize {
+    20
+}
+
+fn default_circuit_min_requests() -> usize {
+    10
+}
+
+fn default_half_open_probes() -> u32 {
+    3
+}
+
+/// OpenTelemetry tracing settings
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TelemetryConfig {
+    /// OTLP/HTTP endpoint spans are exported to
+    #[serde(default = "default_otlp_endpoint")]
+    pub otlp_endpoint: String,
+    /// `service.name` reported for the proxy
+    #[serde(default = "default_service_name")]
+    pub service_name: String,
+    /// Fraction of new traces that are sampled
+    #[serde(default = "default_sample_ratio")]
+    pub sample_ratio: f64,
+    /// Extra resource attributes attached to every span
+    #[serde(default)]
+    pub resource_attributes: HashMap<String, String>,
+}
+
+impl Default for TelemetryConfig {
+    fn default() -> Self {
+        Self {
+            otlp_endpoint: default_otlp_endpoint(),
+            service_name: default_service_name(),
+            sample_ratio: default_sample_ratio(),
+            resource_attributes: HashMap::new(),
+        }
+    }
+}
+
+impl TelemetryConfig {
+    fn validate(&self) -> Result<(), ConfigError> {
+        if !self.otlp_endpoint.starts_with("http://") && !self.otlp_endpoint.starts_with("https://") {
+            return Err(ConfigError::ValidationError(format!(
+                "telemetry otlp_endpoint must be an http(s) URL: {}",
+                self.otlp_endpoint
+            )));
+        }
+        if !(0.0..=1.0).contains(&self.sample_ratio) {
+            return Err(ConfigError::ValidationError(
+                "telemetry sample_ratio must be between 0 and 1".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn default_otlp_endpoint() -> String {
+    "http://localhost:4318/v1/traces".to_string()
+}
+
+fn default_service_name() -> String {
+    "puerta".to_string()
+}
+
+fn default_sample_ratio() -> f64 {
+    1.0
+}
+
+/// StatsD metrics push configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatsdConfig {
+    /// `host:port` of the StatsD agent, sent UDP datagrams
+    #[serde(default = "default_statsd_address")]
+    pub address: String,
+    /// Prefix of every metric name, joined with a dot
+    #[serde(default = "default_service_name")]
+    pub prefix: String,
+    /// Seconds between flushes
+    #[serde(default = "default_statsd_flush_interval_sec")]
+    pub flush_interval_sec: u64,
+    /// Send labels as DogStatsD tags instead of appending them to the name
+    #[serde(default)]
+    pub dogstatsd: bool,
+    /// Tags added to every metric, DogStatsD only
+    #[serde(default)]
+    pub tags: HashMap<String, String>,
+}
+
+impl Default for StatsdConfig {
+    fn default() -> Self {
+        Self {
+            address: default_statsd_address(),
+            prefix: default_service_name(),
+            flush_interval_sec: default_statsd_flush_interval_sec(),
+            dogstatsd: false,
+            tags: HashMap::new(),
+        }
+    }
+}
+
+impl StatsdConfig {
+    fn validate(&self) -> Result<(), ConfigError> {
+        let port = self.address.rsplit_once(':').map(|(host, port)| (host, port.parse::<u16>()));
+        if !matches!(port, Some((host, Ok(_))) if !host.is_empty()) {
+            return Err(ConfigError::ValidationError(format!(
+                "statsd address must be host:port: {}",
+                self.address
+            )));
+        }
+        if self.flush_interval_sec == 0 {
+            return Err(ConfigError::ValidationError(
+                "statsd flush_interval_sec must be greater than 0".to_string(),
+            ));
+        }
+        if !self.tags.is_empty() && !self.dogstatsd {
+            return Err(ConfigError::ValidationError(
+                "statsd tags require dogstatsd = true".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn default_statsd_address() -> String {
+    "127.0.0.1:8125".to_string()
+}
+
+fn default_statsd_flush_interval_sec() -> u64 {
+    10
+}
+
+/// Logging configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoggingConfig {
+    /// Log level (error, warn, info, debug, trace)
+    pub level: String,
+    /// Log format (json, text), used for both the application and access logs
+    pub format: String,
+    /// Log to stdout; stderr is used otherwise
+    pub stdout: bool,
+    /// Access log file path (optional); one record is appended per client
+    /// connection
+    pub file: Option<String>,
+}
+
+impl LoggingConfig {
+    /// Convert the configured log level into a `log::LevelFilter`
+    pub fn level_filter(&self) -> log::LevelFilter {
+        match self.level.as_str() {
+            "error" => log::LevelFilter::Error,
+            "warn" => log::LevelFilter::Warn,
+            "debug" => log::LevelFilter::Debug,
+            "trace" => log::LevelFilter::Trace,
+            _ => log::LevelFilter::Info,
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            server: ServerConfig {
+                listen_addr: "0.0.0.0:8080".to_string(),
+                max_connections: 10000,
+                connection_queue_timeout_ms: 0,
+                proxy_protocol: false,
+                connection_timeout_sec: 60,
+                buffer_size: default_buffer_size(),
+                write_high_watermark: default_write_high_watermark(),
+                worker_threads: None, // Use system default
+                dns_refresh_sec: default_dns_refresh_sec(),
+                daemon: None, // Daemon mode disabled by default
+                tls: None,    // Plaintext listener by default
+                tuning: ServerTuningConfig::default(),
+            },
+            proxy: ProxyConfig::MongoDB {
+                mongos_endpoints: vec!["127.0.0.1:27017".into()],
+                session_affinity: true,
+                session_timeout_sec: 3600,
+                max_message_size: default_max_message_size(),
+                tls: None,
+                balance_strategy: BalanceStrategy::default(),
+                client_identification: ClientIdentificationStrategy::default(),
+                read_preference_routes: Vec::new(),
+                affinity_rules: Vec::new(),
+                rebalance: None,
+                on_backend_unhealthy: UnhealthyBackendAction::default(),
+                compressors: None,
+                spare_connections: 0,
+                retry_attempts: default_retry_attempts(),
+                max_connections_per_backend: None,
+                backend_queue_timeout_ms: 0,
+                large_payloads: None,
+                mirror: None,
+                capture: None,
+                zero_copy: false,
+                audit: false,
+                slow_operation_threshold_ms: None,
+                cursor_affinity: false,
+                transaction_pinning: false,
+                announce_addr: None,
+            },
+            proxies: Vec::new(),
+            health: HealthConfig {
+                interval_sec: 10,
+                timeout_sec: 5,
+                failure_threshold: 3,
+                success_threshold: 2,
+                passive_failure_threshold: default_passive_failure_threshold(),
+                jitter_percent: 0,
+                outlier_detection: None,
+                circuit_breaker: None,
+                notifications: None,
+            },
+            logging: LoggingConfig {
+                level: "info".to_string(),
+                format: "text".to_string(),
+                stdout: true,
+                file: None,
+            },
+            telemetry: None,
+            statsd: None,
+            admin: None,
+            acl: None,
+            discovery: None,
+            chaos: None,
+            maintenance: Vec::new(),
+        }
+    }
+}
+
+impl Config {
+    /// Load configuration from TOML file
+    pub fn load_from_file<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
+        let content = fs::read_to_string(path).map_err(|e| ConfigError::IoError(e.to_string()))?;
+
+        let mut config: Config =
+            toml::from_str(&content).map_err(|e| ConfigError::ParseError(e.to_string()))?;
+
+        config.resolve_secrets()?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Replace credentials given as `${VAR}` references or `*_file` paths
+    /// with their values
+    pub fn resolve_secrets(&mut self) -> Result<(), ConfigError> {
+        let proxies = std::iter::once(&mut self.proxy)
+            .chain(self.proxies.iter_mut().map(|instance| &mut instance.proxy));
+        for proxy in proxies {
+            if let ProxyConfig::Redis {
+                auth, client_auth, ..
+            } = proxy
+            {
+                if let Some(auth) = auth {
+                    auth.resolve_secrets("auth")?;
+                }
+                if let Some(client_auth) = client_auth {
+                    client_auth.resolve_secrets("client_auth")?;
+                }
+            }
+        }
+        if let Some(discovery) = &mut self.discovery {
+            discovery.resolve_secrets()?;
+        }
+        if let Some(admin) = &mut self.admin {
+            admin.resolve_secrets()?;
+        }
+        Ok(())
+    }
+
+    /// Save configuration to TOML file
+    pub fn save_to_file<P: AsRef<Path>>(&self, path: P) -> Result<(), ConfigError> {
+        let content =
+            toml::to_string_pretty(self).map_err(|e| ConfigError::SerializeError(e.to_string()))?;
+
+        fs::write(path, content).map_err(|e| ConfigError::IoError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    /// Validate configuration
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        // Validate server config
+        if self.server.max_connections == 0 {
+            return Err(ConfigError::ValidationError(
+                "max_connections must be greater than 0".to_string(),
+            ));
+        }
+
+        if self.server.connection_timeout_sec == 0 {
+            return Err(ConfigError::ValidationError(
+                "connection_timeout_sec must be greater than 0".to_string(),
+            ));
+        }
+
+        if self.server.buffer_size == 0 {
+            return Err(ConfigError::ValidationError(
+                "buffer_size must be greater than 0".to_string(),
+            ));
+        }
+
+        if self.server.write_high_watermark < self.server.buffer_size {
+            return Err(ConfigError::ValidationError(
+                "write_high_watermark must be at least buffer_size".to_string(),
+            ));
+        }
+
+        if self.server.worker_threads == Some(0) {
+            return Err(ConfigError::ValidationError(
+                "worker_threads must be greater than 0".to_string(),
+            ));
+        }
+
+        if self.server.dns_refresh_sec == 0 {
+            return Err(ConfigError::ValidationError(
+                "dns_refresh_sec must be greater than 0".to_string(),
+            ));
+        }
+
+        let tuning = &self.server.tuning;
+        for tcp_keepalive in [&tuning.tcp_keepalive, &tuning.backend_tcp_keepalive]
+            .into_iter()
+            .flatten()
+        {
+            tcp_keepalive.validate()?;
+        }
+
+        if let Some(tls) = &self.server.tls {
+            for (name, path) in [("cert_path", &tls.cert_path), ("key_path", &tls.key_path)] {
+                if path.trim().is_empty() {
+                    return Err(ConfigError::ValidationError(format!(
+                        "tls {name} cannot be empty"
+                    )));
+                }
+                if !Path::new(path).exists() {
+                    return Err(ConfigError::ValidationError(format!(
+                        "tls {name} does not exist: {path}"
+                    )));
+                }
+            }
+        }
+
+        // Validate proxy config
+        self.proxy.validate(self.discovery.is_some())?;
+
+        // Validate additional proxy instances
+        let mut listen_addrs = vec![self.server.listen_addr.as_str()];
+        let mut names = Vec::new();
+        for instance in &self.proxies {
+            if instance.name.trim().is_empty() {
+                return Err(ConfigError::ValidationError(
+                    "proxies name cannot be empty".to_string(),
+                ));
+            }
+            if names.contains(&instance.name.as_str()) {
+                return Err(ConfigError::ValidationError(format!(
+                    "duplicate proxies name: {}",
+                    instance.name
+                )));
+            }
+            names.push(instance.name.as_str());
+
+            if listen_addrs.contains(&instance.listen_addr.as_str()) {
+                return Err(ConfigError::ValidationError(format!(
+                    "proxy {} listen_addr {} is already in use",
+                    instance.name, instance.listen_addr
+                )));
+            }
+            listen_addrs.push(instance.listen_addr.as_str());
+
+            // Discovery only supplies the backends of the primary [proxy]
+            instance.proxy.validate(false).map_err(|e| match e {
+                ConfigError::ValidationError(msg) => {
+                    ConfigError::ValidationError(format!("proxy {}: {msg}", instance.name))
+                }
+                e => e,
+            })?;
+        }
+
+        // Validate health config
+        if self.health.interval_sec == 0 {
+            return Err(ConfigError::ValidationError(
+                "health check interval_sec must be greater than 0".to_string(),
+            ));
+        }
+
+        if self.health.timeout_sec == 0 {
+            return Err(ConfigError::ValidationError(
+                "health check timeout_sec must be greater than 0".to_string(),
+            ));
+        }
+
+        if self.health.timeout_sec >= self.health.interval_sec {
+            return Err(ConfigError::ValidationError(
+                "health check timeout_sec must be less than interval_sec".to_string(),
+            ));
+        }
+
+        if self.health.failure_threshold == 0 || self.health.success_threshold == 0 {
+            return Err(ConfigError::ValidationError(
+                "health check failure_threshold and success_threshold must be greater than 0"
+                    .to_string(),
+            ));
+        }
+
+        if self.health.jitter_percent > crate::health::MAX_JITTER_PERCENT {
+            return Err(ConfigError::ValidationError(format!(
+                "health check jitter_percent must be at most {}",
+                crate::health::MAX_JITTER_PERCENT
+            )));
+        }
+
+        let instances = self.proxies.iter().map(|instance| &instance.proxy);
+        for proxy in std::iter::once(&self.proxy).chain(instances) {
+            let ProxyConfig::MongoDB {
+                mongos_endpoints, ..
+            } = proxy
+            else {
+                continue;
+            };
+            for endpoint in mongos_endpoints {
+                let Some(health_check) = &endpoint.health_check else {
+                    continue;
+                };
+                let interval_sec = health_check.interval_sec.unwrap_or(self.health.interval_sec);
+                if health_check.timeout_sec.is_some_and(|timeout_sec| timeout_sec >= interval_sec) {
+                    return Err(ConfigError::ValidationError(format!(
+                        "mongos endpoint {} health check timeout_sec must be less than interval_sec",
+                        endpoint.addr
+                    )));
+                }
+            }
+        }
+
+        if let Some(outlier_detection) = &self.health.outlier_detection {
+            outlier_detection.validate()?;
+        }
+
+        if let Some(notifications) = &self.health.notifications {
+            notifications.validate()?;
+        }
+
+        if let Some(circuit_breaker) = &self.health.circuit_breaker {
+            circuit_breaker.validate()?;
+        }
+
+        // Validate logging config
+        match self.logging.level.as_str() {
+            "error" | "warn" | "info" | "debug" | "trace" => {}
+            _ => {
+                return Err(ConfigError::ValidationError(format!(
+                    "Invalid log level: {}",
+                    self.logging.level
+                )))
+            }
+        }
+
+        match self.logging.format.as_str() {
+            "json" | "text" => {}
+            _ => {
+                return Err(ConfigError::ValidationError(format!(
+                    "Invalid log format: {}",
+                    self.logging.format
+                )))
+            }
+        }
+
+        if let Some(telemetry) = &self.telemetry {
+            telemetry.validate()?;
+        }
+
+        if let Some(statsd) = &self.statsd {
+            statsd.validate()?;
+        }
+
+        if let Some(discovery) = &self.discovery {
+            discovery.validate()?;
+        }
+
+        if let Some(chaos) = &self.chaos {
+            chaos.validate()?;
+        }
+
+        for window in &self.maintenance {
+            window.validate()?;
+        }
+
+        if let Some(acl) = &self.acl {
+            crate::acl::AclRules::from_config(acl)
+                .map_err(|e| ConfigError::ValidationError(format!("acl: {e}")))?;
+        }
+
+        if let Some(admin) = &self.admin {
+            admin.listen_addr.parse::<std::net::SocketAddr>().map_err(|_| {
+                ConfigError::ValidationError(format!(
+                    "Invalid admin listen_addr: {}",
+                    admin.listen_addr
+                ))
+            })?;
+            if admin.token.as_ref().is_some_and(|token| token.trim().is_empty()) {
+                return Err(ConfigError::ValidationError("admin token cannot be empty".to_string()));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Create example configuration file
+    pub fn create_example_config<P: AsRef<Path>>(path: P, mode: &str) -> Result<(), ConfigError> {
+        let config = match mode {
+            "mongodb" => Config {
+                proxy: ProxyConfig::MongoDB {
+                    mongos_endpoints: vec![
+                        "10.0.1.10:27017".into(),
+                        "10.0.1.11:27017".into(),
+                        "10.0.1.12:27017".into(),
+                    ],
+                    session_affinity: true,
+                    session_timeout_sec: 3600,
+                    max_message_size: default_max_message_size(),
+                    tls: None,
+                    balance_strategy: BalanceStrategy::default(),
+                    client_identification: ClientIdentificationStrategy::default(),
+                    read_preference_routes: Vec::new(),
+                    affinity_rules: Vec::new(),
+                    rebalance: None,
+                    on_backend_unhealthy: UnhealthyBackendAction::default(),
+                    compressors: None,
+                    spare_connections: 0,
+                    retry_attempts: default_retry_attempts(),
+                    max_connections_per_backend: None,
+                    backend_queue_timeout_ms: 0,
+                    large_payloads: None,
+                    mirror: None,
+                    capture: None,
+                    zero_copy: false,
+                    audit: false,
+                    slow_operation_threshold_ms: None,
+                    cursor_affinity: false,
+                    transaction_pinning: false,
+                    announce_addr: None,
+                },
+                ..Default::default()
+            },
+            "redis" => Config {
+                proxy: ProxyConfig::Redis {
+                    cluster_nodes: vec![
+                        "10.0.1.20:6379".into(),
+                        "10.0.1.21:6379".into(),
+                        "10.0.1.22:6379".into(),
+                    ],
+                    slot_refresh_interval_sec: 60,
+                    max_redirects: 3,
+                    connection_timeout_ms: 5000,
+                    pool_size: default_pool_size(),
+                    pool_idle_timeout_sec: default_pool_idle_timeout_sec(),
+                    pool_max_wait_ms: default_pool_max_wait_ms(),
+                    pool_min_idle: 0,
+                    retry_attempts: default_retry_attempts(),
+                    failover_timeout_ms: default_failover_timeout_ms(),
+                    tls: None,
+                    auth: None,
+                    client_auth: None,
+                    read_from_replicas: false,
+                    balance_strategy: BalanceStrategy::default(),
+                    sharding: RedisSharding::default(),
+                    topology_agreement: TopologyAgreement::default(),
+                    slowlog_threshold_us: default_slowlog_threshold_us(),
+                    slowlog_max_len: default_slowlog_max_len(),
+                    fan_out_keyless_commands: true,
+                    command_filter: None,
+                    large_payloads: None,
+                    mirror: None,
+                    capture: None,
+                    cluster_view: None,
+                    snapshot: None,
+                    max_command_size: default_max_resp_size(),
+                    max_reply_size: default_max_resp_size(),
+                },
+                ..Default::default()
+            },
+            _ => {
+                return Err(ConfigError::ValidationError(
+                    "Mode must be 'mongodb' or 'redis'".to_string(),
+                ))
+            }
+        };
+
+        config.save_to_file(path)
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_default_config() {
+        let config = Config::default();
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_config_validation() {
+        let mut config = Config::default();
+
+        // Test invalid max_connections
+        config.server.max_connections = 0;
+        assert!(config.validate().is_err());
+
+        config.server.max_connections = 1000;
+        assert!(config.validate().is_ok());
+
+        // The high watermark must hold at least one read
+        config.server.buffer_size = 0;
+        assert!(config.validate().is_err());
+        config.server.buffer_size = 64 * 1024;
+        config.server.write_high_watermark = 32 * 1024;
+        assert!(config.validate().is_err());
+        config.server.write_high_watermark = 64 * 1024;
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_config_serialization() {
+        let config = Config::default();
+        let toml_str = toml::to_string(&config).unwrap();
+        let parsed_config: Config = toml::from_str(&toml_str).unwrap();
+        assert!(parsed_config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_config_file_operations() {
+        let config = Config::default();
+        let temp_file = NamedTempFile::new().unwrap();
+
+        // Test save and load
+        config.save_to_file(temp_file.path()).unwrap();
+        let loaded_config = Config::load_from_file(temp_file.path()).unwrap();
+        assert!(loaded_config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_redis_pool_settings_default_when_omitted() {
+        let toml_str = r#"
+[server]
+listen_addr = "0.0.0.0:6379"
+max_connections = 1000
+connection_timeout_sec = 30
+
+[proxy]
+mode = "redis"
+cluster_nodes = ["127.0.0.1:7001"]
+slot_refresh_interval_sec = 60
+max_redirects = 3
+connection_timeout_ms = 5000
+
+[health]
+interval_sec = 10
+timeout_sec = 5
+failure_threshold = 3
+success_threshold = 2
+
+[logging]
+level = "info"
+format = "text"
+stdout = true
+"#;
+
+        let config: Config = toml::from_str(toml_str).unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(config.server.buffer_size, 8192);
+        assert_eq!(config.server.write_high_watermark, 1024 * 1024);
+        match config.proxy {
+            ProxyConfig::Redis {
+                pool_size,
+                pool_idle_timeout_sec,
+                pool_max_wait_ms,
+                pool_min_idle,
+                retry_attempts,
+                failover_timeout_ms,
+                fan_out_keyless_commands,
+                max_command_size,
+                max_reply_size,
+                ..
+            } => {
+                assert_eq!(pool_size, 64);
+                assert_eq!(pool_idle_timeout_sec, 300);
+                assert_eq!(pool_max_wait_ms, 1000);
+                assert_eq!(pool_min_idle, 0);
+                assert_eq!(retry_attempts, 2);
+                assert_eq!(failover_timeout_ms, 5000);
+                assert!(fan_out_keyless_commands);
+                assert_eq!(max_command_size, 512 * 1024 * 1024);
+                assert_eq!(max_reply_size, 512 * 1024 * 1024);
+            }
+            _ => panic!("Expected Redis proxy config"),
+        }
+
+        // More idle connections than the pool holds is rejected
+        let config: Config = toml::from_str(&toml_str.replace(
+            "max_redirects = 3\n",
+            "max_redirects = 3\npool_size = 4\npool_min_idle = 8\n",
+        ))
+        .unwrap();
+        assert!(config.validate().is_err());
+
+        // Size limits must let some command through
+        let config: Config =
+            toml::from_str(&toml_str.replace("max_redirects = 3\n", "max_redirects = 3\nmax_command_size = 0\n"))
+                .unwrap();
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_tls_config_validation() {
+        let mut config = Config::default();
+        config.server.tls = Some(TlsConfig {
+            cert_path: "/nonexistent/cert.pem".to_string(),
+            key_path: "/nonexistent/key.pem".to_string(),
+        });
+        assert!(config.validate().is_err());
+
+        let cert = NamedTempFile::new().unwrap();
+        let key = NamedTempFile::new().unwrap();
+        config.server.tls = Some(TlsConfig {
+            cert_path: cert.path().to_string_lossy().to_string(),
+            key_path: key.path().to_string_lossy().to_string(),
+        });
+        assert!(config.validate().is_ok());
+
+        // Round-trips through TOML
+        let toml_str = toml::to_string(&config).unwrap();
+        let parsed: Config = toml::from_str(&toml_str).unwrap();
+        assert!(parsed.server.tls.is_some());
+    }
+
+    #[test]
+    fn test_redis_auth_parsing_and_redaction() {
+        let toml_str = r#"
+[server]
+listen_addr = "0.0.0.0:6379"
+max_connections = 1000
+connection_timeout_sec = 30
+
+[proxy]
+mode = "redis"
+cluster_nodes = ["127.0.0.1:7001"]
+slot_refresh_interval_sec = 60
+max_redirects = 3
+connection_timeout_ms = 5000
+
+[proxy.auth]
+username = "puerta"
+password = "cluster-secret"
+
+[proxy.client_auth]
+password = ""
+
+[health]
+interval_sec = 10
+timeout_sec = 5
+failure_threshold = 3
+success_threshold = 2
+
+[logging]
+level = "info"
+format = "text"
+stdout = true
+"#;
+
+        let config: Config = toml::from_str(toml_str).unwrap();
+        // Empty client password is rejected
+        assert!(config.validate().is_err());
+
+        match &config.proxy {
+            ProxyConfig::Redis { auth: Some(auth), .. } => {
+                assert_eq!(auth.username.as_deref(), Some("puerta"));
+                assert_eq!(auth.password, "cluster-secret");
+            }
+            _ => panic!("Expected Redis proxy config with auth"),
+        }
+
+        // Passwords never show up in debug output (e.g. startup logs)
+        assert!(!format!("{:?}", config.proxy).contains("cluster-secret"));
+    }
+
+    #[test]
+    fn test_secrets_resolved_on_load() {
+        use std::io::Write;
+
+        let mut password_file = tempfile::NamedTempFile::new().unwrap();
+        writeln!(password_file, "file-secret").unwrap();
+        std::env::set_var("PUERTA_TEST_REDIS_USER", "puerta");
+        std::env::set_var("PUERTA_TEST_CONSUL_TOKEN", "consul-secret");
+        std::env::set_var("PUERTA_TEST_ADMIN_TOKEN", "admin-secret");
+        let toml_str = format!(
+            r#"
+[server]
+listen_addr = "0.0.0.0:6379"
+max_connections = 1000
+connection_timeout_sec = 30
+
+[proxy]
+mode = "redis"
+cluster_nodes = ["127.0.0.1:7001"]
+slot_refresh_interval_sec = 60
+max_redirects = 3
+connection_timeout_ms = 5000
+
+[proxy.auth]
+username = "${{PUERTA_TEST_REDIS_USER}}"
+password_file = "{}"
+
+[discovery]
+type = "consul"
+address = "http://127.0.0.1:8500"
+service = "redis"
+token = "${{PUERTA_TEST_CONSUL_TOKEN}}"
+
+[admin]
+listen_addr = "127.0.0.1:9090"
+token = "${{PUERTA_TEST_ADMIN_TOKEN}}"
+
+[health]
+interval_sec = 10
+timeout_sec = 5
+failure_threshold = 3
+success_threshold = 2
+
+[logging]
+level = "info"
+format = "text"
+stdout = true
+"#,
+            password_file.path().display()
+        );
+        let mut config_file = tempfile::NamedTempFile::new().unwrap();
+        config_file.write_all(toml_str.as_bytes()).unwrap();
+
+        let config = Config::load_from_file(config_file.path()).unwrap();
+        match &config.proxy {
+            ProxyConfig::Redis { auth: Some(auth), .. } => {
+                assert_eq!(auth.username.as_deref(), Some("puerta"));
+                assert_eq!(auth.password, "file-secret");
+            }
+            _ => panic!("Expected Redis proxy config with auth"),
+        }
+        let discovery = config.discovery.as_ref().unwrap();
+        assert_eq!(discovery.token.as_deref(), Some("consul-secret"));
+        assert_eq!(config.admin.as_ref().unwrap().token.as_deref(), Some("admin-secret"));
+        let debug = format!("{config:?}");
+        assert!(!debug.contains("file-secret") && !debug.contains("consul-secret"));
+        assert!(!debug.contains("admin-secret"));
+
+        // Unset variables fail the load
+        let unset = toml_str.replace("PUERTA_TEST_CONSUL_TOKEN", "PUERTA_TEST_UNSET_TOKEN");
+        let mut config_file = tempfile::NamedTempFile::new().unwrap();
+        config_file.write_all(unset.as_bytes()).unwrap();
+        assert!(Config::load_from_file(config_file.path()).is_err());
+    }
+
+    #[test]
+    fn test_redis_command_filter() {
+        let toml_str = r#"
+[server]
+listen_addr = "0.0.0.0:6379"
+max_connections = 1000
+connection_timeout_sec = 30
+
+[proxy]
+mode = "redis"
+cluster_nodes = ["127.0.0.1:7001"]
+slot_refresh_interval_sec = 60
+max_redirects = 3
+connection_timeout_ms = 5000
+
+[proxy.command_filter]
+deny = ["FLUSHALL", "keys", "CONFIG"]
+
+[health]
+interval_sec = 10
+timeout_sec = 5
+failure_threshold = 3
+success_threshold = 2
+
+[logging]
+level = "info"
+format = "text"
+stdout = true
+"#;
+
+        let mut config: Config = toml::from_str(toml_str).unwrap();
+        assert!(config.validate().is_ok());
+        let ProxyConfig::Redis {
+            command_filter: Some(command_filter),
+            ..
+        } = &mut config.proxy
+        else {
+            panic!("Expected Redis proxy config with a command filter");
+        };
+        assert!(command_filter.allow.is_empty());
+        assert_eq!(command_filter.deny, vec!["FLUSHALL", "keys", "CONFIG"]);
+
+        command_filter.allow = vec!["Keys".to_string()];
+        assert!(config.validate().is_err());
+
+        let ProxyConfig::Redis {
+            command_filter: Some(command_filter),
+            ..
+        } = &mut config.proxy
+        else {
+            unreachable!();
+        };
+        command_filter.allow = vec!["GET SET".to_string()];
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_redis_cluster_view() {
+        let toml_str = r#"
+[server]
+listen_addr = "0.0.0.0:6379"
+max_connections = 1000
+connection_timeout_sec = 30
+
+[proxy]
+mode = "redis"
+cluster_nodes = ["127.0.0.1:7001"]
+slot_refresh_interval_sec = 60
+max_redirects = 3
+connection_timeout_ms = 5000
+
+[proxy.cluster_view]
+announce_addr = "redis-proxy.example.com:6379"
+
+[health]
+interval_sec = 10
+timeout_sec = 5
+failure_threshold = 3
+success_threshold = 2
+
+[logging]
+level = "info"
+format = "text"
+stdout = true
+"#;
+
+        let mut config: Config = toml::from_str(toml_str).unwrap();
+        assert!(config.validate().is_ok());
+        let ProxyConfig::Redis {
+            cluster_view: Some(cluster_view),
+            ..
+        } = &mut config.proxy
+        else {
+            panic!("Expected Redis proxy config with a cluster view");
+        };
+        cluster_view.announce_addr = Some("redis-proxy.example.com".to_string());
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_redis_snapshot() {
+        let toml_str = r#"
+[server]
+listen_addr = "0.0.0.0:6379"
+max_connections = 1000
+connection_timeout_sec = 30
+
+[proxy]
+mode = "redis"
+cluster_nodes = ["127.0.0.1:7001"]
+slot_refresh_interval_sec = 60
+max_redirects = 3
+connection_timeout_ms = 5000
+
+[proxy.snapshot]
+path = "/var/lib/puerta/topology.json"
+
+[health]
+interval_sec = 10
+timeout_sec = 5
+failure_threshold = 3
+success_threshold = 2
+
+[logging]
+level = "info"
+format = "text"
+stdout = true
+"#;
+
+        let mut config: Config = toml::from_str(toml_str).unwrap();
+        assert!(config.validate().is_ok());
+        let ProxyConfig::Redis {
+            snapshot: Some(snapshot),
+            sharding,
+            ..
+        } = &mut config.proxy
+        else {
+            panic!("Expected Redis proxy config with a snapshot");
+        };
+        assert_eq!(snapshot.interval_sec, 30);
+        assert_eq!(snapshot.max_age_sec, 3600);
+
+        *sharding = RedisSharding::Standalone;
+        assert!(config.validate().is_err());
+
+        let ProxyConfig::Redis {
+            snapshot: Some(snapshot),
+            sharding,
+            ..
+        } = &mut config.proxy
+        else {
+            unreachable!();
+        };
+        *sharding = RedisSharding::Cluster;
+        snapshot.interval_sec = 0;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_redis_topology_agreement() {
+        let toml_str = r#"
+[server]
+listen_addr = "0.0.0.0:6379"
+max_connections = 1000
+connection_timeout_sec = 30
+
+[proxy]
+mode = "redis"
+cluster_nodes = ["127.0.0.1:7001", "127.0.0.1:7002", "127.0.0.1:7003"]
+topology_agreement = { quorum = 2 }
+slot_refresh_interval_sec = 60
+max_redirects = 3
+connection_timeout_ms = 5000
+
+[health]
+interval_sec = 10
+timeout_sec = 5
+failure_threshold = 3
+success_threshold = 2
+
+[logging]
+level = "info"
+format = "text"
+stdout = true
+"#;
+
+        let mut config: Config = toml::from_str(toml_str).unwrap();
+        assert!(config.validate().is_ok());
+        let ProxyConfig::Redis { topology_agreement, .. } = &mut config.proxy else {
+            panic!("Expected Redis proxy config");
+        };
+        assert_eq!(*topology_agreement, TopologyAgreement::Quorum(2));
+
+        // More nodes than are configured can never agree
+        *topology_agreement = TopologyAgreement::Quorum(4);
+        assert!(config.validate().is_err());
+
+        let config: Config = toml::from_str(
+            &toml_str.replace("topology_agreement = { quorum = 2 }", r#"topology_agreement = "highest_epoch""#),
+        )
+        .unwrap();
+        assert!(matches!(
+            config.proxy,
+            ProxyConfig::Redis {
+                topology_agreement: TopologyAgreement::HighestEpoch,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_redis_standalone_sharding() {
+        let toml_str = r#"
+[server]
+listen_addr = "0.0.0.0:6379"
+max_connections = 1000
+connection_timeout_sec = 30
+
+[proxy]
+mode = "redis"
+cluster_nodes = ["127.0.0.1:6380", { addr = "127.0.0.1:6381", weight = 2 }]
+sharding = "standalone"
+slot_refresh_interval_sec = 60
+max_redirects = 3
+connection_timeout_ms = 5000
+
+[health]
+interval_sec = 10
+timeout_sec = 5
+failure_threshold = 3
+success_threshold = 2
+
+[logging]
+level = "info"
+format = "text"
+stdout = true
+"#;
+
+        let mut config: Config = toml::from_str(toml_str).unwrap();
+        assert!(config.validate().is_ok());
+        let ProxyConfig::Redis {
+            sharding,
+            read_from_replicas,
+            ..
+        } = &mut config.proxy
+        else {
+            panic!("Expected Redis proxy config");
+        };
+        assert_eq!(*sharding, RedisSharding::Standalone);
+        *read_from_replicas = true;
+        assert!(config.validate().is_err());
+
+        // Cluster sharding when omitted
+        let config: Config = toml::from_str(&toml_str.replace("sharding = \"standalone\"\n", "")).unwrap();
+        assert!(matches!(
+            config.proxy,
+            ProxyConfig::Redis {
+                sharding: RedisSharding::Cluster,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_upstream_tls_validation() {
+        let ca = NamedTempFile::new().unwrap();
+        let mut tls = UpstreamTlsConfig {
+            ca_file: Some(ca.path().to_string_lossy().to_string()),
+            cert_path: None,
+            key_path: None,
+            sni: Some("redis.example.com".to_string()),
+            verify_cert: true,
+            verify_hostname: true,
+        };
+        assert!(tls.validate().is_ok());
+
+        // Client cert without key
+        tls.cert_path = tls.ca_file.clone();
+        assert!(tls.validate().is_err());
+
+        tls.key_path = Some("/nonexistent/client.key".to_string());
+        assert!(tls.validate().is_err());
+    }
+
+    #[test]
+    fn test_upstream_tls_section_parsing() {
+        let toml_str = r#"
+[server]
+listen_addr = "0.0.0.0:6379"
+max_connections = 1000
+connection_timeout_sec = 30
+
+[proxy]
+mode = "redis"
+cluster_nodes = ["127.0.0.1:7001"]
+slot_refresh_interval_sec = 60
+max_redirects = 3
+connection_timeout_ms = 5000
+
+[proxy.tls]
+ca_file = "/etc/puerta/tls/ca.pem"
+verify_hostname = false
+
+[health]
+interval_sec = 10
+timeout_sec = 5
+failure_threshold = 3
+success_threshold = 2
+
+[logging]
+level = "info"
+format = "text"
+stdout = true
+"#;
+
+        let config: Config = toml::from_str(toml_str).unwrap();
+        match config.proxy {
+            ProxyConfig::Redis { tls: Some(tls), .. } => {
+                assert_eq!(tls.ca_file.as_deref(), Some("/etc/puerta/tls/ca.pem"));
+                assert!(tls.sni.is_none());
+                assert!(tls.verify_cert);
+                assert!(!tls.verify_hostname);
+            }
+            _ => panic!("Expected Redis proxy config with upstream TLS"),
+        }
+    }
+
+    #[test]
+    fn test_weighted_endpoints_and_balance_strategy() {
+        let toml_str = r#"
+[server]
+listen_addr = "0.0.0.0:27016"
+max_connections = 1000
+connection_timeout_sec = 30
+
+[proxy]
+mode = "mongodb"
+mongos_endpoints = [
+    "127.0.0.1:27017",
+    { addr = "127.0.0.1:27018", weight = 3 },
+    { addr = "127.0.0.1:27019" },
+]
+session_affinity = true
+session_timeout_sec = 1800
+balance_strategy = "least_connections"
+client_identification = "session_id"
+
+[health]
+interval_sec = 10
+timeout_sec = 5
+failure_threshold = 3
+success_threshold = 2
+
+[logging]
+level = "info"
+format = "text"
+stdout = true
+"#;
+
+        let mut config: Config = toml::from_str(toml_str).unwrap();
+        assert!(config.validate().is_ok());
+
+        let ProxyConfig::MongoDB {
+            mongos_endpoints,
+            balance_strategy,
+            client_identification,
+            ..
+        } = &config.proxy
+        else {
+            panic!("Expected MongoDB proxy config");
+        };
+        assert_eq!(*balance_strategy, BalanceStrategy::LeastConnections);
+        assert_eq!(*client_identification, ClientIdentificationStrategy::SessionId);
+        let weights: Vec<usize> = mongos_endpoints.iter().map(|e| e.weight).collect();
+        assert_eq!(weights, vec![1, 3, 1]);
+        assert_eq!(Endpoint::weights(mongos_endpoints)["127.0.0.1:27018"], 3);
+        assert_eq!(mongos_endpoints[1].to_string(), "127.0.0.1:27018 (weight 3)");
+
+        // Unweighted endpoints are written back as plain strings
+        let serialized = toml::to_string(&config).unwrap();
+        assert!(serialized.contains("\"127.0.0.1:27017\""));
+        let reparsed: Config = toml::from_str(&serialized).unwrap();
+        match &reparsed.proxy {
+            ProxyConfig::MongoDB {
+                mongos_endpoints, ..
+            } => assert_eq!(mongos_endpoints[1], Endpoint::new("127.0.0.1:27018", 3)),
+            _ => panic!("Expected MongoDB proxy config"),
+        }
+
+        if let ProxyConfig::MongoDB {
+            mongos_endpoints, ..
+        } = &mut config.proxy
+        {
+            mongos_endpoints[0].weight = 0;
+        }
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_hostname_endpoints() {
+        let mut config = Config::default();
+        let set_endpoints = |config: &mut Config, endpoints: &[&str]| {
+            if let ProxyConfig::MongoDB {
+                mongos_endpoints, ..
+            } = &mut config.proxy
+            {
+                *mongos_endpoints = endpoints.iter().map(|addr| Endpoint::from(*addr)).collect();
+            }
+        };
+        set_endpoints(&mut config, &["mongos-1.db.internal:27017", "localhost:27018", "[::1]:27019"]);
+        assert!(config.validate().is_ok());
+        assert!(Endpoint::from("mongos-1.db.internal:27017").is_hostname());
+        assert!(!Endpoint::from("[::1]:27019").is_hostname());
+        assert_eq!(config.server.dns_refresh_sec, 30);
+
+        for invalid in ["mongos-1.db.internal", "mongos-1.db.internal:port", ":27017", "mongos 1:27017", "db..internal:27017"] {
+            set_endpoints(&mut config, &[invalid]);
+            assert!(config.validate().is_err(), "{invalid} accepted");
+        }
+
+        // Routes name single mongos, which a hostname may not be
+        set_endpoints(&mut config, &["mongos-1.db.internal:27017"]);
+        if let ProxyConfig::MongoDB {
+            read_preference_routes, ..
+        } = &mut config.proxy
+        {
+            read_preference_routes.push(ReadPreferenceRoute {
+                modes: vec![ReadPreferenceMode::Secondary],
+                endpoints: vec!["mongos-1.db.internal:27017".to_string()],
+            });
+        }
+        assert!(config.validate().is_err());
+
+        let mut config = Config::default();
+        config.server.dns_refresh_sec = 0;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_endpoint_health_checks() {
+        let mut config = Config::default();
+        let mut toml_str = toml::to_string(&config).unwrap();
+        toml_str = toml_str.replace(
+            "mongos_endpoints = [\"127.0.0.1:27017\"]",
+            r#"mongos_endpoints = [
+    "127.0.0.1:27017",
+    { addr = "10.1.0.5:27017", health_check = { interval_sec = 60, timeout_sec = 20, checker = "tcp" } },
+    { addr = "10.1.0.6:27017", health_check = { timeout_sec = 8 } },
+]"#,
+        );
+
+        let parsed: Config = toml::from_str(&toml_str).unwrap();
+        assert!(parsed.validate().is_ok());
+        let ProxyConfig::MongoDB {
+            mongos_endpoints, ..
+        } = &parsed.proxy
+        else {
+            panic!("Expected MongoDB proxy config");
+        };
+        let health_checks = Endpoint::health_checks(mongos_endpoints);
+        assert_eq!(health_checks.len(), 2);
+        assert_eq!(
+            health_checks["10.1.0.5:27017"],
+            EndpointHealthCheck {
+                interval_sec: Some(60),
+                timeout_sec: Some(20),
+                checker: HealthCheckKind::Tcp,
+            }
+        );
+        assert_eq!(health_checks["10.1.0.6:27017"].checker, HealthCheckKind::Protocol);
+
+        // Overrides survive a round trip
+        let reparsed: Config = toml::from_str(&toml::to_string(&parsed).unwrap()).unwrap();
+        assert_eq!(reparsed.proxy, parsed.proxy);
+
+        // The timeout must fit the endpoint's interval, or the default one
+        let health_check = EndpointHealthCheck {
+            timeout_sec: Some(config.health.interval_sec),
+            ..Default::default()
+        };
+        if let ProxyConfig::MongoDB {
+            mongos_endpoints, ..
+        } = &mut config.proxy
+        {
+            mongos_endpoints[0] = Endpoint::from("127.0.0.1:27017").with_health_check(health_check);
+        }
+        assert!(config.validate().is_err());
+
+        if let ProxyConfig::MongoDB {
+            mongos_endpoints, ..
+        } = &mut config.proxy
+        {
+            mongos_endpoints[0].health_check = Some(EndpointHealthCheck {
+                interval_sec: Some(0),
+                ..Default::default()
+            });
+        }
+        assert!(config.validate().is_err());
+    }
 
     #[test]
-    fn test_config_file_operations() {
-        let config = Config::default();
-        let temp_file = NamedTempFile::new().unwrap();
+    fn test_read_preference_routes() {
+        let toml_str = r#"
+[server]
+listen_addr = "0.0.0.0:27017"
+max_connections = 1000
+connection_timeout_sec = 30
 
-        // Test save and load
-        config.save_to_file(temp_file.path()).unwrap();
-        let loaded_config = Config::load_from_file(temp_file.path()).unwrap();
-        assert!(loaded_config.validate().is_ok());
+[proxy]
+mode = "mongodb"
+mongos_endpoints = ["127.0.0.1:27017", "127.0.0.1:27018", "127.0.0.1:27019"]
+session_affinity = true
+session_timeout_sec = 1800
+
+[[proxy.read_preference_routes]]
+modes = ["primaryPreferred"]
+endpoints = ["127.0.0.1:27017"]
+
+[[proxy.read_preference_routes]]
+modes = ["secondaryPreferred", "secondary"]
+endpoints = ["127.0.0.1:27018", "127.0.0.1:27019"]
+
+[health]
+interval_sec = 10
+timeout_sec = 5
+failure_threshold = 3
+success_threshold = 2
+
+[logging]
+level = "info"
+format = "text"
+stdout = true
+"#;
+
+        let mut config: Config = toml::from_str(toml_str).unwrap();
+        assert!(config.validate().is_ok());
+
+        let ProxyConfig::MongoDB {
+            read_preference_routes,
+            ..
+        } = &mut config.proxy
+        else {
+            panic!("Expected MongoDB proxy config");
+        };
+        assert_eq!(read_preference_routes.len(), 2);
+        assert_eq!(
+            read_preference_routes[1].modes,
+            vec![ReadPreferenceMode::SecondaryPreferred, ReadPreferenceMode::Secondary]
+        );
+        assert_eq!("nearest".parse(), Ok(ReadPreferenceMode::Nearest));
+        assert!("closest".parse::<ReadPreferenceMode>().is_err());
+
+        // A mode may only be routed once
+        read_preference_routes[1].modes.push(ReadPreferenceMode::PrimaryPreferred);
+        assert!(config.validate().is_err());
+
+        // Route endpoints must be mongos endpoints unless discovery supplies them
+        if let ProxyConfig::MongoDB {
+            read_preference_routes,
+            ..
+        } = &mut config.proxy
+        {
+            read_preference_routes[1].modes.pop();
+            read_preference_routes[0].endpoints = vec!["127.0.0.1:27020".to_string()];
+        }
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_compressors() {
+        let mut config: Config = toml::from_str(
+            &toml::to_string(&Config::default())
+                .unwrap()
+                .replace("[proxy]\n", "[proxy]\ncompressors = [\"zstd\", \"snappy\"]\n"),
+        )
+        .unwrap();
+        assert!(config.validate().is_ok());
+
+        let ProxyConfig::MongoDB { compressors, .. } = &mut config.proxy else {
+            panic!("Expected MongoDB proxy config");
+        };
+        assert_eq!(compressors, &Some(vec![Compressor::Zstd, Compressor::Snappy]));
+        assert_eq!(Compressor::Zstd.name(), "zstd");
+
+        compressors.as_mut().unwrap().push(Compressor::Zstd);
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_affinity_rules() {
+        let mut config: Config = toml::from_str(&toml::to_string(&Config::default()).unwrap().replace(
+            "affinity_rules = []",
+            r#"affinity_rules = [{ clients = ["10.20.0.0/16", "10.30.0.7"], endpoints = ["127.0.0.1:27017"] }]"#,
+        ))
+        .unwrap();
+        assert!(config.validate().is_ok());
+
+        let ProxyConfig::MongoDB { affinity_rules, .. } = &mut config.proxy else {
+            panic!("Expected MongoDB proxy config");
+        };
+        assert_eq!(affinity_rules[0].clients, vec!["10.20.0.0/16", "10.30.0.7"]);
+
+        affinity_rules[0].clients.push("10.40.0.0/33".to_string());
+        assert!(config.validate().is_err());
+
+        // Rule endpoints must be mongos endpoints unless discovery supplies them
+        if let ProxyConfig::MongoDB { affinity_rules, .. } = &mut config.proxy {
+            affinity_rules[0].clients.pop();
+            affinity_rules[0].endpoints = vec!["127.0.0.1:27020".to_string()];
+        }
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_rebalance_section() {
+        let toml_str = toml::to_string(&Config::default()).unwrap().replace(
+            r#"client_identification = "socket_address_only""#,
+            "client_identification = \"session_id\"\nrebalance = { max_skew = 2.0 }",
+        );
+        let mut config: Config = toml::from_str(&toml_str).unwrap();
+        assert!(config.validate().is_ok());
+        let ProxyConfig::MongoDB { rebalance: Some(rebalance), .. } = &mut config.proxy else {
+            panic!("Expected a rebalance section");
+        };
+        assert_eq!(rebalance.max_skew, 2.0);
+        assert_eq!(rebalance.max_sessions, 10);
+        assert_eq!(rebalance.min_idle_sec, 60);
+
+        rebalance.max_skew = 1.0;
+        assert!(config.validate().is_err());
+
+        // Sessions by socket address never outlive their connection
+        if let ProxyConfig::MongoDB { rebalance: Some(rebalance), client_identification, .. } = &mut config.proxy {
+            rebalance.max_skew = 2.0;
+            *client_identification = ClientIdentificationStrategy::SocketAddressOnly;
+        }
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_on_backend_unhealthy() {
+        let config: Config = toml::from_str(
+            &toml::to_string(&Config::default())
+                .unwrap()
+                .replace("on_backend_unhealthy = \"close\"", "on_backend_unhealthy = \"drain\""),
+        )
+        .unwrap();
+        let ProxyConfig::MongoDB { on_backend_unhealthy, .. } = config.proxy else {
+            panic!("Expected MongoDB proxy config");
+        };
+        assert_eq!(on_backend_unhealthy, UnhealthyBackendAction::Drain);
+        assert!(toml::from_str::<Config>(
+            &toml::to_string(&Config::default())
+                .unwrap()
+                .replace("on_backend_unhealthy = \"close\"", "on_backend_unhealthy = \"linger\"")
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_large_payloads() {
+        let mut config: Config = toml::from_str(&format!(
+            "{}\n[proxy.large_payloads]\nthreshold_bytes = 1048576\n",
+            toml::to_string(&Config::default()).unwrap()
+        ))
+        .unwrap();
+        assert!(config.validate().is_ok());
+
+        let ProxyConfig::MongoDB {
+            large_payloads: Some(large_payloads),
+            ..
+        } = &mut config.proxy
+        else {
+            panic!("Expected MongoDB proxy config with large payload detection");
+        };
+        assert_eq!(large_payloads.threshold_bytes, 1_048_576);
+        assert_eq!(large_payloads.top, 32);
+
+        large_payloads.threshold_bytes = 0;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_mirror() {
+        let mut config: Config = toml::from_str(&format!(
+            "{}\n[proxy.mirror]\ntarget = \"shadow-mongos:27017\"\npercent = 5\n",
+            toml::to_string(&Config::default()).unwrap()
+        ))
+        .unwrap();
+        assert!(config.validate().is_ok());
+
+        let ProxyConfig::MongoDB {
+            mirror: Some(mirror),
+            ..
+        } = &mut config.proxy
+        else {
+            panic!("Expected MongoDB proxy config with a mirror");
+        };
+        assert_eq!(mirror.percent, 5.0);
+        assert_eq!(mirror.max_queued, 1024);
+
+        mirror.percent = 150.0;
+        assert!(config.validate().is_err());
+        if let ProxyConfig::MongoDB { mirror: Some(mirror), .. } = &mut config.proxy {
+            mirror.percent = 5.0;
+            mirror.target = "not a target".to_string();
+        }
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_capture() {
+        let mut config: Config = toml::from_str(&format!(
+            "{}\n[proxy.capture]\npath = \"/var/lib/puerta/traffic.cap\"\nmax_age_sec = 600\n",
+            toml::to_string(&Config::default()).unwrap()
+        ))
+        .unwrap();
+        assert!(config.validate().is_ok());
+
+        let ProxyConfig::MongoDB {
+            capture: Some(capture),
+            ..
+        } = &mut config.proxy
+        else {
+            panic!("Expected MongoDB proxy config with a capture");
+        };
+        assert_eq!(capture.max_bytes, 100 * 1024 * 1024);
+        assert_eq!(capture.max_age_sec, 600);
+        assert_eq!(capture.max_files, 10);
+        assert!(capture.redact_credentials);
+
+        capture.max_files = 0;
+        assert!(config.validate().is_err());
+        if let ProxyConfig::MongoDB { capture: Some(capture), .. } = &mut config.proxy {
+            capture.max_files = 10;
+            capture.path.clear();
+        }
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_slow_operation_threshold() {
+        let mut config = Config::default();
+        let ProxyConfig::MongoDB {
+            slow_operation_threshold_ms,
+            ..
+        } = &mut config.proxy
+        else {
+            panic!("Expected MongoDB proxy config");
+        };
+        assert_eq!(*slow_operation_threshold_ms, None);
+        *slow_operation_threshold_ms = Some(100);
+        assert!(config.validate().is_ok());
+
+        let parsed: Config = toml::from_str(&toml::to_string(&config).unwrap()).unwrap();
+        assert_eq!(parsed.proxy, config.proxy);
+
+        if let ProxyConfig::MongoDB {
+            slow_operation_threshold_ms,
+            ..
+        } = &mut config.proxy
+        {
+            *slow_operation_threshold_ms = Some(0);
+        }
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_mongodb_backend_connection_limit() {
+        let mut config = Config::default();
+        let ProxyConfig::MongoDB { max_connections_per_backend, .. } = &mut config.proxy else {
+            panic!("Expected MongoDB proxy config");
+        };
+        *max_connections_per_backend = Some(500);
+        assert!(config.validate().is_ok());
+
+        if let ProxyConfig::MongoDB { max_connections_per_backend, .. } = &mut config.proxy {
+            *max_connections_per_backend = Some(0);
+        }
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_mongodb_announce_addr() {
+        let mut config = Config::default();
+        let ProxyConfig::MongoDB { announce_addr, .. } = &mut config.proxy else {
+            panic!("Expected MongoDB proxy config");
+        };
+        *announce_addr = Some("mongodb-proxy.example.com:27017".to_string());
+        assert!(config.validate().is_ok());
+
+        if let ProxyConfig::MongoDB { announce_addr, .. } = &mut config.proxy {
+            *announce_addr = Some("mongodb-proxy.example.com".to_string());
+        }
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_outlier_detection_section() {
+        let toml_str = r#"
+[server]
+listen_addr = "0.0.0.0:6379"
+max_connections = 1000
+connection_timeout_sec = 30
+
+[proxy]
+mode = "redis"
+cluster_nodes = ["127.0.0.1:7001"]
+slot_refresh_interval_sec = 60
+max_redirects = 3
+connection_timeout_ms = 5000
+
+[health]
+interval_sec = 10
+timeout_sec = 5
+failure_threshold = 3
+success_threshold = 2
+
+[health.outlier_detection]
+error_rate_threshold = 0.3
+cooldown_sec = 60
+
+[logging]
+level = "info"
+format = "text"
+stdout = true
+"#;
+
+        let mut config: Config = toml::from_str(toml_str).unwrap();
+        assert!(config.validate().is_ok());
+
+        let outlier = config.health.outlier_detection.clone().unwrap();
+        assert_eq!(outlier.error_rate_threshold, 0.3);
+        assert_eq!(outlier.cooldown_sec, 60);
+        // Omitted settings take their defaults
+        assert_eq!(outlier.ramp_up_percent, 10);
+        assert_eq!(outlier.max_ejection_percent, 50);
+
+        // Disabled unless the section is present
+        assert!(Config::default().health.outlier_detection.is_none());
+
+        config.health.outlier_detection = Some(OutlierDetectionConfig {
+            ramp_up_percent: 0,
+            ..outlier
+        });
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_circuit_breaker_section() {
+        let mut config: Config = toml::from_str(&format!(
+            "{}\n[health.circuit_breaker]\nfailure_rate_threshold = 0.25\nhalf_open_probes = 5\n",
+            toml::to_string(&Config::default()).unwrap()
+        ))
+        .unwrap();
+        assert!(config.validate().is_ok());
+
+        let circuit_breaker = config.health.circuit_breaker.clone().unwrap();
+        assert_eq!(circuit_breaker.failure_rate_threshold, 0.25);
+        assert_eq!(circuit_breaker.half_open_probes, 5);
+        // Omitted settings take their defaults
+        assert_eq!(circuit_breaker.window_size, 20);
+        assert_eq!(circuit_breaker.cooldown_sec, 30);
+
+        assert!(Config::default().health.circuit_breaker.is_none());
+
+        config.health.circuit_breaker = Some(CircuitBreakerConfig {
+            min_requests: 50,
+            ..circuit_breaker
+        });
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_health_jitter_percent() {
+        let health = "interval_sec = 10\ntimeout_sec = 5\nfailure_threshold = 3\nsuccess_threshold = 2\n";
+        let unset: HealthConfig = toml::from_str(health).unwrap();
+        assert_eq!(unset.jitter_percent, 0);
+
+        let mut config = Config {
+            health: toml::from_str(&format!("{health}jitter_percent = 20\n")).unwrap(),
+            ..Config::default()
+        };
+        assert_eq!(config.health.jitter_percent, 20);
+        assert!(config.validate().is_ok());
+
+        config.health.jitter_percent = 51;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_health_notifications_section() {
+        let mut config: Config = toml::from_str(&format!(
+            "{}\n[health.notifications]\nwebhook_url = \"http://alerts.internal:9000/puerta\"\n",
+            toml::to_string(&Config::default()).unwrap()
+        ))
+        .unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(
+            config.health.notifications.as_ref().unwrap().webhook_url,
+            "http://alerts.internal:9000/puerta"
+        );
+        assert!(Config::default().health.notifications.is_none());
+
+        for webhook_url in ["https://alerts.internal/puerta", "http:///puerta"] {
+            config.health.notifications = Some(HealthNotificationsConfig {
+                webhook_url: webhook_url.to_string(),
+            });
+            assert!(config.validate().is_err(), "{webhook_url}");
+        }
+    }
+
+    #[test]
+    fn test_server_tuning_section() {
+        let mut config = Config::default();
+        assert_eq!(config.server.tuning, ServerTuningConfig::default());
+
+        config.server.worker_threads = Some(8);
+        config.server.tuning = toml::from_str(
+            "reuse_port = true\nio_uring = true\ncpu_affinity = [2, 3]\n[tcp_keepalive]\nidle_sec = 60\ninterval_sec = 10\ncount = 6\n",
+        )
+        .unwrap();
+        assert!(config.validate().is_ok());
+        assert!(config.server.tuning.reuse_port);
+        assert!(config.server.tuning.io_uring);
+        assert_eq!(config.server.tuning.cpu_affinity, vec![2, 3]);
+        assert_eq!(config.server.tuning.tcp_keepalive.as_ref().unwrap().idle_sec, 60);
+
+        let parsed: Config = toml::from_str(&toml::to_string(&config).unwrap()).unwrap();
+        assert_eq!(parsed.server.tuning, config.server.tuning);
+
+        config.server.tuning.tcp_keepalive.as_mut().unwrap().count = 0;
+        assert!(config.validate().is_err());
+        config.server.tuning.tcp_keepalive = None;
+
+        config.server.tuning.backend_tcp_keepalive = Some(
+            toml::from_str("idle_sec = 30\ninterval_sec = 5\ncount = 3\nuser_timeout_sec = 45\n")
+                .unwrap(),
+        );
+        assert!(config.validate().is_ok());
+        config.server.tuning.backend_tcp_keepalive.as_mut().unwrap().user_timeout_sec = Some(0);
+        assert!(config.validate().is_err());
+        config.server.tuning.backend_tcp_keepalive = None;
+        config.server.worker_threads = Some(0);
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_telemetry_section() {
+        let mut config = Config::default();
+        let mut toml_str = toml::to_string(&config).unwrap();
+        toml_str.push_str(
+            r#"
+[telemetry]
+service_name = "puerta-edge"
+sample_ratio = 0.25
+
+[telemetry.resource_attributes]
+"deployment.environment" = "staging"
+"#,
+        );
+
+        let parsed: Config = toml::from_str(&toml_str).unwrap();
+        assert!(parsed.validate().is_ok());
+        let telemetry = parsed.telemetry.unwrap();
+        assert_eq!(telemetry.service_name, "puerta-edge");
+        assert_eq!(telemetry.sample_ratio, 0.25);
+        assert_eq!(telemetry.otlp_endpoint, "http://localhost:4318/v1/traces");
+        assert_eq!(telemetry.resource_attributes["deployment.environment"], "staging");
+
+        config.telemetry = Some(TelemetryConfig {
+            sample_ratio: 1.5,
+            ..TelemetryConfig::default()
+        });
+        assert!(config.validate().is_err());
+
+        config.telemetry = Some(TelemetryConfig {
+            otlp_endpoint: "localhost:4318".to_string(),
+            ..TelemetryConfig::default()
+        });
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_statsd_section() {
+        let mut config = Config::default();
+        let mut toml_str = toml::to_string(&config).unwrap();
+        toml_str.push_str(
+            r#"
+[statsd]
+address = "datadog-agent:8125"
+dogstatsd = true
+
+[statsd.tags]
+env = "staging"
+"#,
+        );
+
+        let parsed: Config = toml::from_str(&toml_str).unwrap();
+        assert!(parsed.validate().is_ok());
+        let statsd = parsed.statsd.unwrap();
+        assert_eq!(statsd.address, "datadog-agent:8125");
+        assert_eq!(statsd.prefix, "puerta");
+        assert_eq!(statsd.flush_interval_sec, 10);
+        assert_eq!(statsd.tags["env"], "staging");
+
+        config.statsd = Some(StatsdConfig {
+            address: "datadog-agent".to_string(),
+            ..StatsdConfig::default()
+        });
+        assert!(config.validate().is_err());
+
+        config.statsd = Some(StatsdConfig {
+            flush_interval_sec: 0,
+            ..StatsdConfig::default()
+        });
+        assert!(config.validate().is_err());
+
+        config.statsd = Some(StatsdConfig {
+            tags: [("env".to_string(), "staging".to_string())].into_iter().collect(),
+            ..StatsdConfig::default()
+        });
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_chaos_section() {
+        let mut config = Config::default();
+        let mut toml_str = toml::to_string(&config).unwrap();
+        toml_str.push_str(
+            r#"
+[chaos]
+enabled = true
+latency_percent = 5
+latency_ms = 200
+moved_percent = 1.5
+"#,
+        );
+
+        let parsed: Config = toml::from_str(&toml_str).unwrap();
+        assert!(parsed.validate().is_ok());
+        let chaos = parsed.chaos.unwrap();
+        assert!(chaos.enabled);
+        assert_eq!(chaos.latency_percent, 5.0);
+        assert_eq!(chaos.moved_percent, 1.5);
+        assert_eq!(chaos.reset_percent, 0.0);
+
+        config.chaos = Some(ChaosConfig {
+            reset_percent: 101.0,
+            ..ChaosConfig::default()
+        });
+        assert!(config.validate().is_err());
+
+        config.chaos = Some(ChaosConfig {
+            reset_percent: 60.0,
+            moved_percent: 60.0,
+            ..ChaosConfig::default()
+        });
+        assert!(config.validate().is_err());
+
+        config.chaos = Some(ChaosConfig {
+            latency_percent: 10.0,
+            ..ChaosConfig::default()
+        });
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_maintenance_windows() {
+        let mut config = Config::default();
+        let mut toml_str = toml::to_string(&config).unwrap();
+        toml_str.push_str(
+            r#"
+[[maintenance]]
+backend = "127.0.0.1:27017"
+schedule = "0 2 * * sun"
+duration_min = 60
+
+[[maintenance]]
+backend = "127.0.0.1:27018"
+schedule = "30 3 1 * *"
+duration_min = 30
+drain_timeout_sec = 120
+"#,
+        );
+
+        let parsed: Config = toml::from_str(&toml_str).unwrap();
+        assert!(parsed.validate().is_ok());
+        assert_eq!(parsed.maintenance.len(), 2);
+        assert_eq!(parsed.maintenance[0].drain_timeout_sec, None);
+        assert_eq!(parsed.maintenance[1].drain_timeout_sec, Some(120));
+
+        let window = parsed.maintenance[0].clone();
+        for invalid in [
+            MaintenanceWindowConfig {
+                backend: "mongos-1.internal:27017".to_string(),
+                ..window.clone()
+            },
+            MaintenanceWindowConfig {
+                schedule: "0 2 * sun".to_string(),
+                ..window.clone()
+            },
+            MaintenanceWindowConfig {
+                duration_min: 0,
+                ..window.clone()
+            },
+            MaintenanceWindowConfig {
+                duration_min: MAX_MAINTENANCE_DURATION_MIN + 1,
+                ..window.clone()
+            },
+            MaintenanceWindowConfig {
+                drain_timeout_sec: Some(0),
+                ..window.clone()
+            },
+        ] {
+            config.maintenance = vec![invalid];
+            assert!(config.validate().is_err());
+        }
+    }
+
+    #[test]
+    fn test_acl_section() {
+        let mut config = Config::default();
+        let mut toml_str = toml::to_string(&config).unwrap();
+        toml_str.push_str(
+            r#"
+[acl]
+allow = ["10.0.0.0/8", "192.168.1.10"]
+"#,
+        );
+
+        let parsed: Config = toml::from_str(&toml_str).unwrap();
+        assert!(parsed.validate().is_ok());
+        let acl = parsed.acl.unwrap();
+        assert_eq!(acl.allow, vec!["10.0.0.0/8", "192.168.1.10"]);
+        assert!(acl.deny.is_empty());
+
+        config.acl = Some(AclConfig {
+            deny: vec!["10.0.0.0/40".to_string()],
+            ..AclConfig::default()
+        });
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_proxies_section() {
+        let mut toml_str = toml::to_string(&Config::default()).unwrap();
+        toml_str.push_str(
+            r#"
+[[proxies]]
+name = "cache"
+listen_addr = "0.0.0.0:6379"
+cpu_affinity = [4, 5]
+mode = "redis"
+cluster_nodes = ["10.0.1.20:6379"]
+slot_refresh_interval_sec = 60
+max_redirects = 3
+connection_timeout_ms = 5000
+
+[[proxies]]
+name = "analytics"
+listen_addr = "0.0.0.0:27018"
+mode = "mongodb"
+mongos_endpoints = [{ addr = "10.0.2.10:27017", weight = 2 }]
+session_affinity = false
+session_timeout_sec = 600
+"#,
+        );
+
+        let mut config: Config = toml::from_str(&toml_str).unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(config.proxies.len(), 2);
+        assert_eq!(config.proxies[0].name, "cache");
+        assert_eq!(config.proxies[0].cpu_affinity, vec![4, 5]);
+        assert!(config.proxies[1].cpu_affinity.is_empty());
+        match &config.proxies[0].proxy {
+            ProxyConfig::Redis {
+                cluster_nodes,
+                pool_size,
+                ..
+            } => {
+                assert_eq!(cluster_nodes, &vec![Endpoint::from("10.0.1.20:6379")]);
+                assert_eq!(*pool_size, default_pool_size());
+            }
+            _ => panic!("Expected Redis instance"),
+        }
+        match &config.proxies[1].proxy {
+            ProxyConfig::MongoDB {
+                mongos_endpoints, ..
+            } => assert_eq!(mongos_endpoints, &vec![Endpoint::new("10.0.2.10:27017", 2)]),
+            _ => panic!("Expected MongoDB instance"),
+        }
+
+        // Instances survive a save and reload
+        let reparsed: Config = toml::from_str(&toml::to_string(&config).unwrap()).unwrap();
+        assert_eq!(reparsed.proxies.len(), 2);
+        assert_eq!(reparsed.proxies[0].cpu_affinity, vec![4, 5]);
+
+        // Listen addresses and names must be unique
+        config.proxies[1].listen_addr = config.server.listen_addr.clone();
+        assert!(config.validate().is_err());
+        config.proxies[1].listen_addr = "0.0.0.0:27018".to_string();
+        config.proxies[1].name = "cache".to_string();
+        assert!(config.validate().is_err());
+        config.proxies[1].name = "analytics".to_string();
+
+        // Discovery does not stand in for an instance's backends
+        if let ProxyConfig::Redis { cluster_nodes, .. } = &mut config.proxies[0].proxy {
+            cluster_nodes.clear();
+        }
+        config.discovery = Some(DiscoveryConfig {
+            dns_name: "redis.example.com".to_string(),
+            ..Default::default()
+        });
+        let err = config.validate().unwrap_err().to_string();
+        assert!(err.contains("proxy cache"), "{err}");
+    }
+
+    #[test]
+    fn test_discovery_section() {
+        let toml_str = r#"
+[server]
+listen_addr = "127.0.0.1:27016"
+max_connections = 1000
+connection_timeout_sec = 30
+
+[proxy]
+mode = "mongodb"
+session_affinity = true
+session_timeout_sec = 1800
+
+[health]
+interval_sec = 10
+timeout_sec = 5
+failure_threshold = 3
+success_threshold = 2
+
+[logging]
+level = "info"
+format = "text"
+stdout = true
+
+[discovery]
+dns_name = "mongos-headless.db.svc.cluster.local"
+record_type = "a"
+port = 27017
+"#;
+
+        // An empty static list is fine once DNS discovery supplies backends
+        let mut config: Config = toml::from_str(toml_str).unwrap();
+        assert!(config.validate().is_ok());
+        let discovery = config.discovery.clone().unwrap();
+        assert_eq!(discovery.record_type, DnsRecordType::A);
+        assert_eq!(discovery.interval_sec, 30);
+
+        config.discovery = Some(DiscoveryConfig {
+            port: None,
+            ..discovery
+        });
+        assert!(config.validate().is_err());
+
+        config.discovery = None;
+        assert!(config.validate().is_err());
+
+        let consul: Config = toml::from_str(&toml_str.replace(
+            "[discovery]\ndns_name = \"mongos-headless.db.svc.cluster.local\"\nrecord_type = \"a\"\nport = 27017",
+            "[discovery]\ntype = \"consul\"\naddress = \"http://127.0.0.1:8500\"\nservice = \"mongos\"",
+        ))
+        .unwrap();
+        let discovery = consul.discovery.clone().unwrap();
+        assert_eq!(discovery.provider, DiscoveryProvider::Consul);
+        assert_eq!(discovery.service.as_deref(), Some("mongos"));
+        assert!(consul.validate().is_ok());
+
+        let etcd = DiscoveryConfig {
+            provider: DiscoveryProvider::Etcd,
+            ..discovery
+        };
+        // etcd needs a key prefix rather than a service name
+        assert!(etcd.validate().is_err());
+        let etcd = DiscoveryConfig {
+            key_prefix: Some("/puerta/mongos/".to_string()),
+            address: Some("https://127.0.0.1:2379".to_string()),
+            ..etcd
+        };
+        assert!(etcd.validate().is_err());
+        let etcd = DiscoveryConfig {
+            address: Some("http://127.0.0.1:2379".to_string()),
+            ..etcd
+        };
+        assert!(etcd.validate().is_ok());
+    }
+
+    #[test]
+    fn test_logging_level_filter() {
+        let mut config = Config::default();
+        assert_eq!(config.logging.level_filter(), log::LevelFilter::Info);
+
+        config.logging.level = "debug".to_string();
+        assert_eq!(config.logging.level_filter(), log::LevelFilter::Debug);
     }
 }
@@ -0,0 +1,260 @@
+/// Runtime configuration reloading (SIGHUP)
+///
+/// The reloader re-reads the TOML file the process was started with, validates
+/// it, and publishes the new configuration to subscribers through a watch
+/// channel. Settings that require rebinding sockets (listen address, proxy
+/// mode, additional proxy instances) or restarting backend discovery cannot
+/// change at runtime and cause the reload to be rejected.
+use super::{Config, ProxyConfig};
+use crate::error::ConfigError;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::watch;
+
+/// Reloads configuration from disk and notifies subscribers
+pub struct ConfigReloader {
+    path: PathBuf,
+    sender: watch::Sender<Arc<Config>>,
+}
+
+impl ConfigReloader {
+    pub fn new<P: AsRef<Path>>(path: P, initial: Config) -> Self {
+        let (sender, _) = watch::channel(Arc::new(initial));
+        Self {
+            path: path.as_ref().to_path_buf(),
+            sender,
+        }
+    }
+
+    /// Subscribe to configuration updates
+    pub fn subscribe(&self) -> watch::Receiver<Arc<Config>> {
+        self.sender.subscribe()
+    }
+
+    /// Get the currently active configuration
+    pub fn current(&self) -> Arc<Config> {
+        self.sender.borrow().clone()
+    }
+
+    /// Get the path of the configuration file being watched
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Re-read the configuration file and publish it if it is valid
+    pub fn reload(&self) -> Result<Arc<Config>, ConfigError> {
+        let new_config = Config::load_from_file(&self.path)?;
+        Self::check_reloadable(&self.current(), &new_config)?;
+
+        log::set_max_level(new_config.logging.level_filter());
+
+        let new_config = Arc::new(new_config);
+        self.sender.send_replace(Arc::clone(&new_config));
+        Ok(new_config)
+    }
+
+    /// Verify that only settings which can be applied live have changed
+    fn check_reloadable(current: &Config, new_config: &Config) -> Result<(), ConfigError> {
+        if current.server.listen_addr != new_config.server.listen_addr {
+            return Err(ConfigError::ValidationError(format!(
+                "listen_addr cannot be changed at runtime ({} -> {})",
+                current.server.listen_addr, new_config.server.listen_addr
+            )));
+        }
+
+        let same_mode = matches!(
+            (&current.proxy, &new_config.proxy),
+            (ProxyConfig::MongoDB { .. }, ProxyConfig::MongoDB { .. })
+                | (ProxyConfig::Redis { .. }, ProxyConfig::Redis { .. })
+        );
+        if !same_mode {
+            return Err(ConfigError::ValidationError(
+                "proxy mode cannot be changed at runtime".to_string(),
+            ));
+        }
+
+        if current.proxies != new_config.proxies {
+            return Err(ConfigError::ValidationError(
+                "proxies cannot be changed at runtime".to_string(),
+            ));
+        }
+
+        if current.discovery != new_config.discovery {
+            return Err(ConfigError::ValidationError(
+                "discovery cannot be changed at runtime".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Start a background thread that reloads the configuration on SIGHUP
+    pub fn spawn_signal_handler(self: Arc<Self>) {
+        std::thread::spawn(move || {
+            let rt = crate::utils::background_runtime();
+            rt.block_on(async move {
+                let mut hangup = match tokio::signal::unix::signal(
+                    tokio::signal::unix::SignalKind::hangup(),
+                ) {
+                    Ok(signal) => signal,
+                    Err(e) => {
+                        log::error!("Failed to install SIGHUP handler: {e}");
+                        return;
+                    }
+                };
+
+                log::info!("Configuration reload enabled: send SIGHUP to reload {:?}", self.path);
+
+                while hangup.recv().await.is_some() {
+                    log::info!("SIGHUP received, reloading configuration from {:?}", self.path);
+                    match self.reload() {
+                        Ok(_) => log::info!("Configuration reloaded successfully"),
+                        Err(e) => log::error!("Configuration reload rejected: {e}"),
+                    }
+                }
+            });
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::NamedTempFile;
+
+    /// A Redis proxy configuration with its optional settings at their
+    /// defaults
+    fn redis_proxy() -> ProxyConfig {
+        toml::from_str(
+            r#"
+mode = "redis"
+cluster_nodes = ["127.0.0.1:7001"]
+slot_refresh_interval_sec = 60
+max_redirects = 3
+connection_timeout_ms = 5000
+"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_reload_publishes_new_config() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let config = Config::default();
+        config.save_to_file(temp_file.path()).unwrap();
+
+        let reloader = ConfigReloader::new(temp_file.path(), config.clone());
+        let mut receiver = reloader.subscribe();
+
+        let mut updated = config.clone();
+        updated.health.interval_sec = 30;
+        let ProxyConfig::MongoDB {
+            mongos_endpoints,
+            balance_strategy,
+            ..
+        } = &mut updated.proxy
+        else {
+            panic!("Expected the default MongoDB proxy config");
+        };
+        mongos_endpoints.push("127.0.0.1:27018".into());
+        *balance_strategy = crate::config::BalanceStrategy::LeastConnections;
+        updated.save_to_file(temp_file.path()).unwrap();
+
+        reloader.reload().unwrap();
+        assert!(receiver.has_changed().unwrap());
+
+        let current = receiver.borrow_and_update().clone();
+        assert_eq!(current.health.interval_sec, 30);
+        match &current.proxy {
+            ProxyConfig::MongoDB {
+                mongos_endpoints, ..
+            } => assert_eq!(mongos_endpoints.len(), 2),
+            _ => panic!("Expected MongoDB proxy config"),
+        }
+    }
+
+    #[test]
+    fn test_reload_rejects_listen_addr_change() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let config = Config::default();
+        config.save_to_file(temp_file.path()).unwrap();
+
+        let reloader = ConfigReloader::new(temp_file.path(), config.clone());
+
+        let mut updated = config.clone();
+        updated.server.listen_addr = "0.0.0.0:9999".to_string();
+        updated.save_to_file(temp_file.path()).unwrap();
+
+        assert!(reloader.reload().is_err());
+        assert_eq!(reloader.current().server.listen_addr, config.server.listen_addr);
+    }
+
+    #[test]
+    fn test_reload_rejects_mode_change() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let config = Config::default();
+        config.save_to_file(temp_file.path()).unwrap();
+
+        let reloader = ConfigReloader::new(temp_file.path(), config.clone());
+
+        let mut updated = config;
+        updated.proxy = redis_proxy();
+        updated.save_to_file(temp_file.path()).unwrap();
+
+        assert!(reloader.reload().is_err());
+    }
+
+    #[test]
+    fn test_reload_rejects_discovery_change() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let config = Config::default();
+        config.save_to_file(temp_file.path()).unwrap();
+
+        let reloader = ConfigReloader::new(temp_file.path(), config.clone());
+
+        let mut updated = config;
+        updated.discovery = Some(crate::config::DiscoveryConfig {
+            dns_name: "_mongos._tcp.mongos.db.svc.cluster.local".to_string(),
+            record_type: crate::config::DnsRecordType::Srv,
+            ..Default::default()
+        });
+        updated.save_to_file(temp_file.path()).unwrap();
+
+        assert!(reloader.reload().is_err());
+        assert!(reloader.current().discovery.is_none());
+    }
+
+    #[test]
+    fn test_reload_rejects_proxies_change() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let config = Config::default();
+        config.save_to_file(temp_file.path()).unwrap();
+
+        let reloader = ConfigReloader::new(temp_file.path(), config.clone());
+
+        let mut updated = config;
+        updated.proxies.push(crate::config::ProxyInstanceConfig {
+            name: "secondary".to_string(),
+            listen_addr: "127.0.0.1:27018".to_string(),
+            cpu_affinity: Vec::new(),
+            proxy: updated.proxy.clone(),
+        });
+        updated.save_to_file(temp_file.path()).unwrap();
+
+        assert!(reloader.reload().is_err());
+        assert!(reloader.current().proxies.is_empty());
+    }
+
+    #[test]
+    fn test_reload_invalid_file_keeps_current() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let config = Config::default();
+        config.save_to_file(temp_file.path()).unwrap();
+
+        let reloader = ConfigReloader::new(temp_file.path(), config);
+        std::fs::write(temp_file.path(), "not valid toml [").unwrap();
+
+        assert!(reloader.reload().is_err());
+        assert_eq!(reloader.current().health.interval_sec, 10);
+    }
+}
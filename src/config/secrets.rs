@@ -0,0 +1,101 @@
+/// Credentials kept out of the configuration file
+///
+/// Credential fields accept `${VAR}` references, replaced with the value of
+/// the environment variable when the file is loaded, and most have a
+/// `*_file` sibling naming a file holding the secret, e.g. a Docker or
+/// Kubernetes secret mounted at `/run/secrets/redis`. `$$` stands for a
+/// literal `$`.
+use crate::error::ConfigError;
+use std::fs;
+
+/// Replace the `${VAR}` references in `value`, the setting `name`, with
+/// their environment variables
+pub fn interpolate(name: &str, value: &str) -> Result<String, ConfigError> {
+    let mut resolved = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(index) = rest.find('$') {
+        resolved.push_str(&rest[..index]);
+        rest = &rest[index..];
+        if let Some(after) = rest.strip_prefix("$$") {
+            resolved.push('$');
+            rest = after;
+        } else if let Some(reference) = rest.strip_prefix("${") {
+            let end = reference.find('}').ok_or_else(|| {
+                ConfigError::ValidationError(format!("{name} has an unterminated ${{ reference"))
+            })?;
+            let var = &reference[..end];
+            let value = std::env::var(var).map_err(|_| {
+                ConfigError::ValidationError(format!(
+                    "{name} references environment variable {var}, which is not set"
+                ))
+            })?;
+            resolved.push_str(&value);
+            rest = &reference[end + 1..];
+        } else {
+            resolved.push('$');
+            rest = &rest[1..];
+        }
+    }
+    resolved.push_str(rest);
+    Ok(resolved)
+}
+
+/// Resolve a credential given inline or in a file: `value` is interpolated,
+/// or else read from `file` without its trailing newline
+pub fn resolve(name: &str, value: &mut String, file: Option<&str>) -> Result<(), ConfigError> {
+    match file {
+        Some(_) if !value.is_empty() => Err(ConfigError::ValidationError(format!(
+            "{name} and {name}_file cannot both be set"
+        ))),
+        Some(path) => {
+            let path = interpolate(&format!("{name}_file"), path)?;
+            let secret = fs::read_to_string(&path).map_err(|e| {
+                ConfigError::ValidationError(format!("cannot read {name}_file {path}: {e}"))
+            })?;
+            *value = secret.trim_end_matches(['\r', '\n']).to_string();
+            Ok(())
+        }
+        None => {
+            *value = interpolate(name, value)?;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_interpolate() {
+        std::env::set_var("PUERTA_TEST_SECRET", "s3cret");
+        assert_eq!(
+            interpolate("password", "${PUERTA_TEST_SECRET}").unwrap(),
+            "s3cret"
+        );
+        assert_eq!(
+            interpolate("password", "pre-${PUERTA_TEST_SECRET}-$$-$5").unwrap(),
+            "pre-s3cret-$-$5"
+        );
+        assert!(interpolate("password", "${PUERTA_TEST_UNSET_VARIABLE}").is_err());
+        assert!(interpolate("password", "${PUERTA_TEST_SECRET").is_err());
+    }
+
+    #[test]
+    fn test_resolve_from_file() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        writeln!(file, "from-file").unwrap();
+        let path = file.path().to_string_lossy().to_string();
+
+        let mut password = String::new();
+        resolve("password", &mut password, Some(&path)).unwrap();
+        assert_eq!(password, "from-file");
+
+        // Inline and file credentials are exclusive
+        assert!(resolve("password", &mut password, Some(&path)).is_err());
+
+        let mut password = String::new();
+        assert!(resolve("password", &mut password, Some("/nonexistent/secret")).is_err());
+    }
+}
@@ -71,6 +71,7 @@ mod tests {
             last_health_check: Some(SystemTime::now()),
             metadata: BackendMetadata::MongoDB {
                 version: Some("4.4.0".to_string()),
+                max_wire_version: None,
                 is_primary: true,
                 connection_count: 0,
             },
@@ -0,0 +1,140 @@
+/// Per-backend caps on concurrent client connections
+///
+/// Session affinity and affinity rules can pile clients onto one backend
+/// while the others sit idle. With a cap, a backend at its limit takes no
+/// further connections: they go to another healthy backend, or wait a
+/// moment for a connection to the backend to close when every candidate is
+/// full. Each connection holds a permit for its lifetime.
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+
+/// Open connections of every backend against a common cap
+pub struct BackendLimits {
+    max_connections: usize,
+    queue_timeout: Duration,
+    active: Mutex<HashMap<SocketAddr, usize>>,
+    /// Notifies waiters when a connection closes
+    released: watch::Sender<()>,
+}
+
+impl BackendLimits {
+    pub fn new(max_connections: usize, queue_timeout: Duration) -> Self {
+        Self {
+            max_connections,
+            queue_timeout,
+            active: Mutex::new(HashMap::new()),
+            released: watch::channel(()).0,
+        }
+    }
+
+    pub fn max_connections(&self) -> usize {
+        self.max_connections
+    }
+
+    /// How long a connection may wait when every candidate is full
+    pub fn queue_timeout(&self) -> Duration {
+        self.queue_timeout
+    }
+
+    /// Connections open to `addr`
+    pub fn active(&self, addr: SocketAddr) -> usize {
+        self.active.lock().unwrap().get(&addr).copied().unwrap_or(0)
+    }
+
+    /// Addresses of every backend at its limit
+    pub fn full(&self) -> Vec<SocketAddr> {
+        self.active
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, active)| **active >= self.max_connections)
+            .map(|(addr, _)| *addr)
+            .collect()
+    }
+
+    /// Take a connection slot on `addr`, `None` when it is at its limit
+    pub fn try_acquire(self: &Arc<Self>, addr: SocketAddr) -> Option<BackendPermit> {
+        let mut active = self.active.lock().unwrap();
+        let count = active.entry(addr).or_insert(0);
+        if *count >= self.max_connections {
+            return None;
+        }
+        *count += 1;
+        Some(BackendPermit {
+            limits: Arc::clone(self),
+            addr,
+        })
+    }
+
+    /// Watch for connections closing, to wait for a slot from now on
+    pub fn subscribe(&self) -> watch::Receiver<()> {
+        self.released.subscribe()
+    }
+
+    /// Wait until a connection seen by `released` closes or `deadline`
+    /// passes, returning whether one closed
+    pub async fn wait_for_release(released: &mut watch::Receiver<()>, deadline: Instant) -> bool {
+        tokio::time::timeout_at(deadline.into(), released.changed())
+            .await
+            .is_ok_and(|changed| changed.is_ok())
+    }
+
+    fn release(&self, addr: SocketAddr) {
+        let mut active = self.active.lock().unwrap();
+        if let Some(count) = active.get_mut(&addr) {
+            *count = count.saturating_sub(1);
+            if *count == 0 {
+                active.remove(&addr);
+            }
+        }
+        drop(active);
+        self.released.send_replace(());
+    }
+}
+
+/// A connection slot on a backend, released on drop
+pub struct BackendPermit {
+    limits: Arc<BackendLimits>,
+    addr: SocketAddr,
+}
+
+impl Drop for BackendPermit {
+    fn drop(&mut self) {
+        self.limits.release(self.addr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_backend_limits() {
+        let limits = Arc::new(BackendLimits::new(2, Duration::from_millis(200)));
+        let (busy, idle): (SocketAddr, SocketAddr) =
+            ("10.0.0.1:27017".parse().unwrap(), "10.0.0.2:27017".parse().unwrap());
+
+        let first = limits.try_acquire(busy).unwrap();
+        let _second = limits.try_acquire(busy).unwrap();
+        assert!(limits.try_acquire(busy).is_none());
+        assert_eq!(limits.full(), vec![busy]);
+        let _other = limits.try_acquire(idle).unwrap();
+        assert_eq!(limits.active(idle), 1);
+
+        // A waiter is woken when a connection closes, and times out otherwise
+        let mut released = limits.subscribe();
+        let deadline = Instant::now() + limits.queue_timeout();
+        let waiter = tokio::spawn(async move { BackendLimits::wait_for_release(&mut released, deadline).await });
+        drop(first);
+        assert!(waiter.await.unwrap());
+        assert_eq!(limits.active(busy), 1);
+        assert!(limits.full().is_empty());
+
+        let mut released = limits.subscribe();
+        let deadline = Instant::now() + Duration::from_millis(20);
+        assert!(!BackendLimits::wait_for_release(&mut released, deadline).await);
+    }
+}
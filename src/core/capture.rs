@@ -0,0 +1,429 @@
+/// Capture of client traffic to files
+///
+/// With `capture` set, every framed message a client sends towards the
+/// backends (MongoDB wire messages or Redis commands) is appended to a
+/// capture file with its time and connection id, for offline analysis or
+/// for replay with `puerta bench --replay`. Messages are handed to a writer
+/// thread through a bounded queue and dropped when it is full, so capturing
+/// never holds up forwarding. The file is rotated once it reaches
+/// `max_bytes` or `max_age_sec`, keeping `max_files` rotated files as
+/// `<path>.1` (newest) to `<path>.<max_files>`.
+///
+/// A capture file starts with [`CAPTURE_MAGIC`] and the mode's name as one
+/// length byte and the name. Each message follows as its capture time in
+/// microseconds since the Unix epoch (u64), connection id (u64) and length
+/// (u32), all little-endian, then its bytes.
+///
+/// Redactors rewrite or withhold messages before they are written; messages
+/// carrying credentials are withheld unless `redact_credentials` is off.
+use crate::config::CaptureConfig;
+use bytes::Bytes;
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender, TrySendError};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Bytes every capture file starts with
+pub const CAPTURE_MAGIC: &[u8; 8] = b"PUERTA\x00\x01";
+
+/// How long written messages may sit in the write buffer
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Largest message read back from a capture file, the default
+/// `max_command_size` of Redis mode and above any MongoDB wire message
+pub const MAX_CAPTURED_MESSAGE_SIZE: usize = 512 * 1024 * 1024;
+
+/// Rewrites or withholds captured messages
+pub trait Redactor: Send + Sync {
+    /// The message to write in place of `message`, or `None` to leave it out
+    fn redact(&self, message: Bytes) -> Option<Bytes>;
+}
+
+impl<F: Fn(Bytes) -> Option<Bytes> + Send + Sync> Redactor for F {
+    fn redact(&self, message: Bytes) -> Option<Bytes> {
+        self(message)
+    }
+}
+
+/// One captured client message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapturedMessage {
+    /// Microseconds since the Unix epoch
+    pub timestamp_us: u64,
+    pub connection_id: u64,
+    pub message: Bytes,
+}
+
+/// Capture of the client messages of a proxy instance
+pub struct Capture {
+    sender: SyncSender<CapturedMessage>,
+    writer: std::thread::JoinHandle<()>,
+    redactors: Vec<Arc<dyn Redactor>>,
+    mode: &'static str,
+}
+
+impl Capture {
+    /// Start capturing as configured in `config` for `mode`, or `None` when
+    /// absent; `carries_credentials` tells the mode's messages that are
+    /// withheld when `redact_credentials` is on
+    ///
+    /// Fails when the capture file cannot be created.
+    pub fn from_config(
+        config: Option<&CaptureConfig>,
+        mode: &'static str,
+        carries_credentials: fn(&Bytes) -> bool,
+    ) -> std::io::Result<Option<Self>> {
+        let Some(config) = config else {
+            return Ok(None);
+        };
+        let writer = CaptureWriter::create(config, mode)?;
+        let (sender, receiver) = mpsc::sync_channel(config.max_queued);
+        let writer = std::thread::Builder::new()
+            .name("puerta-capture".to_string())
+            .spawn(move || writer.run(receiver))?;
+        log::info!("Capturing {mode} client traffic to {}", config.path);
+
+        let mut capture = Self {
+            sender,
+            writer,
+            redactors: Vec::new(),
+            mode,
+        };
+        if config.redact_credentials {
+            capture = capture.with_redactor(Arc::new(move |message: Bytes| {
+                (!carries_credentials(&message)).then_some(message)
+            }));
+        }
+        Ok(Some(capture))
+    }
+
+    /// Pass messages through `redactor` before they are written, after the
+    /// redactors already added
+    pub fn with_redactor(mut self, redactor: Arc<dyn Redactor>) -> Self {
+        self.redactors.push(redactor);
+        self
+    }
+
+    /// Capture `message` of client connection `connection_id` without
+    /// waiting for it to be written
+    pub fn record(&self, connection_id: u64, message: &Bytes) {
+        let Some(message) = self
+            .redactors
+            .iter()
+            .try_fold(message.clone(), |message, redactor| redactor.redact(message))
+        else {
+            return;
+        };
+        let timestamp_us = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_micros() as u64;
+        let captured = CapturedMessage {
+            timestamp_us,
+            connection_id,
+            message,
+        };
+        let outcome = match self.sender.try_send(captured) {
+            Ok(()) => "captured",
+            Err(TrySendError::Full(_) | TrySendError::Disconnected(_)) => "dropped",
+        };
+        crate::metrics::global()
+            .counter_with_labels(
+                "puerta_capture_messages_total",
+                "Client messages written to the capture file, or dropped",
+                &[("mode", self.mode), ("outcome", outcome)],
+            )
+            .inc();
+    }
+
+    /// Stop capturing and wait until the messages captured are written
+    pub fn finish(self) {
+        drop(self.sender);
+        let _ = self.writer.join();
+    }
+}
+
+/// The current capture file and when to rotate it
+struct CaptureWriter {
+    path: PathBuf,
+    mode: &'static str,
+    max_bytes: u64,
+    max_age: Option<Duration>,
+    max_files: usize,
+    file: BufWriter<File>,
+    written: u64,
+    opened: Instant,
+    flushed: Instant,
+}
+
+impl CaptureWriter {
+    fn create(config: &CaptureConfig, mode: &'static str) -> std::io::Result<Self> {
+        let path = PathBuf::from(&config.path);
+        // The capture of an earlier run becomes the newest rotated file
+        if path.exists() {
+            shift_rotated(&path, config.max_files)?;
+        }
+        let (file, written) = Self::open(&path, mode)?;
+        Ok(Self {
+            path,
+            mode,
+            max_bytes: config.max_bytes,
+            max_age: (config.max_age_sec > 0).then(|| Duration::from_secs(config.max_age_sec)),
+            max_files: config.max_files,
+            file,
+            written,
+            opened: Instant::now(),
+            flushed: Instant::now(),
+        })
+    }
+
+    /// Create the file at `path`, readable by the owner only as it holds
+    /// client traffic, returning it with the header written
+    fn open(path: &Path, mode: &str) -> std::io::Result<(BufWriter<File>, u64)> {
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = BufWriter::new(options.open(path)?);
+        file.write_all(CAPTURE_MAGIC)?;
+        file.write_all(&[mode.len() as u8])?;
+        file.write_all(mode.as_bytes())?;
+        Ok((file, (CAPTURE_MAGIC.len() + 1 + mode.len()) as u64))
+    }
+
+    /// Write messages until every sender is gone
+    fn run(mut self, receiver: Receiver<CapturedMessage>) {
+        loop {
+            let result = match receiver.recv_timeout(FLUSH_INTERVAL) {
+                Ok(captured) => self.write(&captured),
+                Err(RecvTimeoutError::Timeout) => self.flush(),
+                Err(RecvTimeoutError::Disconnected) => break,
+            };
+            if let Err(e) = result {
+                log::error!("Failed to write capture file {}, capture stopped: {e}", self.path.display());
+                return;
+            }
+        }
+        if let Err(e) = self.file.flush() {
+            log::error!("Failed to write capture file {}: {e}", self.path.display());
+        }
+    }
+
+    fn write(&mut self, captured: &CapturedMessage) -> std::io::Result<()> {
+        if self.written >= self.max_bytes || self.max_age.is_some_and(|max_age| self.opened.elapsed() >= max_age) {
+            self.rotate()?;
+        }
+        self.file.write_all(&captured.timestamp_us.to_le_bytes())?;
+        self.file.write_all(&captured.connection_id.to_le_bytes())?;
+        self.file.write_all(&(captured.message.len() as u32).to_le_bytes())?;
+        self.file.write_all(&captured.message)?;
+        self.written += 20 + captured.message.len() as u64;
+        if self.flushed.elapsed() >= FLUSH_INTERVAL {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.flushed = Instant::now();
+        self.file.flush()
+    }
+
+    /// Shift the rotated files up by one, dropping the oldest, and start a
+    /// new file
+    fn rotate(&mut self) -> std::io::Result<()> {
+        self.file.flush()?;
+        shift_rotated(&self.path, self.max_files)?;
+        (self.file, self.written) = Self::open(&self.path, self.mode)?;
+        self.opened = Instant::now();
+        Ok(())
+    }
+}
+
+/// Move the file at `path` to `<path>.1`, shifting the rotated files up by
+/// one and dropping the oldest beyond `max_files`
+fn shift_rotated(path: &Path, max_files: usize) -> std::io::Result<()> {
+    let rotated = |n: usize| PathBuf::from(format!("{}.{n}", path.display()));
+    let _ = std::fs::remove_file(rotated(max_files));
+    for n in (1..max_files).rev() {
+        let _ = std::fs::rename(rotated(n), rotated(n + 1));
+    }
+    std::fs::rename(path, rotated(1))
+}
+
+/// Read a capture file, returning its mode and messages
+pub fn read_capture(path: &Path) -> Result<(String, Vec<CapturedMessage>), String> {
+    let file = File::open(path).map_err(|e| format!("Failed to open capture {}: {e}", path.display()))?;
+    let mut reader = BufReader::new(file);
+    let invalid = || format!("{} is not a capture file", path.display());
+
+    let mut magic = [0u8; 8];
+    reader.read_exact(&mut magic).map_err(|_| invalid())?;
+    if &magic != CAPTURE_MAGIC {
+        return Err(invalid());
+    }
+    let mut len = [0u8; 1];
+    reader.read_exact(&mut len).map_err(|_| invalid())?;
+    let mut mode = vec![0u8; len[0] as usize];
+    reader.read_exact(&mut mode).map_err(|_| invalid())?;
+    let mode = String::from_utf8(mode).map_err(|_| invalid())?;
+
+    let mut messages = Vec::new();
+    let mut header = [0u8; 20];
+    loop {
+        match reader.read_exact(&mut header) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(format!("Failed to read capture {}: {e}", path.display())),
+        }
+        let len = u32::from_le_bytes(header[16..20].try_into().unwrap()) as usize;
+        let message = match read_message(&mut reader, len) {
+            Ok(Some(message)) => message,
+            // A message cut short was being written when the capture stopped
+            Ok(None) => break,
+            Err(e) => return Err(format!("Failed to read capture {}: {e}", path.display())),
+        };
+        messages.push(CapturedMessage {
+            timestamp_us: u64::from_le_bytes(header[..8].try_into().unwrap()),
+            connection_id: u64::from_le_bytes(header[8..16].try_into().unwrap()),
+            message: Bytes::from(message),
+        });
+    }
+    Ok((mode, messages))
+}
+
+/// Read a message of `len` bytes, `None` when the file ends before it does
+fn read_message(reader: &mut impl Read, len: usize) -> std::io::Result<Option<Vec<u8>>> {
+    if len > MAX_CAPTURED_MESSAGE_SIZE {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("message of {len} bytes exceeds the maximum of {MAX_CAPTURED_MESSAGE_SIZE} bytes"),
+        ));
+    }
+    // Grown as bytes are read, so a truncated file never allocates `len`
+    let mut message = Vec::new();
+    reader.take(len as u64).read_to_end(&mut message)?;
+    Ok((message.len() == len).then_some(message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(path: &Path) -> CaptureConfig {
+        CaptureConfig {
+            path: path.display().to_string(),
+            max_bytes: 1024 * 1024,
+            max_age_sec: 0,
+            max_files: 2,
+            max_queued: 1024,
+            redact_credentials: true,
+        }
+    }
+
+    fn capture_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("puerta-capture-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join("traffic.cap")
+    }
+
+    #[test]
+    fn test_capture_round_trip_with_redaction() {
+        let path = capture_path("round-trip");
+        let carries_credentials = |message: &Bytes| message.starts_with(b"AUTH");
+        let capture = Capture::from_config(Some(&config(&path)), "redis", carries_credentials)
+            .unwrap()
+            .unwrap()
+            .with_redactor(Arc::new(|message: Bytes| {
+                Some(if message.starts_with(b"SET") { Bytes::from_static(b"SET <redacted>") } else { message })
+            }));
+
+        capture.record(1, &Bytes::from_static(b"PING"));
+        capture.record(2, &Bytes::from_static(b"AUTH secret"));
+        capture.record(2, &Bytes::from_static(b"SET k v"));
+        capture.finish();
+
+        let (mode, messages) = read_capture(&path).unwrap();
+        assert_eq!(mode, "redis");
+        let messages: Vec<(u64, &[u8])> = messages
+            .iter()
+            .map(|captured| (captured.connection_id, captured.message.as_ref()))
+            .collect();
+        assert_eq!(messages, vec![(1, b"PING".as_ref()), (2, b"SET <redacted>".as_ref())]);
+
+        assert!(Capture::from_config(None, "redis", carries_credentials).unwrap().is_none());
+        assert!(read_capture(&path.with_extension("missing")).is_err());
+    }
+
+    #[test]
+    fn test_rotation_by_size() {
+        let path = capture_path("rotation");
+        let config = CaptureConfig {
+            max_bytes: 64,
+            ..config(&path)
+        };
+        let capture = Capture::from_config(Some(&config), "mongodb", |_| false).unwrap().unwrap();
+        // Each message takes 20 + 40 bytes, so every one after the first
+        // starts a new file
+        for n in 0..4u8 {
+            capture.record(7, &Bytes::from(vec![n; 40]));
+        }
+        capture.finish();
+
+        let first_byte = |path: &Path| {
+            let (_, messages) = read_capture(path).unwrap();
+            assert_eq!(messages.len(), 1);
+            messages[0].message[0]
+        };
+        assert_eq!(first_byte(&path), 3);
+        assert_eq!(first_byte(&PathBuf::from(format!("{}.1", path.display()))), 2);
+        assert_eq!(first_byte(&PathBuf::from(format!("{}.2", path.display()))), 1);
+        // Only max_files rotated files are kept
+        assert!(!PathBuf::from(format!("{}.3", path.display())).exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_capture_file_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = capture_path("private");
+        std::fs::write(&path, b"earlier run").unwrap();
+        let capture = Capture::from_config(Some(&config(&path)), "redis", |_| false).unwrap().unwrap();
+        capture.finish();
+
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        // The existing file is rotated rather than reused
+        let rotated = PathBuf::from(format!("{}.1", path.display()));
+        assert_eq!(std::fs::read(rotated).unwrap(), b"earlier run");
+    }
+
+    #[test]
+    fn test_truncated_or_oversized_record() {
+        let path = capture_path("corrupt");
+        let capture = Capture::from_config(Some(&config(&path)), "redis", |_| false).unwrap().unwrap();
+        capture.record(1, &Bytes::from_static(b"PING"));
+        capture.finish();
+        let complete = std::fs::read(&path).unwrap();
+
+        // A record announcing more bytes than the file holds
+        let record = |len: u32| {
+            let mut contents = complete.clone();
+            contents.extend_from_slice(&[0u8; 16]);
+            contents.extend_from_slice(&len.to_le_bytes());
+            contents.extend_from_slice(b"PI");
+            contents
+        };
+        std::fs::write(&path, record(100)).unwrap();
+        let (_, messages) = read_capture(&path).unwrap();
+        assert_eq!(messages.len(), 1);
+
+        std::fs::write(&path, record(u32::MAX)).unwrap();
+        let err = read_capture(&path).unwrap_err();
+        assert!(err.contains("exceeds the maximum"), "{err}");
+    }
+}
@@ -0,0 +1,147 @@
+/// Fault injection for resilience testing
+///
+/// With `[chaos]` enabled, a share of client requests is delayed, has its
+/// client connection closed, or (Redis) is answered with a `MOVED` redirect
+/// back to the proxy, so client applications can be tested against a slow
+/// or failing proxy without touching the backends. Each request draws one
+/// number that selects at most one fault. Injected faults are counted in
+/// `puerta_chaos_faults_total`.
+use crate::config::ChaosConfig;
+use rand::Rng;
+use std::time::Duration;
+
+/// A fault to inject into one request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    /// Hold the request for this long before forwarding it
+    Delay(Duration),
+    /// Close the client connection without answering
+    Reset,
+    /// Redirect the client to the proxy with `MOVED`
+    Moved,
+}
+
+impl Fault {
+    fn name(&self) -> &'static str {
+        match self {
+            Fault::Delay(_) => "latency",
+            Fault::Reset => "reset",
+            Fault::Moved => "moved",
+        }
+    }
+}
+
+/// Fault injection shared by every connection of a proxy instance
+#[derive(Debug)]
+pub struct Chaos {
+    config: ChaosConfig,
+    mode: &'static str,
+}
+
+impl Chaos {
+    /// Faults configured in `config` for `mode`, or `None` when disabled
+    pub fn from_config(config: Option<&ChaosConfig>, mode: &'static str) -> Option<Self> {
+        let config = config.filter(|config| config.enabled)?;
+        Some(Self {
+            config: config.clone(),
+            mode,
+        })
+    }
+
+    /// Fault to inject into the next request, if any; `MOVED` only for
+    /// requests that can be redirected
+    pub fn fault(&self, redirectable: bool) -> Option<Fault> {
+        let fault = self.fault_for(rand::random::<f64>() * 100.0, redirectable)?;
+        crate::metrics::global()
+            .counter_with_labels(
+                "puerta_chaos_faults_total",
+                "Faults injected into client requests",
+                &[("mode", self.mode), ("fault", fault.name())],
+            )
+            .inc();
+        Some(fault)
+    }
+
+    /// Fault selected by `roll`, a number in `[0, 100)`
+    fn fault_for(&self, roll: f64, redirectable: bool) -> Option<Fault> {
+        let mut threshold = self.config.reset_percent;
+        if roll < threshold {
+            return Some(Fault::Reset);
+        }
+        threshold += self.config.moved_percent;
+        if roll < threshold {
+            return redirectable.then_some(Fault::Moved);
+        }
+        threshold += self.config.latency_percent;
+        if roll < threshold {
+            let jitter = match self.config.latency_jitter_ms {
+                0 => 0,
+                jitter => rand::thread_rng().gen_range(0..=jitter),
+            };
+            return Some(Fault::Delay(Duration::from_millis(
+                self.config.latency_ms + jitter,
+            )));
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chaos(config: ChaosConfig) -> Chaos {
+        Chaos::from_config(Some(&config), "redis").unwrap()
+    }
+
+    #[test]
+    fn test_disabled_by_default() {
+        assert!(Chaos::from_config(None, "redis").is_none());
+        let config = ChaosConfig {
+            reset_percent: 100.0,
+            ..ChaosConfig::default()
+        };
+        assert!(Chaos::from_config(Some(&config), "redis").is_none());
+    }
+
+    #[test]
+    fn test_fault_bands() {
+        let chaos = chaos(ChaosConfig {
+            enabled: true,
+            reset_percent: 10.0,
+            moved_percent: 20.0,
+            latency_percent: 30.0,
+            latency_ms: 250,
+            ..ChaosConfig::default()
+        });
+        assert_eq!(chaos.fault_for(5.0, true), Some(Fault::Reset));
+        assert_eq!(chaos.fault_for(15.0, true), Some(Fault::Moved));
+        // Requests that cannot be redirected are left alone
+        assert_eq!(chaos.fault_for(15.0, false), None);
+        assert_eq!(
+            chaos.fault_for(45.0, false),
+            Some(Fault::Delay(Duration::from_millis(250)))
+        );
+        assert_eq!(chaos.fault_for(60.0, true), None);
+        assert_eq!(chaos.fault_for(99.9, true), None);
+    }
+
+    #[test]
+    fn test_latency_jitter() {
+        let chaos = chaos(ChaosConfig {
+            enabled: true,
+            latency_percent: 100.0,
+            latency_ms: 100,
+            latency_jitter_ms: 50,
+            ..ChaosConfig::default()
+        });
+        for _ in 0..100 {
+            let Some(Fault::Delay(delay)) = chaos.fault(true) else {
+                panic!("every request should be delayed");
+            };
+            assert!((100..=150).contains(&delay.as_millis()));
+        }
+        let metrics = crate::metrics::global().render_prometheus();
+        assert!(metrics.contains("puerta_chaos_faults_total{mode=\"redis\",fault=\"latency\"}"));
+    }
+}
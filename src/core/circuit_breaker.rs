@@ -0,0 +1,308 @@
+/// Per-backend circuit breakers
+///
+/// The outcomes of each backend's recent requests are kept in a sliding
+/// window. Once enough of them failed the circuit opens and requests routed
+/// to the backend are refused at once for a cooldown, failing fast instead
+/// of waiting on a backend that is down. The circuit then half-opens: a few
+/// probe requests are let through, and it closes once they all succeed or
+/// opens again on the first failure.
+use crate::config::CircuitBreakerConfig;
+use crate::metrics::Counter;
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Circuit state of a backend
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Requests flow normally
+    Closed,
+    /// Requests are refused until the cooldown ends
+    Open,
+    /// Probe requests are let through
+    HalfOpen,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum State {
+    Closed,
+    Open {
+        until: Instant,
+    },
+    HalfOpen {
+        since: Instant,
+        admitted: u32,
+        successes: u32,
+    },
+}
+
+/// Recent outcomes and state for one backend
+#[derive(Debug)]
+struct Circuit {
+    /// Whether each recent request succeeded, oldest first
+    outcomes: VecDeque<bool>,
+    failures: usize,
+    state: State,
+}
+
+impl Default for Circuit {
+    fn default() -> Self {
+        Self {
+            outcomes: VecDeque::new(),
+            failures: 0,
+            state: State::Closed,
+        }
+    }
+}
+
+/// Circuit breakers for every backend of a proxy
+pub struct CircuitBreaker {
+    config: CircuitBreakerConfig,
+    circuits: Mutex<HashMap<SocketAddr, Circuit>>,
+    opened: Arc<Counter>,
+}
+
+impl CircuitBreaker {
+    pub fn new(config: CircuitBreakerConfig) -> Self {
+        Self {
+            config,
+            circuits: Mutex::new(HashMap::new()),
+            opened: crate::metrics::global().counter(
+                "puerta_circuit_breaker_opened_total",
+                "Backend circuits opened after a high failure rate",
+            ),
+        }
+    }
+
+    fn cooldown(&self) -> Duration {
+        Duration::from_secs(self.config.cooldown_sec)
+    }
+
+    /// Whether a request may be sent to `addr`
+    ///
+    /// Open circuits refuse requests until their cooldown ends. A half-open
+    /// circuit admits up to `half_open_probes` requests; probes that never
+    /// report back are replaced after another cooldown.
+    pub fn allow(&self, addr: SocketAddr) -> bool {
+        let now = Instant::now();
+        let mut circuits = self.circuits.lock().unwrap();
+        let Some(circuit) = circuits.get_mut(&addr) else {
+            return true;
+        };
+
+        if let State::Open { until } = circuit.state {
+            if now < until {
+                return false;
+            }
+            log::info!("Half-opening circuit to backend {addr} after cooldown");
+            circuit.state = State::HalfOpen {
+                since: now,
+                admitted: 0,
+                successes: 0,
+            };
+        }
+
+        match &mut circuit.state {
+            State::HalfOpen {
+                since, admitted, ..
+            } => {
+                if *admitted >= self.config.half_open_probes
+                    && now.duration_since(*since) >= self.cooldown()
+                {
+                    *since = now;
+                    *admitted = 0;
+                }
+                if *admitted < self.config.half_open_probes {
+                    *admitted += 1;
+                    true
+                } else {
+                    false
+                }
+            }
+            _ => true,
+        }
+    }
+
+    /// Whether requests to `addr` are currently refused, without taking a
+    /// probe slot
+    pub fn is_open(&self, addr: SocketAddr) -> bool {
+        let now = Instant::now();
+        let circuits = self.circuits.lock().unwrap();
+        match circuits.get(&addr).map(|circuit| circuit.state) {
+            Some(State::Open { until }) => now < until,
+            Some(State::HalfOpen {
+                since, admitted, ..
+            }) => {
+                admitted >= self.config.half_open_probes
+                    && now.duration_since(since) < self.cooldown()
+            }
+            _ => false,
+        }
+    }
+
+    /// Record the outcome of a request to `addr`
+    ///
+    /// Returns `true` when this outcome opens the circuit.
+    pub fn record(&self, addr: SocketAddr, success: bool) -> bool {
+        let mut circuits = self.circuits.lock().unwrap();
+        let circuit = circuits.entry(addr).or_default();
+
+        match &mut circuit.state {
+            // Requests sent before the circuit opened
+            State::Open { .. } => false,
+            State::HalfOpen { successes, .. } => {
+                if !success {
+                    log::warn!("Reopening circuit to backend {addr} after a failed probe");
+                    self.open(circuit);
+                    return true;
+                }
+                *successes += 1;
+                if *successes >= self.config.half_open_probes {
+                    log::info!("Closing circuit to backend {addr} after successful probes");
+                    *circuit = Circuit::default();
+                }
+                false
+            }
+            State::Closed => {
+                circuit.outcomes.push_back(success);
+                if !success {
+                    circuit.failures += 1;
+                }
+                if circuit.outcomes.len() > self.config.window_size
+                    && circuit.outcomes.pop_front() == Some(false)
+                {
+                    circuit.failures -= 1;
+                }
+
+                let requests = circuit.outcomes.len();
+                if requests < self.config.min_requests
+                    || (circuit.failures as f64 / requests as f64)
+                        < self.config.failure_rate_threshold
+                {
+                    return false;
+                }
+                log::warn!(
+                    "Opening circuit to backend {addr}: {} of {requests} recent requests failed",
+                    circuit.failures
+                );
+                self.open(circuit);
+                true
+            }
+        }
+    }
+
+    fn open(&self, circuit: &mut Circuit) {
+        *circuit = Circuit {
+            state: State::Open {
+                until: Instant::now() + self.cooldown(),
+            },
+            ..Circuit::default()
+        };
+        self.opened.inc();
+    }
+
+    /// Current circuit state of `addr`
+    pub fn state(&self, addr: SocketAddr) -> CircuitState {
+        let circuits = self.circuits.lock().unwrap();
+        match circuits.get(&addr).map(|circuit| circuit.state) {
+            Some(State::Open { until }) if Instant::now() < until => CircuitState::Open,
+            // An open circuit past its cooldown half-opens on the next request
+            Some(State::Open { .. }) | Some(State::HalfOpen { .. }) => CircuitState::HalfOpen,
+            _ => CircuitState::Closed,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn breaker() -> CircuitBreaker {
+        CircuitBreaker::new(CircuitBreakerConfig {
+            failure_rate_threshold: 0.5,
+            window_size: 10,
+            min_requests: 4,
+            cooldown_sec: 30,
+            half_open_probes: 2,
+        })
+    }
+
+    /// End the cooldown of an open circuit
+    fn expire(breaker: &CircuitBreaker, addr: SocketAddr) {
+        let mut circuits = breaker.circuits.lock().unwrap();
+        circuits.get_mut(&addr).unwrap().state = State::Open {
+            until: Instant::now(),
+        };
+    }
+
+    #[test]
+    fn test_opens_on_failure_rate() {
+        let breaker = breaker();
+        let addr: SocketAddr = "127.0.0.1:7001".parse().unwrap();
+        assert!(breaker.allow(addr));
+
+        // Too few requests to judge
+        assert!(!breaker.record(addr, false));
+        assert!(!breaker.record(addr, false));
+        assert!(!breaker.record(addr, true));
+        assert_eq!(breaker.state(addr), CircuitState::Closed);
+
+        // Two failures out of four reaches the threshold
+        assert!(breaker.record(addr, true));
+        assert_eq!(breaker.state(addr), CircuitState::Open);
+        assert!(breaker.is_open(addr));
+        assert!(!breaker.allow(addr));
+
+        // Late outcomes don't change an open circuit
+        assert!(!breaker.record(addr, false));
+        assert_eq!(breaker.state(addr), CircuitState::Open);
+    }
+
+    #[test]
+    fn test_failures_slide_out_of_window() {
+        let breaker = breaker();
+        let addr: SocketAddr = "127.0.0.1:7001".parse().unwrap();
+        for _ in 0..3 {
+            breaker.record(addr, false);
+            for _ in 0..4 {
+                breaker.record(addr, true);
+            }
+        }
+        // Never more than 3 failures in the last 10 requests
+        assert_eq!(breaker.state(addr), CircuitState::Closed);
+        assert!(breaker.allow(addr));
+    }
+
+    #[test]
+    fn test_half_open_probes() {
+        let breaker = breaker();
+        let addr: SocketAddr = "127.0.0.1:7001".parse().unwrap();
+        for _ in 0..4 {
+            breaker.record(addr, false);
+        }
+        expire(&breaker, addr);
+        assert_eq!(breaker.state(addr), CircuitState::HalfOpen);
+
+        // Only the configured number of probes is let through
+        assert!(breaker.allow(addr));
+        assert!(breaker.allow(addr));
+        assert!(!breaker.allow(addr));
+        assert!(breaker.is_open(addr));
+
+        // A failed probe opens the circuit again
+        assert!(breaker.record(addr, false));
+        assert_eq!(breaker.state(addr), CircuitState::Open);
+
+        // Successful probes close it
+        expire(&breaker, addr);
+        assert!(breaker.allow(addr));
+        assert!(breaker.allow(addr));
+        breaker.record(addr, true);
+        assert_eq!(breaker.state(addr), CircuitState::HalfOpen);
+        breaker.record(addr, true);
+        assert_eq!(breaker.state(addr), CircuitState::Closed);
+        assert!(breaker.allow(addr));
+        assert!(!breaker.is_open(addr));
+    }
+}
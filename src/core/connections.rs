@@ -0,0 +1,396 @@
+/// Live client connection table
+///
+/// Every proxied client connection registers its `ConnectionStats` for as
+/// long as it is open: the backend it is forwarded to, when it started and
+/// the bytes and requests it has forwarded so far. `GET /connections` lists
+/// them with totals per backend, and per application for MongoDB clients
+/// naming one in their handshake, closed connections included. At most
+/// `MAX_TRACKED_CONNECTIONS` are listed; connections beyond that still
+/// count towards the totals.
+use crate::admin::{AdminResponse, AdminRouter};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Instant;
+
+/// Most connections listed at once
+pub const MAX_TRACKED_CONNECTIONS: usize = 10_000;
+
+/// Counters of one open client connection
+#[derive(Debug)]
+pub struct ConnectionStats {
+    pub id: u64,
+    /// Proxy mode serving the connection (`mongodb` or `redis`)
+    pub mode: &'static str,
+    pub client_addr: String,
+    /// Upstream the connection is forwarded to, `cluster` in Redis mode
+    pub backend: String,
+    pub started: Instant,
+    /// Application the client named, once known
+    application: OnceLock<String>,
+    bytes_from_client: AtomicU64,
+    bytes_to_client: AtomicU64,
+    requests: AtomicU64,
+}
+
+impl ConnectionStats {
+    /// Set the bytes forwarded each way and the requests so far
+    pub fn update(&self, bytes_from_client: u64, bytes_to_client: u64, requests: u64) {
+        self.bytes_from_client
+            .store(bytes_from_client, Ordering::Relaxed);
+        self.bytes_to_client
+            .store(bytes_to_client, Ordering::Relaxed);
+        self.requests.store(requests, Ordering::Relaxed);
+    }
+
+    /// Attribute the connection to `application`; only the first one
+    /// named counts
+    pub fn set_application(&self, application: &str) {
+        let _ = self.application.set(application.to_string());
+    }
+
+    fn info(&self) -> ConnectionInfo {
+        ConnectionInfo {
+            id: self.id,
+            mode: self.mode.to_string(),
+            client_addr: self.client_addr.clone(),
+            backend: self.backend.clone(),
+            application: self.application.get().cloned(),
+            age_secs: self.started.elapsed().as_secs(),
+            bytes_from_client: self.bytes_from_client.load(Ordering::Relaxed),
+            bytes_to_client: self.bytes_to_client.load(Ordering::Relaxed),
+            requests: self.requests.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// An open connection as reported by the admin API
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConnectionInfo {
+    pub id: u64,
+    pub mode: String,
+    pub client_addr: String,
+    pub backend: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub application: Option<String>,
+    /// Seconds since the connection was accepted
+    pub age_secs: u64,
+    pub bytes_from_client: u64,
+    pub bytes_to_client: u64,
+    pub requests: u64,
+}
+
+/// Connections of one backend or application, open and closed
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BackendTotals {
+    /// Connections currently open
+    pub active: u64,
+    /// Connections opened since startup
+    pub total: u64,
+    pub bytes_from_client: u64,
+    pub bytes_to_client: u64,
+    pub requests: u64,
+}
+
+impl BackendTotals {
+    fn add(&mut self, connection: &ConnectionInfo) {
+        self.bytes_from_client += connection.bytes_from_client;
+        self.bytes_to_client += connection.bytes_to_client;
+        self.requests += connection.requests;
+    }
+}
+
+/// Open connections, oldest first, and totals per backend and application
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConnectionsReport {
+    pub connections: Vec<ConnectionInfo>,
+    pub backends: BTreeMap<String, BackendTotals>,
+    /// Totals of the connections attributed to an application
+    #[serde(default)]
+    pub applications: BTreeMap<String, BackendTotals>,
+    /// Open connections left out of the list past `MAX_TRACKED_CONNECTIONS`
+    pub untracked: u64,
+}
+
+/// Open connections of every proxy instance
+pub struct ConnectionRegistry {
+    capacity: usize,
+    next_id: AtomicU64,
+    connections: Mutex<HashMap<u64, Arc<ConnectionStats>>>,
+    /// Open connections not listed, per backend
+    untracked: Mutex<HashMap<String, Vec<Arc<ConnectionStats>>>>,
+    /// Totals of closed connections per backend
+    closed: Mutex<HashMap<String, BackendTotals>>,
+    /// Totals of closed connections per application
+    closed_applications: Mutex<HashMap<String, BackendTotals>>,
+}
+
+/// Registration of an open connection, removed when dropped
+pub struct ConnectionHandle<'a> {
+    registry: &'a ConnectionRegistry,
+    stats: Arc<ConnectionStats>,
+    tracked: bool,
+}
+
+impl std::ops::Deref for ConnectionHandle<'_> {
+    type Target = ConnectionStats;
+
+    fn deref(&self) -> &ConnectionStats {
+        &self.stats
+    }
+}
+
+impl Drop for ConnectionHandle<'_> {
+    fn drop(&mut self) {
+        self.registry.deregister(&self.stats, self.tracked);
+    }
+}
+
+impl ConnectionRegistry {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            next_id: AtomicU64::new(1),
+            connections: Mutex::new(HashMap::new()),
+            untracked: Mutex::new(HashMap::new()),
+            closed: Mutex::new(HashMap::new()),
+            closed_applications: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Register a connection from `client_addr` forwarded to `backend`
+    pub fn register(
+        &self,
+        mode: &'static str,
+        client_addr: &str,
+        backend: &str,
+    ) -> ConnectionHandle<'_> {
+        let stats = Arc::new(ConnectionStats {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            mode,
+            client_addr: client_addr.to_string(),
+            backend: backend.to_string(),
+            started: Instant::now(),
+            application: OnceLock::new(),
+            bytes_from_client: AtomicU64::new(0),
+            bytes_to_client: AtomicU64::new(0),
+            requests: AtomicU64::new(0),
+        });
+        let tracked = {
+            let mut connections = self.connections.lock().unwrap();
+            let tracked = connections.len() < self.capacity;
+            if tracked {
+                connections.insert(stats.id, Arc::clone(&stats));
+            }
+            tracked
+        };
+        if !tracked {
+            self.untracked
+                .lock()
+                .unwrap()
+                .entry(stats.backend.clone())
+                .or_default()
+                .push(Arc::clone(&stats));
+        }
+        ConnectionHandle {
+            registry: self,
+            stats,
+            tracked,
+        }
+    }
+
+    fn deregister(&self, stats: &ConnectionStats, tracked: bool) {
+        if tracked {
+            self.connections.lock().unwrap().remove(&stats.id);
+        } else if let Some(untracked) = self.untracked.lock().unwrap().get_mut(&stats.backend) {
+            untracked.retain(|connection| connection.id != stats.id);
+        }
+        let info = stats.info();
+        let mut closed = self.closed.lock().unwrap();
+        let totals = closed.entry(stats.backend.clone()).or_default();
+        totals.total += 1;
+        totals.add(&info);
+        if let Some(application) = &info.application {
+            let mut closed = self.closed_applications.lock().unwrap();
+            let totals = closed.entry(application.clone()).or_default();
+            totals.total += 1;
+            totals.add(&info);
+        }
+    }
+
+    /// Open connections, only those to `backend` if given, with the totals
+    /// of every backend
+    pub fn report(&self, backend: Option<&str>) -> ConnectionsReport {
+        let mut backends: BTreeMap<String, BackendTotals> = self
+            .closed
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(backend, totals)| (backend.clone(), totals.clone()))
+            .collect();
+        let mut applications: BTreeMap<String, BackendTotals> = self
+            .closed_applications
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(application, totals)| (application.clone(), totals.clone()))
+            .collect();
+        let mut open = |connection: &ConnectionInfo| {
+            let backend = backends.entry(connection.backend.clone()).or_default();
+            let application = connection
+                .application
+                .as_ref()
+                .map(|application| applications.entry(application.clone()).or_default());
+            for totals in std::iter::once(backend).chain(application) {
+                totals.active += 1;
+                totals.total += 1;
+                totals.add(connection);
+            }
+        };
+
+        let mut connections: Vec<ConnectionInfo> = self
+            .connections
+            .lock()
+            .unwrap()
+            .values()
+            .map(|stats| stats.info())
+            .collect();
+        connections.iter().for_each(&mut open);
+        let mut untracked = 0;
+        for stats in self.untracked.lock().unwrap().values().flatten() {
+            open(&stats.info());
+            untracked += 1;
+        }
+
+        connections
+            .retain(|connection| backend.is_none_or(|backend| connection.backend == backend));
+        connections.sort_by_key(|connection| connection.id);
+        ConnectionsReport {
+            connections,
+            backends,
+            applications,
+            untracked,
+        }
+    }
+
+    /// Register `GET /connections`, optionally restricted with `?backend=`
+    pub fn register_admin_routes(&'static self, router: AdminRouter) -> AdminRouter {
+        router.route("GET", "/connections", move |request| {
+            let report = self.report(request.query.get("backend").map(String::as_str));
+            async move { AdminResponse::ok(serde_json::to_value(report).unwrap_or_default()) }
+        })
+    }
+}
+
+/// The process-wide connection table
+pub fn global() -> &'static ConnectionRegistry {
+    static REGISTRY: OnceLock<ConnectionRegistry> = OnceLock::new();
+    REGISTRY.get_or_init(|| ConnectionRegistry::new(MAX_TRACKED_CONNECTIONS))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_connection_registry() {
+        let registry = ConnectionRegistry::new(2);
+        let first = registry.register("mongodb", "10.0.0.5:50000", "10.0.1.1:27017");
+        first.update(100, 2000, 3);
+        let second = registry.register("redis", "10.0.0.6:50001", "cluster");
+        second.update(10, 20, 1);
+        // Past the capacity the connection is counted but not listed
+        let third = registry.register("mongodb", "10.0.0.7:50002", "10.0.1.1:27017");
+        third.update(1, 2, 1);
+
+        let report = registry.report(None);
+        assert_eq!(report.untracked, 1);
+        assert_eq!(report.connections.len(), 2);
+        assert_eq!(report.connections[0].client_addr, "10.0.0.5:50000");
+        assert_eq!(report.connections[0].bytes_to_client, 2000);
+        assert_eq!(
+            report.backends["10.0.1.1:27017"],
+            BackendTotals {
+                active: 2,
+                total: 2,
+                bytes_from_client: 101,
+                bytes_to_client: 2002,
+                requests: 4,
+            }
+        );
+
+        let report = registry.report(Some("cluster"));
+        assert_eq!(report.connections.len(), 1);
+        assert_eq!(report.connections[0].mode, "redis");
+
+        // Closed connections leave the list and stay in the totals
+        drop(first);
+        drop(third);
+        let report = registry.report(None);
+        assert_eq!(report.untracked, 0);
+        assert_eq!(report.connections.len(), 1);
+        let totals = &report.backends["10.0.1.1:27017"];
+        assert_eq!((totals.active, totals.total, totals.requests), (0, 2, 4));
+
+        // A slot freed by a closed connection is used again
+        let _fourth = registry.register("redis", "10.0.0.8:50003", "cluster");
+        assert_eq!(registry.report(None).connections.len(), 2);
+    }
+
+    #[test]
+    fn test_application_totals() {
+        let registry = ConnectionRegistry::new(10);
+        let first = registry.register("mongodb", "10.0.0.5:50000", "10.0.1.1:27017");
+        first.set_application("orders");
+        // A later handshake does not move the connection
+        first.set_application("billing");
+        first.update(100, 2000, 3);
+        let second = registry.register("mongodb", "10.0.0.6:50001", "10.0.1.2:27017");
+        second.set_application("orders");
+        second.update(10, 20, 1);
+        let _anonymous = registry.register("mongodb", "10.0.0.7:50002", "10.0.1.2:27017");
+
+        let report = registry.report(None);
+        assert_eq!(report.connections[0].application.as_deref(), Some("orders"));
+        assert_eq!(report.connections[2].application, None);
+        assert_eq!(report.applications.len(), 1);
+        assert_eq!(
+            report.applications["orders"],
+            BackendTotals {
+                active: 2,
+                total: 2,
+                bytes_from_client: 110,
+                bytes_to_client: 2020,
+                requests: 4,
+            }
+        );
+
+        drop(first);
+        let totals = &registry.report(None).applications["orders"];
+        assert_eq!((totals.active, totals.total, totals.bytes_to_client), (1, 2, 2020));
+    }
+
+    #[tokio::test]
+    async fn test_connections_route() {
+        let router = global().register_admin_routes(AdminRouter::new());
+        let connection = global().register("redis", "10.0.0.9:50004", "test-connections-route");
+        connection.update(7, 8, 2);
+
+        let response = router
+            .handle(crate::admin::AdminRequest {
+                method: "GET".to_string(),
+                path: "/connections".to_string(),
+                query: [("backend".to_string(), "test-connections-route".to_string())]
+                    .into_iter()
+                    .collect(),
+                ..Default::default()
+            })
+            .await;
+        assert_eq!(response.status, 200);
+        let report: ConnectionsReport = serde_json::from_value(response.body).unwrap();
+        assert_eq!(report.connections.len(), 1);
+        assert_eq!(report.connections[0].requests, 2);
+        assert_eq!(report.backends["test-connections-route"].active, 1);
+    }
+}
@@ -0,0 +1,275 @@
+/// Worker thread CPU pinning
+///
+/// Pingora starts the worker threads of each listening service itself, so
+/// they are pinned as they go: the first connection a worker thread serves
+/// pins it to the next core of its listener's list, round robin. Listeners
+/// and the threads pinned for them are listed by `GET /threads`.
+use crate::admin::{AdminResponse, AdminRouter};
+use async_trait::async_trait;
+use pingora_core::apps::ServerApp;
+use pingora_core::protocols::Stream;
+use pingora_core::server::ShutdownWatch;
+use serde::{Deserialize, Serialize};
+use std::cell::Cell;
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+
+thread_local! {
+    /// This thread was already pinned, or failed to be
+    static PINNED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// The cores the worker threads of one listener run on
+#[derive(Debug)]
+pub struct CpuPinning {
+    listener: String,
+    cores: Vec<usize>,
+    next: AtomicUsize,
+    registry: &'static ThreadRegistry,
+}
+
+impl CpuPinning {
+    /// Pinning for `listener`, none without cores
+    pub fn new(listener: String, cores: Vec<usize>) -> Option<Arc<Self>> {
+        Self::with_registry(listener, cores, global())
+    }
+
+    fn with_registry(listener: String, cores: Vec<usize>, registry: &'static ThreadRegistry) -> Option<Arc<Self>> {
+        if cores.is_empty() {
+            return None;
+        }
+        registry.add_listener(&listener, &cores);
+        Some(Arc::new(Self {
+            listener,
+            cores,
+            next: AtomicUsize::new(0),
+            registry,
+        }))
+    }
+
+    /// Pin the calling thread to this listener's next core, unless it was
+    /// pinned before
+    pub fn pin_current_thread(&self) {
+        if PINNED.with(|pinned| pinned.replace(true)) {
+            return;
+        }
+        let core = self.cores[self.next.fetch_add(1, Ordering::Relaxed) % self.cores.len()];
+        let thread = std::thread::current().name().unwrap_or_default().to_string();
+        match set_current_thread_affinity(core) {
+            Ok(()) => {
+                log::info!("Pinned worker thread {thread} of {} to core {core}", self.listener);
+                self.registry.add_thread(&self.listener, ThreadAssignment {
+                    thread,
+                    tid: current_thread_id(),
+                    core,
+                });
+            }
+            Err(e) => log::warn!("Failed to pin worker thread {thread} of {} to core {core}: {e}", self.listener),
+        }
+    }
+}
+
+/// A server app whose worker threads are pinned before serving connections
+pub struct Pinned<A> {
+    app: Arc<A>,
+    pinning: Option<Arc<CpuPinning>>,
+}
+
+impl<A> Pinned<A> {
+    pub fn new(app: A, pinning: Option<Arc<CpuPinning>>) -> Self {
+        Self {
+            app: Arc::new(app),
+            pinning,
+        }
+    }
+}
+
+#[async_trait]
+impl<A: ServerApp + Send + Sync + 'static> ServerApp for Pinned<A> {
+    async fn process_new(self: &Arc<Self>, session: Stream, shutdown: &ShutdownWatch) -> Option<Stream> {
+        if let Some(pinning) = &self.pinning {
+            pinning.pin_current_thread();
+        }
+        self.app.process_new(session, shutdown).await
+    }
+
+    async fn cleanup(&self) {
+        self.app.cleanup().await
+    }
+}
+
+/// A worker thread pinned to a core
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ThreadAssignment {
+    /// Thread name, the service name for Pingora workers
+    pub thread: String,
+    /// Kernel thread id, as shown by `ps -T` or used with `taskset -p`
+    pub tid: i64,
+    pub core: usize,
+}
+
+/// The cores of a listener and the threads pinned to them so far
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ListenerThreads {
+    pub listener: String,
+    pub cores: Vec<usize>,
+    pub threads: Vec<ThreadAssignment>,
+}
+
+/// Thread to core assignments as reported by the admin API
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ThreadsReport {
+    /// Cores available to the process
+    pub available_cores: usize,
+    pub listeners: Vec<ListenerThreads>,
+}
+
+/// Listeners with pinned worker threads
+#[derive(Debug, Default)]
+pub struct ThreadRegistry {
+    listeners: Mutex<Vec<ListenerThreads>>,
+}
+
+impl ThreadRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn add_listener(&self, listener: &str, cores: &[usize]) {
+        self.listeners.lock().unwrap().push(ListenerThreads {
+            listener: listener.to_string(),
+            cores: cores.to_vec(),
+            threads: Vec::new(),
+        });
+    }
+
+    fn add_thread(&self, listener: &str, assignment: ThreadAssignment) {
+        let mut listeners = self.listeners.lock().unwrap();
+        if let Some(entry) = listeners.iter_mut().find(|entry| entry.listener == listener) {
+            entry.threads.push(assignment);
+        }
+    }
+
+    pub fn report(&self) -> ThreadsReport {
+        ThreadsReport {
+            available_cores: std::thread::available_parallelism().map_or(1, usize::from),
+            listeners: self.listeners.lock().unwrap().clone(),
+        }
+    }
+
+    /// Add `GET /threads` to `router`
+    pub fn register_admin_routes(&'static self, router: AdminRouter) -> AdminRouter {
+        router.route("GET", "/threads", move |_request| {
+            let report = self.report();
+            async move { AdminResponse::ok(serde_json::to_value(report).unwrap_or_default()) }
+        })
+    }
+}
+
+/// The process-wide thread assignments
+pub fn global() -> &'static ThreadRegistry {
+    static REGISTRY: OnceLock<ThreadRegistry> = OnceLock::new();
+    REGISTRY.get_or_init(ThreadRegistry::new)
+}
+
+#[cfg(target_os = "linux")]
+fn set_current_thread_affinity(core: usize) -> io::Result<()> {
+    if core >= libc::CPU_SETSIZE as usize {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "core number out of range"));
+    }
+    // Safety: the set is zeroed and `core` is within its size
+    let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+    unsafe { libc::CPU_SET(core, &mut set) };
+    // Safety: pid 0 is the calling thread, `set` outlives the call
+    if unsafe { libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_current_thread_affinity(_core: usize) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "CPU pinning is only available on Linux"))
+}
+
+#[cfg(target_os = "linux")]
+fn current_thread_id() -> i64 {
+    // Safety: gettid has no preconditions
+    i64::from(unsafe { libc::gettid() })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn current_thread_id() -> i64 {
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_no_pinning_without_cores() {
+        assert!(CpuPinning::new("empty".to_string(), Vec::new()).is_none());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_threads_pinned_round_robin() {
+        let registry: &'static ThreadRegistry = Box::leak(Box::new(ThreadRegistry::new()));
+        // A core this process may run on, listed twice so both threads use it
+        let core = unsafe { libc::sched_getcpu() } as usize;
+        let pinning = CpuPinning::with_registry("MongoDB TCP Proxy".to_string(), vec![core, core], registry).unwrap();
+
+        for name in ["worker-1", "worker-2"] {
+            let pinning = Arc::clone(&pinning);
+            std::thread::Builder::new()
+                .name(name.to_string())
+                .spawn(move || {
+                    pinning.pin_current_thread();
+                    // Later connections on the same thread keep its core
+                    pinning.pin_current_thread();
+                    assert_eq!(unsafe { libc::sched_getcpu() } as usize, core);
+                })
+                .unwrap()
+                .join()
+                .unwrap();
+        }
+
+        let report = registry.report();
+        assert!(report.available_cores >= 1);
+        assert_eq!(report.listeners.len(), 1);
+        let threads = &report.listeners[0].threads;
+        assert_eq!(threads.len(), 2);
+        assert_eq!(threads[0].thread, "worker-1");
+        assert_eq!(threads[1].thread, "worker-2");
+        assert!(threads.iter().all(|thread| thread.core == core && thread.tid > 0));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_failed_pinning_is_not_reported() {
+        let registry: &'static ThreadRegistry = Box::leak(Box::new(ThreadRegistry::new()));
+        let pinning = CpuPinning::with_registry("cache".to_string(), vec![usize::MAX], registry).unwrap();
+        std::thread::spawn(move || pinning.pin_current_thread()).join().unwrap();
+
+        let report = registry.report();
+        assert_eq!(report.listeners[0].cores, vec![usize::MAX]);
+        assert!(report.listeners[0].threads.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_threads_route() {
+        let router = global().register_admin_routes(AdminRouter::new());
+        let response = router
+            .handle(crate::admin::AdminRequest {
+                method: "GET".to_string(),
+                path: "/threads".to_string(),
+                ..Default::default()
+            })
+            .await;
+        assert_eq!(response.status, 200);
+        let report: ThreadsReport = serde_json::from_value(response.body).unwrap();
+        assert!(report.available_cores >= 1);
+    }
+}
@@ -0,0 +1,256 @@
+/// Administrative draining of backends
+///
+/// Operators drain a backend before maintenance: it gets no new sessions
+/// while existing ones finish, and sessions still open when the drain
+/// timeout runs out are closed. Re-enabling the backend ends the drain.
+/// Backends are keyed by address so the same registry serves every proxy
+/// instance.
+use crate::admin::{AdminRequest, AdminResponse, AdminRouter};
+use serde::Serialize;
+use serde_json::json;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+
+/// Drain timeout when the request doesn't give one
+pub const DEFAULT_DRAIN_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// A backend being drained, as reported by the admin API
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DrainStatus {
+    pub addr: SocketAddr,
+    /// Seconds until open sessions are closed, 0 once they are
+    pub remaining_secs: u64,
+}
+
+/// Backends being drained and when their sessions are closed
+pub struct Drains {
+    deadlines: Mutex<HashMap<SocketAddr, Instant>>,
+    /// Notifies waiters when a drain starts or ends
+    changes: watch::Sender<()>,
+}
+
+impl Drains {
+    pub fn new() -> Self {
+        Self {
+            deadlines: Mutex::new(HashMap::new()),
+            changes: watch::channel(()).0,
+        }
+    }
+
+    /// Stop new sessions to `addr` and close open ones after `timeout`
+    ///
+    /// Draining a backend again restarts the timeout.
+    pub fn drain(&self, addr: SocketAddr, timeout: Duration) {
+        log::warn!("Draining backend {addr}, closing its sessions in {}s", timeout.as_secs());
+        self.deadlines
+            .lock()
+            .unwrap()
+            .insert(addr, Instant::now() + timeout);
+        self.changes.send_replace(());
+    }
+
+    /// Return `addr` to service, returning whether it was draining
+    pub fn enable(&self, addr: SocketAddr) -> bool {
+        let was_draining = self.deadlines.lock().unwrap().remove(&addr).is_some();
+        if was_draining {
+            log::info!("Re-enabling drained backend {addr}");
+            self.changes.send_replace(());
+        }
+        was_draining
+    }
+
+    pub fn is_draining(&self, addr: SocketAddr) -> bool {
+        self.deadlines.lock().unwrap().contains_key(&addr)
+    }
+
+    /// Addresses of every draining backend
+    pub fn draining(&self) -> Vec<SocketAddr> {
+        self.deadlines.lock().unwrap().keys().copied().collect()
+    }
+
+    /// Every draining backend, by address
+    pub fn list(&self) -> Vec<DrainStatus> {
+        let now = Instant::now();
+        let mut drains: Vec<DrainStatus> = self
+            .deadlines
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(addr, deadline)| DrainStatus {
+                addr: *addr,
+                remaining_secs: deadline.saturating_duration_since(now).as_secs(),
+            })
+            .collect();
+        drains.sort_by_key(|drain| drain.addr);
+        drains
+    }
+
+    fn deadline(&self, addr: SocketAddr) -> Option<Instant> {
+        self.deadlines.lock().unwrap().get(&addr).copied()
+    }
+
+    /// Wait until sessions to `addr` must be closed: the backend is
+    /// draining and its timeout has passed
+    pub async fn force_closed(&self, addr: SocketAddr) {
+        let mut changes = self.changes.subscribe();
+        loop {
+            changes.borrow_and_update();
+            match self.deadline(addr) {
+                Some(deadline) if deadline <= Instant::now() => return,
+                Some(deadline) => {
+                    tokio::select! {
+                        _ = tokio::time::sleep_until(deadline.into()) => {}
+                        _ = changes.changed() => {}
+                    }
+                }
+                // The sender lives as long as `self`
+                None => {
+                    let _ = changes.changed().await;
+                }
+            }
+        }
+    }
+
+    /// Register the `/backends/drain`, `/backends/enable` and
+    /// `/backends/drains` admin endpoints
+    pub fn register_admin_routes(self: &Arc<Self>, router: AdminRouter) -> AdminRouter {
+        let drain = Arc::clone(self);
+        let enable = Arc::clone(self);
+        let list = Arc::clone(self);
+        router
+            .route("POST", "/backends/drain", move |request| {
+                let response = match Self::addr_param(&request) {
+                    Ok(addr) => {
+                        let timeout = request
+                            .query_param("timeout_sec")
+                            .map_or(DEFAULT_DRAIN_TIMEOUT, Duration::from_secs);
+                        drain.drain(addr, timeout);
+                        AdminResponse::ok(json!({
+                            "addr": addr,
+                            "draining": true,
+                            "timeout_sec": timeout.as_secs(),
+                        }))
+                    }
+                    Err(response) => response,
+                };
+                async move { response }
+            })
+            .route("POST", "/backends/enable", move |request| {
+                let response = match Self::addr_param(&request) {
+                    Ok(addr) => AdminResponse::ok(json!({
+                        "addr": addr,
+                        "draining": false,
+                        "was_draining": enable.enable(addr),
+                    })),
+                    Err(response) => response,
+                };
+                async move { response }
+            })
+            .route("GET", "/backends/drains", move |_| {
+                let body = json!({ "drains": list.list() });
+                async move { AdminResponse::ok(body) }
+            })
+    }
+
+    /// The backend address in the `addr` query parameter
+    fn addr_param(request: &AdminRequest) -> Result<SocketAddr, AdminResponse> {
+        let addr = request
+            .query
+            .get("addr")
+            .ok_or_else(|| AdminResponse::error(400, "missing addr parameter"))?;
+        addr.parse()
+            .map_err(|_| AdminResponse::error(400, format!("invalid backend address {addr}")))
+    }
+}
+
+impl Default for Drains {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_drain_and_enable() {
+        let drains = Drains::new();
+        let addr: SocketAddr = "10.0.0.1:27017".parse().unwrap();
+        assert!(!drains.is_draining(addr));
+        assert!(!drains.enable(addr));
+
+        drains.drain(addr, Duration::from_secs(60));
+        assert!(drains.is_draining(addr));
+        assert_eq!(drains.draining(), vec![addr]);
+        assert_eq!(drains.list()[0].addr, addr);
+        assert!(drains.list()[0].remaining_secs > 50);
+
+        assert!(drains.enable(addr));
+        assert!(!drains.is_draining(addr));
+        assert!(drains.list().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_force_closed_after_timeout() {
+        let drains = Arc::new(Drains::new());
+        let addr: SocketAddr = "10.0.0.1:27017".parse().unwrap();
+        let waiter = tokio::spawn({
+            let drains = Arc::clone(&drains);
+            async move { drains.force_closed(addr).await }
+        });
+
+        // Not draining yet, then re-enabled before the timeout
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        drains.drain(addr, Duration::from_secs(60));
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        drains.enable(addr);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!waiter.is_finished());
+
+        drains.drain(addr, Duration::from_millis(20));
+        tokio::time::timeout(Duration::from_secs(5), waiter)
+            .await
+            .unwrap()
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_admin_routes() {
+        let router = Arc::new(Drains::new()).register_admin_routes(AdminRouter::new());
+        let request = |method: &str, path: &str, query: &[(&str, &str)]| AdminRequest {
+            method: method.to_string(),
+            path: path.to_string(),
+            query: query
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            ..AdminRequest::default()
+        };
+
+        let drained = router
+            .handle(request(
+                "POST",
+                "/backends/drain",
+                &[("addr", "10.0.0.1:6379"), ("timeout_sec", "30")],
+            ))
+            .await;
+        assert_eq!(drained.body["timeout_sec"], 30);
+
+        let listed = router.handle(request("GET", "/backends/drains", &[])).await;
+        assert_eq!(listed.body["drains"][0]["addr"], "10.0.0.1:6379");
+
+        let enabled = router
+            .handle(request("POST", "/backends/enable", &[("addr", "10.0.0.1:6379")]))
+            .await;
+        assert_eq!(enabled.body["was_draining"], true);
+
+        let invalid = router
+            .handle(request("POST", "/backends/drain", &[("addr", "mongos-0")]))
+            .await;
+        assert_eq!(invalid.status, 400);
+    }
+}
@@ -1,9 +1,14 @@
 /// Frontend connection management
 use crate::core::Frontend;
+use bytes::{Buf, Bytes};
 use fnv::FnvHashMap;
+use crate::metrics::{Counter, Gauge};
+use std::collections::VecDeque;
 use std::net::SocketAddr;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::sync::{OwnedSemaphorePermit, RwLock, Semaphore};
 
 /// Frontend connection manager
 pub struct FrontendManager {
@@ -57,3 +62,294 @@ impl Default for FrontendManager {
         Self::new()
     }
 }
+
+/// Global cap on concurrent client connections
+///
+/// Each accepted connection holds a permit for its lifetime. Past
+/// `max_connections`, new connections wait up to `queue_timeout` for a
+/// permit and are rejected if none frees up; a zero timeout rejects at once.
+#[derive(Clone)]
+pub struct ConnectionLimiter {
+    semaphore: Arc<Semaphore>,
+    max_connections: usize,
+    queue_timeout: Duration,
+    active: Arc<Gauge>,
+    rejected: Arc<Counter>,
+}
+
+impl ConnectionLimiter {
+    pub fn new(max_connections: usize, queue_timeout: Duration) -> Self {
+        let registry = crate::metrics::global();
+        Self {
+            semaphore: Arc::new(Semaphore::new(max_connections)),
+            max_connections,
+            queue_timeout,
+            active: registry.gauge(
+                "puerta_connections_active",
+                "Client connections currently open",
+            ),
+            rejected: registry.counter(
+                "puerta_connections_rejected_total",
+                "Client connections rejected at the max_connections limit",
+            ),
+        }
+    }
+
+    /// Take a connection slot, `None` when the limit is reached
+    pub async fn acquire(&self) -> Option<ConnectionPermit> {
+        let semaphore = Arc::clone(&self.semaphore);
+        let permit = if self.queue_timeout.is_zero() {
+            semaphore.try_acquire_owned().ok()
+        } else {
+            tokio::time::timeout(self.queue_timeout, semaphore.acquire_owned())
+                .await
+                .ok()
+                .and_then(Result::ok)
+        };
+
+        match permit {
+            Some(permit) => {
+                self.active.inc();
+                Some(ConnectionPermit {
+                    _permit: permit,
+                    active: Arc::clone(&self.active),
+                })
+            }
+            None => {
+                self.rejected.inc();
+                None
+            }
+        }
+    }
+
+    pub fn max_connections(&self) -> usize {
+        self.max_connections
+    }
+
+    /// Connections currently holding a permit
+    pub fn active_connections(&self) -> usize {
+        self.max_connections - self.semaphore.available_permits()
+    }
+}
+
+/// A connection slot, released on drop
+pub struct ConnectionPermit {
+    _permit: OwnedSemaphorePermit,
+    active: Arc<Gauge>,
+}
+
+impl Drop for ConnectionPermit {
+    fn drop(&mut self) {
+        self.active.dec();
+    }
+}
+
+/// Default bytes read from a socket at a time
+pub const DEFAULT_BUFFER_SIZE: usize = 8192;
+
+/// Default bytes queued toward a peer before reads from the other side pause
+pub const DEFAULT_WRITE_HIGH_WATERMARK: usize = 1024 * 1024;
+
+/// Read buffer size and write backpressure thresholds for forwarding
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BufferConfig {
+    /// Bytes read from a socket at a time
+    pub buffer_size: usize,
+    /// Queued bytes at which reads feeding the queue pause
+    pub high_watermark: usize,
+}
+
+impl BufferConfig {
+    pub fn new(buffer_size: usize, high_watermark: usize) -> Self {
+        Self {
+            buffer_size,
+            high_watermark,
+        }
+    }
+
+    /// Queued bytes below which paused reads resume
+    pub fn low_watermark(&self) -> usize {
+        self.high_watermark / 2
+    }
+}
+
+impl Default for BufferConfig {
+    fn default() -> Self {
+        Self::new(DEFAULT_BUFFER_SIZE, DEFAULT_WRITE_HIGH_WATERMARK)
+    }
+}
+
+/// Data waiting to be written to one side of a forwarded connection
+///
+/// Once the queue reaches the high watermark the side feeding it should stop
+/// being read until the queue drains below the low watermark, so a slow
+/// reader bounds what is buffered for it instead of stalling the other
+/// direction or growing without limit.
+pub struct WriteQueue {
+    chunks: VecDeque<Bytes>,
+    len: usize,
+    high_watermark: usize,
+    low_watermark: usize,
+    paused: bool,
+}
+
+impl WriteQueue {
+    pub fn new(config: &BufferConfig) -> Self {
+        Self {
+            chunks: VecDeque::new(),
+            len: 0,
+            high_watermark: config.high_watermark,
+            low_watermark: config.low_watermark(),
+            paused: false,
+        }
+    }
+
+    /// Queue data for writing
+    pub fn push(&mut self, data: Bytes) {
+        self.len += data.len();
+        self.chunks.push_back(data);
+        if self.len >= self.high_watermark {
+            self.paused = true;
+        }
+    }
+
+    /// The next bytes to write
+    pub fn front(&self) -> &[u8] {
+        self.chunks.front().map(|chunk| chunk.as_ref()).unwrap_or(&[])
+    }
+
+    /// Drop `n` written bytes from the front of the queue
+    pub fn consume(&mut self, mut n: usize) {
+        self.len -= n;
+        while n > 0 {
+            let Some(chunk) = self.chunks.front_mut() else {
+                break;
+            };
+            if n < chunk.len() {
+                chunk.advance(n);
+                break;
+            }
+            n -= chunk.len();
+            self.chunks.pop_front();
+        }
+        if self.len <= self.low_watermark {
+            self.paused = false;
+        }
+    }
+
+    /// Whether the side feeding this queue may be read from
+    pub fn accepts_reads(&self) -> bool {
+        !self.paused
+    }
+
+    /// Bytes queued and not yet written
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+/// Read from `reader`, giving up once `idle_timeout` passes without data
+///
+/// Returns `Ok(None)` when the timeout expires first.
+pub async fn read_idle<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    buf: &mut [u8],
+    idle_timeout: Option<Duration>,
+) -> std::io::Result<Option<usize>> {
+    match idle_timeout {
+        Some(idle_timeout) => match tokio::time::timeout(idle_timeout, reader.read(buf)).await {
+            Ok(result) => result.map(Some),
+            Err(_) => Ok(None),
+        },
+        None => reader.read(buf).await.map(Some),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_read_idle_times_out_without_data() {
+        use tokio::io::AsyncWriteExt;
+
+        let (mut client, mut proxy) = tokio::io::duplex(64);
+        let mut buf = [0u8; 16];
+        let idle_timeout = Some(Duration::from_millis(20));
+        assert_eq!(read_idle(&mut proxy, &mut buf, idle_timeout).await.unwrap(), None);
+
+        client.write_all(b"PING").await.unwrap();
+        assert_eq!(read_idle(&mut proxy, &mut buf, idle_timeout).await.unwrap(), Some(4));
+        drop(client);
+        assert_eq!(read_idle(&mut proxy, &mut buf, None).await.unwrap(), Some(0));
+    }
+
+    #[test]
+    fn test_write_queue_watermarks() {
+        let mut queue = WriteQueue::new(&BufferConfig::new(4, 8));
+        queue.push(Bytes::from_static(b"abcd"));
+        queue.push(Bytes::from_static(b"efg"));
+        assert!(queue.accepts_reads());
+
+        // Reads pause at the high watermark
+        queue.push(Bytes::from_static(b"h"));
+        assert!(!queue.accepts_reads());
+        assert_eq!(queue.len(), 8);
+
+        // and stay paused until the queue is back down to the low watermark
+        queue.consume(3);
+        assert_eq!(queue.front(), b"d");
+        assert!(!queue.accepts_reads());
+        queue.consume(1);
+        assert!(queue.accepts_reads());
+        assert_eq!(queue.front(), b"efg");
+
+        queue.consume(4);
+        assert!(queue.is_empty());
+        assert_eq!(queue.front(), b"");
+    }
+
+    #[tokio::test]
+    async fn test_connection_limiter_rejects_past_limit() {
+        let limiter = ConnectionLimiter::new(2, Duration::ZERO);
+        let rejected = limiter.rejected.get();
+
+        let first = limiter.acquire().await.unwrap();
+        let _second = limiter.acquire().await.unwrap();
+        assert_eq!(limiter.active_connections(), 2);
+        assert!(limiter.acquire().await.is_none());
+        assert_eq!(limiter.rejected.get(), rejected + 1);
+
+        // Closing a connection frees its slot
+        drop(first);
+        assert_eq!(limiter.active_connections(), 1);
+        assert!(limiter.acquire().await.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_connection_limiter_queues_until_timeout() {
+        let limiter = ConnectionLimiter::new(1, Duration::from_millis(200));
+        let held = limiter.acquire().await.unwrap();
+
+        // A queued connection gets the slot once it is released
+        let waiter = {
+            let limiter = limiter.clone();
+            tokio::spawn(async move { limiter.acquire().await.is_some() })
+        };
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        drop(held);
+        assert!(waiter.await.unwrap());
+
+        // Without a release the wait times out
+        let _held = limiter.acquire().await.unwrap();
+        let limiter = ConnectionLimiter {
+            queue_timeout: Duration::from_millis(20),
+            ..limiter
+        };
+        assert!(limiter.acquire().await.is_none());
+    }
+}
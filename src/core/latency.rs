@@ -0,0 +1,148 @@
+/// Peak-EWMA latency estimates per backend
+///
+/// Each sample moves a backend's estimate toward it with a decay time of
+/// `decay`, except that a sample above the estimate replaces it at once: a
+/// backend turning slow is avoided straight away and trusted again only
+/// gradually. Between samples the estimate decays toward zero, so a backend
+/// passed over for being slow is tried again and recovers once its latency
+/// is back to normal.
+use rand::Rng;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Decay time of latency estimates
+pub const DEFAULT_LATENCY_DECAY: Duration = Duration::from_secs(10);
+
+/// Latency estimates of backends by address
+pub struct LatencyTracker {
+    decay: Duration,
+    /// Estimate in nanoseconds and when it was last updated
+    estimates: Mutex<HashMap<String, (f64, Instant)>>,
+}
+
+impl LatencyTracker {
+    pub fn new(decay: Duration) -> Self {
+        Self {
+            decay,
+            estimates: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Record a connect or first-byte latency of `addr`
+    pub fn record(&self, addr: &str, latency: Duration) {
+        self.record_at(addr, latency, Instant::now());
+    }
+
+    fn record_at(&self, addr: &str, latency: Duration, now: Instant) {
+        let sample = latency.as_nanos() as f64;
+        let mut estimates = self.estimates.lock().unwrap();
+        let estimate = match estimates.get(addr) {
+            Some(&(estimate, updated)) if sample < estimate => {
+                let weight = self.weight(now.saturating_duration_since(updated));
+                estimate * weight + sample * (1.0 - weight)
+            }
+            _ => sample,
+        };
+        estimates.insert(addr.to_string(), (estimate, now));
+    }
+
+    /// Current latency estimate of `addr`, `None` until it has a sample
+    pub fn estimate(&self, addr: &str) -> Option<Duration> {
+        self.estimate_at(addr, Instant::now())
+    }
+
+    fn estimate_at(&self, addr: &str, now: Instant) -> Option<Duration> {
+        let &(estimate, updated) = self.estimates.lock().unwrap().get(addr)?;
+        let weight = self.weight(now.saturating_duration_since(updated));
+        Some(Duration::from_nanos((estimate * weight) as u64))
+    }
+
+    /// Share of an estimate left after `elapsed`
+    fn weight(&self, elapsed: Duration) -> f64 {
+        (-elapsed.as_secs_f64() / self.decay.as_secs_f64().max(f64::EPSILON)).exp()
+    }
+
+    /// Index of the faster of two candidates picked at random, by estimate
+    /// per unit of weight; `candidates` are `(address, weight)` pairs
+    ///
+    /// Candidates without a sample go first so they get one. Choosing
+    /// between two rather than the fastest of all keeps a burst of requests
+    /// from landing on one backend before its estimate catches up.
+    pub fn select(&self, candidates: &[(&str, usize)]) -> Option<usize> {
+        let cost = |index: usize| {
+            let (addr, weight) = candidates[index];
+            if weight == 0 {
+                return f64::INFINITY;
+            }
+            self.estimate(addr).map_or(0.0, |estimate| estimate.as_nanos() as f64 / weight as f64)
+        };
+        match candidates.len() {
+            0 => None,
+            1 => Some(0),
+            len => {
+                let mut rng = rand::thread_rng();
+                let first = rng.gen_range(0..len);
+                let second = (first + rng.gen_range(1..len)) % len;
+                Some(if cost(second) < cost(first) { second } else { first })
+            }
+        }
+    }
+}
+
+impl Default for LatencyTracker {
+    fn default() -> Self {
+        Self::new(DEFAULT_LATENCY_DECAY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_peak_ewma() {
+        let tracker = LatencyTracker::new(Duration::from_secs(10));
+        let start = Instant::now();
+        assert_eq!(tracker.estimate_at("10.0.0.1:27017", start), None);
+
+        // A slower sample takes over at once
+        tracker.record_at("10.0.0.1:27017", Duration::from_millis(2), start);
+        tracker.record_at("10.0.0.1:27017", Duration::from_millis(50), start);
+        assert_eq!(tracker.estimate_at("10.0.0.1:27017", start), Some(Duration::from_millis(50)));
+
+        // Faster samples bring it down gradually
+        let later = start + Duration::from_secs(10);
+        tracker.record_at("10.0.0.1:27017", Duration::from_millis(2), later);
+        let estimate = tracker.estimate_at("10.0.0.1:27017", later).unwrap();
+        assert!(estimate > Duration::from_millis(15) && estimate < Duration::from_millis(25), "{estimate:?}");
+
+        // Without samples it decays, so a slow backend gets tried again
+        let idle = later + Duration::from_secs(60);
+        assert!(tracker.estimate_at("10.0.0.1:27017", idle).unwrap() < Duration::from_millis(1));
+    }
+
+    #[test]
+    fn test_select_prefers_lower_latency_per_weight() {
+        let tracker = LatencyTracker::default();
+        tracker.record("fast", Duration::from_millis(1));
+        tracker.record("slow", Duration::from_millis(40));
+        let pick = |candidates: &[(&'static str, usize)]| {
+            tracker.select(candidates).map(|index| candidates[index].0)
+        };
+
+        for _ in 0..20 {
+            assert_eq!(pick(&[("slow", 1), ("fast", 1)]), Some("fast"));
+            // Unmeasured backends are tried first
+            assert_eq!(pick(&[("fast", 1), ("new", 1)]), Some("new"));
+            // Weight scales the latency a backend is allowed
+            assert_eq!(pick(&[("fast", 1), ("slow", 100)]), Some("slow"));
+        }
+        // The slowest of three is never picked
+        let candidates = [("slow", 1), ("fast", 1), ("new", 1)];
+        assert!((0..50).all(|_| pick(&candidates) != Some("slow")));
+
+        assert_eq!(pick(&[]), None);
+        assert_eq!(pick(&[("slow", 1)]), Some("slow"));
+    }
+}
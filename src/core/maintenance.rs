@@ -0,0 +1,375 @@
+/// Scheduled maintenance windows
+///
+/// Backends with a `[[maintenance]]` window are drained when it opens and
+/// re-enabled when it closes, through the same registry as administrative
+/// drains. Windows open on a cron schedule in UTC. Each start and end is
+/// logged, and the `puerta_backend_maintenance` gauge is 1 for a backend
+/// while it is in a window, for dashboards to annotate.
+use super::drain::{Drains, DEFAULT_DRAIN_TIMEOUT};
+use crate::config::{Config, MaintenanceWindowConfig};
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::watch;
+
+/// How often the windows are checked
+const CHECK_INTERVAL: Duration = Duration::from_secs(15);
+
+const MONTH_NAMES: &[&str] = &["jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"];
+const DAY_NAMES: &[&str] = &["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+/// A cron schedule: `minute hour day-of-month month day-of-week`
+///
+/// Fields take `*`, values, ranges `a-b`, steps `*/n` or `a-b/n` and
+/// comma-separated lists of those; months and days of the week may be
+/// given by their first three letters, and Sunday is 0 or 7. As in cron,
+/// when both the day of the month and the day of the week are restricted,
+/// either matching is enough.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schedule {
+    minutes: u64,
+    hours: u64,
+    days_of_month: u64,
+    months: u64,
+    days_of_week: u64,
+    any_day_of_month: bool,
+    any_day_of_week: bool,
+}
+
+impl Schedule {
+    pub fn parse(expression: &str) -> Result<Self, String> {
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        let [minute, hour, day_of_month, month, day_of_week] = fields[..] else {
+            return Err(format!("expected 5 fields, found {}", fields.len()));
+        };
+        let days_of_week = parse_field(day_of_week, 0, 7, DAY_NAMES)?;
+        Ok(Self {
+            minutes: parse_field(minute, 0, 59, &[])?,
+            hours: parse_field(hour, 0, 23, &[])?,
+            days_of_month: parse_field(day_of_month, 1, 31, &[])?,
+            months: parse_field(month, 1, 12, MONTH_NAMES)?,
+            // Sunday is both 0 and 7
+            days_of_week: (days_of_week | days_of_week >> 7) & 0x7f,
+            any_day_of_month: day_of_month == "*",
+            any_day_of_week: day_of_week == "*",
+        })
+    }
+
+    /// Whether the schedule fires at the minute starting `minute` minutes
+    /// after the Unix epoch
+    pub fn matches(&self, minute: i64) -> bool {
+        let days = minute.div_euclid(24 * 60);
+        let minute_of_day = minute.rem_euclid(24 * 60);
+        let (_, month, day) = civil_from_days(days);
+        let day_of_week = (days + 4).rem_euclid(7);
+
+        let day_of_month_matches = self.days_of_month & (1 << day) != 0;
+        let day_of_week_matches = self.days_of_week & (1 << day_of_week) != 0;
+        let day_matches = match (self.any_day_of_month, self.any_day_of_week) {
+            (false, false) => day_of_month_matches || day_of_week_matches,
+            _ => day_of_month_matches && day_of_week_matches,
+        };
+        self.minutes & (1 << (minute_of_day % 60)) != 0
+            && self.hours & (1 << (minute_of_day / 60)) != 0
+            && self.months & (1 << month) != 0
+            && day_matches
+    }
+
+    /// The latest minute the schedule fired at within the `duration_min`
+    /// minutes up to and including `minute`
+    pub fn last_fired(&self, minute: i64, duration_min: u64) -> Option<i64> {
+        (0..duration_min as i64)
+            .map(|ago| minute - ago)
+            .find(|&start| self.matches(start))
+    }
+}
+
+/// Bits of the values a cron field allows, `names` standing for `min`
+/// onwards
+fn parse_field(field: &str, min: u32, max: u32, names: &[&str]) -> Result<u64, String> {
+    let value = |text: &str| -> Result<u32, String> {
+        let lower = text.to_ascii_lowercase();
+        let value = match names.iter().position(|name| *name == lower) {
+            Some(index) => index as u32 + min,
+            None => text.parse().map_err(|_| format!("invalid value '{text}'"))?,
+        };
+        if !(min..=max).contains(&value) {
+            return Err(format!("{value} is not between {min} and {max}"));
+        }
+        Ok(value)
+    };
+
+    let mut bits = 0u64;
+    for item in field.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => {
+                let step: u32 = step.parse().map_err(|_| format!("invalid step '{step}'"))?;
+                if step == 0 {
+                    return Err("step must be greater than 0".to_string());
+                }
+                (range, step)
+            }
+            None => (item, 1),
+        };
+        let (first, last) = match range {
+            "*" => (min, max),
+            range => match range.split_once('-') {
+                Some((first, last)) => (value(first)?, value(last)?),
+                // A single value with a step runs to the end of the field
+                None if step > 1 => (value(range)?, max),
+                None => (value(range)?, value(range)?),
+            },
+        };
+        if first > last {
+            return Err(format!("range {first}-{last} is empty"));
+        }
+        for value in (first..=last).step_by(step as usize) {
+            bits |= 1 << value;
+        }
+    }
+    Ok(bits)
+}
+
+/// Year, month and day of the date `days` days after the Unix epoch
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+    let month = (if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 }) as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// A maintenance window with its schedule parsed
+#[derive(Debug, Clone)]
+struct Window {
+    backend: SocketAddr,
+    /// The cron expression, for logging
+    expression: String,
+    schedule: Schedule,
+    duration_min: u64,
+    drain_timeout: Duration,
+}
+
+impl Window {
+    fn from_config(config: &MaintenanceWindowConfig) -> Result<Self, String> {
+        Ok(Self {
+            backend: config
+                .backend
+                .parse()
+                .map_err(|_| format!("invalid backend address {}", config.backend))?,
+            expression: config.schedule.clone(),
+            schedule: Schedule::parse(&config.schedule)?,
+            duration_min: config.duration_min,
+            drain_timeout: config
+                .drain_timeout_sec
+                .map_or(DEFAULT_DRAIN_TIMEOUT, Duration::from_secs),
+        })
+    }
+}
+
+/// Drains backends during their maintenance windows
+pub struct MaintenanceScheduler {
+    drains: Arc<Drains>,
+    windows: Vec<Window>,
+    reloads: Option<watch::Receiver<Arc<Config>>>,
+    /// Backends drained by a window that is still open
+    in_maintenance: HashSet<SocketAddr>,
+}
+
+impl MaintenanceScheduler {
+    /// Schedule `windows`, skipping invalid ones
+    pub fn new(drains: Arc<Drains>, windows: &[MaintenanceWindowConfig]) -> Self {
+        Self {
+            drains,
+            windows: Self::parse_windows(windows),
+            reloads: None,
+            in_maintenance: HashSet::new(),
+        }
+    }
+
+    /// Follow the windows of reloaded configurations
+    pub fn with_reloads(mut self, receiver: watch::Receiver<Arc<Config>>) -> Self {
+        self.reloads = Some(receiver);
+        self
+    }
+
+    fn parse_windows(windows: &[MaintenanceWindowConfig]) -> Vec<Window> {
+        windows
+            .iter()
+            .filter_map(|config| {
+                Window::from_config(config)
+                    .map_err(|e| log::error!("Ignoring maintenance window of {}: {e}", config.backend))
+                    .ok()
+            })
+            .collect()
+    }
+
+    /// Backends in maintenance at `minute` (after the Unix epoch), with the
+    /// schedule and drain timeout of the window they are in
+    fn open_windows(&self, minute: i64) -> HashMap<SocketAddr, &Window> {
+        let mut open = HashMap::new();
+        for window in &self.windows {
+            if window.schedule.last_fired(minute, window.duration_min).is_some() {
+                open.entry(window.backend).or_insert(window);
+            }
+        }
+        open
+    }
+
+    /// Drain the backends whose window opened and re-enable those whose
+    /// window closed by `minute`
+    pub fn check(&mut self, minute: i64) {
+        let open = self.open_windows(minute);
+        let registry = crate::metrics::global();
+        for (addr, window) in &open {
+            if self.in_maintenance.contains(addr) {
+                continue;
+            }
+            log::warn!(
+                "Maintenance window of backend {addr} opened ({} for {} min), draining it",
+                window.expression,
+                window.duration_min
+            );
+            self.drains.drain(*addr, window.drain_timeout);
+            Self::maintenance_gauge(registry, *addr).set(1);
+            registry
+                .counter_with_labels(
+                    "puerta_backend_maintenance_windows_total",
+                    "Maintenance windows the backend entered",
+                    &[("backend", addr.to_string().as_str())],
+                )
+                .inc();
+        }
+
+        let closed: Vec<SocketAddr> = self
+            .in_maintenance
+            .iter()
+            .filter(|addr| !open.contains_key(addr))
+            .copied()
+            .collect();
+        for addr in closed {
+            log::info!("Maintenance window of backend {addr} closed, returning it to service");
+            self.drains.enable(addr);
+            Self::maintenance_gauge(registry, addr).set(0);
+        }
+        self.in_maintenance = open.into_keys().collect();
+    }
+
+    fn maintenance_gauge(registry: &crate::metrics::Registry, addr: SocketAddr) -> Arc<crate::metrics::Gauge> {
+        registry.gauge_with_labels(
+            "puerta_backend_maintenance",
+            "Whether the backend is in a maintenance window",
+            &[("backend", addr.to_string().as_str())],
+        )
+    }
+
+    /// Check the windows every few seconds from a background thread
+    pub fn spawn(mut self) {
+        std::thread::spawn(move || {
+            let rt = crate::utils::background_runtime();
+            rt.block_on(async move {
+                let mut interval = tokio::time::interval(CHECK_INTERVAL);
+                loop {
+                    interval.tick().await;
+                    let reloaded = self
+                        .reloads
+                        .as_mut()
+                        .filter(|receiver| receiver.has_changed().unwrap_or(false))
+                        .map(|receiver| receiver.borrow_and_update().maintenance.clone());
+                    if let Some(windows) = reloaded {
+                        self.windows = Self::parse_windows(&windows);
+                    }
+                    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+                    self.check((now.as_secs() / 60) as i64);
+                }
+            });
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Minutes after the Unix epoch of a UTC date and time
+    fn minute(days: i64, hour: i64, minute: i64) -> i64 {
+        days * 24 * 60 + hour * 60 + minute
+    }
+
+    /// 2024-03-10, a Sunday
+    const SUNDAY: i64 = 19_792;
+
+    #[test]
+    fn test_civil_from_days() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(SUNDAY), (2024, 3, 10));
+        assert_eq!(civil_from_days(19_782), (2024, 2, 29));
+    }
+
+    #[test]
+    fn test_schedule_matches() {
+        let sunday_2am = Schedule::parse("0 2 * * sun").unwrap();
+        assert!(sunday_2am.matches(minute(SUNDAY, 2, 0)));
+        assert!(!sunday_2am.matches(minute(SUNDAY, 2, 1)));
+        assert!(!sunday_2am.matches(minute(SUNDAY + 1, 2, 0)));
+        assert!(sunday_2am.matches(minute(SUNDAY + 7, 2, 0)));
+        assert_eq!(Schedule::parse("0 2 * * 7").unwrap(), sunday_2am);
+
+        let weekday_nights = Schedule::parse("*/15 22-23 * JAN-mar 1-5").unwrap();
+        assert!(weekday_nights.matches(minute(SUNDAY + 1, 22, 45)));
+        assert!(!weekday_nights.matches(minute(SUNDAY + 1, 22, 50)));
+        assert!(!weekday_nights.matches(minute(SUNDAY, 23, 0)));
+        // April 1st is a Monday, but out of the months
+        assert!(!weekday_nights.matches(minute(SUNDAY + 22, 22, 0)));
+
+        // A restricted day of month or of week is enough
+        let first_or_sunday = Schedule::parse("30 4 1,15 * 0").unwrap();
+        assert!(first_or_sunday.matches(minute(SUNDAY, 4, 30)));
+        assert!(first_or_sunday.matches(minute(SUNDAY + 5, 4, 30)));
+        assert!(!first_or_sunday.matches(minute(SUNDAY + 1, 4, 30)));
+
+        for invalid in ["", "0 2 * *", "60 * * * *", "* * 0 * *", "*/0 * * * *", "5-1 * * * *", "* * * foo *"] {
+            assert!(Schedule::parse(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn test_backends_drained_during_their_windows() {
+        let drains = Arc::new(Drains::new());
+        let windows = vec![MaintenanceWindowConfig {
+            backend: "10.0.0.11:27017".to_string(),
+            schedule: "0 2 * * sun".to_string(),
+            duration_min: 60,
+            drain_timeout_sec: Some(120),
+        }];
+        let mut scheduler = MaintenanceScheduler::new(Arc::clone(&drains), &windows);
+        let addr: SocketAddr = "10.0.0.11:27017".parse().unwrap();
+
+        scheduler.check(minute(SUNDAY, 1, 59));
+        assert!(!drains.is_draining(addr));
+        scheduler.check(minute(SUNDAY, 2, 0));
+        assert!(drains.is_draining(addr));
+        assert!((119..=120).contains(&drains.list()[0].remaining_secs));
+
+        // An operator re-enabling the backend is not overruled by the window
+        drains.enable(addr);
+        scheduler.check(minute(SUNDAY, 2, 30));
+        assert!(!drains.is_draining(addr));
+        scheduler.check(minute(SUNDAY, 3, 0));
+        assert!(!drains.is_draining(addr));
+
+        scheduler.check(minute(SUNDAY + 7, 2, 10));
+        assert!(drains.is_draining(addr));
+        scheduler.check(minute(SUNDAY + 7, 3, 0));
+        assert!(!drains.is_draining(addr));
+        assert!(crate::metrics::global()
+            .render_prometheus()
+            .contains("puerta_backend_maintenance{backend=\"10.0.0.11:27017\"} 0"));
+    }
+}
@@ -0,0 +1,181 @@
+/// Shadow traffic to a test backend
+///
+/// With `mirror` set, a share of client connections is copied to a shadow
+/// mongos or Redis node for capacity testing or upgrade validation. Each
+/// mirrored connection gets its own plain TCP connection to the shadow that
+/// carries the same messages, handshake and authentication included, and the
+/// shadow's replies are read and discarded. Mirroring never holds up the
+/// primary path: messages are handed to a background task through a bounded
+/// queue, and a connection whose shadow fails, falls behind or stops reading
+/// is no longer mirrored. `puerta_mirror_messages_total{mode,outcome}` counts messages
+/// mirrored and dropped.
+use crate::config::MirrorConfig;
+use bytes::Bytes;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc::{self, error::TrySendError};
+
+/// How long connecting to the shadow may take
+const MIRROR_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long one write to the shadow may take before it counts as stalled
+const MIRROR_WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Mirroring shared by every connection of a proxy instance
+#[derive(Debug)]
+pub struct Mirror {
+    config: MirrorConfig,
+    mode: &'static str,
+}
+
+impl Mirror {
+    /// Mirroring configured in `config` for `mode`, or `None` when absent
+    pub fn from_config(config: Option<&MirrorConfig>, mode: &'static str) -> Option<Self> {
+        Some(Self {
+            config: config?.clone(),
+            mode,
+        })
+    }
+
+    /// Start mirroring a new client connection, when it is sampled
+    pub fn session(&self) -> Option<MirrorSession> {
+        if rand::random::<f64>() * 100.0 >= self.config.percent {
+            return None;
+        }
+        let (sender, receiver) = mpsc::channel(self.config.max_queued);
+        tokio::spawn(Self::run(self.config.target.clone(), receiver));
+        Some(MirrorSession {
+            sender: Some(sender),
+            target: self.config.target.clone(),
+            mode: self.mode,
+        })
+    }
+
+    /// Write the queued messages to the shadow until the client connection
+    /// ends or the shadow fails
+    async fn run(target: String, mut receiver: mpsc::Receiver<Bytes>) {
+        let stream = match tokio::time::timeout(MIRROR_CONNECT_TIMEOUT, TcpStream::connect(&target)).await {
+            Ok(Ok(stream)) => stream,
+            Ok(Err(e)) => {
+                log::warn!("Failed to connect to mirror target {target}: {e}");
+                return;
+            }
+            Err(_) => {
+                log::warn!("Timed out connecting to mirror target {target}");
+                return;
+            }
+        };
+        let (mut reader, mut writer) = stream.into_split();
+        // Unread replies would fill the socket and stall the shadow
+        let discard = tokio::spawn(async move {
+            let mut buf = vec![0u8; 16 * 1024];
+            while reader.read(&mut buf).await.is_ok_and(|n| n > 0) {}
+        });
+        while let Some(message) = receiver.recv().await {
+            // A shadow that stops reading would otherwise hold this task and
+            // its socket after the client connection is gone
+            match tokio::time::timeout(MIRROR_WRITE_TIMEOUT, writer.write_all(&message)).await {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => {
+                    log::warn!("Failed to write to mirror target {target}: {e}");
+                    break;
+                }
+                Err(_) => {
+                    log::warn!("Mirror target {target} stopped reading, closing its connection");
+                    break;
+                }
+            }
+        }
+        discard.abort();
+    }
+}
+
+/// The mirror of one client connection
+pub struct MirrorSession {
+    /// `None` once the shadow failed or fell behind
+    sender: Option<mpsc::Sender<Bytes>>,
+    target: String,
+    mode: &'static str,
+}
+
+impl MirrorSession {
+    /// Copy `message` to the shadow without waiting for it
+    pub fn send(&mut self, message: &Bytes) {
+        let Some(sender) = &self.sender else {
+            return;
+        };
+        let outcome = match sender.try_send(message.clone()) {
+            Ok(()) => "mirrored",
+            Err(e) => {
+                if let TrySendError::Full(_) = e {
+                    log::warn!("Mirror target {} fell behind, no longer mirroring a client connection", self.target);
+                }
+                // Later messages would reach the shadow out of context
+                self.sender = None;
+                "dropped"
+            }
+        };
+        crate::metrics::global()
+            .counter_with_labels(
+                "puerta_mirror_messages_total",
+                "Client messages copied to the mirror target, or dropped",
+                &[("mode", self.mode), ("outcome", outcome)],
+            )
+            .inc();
+    }
+
+    /// Whether messages are still copied to the shadow
+    pub fn is_active(&self) -> bool {
+        self.sender.is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    fn mirror(target: String, percent: f64, max_queued: usize) -> Mirror {
+        let config = MirrorConfig {
+            target,
+            percent,
+            max_queued,
+        };
+        Mirror::from_config(Some(&config), "redis").unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_messages_reach_shadow() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mirror = mirror(listener.local_addr().unwrap().to_string(), 100.0, 16);
+
+        let mut session = mirror.session().unwrap();
+        session.send(&Bytes::from_static(b"*1\r\n$4\r\nPING\r\n"));
+        session.send(&Bytes::from_static(b"*2\r\n$3\r\nGET\r\n$1\r\nk\r\n"));
+        drop(session);
+
+        let (mut shadow, _) = listener.accept().await.unwrap();
+        let mut received = Vec::new();
+        shadow.read_to_end(&mut received).await.unwrap();
+        assert_eq!(received, b"*1\r\n$4\r\nPING\r\n*2\r\n$3\r\nGET\r\n$1\r\nk\r\n");
+    }
+
+    #[tokio::test]
+    async fn test_sampling_and_backlog() {
+        assert!(Mirror::from_config(None, "redis").is_none());
+        assert!(mirror("127.0.0.1:6379".to_string(), 0.0, 16).session().is_none());
+
+        // Messages stay queued until the mirroring task gets to run, and
+        // the connection stops being mirrored once the queue is full
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mirror = mirror(listener.local_addr().unwrap().to_string(), 100.0, 2);
+        let mut session = mirror.session().unwrap();
+        for _ in 0..2 {
+            session.send(&Bytes::from_static(b"*1\r\n$4\r\nPING\r\n"));
+            assert!(session.is_active());
+        }
+        session.send(&Bytes::from_static(b"*1\r\n$4\r\nPING\r\n"));
+        assert!(!session.is_active());
+    }
+}
@@ -1,7 +1,21 @@
 /// Core abstractions shared between MongoDB and Redis modes
 pub mod backend;
+pub mod backend_limit;
+pub mod capture;
+pub mod chaos;
+pub mod circuit_breaker;
+pub mod connections;
+pub mod cpu;
+pub mod drain;
 pub mod frontend;
+pub mod latency;
+pub mod maintenance;
+pub mod mirror;
+pub mod proxy_protocol;
 pub mod session;
+pub mod splice;
+pub mod upstream;
+pub mod uring;
 
 use std::net::SocketAddr;
 use std::time::SystemTime;
@@ -23,6 +37,8 @@ pub enum BackendMetadata {
     /// MongoDB mongos instance metadata
     MongoDB {
         version: Option<String>,
+        /// Newest wire protocol version the mongos speaks
+        max_wire_version: Option<i32>,
         is_primary: bool,
         connection_count: usize,
     },
@@ -54,6 +70,7 @@ impl Backend {
             last_health_check: None,
             metadata: BackendMetadata::MongoDB {
                 version: None,
+                max_wire_version: None,
                 is_primary: false,
                 connection_count: 0,
             },
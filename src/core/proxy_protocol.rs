@@ -0,0 +1,314 @@
+/// HAProxy PROXY protocol (v1 and v2) header parsing
+///
+/// When puerta runs behind another L4 load balancer, that balancer prefixes
+/// each connection with a PROXY header carrying the original client address.
+/// The header is read before any database traffic, and whatever the client
+/// sent after it is handed back so it can be forwarded as usual.
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+/// Signature opening every v2 header
+const V2_SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+
+/// Longest v1 header including the trailing CRLF
+const V1_MAX_LEN: usize = 107;
+
+/// Fixed part of a v2 header: signature, version/command, family, length
+const V2_HEADER_LEN: usize = 16;
+
+/// How long a client has to send its PROXY header
+pub const HEADER_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Error, PartialEq)]
+pub enum ProxyProtocolError {
+    #[error("connection does not start with a PROXY protocol header")]
+    Missing,
+    #[error("malformed PROXY protocol header: {0}")]
+    Malformed(&'static str),
+    #[error("connection closed before the PROXY protocol header was complete")]
+    Incomplete,
+    #[error("timed out waiting for the PROXY protocol header")]
+    Timeout,
+    #[error("failed to read PROXY protocol header: {0}")]
+    Io(String),
+}
+
+/// What the PROXY header says about the connection
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProxyHeader {
+    /// Relayed connection from `source` to `destination`
+    Proxied {
+        source: SocketAddr,
+        destination: SocketAddr,
+    },
+    /// The balancer's own connection (v2 `LOCAL`) or an unknown protocol;
+    /// the socket peer address applies
+    Local,
+}
+
+impl ProxyHeader {
+    /// The original client address, if the header carries one
+    pub fn source(&self) -> Option<SocketAddr> {
+        match self {
+            ProxyHeader::Proxied { source, .. } => Some(*source),
+            ProxyHeader::Local => None,
+        }
+    }
+}
+
+/// Parse a PROXY header at the start of `buf`
+///
+/// Returns the header and its length in bytes, or `None` if more data is
+/// needed to decide.
+pub fn parse(buf: &[u8]) -> Result<Option<(ProxyHeader, usize)>, ProxyProtocolError> {
+    if buf.starts_with(b"PROXY ") {
+        return parse_v1(buf);
+    }
+    if buf.len() >= V2_SIGNATURE.len() && buf[..V2_SIGNATURE.len()] == V2_SIGNATURE {
+        return parse_v2(buf);
+    }
+
+    // Wait until the prefix rules out both signatures
+    let could_be_v1 = b"PROXY ".starts_with(&buf[..buf.len().min(6)]);
+    let could_be_v2 = V2_SIGNATURE.starts_with(&buf[..buf.len().min(V2_SIGNATURE.len())]);
+    if could_be_v1 || could_be_v2 {
+        Ok(None)
+    } else {
+        Err(ProxyProtocolError::Missing)
+    }
+}
+
+/// `PROXY TCP4 <src> <dst> <src port> <dst port>\r\n` or `PROXY UNKNOWN ...\r\n`
+fn parse_v1(buf: &[u8]) -> Result<Option<(ProxyHeader, usize)>, ProxyProtocolError> {
+    let Some(end) = buf.windows(2).position(|window| window == b"\r\n") else {
+        if buf.len() >= V1_MAX_LEN {
+            return Err(ProxyProtocolError::Malformed("v1 header too long"));
+        }
+        return Ok(None);
+    };
+    if end + 2 > V1_MAX_LEN {
+        return Err(ProxyProtocolError::Malformed("v1 header too long"));
+    }
+
+    let line = std::str::from_utf8(&buf[..end])
+        .map_err(|_| ProxyProtocolError::Malformed("v1 header is not ASCII"))?;
+    let mut fields = line.split(' ').skip(1);
+    let header = match fields.next() {
+        Some("UNKNOWN") => ProxyHeader::Local,
+        Some(protocol @ ("TCP4" | "TCP6")) => {
+            let mut next = || {
+                fields
+                    .next()
+                    .ok_or(ProxyProtocolError::Malformed("v1 header is missing fields"))
+            };
+            let (src_ip, dst_ip, src_port, dst_port) = (next()?, next()?, next()?, next()?);
+            let parse_ip = |ip: &str| -> Result<IpAddr, ProxyProtocolError> {
+                let ip: IpAddr = ip
+                    .parse()
+                    .map_err(|_| ProxyProtocolError::Malformed("invalid v1 address"))?;
+                if ip.is_ipv4() != (protocol == "TCP4") {
+                    return Err(ProxyProtocolError::Malformed("v1 address family mismatch"));
+                }
+                Ok(ip)
+            };
+            let parse_port = |port: &str| {
+                port.parse::<u16>()
+                    .map_err(|_| ProxyProtocolError::Malformed("invalid v1 port"))
+            };
+            ProxyHeader::Proxied {
+                source: SocketAddr::new(parse_ip(src_ip)?, parse_port(src_port)?),
+                destination: SocketAddr::new(parse_ip(dst_ip)?, parse_port(dst_port)?),
+            }
+        }
+        _ => return Err(ProxyProtocolError::Malformed("unknown v1 protocol")),
+    };
+    Ok(Some((header, end + 2)))
+}
+
+/// Binary v2 header: signature, version/command, family/transport, length,
+/// addresses and optional TLVs (ignored)
+fn parse_v2(buf: &[u8]) -> Result<Option<(ProxyHeader, usize)>, ProxyProtocolError> {
+    if buf.len() < V2_HEADER_LEN {
+        return Ok(None);
+    }
+    let version_command = buf[12];
+    if version_command >> 4 != 2 {
+        return Err(ProxyProtocolError::Malformed("unsupported version"));
+    }
+    let family = buf[13];
+    let len = u16::from_be_bytes([buf[14], buf[15]]) as usize;
+    let total = V2_HEADER_LEN + len;
+    if buf.len() < total {
+        return Ok(None);
+    }
+    let addresses = &buf[V2_HEADER_LEN..total];
+
+    let header = match version_command & 0x0f {
+        0x0 => ProxyHeader::Local,
+        0x1 => match family {
+            // TCP or UDP over IPv4
+            0x11 | 0x12 => {
+                if addresses.len() < 12 {
+                    return Err(ProxyProtocolError::Malformed("short IPv4 address block"));
+                }
+                let ip = |at: usize| {
+                    IpAddr::V4(Ipv4Addr::new(
+                        addresses[at],
+                        addresses[at + 1],
+                        addresses[at + 2],
+                        addresses[at + 3],
+                    ))
+                };
+                let port = |at: usize| u16::from_be_bytes([addresses[at], addresses[at + 1]]);
+                ProxyHeader::Proxied {
+                    source: SocketAddr::new(ip(0), port(8)),
+                    destination: SocketAddr::new(ip(4), port(10)),
+                }
+            }
+            // TCP or UDP over IPv6
+            0x21 | 0x22 => {
+                if addresses.len() < 36 {
+                    return Err(ProxyProtocolError::Malformed("short IPv6 address block"));
+                }
+                let ip = |at: usize| {
+                    let mut octets = [0u8; 16];
+                    octets.copy_from_slice(&addresses[at..at + 16]);
+                    IpAddr::V6(Ipv6Addr::from(octets))
+                };
+                let port = |at: usize| u16::from_be_bytes([addresses[at], addresses[at + 1]]);
+                ProxyHeader::Proxied {
+                    source: SocketAddr::new(ip(0), port(32)),
+                    destination: SocketAddr::new(ip(16), port(34)),
+                }
+            }
+            // Unspecified or Unix sockets carry no usable client IP
+            _ => ProxyHeader::Local,
+        },
+        _ => return Err(ProxyProtocolError::Malformed("unsupported command")),
+    };
+    Ok(Some((header, total)))
+}
+
+/// Read a PROXY header from the start of `stream`
+///
+/// Returns the header and any bytes read past it, which belong to the
+/// client's first request.
+pub async fn read_header<S: AsyncRead + Unpin>(
+    stream: &mut S,
+) -> Result<(ProxyHeader, Vec<u8>), ProxyProtocolError> {
+    tokio::time::timeout(HEADER_TIMEOUT, async {
+        let mut buf = Vec::with_capacity(V2_HEADER_LEN);
+        let mut chunk = [0u8; 512];
+        loop {
+            if let Some((header, len)) = parse(&buf)? {
+                return Ok((header, buf.split_off(len)));
+            }
+            let n = stream
+                .read(&mut chunk)
+                .await
+                .map_err(|e| ProxyProtocolError::Io(e.to_string()))?;
+            if n == 0 {
+                return Err(ProxyProtocolError::Incomplete);
+            }
+            buf.extend_from_slice(&chunk[..n]);
+        }
+    })
+    .await
+    .map_err(|_| ProxyProtocolError::Timeout)?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v2_header(command: u8, family: u8, addresses: &[u8]) -> Vec<u8> {
+        let mut header = V2_SIGNATURE.to_vec();
+        header.push(0x20 | command);
+        header.push(family);
+        header.extend_from_slice(&(addresses.len() as u16).to_be_bytes());
+        header.extend_from_slice(addresses);
+        header
+    }
+
+    #[test]
+    fn test_parse_v1() {
+        let buf = b"PROXY TCP4 192.168.0.1 10.0.0.1 56324 27017\r\nrest";
+        let (header, len) = parse(buf).unwrap().unwrap();
+        assert_eq!(header.source(), Some("192.168.0.1:56324".parse().unwrap()));
+        assert_eq!(&buf[len..], b"rest");
+
+        let (header, _) = parse(b"PROXY TCP6 ::1 ::2 1 2\r\n").unwrap().unwrap();
+        assert_eq!(header.source(), Some("[::1]:1".parse().unwrap()));
+        let (header, _) = parse(b"PROXY UNKNOWN\r\n").unwrap().unwrap();
+        assert_eq!(header, ProxyHeader::Local);
+
+        // Incomplete headers wait for more data
+        assert_eq!(parse(b"PRO").unwrap(), None);
+        assert_eq!(parse(b"PROXY TCP4 192.168").unwrap(), None);
+
+        assert!(parse(b"PROXY TCP4 ::1 ::2 1 2\r\n").is_err());
+        assert!(parse(b"PROXY TCP4 1.2.3.4 5.6.7.8 99999 1\r\n").is_err());
+        assert!(parse(&[b'x'; 200]).is_err());
+    }
+
+    #[test]
+    fn test_parse_v2() {
+        let mut addresses = vec![192, 168, 0, 1, 10, 0, 0, 1];
+        addresses.extend_from_slice(&56324u16.to_be_bytes());
+        addresses.extend_from_slice(&6379u16.to_be_bytes());
+        let mut buf = v2_header(0x1, 0x11, &addresses);
+        let header_len = buf.len();
+        buf.extend_from_slice(b"*1\r\n");
+
+        let (header, len) = parse(&buf).unwrap().unwrap();
+        assert_eq!(
+            header,
+            ProxyHeader::Proxied {
+                source: "192.168.0.1:56324".parse().unwrap(),
+                destination: "10.0.0.1:6379".parse().unwrap(),
+            }
+        );
+        assert_eq!(len, header_len);
+        assert_eq!(parse(&buf[..20]).unwrap(), None);
+
+        let mut addresses = vec![0u8; 36];
+        addresses[15] = 1;
+        addresses[32..34].copy_from_slice(&443u16.to_be_bytes());
+        let (header, _) = parse(&v2_header(0x1, 0x21, &addresses)).unwrap().unwrap();
+        assert_eq!(header.source(), Some("[::1]:443".parse().unwrap()));
+
+        // Health checks from the balancer use LOCAL
+        let (header, _) = parse(&v2_header(0x0, 0x00, &[])).unwrap().unwrap();
+        assert_eq!(header, ProxyHeader::Local);
+
+        assert!(parse(&v2_header(0x1, 0x11, &[1, 2, 3])).is_err());
+    }
+
+    #[test]
+    fn test_parse_rejects_missing_header() {
+        assert_eq!(
+            parse(b"*1\r\n$4\r\nPING\r\n"),
+            Err(ProxyProtocolError::Missing)
+        );
+        assert_eq!(parse(&[0x3a, 0, 0, 0]), Err(ProxyProtocolError::Missing));
+    }
+
+    #[tokio::test]
+    async fn test_read_header_returns_remaining_bytes() {
+        let (mut client, mut server) = tokio::io::duplex(64);
+        tokio::spawn(async move {
+            use tokio::io::AsyncWriteExt;
+            client.write_all(b"PROXY TCP4 1.2.3.4 ").await.unwrap();
+            client
+                .write_all(b"5.6.7.8 1000 27017\r\nhello")
+                .await
+                .unwrap();
+        });
+
+        let (header, remaining) = read_header(&mut server).await.unwrap();
+        assert_eq!(header.source(), Some("1.2.3.4:1000".parse().unwrap()));
+        assert_eq!(remaining, b"hello");
+    }
+}
@@ -0,0 +1,246 @@
+/// Zero-copy relay between sockets (Linux)
+///
+/// Each direction of a relayed connection has a pipe: bytes are spliced from
+/// the sending socket into the pipe and from the pipe into the receiving
+/// socket with splice(2), so they never enter userspace. Sockets are watched
+/// for readiness through duplicates of their descriptors while the streams
+/// owning them stay open. On other platforms setting up a relay fails and
+/// callers fall back to their copy loop.
+use std::io;
+
+/// Failure of one step of a relay, by the socket it happened on
+#[derive(Debug, thiserror::Error)]
+pub enum SpliceError {
+    #[error("read failed: {0}")]
+    Read(io::Error),
+    #[error("write failed: {0}")]
+    Write(io::Error),
+}
+
+#[cfg(target_os = "linux")]
+pub use linux::{Splice, SpliceSocket};
+
+#[cfg(not(target_os = "linux"))]
+pub use fallback::{Splice, SpliceSocket};
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use super::SpliceError;
+    use std::io;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+    use tokio::io::unix::AsyncFd;
+
+    /// Bytes a relay pipe holds, the most one splice moves
+    const PIPE_SIZE: usize = 1024 * 1024;
+
+    /// A socket of a relayed connection, registered for readiness apart
+    /// from the stream owning it
+    pub struct SpliceSocket {
+        fd: AsyncFd<OwnedFd>,
+    }
+
+    impl SpliceSocket {
+        pub fn new(fd: RawFd) -> io::Result<Self> {
+            // Safety: plain fcntl on a descriptor the caller keeps open
+            let dup = unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, 0) };
+            if dup < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            // Safety: `dup` is a new descriptor nothing else owns
+            let fd = unsafe { OwnedFd::from_raw_fd(dup) };
+            Ok(Self { fd: AsyncFd::new(fd)? })
+        }
+    }
+
+    /// One direction of a relay, with the pipe its bytes pass through
+    pub struct Splice {
+        pipe_read: OwnedFd,
+        pipe_write: OwnedFd,
+        /// Bytes in the pipe not yet written out
+        buffered: usize,
+        /// The sending socket reached end of stream
+        eof: bool,
+    }
+
+    impl Splice {
+        pub fn new() -> io::Result<Self> {
+            let mut fds = [0; 2];
+            // Safety: `fds` has room for the two descriptors
+            if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_NONBLOCK | libc::O_CLOEXEC) } < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            // Safety: both descriptors are new and owned here
+            let (pipe_read, pipe_write) = unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) };
+            // A larger pipe moves more per splice; the default size still works
+            // Safety: plain fcntl on an owned descriptor
+            unsafe { libc::fcntl(pipe_write.as_raw_fd(), libc::F_SETPIPE_SZ, PIPE_SIZE as libc::c_int) };
+            Ok(Self {
+                pipe_read,
+                pipe_write,
+                buffered: 0,
+                eof: false,
+            })
+        }
+
+        /// Move bytes from `from` to `to`, returning how many reached `to`:
+        /// 0 once `from` ended and everything it sent was delivered
+        ///
+        /// Cancel safe: bytes already in the pipe are written by the next
+        /// call.
+        pub async fn step(&mut self, from: &SpliceSocket, to: &SpliceSocket) -> Result<usize, SpliceError> {
+            loop {
+                if self.buffered == 0 {
+                    if self.eof {
+                        return Ok(0);
+                    }
+                    let mut ready = from.fd.readable().await.map_err(SpliceError::Read)?;
+                    match ready.try_io(|fd| splice(fd.as_raw_fd(), self.pipe_write.as_raw_fd(), PIPE_SIZE)) {
+                        Ok(Ok(0)) => self.eof = true,
+                        Ok(Ok(n)) => self.buffered = n,
+                        Ok(Err(e)) => return Err(SpliceError::Read(e)),
+                        Err(_would_block) => {}
+                    }
+                    continue;
+                }
+
+                let mut ready = to.fd.writable().await.map_err(SpliceError::Write)?;
+                match ready.try_io(|fd| splice(self.pipe_read.as_raw_fd(), fd.as_raw_fd(), self.buffered)) {
+                    Ok(Ok(n)) => {
+                        self.buffered -= n;
+                        return Ok(n);
+                    }
+                    Ok(Err(e)) => return Err(SpliceError::Write(e)),
+                    Err(_would_block) => {}
+                }
+            }
+        }
+    }
+
+    /// Splice up to `len` bytes from `fd_in` to `fd_out`
+    fn splice(fd_in: RawFd, fd_out: RawFd, len: usize) -> io::Result<usize> {
+        // Safety: no offsets are passed, both descriptors are open
+        let n = unsafe {
+            libc::splice(
+                fd_in,
+                std::ptr::null_mut(),
+                fd_out,
+                std::ptr::null_mut(),
+                len,
+                libc::SPLICE_F_MOVE | libc::SPLICE_F_NONBLOCK,
+            )
+        };
+        if n < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(n as usize)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use std::time::Duration;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::net::{TcpListener, TcpStream};
+
+        async fn pair() -> (TcpStream, TcpStream) {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let client = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+            let (server, _) = listener.accept().await.unwrap();
+            (client, server)
+        }
+
+        #[tokio::test]
+        async fn test_relay_between_sockets() {
+            // client <-> (front, back) <-> server, relayed from front to back
+            let (mut client, front) = pair().await;
+            let (back, mut server) = pair().await;
+            let from = SpliceSocket::new(front.as_raw_fd()).unwrap();
+            let to = SpliceSocket::new(back.as_raw_fd()).unwrap();
+            let mut splice = Splice::new().unwrap();
+
+            let payload: Vec<u8> = (0..3_000_000u32).map(|i| i as u8).collect();
+            let sent = payload.clone();
+            let sender = tokio::spawn(async move {
+                client.write_all(&sent).await.unwrap();
+                // End of stream reaches the relay once everything was read
+                drop(client);
+            });
+            let receiver = tokio::spawn(async move {
+                let mut received = Vec::new();
+                server.read_to_end(&mut received).await.unwrap();
+                received
+            });
+
+            let mut relayed = 0;
+            loop {
+                let n = tokio::time::timeout(Duration::from_secs(10), splice.step(&from, &to))
+                    .await
+                    .unwrap()
+                    .unwrap();
+                if n == 0 {
+                    break;
+                }
+                relayed += n;
+            }
+            assert_eq!(relayed, payload.len());
+            sender.await.unwrap();
+            drop((from, to, back));
+            assert_eq!(receiver.await.unwrap(), payload);
+        }
+
+        #[tokio::test]
+        async fn test_step_is_cancel_safe() {
+            let (mut client, front) = pair().await;
+            let (back, mut server) = pair().await;
+            let from = SpliceSocket::new(front.as_raw_fd()).unwrap();
+            let to = SpliceSocket::new(back.as_raw_fd()).unwrap();
+            let mut splice = Splice::new().unwrap();
+
+            // Nothing to relay yet: the step is dropped while waiting
+            let step = tokio::time::timeout(Duration::from_millis(20), splice.step(&from, &to)).await;
+            assert!(step.is_err());
+
+            client.write_all(b"hello").await.unwrap();
+            assert_eq!(splice.step(&from, &to).await.unwrap(), 5);
+            let mut received = [0u8; 5];
+            server.read_exact(&mut received).await.unwrap();
+            assert_eq!(&received, b"hello");
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod fallback {
+    use super::SpliceError;
+    use std::io;
+
+    fn unsupported() -> io::Error {
+        io::Error::new(io::ErrorKind::Unsupported, "splice(2) is only available on Linux")
+    }
+
+    /// A socket of a relayed connection, never set up off Linux
+    pub struct SpliceSocket {
+        _private: (),
+    }
+
+    impl SpliceSocket {
+        pub fn new(_fd: i32) -> io::Result<Self> {
+            Err(unsupported())
+        }
+    }
+
+    /// One direction of a relay, never set up off Linux
+    pub struct Splice {
+        _private: (),
+    }
+
+    impl Splice {
+        pub fn new() -> io::Result<Self> {
+            Err(unsupported())
+        }
+
+        pub async fn step(&mut self, _from: &SpliceSocket, _to: &SpliceSocket) -> Result<usize, SpliceError> {
+            Err(SpliceError::Read(unsupported()))
+        }
+    }
+}
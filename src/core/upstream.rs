@@ -0,0 +1,277 @@
+/// Upstream peer and connector construction
+///
+/// Builds Pingora peers and connectors for backend connections, applying the
+/// optional upstream TLS settings (CA bundle, client certificate for mTLS and
+/// SNI override) and TCP keepalive shared by MongoDB and Redis modes.
+use crate::config::{TcpKeepaliveConfig, UpstreamTlsConfig};
+use pingora_core::connectors::{ConnectorOptions, TransportConnector};
+use pingora_core::protocols::Stream;
+use pingora_core::upstreams::peer::{BasicPeer, Peer, TcpKeepalive};
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Keepalive pool size used for upstream connectors
+const KEEPALIVE_POOL_SIZE: usize = 128;
+
+/// Build a peer for `addr`, enabling TLS when `tls` is set and keepalive
+/// probes when `keepalive` is
+pub fn new_peer(
+    addr: &str,
+    tls: Option<&UpstreamTlsConfig>,
+    keepalive: Option<&TcpKeepaliveConfig>,
+) -> BasicPeer {
+    let mut peer = BasicPeer::new(addr);
+    peer.options.tcp_keepalive = keepalive.map(tcp_keepalive);
+
+    if let Some(tls) = tls {
+        // A non-empty SNI is what turns TLS on for a BasicPeer
+        peer.sni = match &tls.sni {
+            Some(sni) => sni.clone(),
+            None => host_part(addr).to_string(),
+        };
+        peer.options.verify_cert = tls.verify_cert;
+        peer.options.verify_hostname = tls.verify_hostname;
+    }
+
+    peer
+}
+
+/// Pingora keepalive settings for a socket
+pub fn tcp_keepalive(config: &TcpKeepaliveConfig) -> TcpKeepalive {
+    TcpKeepalive {
+        idle: Duration::from_secs(config.idle_sec),
+        interval: Duration::from_secs(config.interval_sec),
+        count: config.count,
+        // Zero keeps the kernel default
+        #[cfg(target_os = "linux")]
+        user_timeout: Duration::from_secs(config.user_timeout_sec.unwrap_or_default()),
+    }
+}
+
+/// Connector options carrying the CA bundle and client certificate
+pub fn connector_options(tls: Option<&UpstreamTlsConfig>) -> Option<ConnectorOptions> {
+    let tls = tls?;
+
+    let mut options = ConnectorOptions::new(KEEPALIVE_POOL_SIZE);
+    options.ca_file = tls.ca_file.clone();
+    if let (Some(cert), Some(key)) = (&tls.cert_path, &tls.key_path) {
+        options.cert_key_file = Some((cert.clone(), key.clone()));
+    }
+
+    Some(options)
+}
+
+/// Build a connector for upstream connections
+pub fn new_connector(tls: Option<&UpstreamTlsConfig>) -> TransportConnector {
+    TransportConnector::new(connector_options(tls))
+}
+
+/// Connections opened to backends ahead of demand, each handed out once
+///
+/// Spares older than `max_age` are closed rather than handed out, as the
+/// backend or a middlebox may have dropped them meanwhile.
+pub struct SpareConnections {
+    connector: TransportConnector,
+    per_backend: usize,
+    max_age: Duration,
+    spares: Mutex<HashMap<String, Vec<(Stream, Instant)>>>,
+}
+
+impl SpareConnections {
+    pub fn new(connector: TransportConnector, per_backend: usize, max_age: Duration) -> Self {
+        Self {
+            connector,
+            per_backend,
+            max_age,
+            spares: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Take a spare connection to `addr`, if one is fresh enough
+    pub fn take(&self, addr: &str) -> Option<Stream> {
+        self.lend(addr).map(|(stream, _)| stream)
+    }
+
+    /// Take a spare connection to `addr` with when it was opened, to be
+    /// handed back with [`Self::put_back`] after a health check used it
+    pub fn lend(&self, addr: &str) -> Option<(Stream, Instant)> {
+        let mut spares = self.spares.lock().unwrap();
+        let spares = spares.get_mut(addr)?;
+        while let Some((stream, opened)) = spares.pop() {
+            if opened.elapsed() < self.max_age {
+                return Some((stream, opened));
+            }
+        }
+        None
+    }
+
+    /// Hand back a lent spare, unless `addr` got its spares meanwhile
+    pub fn put_back(&self, addr: &str, stream: Stream, opened: Instant) {
+        let mut spares = self.spares.lock().unwrap();
+        let spares = spares.entry(addr.to_string()).or_default();
+        if spares.len() < self.per_backend {
+            spares.push((stream, opened));
+        }
+    }
+
+    /// Open connections to `peer` until it has `per_backend` fresh spares,
+    /// returning how many were opened
+    pub async fn fill(&self, peer: &BasicPeer) -> Result<usize, Box<dyn Error + Send + Sync>> {
+        let addr = peer.address().to_string();
+        let missing = {
+            let mut spares = self.spares.lock().unwrap();
+            let spares = spares.entry(addr.clone()).or_default();
+            spares.retain(|(_, opened)| opened.elapsed() < self.max_age);
+            self.per_backend.saturating_sub(spares.len())
+        };
+        for _ in 0..missing {
+            let stream = self.connector.new_stream(peer).await?;
+            self.spares
+                .lock()
+                .unwrap()
+                .entry(addr.clone())
+                .or_default()
+                .push((stream, Instant::now()));
+        }
+        if missing > 0 {
+            log::debug!("Opened {missing} spare connections to {addr}");
+        }
+        Ok(missing)
+    }
+
+    /// Close the spares of backends not in `addrs`, e.g. after they turned
+    /// unhealthy or were removed
+    pub fn retain(&self, addrs: &[String]) {
+        self.spares
+            .lock()
+            .unwrap()
+            .retain(|addr, _| addrs.contains(addr));
+    }
+
+    /// Number of spare connections held for `addr`
+    pub fn count(&self, addr: &str) -> usize {
+        self.spares.lock().unwrap().get(addr).map_or(0, Vec::len)
+    }
+}
+
+/// Strip the port from a `host:port` address
+fn host_part(addr: &str) -> &str {
+    match addr.rsplit_once(':') {
+        Some((host, _)) => host.trim_start_matches('[').trim_end_matches(']'),
+        None => addr,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pingora_core::upstreams::peer::Peer;
+
+    fn tls_config() -> UpstreamTlsConfig {
+        UpstreamTlsConfig {
+            ca_file: Some("/etc/puerta/ca.pem".to_string()),
+            cert_path: Some("/etc/puerta/client.pem".to_string()),
+            key_path: Some("/etc/puerta/client.key".to_string()),
+            sni: None,
+            verify_cert: true,
+            verify_hostname: false,
+        }
+    }
+
+    #[test]
+    fn test_plain_peer() {
+        let peer = new_peer("127.0.0.1:27017", None, None);
+        assert!(!peer.tls());
+        assert!(connector_options(None).is_none());
+    }
+
+    #[test]
+    fn test_tls_peer_defaults_sni_to_host() {
+        let tls = tls_config();
+        let peer = new_peer("127.0.0.1:27017", Some(&tls), None);
+        assert!(peer.tls());
+        assert_eq!(peer.sni(), "127.0.0.1");
+        assert!(peer.options.verify_cert);
+        assert!(!peer.options.verify_hostname);
+    }
+
+    #[test]
+    fn test_tls_peer_sni_override() {
+        let tls = UpstreamTlsConfig {
+            sni: Some("mongos.example.com".to_string()),
+            ..tls_config()
+        };
+        let peer = new_peer("10.0.0.1:27017", Some(&tls), None);
+        assert_eq!(peer.sni(), "mongos.example.com");
+    }
+
+    #[test]
+    fn test_peer_keepalive() {
+        let keepalive = TcpKeepaliveConfig {
+            idle_sec: 30,
+            interval_sec: 5,
+            count: 3,
+            user_timeout_sec: Some(45),
+        };
+        let peer = new_peer("10.0.0.1:6379", None, Some(&keepalive));
+        let tcp_keepalive = peer.options.tcp_keepalive.unwrap();
+        assert_eq!(tcp_keepalive.idle, Duration::from_secs(30));
+        assert_eq!(tcp_keepalive.interval, Duration::from_secs(5));
+        assert_eq!(tcp_keepalive.count, 3);
+        #[cfg(target_os = "linux")]
+        assert_eq!(tcp_keepalive.user_timeout, Duration::from_secs(45));
+
+        assert!(new_peer("10.0.0.1:6379", None, None).options.tcp_keepalive.is_none());
+    }
+
+    #[test]
+    fn test_connector_options_mtls() {
+        let options = connector_options(Some(&tls_config())).unwrap();
+        assert_eq!(options.ca_file.as_deref(), Some("/etc/puerta/ca.pem"));
+        assert_eq!(
+            options.cert_key_file,
+            Some((
+                "/etc/puerta/client.pem".to_string(),
+                "/etc/puerta/client.key".to_string()
+            ))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_spare_connections() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let mut held = Vec::new();
+            while let Ok((socket, _)) = listener.accept().await {
+                held.push(socket);
+            }
+        });
+
+        let spares = SpareConnections::new(TransportConnector::new(None), 2, Duration::from_secs(60));
+        let peer = new_peer(&addr, None, None);
+        assert_eq!(spares.fill(&peer).await.unwrap(), 2);
+        assert_eq!(spares.fill(&peer).await.unwrap(), 0);
+
+        assert!(spares.take(&addr).is_some());
+        assert_eq!(spares.count(&addr), 1);
+        assert_eq!(spares.fill(&peer).await.unwrap(), 1);
+        assert!(spares.take("127.0.0.1:1").is_none());
+
+        spares.retain(&[]);
+        assert_eq!(spares.count(&addr), 0);
+
+        // Expired spares are not handed out
+        let spares = SpareConnections::new(TransportConnector::new(None), 1, Duration::ZERO);
+        spares.fill(&peer).await.unwrap();
+        assert!(spares.take(&addr).is_none());
+    }
+
+    #[test]
+    fn test_host_part() {
+        assert_eq!(host_part("127.0.0.1:6379"), "127.0.0.1");
+        assert_eq!(host_part("[::1]:6379"), "::1");
+    }
+}
@@ -0,0 +1,515 @@
+/// io_uring forwarding of plain TCP connections (Linux)
+///
+/// MongoDB forwarding moves bytes between two sockets with a readiness
+/// wakeup and a read or write syscall per chunk. With the `io-uring` feature
+/// and `[server.tuning] io_uring = true`, connections without TLS do their
+/// socket I/O through one process-wide io_uring instead: reads and writes are
+/// queued as `recv` and `send` operations, and a driver thread waiting on the
+/// ring wakes the tasks whose operations completed. The forwarding loop keeps
+/// running on the Pingora runtime over [`UringStream`]s, which implement
+/// `AsyncRead` and `AsyncWrite`; tokio-uring is not used as it needs a
+/// runtime of its own.
+///
+/// Without the feature, or on kernels that refuse to set up a ring or lack
+/// the socket operations, connections use the regular streams.
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+pub use ring::{Uring, UringStream};
+
+/// Without the `io-uring` feature no ring is ever set up
+#[cfg(not(all(target_os = "linux", feature = "io-uring")))]
+pub struct Uring {
+    _private: (),
+}
+
+#[cfg(not(all(target_os = "linux", feature = "io-uring")))]
+impl Uring {
+    /// The process-wide ring, never available in this build
+    pub fn shared() -> Option<std::sync::Arc<Self>> {
+        log::warn!("io_uring is configured but puerta was built without the `io-uring` feature");
+        None
+    }
+
+    /// `inner` itself
+    pub fn stream<S>(&self, inner: S, _fd: i32) -> S {
+        inner
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+mod ring {
+    use io_uring::{opcode, squeue, types, IoUring, Probe};
+    use std::io;
+    use std::os::fd::{BorrowedFd, RawFd};
+    use std::pin::Pin;
+    use std::sync::{Arc, Mutex, OnceLock};
+    use std::task::{Context, Poll, Waker};
+    use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+    /// Submission queue entries
+    const RING_ENTRIES: u32 = 1024;
+
+    /// Largest single `recv` or `send` queued
+    const MAX_OP_SIZE: usize = 64 * 1024;
+
+    /// `user_data` of cancellations, whose completions are ignored
+    const CANCEL: u64 = u64::MAX;
+
+    /// An operation queued on the ring
+    struct Op {
+        /// Buffer the kernel reads into or writes from, kept until completion
+        buf: Vec<u8>,
+        /// Bytes transferred, or the negated errno, once completed
+        result: Option<i32>,
+        waker: Option<Waker>,
+        /// The stream that queued it is gone; freed on completion
+        abandoned: bool,
+    }
+
+    /// Queued operations by id, the `user_data` of their entries
+    #[derive(Default)]
+    struct Ops {
+        slots: Vec<Option<Op>>,
+        free: Vec<usize>,
+    }
+
+    impl Ops {
+        fn insert(&mut self, op: Op) -> usize {
+            match self.free.pop() {
+                Some(id) => {
+                    self.slots[id] = Some(op);
+                    id
+                }
+                None => {
+                    self.slots.push(Some(op));
+                    self.slots.len() - 1
+                }
+            }
+        }
+
+        fn get_mut(&mut self, id: usize) -> Option<&mut Op> {
+            self.slots.get_mut(id)?.as_mut()
+        }
+
+        fn remove(&mut self, id: usize) -> Option<Op> {
+            let op = self.slots.get_mut(id)?.take()?;
+            self.free.push(id);
+            Some(op)
+        }
+    }
+
+    /// A ring shared by every [`UringStream`] of the process
+    pub struct Uring {
+        ring: IoUring,
+        /// Also serializes access to the submission queue
+        ops: Mutex<Ops>,
+    }
+
+    impl Uring {
+        /// The process-wide ring, set up with its driver thread on first use;
+        /// `None`, logged once, when the kernel does not support it
+        pub fn shared() -> Option<Arc<Self>> {
+            static SHARED: OnceLock<Option<Arc<Uring>>> = OnceLock::new();
+            SHARED
+                .get_or_init(|| match Self::setup() {
+                    Ok(uring) => {
+                        log::info!("Forwarding plain TCP connections over io_uring");
+                        Some(uring)
+                    }
+                    Err(e) => {
+                        log::warn!("io_uring is unavailable, using regular sockets: {e}");
+                        None
+                    }
+                })
+                .clone()
+        }
+
+        fn setup() -> io::Result<Arc<Self>> {
+            let ring = IoUring::new(RING_ENTRIES)?;
+            let mut probe = Probe::new();
+            ring.submitter().register_probe(&mut probe)?;
+            for code in [opcode::Recv::CODE, opcode::Send::CODE, opcode::AsyncCancel::CODE] {
+                if !probe.is_supported(code) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::Unsupported,
+                        format!("kernel lacks io_uring opcode {code}"),
+                    ));
+                }
+            }
+
+            let uring = Arc::new(Self {
+                ring,
+                ops: Mutex::default(),
+            });
+            let driver = Arc::clone(&uring);
+            std::thread::Builder::new()
+                .name("puerta-io-uring".to_string())
+                .spawn(move || driver.drive())?;
+            Ok(uring)
+        }
+
+        /// `inner` doing its I/O on socket `fd` through the ring
+        ///
+        /// `inner` keeps the socket open and shuts it down; it must not
+        /// hold data it read ahead or writes it buffered.
+        pub fn stream<S>(self: &Arc<Self>, inner: S, fd: RawFd) -> UringStream<S> {
+            // Operations on blocking sockets wait in the kernel instead of
+            // completing with EAGAIN
+            // Safety: `inner` owns `fd` and outlives the borrow
+            let socket = unsafe { BorrowedFd::borrow_raw(fd) };
+            if let Err(e) = socket2::SockRef::from(&socket).set_nonblocking(false) {
+                log::warn!("Failed to make socket {fd} blocking for io_uring: {e}");
+            }
+            UringStream {
+                inner,
+                fd,
+                uring: Arc::clone(self),
+                read: None,
+                received: Vec::new(),
+                read_pos: 0,
+                write: None,
+                sending: Vec::new(),
+            }
+        }
+
+        /// Wait for completions and wake their streams, forever
+        fn drive(&self) {
+            loop {
+                if let Err(e) = self.ring.submitter().submit_and_wait(1) {
+                    if !matches!(e.kind(), io::ErrorKind::Interrupted | io::ErrorKind::ResourceBusy) {
+                        log::error!("io_uring wait failed: {e}");
+                        std::thread::sleep(std::time::Duration::from_millis(10));
+                    }
+                }
+
+                let mut wakers = Vec::new();
+                {
+                    let mut ops = self.ops.lock().unwrap();
+                    // Safety: only this thread takes the completion queue
+                    for cqe in unsafe { self.ring.completion_shared() } {
+                        if cqe.user_data() == CANCEL {
+                            continue;
+                        }
+                        let id = cqe.user_data() as usize;
+                        let Some(op) = ops.get_mut(id) else {
+                            continue;
+                        };
+                        if op.abandoned {
+                            ops.remove(id);
+                            continue;
+                        }
+                        op.result = Some(cqe.result());
+                        wakers.extend(op.waker.take());
+                    }
+                }
+                wakers.into_iter().for_each(Waker::wake);
+            }
+        }
+
+        /// Queue `op` with the entry `entry` builds for its buffer
+        fn submit(
+            &self,
+            mut op: Op,
+            entry: impl FnOnce(&mut Vec<u8>) -> squeue::Entry,
+        ) -> io::Result<usize> {
+            let mut ops = self.ops.lock().unwrap();
+            let entry = entry(&mut op.buf);
+            let id = ops.insert(op);
+            if let Err(e) = self.push(&entry.user_data(id as u64)) {
+                ops.remove(id);
+                return Err(e);
+            }
+            Ok(id)
+        }
+
+        /// Push `entry` and submit it; the caller holds the `ops` lock
+        fn push(&self, entry: &squeue::Entry) -> io::Result<()> {
+            // Safety: the `ops` lock is held, so no other submission queue exists
+            while unsafe { self.ring.submission_shared().push(entry) }.is_err() {
+                self.ring.submit()?;
+            }
+            self.ring.submit()?;
+            Ok(())
+        }
+
+        /// Result of operation `id` with its buffer, or `Pending` with `cx`
+        /// woken on completion
+        fn poll_op(&self, id: usize, cx: &mut Context<'_>) -> Poll<(i32, Vec<u8>)> {
+            let mut ops = self.ops.lock().unwrap();
+            let op = ops.get_mut(id).expect("queued io_uring operation");
+            if op.result.is_none() {
+                op.waker = Some(cx.waker().clone());
+                return Poll::Pending;
+            }
+            let op = ops.remove(id).expect("queued io_uring operation");
+            Poll::Ready((op.result.unwrap_or_default(), op.buf))
+        }
+
+        /// Give up on operation `id`, cancelling it unless it completed
+        fn abandon(&self, id: usize) {
+            let mut ops = self.ops.lock().unwrap();
+            let Some(op) = ops.get_mut(id) else {
+                return;
+            };
+            if op.result.is_some() {
+                ops.remove(id);
+                return;
+            }
+            op.abandoned = true;
+            let cancel = opcode::AsyncCancel::new(id as u64).build().user_data(CANCEL);
+            if let Err(e) = self.push(&cancel) {
+                log::warn!("Failed to cancel io_uring operation: {e}");
+            }
+        }
+    }
+
+    /// A socket read and written through the shared [`Uring`]
+    ///
+    /// The kernel only ever sees buffers owned by the stream, so polls may
+    /// pass a different buffer each time. A `recv` queued by a poll that
+    /// returned `Pending` keeps the bytes it receives in the stream, for
+    /// whichever buffer the next poll passes. A write copies its bytes and
+    /// queues their `send` before returning; the next write, a flush or a
+    /// shutdown waits for them to be sent, and reports a failed `send`.
+    pub struct UringStream<S> {
+        inner: S,
+        fd: RawFd,
+        uring: Arc<Uring>,
+        /// Queued `recv`
+        read: Option<usize>,
+        /// Bytes received but not yet returned, from `read_pos`
+        received: Vec<u8>,
+        read_pos: usize,
+        /// Queued `send`
+        write: Option<usize>,
+        /// Buffer of the last `send`, reused by the next
+        sending: Vec<u8>,
+    }
+
+    impl<S> UringStream<S> {
+        /// Queue a `send` of `data`
+        fn send(&mut self, data: Vec<u8>) -> io::Result<()> {
+            let fd = self.fd;
+            let op = Op {
+                buf: data,
+                result: None,
+                waker: None,
+                abandoned: false,
+            };
+            self.write = Some(self.uring.submit(op, |data| {
+                opcode::Send::new(types::Fd(fd), data.as_ptr(), data.len() as u32).build()
+            })?);
+            Ok(())
+        }
+
+        /// Wait until every byte written so far is sent, queueing the rest
+        /// of a partial `send` again
+        fn poll_sent(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            while let Some(id) = self.write {
+                let (result, mut data) = std::task::ready!(self.uring.poll_op(id, cx));
+                self.write = None;
+                let sent = match result {
+                    result if result < 0 => Err(io::Error::from_raw_os_error(-result)),
+                    0 => Err(io::ErrorKind::WriteZero.into()),
+                    sent => Ok(sent as usize),
+                };
+                match sent {
+                    Ok(sent) if sent < data.len() => {
+                        data.drain(..sent);
+                        self.send(data)?;
+                    }
+                    sent => {
+                        data.clear();
+                        self.sending = data;
+                        sent?;
+                    }
+                }
+            }
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    impl<S: Unpin> AsyncRead for UringStream<S> {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<io::Result<()>> {
+            let this = self.get_mut();
+            loop {
+                if this.read_pos < this.received.len() {
+                    let n = buf.remaining().min(this.received.len() - this.read_pos);
+                    buf.put_slice(&this.received[this.read_pos..this.read_pos + n]);
+                    this.read_pos += n;
+                    return Poll::Ready(Ok(()));
+                }
+
+                match this.read {
+                    Some(id) => {
+                        let (result, mut data) = std::task::ready!(this.uring.poll_op(id, cx));
+                        this.read = None;
+                        if result < 0 {
+                            this.received = data;
+                            this.received.clear();
+                            return Poll::Ready(Err(io::Error::from_raw_os_error(-result)));
+                        }
+                        if result == 0 {
+                            // End of stream
+                            data.clear();
+                            this.received = data;
+                            return Poll::Ready(Ok(()));
+                        }
+                        data.truncate(result as usize);
+                        this.received = data;
+                        this.read_pos = 0;
+                    }
+                    None => {
+                        if buf.remaining() == 0 {
+                            return Poll::Ready(Ok(()));
+                        }
+                        let mut data = std::mem::take(&mut this.received);
+                        data.resize(buf.remaining().min(MAX_OP_SIZE), 0);
+                        this.read_pos = 0;
+                        let fd = this.fd;
+                        let op = Op {
+                            buf: data,
+                            result: None,
+                            waker: None,
+                            abandoned: false,
+                        };
+                        this.read = Some(this.uring.submit(op, |data| {
+                            opcode::Recv::new(types::Fd(fd), data.as_mut_ptr(), data.len() as u32).build()
+                        })?);
+                    }
+                }
+            }
+        }
+    }
+
+    impl<S: AsyncWrite + Unpin> AsyncWrite for UringStream<S> {
+        fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+            let this = self.get_mut();
+            std::task::ready!(this.poll_sent(cx))?;
+            if buf.is_empty() {
+                return Poll::Ready(Ok(0));
+            }
+            let n = buf.len().min(MAX_OP_SIZE);
+            let mut data = std::mem::take(&mut this.sending);
+            data.extend_from_slice(&buf[..n]);
+            this.send(data)?;
+            Poll::Ready(Ok(n))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            self.get_mut().poll_sent(cx)
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            let this = self.get_mut();
+            std::task::ready!(this.poll_sent(cx))?;
+            Pin::new(&mut this.inner).poll_shutdown(cx)
+        }
+    }
+
+    impl<S> Drop for UringStream<S> {
+        fn drop(&mut self) {
+            // Operations left queued would keep the socket open
+            for id in [self.read.take(), self.write.take()].into_iter().flatten() {
+                self.uring.abandon(id);
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use std::os::fd::AsRawFd;
+        use std::time::Duration;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::net::{TcpListener, TcpStream};
+
+        async fn pair() -> (TcpStream, TcpStream) {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let client = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+            let (server, _) = listener.accept().await.unwrap();
+            (client, server)
+        }
+
+        #[tokio::test]
+        async fn test_round_trip() {
+            // Sandboxes may refuse io_uring, as the proxy then does
+            let Some(uring) = Uring::shared() else {
+                return;
+            };
+            let (client, mut peer) = pair().await;
+            let fd = client.as_raw_fd();
+            let mut stream = uring.stream(client, fd);
+
+            let payload: Vec<u8> = (0..200_000u32).map(|i| i as u8).collect();
+            let echo = tokio::spawn(async move {
+                let mut received = vec![0u8; 200_000];
+                peer.read_exact(&mut received).await.unwrap();
+                peer.write_all(&received).await.unwrap();
+                peer
+            });
+            stream.write_all(&payload).await.unwrap();
+            let mut echoed = vec![0u8; payload.len()];
+            stream.read_exact(&mut echoed).await.unwrap();
+            assert_eq!(echoed, payload);
+
+            // Shutdown reaches the peer through the wrapped stream
+            stream.shutdown().await.unwrap();
+            let mut peer = echo.await.unwrap();
+            assert_eq!(peer.read(&mut [0u8; 16]).await.unwrap(), 0);
+        }
+
+        #[tokio::test]
+        async fn test_polls_may_pass_other_buffers() {
+            let Some(uring) = Uring::shared() else {
+                return;
+            };
+            let (client, mut peer) = pair().await;
+            let fd = client.as_raw_fd();
+            let mut stream = uring.stream(client, fd);
+
+            // A write is done once queued; the caller's buffer can go at once
+            let mut buf = b"first".to_vec();
+            assert_eq!(stream.write(&buf).await.unwrap(), 5);
+            buf.copy_from_slice(b"XXXXX");
+            assert_eq!(stream.write(b"second").await.unwrap(), 6);
+            stream.flush().await.unwrap();
+            let mut sent = [0u8; 11];
+            peer.read_exact(&mut sent).await.unwrap();
+            assert_eq!(&sent, b"firstsecond");
+
+            // Bytes received for an abandoned poll's buffer go to the next one
+            let mut small = [0u8; 4];
+            let read = tokio::time::timeout(Duration::from_millis(50), stream.read(&mut small)).await;
+            assert!(read.is_err());
+            peer.write_all(b"reply").await.unwrap();
+            let mut other = [0u8; 16];
+            let n = stream.read(&mut other).await.unwrap();
+            assert_eq!(&other[..n], b"repl");
+            assert_eq!(stream.read(&mut other).await.unwrap(), 1);
+            assert_eq!(other[0], b'y');
+        }
+
+        #[tokio::test]
+        async fn test_dropped_read_is_cancelled() {
+            let Some(uring) = Uring::shared() else {
+                return;
+            };
+            let (client, mut peer) = pair().await;
+            let fd = client.as_raw_fd();
+            let mut stream = uring.stream(client, fd);
+
+            // A read left waiting when the stream goes away
+            let mut buf = [0u8; 16];
+            let read = tokio::time::timeout(Duration::from_millis(50), stream.read(&mut buf)).await;
+            assert!(read.is_err());
+            drop(stream);
+
+            let closed = tokio::time::timeout(Duration::from_secs(5), peer.read(&mut buf)).await;
+            assert_eq!(closed.unwrap().unwrap(), 0);
+        }
+    }
+}
@@ -0,0 +1,302 @@
+/// Consul catalog discovery
+///
+/// Reads `/v1/health/service/<service>` for every instance of the service
+/// together with its checks. An instance is healthy while none of its checks
+/// are critical; instances with warning checks stay in rotation at their
+/// `Weights.Warning`, as Consul's own DNS interface does.
+///
+/// Between reads the service is watched with a blocking query: the request
+/// carries the `X-Consul-Index` of the last read and Consul answers once the
+/// service changes, or after the wait time.
+use super::{http, resolve_host, BackendSource, DiscoveredEndpoint, DiscoveryError};
+use crate::config::{DiscoveryConfig, Endpoint};
+use serde::Deserialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Backends from the instances of a Consul service
+#[derive(Debug)]
+pub struct ConsulDiscovery {
+    address: String,
+    service: String,
+    token: Option<String>,
+    /// `X-Consul-Index` of the last read, 0 before the first
+    index: AtomicU64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ServiceEntry {
+    node: Node,
+    service: Service,
+    #[serde(default)]
+    checks: Vec<Check>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Node {
+    #[serde(default)]
+    address: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Service {
+    #[serde(default)]
+    address: String,
+    port: u16,
+    #[serde(default)]
+    weights: Option<Weights>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Weights {
+    passing: usize,
+    warning: usize,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Check {
+    status: String,
+}
+
+/// An instance as listed by Consul, before its host is resolved
+#[derive(Debug, PartialEq)]
+struct Instance {
+    host: String,
+    port: u16,
+    weight: usize,
+    healthy: bool,
+}
+
+impl ConsulDiscovery {
+    pub fn new(config: DiscoveryConfig) -> Self {
+        Self {
+            address: config.address.unwrap_or_default(),
+            service: config.service.unwrap_or_default(),
+            token: config.token,
+            index: AtomicU64::new(0),
+        }
+    }
+
+    fn headers(&self) -> Vec<(&str, &str)> {
+        self.token
+            .iter()
+            .map(|token| ("X-Consul-Token", token.as_str()))
+            .collect()
+    }
+
+    /// Index a response was read at; Consul may reset its index, in which
+    /// case the next blocking query must start over from 0
+    fn response_index(&self, response: &http::Response) -> u64 {
+        response
+            .header("X-Consul-Index")
+            .and_then(|index| index.parse().ok())
+            .unwrap_or(0)
+    }
+}
+
+/// Parse a `/v1/health/service` response
+fn parse_instances(body: &str) -> Result<Vec<Instance>, DiscoveryError> {
+    let entries: Vec<ServiceEntry> = serde_json::from_str(body)?;
+    Ok(entries
+        .into_iter()
+        .filter(|entry| entry.service.port != 0)
+        .map(|entry| {
+            let healthy = entry
+                .checks
+                .iter()
+                .all(|check| check.status == "passing" || check.status == "warning");
+            let warning = entry.checks.iter().any(|check| check.status == "warning");
+            let weight = match &entry.service.weights {
+                Some(weights) if warning => weights.warning,
+                Some(weights) => weights.passing,
+                None => 1,
+            };
+            // Services registered without an address use the node's
+            let host = if entry.service.address.is_empty() {
+                entry.node.address
+            } else {
+                entry.service.address
+            };
+            Instance {
+                host,
+                port: entry.service.port,
+                weight: weight.max(1),
+                healthy,
+            }
+        })
+        .collect())
+}
+
+#[async_trait::async_trait]
+impl BackendSource for ConsulDiscovery {
+    async fn discover(&self) -> Result<Vec<DiscoveredEndpoint>, DiscoveryError> {
+        let path = format!("/v1/health/service/{}", self.service);
+        let response = http::send(&self.address, "GET", &path, &self.headers(), None, http::REQUEST_TIMEOUT).await?;
+        self.index.store(self.response_index(&response), Ordering::Relaxed);
+
+        let mut discovered = Vec::new();
+        for instance in parse_instances(&response.body)? {
+            for addr in resolve_host(&instance.host, instance.port).await? {
+                discovered.push(DiscoveredEndpoint::new(
+                    Endpoint::new(addr.to_string(), instance.weight),
+                    instance.healthy,
+                ));
+            }
+        }
+        Ok(discovered)
+    }
+
+    fn describe(&self) -> String {
+        format!("Consul service {}", self.service)
+    }
+
+    async fn wait_for_change(&self, timeout: Duration) -> Result<(), DiscoveryError> {
+        let index = self.index.load(Ordering::Relaxed);
+        if index == 0 {
+            tokio::time::sleep(timeout).await;
+            return Ok(());
+        }
+        let path = format!(
+            "/v1/health/service/{}?index={index}&wait={}s",
+            self.service,
+            timeout.as_secs().max(1)
+        );
+        // Consul adds up to a sixteenth of the wait time to spread answers
+        let request_timeout = timeout + timeout / 16 + http::REQUEST_TIMEOUT;
+        let response = http::send(&self.address, "GET", &path, &self.headers(), None, request_timeout).await?;
+        if self.response_index(&response) < index {
+            self.index.store(0, Ordering::Relaxed);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_instances() {
+        let body = r#"[
+            {
+                "Node": {"Node": "node-1", "Address": "10.0.0.1"},
+                "Service": {"Service": "mongos", "Address": "", "Port": 27017,
+                            "Weights": {"Passing": 3, "Warning": 1}},
+                "Checks": [{"Status": "passing"}, {"Status": "passing"}]
+            },
+            {
+                "Node": {"Node": "node-2", "Address": "10.0.0.2"},
+                "Service": {"Service": "mongos", "Address": "10.1.0.2", "Port": 27018,
+                            "Weights": {"Passing": 3, "Warning": 1}},
+                "Checks": [{"Status": "passing"}, {"Status": "warning"}]
+            },
+            {
+                "Node": {"Node": "node-3", "Address": "10.0.0.3"},
+                "Service": {"Service": "mongos", "Address": "", "Port": 27017},
+                "Checks": [{"Status": "critical"}]
+            }
+        ]"#;
+
+        assert_eq!(
+            parse_instances(body).unwrap(),
+            vec![
+                Instance {
+                    host: "10.0.0.1".to_string(),
+                    port: 27017,
+                    weight: 3,
+                    healthy: true,
+                },
+                Instance {
+                    host: "10.1.0.2".to_string(),
+                    port: 27018,
+                    weight: 1,
+                    healthy: true,
+                },
+                Instance {
+                    host: "10.0.0.3".to_string(),
+                    port: 27017,
+                    weight: 1,
+                    healthy: false,
+                },
+            ]
+        );
+        assert!(parse_instances("{\"error\": true}").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_discover_from_consul_agent() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = vec![0u8; 1024];
+            let n = stream.read(&mut request).await.unwrap();
+            let request = String::from_utf8_lossy(&request[..n]);
+            assert!(request.starts_with("GET /v1/health/service/mongos HTTP/1.0\r\n"));
+            assert!(request.contains("X-Consul-Token: secret\r\n"));
+            let body = r#"[{"Node": {"Address": "127.0.0.1"},
+                            "Service": {"Address": "", "Port": 27017},
+                            "Checks": [{"Status": "critical"}]}]"#;
+            let response = format!("HTTP/1.0 200 OK\r\n\r\n{body}");
+            stream.write_all(response.as_bytes()).await.unwrap();
+        });
+
+        let discovery = ConsulDiscovery::new(DiscoveryConfig {
+            provider: crate::config::DiscoveryProvider::Consul,
+            address: Some(format!("http://{addr}")),
+            service: Some("mongos".to_string()),
+            token: Some("secret".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(
+            discovery.discover().await.unwrap(),
+            vec![DiscoveredEndpoint::new(
+                Endpoint::new("127.0.0.1:27017", 1),
+                false
+            )]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_blocking_query_from_last_index() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let requests = tokio::spawn(async move {
+            let mut requests = Vec::new();
+            for index in [42, 43] {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = vec![0u8; 1024];
+                let n = stream.read(&mut request).await.unwrap();
+                requests.push(String::from_utf8_lossy(&request[..n]).lines().next().unwrap().to_string());
+                let response = format!("HTTP/1.0 200 OK\r\nX-Consul-Index: {index}\r\n\r\n[]");
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+            requests
+        });
+
+        let discovery = ConsulDiscovery::new(DiscoveryConfig {
+            provider: crate::config::DiscoveryProvider::Consul,
+            address: Some(format!("http://{addr}")),
+            service: Some("mongos".to_string()),
+            ..Default::default()
+        });
+        assert!(discovery.discover().await.unwrap().is_empty());
+        discovery.wait_for_change(Duration::from_secs(30)).await.unwrap();
+        assert_eq!(
+            requests.await.unwrap(),
+            vec![
+                "GET /v1/health/service/mongos HTTP/1.0",
+                "GET /v1/health/service/mongos?index=42&wait=30s HTTP/1.0",
+            ]
+        );
+    }
+}
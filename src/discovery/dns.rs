@@ -0,0 +1,111 @@
+/// DNS-based backend discovery
+///
+/// Resolves mongos or Redis seed endpoints from a DNS name, either SRV
+/// records (host, port and weight per backend) or the A/AAAA records of a
+/// Kubernetes headless service on a fixed port.
+use super::{BackendSource, DiscoveredEndpoint, DiscoveryError};
+use crate::config::{DiscoveryConfig, DnsRecordType, Endpoint};
+use hickory_resolver::config::{ResolverConfig, ResolverOpts};
+use hickory_resolver::TokioAsyncResolver;
+use std::net::SocketAddr;
+
+/// DNS resolution of backend endpoints
+#[derive(Debug, Clone)]
+pub struct DnsDiscovery {
+    config: DiscoveryConfig,
+}
+
+impl DnsDiscovery {
+    pub fn new(config: DiscoveryConfig) -> Self {
+        Self { config }
+    }
+
+    /// Resolve the configured name to endpoints, sorted by address
+    pub async fn resolve(&self) -> Result<Vec<Endpoint>, DiscoveryError> {
+        // Built per lookup so changes to resolv.conf are picked up
+        let resolver = TokioAsyncResolver::tokio_from_system_conf().unwrap_or_else(|e| {
+            log::warn!("Failed to read system DNS configuration, using defaults: {e}");
+            TokioAsyncResolver::tokio(ResolverConfig::default(), ResolverOpts::default())
+        });
+
+        let mut endpoints = Vec::new();
+        match self.config.record_type {
+            DnsRecordType::Srv => {
+                let records = resolver.srv_lookup(self.config.dns_name.as_str()).await?;
+                for record in records.iter() {
+                    let target = record.target().to_utf8();
+                    // SRV weight 0 means "rarely used", not "never"
+                    let weight = (record.weight() as usize).max(1);
+                    for ip in resolver.lookup_ip(target.as_str()).await?.iter() {
+                        endpoints.push(Endpoint::new(
+                            SocketAddr::new(ip, record.port()).to_string(),
+                            weight,
+                        ));
+                    }
+                }
+            }
+            DnsRecordType::A => {
+                let port = self
+                    .config
+                    .port
+                    .ok_or("discovery port is required for A records")?;
+                for ip in resolver
+                    .lookup_ip(self.config.dns_name.as_str())
+                    .await?
+                    .iter()
+                {
+                    endpoints.push(Endpoint::new(SocketAddr::new(ip, port).to_string(), 1));
+                }
+            }
+        }
+
+        endpoints.sort_by(|a, b| a.addr.cmp(&b.addr));
+        endpoints.dedup_by(|a, b| a.addr == b.addr);
+        Ok(endpoints)
+    }
+}
+
+#[async_trait::async_trait]
+impl BackendSource for DnsDiscovery {
+    async fn discover(&self) -> Result<Vec<DiscoveredEndpoint>, DiscoveryError> {
+        let endpoints = self.resolve().await?;
+        Ok(endpoints
+            .into_iter()
+            .map(|endpoint| DiscoveredEndpoint::new(endpoint, true))
+            .collect())
+    }
+
+    fn describe(&self) -> String {
+        format!("DNS name {}", self.config.dns_name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(dns_name: &str, record_type: DnsRecordType, port: Option<u16>) -> DiscoveryConfig {
+        DiscoveryConfig {
+            dns_name: dns_name.to_string(),
+            record_type,
+            port,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resolve_a_records_on_fixed_port() {
+        // IP literals resolve without a DNS server
+        let discovery = DnsDiscovery::new(config("10.0.0.7", DnsRecordType::A, Some(27017)));
+        assert_eq!(
+            discovery.resolve().await.unwrap(),
+            vec![Endpoint::new("10.0.0.7:27017", 1)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_resolve_a_records_requires_port() {
+        let discovery = DnsDiscovery::new(config("10.0.0.7", DnsRecordType::A, None));
+        assert!(discovery.resolve().await.is_err());
+    }
+}
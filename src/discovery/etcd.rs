@@ -0,0 +1,332 @@
+/// etcd key prefix discovery
+///
+/// Reads every key under `key_prefix` through the etcd v3 JSON gateway
+/// (`POST /v3/kv/range`). Each value is either a plain `host:port` or a JSON
+/// object `{"addr": "host:port", "weight": 2, "healthy": true}` so that
+/// registrars can publish weights and take backends out of rotation.
+///
+/// Between reads the prefix is watched (`POST /v3/watch`) from the revision
+/// after the last read, so a change is picked up as soon as etcd streams it.
+use super::{http, resolve_host, BackendSource, DiscoveredEndpoint, DiscoveryError};
+use crate::config::{DiscoveryConfig, Endpoint};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::Deserialize;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::time::Duration;
+
+/// Backends from the values under an etcd key prefix
+#[derive(Debug)]
+pub struct EtcdDiscovery {
+    address: String,
+    key_prefix: String,
+    token: Option<String>,
+    /// Revision of the last read, 0 before the first
+    revision: AtomicI64,
+}
+
+#[derive(Debug, Deserialize)]
+struct RangeResponse {
+    #[serde(default)]
+    header: ResponseHeader,
+    #[serde(default)]
+    kvs: Vec<KeyValue>,
+}
+
+/// The JSON gateway encodes 64-bit integers as strings
+#[derive(Debug, Default, Deserialize)]
+struct ResponseHeader {
+    #[serde(default)]
+    revision: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct KeyValue {
+    key: String,
+    #[serde(default)]
+    value: String,
+}
+
+/// A backend registered as a JSON value
+#[derive(Debug, Deserialize)]
+struct Registration {
+    addr: String,
+    #[serde(default = "default_weight")]
+    weight: usize,
+    #[serde(default = "default_healthy")]
+    healthy: bool,
+}
+
+fn default_weight() -> usize {
+    1
+}
+
+fn default_healthy() -> bool {
+    true
+}
+
+impl EtcdDiscovery {
+    pub fn new(config: DiscoveryConfig) -> Self {
+        Self {
+            address: config.address.unwrap_or_default(),
+            key_prefix: config.key_prefix.unwrap_or_default(),
+            token: config.token,
+            revision: AtomicI64::new(0),
+        }
+    }
+
+    fn headers(&self) -> Vec<(&str, &str)> {
+        self.token
+            .iter()
+            .map(|token| ("Authorization", token.as_str()))
+            .collect()
+    }
+}
+
+/// The smallest key greater than every key starting with `prefix`
+fn prefix_range_end(prefix: &[u8]) -> Vec<u8> {
+    let mut end = prefix.to_vec();
+    while let Some(last) = end.pop() {
+        if last < 0xff {
+            end.push(last + 1);
+            return end;
+        }
+    }
+    // All 0xff: range to the end of the keyspace
+    vec![0]
+}
+
+/// Parse a range response into its revision and registrations, skipping
+/// malformed values
+fn parse_registrations(body: &str) -> Result<(i64, Vec<Registration>), DiscoveryError> {
+    let response: RangeResponse = serde_json::from_str(body)?;
+    let revision = response.header.revision.parse().unwrap_or(0);
+    let mut registrations = Vec::new();
+    for kv in response.kvs {
+        let key = String::from_utf8_lossy(&BASE64.decode(&kv.key)?).to_string();
+        let value = String::from_utf8(BASE64.decode(&kv.value)?)?;
+        let value = value.trim();
+        let registration = if value.starts_with('{') {
+            match serde_json::from_str::<Registration>(value) {
+                Ok(registration) => registration,
+                Err(e) => {
+                    log::warn!("Ignoring etcd key {key} with invalid registration: {e}");
+                    continue;
+                }
+            }
+        } else {
+            Registration {
+                addr: value.to_string(),
+                weight: default_weight(),
+                healthy: default_healthy(),
+            }
+        };
+        registrations.push(registration);
+    }
+    Ok((revision, registrations))
+}
+
+/// Whether a watch response reports changed keys, or a failed or cancelled
+/// watch (e.g. when its revision was compacted) that calls for a new read
+fn is_change(message: &str) -> bool {
+    let Ok(message) = serde_json::from_str::<serde_json::Value>(message) else {
+        return false;
+    };
+    let result = &message["result"];
+    result["events"].as_array().is_some_and(|events| !events.is_empty())
+        || result["canceled"].as_bool().unwrap_or(false)
+        || !message["error"].is_null()
+}
+
+#[async_trait::async_trait]
+impl BackendSource for EtcdDiscovery {
+    async fn discover(&self) -> Result<Vec<DiscoveredEndpoint>, DiscoveryError> {
+        let prefix = self.key_prefix.as_bytes();
+        let body = serde_json::json!({
+            "key": BASE64.encode(prefix),
+            "range_end": BASE64.encode(prefix_range_end(prefix)),
+        })
+        .to_string();
+        let response =
+            http::request(&self.address, "POST", "/v3/kv/range", &self.headers(), Some(&body)).await?;
+        let (revision, registrations) = parse_registrations(&response)?;
+        self.revision.store(revision, Ordering::Relaxed);
+
+        let mut discovered = Vec::new();
+        for registration in registrations {
+            let Some((host, port)) = registration.addr.rsplit_once(':') else {
+                log::warn!(
+                    "Ignoring etcd backend without a port: {}",
+                    registration.addr
+                );
+                continue;
+            };
+            let Ok(port) = port.parse::<u16>() else {
+                log::warn!(
+                    "Ignoring etcd backend with an invalid port: {}",
+                    registration.addr
+                );
+                continue;
+            };
+            let host = host.trim_start_matches('[').trim_end_matches(']');
+            for addr in resolve_host(host, port).await? {
+                discovered.push(DiscoveredEndpoint::new(
+                    Endpoint::new(addr.to_string(), registration.weight.max(1)),
+                    registration.healthy,
+                ));
+            }
+        }
+        Ok(discovered)
+    }
+
+    fn describe(&self) -> String {
+        format!("etcd prefix {}", self.key_prefix)
+    }
+
+    async fn wait_for_change(&self, timeout: Duration) -> Result<(), DiscoveryError> {
+        let revision = self.revision.load(Ordering::Relaxed);
+        if revision == 0 {
+            tokio::time::sleep(timeout).await;
+            return Ok(());
+        }
+        let prefix = self.key_prefix.as_bytes();
+        let body = serde_json::json!({
+            "create_request": {
+                "key": BASE64.encode(prefix),
+                "range_end": BASE64.encode(prefix_range_end(prefix)),
+                "start_revision": (revision + 1).to_string(),
+            }
+        })
+        .to_string();
+        http::watch(&self.address, "/v3/watch", &self.headers(), &body, timeout, is_change).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kv(key: &str, value: &str) -> serde_json::Value {
+        serde_json::json!({"key": BASE64.encode(key), "value": BASE64.encode(value)})
+    }
+
+    #[test]
+    fn test_prefix_range_end() {
+        assert_eq!(prefix_range_end(b"/puerta/"), b"/puerta0".to_vec());
+        assert_eq!(prefix_range_end(b"a\xff"), b"b".to_vec());
+        assert_eq!(prefix_range_end(b"\xff"), vec![0]);
+    }
+
+    #[test]
+    fn test_parse_registrations() {
+        let body = serde_json::json!({
+            "header": {"revision": "7"},
+            "kvs": [
+                kv("/puerta/mongos/a", "10.0.0.1:27017"),
+                kv("/puerta/mongos/b", r#"{"addr": "10.0.0.2:27017", "weight": 3, "healthy": false}"#),
+                kv("/puerta/mongos/c", "{not json"),
+            ],
+            "count": "3"
+        })
+        .to_string();
+
+        let (revision, registrations) = parse_registrations(&body).unwrap();
+        assert_eq!(revision, 7);
+        assert_eq!(registrations.len(), 2);
+        assert_eq!(registrations[0].addr, "10.0.0.1:27017");
+        assert_eq!(registrations[0].weight, 1);
+        assert!(registrations[0].healthy);
+        assert_eq!(registrations[1].addr, "10.0.0.2:27017");
+        assert_eq!(registrations[1].weight, 3);
+        assert!(!registrations[1].healthy);
+
+        // etcd omits `kvs` when nothing matches
+        assert!(parse_registrations("{\"header\": {}}").unwrap().1.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_discover_from_etcd_gateway() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = vec![0u8; 1024];
+            let n = stream.read(&mut request).await.unwrap();
+            let request = String::from_utf8_lossy(&request[..n]);
+            assert!(request.starts_with("POST /v3/kv/range HTTP/1.0\r\n"));
+            assert!(request.contains(&BASE64.encode("/redis/")));
+            let body = serde_json::json!({
+                "kvs": [kv("/redis/a", "[::1]:7000"), kv("/redis/b", "127.0.0.1:7001")]
+            });
+            let response = format!("HTTP/1.0 200 OK\r\n\r\n{body}");
+            stream.write_all(response.as_bytes()).await.unwrap();
+        });
+
+        let discovery = EtcdDiscovery::new(DiscoveryConfig {
+            provider: crate::config::DiscoveryProvider::Etcd,
+            address: Some(format!("http://{addr}")),
+            key_prefix: Some("/redis/".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(
+            discovery.discover().await.unwrap(),
+            vec![
+                DiscoveredEndpoint::new(Endpoint::new("[::1]:7000", 1), true),
+                DiscoveredEndpoint::new(Endpoint::new("127.0.0.1:7001", 1), true),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_is_change() {
+        assert!(!is_change(r#"{"result":{"header":{"revision":"7"},"created":true}}"#));
+        assert!(is_change(
+            r#"{"result":{"header":{"revision":"8"},"events":[{"kv":{"key":"L3JlZGlzL2E="}}]}}"#
+        ));
+        assert!(is_change(r#"{"result":{"canceled":true,"compact_revision":"9"}}"#));
+        assert!(!is_change("not json"));
+    }
+
+    #[tokio::test]
+    async fn test_watch_from_next_revision() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let watch = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = vec![0u8; 1024];
+            let n = stream.read(&mut request).await.unwrap();
+            stream
+                .write_all(b"HTTP/1.0 200 OK\r\n\r\n{\"result\":{\"created\":true}}\n")
+                .await
+                .unwrap();
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            stream
+                .write_all(b"{\"result\":{\"events\":[{\"type\":\"DELETE\"}]}}\n")
+                .await
+                .unwrap();
+            // Keeps streaming until the client hangs up
+            let _ = stream.read(&mut request).await;
+            String::from_utf8_lossy(&request[..n]).to_string()
+        });
+
+        let discovery = EtcdDiscovery::new(DiscoveryConfig {
+            provider: crate::config::DiscoveryProvider::Etcd,
+            address: Some(format!("http://{addr}")),
+            key_prefix: Some("/redis/".to_string()),
+            ..Default::default()
+        });
+        discovery.revision.store(7, Ordering::Relaxed);
+        let started = std::time::Instant::now();
+        discovery.wait_for_change(Duration::from_secs(30)).await.unwrap();
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        let request = watch.await.unwrap();
+        assert!(request.starts_with("POST /v3/watch HTTP/1.0\r\n"));
+        assert!(request.contains("\"start_revision\":\"8\""));
+    }
+}
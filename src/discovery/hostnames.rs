@@ -0,0 +1,165 @@
+/// Resolution of static endpoints given by hostname
+///
+/// Endpoint lists may name backends by hostname, e.g.
+/// `mongos-1.db.internal:27017`. Each hostname stands for every address of
+/// its A/AAAA records: one backend per address, with the endpoint's weight
+/// and health check settings. The names are looked up again every
+/// `dns_refresh_sec`, so the backend set follows DNS changes; IP endpoints
+/// are kept as they are.
+use super::{resolve_host, BackendSource, DiscoveredEndpoint, DiscoveryError};
+use crate::config::{Config, Endpoint};
+use std::collections::HashSet;
+use std::sync::Arc;
+use tokio::sync::watch;
+
+/// The endpoints of a configuration, or `None` for another proxy mode
+pub type EndpointsOf = fn(&Config) -> Option<Vec<Endpoint>>;
+
+/// Backends of a static endpoint list, some given by hostname
+pub struct HostnameDiscovery {
+    endpoints: Vec<Endpoint>,
+    reloads: Option<(watch::Receiver<Arc<Config>>, EndpointsOf)>,
+}
+
+impl HostnameDiscovery {
+    pub fn new(endpoints: Vec<Endpoint>) -> Self {
+        Self {
+            endpoints,
+            reloads: None,
+        }
+    }
+
+    /// Resolve the endpoints `endpoints_of` finds in the latest reloaded
+    /// configuration instead of the initial ones
+    pub fn with_reloads(mut self, receiver: watch::Receiver<Arc<Config>>, endpoints_of: EndpointsOf) -> Self {
+        self.reloads = Some((receiver, endpoints_of));
+        self
+    }
+
+    fn current_endpoints(&self) -> Vec<Endpoint> {
+        self.reloads
+            .as_ref()
+            .and_then(|(receiver, endpoints_of)| endpoints_of(&receiver.borrow()))
+            .unwrap_or_else(|| self.endpoints.clone())
+    }
+}
+
+#[async_trait::async_trait]
+impl BackendSource for HostnameDiscovery {
+    async fn discover(&self) -> Result<Vec<DiscoveredEndpoint>, DiscoveryError> {
+        let endpoints = resolve_endpoints(&self.current_endpoints()).await?;
+        Ok(endpoints
+            .into_iter()
+            .map(|endpoint| DiscoveredEndpoint::new(endpoint, true))
+            .collect())
+    }
+
+    fn describe(&self) -> String {
+        let hostnames: Vec<&str> = self
+            .endpoints
+            .iter()
+            .filter(|endpoint| endpoint.is_hostname())
+            .map(|endpoint| endpoint.addr.as_str())
+            .collect();
+        format!("endpoint hostnames {}", hostnames.join(", "))
+    }
+}
+
+/// `endpoints` with each one given by hostname replaced by one endpoint per
+/// address it resolves to
+///
+/// Fails if any hostname fails to resolve, so a DNS outage does not shrink
+/// the backend set.
+pub async fn resolve_endpoints(endpoints: &[Endpoint]) -> Result<Vec<Endpoint>, DiscoveryError> {
+    let mut resolved = Vec::new();
+    let mut seen = HashSet::new();
+    for endpoint in endpoints {
+        if !endpoint.is_hostname() {
+            if seen.insert(endpoint.addr.clone()) {
+                resolved.push(endpoint.clone());
+            }
+            continue;
+        }
+        let (host, port) = crate::modes::redis::cluster_view::split_host_port(&endpoint.addr)
+            .ok_or_else(|| format!("Invalid endpoint {}", endpoint.addr))?;
+        let addrs = resolve_host(&host, port)
+            .await
+            .map_err(|e| format!("Failed to resolve {}: {e}", endpoint.addr))?;
+        if addrs.is_empty() {
+            return Err(format!("{} resolved to no addresses", endpoint.addr).into());
+        }
+        for addr in addrs {
+            if seen.insert(addr.to_string()) {
+                resolved.push(Endpoint {
+                    addr: addr.to_string(),
+                    ..endpoint.clone()
+                });
+            }
+        }
+    }
+    Ok(resolved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{EndpointHealthCheck, ProxyConfig};
+
+    #[tokio::test]
+    async fn test_hostnames_expand_to_their_addresses() {
+        let health_check = EndpointHealthCheck {
+            timeout_sec: Some(3),
+            ..Default::default()
+        };
+        let endpoints = vec![
+            Endpoint::new("localhost:27017", 2).with_health_check(health_check.clone()),
+            Endpoint::new("10.0.0.7:27017", 1),
+            Endpoint::new("127.0.0.1:27017", 5),
+        ];
+        let resolved = resolve_endpoints(&endpoints).await.unwrap();
+        // Weight and health check carry over to every address
+        assert!(resolved.iter().any(|endpoint| endpoint.addr == "127.0.0.1:27017"));
+        for endpoint in resolved.iter().filter(|endpoint| endpoint.addr != "10.0.0.7:27017") {
+            assert!(!endpoint.is_hostname());
+            assert_eq!(endpoint.weight, 2);
+            assert_eq!(endpoint.health_check.as_ref(), Some(&health_check));
+        }
+        // IP endpoints are kept, unless a hostname already named them
+        assert!(resolved.contains(&Endpoint::new("10.0.0.7:27017", 1)));
+        assert_eq!(resolved.iter().filter(|endpoint| endpoint.addr == "127.0.0.1:27017").count(), 1);
+
+        assert!(resolve_endpoints(&[Endpoint::from("no-such-host.invalid:27017")]).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_reloaded_endpoints_are_resolved() {
+        let source = HostnameDiscovery::new(vec![Endpoint::from("localhost:27017")]);
+        assert_eq!(source.describe(), "endpoint hostnames localhost:27017");
+
+        let mut config = Config::default();
+        let (sender, receiver) = watch::channel(Arc::new(config.clone()));
+        let endpoints_of: EndpointsOf = |config| match &config.proxy {
+            ProxyConfig::MongoDB {
+                mongos_endpoints, ..
+            } => Some(mongos_endpoints.clone()),
+            _ => None,
+        };
+        let source = source.with_reloads(receiver, endpoints_of);
+        assert_eq!(
+            source.discover().await.unwrap(),
+            vec![DiscoveredEndpoint::new(Endpoint::from("127.0.0.1:27017"), true)]
+        );
+
+        if let ProxyConfig::MongoDB {
+            mongos_endpoints, ..
+        } = &mut config.proxy
+        {
+            *mongos_endpoints = vec![Endpoint::new("10.0.0.8:27018", 4)];
+        }
+        sender.send(Arc::new(config)).unwrap();
+        assert_eq!(
+            source.discover().await.unwrap(),
+            vec![DiscoveredEndpoint::new(Endpoint::new("10.0.0.8:27018", 4), true)]
+        );
+    }
+}
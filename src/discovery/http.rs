@@ -0,0 +1,249 @@
+/// Minimal HTTP client for the Consul and etcd APIs
+///
+/// Requests are sent as HTTP/1.0 with `Connection: close` so responses are
+/// never chunked and the body runs to the end of the stream.
+use super::DiscoveryError;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// Timeout for connecting, sending and reading a whole response
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Largest response body accepted
+const MAX_RESPONSE_SIZE: usize = 16 * 1024 * 1024;
+
+/// A 2xx response
+#[derive(Debug)]
+pub struct Response {
+    /// Status line and headers
+    head: String,
+    pub body: String,
+}
+
+impl Response {
+    /// Value of the header `name`, compared case-insensitively
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.head.lines().skip(1).find_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            key.trim().eq_ignore_ascii_case(name).then(|| value.trim())
+        })
+    }
+}
+
+/// Send a request to `base_url` (`http://host:port`) and return the body of
+/// a 2xx response
+pub async fn request(
+    base_url: &str,
+    method: &str,
+    path: &str,
+    headers: &[(&str, &str)],
+    body: Option<&str>,
+) -> Result<String, DiscoveryError> {
+    Ok(send(base_url, method, path, headers, body, REQUEST_TIMEOUT).await?.body)
+}
+
+/// Send a request, waiting up to `timeout` for the whole of a 2xx response,
+/// as blocking queries need
+pub async fn send(
+    base_url: &str,
+    method: &str,
+    path: &str,
+    headers: &[(&str, &str)],
+    body: Option<&str>,
+    timeout: Duration,
+) -> Result<Response, DiscoveryError> {
+    let (authority, request) = build_request(base_url, method, path, headers, body)?;
+    let response = tokio::time::timeout(timeout, async {
+        let mut stream = TcpStream::connect(authority).await?;
+        stream.write_all(request.as_bytes()).await?;
+        let mut response = Vec::new();
+        (&mut stream)
+            .take(MAX_RESPONSE_SIZE as u64 + 1)
+            .read_to_end(&mut response)
+            .await?;
+        Ok::<_, std::io::Error>(response)
+    })
+    .await
+    .map_err(|_| format!("request to {base_url}{path} timed out"))??;
+    if response.len() > MAX_RESPONSE_SIZE {
+        return Err(format!("response from {base_url}{path} is too large").into());
+    }
+
+    let (status, head, body) = parse_response(&response)?;
+    if !(200..300).contains(&status) {
+        return Err(format!("{method} {base_url}{path} returned HTTP {status}: {body}").into());
+    }
+    Ok(Response { head, body })
+}
+
+/// POST `body` to a streaming endpoint and read the newline-delimited
+/// messages it answers with until `until` accepts one, returning `false`
+/// when `timeout` elapses first
+pub async fn watch(
+    base_url: &str,
+    path: &str,
+    headers: &[(&str, &str)],
+    body: &str,
+    timeout: Duration,
+    mut until: impl FnMut(&str) -> bool + Send,
+) -> Result<bool, DiscoveryError> {
+    let (authority, request) = build_request(base_url, "POST", path, headers, Some(body))?;
+    let watched: Result<Result<bool, DiscoveryError>, _> = tokio::time::timeout(timeout, async {
+        let mut stream = TcpStream::connect(authority).await?;
+        stream.write_all(request.as_bytes()).await?;
+        let mut buf = Vec::new();
+        // Where the body starts, once the headers are read
+        let mut body_start = None;
+        loop {
+            if stream.read_buf(&mut buf).await? == 0 {
+                return Err(format!("{base_url}{path} closed the stream").into());
+            }
+            if buf.len() > MAX_RESPONSE_SIZE {
+                return Err(format!("message from {base_url}{path} is too large").into());
+            }
+            let start = match body_start {
+                Some(start) => start,
+                None => {
+                    let Some(end) = buf.windows(4).position(|window| window == b"\r\n\r\n") else {
+                        continue;
+                    };
+                    let (status, _, body) = parse_response(&buf)?;
+                    if !(200..300).contains(&status) {
+                        return Err(format!("POST {base_url}{path} returned HTTP {status}: {body}").into());
+                    }
+                    end + 4
+                }
+            };
+            let mut consumed = start;
+            while let Some(newline) = buf[consumed..].iter().position(|&byte| byte == b'\n') {
+                let message = String::from_utf8_lossy(&buf[consumed..consumed + newline]).to_string();
+                if until(message.trim()) {
+                    return Ok(true);
+                }
+                consumed += newline + 1;
+            }
+            buf.drain(start..consumed);
+            body_start = Some(start);
+        }
+    })
+    .await;
+    watched.unwrap_or(Ok(false))
+}
+
+/// The authority to connect to and the request to send it
+fn build_request(
+    base_url: &str,
+    method: &str,
+    path: &str,
+    headers: &[(&str, &str)],
+    body: Option<&str>,
+) -> Result<(String, String), DiscoveryError> {
+    let authority = base_url
+        .strip_prefix("http://")
+        .ok_or_else(|| format!("unsupported discovery address {base_url}, expected http://"))?
+        .trim_end_matches('/');
+
+    let mut request =
+        format!("{method} {path} HTTP/1.0\r\nHost: {authority}\r\nConnection: close\r\n");
+    for (name, value) in headers {
+        request.push_str(&format!("{name}: {value}\r\n"));
+    }
+    if let Some(body) = body {
+        request.push_str(&format!(
+            "Content-Type: application/json\r\nContent-Length: {}\r\n",
+            body.len()
+        ));
+    }
+    request.push_str("\r\n");
+    if let Some(body) = body {
+        request.push_str(body);
+    }
+    Ok((authority.to_string(), request))
+}
+
+/// Split a complete response into its status code, head and body
+fn parse_response(response: &[u8]) -> Result<(u16, String, String), DiscoveryError> {
+    let text = String::from_utf8_lossy(response);
+    let (head, body) = text
+        .split_once("\r\n\r\n")
+        .ok_or("malformed HTTP response: missing header terminator")?;
+    let status = head
+        .lines()
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|code| code.parse::<u16>().ok())
+        .ok_or("malformed HTTP response: missing status code")?;
+    Ok((status, head.to_string(), body.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn test_parse_response() {
+        let (status, head, body) =
+            parse_response(b"HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\r\n[]").unwrap();
+        assert_eq!(status, 200);
+        assert_eq!(body, "[]");
+        let response = Response { head, body };
+        assert_eq!(response.header("content-type"), Some("application/json"));
+        assert_eq!(response.header("X-Consul-Index"), None);
+        assert!(parse_response(b"HTTP/1.1 200 OK\r\n").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_request_round_trip() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = vec![0u8; 1024];
+            let n = stream.read(&mut request).await.unwrap();
+            stream
+                .write_all(b"HTTP/1.0 200 OK\r\n\r\n{\"ok\":true}")
+                .await
+                .unwrap();
+            String::from_utf8_lossy(&request[..n]).to_string()
+        });
+
+        let body = request(
+            &format!("http://{addr}"),
+            "POST",
+            "/v3/kv/range",
+            &[("Authorization", "secret")],
+            Some("{}"),
+        )
+        .await
+        .unwrap();
+        assert_eq!(body, "{\"ok\":true}");
+
+        let sent = server.await.unwrap();
+        assert!(sent.starts_with("POST /v3/kv/range HTTP/1.0\r\n"));
+        assert!(sent.contains("Authorization: secret\r\n"));
+        assert!(sent.ends_with("\r\n\r\n{}"));
+    }
+
+    #[tokio::test]
+    async fn test_watch_times_out_without_a_message() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = vec![0u8; 1024];
+            let _ = stream.read(&mut request).await.unwrap();
+            stream.write_all(b"HTTP/1.0 200 OK\r\n\r\n{\"created\":true}\n").await.unwrap();
+            let _ = stream.read(&mut request).await;
+        });
+
+        let base_url = format!("http://{addr}");
+        let watched = watch(&base_url, "/v3/watch", &[], "{}", Duration::from_millis(200), |message| {
+            message.contains("events")
+        })
+        .await
+        .unwrap();
+        assert!(!watched);
+    }
+}
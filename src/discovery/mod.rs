@@ -0,0 +1,252 @@
+/// Dynamic backend discovery
+///
+/// Backends come from one of three sources: DNS SRV or A/AAAA records, the
+/// health endpoint of a Consul service, or the keys under an etcd prefix.
+/// DNS is polled every `interval_sec`. Consul and etcd are watched, with
+/// blocking queries and the watch API, so changes are picked up as they
+/// happen; they are also read again every `interval_sec`. Changes to the
+/// discovered set, including the health reported by the catalog, are
+/// published on a watch channel for the proxy mode to apply; failed or empty
+/// lookups keep the previous set.
+/// Static endpoint lists naming backends by hostname are resolved the same
+/// way, every `dns_refresh_sec`.
+pub mod consul;
+pub mod dns;
+pub mod etcd;
+pub mod hostnames;
+pub(crate) mod http;
+
+use crate::config::{DiscoveryConfig, DiscoveryProvider, Endpoint};
+use std::collections::HashSet;
+use std::error::Error;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::sync::watch;
+
+pub use consul::ConsulDiscovery;
+pub use dns::DnsDiscovery;
+pub use etcd::EtcdDiscovery;
+pub use hostnames::{resolve_endpoints, HostnameDiscovery};
+
+pub type DiscoveryError = Box<dyn Error + Send + Sync>;
+
+/// A backend and the health its source reports for it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiscoveredEndpoint {
+    pub endpoint: Endpoint,
+    /// False when the catalog's own checks fail; DNS never reports this
+    pub healthy: bool,
+}
+
+impl DiscoveredEndpoint {
+    pub fn new(endpoint: Endpoint, healthy: bool) -> Self {
+        Self { endpoint, healthy }
+    }
+
+    /// Endpoints of a discovered set
+    pub fn endpoints(discovered: &[DiscoveredEndpoint]) -> Vec<Endpoint> {
+        discovered.iter().map(|d| d.endpoint.clone()).collect()
+    }
+
+    /// Endpoints the catalog reports as healthy, or all of them when none are
+    /// so that a catalog outage does not empty the backend set
+    pub fn healthy_endpoints(discovered: &[DiscoveredEndpoint]) -> Vec<Endpoint> {
+        let healthy: Vec<Endpoint> = discovered
+            .iter()
+            .filter(|d| d.healthy)
+            .map(|d| d.endpoint.clone())
+            .collect();
+        if healthy.is_empty() {
+            return Self::endpoints(discovered);
+        }
+        healthy
+    }
+
+    /// Addresses the catalog reports as failing
+    pub fn unhealthy_addrs(discovered: &[DiscoveredEndpoint]) -> HashSet<SocketAddr> {
+        discovered
+            .iter()
+            .filter(|d| !d.healthy)
+            .filter_map(|d| d.endpoint.addr.parse().ok())
+            .collect()
+    }
+}
+
+/// A source of backends
+#[async_trait::async_trait]
+pub trait BackendSource: Send + Sync {
+    /// Fetch the current backends
+    async fn discover(&self) -> Result<Vec<DiscoveredEndpoint>, DiscoveryError>;
+
+    /// Human-readable description for logs, e.g. `Consul service mongos`
+    fn describe(&self) -> String;
+
+    /// Return once the backends may have changed since the last
+    /// `discover`, or after `timeout`; sources that cannot be watched wait
+    /// out the timeout and so are polled
+    async fn wait_for_change(&self, timeout: Duration) -> Result<(), DiscoveryError> {
+        tokio::time::sleep(timeout).await;
+        Ok(())
+    }
+}
+
+/// Least time between two lookups, so a burst of catalog changes is
+/// applied in one go
+const MIN_LOOKUP_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Periodic discovery of backends from the configured source
+pub struct Discovery {
+    source: Box<dyn BackendSource>,
+    interval: Duration,
+}
+
+impl Discovery {
+    pub fn new(config: DiscoveryConfig) -> Self {
+        let interval = Duration::from_secs(config.interval_sec);
+        let source: Box<dyn BackendSource> = match config.provider {
+            DiscoveryProvider::Dns => Box::new(DnsDiscovery::new(config)),
+            DiscoveryProvider::Consul => Box::new(ConsulDiscovery::new(config)),
+            DiscoveryProvider::Etcd => Box::new(EtcdDiscovery::new(config)),
+        };
+        Self { source, interval }
+    }
+
+    /// Discovery from `source` every `interval`
+    pub fn from_source(source: Box<dyn BackendSource>, interval: Duration) -> Self {
+        Self { source, interval }
+    }
+
+    /// Fetch the backends, sorted and deduplicated by address
+    pub async fn resolve(&self) -> Result<Vec<DiscoveredEndpoint>, DiscoveryError> {
+        let mut discovered = self.source.discover().await?;
+        discovered.sort_by(|a, b| a.endpoint.addr.cmp(&b.endpoint.addr));
+        discovered.dedup_by(|a, b| a.endpoint.addr == b.endpoint.addr);
+        Ok(discovered)
+    }
+
+    /// Resolve once from outside any runtime, for startup
+    pub fn resolve_blocking(&self) -> Result<Vec<DiscoveredEndpoint>, DiscoveryError> {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?
+            .block_on(self.resolve())
+    }
+
+    pub fn describe(&self) -> String {
+        self.source.describe()
+    }
+
+    /// Re-resolve on every change the source reports, and at least every
+    /// `interval_sec`, from a background thread, starting from `initial`,
+    /// and publish each changed backend set
+    pub fn spawn_watcher(
+        self,
+        initial: Vec<DiscoveredEndpoint>,
+    ) -> watch::Receiver<Vec<DiscoveredEndpoint>> {
+        let (sender, receiver) = watch::channel(initial);
+
+        std::thread::spawn(move || {
+            let rt = crate::utils::background_runtime();
+            rt.block_on(async move {
+                let source = self.describe();
+                log::info!(
+                    "Discovering backends from {source}, at least every {}s",
+                    self.interval.as_secs()
+                );
+
+                loop {
+                    // Startup already resolved, so each lookup follows a wait
+                    let waited = tokio::time::Instant::now();
+                    if let Err(e) = self.source.wait_for_change(self.interval).await {
+                        log::warn!("Watching {source} failed, retrying in {}s: {e}", self.interval.as_secs());
+                        tokio::time::sleep_until(waited + self.interval).await;
+                    }
+                    tokio::time::sleep_until(waited + MIN_LOOKUP_INTERVAL).await;
+                    match self.resolve().await {
+                        Ok(discovered) if discovered.is_empty() => {
+                            log::warn!("{source} returned no backends, keeping current set");
+                        }
+                        Ok(discovered) => {
+                            sender.send_if_modified(|current| {
+                                if *current == discovered {
+                                    return false;
+                                }
+                                log::info!("Discovery updated backends: {discovered:?}");
+                                *current = discovered;
+                                true
+                            });
+                        }
+                        Err(e) => {
+                            log::warn!(
+                                "Discovery from {source} failed, keeping current backends: {e}"
+                            )
+                        }
+                    }
+                    if sender.is_closed() {
+                        return;
+                    }
+                }
+            });
+        });
+
+        receiver
+    }
+}
+
+/// Resolve `host` to socket addresses on `port`, skipping DNS for IP literals
+async fn resolve_host(host: &str, port: u16) -> Result<Vec<SocketAddr>, DiscoveryError> {
+    if let Ok(ip) = host.parse::<std::net::IpAddr>() {
+        return Ok(vec![SocketAddr::new(ip, port)]);
+    }
+    Ok(tokio::net::lookup_host((host, port)).await?.collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::DnsRecordType;
+
+    #[test]
+    fn test_discovered_endpoint_helpers() {
+        let discovered = vec![
+            DiscoveredEndpoint::new(Endpoint::new("10.0.0.1:27017", 2), true),
+            DiscoveredEndpoint::new(Endpoint::new("10.0.0.2:27017", 1), false),
+        ];
+        assert_eq!(
+            DiscoveredEndpoint::endpoints(&discovered),
+            vec![
+                Endpoint::new("10.0.0.1:27017", 2),
+                Endpoint::new("10.0.0.2:27017", 1)
+            ]
+        );
+        assert_eq!(
+            DiscoveredEndpoint::healthy_endpoints(&discovered),
+            vec![Endpoint::new("10.0.0.1:27017", 2)]
+        );
+        assert_eq!(
+            DiscoveredEndpoint::healthy_endpoints(&discovered[1..]),
+            vec![Endpoint::new("10.0.0.2:27017", 1)]
+        );
+        assert_eq!(
+            DiscoveredEndpoint::unhealthy_addrs(&discovered),
+            HashSet::from(["10.0.0.2:27017".parse().unwrap()])
+        );
+    }
+
+    #[test]
+    fn test_resolve_blocking() {
+        let discovery = Discovery::new(DiscoveryConfig {
+            dns_name: "::1".to_string(),
+            record_type: DnsRecordType::A,
+            port: Some(6379),
+            ..Default::default()
+        });
+        assert_eq!(
+            discovery.resolve_blocking().unwrap(),
+            vec![DiscoveredEndpoint::new(
+                Endpoint::new("[::1]:6379", 1),
+                true
+            )]
+        );
+    }
+}
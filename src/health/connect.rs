@@ -0,0 +1,96 @@
+/// Connections health checks run over
+///
+/// A check must reach the backend the way client traffic does: against a
+/// mongos or Redis node accepting only TLS, a plaintext probe always fails
+/// and every backend ends up unhealthy.
+use crate::config::{RedisAuthConfig, RedisSharding, UpstreamTlsConfig};
+use crate::core::upstream;
+use pingora_core::connectors::TransportConnector;
+use pingora_core::protocols::Stream;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+/// How the checks of a proxy instance reach its backends
+#[derive(Debug, Clone, Default)]
+pub struct CheckOptions {
+    /// TLS the proxy connects to backends over
+    pub upstream_tls: Option<UpstreamTlsConfig>,
+    /// Whether Redis nodes form a cluster answering `CLUSTER NODES`
+    pub redis_sharding: RedisSharding,
+    /// Credentials Redis nodes require before answering
+    pub redis_auth: Option<RedisAuthConfig>,
+}
+
+/// Opens check connections, over TLS when the proxy uses it upstream
+#[derive(Clone)]
+pub struct CheckConnector {
+    connector: Arc<TransportConnector>,
+    tls: Option<UpstreamTlsConfig>,
+}
+
+impl CheckConnector {
+    pub fn new(tls: Option<&UpstreamTlsConfig>) -> Self {
+        Self {
+            connector: Arc::new(upstream::new_connector(tls)),
+            tls: tls.cloned(),
+        }
+    }
+
+    /// Open a connection to `addr`, completing the TLS handshake when set
+    pub async fn connect(&self, addr: SocketAddr) -> Result<Stream, String> {
+        let peer = upstream::new_peer(&addr.to_string(), self.tls.as_ref(), None);
+        self.connector
+            .new_stream(&peer)
+            .await
+            .map_err(|e| format!("Connection failed: {e}"))
+    }
+}
+
+impl Default for CheckConnector {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpListener;
+
+    /// First record type of a TLS handshake
+    const TLS_HANDSHAKE: u8 = 0x16;
+
+    /// A backend accepting only TLS: it reads the first byte of a
+    /// connection and closes it, returning whether that byte starts a TLS
+    /// handshake
+    pub(crate) async fn tls_only_backend() -> (SocketAddr, tokio::task::JoinHandle<bool>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handshake = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut first = [0u8; 1];
+            stream.read_exact(&mut first).await.is_ok() && first[0] == TLS_HANDSHAKE
+        });
+        (addr, handshake)
+    }
+
+    pub(crate) fn tls_config() -> UpstreamTlsConfig {
+        UpstreamTlsConfig {
+            ca_file: None,
+            cert_path: None,
+            key_path: None,
+            sni: Some("mongos.example.com".to_string()),
+            verify_cert: true,
+            verify_hostname: true,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_connect_over_tls() {
+        let (addr, handshake) = tls_only_backend().await;
+        let connector = CheckConnector::new(Some(&tls_config()));
+        assert!(connector.connect(addr).await.is_err());
+        assert!(handshake.await.unwrap());
+    }
+}
@@ -0,0 +1,193 @@
+/// Backend health state change events
+///
+/// Every healthy/unhealthy transition is logged as a structured event,
+/// counted per backend and target state, and reflected in a per-backend
+/// health gauge on the metrics endpoint. With `[health.notifications]`
+/// configured, each event is also POSTed as JSON to a webhook.
+use crate::config::HealthNotificationsConfig;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Mutex;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// A backend moving between healthy and unhealthy
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HealthEvent {
+    /// Proxy mode of the backend, `mongodb` or `redis`
+    pub mode: &'static str,
+    pub backend: String,
+    pub addr: SocketAddr,
+    /// State the backend moved to
+    pub healthy: bool,
+    /// Why the backend changed state
+    pub reason: String,
+    /// Seconds spent in the previous state
+    pub previous_state_secs: u64,
+    /// Unix time of the transition, in seconds
+    pub timestamp: u64,
+}
+
+/// Health event recorder shared by the health checks of one proxy
+pub struct HealthEvents {
+    mode: &'static str,
+    webhook_url: Option<String>,
+    /// When the recorder was created, standing in for the last transition of
+    /// backends that never changed state
+    started: Instant,
+    /// Latest recorded state of each backend and when it was entered
+    states: Mutex<HashMap<SocketAddr, (bool, Instant)>>,
+}
+
+impl HealthEvents {
+    /// Record events for backends of `mode`, pushing them to the webhook of
+    /// `notifications` if set
+    pub fn new(mode: &'static str, notifications: Option<&HealthNotificationsConfig>) -> Self {
+        Self {
+            mode,
+            webhook_url: notifications.map(|notifications| notifications.webhook_url.clone()),
+            started: Instant::now(),
+            states: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Record `backend` at `addr` becoming healthy or unhealthy for `reason`
+    pub fn record(&self, backend: &str, addr: SocketAddr, healthy: bool, reason: &str) -> HealthEvent {
+        let now = Instant::now();
+        let since = self
+            .states
+            .lock()
+            .unwrap()
+            .insert(addr, (healthy, now))
+            .map_or(self.started, |(_, since)| since);
+        let event = HealthEvent {
+            mode: self.mode,
+            backend: backend.to_string(),
+            addr,
+            healthy,
+            reason: reason.to_string(),
+            previous_state_secs: now.duration_since(since).as_secs(),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+        };
+
+        let state = if healthy { "healthy" } else { "unhealthy" };
+        let registry = crate::metrics::global();
+        registry
+            .counter_with_labels(
+                "puerta_backend_health_transitions_total",
+                "Backend transitions between healthy and unhealthy",
+                &[("mode", self.mode), ("backend", backend), ("state", state)],
+            )
+            .inc();
+        registry
+            .gauge_with_labels(
+                "puerta_backend_healthy",
+                "Whether the backend is healthy (1) or not (0)",
+                &[("mode", self.mode), ("backend", backend)],
+            )
+            .set(healthy as i64);
+
+        let json = serde_json::to_string(&event).unwrap_or_default();
+        if healthy {
+            log::info!("Backend health event: {json}");
+        } else {
+            log::warn!("Backend health event: {json}");
+        }
+        if let Some(webhook_url) = &self.webhook_url {
+            Self::notify(webhook_url.clone(), json);
+        }
+        event
+    }
+
+    /// Whether the latest event recorded for `addr` marked it unhealthy
+    pub fn is_unhealthy(&self, addr: SocketAddr) -> bool {
+        self.states
+            .lock()
+            .unwrap()
+            .get(&addr)
+            .is_some_and(|(healthy, _)| !healthy)
+    }
+
+    /// POST an event to the webhook without holding up the caller
+    fn notify(webhook_url: String, json: String) {
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            log::warn!("No runtime to send health event to {webhook_url}");
+            return;
+        };
+        runtime.spawn(async move {
+            // Validation guarantees an http:// URL
+            let rest = webhook_url.strip_prefix("http://").unwrap_or_default();
+            let (authority, path) = match rest.find('/') {
+                Some(index) => rest.split_at(index),
+                None => (rest, "/"),
+            };
+            let base_url = format!("http://{authority}");
+            if let Err(e) =
+                crate::discovery::http::request(&base_url, "POST", path, &[], Some(&json)).await
+            {
+                log::warn!("Failed to send health event to {webhook_url}: {e}");
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn test_records_transitions() {
+        let events = HealthEvents::new("mongodb", None);
+        let addr: SocketAddr = "127.0.0.1:27101".parse().unwrap();
+
+        assert!(!events.is_unhealthy(addr));
+        let event = events.record("mongos-events", addr, false, "Connection refused");
+        assert!(events.is_unhealthy(addr));
+        assert_eq!(event.mode, "mongodb");
+        assert!(!event.healthy);
+        assert_eq!(event.reason, "Connection refused");
+        assert!(event.timestamp > 0);
+
+        let event = events.record("mongos-events", addr, true, "health check passed");
+        assert!(event.healthy);
+        assert_eq!(event.previous_state_secs, 0);
+        assert!(!events.is_unhealthy(addr));
+
+        let metrics = crate::metrics::global().render_prometheus();
+        assert!(metrics.contains(
+            "puerta_backend_health_transitions_total{mode=\"mongodb\",backend=\"mongos-events\",state=\"unhealthy\"} 1"
+        ));
+        assert!(metrics.contains("puerta_backend_healthy{mode=\"mongodb\",backend=\"mongos-events\"} 1"));
+    }
+
+    #[tokio::test]
+    async fn test_webhook_notification() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let notifications = HealthNotificationsConfig {
+            webhook_url: format!("http://{}/hooks/puerta", listener.local_addr().unwrap()),
+        };
+        let events = HealthEvents::new("redis", Some(&notifications));
+        let addr: SocketAddr = "127.0.0.1:7101".parse().unwrap();
+        events.record("redis-events", addr, false, "PING timed out");
+
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut request = Vec::new();
+        let mut buf = [0u8; 1024];
+        while !String::from_utf8_lossy(&request).contains("PING timed out") {
+            let n = stream.read(&mut buf).await.unwrap();
+            assert!(n > 0, "webhook request ended early");
+            request.extend_from_slice(&buf[..n]);
+        }
+        stream.write_all(b"HTTP/1.0 200 OK\r\n\r\n").await.unwrap();
+
+        let request = String::from_utf8(request).unwrap();
+        assert!(request.starts_with("POST /hooks/puerta HTTP/1.0"));
+        assert!(request.contains("\"backend\":\"redis-events\""));
+        assert!(request.contains("\"healthy\":false"));
+    }
+}
@@ -1,12 +1,41 @@
 /// Health checking for MongoDB and Redis backends
+pub mod connect;
+pub mod events;
 pub mod mongodb;
+pub mod outlier;
+pub mod probe;
 pub mod redis;
+pub mod tcp;
 
+use crate::config::{EndpointHealthCheck, HealthCheckKind, OutlierDetectionConfig, RedisSharding};
 use crate::core::{Backend, BackendMetadata};
-use std::time::{Duration, SystemTime};
+pub use connect::{CheckConnector, CheckOptions};
+use outlier::OutlierDetector;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant, SystemTime};
 use tokio::time::timeout;
 use std::fmt;
 
+/// Consecutive live-traffic failures after which a backend is ejected
+pub const DEFAULT_PASSIVE_FAILURE_THRESHOLD: u32 = 5;
+
+/// Largest share of the interval, in percent, a round of checks is spread over
+pub const MAX_JITTER_PERCENT: u32 = 50;
+
+/// Delay before the `index`th of `count` checks of a round spread over
+/// `spread`: each check gets an equal share of it, at a random point within
+/// that share, so checks neither fire together nor line up round after round
+pub fn stagger_delay(index: usize, count: usize, spread: Duration) -> Duration {
+    if count == 0 || spread.is_zero() {
+        return Duration::ZERO;
+    }
+    let share = spread / count as u32;
+    share * index as u32 + share.mul_f64(rand::random::<f64>())
+}
+
 /// Health status of a backend
 #[derive(Debug, Clone, PartialEq)]
 pub enum HealthStatus {
@@ -45,34 +74,256 @@ pub trait HealthChecker: Send + Sync {
 
     /// Get the timeout for health checks
     fn check_timeout(&self) -> Duration;
+
+    /// Copy what the last check of `backend` learned into its metadata
+    fn update_metadata(&self, _backend: &mut Backend) {}
+}
+
+/// Failures observed on live traffic for one backend
+#[derive(Debug, Default)]
+struct PassiveState {
+    consecutive_failures: u32,
+    ejected_at: Option<Instant>,
+}
+
+/// Consecutive active check results for one backend
+#[derive(Debug, Default)]
+struct ActiveState {
+    consecutive_failures: u32,
+    consecutive_successes: u32,
 }
 
 /// Generic health check manager
+///
+/// A healthy backend is marked unhealthy after `failure_threshold`
+/// consecutive failed probes, and an unhealthy one healthy again after
+/// `success_threshold` consecutive passed probes, so a single lost probe
+/// does not make a backend flap. The first probe of a backend decides on
+/// its own. Backends with endpoint overrides are probed by a checker built
+/// from their own interval, timeout and checker type.
+///
+/// Besides active probes, the manager counts connect failures and I/O errors
+/// reported from live traffic. After `passive_failure_threshold` consecutive
+/// failures a backend is ejected without waiting for the next probe; it is
+/// re-admitted by a successful probe or, after one check interval, on
+/// trial until the next failure.
 pub struct HealthCheckManager {
     checker: Box<dyn HealthChecker>,
+    endpoint_overrides: RwLock<HashMap<SocketAddr, EndpointHealthCheck>>,
+    active: Mutex<HashMap<SocketAddr, ActiveState>>,
+    failure_threshold: AtomicU32,
+    success_threshold: AtomicU32,
+    passive: Mutex<HashMap<SocketAddr, PassiveState>>,
+    passive_failure_threshold: u32,
+    outlier_detector: Option<OutlierDetector>,
+    /// How checkers built for endpoint overrides reach backends
+    check_options: CheckOptions,
 }
 
 impl HealthCheckManager {
     pub fn new(checker: Box<dyn HealthChecker>) -> Self {
-        Self { checker }
+        Self {
+            checker,
+            endpoint_overrides: RwLock::new(HashMap::new()),
+            active: Mutex::new(HashMap::new()),
+            failure_threshold: AtomicU32::new(1),
+            success_threshold: AtomicU32::new(1),
+            passive: Mutex::new(HashMap::new()),
+            passive_failure_threshold: DEFAULT_PASSIVE_FAILURE_THRESHOLD,
+            outlier_detector: None,
+            check_options: CheckOptions::default(),
+        }
+    }
+
+    /// Require this many consecutive failed or passed probes before a
+    /// backend changes state; both default to 1
+    pub fn with_thresholds(self, failure_threshold: u32, success_threshold: u32) -> Self {
+        self.set_thresholds(failure_threshold, success_threshold);
+        self
+    }
+
+    /// Change the probe thresholds, e.g. on configuration reload; zeros are
+    /// ignored
+    pub fn set_thresholds(&self, failure_threshold: u32, success_threshold: u32) {
+        if failure_threshold > 0 {
+            self.failure_threshold.store(failure_threshold, Ordering::Relaxed);
+        }
+        if success_threshold > 0 {
+            self.success_threshold.store(success_threshold, Ordering::Relaxed);
+        }
+    }
+
+    /// Reach backends this way from the checkers of endpoint overrides
+    pub fn with_check_options(mut self, options: CheckOptions) -> Self {
+        self.check_options = options;
+        self
+    }
+
+    /// Check these backends with their own settings instead of the defaults
+    pub fn with_endpoint_overrides(self, overrides: HashMap<SocketAddr, EndpointHealthCheck>) -> Self {
+        self.set_endpoint_overrides(overrides);
+        self
+    }
+
+    /// Replace the per-backend health check settings, e.g. on configuration
+    /// reload
+    pub fn set_endpoint_overrides(&self, overrides: HashMap<SocketAddr, EndpointHealthCheck>) {
+        *self.endpoint_overrides.write().unwrap() = overrides;
+    }
+
+    /// Check interval of a backend with its own interval setting
+    pub fn endpoint_interval(&self, addr: SocketAddr) -> Option<Duration> {
+        let overrides = self.endpoint_overrides.read().unwrap();
+        overrides
+            .get(&addr)
+            .and_then(|overrides| overrides.interval_sec)
+            .map(Duration::from_secs)
+    }
+
+    /// Shortest check interval set for any backend
+    pub fn shortest_endpoint_interval(&self) -> Option<Duration> {
+        let overrides = self.endpoint_overrides.read().unwrap();
+        overrides
+            .values()
+            .filter_map(|overrides| overrides.interval_sec)
+            .min()
+            .map(Duration::from_secs)
+    }
+
+    /// Eject backends whose error rate or latency stands out
+    pub fn with_outlier_detection(mut self, config: Option<OutlierDetectionConfig>) -> Self {
+        self.outlier_detector = config.map(OutlierDetector::new);
+        self
+    }
+
+    /// Record the outcome of a live request for outlier detection
+    ///
+    /// Returns `true` when this outcome ejects the backend.
+    pub fn record_outcome(&self, addr: SocketAddr, success: bool, latency: Option<Duration>) -> bool {
+        self.outlier_detector
+            .as_ref()
+            .is_some_and(|detector| detector.record(addr, success, latency))
+    }
+
+    /// Whether a request may be sent to `addr`, considering passive
+    /// ejection and outlier detection
+    pub fn admits(&self, addr: SocketAddr) -> bool {
+        !self.is_passively_ejected(addr)
+            && self
+                .outlier_detector
+                .as_ref()
+                .is_none_or(|detector| detector.admits(addr))
+    }
+
+    /// Eject after this many consecutive live-traffic failures; 0 disables
+    /// passive checking
+    pub fn with_passive_failure_threshold(mut self, threshold: u32) -> Self {
+        self.passive_failure_threshold = threshold;
+        self
+    }
+
+    /// Record a failure seen on live traffic
+    ///
+    /// Returns `true` when this failure ejects the backend.
+    pub fn record_passive_failure(&self, addr: SocketAddr) -> bool {
+        if self.passive_failure_threshold == 0 {
+            return false;
+        }
+
+        let mut passive = self.passive.lock().unwrap();
+        let state = passive.entry(addr).or_default();
+        state.consecutive_failures = state.consecutive_failures.saturating_add(1);
+
+        let ejected = state.consecutive_failures >= self.passive_failure_threshold
+            && !Self::within_ejection(state, self.checker.check_interval());
+        if ejected {
+            state.ejected_at = Some(Instant::now());
+        }
+        ejected
+    }
+
+    /// Record a successful exchange seen on live traffic
+    pub fn record_passive_success(&self, addr: SocketAddr) {
+        self.passive.lock().unwrap().remove(&addr);
+    }
+
+    /// Eject the backend for one check interval, as passive checking does
+    /// after repeated failures
+    pub fn eject(&self, addr: SocketAddr) {
+        self.passive.lock().unwrap().entry(addr).or_default().ejected_at = Some(Instant::now());
+    }
+
+    /// Whether the backend is currently ejected by passive checking
+    pub fn is_passively_ejected(&self, addr: SocketAddr) -> bool {
+        self.passive
+            .lock()
+            .unwrap()
+            .get(&addr)
+            .is_some_and(|state| Self::within_ejection(state, self.checker.check_interval()))
+    }
+
+    fn within_ejection(state: &PassiveState, period: Duration) -> bool {
+        state
+            .ejected_at
+            .is_some_and(|ejected_at| ejected_at.elapsed() < period)
     }
 
     /// Perform health check with timeout
+    #[tracing::instrument(
+        name = "health.check",
+        skip_all,
+        fields(backend = %backend.addr, status = tracing::field::Empty)
+    )]
     pub async fn check_backend_health(&self, backend: &mut Backend) -> HealthStatus {
-        let check_timeout = self.checker.check_timeout();
+        let overrides = self.endpoint_overrides.read().unwrap().get(&backend.addr).cloned();
+        let endpoint_checker =
+            overrides.map(|overrides| create_health_checker_with(backend, &overrides, &self.check_options));
+        let checker = endpoint_checker.as_deref().unwrap_or(self.checker.as_ref());
+        let check_timeout = checker.check_timeout();
 
-        let status = match timeout(check_timeout, self.checker.check_health(backend)).await {
+        let status = match timeout(check_timeout, checker.check_health(backend)).await {
             Ok(status) => status,
             Err(_) => HealthStatus::Timeout,
         };
+        tracing::Span::current().record("status", tracing::field::debug(&status));
+        checker.update_metadata(backend);
 
         // Update backend status
+        let passed = matches!(status, HealthStatus::Healthy);
+        backend.healthy = self.apply_check_result(backend, passed);
         backend.last_health_check = Some(SystemTime::now());
-        backend.healthy = matches!(status, HealthStatus::Healthy);
+
+        // A passing probe re-admits a passively ejected backend
+        if passed {
+            self.record_passive_success(backend.addr);
+        }
 
         status
     }
 
+    /// Count a probe result and return whether the backend is now healthy
+    fn apply_check_result(&self, backend: &Backend, passed: bool) -> bool {
+        let mut active = self.active.lock().unwrap();
+        let state = active.entry(backend.addr).or_default();
+        if passed {
+            state.consecutive_successes = state.consecutive_successes.saturating_add(1);
+            state.consecutive_failures = 0;
+        } else {
+            state.consecutive_failures = state.consecutive_failures.saturating_add(1);
+            state.consecutive_successes = 0;
+        }
+
+        // Without a previous probe there is no state to hold on to
+        if backend.last_health_check.is_none() {
+            return passed;
+        }
+        if backend.healthy {
+            state.consecutive_failures < self.failure_threshold.load(Ordering::Relaxed)
+        } else {
+            state.consecutive_successes >= self.success_threshold.load(Ordering::Relaxed)
+        }
+    }
+
     /// Run continuous health checking for a backend
     pub async fn run_health_checks(&self, backend: &mut Backend) {
         let mut interval = tokio::time::interval(self.checker.check_interval());
@@ -106,8 +357,22 @@ mod tests {
     use crate::core::{Backend, BackendMetadata};
       use std::net::SocketAddr;
     use std::time::{Duration, SystemTime};
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Arc;
     use async_trait::async_trait;
 
+    #[test]
+    fn test_stagger_delay() {
+        let spread = Duration::from_secs(4);
+        for index in 0..4 {
+            let delay = stagger_delay(index, 4, spread);
+            assert!(delay >= Duration::from_secs(index as u64), "{delay:?}");
+            assert!(delay < Duration::from_secs(index as u64 + 1), "{delay:?}");
+        }
+        assert_eq!(stagger_delay(0, 0, spread), Duration::ZERO);
+        assert_eq!(stagger_delay(3, 4, Duration::ZERO), Duration::ZERO);
+    }
+
     // Mock health checker for testing
     struct MockHealthChecker {
         should_pass: bool,
@@ -143,6 +408,7 @@ mod tests {
             last_health_check: Some(SystemTime::now()),
             metadata: BackendMetadata::MongoDB {
                 version: Some("4.4.0".to_string()),
+                max_wire_version: None,
                 is_primary: true,
                 connection_count: 0,
             },
@@ -195,10 +461,110 @@ mod tests {
         assert!(!backend.healthy); // Backend should be updated
     }
 
+    /// Checker whose result can be flipped between probes
+    struct SwitchableHealthChecker {
+        pass: Arc<AtomicBool>,
+    }
+
+    #[async_trait]
+    impl HealthChecker for SwitchableHealthChecker {
+        async fn check_health(&self, _backend: &Backend) -> HealthStatus {
+            if self.pass.load(Ordering::Relaxed) {
+                HealthStatus::Healthy
+            } else {
+                HealthStatus::Timeout
+            }
+        }
+
+        fn check_timeout(&self) -> Duration {
+            Duration::from_secs(1)
+        }
+
+        fn check_interval(&self) -> Duration {
+            Duration::from_secs(5)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_health_check_thresholds() {
+        let pass = Arc::new(AtomicBool::new(true));
+        let checker = Box::new(SwitchableHealthChecker { pass: Arc::clone(&pass) });
+        let manager = HealthCheckManager::new(checker).with_thresholds(3, 2);
+        let mut backend = create_test_backend("test1", false);
+        backend.last_health_check = None;
+
+        // The first probe decides on its own
+        manager.check_backend_health(&mut backend).await;
+        assert!(backend.healthy);
+
+        // A blip shorter than the failure threshold is ignored
+        pass.store(false, Ordering::Relaxed);
+        manager.check_backend_health(&mut backend).await;
+        manager.check_backend_health(&mut backend).await;
+        assert!(backend.healthy);
+        pass.store(true, Ordering::Relaxed);
+        manager.check_backend_health(&mut backend).await;
+        pass.store(false, Ordering::Relaxed);
+        manager.check_backend_health(&mut backend).await;
+        manager.check_backend_health(&mut backend).await;
+        assert!(backend.healthy);
+        manager.check_backend_health(&mut backend).await;
+        assert!(!backend.healthy);
+
+        // Re-admission takes consecutive passes
+        pass.store(true, Ordering::Relaxed);
+        manager.check_backend_health(&mut backend).await;
+        assert!(!backend.healthy);
+        manager.check_backend_health(&mut backend).await;
+        assert!(backend.healthy);
+
+        // Thresholds can change at runtime
+        manager.set_thresholds(1, 0);
+        pass.store(false, Ordering::Relaxed);
+        manager.check_backend_health(&mut backend).await;
+        assert!(!backend.healthy);
+    }
+
+    #[tokio::test]
+    async fn test_passive_ejection_after_consecutive_failures() {
+        let checker = Box::new(MockHealthChecker { should_pass: true });
+        let manager = HealthCheckManager::new(checker).with_passive_failure_threshold(3);
+        let mut backend = create_test_backend("test1", true);
+
+        assert!(!manager.record_passive_failure(backend.addr));
+        assert!(!manager.record_passive_failure(backend.addr));
+        // A success in between resets the count
+        manager.record_passive_success(backend.addr);
+        assert!(!manager.record_passive_failure(backend.addr));
+        assert!(!manager.record_passive_failure(backend.addr));
+        assert!(!manager.is_passively_ejected(backend.addr));
+
+        assert!(manager.record_passive_failure(backend.addr));
+        assert!(manager.is_passively_ejected(backend.addr));
+        // Further failures while ejected do not eject again
+        assert!(!manager.record_passive_failure(backend.addr));
+
+        // The next passing probe re-admits the backend
+        manager.check_backend_health(&mut backend).await;
+        assert!(!manager.is_passively_ejected(backend.addr));
+    }
+
+    #[test]
+    fn test_passive_checking_disabled() {
+        let checker = Box::new(MockHealthChecker { should_pass: true });
+        let manager = HealthCheckManager::new(checker).with_passive_failure_threshold(0);
+        let addr = "127.0.0.1:8080".parse::<SocketAddr>().unwrap();
+
+        for _ in 0..10 {
+            assert!(!manager.record_passive_failure(addr));
+        }
+        assert!(!manager.is_passively_ejected(addr));
+    }
+
     #[test]
     fn test_create_health_checker_mongodb() {
         let backend = create_test_backend("test", true);
-        let checker = create_health_checker(&backend);
+        let checker = create_health_checker(&backend, &CheckOptions::default());
         
         // Verify we get a MongoDB health checker
         assert_eq!(checker.check_timeout(), Duration::from_secs(5));
@@ -214,17 +580,117 @@ mod tests {
             replication_offset: Some(0),
         };
         
-        let checker = create_health_checker(&backend);
+        let checker = create_health_checker(&backend, &CheckOptions::default());
         
         // Verify we get a Redis health checker
         assert_eq!(checker.check_timeout(), Duration::from_secs(3));
     }
+
+    #[test]
+    fn test_create_health_checker_with_overrides() {
+        let backend = create_test_backend("test", true);
+        let overrides = EndpointHealthCheck {
+            timeout_sec: Some(20),
+            ..Default::default()
+        };
+        let checker = create_health_checker_with(&backend, &overrides, &CheckOptions::default());
+        assert_eq!(checker.check_timeout(), Duration::from_secs(20));
+        assert_eq!(checker.check_interval(), Duration::from_secs(10));
+
+        let overrides = EndpointHealthCheck {
+            interval_sec: Some(60),
+            timeout_sec: None,
+            checker: HealthCheckKind::Tcp,
+        };
+        let checker = create_health_checker_with(&backend, &overrides, &CheckOptions::default());
+        assert_eq!(checker.check_timeout(), Duration::from_secs(5));
+        assert_eq!(checker.check_interval(), Duration::from_secs(60));
+    }
+
+    #[tokio::test]
+    async fn test_endpoint_overrides() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut backend = create_test_backend("test1", false);
+        backend.addr = listener.local_addr().unwrap();
+        let other: SocketAddr = "127.0.0.1:27019".parse().unwrap();
+
+        // The default checker fails, the TCP override only needs a listener
+        let checker = Box::new(MockHealthChecker { should_pass: false });
+        let overrides = EndpointHealthCheck {
+            interval_sec: Some(60),
+            timeout_sec: Some(2),
+            checker: HealthCheckKind::Tcp,
+        };
+        let manager = HealthCheckManager::new(checker)
+            .with_endpoint_overrides(HashMap::from([(backend.addr, overrides)]));
+        assert_eq!(manager.endpoint_interval(backend.addr), Some(Duration::from_secs(60)));
+        assert_eq!(manager.endpoint_interval(other), None);
+        assert_eq!(manager.shortest_endpoint_interval(), Some(Duration::from_secs(60)));
+
+        manager.check_backend_health(&mut backend).await;
+        assert!(backend.healthy);
+
+        manager.set_endpoint_overrides(HashMap::new());
+        manager.check_backend_health(&mut backend).await;
+        assert!(!backend.healthy);
+        assert_eq!(manager.shortest_endpoint_interval(), None);
+    }
 }
 
 /// Utility function to create appropriate health checker based on backend type
-pub fn create_health_checker(backend: &Backend) -> Box<dyn HealthChecker> {
+pub fn create_health_checker(backend: &Backend, options: &CheckOptions) -> Box<dyn HealthChecker> {
     match &backend.metadata {
-        BackendMetadata::MongoDB { .. } => Box::new(mongodb::MongoDBHealthChecker::new()),
-        BackendMetadata::Redis { .. } => Box::new(redis::RedisHealthChecker::new()),
+        BackendMetadata::MongoDB { .. } => Box::new(
+            mongodb::MongoDBHealthChecker::new().with_upstream_tls(options.upstream_tls.as_ref()),
+        ),
+        BackendMetadata::Redis { .. } => Box::new(
+            redis::RedisHealthChecker::new()
+                .with_cluster_check(options.redis_sharding == RedisSharding::Cluster)
+                .with_upstream_tls(options.upstream_tls.as_ref())
+                .with_auth(options.redis_auth.as_ref()),
+        ),
+    }
+}
+
+/// Create the health checker for a backend with its endpoint overrides;
+/// unset values fall back to the defaults of the backend type
+pub fn create_health_checker_with(
+    backend: &Backend,
+    overrides: &EndpointHealthCheck,
+    options: &CheckOptions,
+) -> Box<dyn HealthChecker> {
+    let defaults = create_health_checker(backend, options);
+    let tls = options.upstream_tls.as_ref();
+    let check_interval = overrides
+        .interval_sec
+        .map_or_else(|| defaults.check_interval(), Duration::from_secs);
+    let check_timeout = overrides
+        .timeout_sec
+        .map_or_else(|| defaults.check_timeout(), Duration::from_secs);
+
+    match (overrides.checker, &backend.metadata) {
+        (HealthCheckKind::Tcp, _) => {
+            Box::new(tcp::TcpHealthChecker::new(check_interval, check_timeout).with_upstream_tls(tls))
+        }
+        (HealthCheckKind::Protocol, BackendMetadata::MongoDB { .. }) => Box::new(
+            mongodb::MongoDBHealthChecker::with_config(
+                check_interval,
+                check_timeout,
+                3,
+                Duration::from_millis(500),
+            )
+            .with_upstream_tls(tls),
+        ),
+        (HealthCheckKind::Protocol, BackendMetadata::Redis { .. }) => Box::new(
+            redis::RedisHealthChecker::with_config(
+                check_interval,
+                check_timeout,
+                3,
+                Duration::from_millis(300),
+                options.redis_sharding == RedisSharding::Cluster,
+            )
+            .with_upstream_tls(tls)
+            .with_auth(options.redis_auth.as_ref()),
+        ),
     }
 }
@@ -1,17 +1,40 @@
 /// MongoDB mongos health checker
-use super::{HealthChecker, HealthStatus};
-use crate::core::Backend;
+use super::{CheckConnector, HealthChecker, HealthStatus};
+use crate::config::UpstreamTlsConfig;
+use crate::core::upstream::SpareConnections;
+use crate::core::{Backend, BackendMetadata};
+use crate::modes::mongodb::bson::{Document, DocumentBuilder, Value};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::TcpStream;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// OP_REPLY fields between the message header and the documents
+const OP_REPLY_PREFIX_LEN: usize = 20;
+
+/// What a health check learned about a mongos
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MongosInfo {
+    /// Server version reported by buildInfo
+    pub version: Option<String>,
+    /// Newest wire protocol version the mongos speaks
+    pub max_wire_version: Option<i32>,
+}
 
 /// MongoDB health checker implementation
-/// Uses ismaster command to check mongos availability and status
+/// Uses ismaster command to check mongos availability and status, and
+/// buildInfo for the server version
 pub struct MongoDBHealthChecker {
     check_interval: Duration,
     check_timeout: Duration,
     max_retries: u32,
     retry_delay: Duration,
+    /// Latest topology metadata per mongos, until copied into its backend
+    topology: Mutex<HashMap<SocketAddr, MongosInfo>>,
+    /// Idle connections to mongos that checks run over before connecting
+    spares: Option<Arc<SpareConnections>>,
+    connector: CheckConnector,
 }
 
 impl MongoDBHealthChecker {
@@ -21,6 +44,9 @@ impl MongoDBHealthChecker {
             check_timeout: Duration::from_secs(5),
             max_retries: 3,
             retry_delay: Duration::from_millis(500),
+            topology: Mutex::new(HashMap::new()),
+            spares: None,
+            connector: CheckConnector::default(),
         }
     }
     
@@ -30,9 +56,25 @@ impl MongoDBHealthChecker {
             check_timeout,
             max_retries,
             retry_delay,
+            topology: Mutex::new(HashMap::new()),
+            spares: None,
+            connector: CheckConnector::default(),
         }
     }
 
+    /// Connect to mongos over TLS when the proxy does
+    pub fn with_upstream_tls(mut self, tls: Option<&UpstreamTlsConfig>) -> Self {
+        self.connector = CheckConnector::new(tls);
+        self
+    }
+
+    /// Check a mongos over one of its spare connections when it has one,
+    /// handing it back afterwards, and only connect when it has none
+    pub fn with_spare_connections(mut self, spares: Arc<SpareConnections>) -> Self {
+        self.spares = Some(spares);
+        self
+    }
+
     /// Perform comprehensive MongoDB health check with retry mechanism
     async fn mongodb_health_check_with_retry(&self, backend: &Backend) -> HealthStatus {
         for attempt in 0..=self.max_retries {
@@ -61,59 +103,116 @@ impl MongoDBHealthChecker {
     
     /// Implement proper MongoDB Wire Protocol health check using ismaster command
     async fn mongodb_wire_protocol_check(&self, backend: &Backend) -> HealthStatus {
-        let stream = match TcpStream::connect(backend.addr).await {
+        if let Some(status) = self.spare_connection_check(backend).await {
+            return status;
+        }
+
+        let mut stream = match self.connector.connect(backend.addr).await {
             Ok(stream) => stream,
-            Err(e) => {
-                return HealthStatus::Unhealthy {
-                    reason: format!("Connection failed: {e}"),
-                };
-            }
+            Err(reason) => return HealthStatus::Unhealthy { reason },
         };
         
-        let mut stream = stream;
+        match self.check_over(&mut stream, backend).await {
+            Ok((status, _)) => status,
+            Err(reason) => HealthStatus::Unhealthy { reason },
+        }
+    }
+
+    /// Check `backend` over a spare connection, or `None` when it has no
+    /// spare or the spare failed
+    ///
+    /// A failed spare is closed and leaves the decision to a new connection,
+    /// as the mongos may only have dropped an idle connection.
+    async fn spare_connection_check(&self, backend: &Backend) -> Option<HealthStatus> {
+        let spares = self.spares.as_ref()?;
+        let addr = backend.addr.to_string();
+        let (mut stream, opened) = spares.lend(&addr)?;
+        match self.check_over(&mut stream, backend).await {
+            Ok((status, in_step)) => {
+                if in_step {
+                    spares.put_back(&addr, stream, opened);
+                }
+                Some(status)
+            }
+            Err(reason) => {
+                log::debug!("Health check over a spare connection to mongos {addr} failed, connecting: {reason}");
+                None
+            }
+        }
+    }
+
+    /// Run the ismaster and buildInfo checks over `stream`
+    ///
+    /// Fails when the ismaster exchange does; otherwise returns the status
+    /// and whether the stream is still in step for reuse.
+    async fn check_over<S>(&self, stream: &mut S, backend: &Backend) -> Result<(HealthStatus, bool), String>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        // Send ismaster command and check that a mongos answered
+        let body = self.run_command(stream, &self.create_ismaster_command()).await?;
+        let mut info = match self.parse_ismaster_response(&body) {
+            Ok(info) => info,
+            Err(reason) => return Ok((HealthStatus::Unhealthy { reason }, true)),
+        };
         
-        // Create ismaster command in MongoDB Wire Protocol format
-        let ismaster_command = self.create_ismaster_command();
+        // isMaster carries no server version; a failed buildInfo only leaves it unknown
+        let build_info = self.create_query_command(&DocumentBuilder::new().int32("buildinfo", 1).build());
+        let in_step = match self.run_command(stream, &build_info).await {
+            Ok(body) => {
+                info.version = Self::parse_build_info_response(&body);
+                true
+            }
+            Err(reason) => {
+                log::debug!("buildInfo failed for mongos {}: {reason}", backend.addr);
+                false
+            }
+        };
         
-        // Send ismaster command
-        if let Err(e) = stream.write_all(&ismaster_command).await {
-            return HealthStatus::Unhealthy {
-                reason: format!("Failed to send ismaster command: {e}"),
-            };
-        }
+        self.topology.lock().unwrap().insert(backend.addr, info);
+        Ok((HealthStatus::Healthy, in_step))
+    }
+    
+    /// Send an OP_QUERY command and return the body of its reply
+    async fn run_command<S>(&self, stream: &mut S, command: &[u8]) -> Result<Vec<u8>, String>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        stream
+            .write_all(command)
+            .await
+            .map_err(|e| format!("Failed to send command: {e}"))?;
         
         // Read response header (16 bytes)
         let mut header = [0u8; 16];
-        if let Err(e) = stream.read_exact(&mut header).await {
-            return HealthStatus::Unhealthy {
-                reason: format!("Failed to read response header: {e}"),
-            };
-        }
+        stream
+            .read_exact(&mut header)
+            .await
+            .map_err(|e| format!("Failed to read response header: {e}"))?;
         
         // Parse message length from header (first 4 bytes, little-endian)
         let message_length = u32::from_le_bytes([header[0], header[1], header[2], header[3]]) as usize;
         
         if !(16..=48 * 1024 * 1024).contains(&message_length) {
-            return HealthStatus::Unhealthy {
-                reason: format!("Invalid message length: {message_length}"),
-            };
+            return Err(format!("Invalid message length: {message_length}"));
         }
         
         // Read remaining message body
-        let body_length = message_length - 16;
-        let mut body = vec![0u8; body_length];
-        if let Err(e) = stream.read_exact(&mut body).await {
-            return HealthStatus::Unhealthy {
-                reason: format!("Failed to read response body: {e}"),
-            };
-        }
-        
-        // Parse response to check if ismaster succeeded
-        self.parse_ismaster_response(&body)
+        let mut body = vec![0u8; message_length - 16];
+        stream
+            .read_exact(&mut body)
+            .await
+            .map_err(|e| format!("Failed to read response body: {e}"))?;
+        Ok(body)
     }
     
     /// Create MongoDB Wire Protocol ismaster command
     fn create_ismaster_command(&self) -> Vec<u8> {
+        self.create_query_command(&self.create_ismaster_bson())
+    }
+    
+    /// Create an OP_QUERY message running `query` against `admin.$cmd`
+    fn create_query_command(&self, query: &[u8]) -> Vec<u8> {
         // MongoDB Wire Protocol message structure:
         // - Message Length (4 bytes)
         // - Request ID (4 bytes) 
@@ -151,9 +250,8 @@ impl MongoDBHealthChecker {
         // Number to return (1)
         command.extend_from_slice(&1u32.to_le_bytes());
         
-        // Query document: {"ismaster": 1} in BSON format
-        let bson_query = self.create_ismaster_bson();
-        command.extend_from_slice(&bson_query);
+        // Query document
+        command.extend_from_slice(query);
         
         // Update message length
         let total_length = command.len() as u32;
@@ -188,39 +286,55 @@ impl MongoDBHealthChecker {
         bson
     }
     
-    /// Parse ismaster response to determine health status
-    fn parse_ismaster_response(&self, body: &[u8]) -> HealthStatus {
-        if body.len() < 4 {
-            return HealthStatus::Unhealthy {
-                reason: "Response body too short".to_string(),
-            };
-        }
-        
-        // For a comprehensive implementation, we would parse the full BSON response
-        // For now, we check if we received a valid BSON document structure
-        let doc_length = u32::from_le_bytes([body[0], body[1], body[2], body[3]]) as usize;
-        
-        if doc_length > body.len() || doc_length < 5 {
-            return HealthStatus::Unhealthy {
-                reason: "Invalid BSON document length".to_string(),
-            };
+    /// Parse an ismaster reply, requiring it to come from a mongos
+    fn parse_ismaster_response(&self, body: &[u8]) -> Result<MongosInfo, String> {
+        let reply = Self::reply_document(body)?;
+        
+        let ok = match reply.get("ok") {
+            Some(Value::Double(ok)) => ok == 1.0,
+            Some(Value::Int32(ok)) => ok == 1,
+            Some(Value::Int64(ok)) => ok == 1,
+            _ => false,
+        };
+        if !ok {
+            let errmsg = reply.get_str("errmsg").unwrap_or("no error message");
+            return Err(format!("ismaster failed: {errmsg}"));
         }
         
-        // Check for document terminator
-        if body.get(doc_length - 1) != Some(&0x00) {
-            return HealthStatus::Unhealthy {
-                reason: "Invalid BSON document terminator".to_string(),
-            };
+        // Only a mongos answers with msg: "isdbgrid"
+        if reply.get_str("msg") != Some("isdbgrid") {
+            return Err(match reply.get_str("setName") {
+                Some(set_name) => format!("Not a mongos: member of replica set {set_name}"),
+                None => "Not a mongos: ismaster reply lacks msg \"isdbgrid\"".to_string(),
+            });
         }
         
-        // If we got a valid BSON response, consider the mongos healthy
-        // In a full implementation, we would parse the response to check:
-        // - ismaster: true/false
-        // - msg: "isdbgrid" (indicates this is a mongos)
-        // - hosts: array of shard hosts
-        // - setName: replica set name (if applicable)
-        
-        HealthStatus::Healthy
+        let max_wire_version = match reply.get("maxWireVersion") {
+            Some(Value::Int32(version)) => Some(version),
+            Some(Value::Int64(version)) => i32::try_from(version).ok(),
+            _ => None,
+        };
+        Ok(MongosInfo {
+            version: None,
+            max_wire_version,
+        })
+    }
+    
+    /// Server version from a buildInfo reply
+    fn parse_build_info_response(body: &[u8]) -> Option<String> {
+        Self::reply_document(body)
+            .ok()?
+            .get_str("version")
+            .map(str::to_string)
+    }
+    
+    /// First document of an OP_REPLY body
+    fn reply_document(body: &[u8]) -> Result<Document<'_>, String> {
+        // responseFlags, cursorID, startingFrom and numberReturned precede the documents
+        let documents = body
+            .get(OP_REPLY_PREFIX_LEN..)
+            .ok_or_else(|| "Response body too short".to_string())?;
+        Document::from_bytes(documents).ok_or_else(|| "Invalid BSON document in reply".to_string())
     }
 
     /// Future enhancement: Implement proper MongoDB Wire Protocol health check
@@ -252,6 +366,21 @@ impl HealthChecker for MongoDBHealthChecker {
     fn check_timeout(&self) -> Duration {
         self.check_timeout
     }
+
+    fn update_metadata(&self, backend: &mut Backend) {
+        let Some(info) = self.topology.lock().unwrap().remove(&backend.addr) else {
+            return;
+        };
+        if let BackendMetadata::MongoDB {
+            version,
+            max_wire_version,
+            ..
+        } = &mut backend.metadata
+        {
+            *version = info.version;
+            *max_wire_version = info.max_wire_version;
+        }
+    }
 }
 
 impl Default for MongoDBHealthChecker {
@@ -289,4 +418,200 @@ mod tests {
             _ => panic!("Expected unhealthy status for invalid address"),
         }
     }
+
+    /// OP_REPLY body carrying `document`
+    fn reply_body(document: Vec<u8>) -> Vec<u8> {
+        let mut body = vec![0u8; OP_REPLY_PREFIX_LEN];
+        body.extend_from_slice(&document);
+        body
+    }
+
+    #[test]
+    fn test_parse_ismaster_response() {
+        let checker = MongoDBHealthChecker::new();
+        let mongos = DocumentBuilder::new()
+            .boolean("ismaster", true)
+            .string("msg", "isdbgrid")
+            .int32("maxWireVersion", 21)
+            .int32("ok", 1)
+            .build();
+        assert_eq!(
+            checker.parse_ismaster_response(&reply_body(mongos)),
+            Ok(MongosInfo {
+                version: None,
+                max_wire_version: Some(21),
+            })
+        );
+
+        let replica_set_member = DocumentBuilder::new()
+            .boolean("ismaster", true)
+            .string("setName", "rs0")
+            .int32("ok", 1)
+            .build();
+        let reason = checker
+            .parse_ismaster_response(&reply_body(replica_set_member))
+            .unwrap_err();
+        assert!(reason.contains("replica set rs0"), "{reason}");
+
+        let failed = DocumentBuilder::new()
+            .int32("ok", 0)
+            .string("errmsg", "not authorized")
+            .build();
+        let reason = checker.parse_ismaster_response(&reply_body(failed)).unwrap_err();
+        assert!(reason.contains("not authorized"), "{reason}");
+
+        assert!(checker.parse_ismaster_response(&[0u8; 8]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_mongos_topology_metadata() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let replies = [
+                DocumentBuilder::new()
+                    .string("msg", "isdbgrid")
+                    .int32("maxWireVersion", 21)
+                    .int32("ok", 1)
+                    .build(),
+                DocumentBuilder::new().string("version", "7.0.2").int32("ok", 1).build(),
+            ];
+            for document in replies {
+                let mut header = [0u8; 16];
+                stream.read_exact(&mut header).await.unwrap();
+                let len = u32::from_le_bytes(header[..4].try_into().unwrap()) as usize;
+                let mut request = vec![0u8; len - 16];
+                stream.read_exact(&mut request).await.unwrap();
+
+                let body = reply_body(document);
+                let mut reply = ((16 + body.len()) as i32).to_le_bytes().to_vec();
+                reply.extend_from_slice(&[0u8; 8]);
+                reply.extend_from_slice(&1i32.to_le_bytes());
+                reply.extend_from_slice(&body);
+                stream.write_all(&reply).await.unwrap();
+            }
+        });
+
+        let checker = MongoDBHealthChecker::new();
+        let mut backend = Backend::new_mongodb("mongos-0".to_string(), addr);
+        assert_eq!(checker.check_health(&backend).await, HealthStatus::Healthy);
+
+        checker.update_metadata(&mut backend);
+        match backend.metadata {
+            BackendMetadata::MongoDB {
+                version,
+                max_wire_version,
+                ..
+            } => {
+                assert_eq!(version.as_deref(), Some("7.0.2"));
+                assert_eq!(max_wire_version, Some(21));
+            }
+            _ => panic!("Expected MongoDB metadata"),
+        }
+    }
+
+    /// A mongos answering every command, closing the first `dropped`
+    /// connections at once, and the number of connections it accepted
+    async fn counting_mongos(dropped: usize) -> (SocketAddr, Arc<std::sync::atomic::AtomicUsize>) {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let accepted = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&accepted);
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                if counter.fetch_add(1, Ordering::SeqCst) < dropped {
+                    continue;
+                }
+                tokio::spawn(async move {
+                    let mut header = [0u8; 16];
+                    while stream.read_exact(&mut header).await.is_ok() {
+                        let len = u32::from_le_bytes(header[..4].try_into().unwrap()) as usize;
+                        let mut request = vec![0u8; len - 16];
+                        stream.read_exact(&mut request).await.unwrap();
+
+                        let body = reply_body(
+                            DocumentBuilder::new()
+                                .string("msg", "isdbgrid")
+                                .string("version", "7.0.2")
+                                .int32("ok", 1)
+                                .build(),
+                        );
+                        let mut reply = ((16 + body.len()) as i32).to_le_bytes().to_vec();
+                        reply.extend_from_slice(&[0u8; 8]);
+                        reply.extend_from_slice(&1i32.to_le_bytes());
+                        reply.extend_from_slice(&body);
+                        if stream.write_all(&reply).await.is_err() {
+                            return;
+                        }
+                    }
+                });
+            }
+        });
+        (addr, accepted)
+    }
+
+    #[tokio::test]
+    async fn test_checks_run_over_spare_connections() {
+        use pingora_core::connectors::TransportConnector;
+        use pingora_core::upstreams::peer::BasicPeer;
+        use std::sync::atomic::Ordering;
+
+        let (addr, accepted) = counting_mongos(0).await;
+        let spares = Arc::new(SpareConnections::new(TransportConnector::new(None), 1, Duration::from_secs(60)));
+        spares.fill(&BasicPeer::new(&addr.to_string())).await.unwrap();
+        let checker = MongoDBHealthChecker::new().with_spare_connections(Arc::clone(&spares));
+        let backend = Backend::new_mongodb("mongos-0".to_string(), addr);
+
+        // The spare serves every check and stays available to clients
+        for _ in 0..3 {
+            assert_eq!(checker.check_health(&backend).await, HealthStatus::Healthy);
+        }
+        assert_eq!(accepted.load(Ordering::SeqCst), 1);
+        assert_eq!(spares.count(&addr.to_string()), 1);
+
+        // Without spares the check connects
+        let (addr, accepted) = counting_mongos(0).await;
+        let backend = Backend::new_mongodb("mongos-1".to_string(), addr);
+        assert_eq!(checker.check_health(&backend).await, HealthStatus::Healthy);
+        assert_eq!(accepted.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_failed_spare_falls_back_to_new_connection() {
+        use pingora_core::connectors::TransportConnector;
+        use pingora_core::upstreams::peer::BasicPeer;
+        use std::sync::atomic::Ordering;
+
+        // The mongos has closed the spare
+        let (addr, accepted) = counting_mongos(1).await;
+        let spares = Arc::new(SpareConnections::new(TransportConnector::new(None), 1, Duration::from_secs(60)));
+        spares.fill(&BasicPeer::new(&addr.to_string())).await.unwrap();
+        let checker = MongoDBHealthChecker::new().with_spare_connections(Arc::clone(&spares));
+        let backend = Backend::new_mongodb("mongos-0".to_string(), addr);
+
+        assert_eq!(checker.check_health(&backend).await, HealthStatus::Healthy);
+        assert_eq!(accepted.load(Ordering::SeqCst), 2);
+        assert_eq!(spares.count(&addr.to_string()), 0);
+    }
+
+    #[tokio::test]
+    async fn test_check_over_upstream_tls() {
+        use crate::health::connect::tests::{tls_config, tls_only_backend};
+
+        // A plaintext ismaster never reaches a TLS-only mongos
+        let (addr, handshake) = tls_only_backend().await;
+        let checker = MongoDBHealthChecker::with_config(
+            Duration::from_secs(10),
+            Duration::from_secs(5),
+            0,
+            Duration::ZERO,
+        )
+        .with_upstream_tls(Some(&tls_config()));
+        let backend = Backend::new_mongodb("mongos-0".to_string(), addr);
+        assert!(!checker.check_health(&backend).await.is_healthy());
+        assert!(handshake.await.unwrap());
+    }
 }
@@ -0,0 +1,286 @@
+/// Outlier detection from live traffic
+///
+/// Each backend's error rate and latency are tracked as exponentially
+/// weighted moving averages. A backend whose error rate crosses the threshold,
+/// or whose latency is a multiple of the other backends' average, is ejected
+/// from selection for a cooldown period. It is then re-admitted with a small
+/// share of traffic that grows linearly back to its full share.
+use crate::config::OutlierDetectionConfig;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Weight of the newest sample in the moving averages
+const EWMA_ALPHA: f64 = 0.1;
+
+/// Selection state of a backend
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum OutlierState {
+    Active,
+    Ejected { until: Instant },
+    RampingUp { since: Instant },
+}
+
+/// Moving averages and state for one backend
+#[derive(Debug)]
+struct OutlierStats {
+    requests: u64,
+    error_ewma: f64,
+    /// Latency EWMA in milliseconds, `None` until a latency was sampled
+    latency_ewma_ms: Option<f64>,
+    state: OutlierState,
+    /// Admission counter used to sample traffic while ramping up
+    ramp_counter: u64,
+}
+
+impl Default for OutlierStats {
+    fn default() -> Self {
+        Self {
+            requests: 0,
+            error_ewma: 0.0,
+            latency_ewma_ms: None,
+            state: OutlierState::Active,
+            ramp_counter: 0,
+        }
+    }
+}
+
+impl OutlierStats {
+    fn record(&mut self, success: bool, latency: Option<Duration>) {
+        let error = if success { 0.0 } else { 1.0 };
+        self.error_ewma = if self.requests == 0 {
+            error
+        } else {
+            EWMA_ALPHA * error + (1.0 - EWMA_ALPHA) * self.error_ewma
+        };
+
+        if let Some(latency) = latency {
+            let latency_ms = latency.as_secs_f64() * 1000.0;
+            self.latency_ewma_ms = Some(match self.latency_ewma_ms {
+                Some(ewma) => EWMA_ALPHA * latency_ms + (1.0 - EWMA_ALPHA) * ewma,
+                None => latency_ms,
+            });
+        }
+
+        self.requests += 1;
+    }
+}
+
+/// Tracks live-traffic outcomes per backend and ejects outliers
+pub struct OutlierDetector {
+    config: OutlierDetectionConfig,
+    backends: Mutex<HashMap<SocketAddr, OutlierStats>>,
+}
+
+impl OutlierDetector {
+    pub fn new(config: OutlierDetectionConfig) -> Self {
+        Self {
+            config,
+            backends: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Record the outcome of a request to `addr`
+    ///
+    /// Returns `true` when this outcome ejects the backend.
+    pub fn record(&self, addr: SocketAddr, success: bool, latency: Option<Duration>) -> bool {
+        let mut backends = self.backends.lock().unwrap();
+        let stats = backends.entry(addr).or_default();
+        stats.record(success, latency);
+
+        // Backends ramping up are judged again once they have enough samples
+        if matches!(stats.state, OutlierState::Ejected { .. })
+            || stats.requests < self.config.min_requests
+        {
+            return false;
+        }
+
+        let error_outlier = stats.error_ewma > self.config.error_rate_threshold;
+        let own_latency = stats.latency_ewma_ms;
+        let latency_outlier = match (own_latency, Self::peer_latency_ms(&backends, addr, self.config.min_requests)) {
+            (Some(own), Some(peers)) => own > peers * self.config.latency_multiplier,
+            _ => false,
+        };
+        if !error_outlier && !latency_outlier {
+            return false;
+        }
+
+        // Never eject more than the configured share of backends
+        let now = Instant::now();
+        let ejected = backends
+            .values()
+            .filter(|stats| matches!(stats.state, OutlierState::Ejected { until } if until > now))
+            .count();
+        let max_ejected = backends.len() * self.config.max_ejection_percent as usize / 100;
+        if ejected >= max_ejected {
+            return false;
+        }
+
+        let stats = backends.get_mut(&addr).expect("backend was just recorded");
+        log::warn!(
+            "Ejecting outlier backend {addr}: error rate {:.2}, latency {:.1}ms",
+            stats.error_ewma,
+            stats.latency_ewma_ms.unwrap_or_default()
+        );
+        *stats = OutlierStats {
+            state: OutlierState::Ejected {
+                until: now + Duration::from_secs(self.config.cooldown_sec),
+            },
+            ..OutlierStats::default()
+        };
+        true
+    }
+
+    /// Whether a request may be sent to `addr`
+    ///
+    /// Ejected backends are refused until their cooldown ends. While ramping
+    /// up, a growing share of requests is admitted.
+    pub fn admits(&self, addr: SocketAddr) -> bool {
+        let mut backends = self.backends.lock().unwrap();
+        let Some(stats) = backends.get_mut(&addr) else {
+            return true;
+        };
+
+        let now = Instant::now();
+        if let OutlierState::Ejected { until } = stats.state {
+            if now < until {
+                return false;
+            }
+            log::info!("Re-admitting backend {addr} after outlier cooldown");
+            stats.state = OutlierState::RampingUp { since: now };
+        }
+
+        let OutlierState::RampingUp { since } = stats.state else {
+            return true;
+        };
+
+        let ramp_up = Duration::from_secs(self.config.ramp_up_sec);
+        let elapsed = now.duration_since(since);
+        if elapsed >= ramp_up {
+            stats.state = OutlierState::Active;
+            return true;
+        }
+
+        let start = self.config.ramp_up_percent as f64;
+        let percent = start + (100.0 - start) * elapsed.as_secs_f64() / ramp_up.as_secs_f64();
+        stats.ramp_counter += 1;
+        ((stats.ramp_counter * percent.round() as u64) / 100)
+            > (((stats.ramp_counter - 1) * percent.round() as u64) / 100)
+    }
+
+    /// Whether `addr` is currently ejected
+    pub fn is_ejected(&self, addr: SocketAddr) -> bool {
+        let backends = self.backends.lock().unwrap();
+        backends
+            .get(&addr)
+            .is_some_and(|stats| matches!(stats.state, OutlierState::Ejected { until } if until > Instant::now()))
+    }
+
+    /// Average latency EWMA of the backends other than `addr`
+    fn peer_latency_ms(
+        backends: &HashMap<SocketAddr, OutlierStats>,
+        addr: SocketAddr,
+        min_requests: u64,
+    ) -> Option<f64> {
+        let latencies: Vec<f64> = backends
+            .iter()
+            .filter(|(peer, stats)| {
+                **peer != addr
+                    && stats.requests >= min_requests
+                    && matches!(stats.state, OutlierState::Active)
+            })
+            .filter_map(|(_, stats)| stats.latency_ewma_ms)
+            .collect();
+
+        if latencies.is_empty() {
+            None
+        } else {
+            Some(latencies.iter().sum::<f64>() / latencies.len() as f64)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(port: u16) -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], port))
+    }
+
+    fn config() -> OutlierDetectionConfig {
+        OutlierDetectionConfig {
+            min_requests: 5,
+            cooldown_sec: 60,
+            ..OutlierDetectionConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_error_rate_outlier_is_ejected() {
+        let detector = OutlierDetector::new(config());
+        for _ in 0..10 {
+            detector.record(addr(1), true, Some(Duration::from_millis(5)));
+        }
+
+        // A burst of errors pushes the error EWMA over the threshold
+        let mut ejected = false;
+        for _ in 0..10 {
+            ejected |= detector.record(addr(2), false, None);
+        }
+        assert!(ejected);
+        assert!(detector.is_ejected(addr(2)));
+        assert!(!detector.admits(addr(2)));
+        assert!(detector.admits(addr(1)));
+    }
+
+    #[test]
+    fn test_latency_outlier_is_ejected() {
+        let detector = OutlierDetector::new(config());
+        for _ in 0..10 {
+            detector.record(addr(1), true, Some(Duration::from_millis(5)));
+            detector.record(addr(2), true, Some(Duration::from_millis(6)));
+        }
+
+        let mut ejected = false;
+        for _ in 0..10 {
+            ejected |= detector.record(addr(3), true, Some(Duration::from_millis(100)));
+        }
+        assert!(ejected);
+        assert!(detector.is_ejected(addr(3)));
+        assert!(!detector.is_ejected(addr(1)));
+    }
+
+    #[test]
+    fn test_max_ejection_percent() {
+        let detector = OutlierDetector::new(config());
+        for _ in 0..10 {
+            detector.record(addr(1), false, None);
+            detector.record(addr(2), false, None);
+        }
+
+        // With two backends at most one may be ejected at a time
+        assert!(detector.is_ejected(addr(1)) ^ detector.is_ejected(addr(2)));
+    }
+
+    #[test]
+    fn test_ramp_up_after_cooldown() {
+        let detector = OutlierDetector::new(OutlierDetectionConfig {
+            min_requests: 1,
+            cooldown_sec: 0,
+            ramp_up_percent: 10,
+            ramp_up_sec: 3600,
+            max_ejection_percent: 100,
+            ..OutlierDetectionConfig::default()
+        });
+        assert!(detector.record(addr(1), false, None));
+
+        // The zero cooldown has passed; about 10% of requests are admitted
+        let admitted = (0..100).filter(|_| detector.admits(addr(1))).count();
+        assert!((10..=11).contains(&admitted), "admitted {admitted}");
+
+        // Unknown backends are always admitted
+        assert!(detector.admits(addr(2)));
+    }
+}
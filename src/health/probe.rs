@@ -0,0 +1,240 @@
+/// One-off backend probes for `puerta validate --probe`
+///
+/// Each configured backend gets a TCP connect followed by the same health
+/// check the proxy runs, endpoint overrides included, over the upstream
+/// TLS and with the credentials of its proxy. The report says
+/// whether it is reachable and what role it plays: a mongos and its
+/// version, or a Redis master and the slots it serves versus a replica.
+use super::{create_health_checker, create_health_checker_with, CheckOptions, HealthStatus};
+use crate::config::Endpoint;
+use crate::core::{Backend, BackendMetadata};
+use std::time::{Duration, Instant};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+
+/// Outcome of probing one backend
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProbeReport {
+    /// Endpoint as configured
+    pub endpoint: String,
+    /// A TCP connection could be opened
+    pub reachable: bool,
+    /// Role found by the protocol check, when it passed
+    pub role: Option<String>,
+    /// Why the backend failed the probe
+    pub error: Option<String>,
+    /// Time taken by the protocol check
+    pub latency: Duration,
+}
+
+impl ProbeReport {
+    pub fn passed(&self) -> bool {
+        self.error.is_none()
+    }
+
+    fn failed(endpoint: &str, reachable: bool, error: String) -> Self {
+        Self {
+            endpoint: endpoint.to_string(),
+            reachable,
+            role: None,
+            error: Some(error),
+            latency: Duration::ZERO,
+        }
+    }
+}
+
+/// Probe every endpoint of `mode` (`mongodb` or `redis`) concurrently,
+/// reporting in the order given
+pub async fn probe_all(mode: &str, endpoints: &[Endpoint], options: &CheckOptions) -> Vec<ProbeReport> {
+    futures::future::join_all(endpoints.iter().map(|endpoint| probe(mode, endpoint, options))).await
+}
+
+/// Probe one endpoint, reaching it as the proxy would with `options`
+pub async fn probe(mode: &str, endpoint: &Endpoint, options: &CheckOptions) -> ProbeReport {
+    let endpoint_health_check = endpoint.health_check.as_ref();
+    let endpoint = endpoint.addr.as_str();
+    let addr = match tokio::net::lookup_host(endpoint)
+        .await
+        .map(|mut addrs| addrs.next())
+    {
+        Ok(Some(addr)) => addr,
+        Ok(None) => return ProbeReport::failed(endpoint, false, "no address found".to_string()),
+        Err(e) => return ProbeReport::failed(endpoint, false, format!("cannot resolve: {e}")),
+    };
+    let mut backend = match mode {
+        "redis" => Backend::new_redis(endpoint.to_string(), addr, String::new()),
+        _ => Backend::new_mongodb(endpoint.to_string(), addr),
+    };
+    let checker = match endpoint_health_check {
+        Some(overrides) => create_health_checker_with(&backend, overrides, options),
+        None => create_health_checker(&backend, options),
+    };
+
+    match timeout(checker.check_timeout(), TcpStream::connect(addr)).await {
+        Ok(Ok(_)) => {}
+        Ok(Err(e)) => return ProbeReport::failed(endpoint, false, e.to_string()),
+        Err(_) => return ProbeReport::failed(endpoint, false, "connection timed out".to_string()),
+    }
+
+    let started = Instant::now();
+    let status = timeout(checker.check_timeout(), checker.check_health(&backend))
+        .await
+        .unwrap_or(HealthStatus::Timeout);
+    let latency = started.elapsed();
+    checker.update_metadata(&mut backend);
+
+    if !status.is_healthy() {
+        return ProbeReport {
+            latency,
+            ..ProbeReport::failed(endpoint, true, status.to_string())
+        };
+    }
+    ProbeReport {
+        endpoint: endpoint.to_string(),
+        reachable: true,
+        role: Some(describe_role(&backend.metadata)),
+        error: None,
+        latency,
+    }
+}
+
+/// Role of a backend from the metadata its health check recorded
+fn describe_role(metadata: &BackendMetadata) -> String {
+    match metadata {
+        BackendMetadata::MongoDB {
+            version: Some(version),
+            ..
+        } => format!("mongos {version}"),
+        BackendMetadata::MongoDB { .. } => "mongos".to_string(),
+        // No CLUSTER NODES reply, as with TCP checks
+        BackendMetadata::Redis { node_id, .. } if node_id.is_empty() => "redis".to_string(),
+        BackendMetadata::Redis {
+            slot_ranges,
+            is_master: true,
+            ..
+        } => {
+            let slots: usize = slot_ranges
+                .iter()
+                .map(|(start, end)| (end - start) as usize + 1)
+                .sum();
+            format!("master, {slots} slots")
+        }
+        BackendMetadata::Redis { .. } => "replica".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn test_describe_role() {
+        let master = BackendMetadata::Redis {
+            node_id: "07c37dfeb235213a872192d90877d0cd55635b91".to_string(),
+            slot_ranges: vec![(0, 5460), (10923, 10923)],
+            is_master: true,
+            replication_offset: None,
+        };
+        assert_eq!(describe_role(&master), "master, 5462 slots");
+
+        let mongos = BackendMetadata::MongoDB {
+            version: Some("7.0.2".to_string()),
+            max_wire_version: Some(21),
+            is_primary: false,
+            connection_count: 0,
+        };
+        assert_eq!(describe_role(&mongos), "mongos 7.0.2");
+    }
+
+    #[tokio::test]
+    async fn test_probe_unreachable_and_failing() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let closed = listener.local_addr().unwrap().to_string();
+        drop(listener);
+        let report = probe("redis", &Endpoint::new(closed, 1), &CheckOptions::default()).await;
+        assert!(!report.reachable);
+        assert!(!report.passed());
+
+        // Accepts connections but never answers PING
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let silent = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let mut streams = Vec::new();
+            while let Ok((stream, _)) = listener.accept().await {
+                streams.push(stream);
+            }
+        });
+        let reports = probe_all(
+            "redis",
+            &[
+                Endpoint::new(silent.clone(), 1),
+                Endpoint::new("not a host", 1),
+            ],
+            &CheckOptions::default(),
+        )
+        .await;
+        assert_eq!(reports[0].endpoint, silent);
+        assert!(reports[0].reachable);
+        assert!(!reports[0].passed());
+        assert!(!reports[1].reachable);
+
+        // A TCP check passes without speaking the protocol
+        let tcp_only = Endpoint {
+            health_check: Some(crate::config::EndpointHealthCheck {
+                interval_sec: None,
+                timeout_sec: None,
+                checker: crate::config::HealthCheckKind::Tcp,
+            }),
+            ..Endpoint::new(silent, 1)
+        };
+        let report = probe("redis", &tcp_only, &CheckOptions::default()).await;
+        assert!(report.passed());
+        assert_eq!(report.role.as_deref(), Some("redis"));
+    }
+
+    #[tokio::test]
+    async fn test_probe_with_proxy_options() {
+        // A standalone server requiring a password and refusing CLUSTER NODES
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut authenticated = false;
+                    let mut request = [0u8; 64];
+                    while let Ok(n) = stream.read(&mut request).await {
+                        let reply: &[u8] = match &request[..n] {
+                            [] => return,
+                            b"*2\r\n$4\r\nAUTH\r\n$6\r\nsecret\r\n" => {
+                                authenticated = true;
+                                b"+OK\r\n"
+                            }
+                            _ if !authenticated => b"-NOAUTH Authentication required.\r\n",
+                            b"*1\r\n$4\r\nPING\r\n" => b"+PONG\r\n",
+                            _ => b"-ERR This instance has cluster support disabled\r\n",
+                        };
+                        stream.write_all(reply).await.unwrap();
+                    }
+                });
+            }
+        });
+
+        let endpoint = Endpoint::new(addr, 1);
+        assert!(!probe("redis", &endpoint, &CheckOptions::default()).await.passed());
+
+        let options = CheckOptions {
+            redis_sharding: crate::config::RedisSharding::Standalone,
+            redis_auth: Some(crate::config::RedisAuthConfig {
+                username: None,
+                password: "secret".to_string(),
+                password_file: None,
+            }),
+            ..Default::default()
+        };
+        let report = probe("redis", &endpoint, &options).await;
+        assert!(report.passed(), "{report:?}");
+        assert_eq!(report.role.as_deref(), Some("redis"));
+    }
+}
@@ -1,9 +1,29 @@
 /// Redis cluster node health checker
-use super::{HealthChecker, HealthStatus};
-use crate::core::Backend;
+use super::{CheckConnector, HealthChecker, HealthStatus};
+use crate::config::{RedisAuthConfig, UpstreamTlsConfig};
+use crate::core::{Backend, BackendMetadata};
+use crate::modes::redis::auth;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Mutex;
 use std::time::Duration;
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
-use tokio::net::TcpStream;
+use crate::modes::redis::resp::{RespParser, RespValue};
+use bytes::BytesMut;
+use pingora_core::protocols::Stream;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+/// Largest reply accepted from a node; a CLUSTER NODES reply of a full
+/// size cluster takes a few hundred kilobytes
+const MAX_REPLY_SIZE: usize = 16 * 1024 * 1024;
+
+/// What a node reports about itself in CLUSTER NODES
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClusterNodeInfo {
+    pub node_id: String,
+    pub is_master: bool,
+    /// Slots the node serves, `(start, end)` inclusive
+    pub slot_ranges: Vec<(u16, u16)>,
+}
 
 /// Redis health checker implementation
 /// Uses PING command and CLUSTER NODES for comprehensive health checking
@@ -13,6 +33,11 @@ pub struct RedisHealthChecker {
     max_retries: u32,
     retry_delay: Duration,
     enable_cluster_check: bool,
+    /// Latest CLUSTER NODES view of each node, until copied into its backend
+    cluster_nodes: Mutex<HashMap<SocketAddr, ClusterNodeInfo>>,
+    /// Credentials nodes require before answering
+    auth: Option<RedisAuthConfig>,
+    connector: CheckConnector,
 }
 
 impl RedisHealthChecker {
@@ -23,6 +48,9 @@ impl RedisHealthChecker {
             max_retries: 3,
             retry_delay: Duration::from_millis(300),
             enable_cluster_check: true,
+            cluster_nodes: Mutex::new(HashMap::new()),
+            auth: None,
+            connector: CheckConnector::default(),
         }
     }
     
@@ -39,44 +67,76 @@ impl RedisHealthChecker {
             max_retries,
             retry_delay,
             enable_cluster_check,
+            cluster_nodes: Mutex::new(HashMap::new()),
+            auth: None,
+            connector: CheckConnector::default(),
+        }
+    }
+
+    /// Ask nodes for `CLUSTER NODES` after PING; standalone servers, which
+    /// refuse the command, are checked with PING alone
+    pub fn with_cluster_check(mut self, enabled: bool) -> Self {
+        self.enable_cluster_check = enabled;
+        self
+    }
+
+    /// Check nodes over the TLS the proxy connects to them with
+    pub fn with_upstream_tls(mut self, tls: Option<&UpstreamTlsConfig>) -> Self {
+        self.connector = CheckConnector::new(tls);
+        self
+    }
+
+    /// Authenticate check connections, for clusters with requirepass or ACLs
+    pub fn with_auth(mut self, auth: Option<&RedisAuthConfig>) -> Self {
+        self.auth = auth.cloned();
+        self
+    }
+
+    /// Connect to a node, authenticating when it requires credentials
+    async fn connect(&self, backend: &Backend) -> Result<Stream, String> {
+        let mut stream = self.connector.connect(backend.addr).await?;
+        if let Some(auth) = &self.auth {
+            auth::authenticate(&mut stream, auth).await.map_err(|e| e.to_string())?;
+        }
+        Ok(stream)
+    }
+
+    /// Read one reply, refusing replies larger than `MAX_REPLY_SIZE` as
+    /// soon as their length is known
+    async fn read_reply(stream: &mut Stream) -> Result<RespValue, String> {
+        let mut parser = RespParser::new().with_max_frame_size(MAX_REPLY_SIZE);
+        let mut buf = BytesMut::with_capacity(4096);
+        loop {
+            if let Some((reply, _)) = parser.decode_frame(&mut buf).map_err(|e| e.to_string())? {
+                return Ok(reply);
+            }
+            if stream.read_buf(&mut buf).await.map_err(|e| e.to_string())? == 0 {
+                return Err("connection closed".to_string());
+            }
         }
     }
 
     /// Perform Redis PING health check
     async fn redis_ping_check(&self, backend: &Backend) -> HealthStatus {
-        let stream = match TcpStream::connect(backend.addr).await {
+        let mut stream = match self.connect(backend).await {
             Ok(stream) => stream,
-            Err(e) => {
-                return HealthStatus::Unhealthy {
-                    reason: format!("Connection failed: {}", e),
-                };
-            }
+            Err(reason) => return HealthStatus::Unhealthy { reason },
         };
 
-        let (reader, mut writer) = stream.into_split();
-        let mut buf_reader = BufReader::new(reader);
-
         // Send PING command in RESP format
         let ping_command = "*1\r\n$4\r\nPING\r\n";
 
-        if let Err(e) = writer.write_all(ping_command.as_bytes()).await {
+        if let Err(e) = stream.write_all(ping_command.as_bytes()).await {
             return HealthStatus::Unhealthy {
                 reason: format!("Failed to send PING: {}", e),
             };
         }
 
-        // Read response
-        let mut response = String::new();
-        match buf_reader.read_line(&mut response).await {
-            Ok(_) => {
-                if response.trim() == "+PONG" {
-                    HealthStatus::Healthy
-                } else {
-                    HealthStatus::Unhealthy {
-                        reason: format!("Unexpected PING response: {}", response.trim()),
-                    }
-                }
-            }
+        match Self::read_reply(&mut stream).await {
+            Ok(RespValue::SimpleString(pong)) if pong == "PONG" => HealthStatus::Healthy,
+            Ok(reply) => HealthStatus::Unhealthy {
+                reason: format!("Unexpected PING response: {reply:?}"),
+            },
             Err(e) => HealthStatus::Unhealthy {
                 reason: format!("Failed to read PING response: {}", e),
             },
@@ -84,58 +144,46 @@ impl RedisHealthChecker {
     }
 
     /// Perform Redis CLUSTER NODES check to verify cluster membership
-    #[allow(dead_code)]
     async fn redis_cluster_check(&self, backend: &Backend) -> HealthStatus {
-        let stream = match TcpStream::connect(backend.addr).await {
+        let mut stream = match self.connect(backend).await {
             Ok(stream) => stream,
-            Err(e) => {
-                return HealthStatus::Unhealthy {
-                    reason: format!("Connection failed: {}", e),
-                };
-            }
+            Err(reason) => return HealthStatus::Unhealthy { reason },
         };
 
-        let (reader, mut writer) = stream.into_split();
-        let mut buf_reader = BufReader::new(reader);
-
         // Send CLUSTER NODES command in RESP format
         let cluster_command = "*2\r\n$7\r\nCLUSTER\r\n$5\r\nNODES\r\n";
 
-        if let Err(e) = writer.write_all(cluster_command.as_bytes()).await {
+        if let Err(e) = stream.write_all(cluster_command.as_bytes()).await {
             return HealthStatus::Unhealthy {
                 reason: format!("Failed to send CLUSTER NODES: {}", e),
             };
         }
 
-        // Read response (bulk string format)
-        let mut response = String::new();
-        match buf_reader.read_line(&mut response).await {
-            Ok(_) => {
-                if response.starts_with('$') {
-                    // Read the actual cluster nodes data
-                    let mut nodes_data = String::new();
-                    if let Ok(_) = buf_reader.read_line(&mut nodes_data).await {
-                        // Parse cluster nodes to verify this node is part of cluster
-                        // TODO: Implement proper cluster nodes parsing
-                        HealthStatus::Healthy
-                    } else {
-                        HealthStatus::Unhealthy {
-                            reason: "Failed to read cluster nodes data".to_string(),
-                        }
-                    }
-                } else if response.trim().starts_with("-ERR") {
-                    HealthStatus::Unhealthy {
-                        reason: format!("Cluster error: {}", response.trim()),
-                    }
-                } else {
-                    HealthStatus::Unhealthy {
-                        reason: format!("Unexpected cluster response: {}", response.trim()),
-                    }
-                }
+        let nodes_data = match Self::read_reply(&mut stream).await {
+            Ok(RespValue::BulkString(Some(nodes_data))) => nodes_data,
+            Ok(RespValue::Error(e)) => {
+                return HealthStatus::Unhealthy {
+                    reason: format!("Cluster error: {e}"),
+                };
             }
-            Err(e) => HealthStatus::Unhealthy {
-                reason: format!("Failed to read cluster response: {}", e),
-            },
+            Ok(reply) => {
+                return HealthStatus::Unhealthy {
+                    reason: format!("Unexpected cluster response: {reply:?}"),
+                };
+            }
+            Err(e) => {
+                return HealthStatus::Unhealthy {
+                    reason: format!("Failed to read cluster response: {e}"),
+                };
+            }
+        };
+
+        match parse_cluster_nodes(&String::from_utf8_lossy(&nodes_data)) {
+            Ok(node) => {
+                self.cluster_nodes.lock().unwrap().insert(backend.addr, node);
+                HealthStatus::Healthy
+            }
+            Err(reason) => HealthStatus::Unhealthy { reason },
         }
     }
 
@@ -199,6 +247,23 @@ impl HealthChecker for RedisHealthChecker {
     fn check_timeout(&self) -> Duration {
         self.check_timeout
     }
+
+    fn update_metadata(&self, backend: &mut Backend) {
+        let Some(node) = self.cluster_nodes.lock().unwrap().remove(&backend.addr) else {
+            return;
+        };
+        if let BackendMetadata::Redis {
+            node_id,
+            slot_ranges,
+            is_master,
+            ..
+        } = &mut backend.metadata
+        {
+            *node_id = node.node_id;
+            *slot_ranges = node.slot_ranges;
+            *is_master = node.is_master;
+        }
+    }
 }
 
 impl Default for RedisHealthChecker {
@@ -207,6 +272,72 @@ impl Default for RedisHealthChecker {
     }
 }
 
+/// Check the `myself` line of a CLUSTER NODES reply
+///
+/// The node must be connected, not flagged as failing, and no other master
+/// may claim the slots it serves.
+pub fn parse_cluster_nodes(nodes: &str) -> Result<ClusterNodeInfo, String> {
+    // <id> <ip:port@cport> <flags> <master> <ping-sent> <pong-recv> <config-epoch> <link-state> <slot>...
+    let lines: Vec<Vec<&str>> = nodes
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>())
+        .filter(|fields| fields.len() >= 8)
+        .collect();
+    let flags = |fields: &[&str]| fields[2].split(',').map(str::to_string).collect::<Vec<_>>();
+
+    let myself = lines
+        .iter()
+        .find(|fields| flags(fields).iter().any(|flag| flag == "myself"))
+        .ok_or_else(|| "CLUSTER NODES reply has no myself entry".to_string())?;
+    let my_flags = flags(myself);
+    if let Some(flag) = my_flags
+        .iter()
+        .find(|flag| matches!(flag.as_str(), "fail" | "fail?" | "handshake" | "noaddr"))
+    {
+        return Err(format!("Cluster node {} is flagged {flag}", myself[0]));
+    }
+    if myself[7] != "connected" {
+        return Err(format!("Cluster node {} link is {}", myself[0], myself[7]));
+    }
+
+    let slot_ranges = parse_slot_ranges(&myself[8..]);
+    for other in lines.iter().filter(|fields| fields[0] != myself[0]) {
+        if !flags(other).iter().any(|flag| flag == "master") {
+            continue;
+        }
+        for (start, end) in parse_slot_ranges(&other[8..]) {
+            if let Some((my_start, my_end)) = slot_ranges
+                .iter()
+                .find(|(my_start, my_end)| start <= *my_end && *my_start <= end)
+            {
+                return Err(format!(
+                    "Cluster node {} slots {my_start}-{my_end} are also claimed by {}",
+                    myself[0], other[0]
+                ));
+            }
+        }
+    }
+
+    Ok(ClusterNodeInfo {
+        node_id: myself[0].to_string(),
+        is_master: my_flags.iter().any(|flag| flag == "master"),
+        slot_ranges,
+    })
+}
+
+/// Slot ranges of a CLUSTER NODES line; migrating and importing entries
+/// such as `[42->-<id>]` are skipped
+fn parse_slot_ranges(specs: &[&str]) -> Vec<(u16, u16)> {
+    specs
+        .iter()
+        .filter(|spec| !spec.starts_with('['))
+        .filter_map(|spec| match spec.split_once('-') {
+            Some((start, end)) => Some((start.parse().ok()?, end.parse().ok()?)),
+            None => spec.parse().ok().map(|slot| (slot, slot)),
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -249,4 +380,169 @@ mod tests {
         // PING = The command
         assert_eq!(ping_command, "*1\r\n$4\r\nPING\r\n");
     }
+
+    const NODES: &str = "\
+07c37dfeb235213a872192d90877d0cd55635b91 127.0.0.1:30004@31004 slave e7d1eecce10fd6bb5eb35b9f99a514335d9ba9ca 0 1426238317239 4 connected
+67ed2db8d677e59ec4a4cefb06858cf2a1a89fa1 127.0.0.1:30002@31002 master - 0 1426238316232 2 connected 5461-10922
+292f8b365bb7edb5e285caf0b7e6ddc7265d2f4f 127.0.0.1:30003@31003 master - 0 1426238318243 3 connected 10923-16383
+e7d1eecce10fd6bb5eb35b9f99a514335d9ba9ca 127.0.0.1:30001@31001 myself,master - 0 0 1 connected 0-5460 [5461->-67ed2db8d677e59ec4a4cefb06858cf2a1a89fa1]
+";
+
+    #[test]
+    fn test_parse_cluster_nodes() {
+        let node = parse_cluster_nodes(NODES).unwrap();
+        assert_eq!(node.node_id, "e7d1eecce10fd6bb5eb35b9f99a514335d9ba9ca");
+        assert!(node.is_master);
+        assert_eq!(node.slot_ranges, vec![(0, 5460)]);
+
+        let replica = NODES
+            .replace("myself,master", "master")
+            .replace("slave e7d1", "myself,slave e7d1");
+        let node = parse_cluster_nodes(&replica).unwrap();
+        assert!(!node.is_master);
+        assert!(node.slot_ranges.is_empty());
+    }
+
+    #[test]
+    fn test_parse_cluster_nodes_rejects_unhealthy_node() {
+        let failing = NODES.replace("myself,master", "myself,master,fail?");
+        assert!(parse_cluster_nodes(&failing).unwrap_err().contains("fail?"));
+
+        let disconnected = NODES.replace("0 0 1 connected", "0 0 1 disconnected");
+        assert!(parse_cluster_nodes(&disconnected).unwrap_err().contains("disconnected"));
+
+        let conflicting = NODES.replace("connected 5461-10922", "connected 5000-10922");
+        assert!(parse_cluster_nodes(&conflicting).unwrap_err().contains("also claimed"));
+
+        assert!(parse_cluster_nodes("").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_cluster_check_records_metadata() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = [0u8; 64];
+            let _ = stream.read(&mut request).await.unwrap();
+            let reply = format!("${}\r\n{NODES}\r\n", NODES.len());
+            stream.write_all(reply.as_bytes()).await.unwrap();
+        });
+
+        let checker = RedisHealthChecker::new();
+        let mut backend = Backend::new_redis("redis-0".to_string(), addr, String::new());
+        assert_eq!(checker.redis_cluster_check(&backend).await, HealthStatus::Healthy);
+
+        checker.update_metadata(&mut backend);
+        match backend.metadata {
+            BackendMetadata::Redis {
+                node_id,
+                slot_ranges,
+                is_master,
+                ..
+            } => {
+                assert_eq!(node_id, "e7d1eecce10fd6bb5eb35b9f99a514335d9ba9ca");
+                assert_eq!(slot_ranges, vec![(0, 5460)]);
+                assert!(is_master);
+            }
+            _ => panic!("Expected Redis metadata"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_standalone_node_skips_cluster_check() {
+        // A server with cluster support disabled
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut request = [0u8; 64];
+                let n = stream.read(&mut request).await.unwrap();
+                let reply: &[u8] = match &request[..n] {
+                    b"*1\r\n$4\r\nPING\r\n" => b"+PONG\r\n",
+                    _ => b"-ERR This instance has cluster support disabled\r\n",
+                };
+                stream.write_all(reply).await.unwrap();
+            }
+        });
+
+        let checker = |cluster: bool| {
+            RedisHealthChecker::with_config(Duration::from_secs(5), Duration::from_secs(3), 0, Duration::ZERO, true)
+                .with_cluster_check(cluster)
+        };
+        let backend = Backend::new_redis("redis-0".to_string(), addr, String::new());
+        assert!(!checker(true).check_health(&backend).await.is_healthy());
+        assert_eq!(checker(false).check_health(&backend).await, HealthStatus::Healthy);
+    }
+
+    #[tokio::test]
+    async fn test_check_over_upstream_tls() {
+        let (addr, handshake) = crate::health::connect::tests::tls_only_backend().await;
+        let checker = RedisHealthChecker::new().with_upstream_tls(Some(&crate::health::connect::tests::tls_config()));
+        let backend = Backend::new_redis("redis-0".to_string(), addr, String::new());
+        assert!(!checker.check_health(&backend).await.is_healthy());
+        assert!(handshake.await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_oversized_reply_is_refused() {
+        // The node announces a 4 GiB bulk string
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = [0u8; 64];
+            let _ = stream.read(&mut request).await.unwrap();
+            stream.write_all(b"$4294967295\r\n").await.unwrap();
+            let _ = stream.read(&mut request).await;
+        });
+
+        let checker = RedisHealthChecker::new();
+        let backend = Backend::new_redis("redis-0".to_string(), addr, String::new());
+        match checker.redis_cluster_check(&backend).await {
+            HealthStatus::Unhealthy { reason } => assert!(reason.contains("exceeds the maximum"), "{reason}"),
+            status => panic!("Expected an unhealthy status, got {status:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_check_authenticates() {
+        // A node answering NOAUTH until AUTH secret
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut authenticated = false;
+                    let mut request = [0u8; 64];
+                    while let Ok(n) = stream.read(&mut request).await {
+                        let reply: &[u8] = match &request[..n] {
+                            [] => return,
+                            b"*2\r\n$4\r\nAUTH\r\n$6\r\nsecret\r\n" => {
+                                authenticated = true;
+                                b"+OK\r\n"
+                            }
+                            _ if !authenticated => b"-NOAUTH Authentication required.\r\n",
+                            _ => b"+PONG\r\n",
+                        };
+                        stream.write_all(reply).await.unwrap();
+                    }
+                });
+            }
+        });
+
+        let checker = |auth: Option<&RedisAuthConfig>| {
+            RedisHealthChecker::with_config(Duration::from_secs(5), Duration::from_secs(3), 0, Duration::ZERO, false)
+                .with_auth(auth)
+        };
+        let backend = Backend::new_redis("redis-0".to_string(), addr, String::new());
+        assert!(!checker(None).check_health(&backend).await.is_healthy());
+
+        let auth = RedisAuthConfig {
+            username: None,
+            password: "secret".to_string(),
+            password_file: None,
+        };
+        assert_eq!(checker(Some(&auth)).check_health(&backend).await, HealthStatus::Healthy);
+    }
 }
@@ -0,0 +1,77 @@
+/// TCP connect health checker
+///
+/// Only opens a connection, without speaking the backend's protocol. Useful
+/// for backends behind links where a protocol round trip is too slow or
+/// too costly to run on every check.
+use super::{CheckConnector, HealthChecker, HealthStatus};
+use crate::config::UpstreamTlsConfig;
+use crate::core::Backend;
+use std::time::Duration;
+
+/// Health checker that passes when a TCP connection can be opened, and
+/// its TLS handshake completed when the proxy connects over TLS
+pub struct TcpHealthChecker {
+    check_interval: Duration,
+    check_timeout: Duration,
+    connector: CheckConnector,
+}
+
+impl TcpHealthChecker {
+    pub fn new(check_interval: Duration, check_timeout: Duration) -> Self {
+        Self {
+            check_interval,
+            check_timeout,
+            connector: CheckConnector::default(),
+        }
+    }
+
+    /// Complete a TLS handshake when the proxy connects over TLS
+    pub fn with_upstream_tls(mut self, tls: Option<&UpstreamTlsConfig>) -> Self {
+        self.connector = CheckConnector::new(tls);
+        self
+    }
+}
+
+#[async_trait::async_trait]
+impl HealthChecker for TcpHealthChecker {
+    async fn check_health(&self, backend: &Backend) -> HealthStatus {
+        match self.connector.connect(backend.addr).await {
+            Ok(_) => HealthStatus::Healthy,
+            Err(reason) => HealthStatus::Unhealthy { reason },
+        }
+    }
+
+    fn check_interval(&self) -> Duration {
+        self.check_interval
+    }
+
+    fn check_timeout(&self) -> Duration {
+        self.check_timeout
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn test_tcp_health_check() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let checker = TcpHealthChecker::new(Duration::from_secs(10), Duration::from_secs(2));
+
+        let backend = Backend::new_mongodb("mongos-0".to_string(), addr);
+        assert_eq!(checker.check_health(&backend).await, HealthStatus::Healthy);
+
+        drop(listener);
+        assert!(!checker.check_health(&backend).await.is_healthy());
+
+        // Accepting the connection is not enough for a TLS-only backend
+        let (addr, handshake) = crate::health::connect::tests::tls_only_backend().await;
+        let checker = checker.with_upstream_tls(Some(&crate::health::connect::tests::tls_config()));
+        let backend = Backend::new_mongodb("mongos-0".to_string(), addr);
+        assert!(!checker.check_health(&backend).await.is_healthy());
+        assert!(handshake.await.unwrap());
+    }
+}
@@ -1,3 +1,6 @@
+pub mod acl;
+pub mod admin;
+pub mod bench;
 pub mod config;
 pub mod error;
 /// Puerta - High-performance load balancer for MongoDB Sharded Clusters and Redis Clusters
@@ -7,61 +10,77 @@ pub mod error;
 /// 1. MongoDB Mode: Session-aware TCP load balancing across multiple mongos instances using Pingora TCP proxy
 /// 2. Redis Mode: Protocol-aware proxy for Redis Cluster with MOVED/ASK handling using RCProxy
 pub mod core;
+pub mod discovery;
 pub mod health;
+pub mod logging;
+pub mod metrics;
 pub mod modes;
+pub mod telemetry;
 pub mod utils;
 
 use async_trait::async_trait;
+use bytes::Bytes;
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::error::Error;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::io::{AsyncReadExt, AsyncWrite, AsyncWriteExt};
 
 // Pingora framework imports for TCP proxy
 use pingora::apps::ServerApp;
 use pingora_core::connectors::TransportConnector;
-use pingora_core::listeners::Listeners;
+use pingora_core::listeners::{Listeners, TcpSocketOptions, TlsSettings};
 use pingora_core::protocols::Stream;
 use pingora_core::server::configuration::Opt;
 use pingora_core::server::Server;
 use pingora_core::server::ShutdownWatch;
-use pingora_core::services::listening::Service;
 use pingora_core::upstreams::peer::{BasicPeer, Peer};
-use pingora_load_balancing::{health_check, selection::RoundRobin, LoadBalancer};
-
+use pingora_load_balancing::discovery::ServiceDiscovery;
+use pingora_load_balancing::{selection::RoundRobin, LoadBalancer};
+use tokio::sync::watch;
+
+use crate::config::reload::ConfigReloader;
+use crate::config::Endpoint;
+use crate::core::frontend::WriteQueue;
+use crate::modes::mongodb::affinity::{ClientIdentifier, ClientMetadata};
+use crate::modes::mongodb::rebalance::SessionMove;
+use crate::modes::mongodb::wire::MessageFramer;
 use crate::modes::mongodb::MongoDBConfig;
-use crate::modes::redis::{RedisClusterProxy, RedisConfig};
-
-/// Main proxy mode enumeration
-#[derive(Debug, Clone)]
-pub enum ProxyMode {
-    /// MongoDB Sharded Cluster mode: Session-aware TCP load balancing across mongos instances
-    /// Uses Pingora TCP proxy with session affinity for MongoDB Wire Protocol
-    MongoDB {
-        mongos_endpoints: Vec<String>,
-        session_affinity_enabled: bool,
-    },
-    /// Redis Cluster mode: Protocol-aware proxy with slot-based routing
-    /// Uses RCProxy-style Redis cluster handling
-    Redis {
-        cluster_nodes: Vec<String>,
-        slot_refresh_interval_ms: u64,
-    },
-}
+use crate::modes::ProxyModeHandler;
 
 /// Main puerta configuration
 #[derive(Debug, Clone)]
 pub struct PuertaConfig {
     pub listen_addr: String,
-    pub proxy_mode: ProxyMode,
+    /// Protocol of the primary proxy, with its mode settings
+    pub proxy_mode: Box<dyn ProxyModeHandler>,
     pub health_check_interval_ms: u64,
     pub max_connections: usize,
+    /// How long a connection past `max_connections` waits for a slot;
+    /// zero rejects it immediately
+    pub connection_queue_timeout_ms: u64,
+    /// Read the client address from a PROXY protocol header
+    pub proxy_protocol: bool,
+    /// Terminate TLS on the client-facing listener when set
+    pub tls: Option<config::TlsConfig>,
+    /// Read buffer size and write backpressure for forwarded connections
+    pub buffer_config: crate::core::frontend::BufferConfig,
+    /// Close client connections without traffic in either direction for
+    /// this long; zero keeps idle connections open
+    pub connection_timeout_sec: u64,
+    /// Worker threads of each proxy service, Pingora's default when unset
+    pub worker_threads: Option<usize>,
+    /// Seconds between lookups of backend endpoints given by hostname
+    pub dns_refresh_sec: u64,
+    /// Listening socket options
+    pub tuning: config::ServerTuningConfig,
 }
 
 impl PuertaConfig {
     /// Create a new Puerta configuration with validation
     pub fn new(
         listen_addr: String,
-        proxy_mode: ProxyMode,
+        proxy_mode: Box<dyn ProxyModeHandler>,
         health_check_interval_ms: u64,
         max_connections: usize,
     ) -> Result<Self, String> {
@@ -81,41 +100,137 @@ impl PuertaConfig {
         }
 
         // Validate proxy mode specific settings
-        match &proxy_mode {
-            ProxyMode::MongoDB {
-                mongos_endpoints, ..
-            } => {
-                if mongos_endpoints.is_empty() {
-                    return Err("At least one mongos endpoint is required".to_string());
-                }
-            }
-            ProxyMode::Redis {
-                cluster_nodes,
-                slot_refresh_interval_ms,
-            } => {
-                if cluster_nodes.is_empty() {
-                    return Err("At least one Redis cluster node is required".to_string());
-                }
-                if *slot_refresh_interval_ms == 0 {
-                    return Err("Slot refresh interval must be greater than 0".to_string());
-                }
-            }
-        }
+        proxy_mode.validate()?;
 
         Ok(Self {
             listen_addr,
             proxy_mode,
             health_check_interval_ms,
             max_connections,
+            connection_queue_timeout_ms: 0,
+            proxy_protocol: false,
+            tls: None,
+            buffer_config: Default::default(),
+            connection_timeout_sec: 0,
+            worker_threads: None,
+            dns_refresh_sec: 30,
+            tuning: Default::default(),
+        })
+    }
+
+    /// Queue connections past `max_connections` for up to `timeout_ms`
+    pub fn with_connection_queue_timeout_ms(mut self, timeout_ms: u64) -> Self {
+        self.connection_queue_timeout_ms = timeout_ms;
+        self
+    }
+
+    /// Expect a PROXY protocol header on every client connection
+    pub fn with_proxy_protocol(mut self, enabled: bool) -> Self {
+        self.proxy_protocol = enabled;
+        self
+    }
+
+    /// Enable TLS termination on the client-facing listener
+    pub fn with_tls(mut self, tls: config::TlsConfig) -> Self {
+        self.tls = Some(tls);
+        self
+    }
+
+    /// Read and queue forwarded data with the given buffer settings
+    pub fn with_buffer_config(mut self, buffer_config: crate::core::frontend::BufferConfig) -> Self {
+        self.buffer_config = buffer_config;
+        self
+    }
+
+    /// Close client connections idle for `timeout_sec`; zero disables
+    pub fn with_connection_timeout_sec(mut self, timeout_sec: u64) -> Self {
+        self.connection_timeout_sec = timeout_sec;
+        self
+    }
+
+    /// Run each proxy service on `worker_threads` threads
+    pub fn with_worker_threads(mut self, worker_threads: Option<usize>) -> Self {
+        self.worker_threads = worker_threads;
+        self
+    }
+
+    /// Look up backend endpoints given by hostname every `dns_refresh_sec`
+    pub fn with_dns_refresh_sec(mut self, dns_refresh_sec: u64) -> Self {
+        self.dns_refresh_sec = dns_refresh_sec;
+        self
+    }
+
+    /// Apply SO_REUSEPORT and TCP keepalive to the listening sockets
+    pub fn with_tuning(mut self, tuning: config::ServerTuningConfig) -> Self {
+        self.tuning = tuning;
+        self
+    }
+
+    /// How long a connection may go without traffic, if limited
+    pub fn idle_timeout(&self) -> Option<std::time::Duration> {
+        (self.connection_timeout_sec > 0)
+            .then(|| std::time::Duration::from_secs(self.connection_timeout_sec))
+    }
+
+    /// Build the client-facing listeners, plaintext or TLS
+    pub fn listeners(&self) -> Result<Listeners, Box<dyn Error + Send + Sync>> {
+        self.listeners_for(&self.listen_addr)
+    }
+
+    /// Build listeners on `listen_addr` with the same TLS settings, for an
+    /// additional proxy instance
+    pub fn listeners_for(&self, listen_addr: &str) -> Result<Listeners, Box<dyn Error + Send + Sync>> {
+        let socket_options = self.socket_options();
+        match (&self.tls, socket_options) {
+            (Some(tls), socket_options) => {
+                log::info!(
+                    "Terminating TLS on {} (cert: {}, key: {})",
+                    listen_addr,
+                    tls.cert_path,
+                    tls.key_path
+                );
+                let mut listeners = Listeners::new();
+                let settings = TlsSettings::intermediate(&tls.cert_path, &tls.key_path)?;
+                listeners.add_tls_with_settings(listen_addr, socket_options, settings);
+                Ok(listeners)
+            }
+            (None, Some(socket_options)) => {
+                let mut listeners = Listeners::new();
+                listeners.add_tcp_with_settings(listen_addr, socket_options);
+                Ok(listeners)
+            }
+            (None, None) => Ok(Listeners::tcp(listen_addr)),
+        }
+    }
+
+    /// Options for the listening sockets, `None` to keep the defaults
+    fn socket_options(&self) -> Option<TcpSocketOptions> {
+        if !self.tuning.reuse_port && self.tuning.tcp_keepalive.is_none() {
+            return None;
+        }
+        Some(TcpSocketOptions {
+            tcp_keepalive: self
+                .tuning
+                .tcp_keepalive
+                .as_ref()
+                .map(crate::core::upstream::tcp_keepalive),
+            #[cfg(target_os = "linux")]
+            so_reuseport: self.tuning.reuse_port.then_some(true),
+            ..Default::default()
         })
     }
 
+    /// Build the global limiter enforcing `max_connections`
+    pub fn connection_limiter(&self) -> crate::core::frontend::ConnectionLimiter {
+        crate::core::frontend::ConnectionLimiter::new(
+            self.max_connections,
+            std::time::Duration::from_millis(self.connection_queue_timeout_ms),
+        )
+    }
+
     /// Get the proxy mode as a string for logging
     pub fn mode_name(&self) -> &'static str {
-        match self.proxy_mode {
-            ProxyMode::MongoDB { .. } => "MongoDB",
-            ProxyMode::Redis { .. } => "Redis",
-        }
+        self.proxy_mode.name()
     }
 
     /// Check if the configuration is valid
@@ -126,181 +241,1763 @@ impl PuertaConfig {
     }
 }
 
+/// A proxy instance served by the same process as the primary one, with
+/// its own listen address, mode and mode settings
+#[derive(Debug, Clone)]
+pub struct ProxyInstance {
+    /// Name used in logs and as the service name
+    pub name: String,
+    pub listen_addr: String,
+    /// Protocol of the instance, with its mode settings
+    pub proxy_mode: Box<dyn ProxyModeHandler>,
+    /// Cores the instance's worker threads are pinned to
+    pub cpu_affinity: Vec<usize>,
+}
+
+/// Service discovery for the mongos load balancer whose backend set can be
+/// replaced at runtime when the configuration is reloaded
+///
+/// It also reports which backends are enabled: the MongoDB proxy's health
+/// checks are the only health source, and backends they find unhealthy are
+/// disabled in the load balancer.
+#[derive(Clone, Default)]
+pub struct ReloadableDiscovery {
+    backends: Arc<std::sync::RwLock<BTreeSet<pingora_load_balancing::Backend>>>,
+    unhealthy: Arc<std::sync::RwLock<HashSet<String>>>,
+}
+
+impl ReloadableDiscovery {
+    pub fn new(endpoints: &[Endpoint]) -> pingora_core::Result<Self> {
+        let discovery = Self::default();
+        discovery.set_endpoints(endpoints)?;
+        Ok(discovery)
+    }
+
+    /// Replace the backend set; on error the current set is left untouched
+    pub fn set_endpoints(&self, endpoints: &[Endpoint]) -> pingora_core::Result<()> {
+        let mut backends = BTreeSet::new();
+        for endpoint in endpoints {
+            backends.insert(pingora_load_balancing::Backend::new_with_weight(
+                &endpoint.addr,
+                endpoint.weight,
+            )?);
+        }
+        *self.backends.write().unwrap() = backends;
+        Ok(())
+    }
+
+    /// Get the current backend set
+    pub fn backends(&self) -> BTreeSet<pingora_load_balancing::Backend> {
+        self.backends.read().unwrap().clone()
+    }
+
+    /// Replace the set of backends reported as disabled
+    pub fn set_unhealthy(&self, unhealthy: &HashSet<std::net::SocketAddr>) {
+        *self.unhealthy.write().unwrap() = unhealthy.iter().map(|addr| addr.to_string()).collect();
+    }
+}
+
+#[async_trait]
+impl ServiceDiscovery for ReloadableDiscovery {
+    async fn discover(
+        &self,
+    ) -> pingora_core::Result<(BTreeSet<pingora_load_balancing::Backend>, HashMap<u64, bool>)> {
+        let backends = self.backends();
+        let unhealthy = self.unhealthy.read().unwrap();
+        let enabled = backends
+            .iter()
+            .map(|backend| (backend.hash_key(), !unhealthy.contains(&backend.addr.to_string())))
+            .collect();
+        Ok((backends, enabled))
+    }
+}
+
+/// Operations a MongoDB connection tracks while awaiting their replies
+const MAX_PENDING_OPERATIONS: usize = 1024;
+
+/// A client operation awaiting its reply from mongos
+struct PendingOperation {
+    operation: crate::modes::mongodb::wire::Operation,
+    sent: std::time::Instant,
+    /// Its first reply was seen, for exhaust cursors replying more than once
+    answered: bool,
+    /// Cursor a `getMore` reads from, forgotten once a reply exhausts it
+    cursor: Option<i64>,
+}
+
+/// What `forward_tcp_data` keeps about the messages of one client
+struct ClientMessages<'a> {
+    client_addr: &'a str,
+    client_id: &'a ClientIdentifier,
+    mongos_addr: &'a str,
+    connection_id: u64,
+    /// Set when read preferences are routed by client IP
+    client_ip: Option<std::net::IpAddr>,
+    mirror: Option<crate::core::mirror::MirrorSession>,
+    /// The first client message is its handshake, naming its application
+    awaiting_handshake: bool,
+    handshake_metadata: Option<ClientMetadata>,
+    /// Operations awaiting a reply by request id, for large reply tracking
+    /// and auditing
+    pending_operations: HashMap<i32, PendingOperation>,
+}
+
+/// Time between passes topping up spare mongos connections
+const WARM_UP_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Minimum time between recordings of a client's session activity
+const SESSION_TOUCH_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// Time a client identified by its handshake has to send it
+const HANDSHAKE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Spare mongos connections older than this are replaced
+const SPARE_CONNECTION_MAX_AGE: std::time::Duration = std::time::Duration::from_secs(60);
+
 /// MongoDB TCP Proxy App using Pingora framework for MongoDB Wire Protocol
 /// Now uses the structured MongoDBProxy from src/modes/mongodb for routing decisions
 pub struct MongoDBTcpProxy {
     connector: TransportConnector,
     load_balancer: Arc<LoadBalancer<RoundRobin>>,
     mongodb_proxy: Arc<crate::modes::mongodb::MongoDBProxy>,
+    max_message_size: usize,
+    upstream_tls: Option<crate::config::UpstreamTlsConfig>,
+    /// Keepalive probes on connections to mongos
+    backend_tcp_keepalive: Option<crate::config::TcpKeepaliveConfig>,
+    balance_strategy: crate::config::BalanceStrategy,
+    /// Total client operations (complete wire protocol messages) forwarded
+    operations: AtomicU64,
+    /// Cap on concurrent client connections
+    connection_limiter: Option<crate::core::frontend::ConnectionLimiter>,
+    /// Read the client address from a PROXY protocol header
+    proxy_protocol: bool,
+    /// Client IP allow and deny lists
+    access_control: Option<Arc<crate::acl::AccessControl>>,
+    /// Read buffer size and write backpressure
+    buffer_config: crate::core::frontend::BufferConfig,
+    /// Close connections without traffic for this long
+    idle_timeout: Option<std::time::Duration>,
+    /// Compressors clients may negotiate with mongos, `None` to pass through
+    compressors: Option<Vec<crate::config::Compressor>>,
+    /// Replies above the large payload threshold, per operation
+    large_payloads: Option<Arc<crate::metrics::payload::LargePayloads>>,
+    /// Connections opened to healthy mongos ahead of new clients
+    spare_connections: Option<Arc<crate::core::upstream::SpareConnections>>,
+    /// Other mongos tried when connecting to the selected one fails
+    retry_attempts: u32,
+    /// Cap on the client connections of each mongos
+    backend_limits: Option<Arc<crate::core::backend_limit::BackendLimits>>,
+    /// Mongos being drained for maintenance
+    drains: Option<Arc<crate::core::drain::Drains>>,
+    /// Faults injected into client operations for resilience testing
+    chaos: Option<Arc<crate::core::chaos::Chaos>>,
+    /// Copies of a share of client connections sent to a shadow mongos
+    mirror: Option<crate::core::mirror::Mirror>,
+    /// Client messages written to capture files
+    capture: Option<crate::core::capture::Capture>,
+    /// Ring plain TCP connections do their socket I/O through
+    uring: Option<Arc<crate::core::uring::Uring>>,
+    /// Relay connections with splice(2) when nothing inspects messages
+    zero_copy: bool,
+    /// Write every command to the access log
+    audit: bool,
+    /// Operations answered after the slow operation threshold
+    slow_operations: Option<Arc<crate::modes::mongodb::slow::SlowOperations>>,
+    /// Open cursors of clients by the mongos holding them
+    cursors: Option<Arc<crate::modes::mongodb::cursors::CursorAffinity>>,
+    /// Open transactions of clients by the mongos running them
+    transactions: Option<Arc<crate::modes::mongodb::transactions::TransactionPinning>>,
+    /// Address hello replies name as the only member
+    announce_addr: Option<String>,
+    /// Moving of idle sessions off mongos holding more than their share
+    rebalance: Option<crate::config::RebalanceConfig>,
 }
 
 impl MongoDBTcpProxy {
     pub async fn new(load_balancer: Arc<LoadBalancer<RoundRobin>>, config: MongoDBConfig) -> Result<Self, Box<dyn std::error::Error>> {
-        // Create the structured MongoDB proxy with health checking
+        let spare_connections = (config.spare_connections > 0).then(|| {
+            Arc::new(crate::core::upstream::SpareConnections::new(
+                crate::core::upstream::new_connector(config.upstream_tls.as_ref()),
+                config.spare_connections,
+                SPARE_CONNECTION_MAX_AGE,
+            ))
+        });
+
+        // Create the structured MongoDB proxy with health checking, which
+        // runs over the spare connections when there are some
         let mongodb_proxy = crate::modes::mongodb::MongoDBProxy::new(config.clone())
-            .with_health_check();
+            .with_health_check_over(spare_connections.clone());
         
         // Initialize backends
         mongodb_proxy.initialize_backends().await?;
         
         // Start health checks
         mongodb_proxy.start_health_checks().await?;
-        
+        mongodb_proxy.start_session_cleanup();
+
+        // Splicing needs plaintext sockets
+        let zero_copy = config.zero_copy && config.upstream_tls.is_none();
+
         Ok(Self {
-            connector: TransportConnector::new(None),
+            connector: crate::core::upstream::new_connector(config.upstream_tls.as_ref()),
             load_balancer,
             mongodb_proxy: Arc::new(mongodb_proxy),
+            max_message_size: config.max_message_size,
+            upstream_tls: config.upstream_tls,
+            backend_tcp_keepalive: config.backend_tcp_keepalive,
+            balance_strategy: config.balance_strategy,
+            operations: AtomicU64::new(0),
+            connection_limiter: None,
+            proxy_protocol: false,
+            access_control: None,
+            buffer_config: Default::default(),
+            idle_timeout: None,
+            compressors: config.compressors,
+            large_payloads: config.large_payloads.map(|large_payloads| {
+                Arc::new(crate::metrics::payload::LargePayloads::new(
+                    "mongodb",
+                    large_payloads.threshold_bytes,
+                    large_payloads.top,
+                ))
+            }),
+            spare_connections,
+            retry_attempts: config.retry_attempts,
+            backend_limits: config.max_connections_per_backend.map(|max_connections| {
+                Arc::new(crate::core::backend_limit::BackendLimits::new(
+                    max_connections,
+                    std::time::Duration::from_millis(config.backend_queue_timeout_ms),
+                ))
+            }),
+            drains: None,
+            chaos: None,
+            mirror: crate::core::mirror::Mirror::from_config(config.mirror.as_ref(), "mongodb"),
+            capture: crate::core::capture::Capture::from_config(
+                config.capture.as_ref(),
+                "mongodb",
+                |message| crate::modes::mongodb::auth::carries_credentials(message),
+            )?,
+            uring: None,
+            zero_copy,
+            audit: config.audit,
+            slow_operations: config.slow_operation_threshold_ms.map(|threshold_ms| {
+                Arc::new(crate::modes::mongodb::slow::SlowOperations::new(
+                    std::time::Duration::from_millis(threshold_ms),
+                ))
+            }),
+            cursors: config.cursor_affinity.then(Arc::default),
+            transactions: config.transaction_pinning.then(Arc::default),
+            announce_addr: config.announce_addr,
+            rebalance: config.rebalance,
         })
     }
 
+    /// Expect a PROXY protocol header and use its client address for
+    /// session affinity
+    pub fn with_proxy_protocol(mut self, enabled: bool) -> Self {
+        self.proxy_protocol = enabled;
+        self
+    }
+
+    /// Reject clients outside the access control lists
+    pub fn with_access_control(mut self, access_control: Arc<crate::acl::AccessControl>) -> Self {
+        self.access_control = Some(access_control);
+        self
+    }
+
+    /// Reject client connections past the limiter's `max_connections`
+    pub fn with_connection_limiter(
+        mut self,
+        connection_limiter: crate::core::frontend::ConnectionLimiter,
+    ) -> Self {
+        self.connection_limiter = Some(connection_limiter);
+        self
+    }
+
+    /// Read and queue forwarded data with the given buffer settings
+    pub fn with_buffer_config(mut self, buffer_config: crate::core::frontend::BufferConfig) -> Self {
+        self.buffer_config = buffer_config;
+        self
+    }
+
+    /// Close connections with no traffic in either direction for `idle_timeout`
+    pub fn with_idle_timeout(mut self, idle_timeout: Option<std::time::Duration>) -> Self {
+        self.idle_timeout = idle_timeout;
+        self
+    }
+
+    /// Give draining mongos no new clients and close their remaining
+    /// connections once the drain times out
+    pub fn with_drains(mut self, drains: Arc<crate::core::drain::Drains>) -> Self {
+        self.drains = Some(drains);
+        self
+    }
+
+    /// Delay a share of client operations or close their connections
+    pub fn with_chaos(mut self, chaos: Option<Arc<crate::core::chaos::Chaos>>) -> Self {
+        self.chaos = chaos;
+        self
+    }
+
+    /// Forward over io_uring; not used with TLS to mongos, whose streams
+    /// do not expose plaintext sockets
+    pub fn with_uring(mut self, uring: Option<Arc<crate::core::uring::Uring>>) -> Self {
+        self.uring = uring.filter(|_| self.upstream_tls.is_none());
+        self
+    }
+
+    /// Large reply tracking, for the admin API, when enabled
+    pub fn large_payloads(&self) -> Option<Arc<crate::metrics::payload::LargePayloads>> {
+        self.large_payloads.clone()
+    }
+
+    /// Runtime changes to the mongos set through `discovery`, for the
+    /// admin API
+    pub fn backend_admin(&self, discovery: ReloadableDiscovery) -> Arc<crate::modes::mongodb::backends::BackendAdmin> {
+        Arc::new(crate::modes::mongodb::backends::BackendAdmin::new(
+            Arc::clone(&self.load_balancer),
+            Arc::clone(&self.mongodb_proxy),
+            discovery,
+        ))
+    }
+
+    /// Client to mongos pinning, for the admin API
+    pub fn affinity_manager(&self) -> crate::modes::mongodb::SessionAffinityManager {
+        self.mongodb_proxy.get_affinity_manager().clone()
+    }
+
+    /// Status of this proxy for the admin API: mongos health and the client
+    /// connections open to each
+    pub fn status_source(&self, name: String, listen_addr: String) -> crate::admin::status::StatusSource {
+        let mongodb_proxy = Arc::clone(&self.mongodb_proxy);
+        let drains = self.drains.clone();
+        Arc::new(move || {
+            let mongodb_proxy = Arc::clone(&mongodb_proxy);
+            let drains = drains.clone();
+            let name = name.clone();
+            let listen_addr = listen_addr.clone();
+            Box::pin(async move {
+                let mut backends = mongodb_proxy.backend_status().await;
+                if let Some(drains) = &drains {
+                    for backend in &mut backends {
+                        backend.draining =
+                            backend.addr.parse().is_ok_and(|addr| drains.is_draining(addr));
+                    }
+                }
+                crate::admin::status::InstanceStatus {
+                    name,
+                    mode: "mongodb".to_string(),
+                    listen_addr,
+                    active_sessions: backends.iter().filter_map(|backend| backend.sessions).sum(),
+                    backends,
+                    slot_coverage: None,
+                }
+            })
+        })
+    }
+
+    /// How this proxy would route a new session of the client IP of an
+    /// explain request, skipping mongos being drained
+    pub fn explain_source(&self, name: String) -> crate::admin::explain::ExplainSource {
+        let mongodb_proxy = Arc::clone(&self.mongodb_proxy);
+        let drains = self.drains.clone();
+        Arc::new(move |request| {
+            let mongodb_proxy = Arc::clone(&mongodb_proxy);
+            let drains = drains.clone();
+            let name = name.clone();
+            Box::pin(async move {
+                let client_ip = request.client_ip?;
+                let draining = drains.map(|drains| drains.draining()).unwrap_or_default();
+                Some(crate::admin::explain::InstanceExplanation {
+                    name,
+                    mode: "mongodb".to_string(),
+                    route: crate::admin::explain::Route::Client(
+                        mongodb_proxy.explain_client(client_ip, &draining).await,
+                    ),
+                })
+            })
+        })
+    }
+
+    /// Keep spare connections open to every healthy mongos, when enabled
+    ///
+    /// Mongos instances added or recovering get spares on the next pass;
+    /// spares to unhealthy or removed ones are closed.
+    pub fn spawn_warm_up(&self) {
+        let Some(spare_connections) = self.spare_connections.clone() else {
+            return;
+        };
+        let mongodb_proxy = Arc::clone(&self.mongodb_proxy);
+        let drains = self.drains.clone();
+        let tls = self.upstream_tls.clone();
+        let keepalive = self.backend_tcp_keepalive.clone();
+
+        std::thread::spawn(move || {
+            let rt = crate::utils::background_runtime();
+            rt.block_on(async move {
+                let mut interval = tokio::time::interval(WARM_UP_INTERVAL);
+                loop {
+                    interval.tick().await;
+                    let healthy: Vec<String> = {
+                        let backends = mongodb_proxy.get_backends();
+                        let backends = backends.read().await;
+                        backends
+                            .values()
+                            .filter(|backend| {
+                                backend.healthy
+                                    && !mongodb_proxy.is_ejected(backend.addr)
+                                    && !mongodb_proxy.is_circuit_open(backend.addr)
+                                    && !drains
+                                        .as_ref()
+                                        .is_some_and(|drains| drains.is_draining(backend.addr))
+                            })
+                            .map(|backend| backend.addr.to_string())
+                            .collect()
+                    };
+                    spare_connections.retain(&healthy);
+                    for addr in &healthy {
+                        let peer = crate::core::upstream::new_peer(
+                            addr,
+                            tls.as_ref(),
+                            keepalive.as_ref(),
+                        );
+                        if let Err(e) = spare_connections.fill(&peer).await {
+                            log::warn!("Failed to open spare connections to mongos {addr}: {e}");
+                        }
+                    }
+                }
+            });
+        });
+    }
+
+    /// Move idle sessions off mongos holding more than their share, when
+    /// enabled
+    ///
+    /// Only mongos that could take a new session are candidates, and
+    /// sessions of clients with open cursors or transactions stay put.
+    pub fn spawn_rebalancer(&self) {
+        let Some(rebalance) = self.rebalance.clone() else {
+            return;
+        };
+        if !self.mongodb_proxy.identifies_by_handshake() {
+            log::warn!("Not rebalancing sessions: clients identified by socket address end their session on disconnect");
+            return;
+        }
+        let mongodb_proxy = Arc::clone(&self.mongodb_proxy);
+        let drains = self.drains.clone();
+        let backend_limits = self.backend_limits.clone();
+        let cursors = self.cursors.clone();
+        let transactions = self.transactions.clone();
+
+        std::thread::spawn(move || {
+            let rt = crate::utils::background_runtime();
+            rt.block_on(async move {
+                let mut interval = tokio::time::interval(std::time::Duration::from_secs(rebalance.interval_sec));
+                loop {
+                    interval.tick().await;
+                    let full = backend_limits.as_ref().map(|limits| limits.full()).unwrap_or_default();
+                    let weights: HashMap<String, usize> = {
+                        let backends = mongodb_proxy.get_backends();
+                        let backends = backends.read().await;
+                        backends
+                            .values()
+                            .filter(|backend| {
+                                backend.healthy
+                                    && !mongodb_proxy.is_ejected(backend.addr)
+                                    && !mongodb_proxy.is_circuit_open(backend.addr)
+                                    && !full.contains(&backend.addr)
+                                    && !drains
+                                        .as_ref()
+                                        .is_some_and(|drains| drains.is_draining(backend.addr))
+                            })
+                            .map(|backend| (backend.id.clone(), backend.weight))
+                            .collect()
+                    };
+                    let moves = mongodb_proxy
+                        .get_affinity_manager()
+                        .rebalance(&weights, &rebalance, |client| {
+                            cursors.as_ref().is_none_or(|cursors| cursors.backend_of(client).is_none())
+                                && transactions
+                                    .as_ref()
+                                    .is_none_or(|transactions| transactions.backend_of(client).is_none())
+                        })
+                        .await;
+                    for SessionMove { client, from, to } in &moves {
+                        log::info!("Rebalanced session of client {client} from {from} to {to}");
+                        crate::metrics::global()
+                            .counter_with_labels(
+                                "puerta_mongodb_sessions_rebalanced_total",
+                                "MongoDB sessions moved to a less busy mongos",
+                                &[("from", from.as_str()), ("to", to.as_str())],
+                            )
+                            .inc();
+                    }
+                }
+            });
+        });
+    }
+
+    /// Apply mongos endpoint and health check interval changes on configuration reload
+    ///
+    /// With backend discovery configured the endpoint list in the file is ignored.
+    pub fn spawn_reload_watcher(
+        &self,
+        mut receiver: watch::Receiver<Arc<crate::config::Config>>,
+        discovery: ReloadableDiscovery,
+    ) {
+        let load_balancer = Arc::clone(&self.load_balancer);
+        let mongodb_proxy = Arc::clone(&self.mongodb_proxy);
+
+        std::thread::spawn(move || {
+            let rt = crate::utils::background_runtime();
+            rt.block_on(async move {
+                while receiver.changed().await.is_ok() {
+                    let config = receiver.borrow_and_update().clone();
+                    let mongos_endpoints = match &config.proxy {
+                        crate::config::ProxyConfig::MongoDB {
+                            mongos_endpoints, ..
+                        } => mongos_endpoints.clone(),
+                        _ => continue,
+                    };
+                    mongodb_proxy.set_health_check_interval(config.health.interval_sec);
+                    mongodb_proxy.set_health_thresholds(
+                        config.health.failure_threshold,
+                        config.health.success_threshold,
+                    );
+                    // Hostnames stand for the mongos they currently resolve to
+                    let mongos_endpoints = match crate::discovery::resolve_endpoints(&mongos_endpoints).await {
+                        Ok(mongos_endpoints) => mongos_endpoints,
+                        Err(e) => {
+                            log::error!("Failed to resolve reloaded mongos endpoints: {e}");
+                            continue;
+                        }
+                    };
+                    mongodb_proxy.set_endpoint_health_checks(&Endpoint::health_checks(&mongos_endpoints));
+                    if config.discovery.is_some() {
+                        continue;
+                    }
+
+                    if let Err(e) = Self::apply_endpoints(
+                        &load_balancer,
+                        &mongodb_proxy,
+                        &discovery,
+                        &mongos_endpoints,
+                    )
+                    .await
+                    {
+                        log::error!("Failed to apply reloaded mongos endpoints: {e}");
+                        continue;
+                    }
+                    log::info!("Applied reloaded mongos endpoints: {mongos_endpoints:?}");
+                }
+            });
+        });
+    }
+
+    /// Apply mongos endpoints found by backend discovery as they change,
+    /// along with the health the discovery catalog reports for them
+    pub fn spawn_discovery_watcher(
+        &self,
+        mut receiver: watch::Receiver<Vec<crate::discovery::DiscoveredEndpoint>>,
+        discovery: ReloadableDiscovery,
+    ) {
+        let load_balancer = Arc::clone(&self.load_balancer);
+        let mongodb_proxy = Arc::clone(&self.mongodb_proxy);
+
+        std::thread::spawn(move || {
+            let rt = crate::utils::background_runtime();
+            rt.block_on(async move {
+                // The initial endpoints are already in place, only their health is not
+                let discovered = receiver.borrow_and_update().clone();
+                mongodb_proxy
+                    .set_catalog_unhealthy(crate::discovery::DiscoveredEndpoint::unhealthy_addrs(&discovered))
+                    .await;
+
+                while receiver.changed().await.is_ok() {
+                    let discovered = receiver.borrow_and_update().clone();
+                    let mongos_endpoints = crate::discovery::DiscoveredEndpoint::endpoints(&discovered);
+                    if let Err(e) = Self::apply_endpoints(
+                        &load_balancer,
+                        &mongodb_proxy,
+                        &discovery,
+                        &mongos_endpoints,
+                    )
+                    .await
+                    {
+                        log::error!("Failed to apply discovered mongos endpoints: {e}");
+                        continue;
+                    }
+                    mongodb_proxy
+                        .set_catalog_unhealthy(crate::discovery::DiscoveredEndpoint::unhealthy_addrs(&discovered))
+                        .await;
+                }
+            });
+        });
+    }
+
+    /// Keep the Pingora load balancer in step with the MongoDB proxy's
+    /// health checks, disabling the mongos they find unhealthy and
+    /// forgetting the sessions pinned to them
+    pub fn spawn_health_sync(&self, discovery: ReloadableDiscovery) {
+        let load_balancer = Arc::clone(&self.load_balancer);
+        let mongodb_proxy = Arc::clone(&self.mongodb_proxy);
+        let mut receiver = self.mongodb_proxy.health_updates();
+
+        std::thread::spawn(move || {
+            let rt = crate::utils::background_runtime();
+            rt.block_on(async move {
+                let mut previous = HashSet::new();
+                while receiver.changed().await.is_ok() {
+                    let unhealthy = receiver.borrow_and_update().clone();
+                    discovery.set_unhealthy(&unhealthy);
+                    if let Err(e) = load_balancer.update().await {
+                        log::error!("Failed to update mongos load balancer health: {e}");
+                    }
+                    for addr in unhealthy.difference(&previous) {
+                        let invalidated = mongodb_proxy.invalidate_sessions(*addr).await;
+                        if invalidated > 0 {
+                            log::info!("Forgot {invalidated} sessions pinned to unhealthy mongos {addr}");
+                        }
+                    }
+                    previous = unhealthy;
+                }
+            });
+        });
+    }
+
+    /// Replace the mongos set of both the Pingora load balancer and the
+    /// MongoDB proxy, adding and removing backends as needed
+    async fn apply_endpoints(
+        load_balancer: &LoadBalancer<RoundRobin>,
+        mongodb_proxy: &crate::modes::mongodb::MongoDBProxy,
+        discovery: &ReloadableDiscovery,
+        mongos_endpoints: &[Endpoint],
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        discovery.set_endpoints(mongos_endpoints)?;
+        if let Err(e) = load_balancer.update().await {
+            log::error!("Failed to update mongos load balancer: {e}");
+        }
+        if let Err(e) = mongodb_proxy
+            .update_backends(&Endpoint::addrs(mongos_endpoints))
+            .await
+        {
+            log::error!("Failed to update mongos backends: {e}");
+        }
+        mongodb_proxy
+            .update_weights(&Endpoint::weights(mongos_endpoints))
+            .await;
+        Ok(())
+    }
+
+    /// Get the total number of client operations forwarded
+    pub fn operation_count(&self) -> u64 {
+        self.operations.load(Ordering::Relaxed)
+    }
+
     /// Get the current session count for monitoring
     pub async fn session_count(&self) -> usize {
         self.mongodb_proxy.get_affinity_manager().session_count().await
     }
 
-    /// Select backend mongos using Pingora's load balancer with session affinity
+    /// Select a mongos for a client with session affinity, skipping the
+    /// `excluded` ones and those being drained
+    #[tracing::instrument(
+        name = "mongodb.select_backend",
+        skip(self),
+        fields(backend = tracing::field::Empty)
+    )]
     async fn select_backend(
         &self,
         client_addr: &str,
+        client_id: &ClientIdentifier,
+        excluded: &[std::net::SocketAddr],
     ) -> Result<BasicPeer, Box<dyn Error + Send + Sync>> {
+        // Mongos at their connection limit take no one, not even a client
+        // with an open transaction
+        let mut excluded = excluded.to_vec();
+        if let Some(backend_limits) = &self.backend_limits {
+            excluded.extend(backend_limits.full());
+        }
+
+        // An open transaction holds its client to its mongos, draining or not
+        if let Some(backend_addr) = self.transaction_backend(client_id, &excluded).await {
+            if !self.mongodb_proxy.circuit_allows(backend_addr) {
+                return Err(format!("Circuit open for mongos {backend_addr}").into());
+            }
+            log::info!("Using transaction pinning: client {client_addr} ({client_id}) -> backend {backend_addr}");
+            tracing::Span::current().record("backend", tracing::field::display(backend_addr));
+            return Ok(crate::core::upstream::new_peer(
+                &backend_addr.to_string(),
+                self.upstream_tls.as_ref(),
+                self.backend_tcp_keepalive.as_ref(),
+            ));
+        }
+
+        if let Some(drains) = &self.drains {
+            excluded.extend(drains.draining());
+        }
+        let excluded = excluded.as_slice();
+
         // Parse client address to SocketAddr
         let socket_addr: std::net::SocketAddr = client_addr.parse()
             .map_err(|e| format!("Invalid client address {client_addr}: {e}"))?;
         
+        // Static affinity rules come before the client's session
+        if let Some(backend) = self
+            .mongodb_proxy
+            .static_backend(socket_addr.ip(), excluded)
+            .await
+        {
+            log::info!("Using affinity rule: client {client_addr} -> backend {}", backend.id);
+            self.mongodb_proxy
+                .get_affinity_manager()
+                .get_backend_for_client(client_id.clone(), std::slice::from_ref(&backend.id))
+                .await;
+            tracing::Span::current().record("backend", tracing::field::display(backend.addr));
+            return Ok(crate::core::upstream::new_peer(
+                &backend.addr.to_string(),
+                self.upstream_tls.as_ref(),
+                self.backend_tcp_keepalive.as_ref(),
+            ));
+        }
+
         // Check session affinity first  
         if let Some(backend_id) = self.mongodb_proxy
             .get_affinity_manager()
-            .get_backend_for_client(socket_addr, &[])
+            .backend_of(client_id)
             .await
         {
             // If session affinity exists, try to use that backend
             let backend_pool = self.mongodb_proxy.get_backends();
             let backends = backend_pool.read().await;
             if let Some(backend) = backends.get(&backend_id) {
-                if backend.healthy {
-                    log::info!("Using session affinity: client {client_addr} -> backend {backend_id}");
-                    return Ok(BasicPeer::new(&backend.addr.to_string()));
+                if backend.healthy
+                    && !excluded.contains(&backend.addr)
+                    && self.mongodb_proxy.admits_read_preference(socket_addr.ip(), backend.addr)
+                {
+                    // Fail fast rather than move the session to another mongos
+                    if !self.mongodb_proxy.circuit_allows(backend.addr) {
+                        return Err(format!("Circuit open for mongos {}", backend.addr).into());
+                    }
+                    log::info!("Using session affinity: client {client_addr} ({client_id}) -> backend {backend_id}");
+                    tracing::Span::current().record("backend", tracing::field::display(backend.addr));
+                    return Ok(crate::core::upstream::new_peer(
+                        &backend.addr.to_string(),
+                        self.upstream_tls.as_ref(),
+                        self.backend_tcp_keepalive.as_ref(),
+                    ));
                 }
             }
         }
         
+        // Without a session, a client's open cursors keep it on their mongos
+        let cursor_backend = self.cursor_backend(client_id, excluded).await;
+
+        // Least-connections, consistent hashing and read preference routes are
+        // done by MongoDBProxy; until the first health check marks backends
+        // healthy, fall back to Pingora
+        let routed = self.mongodb_proxy.read_preference_route(socket_addr.ip()).is_some();
+        let selected = match self.balance_strategy {
+            _ if cursor_backend.is_some() => None,
+            crate::config::BalanceStrategy::RoundRobin if !routed => None,
+            _ => {
+                self.mongodb_proxy
+                    .select_new_backend_excluding(socket_addr, excluded)
+                    .await
+            }
+        };
+
         // No session affinity or backend unhealthy, use Pingora load balancer
-        let upstream = self.load_balancer
-            .select(client_addr.as_bytes(), 256) // Use client address for consistent hashing
-            .ok_or("No healthy backends available")?;
-        
-        log::info!("Load balancer selected backend: {upstream:?} for client {client_addr}");
-        
-        // Create session affinity for new connection
-        let backend_addr = upstream.addr.to_string();
-        let backend_id = format!("mongos-{backend_addr}");
+        let backend_addr = match (cursor_backend, selected) {
+            (Some(backend_addr), _) => {
+                log::info!("Using cursor affinity: client {client_addr} ({client_id}) -> backend {backend_addr}");
+                backend_addr
+            }
+            (None, Some(backend)) => {
+                log::info!(
+                    "{:?} selected backend: {} for client {client_addr}",
+                    self.balance_strategy,
+                    backend.addr
+                );
+                backend.addr.to_string()
+            }
+            (None, None) => {
+                // Skip mongos ejected by passive health checking, and keep to
+                // the client's read preference route while it has a candidate
+                let mongodb_proxy = &self.mongodb_proxy;
+                let select = |routed: bool| {
+                    self.load_balancer
+                        .select_with(client_addr.as_bytes(), 256, |backend, healthy| {
+                            let addr = backend.addr.to_string().parse().ok();
+                            healthy
+                                && !addr.is_some_and(|addr| {
+                                    excluded.contains(&addr)
+                                        || mongodb_proxy.is_ejected(addr)
+                                        || mongodb_proxy.is_circuit_open(addr)
+                                })
+                                && (!routed
+                                    || addr.is_some_and(|addr| {
+                                        mongodb_proxy.admits_read_preference(socket_addr.ip(), addr)
+                                    }))
+                        })
+                };
+                let upstream = select(routed)
+                    .or_else(|| select(false))
+                    .ok_or("No healthy backends available")?;
+                log::info!("Load balancer selected backend: {upstream:?} for client {client_addr}");
+                upstream.addr.to_string()
+            }
+        };
+
+        if let Ok(addr) = backend_addr.parse() {
+            if !self.mongodb_proxy.circuit_allows(addr) {
+                return Err(format!("Circuit open for mongos {backend_addr}").into());
+            }
+        }
+
+        // Create session affinity for new connection, under the id the
+        // backend pool knows the mongos by
+        let backend_id = {
+            let backend_pool = self.mongodb_proxy.get_backends();
+            let backends = backend_pool.read().await;
+            backends
+                .values()
+                .find(|backend| backend.addr.to_string() == backend_addr)
+                .map(|backend| backend.id.clone())
+        }
+        .unwrap_or_else(|| format!("mongos-{backend_addr}"));
         let available_backends = vec![backend_id.clone()];
         let _ = self.mongodb_proxy
             .get_affinity_manager()
-            .get_backend_for_client(socket_addr, &available_backends)
+            .get_backend_for_client(client_id.clone(), &available_backends)
             .await;
-        
-        Ok(BasicPeer::new(&backend_addr))
+
+        tracing::Span::current().record("backend", backend_addr.as_str());
+        Ok(crate::core::upstream::new_peer(
+            &backend_addr,
+            self.upstream_tls.as_ref(),
+            self.backend_tcp_keepalive.as_ref(),
+        ))
+    }
+
+    /// The mongos holding the most recently used open cursor of a client,
+    /// when cursors are followed and it can take the connection
+    async fn cursor_backend(
+        &self,
+        client_id: &ClientIdentifier,
+        excluded: &[std::net::SocketAddr],
+    ) -> Option<String> {
+        let backend_addr = self.cursors.as_ref()?.backend_of(client_id)?;
+        let backend_pool = self.mongodb_proxy.get_backends();
+        let backends = backend_pool.read().await;
+        backends
+            .values()
+            .find(|backend| backend.addr.to_string() == backend_addr)
+            .filter(|backend| {
+                backend.healthy
+                    && !excluded.contains(&backend.addr)
+                    && !self.mongodb_proxy.is_ejected(backend.addr)
+            })
+            .map(|_| backend_addr)
+    }
+
+    /// The mongos running the most recently started open transaction of a
+    /// client, when transactions are pinned and it is healthy
+    async fn transaction_backend(
+        &self,
+        client_id: &ClientIdentifier,
+        excluded: &[std::net::SocketAddr],
+    ) -> Option<std::net::SocketAddr> {
+        let backend_addr = self.transactions.as_ref()?.backend_of(client_id)?;
+        let backend_pool = self.mongodb_proxy.get_backends();
+        let backends = backend_pool.read().await;
+        backends
+            .values()
+            .find(|backend| backend.addr.to_string() == backend_addr)
+            .filter(|backend| backend.healthy && !excluded.contains(&backend.addr))
+            .map(|backend| backend.addr)
     }
 
     /// Clean up session affinity when client disconnects
     /// Now uses MongoDBProxy's handle_client_disconnect
-    async fn cleanup_session(&self, client_addr: &str) {
-        // Parse client address to SocketAddr for MongoDBProxy
-        if let Ok(socket_addr) = client_addr.parse::<std::net::SocketAddr>() {
-            let removed = self.mongodb_proxy.handle_client_disconnect(socket_addr).await;
-            if removed {
-                log::info!("Cleaned up session affinity for: {}", client_addr);
+    async fn cleanup_session(&self, client_id: &ClientIdentifier) {
+        let removed = self.mongodb_proxy.handle_client_disconnect(client_id.clone()).await;
+        if removed {
+            log::info!("Cleaned up session affinity for: {}", client_id);
+        }
+    }
+
+    /// Read until `buf` holds the first complete message of a client, its
+    /// hello handshake
+    async fn read_handshake(
+        client_stream: &mut Stream,
+        buf: &mut Vec<u8>,
+        max_message_size: usize,
+    ) -> Result<(), String> {
+        let read = async {
+            let mut chunk = [0; 4096];
+            loop {
+                if let Some(header) = crate::modes::mongodb::wire::MsgHeader::parse(buf) {
+                    let length = usize::try_from(header.message_length)
+                        .ok()
+                        .filter(|length| {
+                            (crate::modes::mongodb::wire::HEADER_LEN..=max_message_size)
+                                .contains(length)
+                        })
+                        .ok_or_else(|| format!("invalid message length {}", header.message_length))?;
+                    if buf.len() >= length {
+                        return Ok(());
+                    }
+                }
+                match client_stream.read(&mut chunk).await {
+                    Ok(0) => return Err("connection closed".to_string()),
+                    Ok(n) => buf.extend_from_slice(&chunk[..n]),
+                    Err(e) => return Err(e.to_string()),
+                }
+            }
+        };
+        tokio::time::timeout(HANDSHAKE_TIMEOUT, read)
+            .await
+            .map_err(|_| "timed out".to_string())?
+    }
+
+    /// Bidirectional forwarding of complete MongoDB wire protocol messages
+    /// between client and mongos
+    ///
+    /// Each side is read `buffer_size` bytes at a time and its complete
+    /// messages are queued for the other side, which is written as it accepts
+    /// data. A side is not read while the queue it feeds is above the high
+    /// watermark, so a slow client holds back its mongos instead of replies
+    /// piling up in memory, and neither direction stalls the other. With an
+    /// idle timeout, forwarding ends once nothing has been read or written
+    /// in either direction for that long.
+    ///
+    /// Returns `true` if forwarding ended on an I/O error with mongos. An
+    /// access record is written once forwarding ends.
+    async fn forward_tcp_data<C, M>(
+        &self,
+        client_stream: C,
+        mongos_stream: M,
+        client_addr: &str,
+        client_id: &ClientIdentifier,
+        mongos_addr: &str,
+        initial: &[u8],
+    ) -> bool
+    where
+        C: tokio::io::AsyncRead + AsyncWrite + Unpin,
+        M: tokio::io::AsyncRead + AsyncWrite + Unpin,
+    {
+        let started = std::time::Instant::now();
+        let connection = crate::core::connections::global().register("mongodb", client_addr, mongos_addr);
+        let (mut client_reader, mut client_writer) = tokio::io::split(client_stream);
+        let (mut mongos_reader, mut mongos_writer) = tokio::io::split(mongos_stream);
+        let mut client_buf = vec![0; self.buffer_config.buffer_size];
+        let mut mongos_buf = vec![0; self.buffer_config.buffer_size];
+        let mut client_framer = MessageFramer::new(self.max_message_size);
+        let mut mongos_framer = MessageFramer::new(self.max_message_size);
+        let mut to_mongos = WriteQueue::new(&self.buffer_config);
+        let mut to_client = WriteQueue::new(&self.buffer_config);
+        let mut operations = 0u64;
+        let mut replies = 0u64;
+        let mut bytes_transferred_to_mongos = 0u64;
+        let mut bytes_transferred_to_client = 0u64;
+        let mut mongos_failed = false;
+        let mut closed = false;
+        // Client traffic keeps the session affinity from expiring
+        let mut session_touched = std::time::Instant::now();
+        // When bytes first went to mongos, until its first reply is timed
+        let mut first_write: Option<std::time::Instant> = None;
+        let mut first_reply_timed = false;
+        let mut client_messages = ClientMessages {
+            client_addr,
+            client_id,
+            mongos_addr,
+            connection_id: connection.id,
+            client_ip: self
+                .mongodb_proxy
+                .routes_read_preferences()
+                .then(|| client_addr.parse::<std::net::SocketAddr>().ok())
+                .flatten()
+                .map(|addr| addr.ip()),
+            mirror: self.mirror.as_ref().and_then(|mirror| mirror.session()),
+            awaiting_handshake: true,
+            handshake_metadata: None,
+            pending_operations: HashMap::new(),
+        };
+
+        log::info!("Starting data forwarding for client: {}", client_addr);
+
+        // Bytes the client sent along with its PROXY protocol header
+        if !initial.is_empty() {
+            client_framer.push(initial);
+            match Self::queue_messages(&mut client_framer, &mut to_mongos, |message| {
+                self.handle_client_message(&mut client_messages, message)
+            }) {
+                Ok(count) => {
+                    operations += count;
+                    self.operations.fetch_add(count, Ordering::Relaxed);
+                    if let Some(metadata) = client_messages.handshake_metadata.take() {
+                        self.record_client_metadata(&connection, client_id, metadata).await;
+                    }
+                }
+                Err(e) => {
+                    log::error!("Failed to forward client {client_addr} to mongos: {e}");
+                    closed = true;
+                }
+            }
+        }
+
+        let idle = tokio::time::sleep(self.idle_timeout.unwrap_or_default());
+        tokio::pin!(idle);
+        let drained = self.drain_timed_out(mongos_addr);
+        tokio::pin!(drained);
+        let unhealthy = self.closed_unhealthy(mongos_addr);
+        tokio::pin!(unhealthy);
+
+        if !closed {
+            loop {
+                // Every branch but the timer itself is traffic
+                if let Some(idle_timeout) = self.idle_timeout {
+                    idle.as_mut().reset(tokio::time::Instant::now() + idle_timeout);
+                }
+                connection.update(bytes_transferred_to_mongos, bytes_transferred_to_client, operations);
+
+                tokio::select! {
+                    // Client -> Mongos
+                    result = client_reader.read(&mut client_buf), if to_mongos.accepts_reads() => {
+                        match result {
+                            Ok(0) => {
+                                log::debug!("Client {} connection closed", client_addr);
+                                // Operations already received still reach mongos
+                                match Self::drain_queue(&mut mongos_writer, &mut to_mongos).await {
+                                    Ok(bytes) => bytes_transferred_to_mongos += bytes,
+                                    Err(e) => {
+                                        log::error!("Failed to forward client {client_addr} to mongos: {e}");
+                                        mongos_failed = true;
+                                    }
+                                }
+                                break;
+                            }
+                            Ok(n) => {
+                                client_framer.push(&client_buf[0..n]);
+                                match Self::queue_messages(&mut client_framer, &mut to_mongos, |message| {
+                                    self.handle_client_message(&mut client_messages, message)
+                                }) {
+                                    Ok(count) => {
+                                        operations += count;
+                                        self.operations.fetch_add(count, Ordering::Relaxed);
+                                        if let Some(metadata) = client_messages.handshake_metadata.take() {
+                                            self.record_client_metadata(&connection, client_id, metadata).await;
+                                        }
+                                        log::trace!("Queued {count} operations from client {client_addr} for mongos");
+                                        // Resilience testing holds or drops the operations just received
+                                        let fault = self
+                                            .chaos
+                                            .as_ref()
+                                            .and_then(|chaos| (0..count).find_map(|_| chaos.fault(false)));
+                                        match fault {
+                                            Some(crate::core::chaos::Fault::Delay(delay)) => tokio::time::sleep(delay).await,
+                                            Some(_) => {
+                                                log::info!("Chaos: closing MongoDB client {client_addr}");
+                                                break;
+                                            }
+                                            None => {}
+                                        }
+                                        if session_touched.elapsed() >= SESSION_TOUCH_INTERVAL {
+                                            session_touched = std::time::Instant::now();
+                                            self.mongodb_proxy.touch_session(client_id).await;
+                                        }
+                                    }
+                                    Err(e) => {
+                                        // Framing errors are the client's fault
+                                        log::error!("Failed to forward client {client_addr} to mongos: {e}");
+                                        if let Some(reply) = Self::framing_error_reply(&client_framer, &e) {
+                                            to_client.push(reply);
+                                            match Self::drain_queue(&mut client_writer, &mut to_client).await {
+                                                Ok(bytes) => bytes_transferred_to_client += bytes,
+                                                Err(e) => {
+                                                    log::error!("Failed to reject request of client {client_addr}: {e}");
+                                                }
+                                            }
+                                        }
+                                        break;
+                                    }
+                                }
+                            }
+                            Err(e) => {
+                                log::error!("Failed to read from client {client_addr}: {e}");
+                                break;
+                            }
+                        }
+                    }
+                    // Mongos -> Client
+                    result = mongos_reader.read(&mut mongos_buf), if to_client.accepts_reads() => {
+                        match result {
+                            Ok(0) => {
+                                log::debug!("Mongos connection closed for client {}", client_addr);
+                                // Replies already received still reach the client
+                                match Self::drain_queue(&mut client_writer, &mut to_client).await {
+                                    Ok(bytes) => bytes_transferred_to_client += bytes,
+                                    Err(e) => {
+                                        log::error!("Failed to forward mongos reply to client {client_addr}: {e}");
+                                    }
+                                }
+                                break;
+                            }
+                            Ok(n) => {
+                                if let Some(written) = first_write.filter(|_| !first_reply_timed) {
+                                    first_reply_timed = true;
+                                    if let Ok(addr) = mongos_addr.parse() {
+                                        self.mongodb_proxy.report_first_reply(addr, written.elapsed());
+                                    }
+                                }
+                                mongos_framer.push(&mongos_buf[0..n]);
+                                match Self::queue_messages(&mut mongos_framer, &mut to_client, |message| {
+                                    let message = self.process_mongos_reply(message);
+                                    self.track_reply(
+                                        &mut client_messages.pending_operations,
+                                        &message,
+                                        client_addr,
+                                        client_id,
+                                        mongos_addr,
+                                    );
+                                    message
+                                }) {
+                                    Ok(count) => {
+                                        replies += count;
+                                        log::trace!("Queued {count} replies from mongos for client {client_addr}");
+                                    }
+                                    Err(e) => {
+                                        log::error!("Failed to forward mongos reply to client {client_addr}: {e}");
+                                        break;
+                                    }
+                                }
+                            }
+                            Err(e) => {
+                                log::error!("Failed to read from mongos for client {client_addr}: {e}");
+                                mongos_failed = true;
+                                break;
+                            }
+                        }
+                    }
+                    // Queued operations -> Mongos
+                    result = Self::write_queued(&mut mongos_writer, &mut to_mongos), if !to_mongos.is_empty() => {
+                        match result {
+                            Ok(n) => {
+                                bytes_transferred_to_mongos += n as u64;
+                                first_write.get_or_insert_with(std::time::Instant::now);
+                            }
+                            Err(e) => {
+                                log::error!("Failed to forward client {client_addr} to mongos: {e}");
+                                mongos_failed = true;
+                                break;
+                            }
+                        }
+                    }
+                    // Queued replies -> Client
+                    result = Self::write_queued(&mut client_writer, &mut to_client), if !to_client.is_empty() => {
+                        match result {
+                            Ok(n) => bytes_transferred_to_client += n as u64,
+                            Err(e) => {
+                                log::error!("Failed to forward mongos reply to client {client_addr}: {e}");
+                                break;
+                            }
+                        }
+                    }
+                    _ = &mut idle, if self.idle_timeout.is_some() => {
+                        log::info!(
+                            "Closing MongoDB client {client_addr} after {}s without traffic",
+                            self.idle_timeout.unwrap_or_default().as_secs()
+                        );
+                        break;
+                    }
+                    _ = &mut drained => {
+                        log::info!(
+                            "Closing MongoDB client {client_addr}: drain of mongos {mongos_addr} timed out"
+                        );
+                        break;
+                    }
+                    _ = &mut unhealthy => {
+                        log::warn!("Closing MongoDB client {client_addr}: mongos {mongos_addr} is unhealthy");
+                        break;
+                    }
+                }
             }
-        } else {
-            log::warn!("Invalid client address format for cleanup: {}", client_addr);
         }
+
+        connection.update(bytes_transferred_to_mongos, bytes_transferred_to_client, operations);
+        log::info!(
+            "Data forwarding completed for client {client_addr}: {operations} operations ({bytes_transferred_to_mongos} bytes) to mongos, {replies} replies ({bytes_transferred_to_client} bytes) to client"
+        );
+        crate::logging::access(
+            &crate::logging::AccessRecord {
+                bytes_from_client: bytes_transferred_to_mongos,
+                bytes_to_client: bytes_transferred_to_client,
+                requests: operations,
+                ..crate::logging::AccessRecord::new("mongodb", client_addr, mongos_addr)
+            }
+            .with_duration(started.elapsed()),
+        );
+
+        mongos_failed
     }
 
-    /// Bidirectional TCP data forwarding between MongoDB client and mongos
-    async fn forward_tcp_data(
+    /// Relay a connection needing no message inspection with splice(2)
+    ///
+    /// Bytes move between the sockets without being copied through the
+    /// proxy, so operations are not counted and `max_message_size` is not
+    /// enforced. Falls back to `forward_tcp_data` when the sockets cannot be
+    /// spliced. Returns `true` if relaying ended on an I/O error with mongos.
+    async fn relay_tcp_data(
         &self,
-        mut client_stream: Stream,
+        client_stream: Stream,
         mut mongos_stream: Stream,
         client_addr: &str,
-    ) {
-        let mut client_buf = [0; 8192];
-        let mut mongos_buf = [0; 8192];
+        client_id: &ClientIdentifier,
+        mongos_addr: &str,
+        initial: &[u8],
+    ) -> bool {
+        use crate::core::splice::{Splice, SpliceError, SpliceSocket};
+
+        let relay = (|| {
+            Ok::<_, std::io::Error>((
+                SpliceSocket::new(client_stream.id())?,
+                SpliceSocket::new(mongos_stream.id())?,
+                Splice::new()?,
+                Splice::new()?,
+            ))
+        })();
+        let (client, mongos, mut to_mongos, mut to_client) = match relay {
+            Ok(relay) => relay,
+            Err(e) => {
+                log::debug!("Copying client {client_addr} through the proxy, splice is unavailable: {e}");
+                return self
+                    .forward_tcp_data(client_stream, mongos_stream, client_addr, client_id, mongos_addr, initial)
+                    .await;
+            }
+        };
+
+        let started = std::time::Instant::now();
+        let connection = crate::core::connections::global().register("mongodb", client_addr, mongos_addr);
         let mut bytes_transferred_to_mongos = 0u64;
         let mut bytes_transferred_to_client = 0u64;
+        let mut mongos_failed = false;
+        let mut session_touched = std::time::Instant::now();
 
-        log::info!("Starting data forwarding for client: {}", client_addr);
+        log::info!("Starting zero-copy relay for client: {}", client_addr);
+
+        // The handshake was read ahead when clients are identified by it
+        if let Some(metadata) = ClientMetadata::from_handshake(initial) {
+            self.record_client_metadata(&connection, client_id, metadata).await;
+        }
+
+        // Bytes the client sent along with its PROXY protocol header
+        if !initial.is_empty() {
+            let written = async {
+                mongos_stream.write_all(initial).await?;
+                mongos_stream.flush().await
+            };
+            match written.await {
+                Ok(()) => bytes_transferred_to_mongos += initial.len() as u64,
+                Err(e) => {
+                    log::error!("Failed to forward client {client_addr} to mongos: {e}");
+                    mongos_failed = true;
+                }
+            }
+        }
+
+        let idle = tokio::time::sleep(self.idle_timeout.unwrap_or_default());
+        tokio::pin!(idle);
+        let drained = self.drain_timed_out(mongos_addr);
+        tokio::pin!(drained);
+        let unhealthy = self.closed_unhealthy(mongos_addr);
+        tokio::pin!(unhealthy);
+
+        if !mongos_failed {
+            loop {
+                if let Some(idle_timeout) = self.idle_timeout {
+                    idle.as_mut().reset(tokio::time::Instant::now() + idle_timeout);
+                }
+                connection.update(bytes_transferred_to_mongos, bytes_transferred_to_client, 0);
 
-        loop {
-            tokio::select! {
-                // Client -> Mongos
-                result = client_stream.read(&mut client_buf) => {
-                    match result {
+                tokio::select! {
+                    result = to_mongos.step(&client, &mongos) => match result {
                         Ok(0) => {
                             log::debug!("Client {} connection closed", client_addr);
                             break;
                         }
                         Ok(n) => {
                             bytes_transferred_to_mongos += n as u64;
-                            if let Err(e) = mongos_stream.write_all(&client_buf[0..n]).await {
-                                log::error!("Failed to write {n} bytes to mongos for client {client_addr}: {e}");
-                                break;
-                            }
-                            if let Err(e) = mongos_stream.flush().await {
-                                log::error!("Failed to flush to mongos for client {client_addr}: {e}");
-                                break;
+                            // Client traffic keeps the session affinity from expiring
+                            if session_touched.elapsed() >= SESSION_TOUCH_INTERVAL {
+                                session_touched = std::time::Instant::now();
+                                self.mongodb_proxy.touch_session(client_id).await;
                             }
-                            log::trace!("Forwarded {n} bytes from client {client_addr} to mongos");
                         }
-                        Err(e) => {
+                        Err(SpliceError::Read(e)) => {
                             log::error!("Failed to read from client {client_addr}: {e}");
                             break;
                         }
-                    }
-                }
-                // Mongos -> Client
-                result = mongos_stream.read(&mut mongos_buf) => {
-                    match result {
+                        Err(SpliceError::Write(e)) => {
+                            log::error!("Failed to forward client {client_addr} to mongos: {e}");
+                            mongos_failed = true;
+                            break;
+                        }
+                    },
+                    result = to_client.step(&mongos, &client) => match result {
                         Ok(0) => {
                             log::debug!("Mongos connection closed for client {}", client_addr);
                             break;
                         }
-                        Ok(n) => {
-                            bytes_transferred_to_client += n as u64;
-                            if let Err(e) = client_stream.write_all(&mongos_buf[0..n]).await {
-                                log::error!("Failed to write {n} bytes to client {client_addr}: {e}");
-                                break;
-                            }
-                            if let Err(e) = client_stream.flush().await {
-                                log::error!("Failed to flush to client {client_addr}: {e}");
-                                break;
-                            }
-                            log::trace!("Forwarded {n} bytes from mongos to client {client_addr}");
-                        }
-                        Err(e) => {
+                        Ok(n) => bytes_transferred_to_client += n as u64,
+                        Err(SpliceError::Read(e)) => {
                             log::error!("Failed to read from mongos for client {client_addr}: {e}");
+                            mongos_failed = true;
                             break;
                         }
+                        Err(SpliceError::Write(e)) => {
+                            log::error!("Failed to forward mongos reply to client {client_addr}: {e}");
+                            break;
+                        }
+                    },
+                    _ = &mut idle, if self.idle_timeout.is_some() => {
+                        log::info!(
+                            "Closing MongoDB client {client_addr} after {}s without traffic",
+                            self.idle_timeout.unwrap_or_default().as_secs()
+                        );
+                        break;
+                    }
+                    _ = &mut drained => {
+                        log::info!(
+                            "Closing MongoDB client {client_addr}: drain of mongos {mongos_addr} timed out"
+                        );
+                        break;
+                    }
+                    _ = &mut unhealthy => {
+                        log::warn!("Closing MongoDB client {client_addr}: mongos {mongos_addr} is unhealthy");
+                        break;
                     }
                 }
             }
         }
 
+        connection.update(bytes_transferred_to_mongos, bytes_transferred_to_client, 0);
         log::info!(
-            "Data forwarding completed for client {client_addr}: {bytes_transferred_to_mongos} bytes to mongos, {bytes_transferred_to_client} bytes to client"
+            "Zero-copy relay completed for client {client_addr}: {bytes_transferred_to_mongos} bytes to mongos, {bytes_transferred_to_client} bytes to client"
+        );
+        crate::logging::access(
+            &crate::logging::AccessRecord {
+                bytes_from_client: bytes_transferred_to_mongos,
+                bytes_to_client: bytes_transferred_to_client,
+                ..crate::logging::AccessRecord::new("mongodb", client_addr, mongos_addr)
+            }
+            .with_duration(started.elapsed()),
+        );
+
+        mongos_failed
+    }
+
+    /// Attribute a connection to the application its handshake names, and
+    /// attach the handshake's metadata to the client's session
+    async fn record_client_metadata(
+        &self,
+        connection: &crate::core::connections::ConnectionStats,
+        client_id: &ClientIdentifier,
+        metadata: ClientMetadata,
+    ) {
+        log::debug!(
+            "MongoDB client {client_id} runs application {:?} with driver {:?} {:?}",
+            metadata.application,
+            metadata.driver_name,
+            metadata.driver_version
+        );
+        if let Some(application) = &metadata.application {
+            connection.set_application(application);
+        }
+        self.mongodb_proxy
+            .get_affinity_manager()
+            .set_client_metadata(client_id, metadata)
+            .await;
+    }
+
+    /// Whether connections are relayed with splice(2): zero copy is enabled
+    /// and nothing needs to see the messages
+    fn relays_zero_copy(&self) -> bool {
+        self.zero_copy
+            && self.compressors.is_none()
+            && self.announce_addr.is_none()
+            && !self.tracks_operations()
+            && self.chaos.is_none()
+            && self.mirror.is_none()
+            && self.capture.is_none()
+            && !self.mongodb_proxy.routes_read_preferences()
+    }
+
+    /// Completes when the drain of `mongos_addr` times out, closing its
+    /// connections
+    async fn drain_timed_out(&self, mongos_addr: &str) {
+        match (&self.drains, mongos_addr.parse()) {
+            (Some(drains), Ok(addr)) => drains.force_closed(addr).await,
+            _ => std::future::pending().await,
+        }
+    }
+
+    /// Completes when `mongos_addr` turns unhealthy, unless its connections
+    /// are left to drain
+    async fn closed_unhealthy(&self, mongos_addr: &str) {
+        match (self.mongodb_proxy.get_config().on_backend_unhealthy, mongos_addr.parse()) {
+            (crate::config::UnhealthyBackendAction::Close, Ok(addr)) => {
+                self.mongodb_proxy.became_unhealthy(addr).await
+            }
+            _ => std::future::pending().await,
+        }
+    }
+
+    /// Move every complete message buffered in `framer` to `queue`, passing
+    /// each through `process` first
+    ///
+    /// Returns the number of messages queued.
+    fn queue_messages(
+        framer: &mut MessageFramer,
+        queue: &mut WriteQueue,
+        mut process: impl FnMut(Bytes) -> Bytes,
+    ) -> Result<u64, crate::modes::mongodb::wire::WireError> {
+        let mut count = 0u64;
+        while let Some((_header, message)) = framer.next_message()? {
+            queue.push(process(message));
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Error reply to the request a client framing `error` stopped at, for
+    /// requests over `max_message_size` rather than garbled streams
+    fn framing_error_reply(
+        framer: &MessageFramer,
+        error: &crate::modes::mongodb::wire::WireError,
+    ) -> Option<Bytes> {
+        use crate::modes::mongodb::wire::{self, WireError};
+
+        match error {
+            WireError::MessageTooLarge { .. } => {
+                let header = framer.peek_header()?;
+                // Server error code of BSONObjectTooLarge
+                Some(wire::error_reply(header.request_id, 10334, "BSONObjectTooLarge", &error.to_string()))
+            }
+            WireError::InvalidLength(_) => None,
+        }
+    }
+
+    /// Prepare a client message for mongos, then mirror, capture and track
+    /// the message sent
+    fn handle_client_message(&self, state: &mut ClientMessages, message: Bytes) -> Bytes {
+        let message = self.process_client_message(state.client_ip, message);
+        if let Some(mirror) = &mut state.mirror {
+            mirror.send(&message);
+        }
+        if let Some(capture) = &self.capture {
+            capture.record(state.connection_id, &message);
+        }
+        if std::mem::take(&mut state.awaiting_handshake) {
+            state.handshake_metadata = ClientMetadata::from_handshake(&message);
+        }
+        self.track_operation(
+            &mut state.pending_operations,
+            &message,
+            state.client_addr,
+            state.client_id,
+            state.mongos_addr,
+        );
+        message
+    }
+
+    /// Apply the compression policy to a client's hello handshake and learn
+    /// the read preference of its commands, when read preference routes are
+    /// configured and the client address is known
+    ///
+    /// Compressed commands are inspected decompressed but forwarded as sent.
+    fn process_client_message(
+        &self,
+        client_ip: Option<std::net::IpAddr>,
+        message: Bytes,
+    ) -> Bytes {
+        use crate::modes::mongodb::{compression, wire};
+
+        let message = match &self.compressors {
+            Some(allowed) => match compression::rewrite_hello(&message, allowed) {
+                Some(rewritten) => {
+                    log::debug!("Limited client hello compressors to {allowed:?}");
+                    rewritten
+                }
+                None => message,
+            },
+            None => message,
+        };
+
+        if let Some(client_ip) = client_ip {
+            let op_code = wire::MsgHeader::parse(&message).map(|header| header.op_code);
+            let mode = match op_code {
+                Some(wire::OpCode::Compressed) => {
+                    compression::decompress(&message, self.max_message_size)
+                        .map_err(|e| log::debug!("Cannot inspect compressed command: {e}"))
+                        .ok()
+                        .and_then(|original| wire::read_preference(&original))
+                }
+                _ => wire::read_preference(&message),
+            };
+            if let Some(mode) = mode {
+                self.mongodb_proxy.record_read_preference(client_ip, mode);
+            }
+        }
+        message
+    }
+
+    /// Apply the proxy's policy to a mongos reply before it is forwarded:
+    /// hello replies name the announced address as the only member
+    fn process_mongos_reply(&self, message: Bytes) -> Bytes {
+        let Some(announce_addr) = &self.announce_addr else {
+            return message;
+        };
+        match crate::modes::mongodb::handshake::rewrite_hello_reply(&message, announce_addr) {
+            Some(rewritten) => {
+                log::debug!("Announced {announce_addr} in place of the members of a hello reply");
+                rewritten
+            }
+            None => message,
+        }
+    }
+
+    /// Whether operations are matched with their replies: to attribute large
+    /// replies, audit commands, time them or follow cursors and transactions
+    fn tracks_operations(&self) -> bool {
+        self.large_payloads.is_some()
+            || self.audit
+            || self.slow_operations.is_some()
+            || self.cursors.is_some()
+            || self.transactions.is_some()
+    }
+
+    /// Remember the operation of a client message until mongos replies, when
+    /// operations are tracked
+    fn track_operation(
+        &self,
+        pending: &mut HashMap<i32, PendingOperation>,
+        message: &Bytes,
+        client_addr: &str,
+        client_id: &ClientIdentifier,
+        mongos_addr: &str,
+    ) {
+        use crate::modes::mongodb::{compression, wire};
+
+        if !self.tracks_operations() {
+            return;
+        }
+        let Some(header) = wire::MsgHeader::parse(message) else {
+            return;
+        };
+        let message = match header.op_code {
+            wire::OpCode::Compressed => match compression::decompress(message, self.max_message_size) {
+                Ok(original) => original,
+                Err(_) => return,
+            },
+            _ => message.clone(),
+        };
+        let Some(operation) = wire::operation(&message) else {
+            return;
+        };
+        let cursor = match operation.command.as_str() {
+            "getMore" => wire::get_more_cursor(&message),
+            _ => None,
+        };
+        if let Some(cursors) = &self.cursors {
+            if let Some(id) = cursor {
+                cursors.used(mongos_addr, id);
+            }
+            if operation.command == "killCursors" {
+                cursors.closed(mongos_addr, &wire::killed_cursors(&message));
+            }
+        }
+        if let Some(transactions) = &self.transactions {
+            Self::track_transaction(transactions, &message, client_addr, client_id, mongos_addr);
+        }
+        // Fire-and-forget writes get no reply; a client that never reads its
+        // replies must not grow the map without bound
+        if wire::more_to_come(&message) || pending.len() >= MAX_PENDING_OPERATIONS {
+            if self.audit {
+                Self::audit_operation(&operation, client_addr, mongos_addr, None);
+            }
+            return;
+        }
+        pending.insert(
+            header.request_id,
+            PendingOperation {
+                operation,
+                sent: std::time::Instant::now(),
+                answered: false,
+                cursor,
+            },
         );
     }
+
+    /// Pin a transaction a client starts to its mongos until it is
+    /// committed or aborted
+    fn track_transaction(
+        transactions: &crate::modes::mongodb::transactions::TransactionPinning,
+        message: &[u8],
+        client_addr: &str,
+        client_id: &ClientIdentifier,
+        mongos_addr: &str,
+    ) {
+        use crate::modes::mongodb::wire::{self, TransactionStep};
+
+        let Some(transaction) = wire::transaction(message) else {
+            return;
+        };
+        let lsid = &transaction.lsid;
+        match transaction.step {
+            TransactionStep::Start => {
+                log::debug!("Pinning transaction {} of client {client_addr} to mongos {mongos_addr}", transaction.txn_number);
+                transactions.started(client_id, lsid, transaction.txn_number, mongos_addr);
+            }
+            TransactionStep::Continue => {
+                // Connections opened before the transaction cannot be moved
+                if let Some(backend) = transactions.backend_of_transaction(client_id, lsid, transaction.txn_number) {
+                    if backend != mongos_addr {
+                        log::warn!(
+                            "Transaction {} of client {client_addr} started on mongos {backend} continues on {mongos_addr}",
+                            transaction.txn_number
+                        );
+                    }
+                }
+            }
+            TransactionStep::Commit | TransactionStep::Abort => {
+                transactions.ended(client_id, lsid, transaction.txn_number);
+            }
+        }
+    }
+
+    /// Attribute a mongos reply to its operation: record its size when large
+    /// replies are tracked, audit and time the operation on its first reply,
+    /// and follow the cursor it returns
+    fn track_reply(
+        &self,
+        pending: &mut HashMap<i32, PendingOperation>,
+        message: &Bytes,
+        client_addr: &str,
+        client_id: &ClientIdentifier,
+        mongos_addr: &str,
+    ) {
+        use crate::modes::mongodb::{compression, wire};
+
+        if !self.tracks_operations() {
+            return;
+        }
+        let Some(header) = wire::MsgHeader::parse(message) else {
+            return;
+        };
+        let Some(mut pending_operation) = pending.remove(&header.response_to) else {
+            return;
+        };
+        let operation = &pending_operation.operation;
+        if let Some(large_payloads) = &self.large_payloads {
+            let name = format!("{} {}", operation.command, operation.namespace());
+            large_payloads.record(&name, &operation.command, message.len(), client_addr);
+        }
+        if !pending_operation.answered {
+            let elapsed = pending_operation.sent.elapsed();
+            if self.audit {
+                Self::audit_operation(operation, client_addr, mongos_addr, Some(elapsed));
+            }
+            if let Some(slow_operations) = &self.slow_operations {
+                slow_operations.record(operation, mongos_addr, client_addr, elapsed);
+            }
+            pending_operation.answered = true;
+        }
+        if let Some(cursors) = &self.cursors {
+            let reply = match header.op_code {
+                wire::OpCode::Compressed => compression::decompress(message, self.max_message_size).ok(),
+                _ => Some(message.clone()),
+            };
+            match reply.as_deref().and_then(wire::cursor_id) {
+                Some(0) => {
+                    if let Some(id) = pending_operation.cursor {
+                        cursors.closed(mongos_addr, &[id]);
+                    }
+                }
+                Some(id) => cursors.opened(client_id, mongos_addr, id),
+                None => {}
+            }
+        }
+        // Exhaust cursors answer each reply with the next; compressed replies
+        // are not inspected for it
+        if wire::more_to_come(message) {
+            pending.insert(header.request_id, pending_operation);
+        }
+    }
+
+    /// Write an operation and how long mongos took to answer it to the
+    /// access log
+    fn audit_operation(
+        operation: &crate::modes::mongodb::wire::Operation,
+        client_addr: &str,
+        mongos_addr: &str,
+        duration: Option<std::time::Duration>,
+    ) {
+        crate::logging::audit(&crate::logging::AuditRecord {
+            mode: "mongodb",
+            client_addr: client_addr.to_string(),
+            backend: mongos_addr.to_string(),
+            command: operation.command.clone(),
+            database: operation.database.clone(),
+            collection: operation.collection.clone(),
+            duration_ms: duration.map(|duration| duration.as_millis() as u64),
+        });
+    }
+
+    /// Write the front of `queue` to `target`, flushing once it is empty
+    ///
+    /// Returns the number of bytes written.
+    async fn write_queued<W: AsyncWrite + Unpin>(
+        target: &mut W,
+        queue: &mut WriteQueue,
+    ) -> std::io::Result<usize> {
+        let n = target.write(queue.front()).await?;
+        if n == 0 {
+            return Err(std::io::ErrorKind::WriteZero.into());
+        }
+        queue.consume(n);
+        if queue.is_empty() {
+            target.flush().await?;
+        }
+        Ok(n)
+    }
+
+    /// Write everything left in `queue` to `target`
+    async fn drain_queue<W: AsyncWrite + Unpin>(
+        target: &mut W,
+        queue: &mut WriteQueue,
+    ) -> std::io::Result<u64> {
+        let mut bytes = 0u64;
+        while !queue.is_empty() {
+            bytes += Self::write_queued(target, queue).await? as u64;
+        }
+        Ok(bytes)
+    }
 }
 
 #[async_trait]
 impl ServerApp for MongoDBTcpProxy {
+    #[tracing::instrument(
+        name = "mongodb.connection",
+        skip_all,
+        fields(client.addr = tracing::field::Empty)
+    )]
     async fn process_new(
         self: &Arc<Self>,
-        client_stream: Stream,
+        mut client_stream: Stream,
         _shutdown: &ShutdownWatch,
     ) -> Option<Stream> {
         // Get client address for session affinity
-        let client_addr = match client_stream
+        let mut client_addr = match client_stream
             .get_socket_digest()
             .and_then(|digest| digest.peer_addr().cloned())
         {
@@ -312,59 +2009,377 @@ impl ServerApp for MongoDBTcpProxy {
             }
         };
 
-        log::info!("New MongoDB client connection from: {}", client_addr);
+        // Held until the connection ends; past the limit the client is dropped
+        let _permit = match &self.connection_limiter {
+            Some(limiter) => match limiter.acquire().await {
+                Some(permit) => Some(permit),
+                None => {
+                    log::warn!(
+                        "Rejecting MongoDB client {client_addr}: max_connections ({}) reached",
+                        limiter.max_connections()
+                    );
+                    return None;
+                }
+            },
+            None => None,
+        };
+
+        // Behind another load balancer the real client comes from the PROXY header
+        let mut initial = Vec::new();
+        if self.proxy_protocol {
+            match crate::core::proxy_protocol::read_header(&mut client_stream).await {
+                Ok((header, remaining)) => {
+                    if let Some(source) = header.source() {
+                        log::debug!("PROXY header from {client_addr}: client is {source}");
+                        client_addr = source.to_string();
+                    }
+                    initial = remaining;
+                }
+                Err(e) => {
+                    log::warn!("Rejecting MongoDB client {client_addr}: {e}");
+                    return None;
+                }
+            }
+        }
 
-        // Select backend mongos
-        let backend_peer = match self.select_backend(&client_addr).await {
-            Ok(peer) => peer,
+        if let Some(access_control) = &self.access_control {
+            if !access_control.check(&client_addr) {
+                log::warn!("Rejecting MongoDB client {client_addr}: denied by access control lists");
+                return None;
+            }
+        }
+
+        log::info!("New MongoDB client connection from: {}", client_addr);
+        tracing::Span::current().record("client.addr", client_addr.as_str());
+
+        // Identifying clients by their handshake needs it before a mongos is chosen
+        let by_handshake = self.mongodb_proxy.identifies_by_handshake();
+        if by_handshake {
+            if let Err(e) =
+                Self::read_handshake(&mut client_stream, &mut initial, self.max_message_size).await
+            {
+                log::warn!("Closing MongoDB client {client_addr} before its handshake: {e}");
+                return None;
+            }
+        }
+        let client_id = match client_addr.parse() {
+            Ok(addr) => self
+                .mongodb_proxy
+                .identify_client(addr, by_handshake.then_some(initial.as_slice())),
             Err(e) => {
-                log::error!("Failed to select backend: {e}");
+                log::error!("Failed to select backend: invalid client address {client_addr}: {e}");
                 return None;
             }
         };
-
-        // Connect to mongos
-        let mongos_stream = match self.connector.new_stream(&backend_peer).await {
-            Ok(stream) => stream,
+
+        // Select a mongos and connect, moving on to other mongos while
+        // connecting fails
+        let mut failed: Vec<std::net::SocketAddr> = Vec::new();
+        let queue_deadline = self
+            .backend_limits
+            .as_ref()
+            .map(|backend_limits| std::time::Instant::now() + backend_limits.queue_timeout());
+        let (backend_peer, backend_addr, mongos_stream, _backend_permit) = loop {
+            let mut released = self.backend_limits.as_ref().map(|backend_limits| backend_limits.subscribe());
+            let backend_peer = match self.select_backend(&client_addr, &client_id, &failed).await {
+                Ok(peer) => peer,
+                Err(e) => {
+                    // Every candidate at its limit: wait for a connection to close
+                    if let (Some(backend_limits), Some(released), Some(deadline)) =
+                        (&self.backend_limits, released.as_mut(), queue_deadline)
+                    {
+                        if !backend_limits.full().is_empty() {
+                            if crate::core::backend_limit::BackendLimits::wait_for_release(released, deadline).await {
+                                continue;
+                            }
+                            log::warn!(
+                                "Rejecting MongoDB client {client_addr}: every mongos is at max_connections_per_backend ({})",
+                                backend_limits.max_connections()
+                            );
+                            crate::metrics::global()
+                                .counter_with_labels(
+                                    "puerta_backend_limit_rejected_total",
+                                    "Client connections rejected with every backend at its connection limit",
+                                    &[("mode", "mongodb")],
+                                )
+                                .inc();
+                            return None;
+                        }
+                    }
+                    log::error!("Failed to select backend: {e}");
+                    return None;
+                }
+            };
+
+            let backend_addr = backend_peer.address().to_string().parse::<std::net::SocketAddr>().ok();
+
+            // Another connection may have taken the last slot since selection
+            let backend_permit = match (&self.backend_limits, backend_addr) {
+                (Some(backend_limits), Some(addr)) => match backend_limits.try_acquire(addr) {
+                    Some(permit) => Some(permit),
+                    None => continue,
+                },
+                _ => None,
+            };
+
+            // Connect to mongos, timing the connect for outlier detection
+            let connect_started = std::time::Instant::now();
+            let spare = self
+                .spare_connections
+                .as_ref()
+                .and_then(|spares| spares.take(&backend_peer.address().to_string()));
+            let connected = match spare {
+                Some(stream) => Ok(stream),
+                None => self.connector.new_stream(&backend_peer).await,
+            };
+            match connected {
+                Ok(stream) => {
+                    log::info!(
+                        "Established connection to mongos: {}",
+                        backend_peer.address()
+                    );
+                    // Track live connections per mongos for least-connections balancing
+                    if let Some(addr) = backend_addr {
+                        self.mongodb_proxy.report_success(addr, connect_started.elapsed());
+                        self.mongodb_proxy.connection_opened(addr).await;
+                    }
+                    break (backend_peer, backend_addr, stream, backend_permit);
+                }
+                Err(e) => {
+                    log::error!(
+                        "Failed to connect to mongos {}: {}",
+                        backend_peer.address(),
+                        e
+                    );
+                    // Drop the affinity to the failed mongos so a retry selects another
+                    self.cleanup_session(&client_id).await;
+                    let addr = backend_addr?;
+                    self.mongodb_proxy.report_failure(addr).await;
+                    failed.push(addr);
+                    if failed.len() > self.retry_attempts as usize {
+                        return None;
+                    }
+                    log::warn!(
+                        "Retrying MongoDB client {client_addr} on another mongos ({}/{})",
+                        failed.len(),
+                        self.retry_attempts
+                    );
+                }
+            }
+        };
+
+        // Forward MongoDB Wire Protocol data bidirectionally
+        let mongos_addr = backend_peer.address().to_string();
+        let mongos_failed = match &self.uring {
+            _ if self.relays_zero_copy() => {
+                self.relay_tcp_data(client_stream, mongos_stream, &client_addr, &client_id, &mongos_addr, &initial)
+                    .await
+            }
+            Some(uring) => {
+                // The streams keep their sockets open while the ring does the I/O
+                let (client_fd, mongos_fd) = (client_stream.id(), mongos_stream.id());
+                self.forward_tcp_data(
+                    uring.stream(client_stream, client_fd),
+                    uring.stream(mongos_stream, mongos_fd),
+                    &client_addr,
+                    &client_id,
+                    &mongos_addr,
+                    &initial,
+                )
+                .await
+            }
+            None => {
+                self.forward_tcp_data(client_stream, mongos_stream, &client_addr, &client_id, &mongos_addr, &initial)
+                    .await
+            }
+        };
+
+        if let Some(addr) = backend_addr {
+            self.mongodb_proxy.connection_closed(addr).await;
+            if mongos_failed {
+                self.mongodb_proxy.report_failure(addr).await;
+            }
+        }
+
+        // Affinities by handshake outlive the connection, serving the
+        // client's other connections until they expire
+        if matches!(client_id, ClientIdentifier::SocketAddr(_)) {
+            self.cleanup_session(&client_id).await;
+        }
+
+        None
+    }
+}
+
+/// Main puerta instance using Pingora framework for TCP proxying
+pub struct Puerta {
+    config: PuertaConfig,
+    server: Option<Server>,
+    config_reloader: Option<Arc<ConfigReloader>>,
+    admin_config: Option<crate::config::AdminConfig>,
+    statsd_config: Option<crate::config::StatsdConfig>,
+    acl_config: Option<crate::config::AclConfig>,
+    discovery_config: Option<crate::config::DiscoveryConfig>,
+    chaos_config: Option<crate::config::ChaosConfig>,
+    maintenance: Vec<crate::config::MaintenanceWindowConfig>,
+    /// Additional proxy instances run alongside the primary one
+    instances: Vec<ProxyInstance>,
+    /// Backends drained through the admin API, across all instances
+    drains: Arc<crate::core::drain::Drains>,
+}
+
+impl Puerta {
+    pub fn new(config: PuertaConfig) -> Self {
+        Self {
+            config,
+            server: None,
+            config_reloader: None,
+            admin_config: None,
+            statsd_config: None,
+            acl_config: None,
+            discovery_config: None,
+            chaos_config: None,
+            maintenance: Vec::new(),
+            instances: Vec::new(),
+            drains: Arc::default(),
+        }
+    }
+
+    /// Serve the admin API
+    pub fn with_admin_config(mut self, admin_config: Option<crate::config::AdminConfig>) -> Self {
+        self.admin_config = admin_config;
+        self
+    }
+
+    /// Push metrics to a StatsD agent
+    pub fn with_statsd_config(mut self, statsd_config: Option<crate::config::StatsdConfig>) -> Self {
+        self.statsd_config = statsd_config;
+        self
+    }
+
+    /// Restrict client connections to the given IP allow and deny lists
+    pub fn with_acl_config(mut self, acl_config: Option<crate::config::AclConfig>) -> Self {
+        self.acl_config = acl_config;
+        self
+    }
+
+    /// Discover backends from DNS, Consul or etcd instead of the static
+    /// endpoint list
+    pub fn with_discovery_config(
+        mut self,
+        discovery_config: Option<crate::config::DiscoveryConfig>,
+    ) -> Self {
+        self.discovery_config = discovery_config;
+        self
+    }
+
+    /// Inject faults into a share of client requests
+    pub fn with_chaos_config(mut self, chaos_config: Option<crate::config::ChaosConfig>) -> Self {
+        self.chaos_config = chaos_config;
+        self
+    }
+
+    /// Drain backends during their recurring maintenance windows
+    pub fn with_maintenance(mut self, maintenance: Vec<crate::config::MaintenanceWindowConfig>) -> Self {
+        self.maintenance = maintenance;
+        self
+    }
+
+    /// Serve an additional proxy instance from the same Pingora server
+    pub fn with_proxy_instance(mut self, instance: ProxyInstance) -> Self {
+        self.instances.push(instance);
+        self
+    }
+
+    /// Start backend discovery when configured for the primary instance,
+    /// or resolution of `static_endpoints` when some are given by hostname
+    ///
+    /// The receiver initially holds the discovered backends, or the IP
+    /// endpoints of `static_endpoints` if the first lookup fails or finds
+    /// none, and then publishes every change. The primary instance resolves
+    /// the hostnames `endpoints_of` finds in reloaded configurations.
+    fn backend_discovery(
+        &self,
+        static_endpoints: Vec<Endpoint>,
+        is_primary: bool,
+        endpoints_of: crate::discovery::hostnames::EndpointsOf,
+    ) -> Option<watch::Receiver<Vec<crate::discovery::DiscoveredEndpoint>>> {
+        let discovery = match &self.discovery_config {
+            Some(discovery_config) if is_primary => crate::discovery::Discovery::new(discovery_config.clone()),
+            _ if Endpoint::any_hostname(&static_endpoints) => {
+                let mut source = crate::discovery::HostnameDiscovery::new(static_endpoints.clone());
+                if let (true, Some(reloader)) = (is_primary, &self.config_reloader) {
+                    source = source.with_reloads(reloader.subscribe(), endpoints_of);
+                }
+                crate::discovery::Discovery::from_source(
+                    Box::new(source),
+                    std::time::Duration::from_secs(self.config.dns_refresh_sec),
+                )
+            }
+            _ => return None,
+        };
+        let source = discovery.describe();
+        let initial = match discovery.resolve_blocking() {
+            Ok(discovered) if !discovered.is_empty() => {
+                log::info!("Discovered backends from {source}: {discovered:?}");
+                discovered
+            }
+            Ok(_) => {
+                log::warn!("{source} returned no backends, starting with the static list");
+                Vec::new()
+            }
             Err(e) => {
-                log::error!(
-                    "Failed to connect to mongos {}: {}",
-                    backend_peer.address(),
-                    e
-                );
-                self.cleanup_session(&client_addr).await;
-                return None;
+                log::warn!("Discovery from {source} failed, starting with the static list: {e}");
+                Vec::new()
             }
         };
+        let initial = if initial.is_empty() {
+            static_endpoints
+                .into_iter()
+                .filter(|endpoint| !endpoint.is_hostname())
+                .map(|endpoint| crate::discovery::DiscoveredEndpoint::new(endpoint, true))
+                .collect()
+        } else {
+            initial
+        };
+        Some(discovery.spawn_watcher(initial))
+    }
 
-        log::info!(
-            "Established connection to mongos: {}",
-            backend_peer.address()
-        );
-
-        // Forward MongoDB Wire Protocol data bidirectionally
-        self.forward_tcp_data(client_stream, mongos_stream, &client_addr)
-            .await;
-
-        // Clean up session affinity
-        self.cleanup_session(&client_addr).await;
-
-        None
+    /// Fault injection for the instances in `mode`, when enabled
+    fn chaos(&self, mode: &'static str) -> Option<Arc<crate::core::chaos::Chaos>> {
+        crate::core::chaos::Chaos::from_config(self.chaos_config.as_ref(), mode).map(Arc::new)
     }
-}
 
-/// Main puerta instance using Pingora framework for TCP proxying
-pub struct Puerta {
-    config: PuertaConfig,
-    server: Option<Server>,
-}
+    /// The io_uring MongoDB connections forward over, when enabled and
+    /// clients connect without TLS
+    fn uring(&self) -> Option<Arc<crate::core::uring::Uring>> {
+        if !self.config.tuning.io_uring {
+            return None;
+        }
+        if self.config.tls.is_some() {
+            log::warn!("io_uring forwarding is not used with TLS clients");
+            return None;
+        }
+        crate::core::uring::Uring::shared()
+    }
 
-impl Puerta {
-    pub fn new(config: PuertaConfig) -> Self {
-        Self {
-            config,
-            server: None,
+    /// Build the access control shared by all connections, following
+    /// configuration reloads when a reloader is set
+    fn access_control(&self) -> Result<Arc<crate::acl::AccessControl>, Box<dyn Error + Send + Sync>> {
+        let access_control = Arc::new(
+            crate::acl::AccessControl::from_config(self.acl_config.as_ref())
+                .map_err(|e| format!("Invalid access control lists: {e}"))?,
+        );
+        if let Some(reloader) = &self.config_reloader {
+            access_control.spawn_reload_watcher(reloader.subscribe());
         }
+        Ok(access_control)
+    }
+
+    /// Enable configuration hot reload on SIGHUP
+    pub fn with_config_reloader(mut self, reloader: Arc<ConfigReloader>) -> Self {
+        self.config_reloader = Some(reloader);
+        self
     }
 
     /// Get the current configuration
@@ -372,6 +2387,11 @@ impl Puerta {
         &self.config
     }
 
+    /// Get the additional proxy instances
+    pub fn instances(&self) -> &[ProxyInstance] {
+        &self.instances
+    }
+
     /// Initialize Pingora server with daemon support
     pub fn initialize(
         &mut self, 
@@ -380,11 +2400,14 @@ impl Puerta {
         error_log: Option<std::path::PathBuf>,
         upgrade_sock: std::path::PathBuf
     ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut server_conf = pingora_core::server::configuration::ServerConf::default();
+        if let Some(worker_threads) = self.config.worker_threads {
+            server_conf.threads = worker_threads;
+        }
+
         // Create server configuration if daemon mode is enabled
         let server = if let Some(opt) = opt {
             if opt.daemon {
-                // Create a basic server configuration for daemon mode
-                let mut server_conf = pingora_core::server::configuration::ServerConf::default();
                 server_conf.daemon = true;
                 server_conf.pid_file = pid_file.to_string_lossy().to_string();
                 server_conf.upgrade_sock = upgrade_sock.to_string_lossy().to_string();
@@ -394,9 +2417,13 @@ impl Puerta {
                 
                 // Create server with configuration
                 Server::new_with_opt_and_conf(Some(opt), server_conf)
+            } else if self.config.worker_threads.is_some() {
+                Server::new_with_opt_and_conf(Some(opt), server_conf)
             } else {
                 Server::new(Some(opt))?
             }
+        } else if self.config.worker_threads.is_some() {
+            Server::new_with_opt_and_conf(None, server_conf)
         } else {
             Server::new(None)?
         };
@@ -410,122 +2437,114 @@ impl Puerta {
         self.server.is_some()
     }
 
+    /// Build the services of the primary proxy and every additional
+    /// instance in one Pingora server and run it
     pub fn run(&mut self) -> Result<(), Box<dyn Error + Send + Sync>> {
-        if self.server.is_none() {
+        let Some(mut server) = self.server.take() else {
             return Err("Server not initialized. Call initialize() first.".into());
-        }
-
-        match &self.config.proxy_mode {
-            ProxyMode::MongoDB { .. } => self.run_mongodb_mode(),
-            ProxyMode::Redis { .. } => self.run_redis_mode(),
-        }
-    }
-
-    fn run_mongodb_mode(&mut self) -> Result<(), Box<dyn Error + Send + Sync>> {
-        log::info!("Starting Puerta in MongoDB TCP proxy mode using Pingora framework");
-
-        let mut server = self.server.take().unwrap();
+        };
         server.bootstrap();
 
-        // Extract MongoDB configuration
-        let (mongos_endpoints, session_affinity_enabled) = match &self.config.proxy_mode {
-            ProxyMode::MongoDB {
-                mongos_endpoints,
-                session_affinity_enabled,
-            } => (mongos_endpoints.clone(), *session_affinity_enabled),
-            _ => unreachable!("run_mongodb_mode called with non-MongoDB config"),
-        };
-
-        // Create MongoDB configuration
-        let mongodb_config = MongoDBConfig::new(
-            mongos_endpoints.clone(),
-            session_affinity_enabled,
-            300,
-            self.config.health_check_interval_ms / 1000,
-        )
-        .map_err(|e| format!("Invalid MongoDB configuration: {e}"))?;
-
-        // Create Pingora load balancer with mongos endpoints
-        let mut upstreams =
-            LoadBalancer::try_from_iter(mongos_endpoints.iter().map(|s| s.as_str()))?;
-
-        // Add health check for mongos instances using TCP health check
-        let health_checker = health_check::TcpHealthCheck::new();
-        upstreams.set_health_check(health_checker);
-        upstreams.health_check_frequency = Some(std::time::Duration::from_millis(
-            self.config.health_check_interval_ms,
-        ));
-
-        // Create background health check service
-        let background = pingora_core::services::background::background_service(
-            "mongodb-health-check",
-            upstreams,
-        );
-        let load_balancer = background.task();
-
-        // Create MongoDB TCP proxy service
-        let mongodb_proxy = futures::executor::block_on(MongoDBTcpProxy::new(load_balancer, mongodb_config))
-            .map_err(|e| format!("Failed to create MongoDB proxy: {e}"))?;
+        // Connection limits and access control span all instances
+        let connection_limiter = self.config.connection_limiter();
+        let access_control = self.access_control()?;
+        if let Some(chaos) = self.chaos_config.as_ref().filter(|chaos| chaos.enabled) {
+            log::warn!("Chaos fault injection is enabled: {chaos:?}");
+        }
+        let mut status_reporter = crate::admin::status::StatusReporter::new();
 
-        // Create TCP listening service for MongoDB Wire Protocol
-        let tcp_service = Service::with_listeners(
-            "MongoDB TCP Proxy".to_string(),
-            Listeners::tcp(&self.config.listen_addr),
-            mongodb_proxy,
+        let primary = ProxyInstance {
+            name: self.config.proxy_mode.service_name().to_string(),
+            listen_addr: self.config.listen_addr.clone(),
+            proxy_mode: self.config.proxy_mode.clone(),
+            cpu_affinity: self.config.tuning.cpu_affinity.clone(),
+        };
+        let instances: Vec<ProxyInstance> = std::iter::once(primary)
+            .chain(self.instances.iter().cloned())
+            .collect();
+
+        let mut context = crate::modes::InstanceContext::new(
+            &mut server,
+            &connection_limiter,
+            &access_control,
+            &mut status_reporter,
+            crate::admin::AdminRouter::new().with_metrics(),
         );
+        for (index, instance) in instances.iter().enumerate() {
+            // Discovery and configuration reloads only apply to the primary [proxy]
+            context.is_primary = index == 0;
+            instance.proxy_mode.add_instance(self, instance, &mut context)?;
+        }
+        let (mut admin_router, command_stats) = context.finish();
 
-        // Add services to server
-        server.add_service(tcp_service);
-        server.add_service(background);
-
-        log::info!(
-            "MongoDB TCP proxy listening on: {}",
-            self.config.listen_addr
-        );
-        log::info!("Proxying to mongos endpoints: {mongos_endpoints:?}");
+        if let Some(reloader) = &self.config_reloader {
+            Arc::clone(reloader).spawn_signal_handler();
+        }
+        // Reloads may add windows, so the scheduler runs whenever they can happen
+        if !self.maintenance.is_empty() || self.config_reloader.is_some() {
+            let mut scheduler =
+                crate::core::maintenance::MaintenanceScheduler::new(Arc::clone(&self.drains), &self.maintenance);
+            if let Some(reloader) = &self.config_reloader {
+                scheduler = scheduler.with_reloads(reloader.subscribe());
+            }
+            scheduler.spawn();
+        }
+        if let Some(statsd_config) = &self.statsd_config {
+            let mut exporter = crate::metrics::statsd::StatsdExporter::new(
+                statsd_config.clone(),
+                crate::metrics::global(),
+            );
+            if let Some(command_stats) = command_stats {
+                exporter = exporter.with_command_stats(command_stats);
+            }
+            exporter.spawn();
+        }
+        if let Some(admin_config) = &self.admin_config {
+            admin_router = Arc::new(status_reporter).register_admin_routes(admin_router);
+            admin_router = self.drains.register_admin_routes(admin_router);
+            admin_router = crate::core::connections::global().register_admin_routes(admin_router);
+            admin_router = crate::core::cpu::global().register_admin_routes(admin_router);
+            admin_router
+                .with_token(admin_config.token.clone())
+                .spawn(admin_config.listen_addr.clone());
+        }
 
         // Run the server (consume ownership)
         server.run_forever();
     }
-
-    fn run_redis_mode(&mut self) -> Result<(), Box<dyn Error + Send + Sync>> {
-        log::info!("Starting Puerta in Redis mode using RCProxy architecture");
-
-        // Extract Redis configuration
-        let (cluster_nodes, slot_refresh_interval_ms) = match &self.config.proxy_mode {
-            ProxyMode::Redis {
-                cluster_nodes,
-                slot_refresh_interval_ms,
-            } => (cluster_nodes.clone(), *slot_refresh_interval_ms),
-            _ => unreachable!("run_redis_mode called with non-Redis config"),
-        };
-
-        // Create Redis configuration
-        let redis_config = RedisConfig {
-            cluster_nodes,
-            slot_refresh_interval_sec: slot_refresh_interval_ms / 1000,
-            max_redirects: 3,
-            connection_timeout_ms: 5000,
-        };
-
-        let server = self.server.take().unwrap();
-        let redis_proxy = RedisClusterProxy::new(redis_config, server).with_health_check();
-        futures::executor::block_on(redis_proxy.run_redis_proxy())
-    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::modes::mongodb::handler::MongoDBHandler;
+    use crate::modes::mongodb::test_support;
+    use crate::modes::redis::handler::RedisHandler;
+    use crate::modes::redis::RedisConfig;
+
+    fn mongodb_mode(mongos_endpoints: Vec<String>) -> Box<dyn ProxyModeHandler> {
+        Box::new(MongoDBHandler::new(MongoDBConfig {
+            mongos_endpoints,
+            ..Default::default()
+        }))
+    }
+
+    fn redis_mode(
+        cluster_nodes: Vec<String>,
+        slot_refresh_interval_sec: u64,
+    ) -> Box<dyn ProxyModeHandler> {
+        Box::new(RedisHandler::new(RedisConfig {
+            cluster_nodes,
+            slot_refresh_interval_sec,
+            ..Default::default()
+        }))
+    }
 
     #[test]
     fn test_puerta_config_creation() {
         let config = PuertaConfig::new(
             "127.0.0.1:8080".to_string(),
-            ProxyMode::MongoDB {
-                mongos_endpoints: vec!["127.0.0.1:27017".to_string()],
-                session_affinity_enabled: true,
-            },
+            mongodb_mode(vec!["127.0.0.1:27017".to_string()]),
             1000,
             1000,
         )
@@ -542,10 +2561,7 @@ mod tests {
     fn test_puerta_config_validation_empty_listen_addr() {
         let result = PuertaConfig::new(
             "".to_string(),
-            ProxyMode::MongoDB {
-                mongos_endpoints: vec!["127.0.0.1:27017".to_string()],
-                session_affinity_enabled: true,
-            },
+            mongodb_mode(vec!["127.0.0.1:27017".to_string()]),
             1000,
             1000,
         );
@@ -558,10 +2574,7 @@ mod tests {
     fn test_puerta_config_validation_zero_health_check_interval() {
         let result = PuertaConfig::new(
             "127.0.0.1:8080".to_string(),
-            ProxyMode::MongoDB {
-                mongos_endpoints: vec!["127.0.0.1:27017".to_string()],
-                session_affinity_enabled: true,
-            },
+            mongodb_mode(vec!["127.0.0.1:27017".to_string()]),
             0,
             1000,
         );
@@ -577,10 +2590,7 @@ mod tests {
     fn test_puerta_config_validation_zero_max_connections() {
         let result = PuertaConfig::new(
             "127.0.0.1:8080".to_string(),
-            ProxyMode::MongoDB {
-                mongos_endpoints: vec!["127.0.0.1:27017".to_string()],
-                session_affinity_enabled: true,
-            },
+            mongodb_mode(vec!["127.0.0.1:27017".to_string()]),
             1000,
             0,
         );
@@ -596,10 +2606,7 @@ mod tests {
     fn test_puerta_config_validation_empty_mongos_endpoints() {
         let result = PuertaConfig::new(
             "127.0.0.1:8080".to_string(),
-            ProxyMode::MongoDB {
-                mongos_endpoints: vec![],
-                session_affinity_enabled: true,
-            },
+            mongodb_mode(vec![]),
             1000,
             1000,
         );
@@ -615,10 +2622,7 @@ mod tests {
     fn test_puerta_config_validation_empty_redis_nodes() {
         let result = PuertaConfig::new(
             "127.0.0.1:8080".to_string(),
-            ProxyMode::Redis {
-                cluster_nodes: vec![],
-                slot_refresh_interval_ms: 30000,
-            },
+            redis_mode(vec![], 30),
             1000,
             1000,
         );
@@ -634,10 +2638,7 @@ mod tests {
     fn test_puerta_config_validation_zero_slot_refresh_interval() {
         let result = PuertaConfig::new(
             "127.0.0.1:8080".to_string(),
-            ProxyMode::Redis {
-                cluster_nodes: vec!["127.0.0.1:6379".to_string()],
-                slot_refresh_interval_ms: 0,
-            },
+            redis_mode(vec!["127.0.0.1:6379".to_string()], 0),
             1000,
             1000,
         );
@@ -649,14 +2650,76 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_puerta_config_with_tls() {
+        let config = PuertaConfig::new(
+            "127.0.0.1:8443".to_string(),
+            mongodb_mode(vec!["127.0.0.1:27017".to_string()]),
+            1000,
+            1000,
+        )
+        .unwrap();
+        assert!(config.tls.is_none());
+        assert!(config.listeners().is_ok());
+
+        let config = config.with_tls(config::TlsConfig {
+            cert_path: "/etc/puerta/cert.pem".to_string(),
+            key_path: "/etc/puerta/key.pem".to_string(),
+        });
+        assert_eq!(config.tls.as_ref().unwrap().cert_path, "/etc/puerta/cert.pem");
+    }
+
+    #[test]
+    fn test_puerta_config_tuning() {
+        let config = PuertaConfig::new(
+            "127.0.0.1:6379".to_string(),
+            redis_mode(vec!["127.0.0.1:7000".to_string()], 1),
+            1000,
+            1000,
+        )
+        .unwrap();
+        assert!(config.socket_options().is_none());
+
+        let config = config.with_worker_threads(Some(4)).with_tuning(config::ServerTuningConfig {
+            reuse_port: true,
+            tcp_keepalive: Some(config::TcpKeepaliveConfig {
+                idle_sec: 60,
+                interval_sec: 10,
+                count: 6,
+                user_timeout_sec: Some(30),
+            }),
+            backend_tcp_keepalive: None,
+            io_uring: false,
+            cpu_affinity: Vec::new(),
+        });
+        assert_eq!(config.worker_threads, Some(4));
+        let socket_options = config.socket_options().unwrap();
+        let keepalive = socket_options.tcp_keepalive.unwrap();
+        assert_eq!(keepalive.idle, std::time::Duration::from_secs(60));
+        assert_eq!(keepalive.count, 6);
+        assert!(config.listeners().is_ok());
+    }
+
+    #[test]
+    fn test_puerta_config_idle_timeout() {
+        let config = PuertaConfig::new(
+            "127.0.0.1:6379".to_string(),
+            redis_mode(vec!["127.0.0.1:7000".to_string()], 1),
+            1000,
+            1000,
+        )
+        .unwrap();
+        assert_eq!(config.idle_timeout(), None);
+
+        let config = config.with_connection_timeout_sec(30);
+        assert_eq!(config.idle_timeout(), Some(std::time::Duration::from_secs(30)));
+    }
+
     #[test]
     fn test_redis_config_mode_name() {
         let config = PuertaConfig::new(
             "127.0.0.1:8080".to_string(),
-            ProxyMode::Redis {
-                cluster_nodes: vec!["127.0.0.1:6379".to_string()],
-                slot_refresh_interval_ms: 30000,
-            },
+            redis_mode(vec!["127.0.0.1:6379".to_string()], 30),
             1000,
             1000,
         )
@@ -669,10 +2732,7 @@ mod tests {
     fn test_puerta_creation() {
         let config = PuertaConfig::new(
             "127.0.0.1:8080".to_string(),
-            ProxyMode::MongoDB {
-                mongos_endpoints: vec!["127.0.0.1:27017".to_string()],
-                session_affinity_enabled: true,
-            },
+            mongodb_mode(vec!["127.0.0.1:27017".to_string()]),
             1000,
             1000,
         )
@@ -683,14 +2743,33 @@ mod tests {
         assert_eq!(puerta.config().listen_addr, "127.0.0.1:8080");
     }
 
+    #[test]
+    fn test_puerta_with_proxy_instances() {
+        let config = PuertaConfig::new(
+            "127.0.0.1:27017".to_string(),
+            mongodb_mode(vec!["10.0.0.1:27017".to_string()]),
+            1000,
+            1000,
+        )
+        .unwrap();
+        assert!(config.listeners_for("127.0.0.1:6379").is_ok());
+
+        let puerta = Puerta::new(config).with_proxy_instance(ProxyInstance {
+            name: "cache".to_string(),
+            listen_addr: "127.0.0.1:6379".to_string(),
+            proxy_mode: redis_mode(vec!["10.0.0.2:6379".to_string()], 30),
+            cpu_affinity: Vec::new(),
+        });
+        assert_eq!(puerta.instances().len(), 1);
+        assert_eq!(puerta.instances()[0].name, "cache");
+        assert_eq!(puerta.config().listen_addr, "127.0.0.1:27017");
+    }
+
     #[test]
     fn test_run_without_initialization() {
         let config = PuertaConfig::new(
             "127.0.0.1:8080".to_string(),
-            ProxyMode::MongoDB {
-                mongos_endpoints: vec!["127.0.0.1:27017".to_string()],
-                session_affinity_enabled: true,
-            },
+            mongodb_mode(vec!["127.0.0.1:27017".to_string()]),
             1000,
             1000,
         )
@@ -719,6 +2798,7 @@ mod tests {
             session_affinity_enabled: true,
             session_timeout_sec: 300,
             health_check_interval_sec: 10,
+            ..Default::default()
         };
 
         let proxy = MongoDBTcpProxy::new(load_balancer, config).await.unwrap();
@@ -748,6 +2828,7 @@ mod tests {
             session_affinity_enabled: true,
             session_timeout_sec: 300,
             health_check_interval_sec: 10,
+            ..Default::default()
         };
 
         let proxy = MongoDBTcpProxy::new(load_balancer, config).await.unwrap();
@@ -805,10 +2886,7 @@ mod tests {
     fn test_puerta_initialization() {
         let config = PuertaConfig::new(
             "127.0.0.1:8080".to_string(),
-            ProxyMode::MongoDB {
-                mongos_endpoints: vec!["127.0.0.1:27017".to_string()],
-                session_affinity_enabled: true,
-            },
+            mongodb_mode(vec!["127.0.0.1:27017".to_string()]),
             1000,
             1000,
         )
@@ -821,4 +2899,599 @@ mod tests {
         assert!(result.is_ok());
         assert!(puerta.is_initialized());
     }
+
+    #[tokio::test]
+    async fn test_reloadable_discovery_set_endpoints() {
+        let discovery = ReloadableDiscovery::new(&["127.0.0.1:27017".into()]).unwrap();
+        assert_eq!(discovery.backends().len(), 1);
+
+        discovery
+            .set_endpoints(&["127.0.0.1:27018".into(), "127.0.0.1:27019".into()])
+            .unwrap();
+        let (backends, _) = discovery.discover().await.unwrap();
+        assert_eq!(backends.len(), 2);
+    }
+
+    #[test]
+    fn test_reloadable_discovery_applies_weights() {
+        let discovery = ReloadableDiscovery::new(&[
+            Endpoint::new("127.0.0.1:27017", 3),
+            "127.0.0.1:27018".into(),
+        ])
+        .unwrap();
+
+        let mut weights: Vec<usize> = discovery.backends().iter().map(|b| b.weight).collect();
+        weights.sort();
+        assert_eq!(weights, vec![1, 3]);
+    }
+
+    #[tokio::test]
+    async fn test_reloadable_discovery_reports_unhealthy() {
+        let discovery =
+            ReloadableDiscovery::new(&["127.0.0.1:27017".into(), "127.0.0.1:27018".into()]).unwrap();
+        let (_, enabled) = discovery.discover().await.unwrap();
+        assert!(enabled.values().all(|enabled| *enabled));
+
+        let unhealthy: std::net::SocketAddr = "127.0.0.1:27018".parse().unwrap();
+        discovery.set_unhealthy(&HashSet::from([unhealthy]));
+        let (backends, enabled) = discovery.discover().await.unwrap();
+        for backend in &backends {
+            let expected = backend.addr.to_string() != "127.0.0.1:27018";
+            assert_eq!(enabled[&backend.hash_key()], expected);
+        }
+    }
+
+    #[test]
+    fn test_reloadable_discovery_invalid_endpoint_keeps_current() {
+        let discovery = ReloadableDiscovery::new(&["127.0.0.1:27017".into()]).unwrap();
+
+        assert!(discovery.set_endpoints(&["not-an-address".into()]).is_err());
+        assert_eq!(discovery.backends().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_queued_messages_respect_watermarks() {
+        use crate::core::frontend::BufferConfig;
+
+        // Two 100-byte messages and the first half of a third
+        let mut message = vec![0u8; 100];
+        message[..4].copy_from_slice(&100i32.to_le_bytes());
+        let mut framer = MessageFramer::new(1024);
+        framer.push(&message);
+        framer.push(&message);
+        framer.push(&message[..50]);
+
+        let mut queue = WriteQueue::new(&BufferConfig::new(64, 150));
+        assert_eq!(MongoDBTcpProxy::queue_messages(&mut framer, &mut queue, |message| message).unwrap(), 2);
+        assert_eq!(queue.len(), 200);
+        assert!(!queue.accepts_reads());
+
+        // A peer that only takes 64 bytes at a time drains the queue gradually
+        let (mut writer, mut reader) = tokio::io::duplex(64);
+        let n = MongoDBTcpProxy::write_queued(&mut writer, &mut queue).await.unwrap();
+        assert_eq!(n, 64);
+        assert!(!queue.accepts_reads());
+
+        let drained = tokio::spawn(async move {
+            let mut received = Vec::new();
+            reader.read_to_end(&mut received).await.unwrap();
+            received.len()
+        });
+        assert_eq!(MongoDBTcpProxy::drain_queue(&mut writer, &mut queue).await.unwrap(), 136);
+        assert!(queue.is_empty());
+        assert!(queue.accepts_reads());
+        drop(writer);
+        assert_eq!(drained.await.unwrap(), 200);
+    }
+
+    #[test]
+    fn test_framing_error_reply() {
+        use crate::modes::mongodb::wire::{self, MsgHeader};
+
+        let mut message = 64i32.to_le_bytes().to_vec();
+        message.extend_from_slice(&9i32.to_le_bytes());
+        message.extend_from_slice(&0i32.to_le_bytes());
+        message.extend_from_slice(&2013i32.to_le_bytes());
+        let mut framer = MessageFramer::new(32);
+        framer.push(&message);
+        let error = framer.next_message().unwrap_err();
+
+        // Oversized requests are answered with a server error
+        let reply = MongoDBTcpProxy::framing_error_reply(&framer, &error).unwrap();
+        assert_eq!(MsgHeader::parse(&reply).unwrap().response_to, 9);
+        let body = wire::op_msg_body(&reply).unwrap();
+        assert_eq!(body.get_str("codeName"), Some("BSONObjectTooLarge"));
+        assert!(body.get_str("errmsg").unwrap().contains("maximum of 32 bytes"));
+
+        // Garbled streams have no request to answer
+        let error = wire::WireError::InvalidLength(4);
+        assert!(MongoDBTcpProxy::framing_error_reply(&framer, &error).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_zero_copy_only_without_message_inspection() {
+        let proxy = |config: MongoDBConfig| async move {
+            let upstreams = LoadBalancer::try_from_iter(["127.0.0.1:27017"].iter()).unwrap();
+            let config = MongoDBConfig {
+                mongos_endpoints: vec!["127.0.0.1:27017".to_string()],
+                ..config
+            };
+            MongoDBTcpProxy::new(Arc::new(upstreams), config).await.unwrap()
+        };
+        let zero_copy = || MongoDBConfig::default().with_zero_copy(true);
+
+        assert!(!proxy(MongoDBConfig::default()).await.relays_zero_copy());
+        assert!(proxy(zero_copy()).await.relays_zero_copy());
+        // Compression policies and large reply tracking read the messages
+        assert!(!proxy(zero_copy().with_compressors(Some(Vec::new()))).await.relays_zero_copy());
+        let large_payloads = crate::config::LargePayloadConfig {
+            threshold_bytes: 1024,
+            top: 10,
+        };
+        assert!(!proxy(zero_copy().with_large_payloads(Some(large_payloads))).await.relays_zero_copy());
+        assert!(!proxy(zero_copy().with_audit(true)).await.relays_zero_copy());
+        assert!(!proxy(zero_copy().with_slow_operation_threshold_ms(Some(100))).await.relays_zero_copy());
+        assert!(!proxy(zero_copy().with_transaction_pinning(true)).await.relays_zero_copy());
+        let mirror = crate::config::MirrorConfig {
+            target: "127.0.0.1:27018".to_string(),
+            percent: 10.0,
+            max_queued: 16,
+        };
+        assert!(!proxy(zero_copy().with_mirror(Some(mirror))).await.relays_zero_copy());
+        let capture: crate::config::CaptureConfig = toml::from_str(&format!(
+            "path = \"{}\"",
+            std::env::temp_dir().join(format!("puerta-zero-copy-{}.cap", std::process::id())).display()
+        ))
+        .unwrap();
+        assert!(!proxy(zero_copy().with_capture(Some(capture))).await.relays_zero_copy());
+        let announce_addr = Some("proxy.example.com:27017".to_string());
+        assert!(!proxy(zero_copy().with_announce_addr(announce_addr)).await.relays_zero_copy());
+        // TLS to mongos has no plaintext socket to splice
+        let tls: crate::config::UpstreamTlsConfig = toml::from_str("").unwrap();
+        assert!(!proxy(zero_copy().with_upstream_tls(Some(tls))).await.relays_zero_copy());
+    }
+
+    #[tokio::test]
+    async fn test_client_messages_compression_policy() {
+        use crate::config::{Compressor, ReadPreferenceMode, ReadPreferenceRoute};
+        use crate::modes::mongodb::bson::{Document, DocumentBuilder};
+        use crate::modes::mongodb::{compression, wire};
+
+        let op_msg = |command: Vec<u8>| Bytes::from(test_support::op_msg(0, 0, 0, &command));
+
+        let upstreams = LoadBalancer::try_from_iter(["127.0.0.1:27017"].iter()).unwrap();
+        let config = MongoDBConfig {
+            mongos_endpoints: vec!["127.0.0.1:27017".to_string()],
+            read_preference_routes: vec![ReadPreferenceRoute {
+                modes: vec![ReadPreferenceMode::Secondary],
+                endpoints: vec!["127.0.0.1:27017".to_string()],
+            }],
+            compressors: Some(Vec::new()),
+            ..Default::default()
+        };
+        let proxy = MongoDBTcpProxy::new(Arc::new(upstreams), config).await.unwrap();
+        let client_ip = Some("10.0.0.5".parse().unwrap());
+
+        // Compression is stripped from the handshake
+        let hello = op_msg(
+            DocumentBuilder::new()
+                .int32("hello", 1)
+                .string_array("compression", &["zstd"])
+                .build(),
+        );
+        let forwarded = proxy.process_client_message(client_ip, hello);
+        let command: Document = wire::op_msg_body(&forwarded).unwrap();
+        assert_eq!(command.first_key(), Some("hello"));
+        assert!(command.get("compression").is_none());
+
+        // Compressed commands are inspected but forwarded as sent
+        let find = op_msg(
+            DocumentBuilder::new()
+                .string("find", "orders")
+                .document(
+                    "$readPreference",
+                    DocumentBuilder::new().string("mode", "secondary").build(),
+                )
+                .build(),
+        );
+        let compressed = compression::compress(&find, Compressor::Snappy).unwrap();
+        let forwarded = proxy.process_client_message(client_ip, compressed.clone());
+        assert_eq!(forwarded, compressed);
+        assert!(proxy
+            .mongodb_proxy
+            .read_preference_route("10.0.0.5".parse().unwrap())
+            .is_some());
+    }
+
+    #[tokio::test]
+    async fn test_select_backend_skips_draining_mongos() {
+        let endpoints = ["127.0.0.1:27117", "127.0.0.1:27118"];
+        let upstreams = LoadBalancer::try_from_iter(endpoints.iter()).unwrap();
+        let config = MongoDBConfig {
+            mongos_endpoints: endpoints.iter().map(|endpoint| endpoint.to_string()).collect(),
+            balance_strategy: crate::config::BalanceStrategy::LeastConnections,
+            // Keep the unreachable mongos healthy for the test
+            health_failure_threshold: u32::MAX,
+            ..Default::default()
+        };
+        let drains = Arc::new(crate::core::drain::Drains::new());
+        let proxy = MongoDBTcpProxy::new(Arc::new(upstreams), config)
+            .await
+            .unwrap()
+            .with_drains(Arc::clone(&drains));
+        for backend in proxy.mongodb_proxy.get_backends().write().await.values_mut() {
+            backend.healthy = true;
+        }
+
+        drains.drain(endpoints[0].parse().unwrap(), std::time::Duration::from_secs(60));
+        let client = |port: u16| {
+            let addr = std::net::SocketAddr::from(([10, 0, 0, 1], port));
+            (addr.to_string(), ClientIdentifier::from(addr))
+        };
+        for port in 40000..40010 {
+            let (client_addr, client_id) = client(port);
+            let peer = proxy
+                .select_backend(&client_addr, &client_id, &[])
+                .await
+                .unwrap();
+            assert_eq!(peer.address().to_string(), endpoints[1]);
+        }
+
+        drains.drain(endpoints[1].parse().unwrap(), std::time::Duration::from_secs(60));
+        let (client_addr, client_id) = client(40010);
+        assert!(proxy.select_backend(&client_addr, &client_id, &[]).await.is_err());
+
+        drains.enable(endpoints[0].parse().unwrap());
+        let (client_addr, client_id) = client(40011);
+        let peer = proxy.select_backend(&client_addr, &client_id, &[]).await.unwrap();
+        assert_eq!(peer.address().to_string(), endpoints[0]);
+    }
+
+    #[tokio::test]
+    async fn test_select_backend_skips_mongos_at_connection_limit() {
+        let endpoints = ["127.0.0.1:27147", "127.0.0.1:27148"];
+        let upstreams = LoadBalancer::try_from_iter(endpoints.iter()).unwrap();
+        let config = MongoDBConfig {
+            mongos_endpoints: endpoints.iter().map(|endpoint| endpoint.to_string()).collect(),
+            balance_strategy: crate::config::BalanceStrategy::LeastConnections,
+            // Keep the unreachable mongos healthy for the test
+            health_failure_threshold: u32::MAX,
+            ..Default::default()
+        }
+        .with_backend_connection_limit(Some(1), 0);
+        let proxy = MongoDBTcpProxy::new(Arc::new(upstreams), config).await.unwrap();
+        for backend in proxy.mongodb_proxy.get_backends().write().await.values_mut() {
+            backend.healthy = true;
+        }
+        let backend_limits = proxy.backend_limits.clone().unwrap();
+        let client_addr = "10.0.0.1:40000";
+        let client_id = ClientIdentifier::SessionId("app".to_string());
+
+        // The client's session moves off its mongos once that is full
+        let peer = proxy.select_backend(client_addr, &client_id, &[]).await.unwrap();
+        let pinned: std::net::SocketAddr = peer.address().to_string().parse().unwrap();
+        let _permit = backend_limits.try_acquire(pinned).unwrap();
+        let peer = proxy.select_backend(client_addr, &client_id, &[]).await.unwrap();
+        let other: std::net::SocketAddr = peer.address().to_string().parse().unwrap();
+        assert_ne!(other, pinned);
+
+        let _other_permit = backend_limits.try_acquire(other).unwrap();
+        assert!(proxy.select_backend(client_addr, &client_id, &[]).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_open_transaction_pins_client_to_its_mongos() {
+        use crate::modes::mongodb::bson::DocumentBuilder;
+
+        let op_msg = |request_id: i32, command: Vec<u8>| {
+            Bytes::from(test_support::op_msg(request_id, 0, 0, &command))
+        };
+        let lsid = DocumentBuilder::new().string("id", "session-1").build();
+        let statement = |command: DocumentBuilder| {
+            command
+                .document("lsid", lsid.clone())
+                .int64("txnNumber", 1)
+                .boolean("autocommit", false)
+                .string("$db", "shop")
+                .build()
+        };
+
+        let endpoints = ["127.0.0.1:27217", "127.0.0.1:27218"];
+        let upstreams = LoadBalancer::try_from_iter(endpoints.iter()).unwrap();
+        let config = MongoDBConfig {
+            mongos_endpoints: endpoints.iter().map(|endpoint| endpoint.to_string()).collect(),
+            balance_strategy: crate::config::BalanceStrategy::LeastConnections,
+            // Keep the unreachable mongos healthy for the test
+            health_failure_threshold: u32::MAX,
+            ..Default::default()
+        }
+        .with_transaction_pinning(true);
+        let drains = Arc::new(crate::core::drain::Drains::new());
+        let proxy = MongoDBTcpProxy::new(Arc::new(upstreams), config)
+            .await
+            .unwrap()
+            .with_drains(Arc::clone(&drains));
+        for backend in proxy.mongodb_proxy.get_backends().write().await.values_mut() {
+            backend.healthy = true;
+        }
+        let client_addr = "10.0.0.1:40000";
+        let client_id = ClientIdentifier::SessionId("app".to_string());
+        let mut pending = HashMap::new();
+
+        let start = statement(DocumentBuilder::new().string("insert", "orders").boolean("startTransaction", true));
+        proxy.track_operation(&mut pending, &op_msg(1, start), client_addr, &client_id, endpoints[0]);
+        // Draining the mongos moves new clients, not the open transaction
+        drains.drain(endpoints[0].parse().unwrap(), std::time::Duration::from_secs(60));
+        for _ in 0..3 {
+            let peer = proxy.select_backend(client_addr, &client_id, &[]).await.unwrap();
+            assert_eq!(peer.address().to_string(), endpoints[0]);
+        }
+        // Unless it cannot be reached at all
+        let excluded = [endpoints[0].parse().unwrap()];
+        let peer = proxy.select_backend(client_addr, &client_id, &excluded).await.unwrap();
+        assert_eq!(peer.address().to_string(), endpoints[1]);
+
+        let commit = statement(DocumentBuilder::new().int32("commitTransaction", 1));
+        proxy.track_operation(&mut pending, &op_msg(2, commit), client_addr, &client_id, endpoints[0]);
+        let peer = proxy.select_backend(client_addr, &client_id, &[]).await.unwrap();
+        assert_eq!(peer.address().to_string(), endpoints[1]);
+    }
+
+    #[tokio::test]
+    async fn test_handshake_metadata_recorded() {
+        use crate::modes::mongodb::bson::DocumentBuilder;
+
+        let upstreams = LoadBalancer::try_from_iter(["127.0.0.1:27017"].iter()).unwrap();
+        let config = MongoDBConfig {
+            mongos_endpoints: vec!["127.0.0.1:27017".to_string()],
+            ..Default::default()
+        };
+        let proxy = MongoDBTcpProxy::new(Arc::new(upstreams), config).await.unwrap();
+        let client_id = ClientIdentifier::SessionId("test-metadata".to_string());
+        proxy
+            .mongodb_proxy
+            .get_affinity_manager()
+            .assign_backend(client_id.clone(), "mongos-0".to_string())
+            .await;
+
+        let metadata = DocumentBuilder::new()
+            .document("application", DocumentBuilder::new().string("name", "test-metadata-app").build())
+            .document(
+                "driver",
+                DocumentBuilder::new().string("name", "pymongo").string("version", "4.8.0").build(),
+            )
+            .build();
+        let hello = DocumentBuilder::new()
+            .int32("hello", 1)
+            .document("client", metadata)
+            .string("$db", "admin")
+            .build();
+        let message = test_support::op_msg(1, 0, 0, &hello);
+
+        // The client sends its handshake and hangs up
+        let (client, mut client_peer) = tokio::io::duplex(4096);
+        let (mongos, mut mongos_peer) = tokio::io::duplex(4096);
+        let client_side = async {
+            client_peer.write_all(&message).await.unwrap();
+            client_peer.shutdown().await.unwrap();
+        };
+        let mongos_side = async {
+            let mut forwarded = vec![0; message.len()];
+            mongos_peer.read_exact(&mut forwarded).await.unwrap();
+            forwarded
+        };
+        let (_, _, forwarded) = tokio::join!(
+            proxy.forward_tcp_data(client, mongos, "10.0.0.1:1", &client_id, "127.0.0.1:27017", &[]),
+            client_side,
+            mongos_side
+        );
+        assert_eq!(forwarded, message);
+
+        let sessions = proxy.mongodb_proxy.get_affinity_manager().sessions(None).await;
+        let recorded = sessions[0].metadata.as_ref().unwrap();
+        assert_eq!(recorded.application.as_deref(), Some("test-metadata-app"));
+        assert_eq!(recorded.driver_name.as_deref(), Some("pymongo"));
+        assert_eq!(recorded.driver_version.as_deref(), Some("4.8.0"));
+        let totals = &crate::core::connections::global().report(None).applications["test-metadata-app"];
+        assert_eq!((totals.active, totals.total), (0, 1));
+        assert_eq!(totals.bytes_from_client, message.len() as u64);
+    }
+
+    #[tokio::test]
+    async fn test_large_replies_tracked_per_operation() {
+        use crate::config::LargePayloadConfig;
+        use crate::modes::mongodb::bson::DocumentBuilder;
+
+        let op_msg = |request_id: i32, response_to: i32, command: Vec<u8>| {
+            Bytes::from(test_support::op_msg(request_id, response_to, 0, &command))
+        };
+
+        let upstreams = LoadBalancer::try_from_iter(["127.0.0.1:27017"].iter()).unwrap();
+        let config = MongoDBConfig {
+            mongos_endpoints: vec!["127.0.0.1:27017".to_string()],
+            large_payloads: Some(LargePayloadConfig {
+                threshold_bytes: 256,
+                top: 8,
+            }),
+            ..Default::default()
+        };
+        let proxy = MongoDBTcpProxy::new(Arc::new(upstreams), config).await.unwrap();
+        let large_payloads = proxy.large_payloads().unwrap();
+        let mut pending = HashMap::new();
+        let client_id = ClientIdentifier::SocketAddr("10.0.0.1:1".parse().unwrap());
+
+        let find = |request_id| {
+            op_msg(
+                request_id,
+                0,
+                DocumentBuilder::new()
+                    .string("find", "orders")
+                    .string("$db", "shop")
+                    .build(),
+            )
+        };
+        let reply = |response_to, size: usize| {
+            op_msg(
+                100 + response_to,
+                response_to,
+                DocumentBuilder::new().string("cursor", &"x".repeat(size)).build(),
+            )
+        };
+
+        proxy.track_operation(&mut pending, &find(1), "10.0.0.1:1", &client_id, "127.0.0.1:27017");
+        proxy.track_operation(&mut pending, &find(2), "10.0.0.1:1", &client_id, "127.0.0.1:27017");
+        proxy.track_reply(&mut pending, &reply(1, 16), "10.0.0.1:1", &client_id, "127.0.0.1:27017");
+        assert!(large_payloads.top(10).is_empty());
+        proxy.track_reply(&mut pending, &reply(2, 1024), "10.0.0.1:1", &client_id, "127.0.0.1:27017");
+        // Replies to unknown requests are not attributed
+        proxy.track_reply(&mut pending, &reply(3, 1024), "10.0.0.1:1", &client_id, "127.0.0.1:27017");
+        assert!(pending.is_empty());
+
+        let top = large_payloads.top(10);
+        assert_eq!(top.len(), 1);
+        assert_eq!(top[0].name, "find shop.orders");
+        assert_eq!(top[0].command, "find");
+        assert_eq!(top[0].count, 1);
+    }
+
+    #[tokio::test]
+    async fn test_audit_tracks_operations_until_replied() {
+        use crate::modes::mongodb::bson::DocumentBuilder;
+
+        let op_msg = |request_id: i32, response_to: i32, flags: u32, command: Vec<u8>| {
+            Bytes::from(test_support::op_msg(request_id, response_to, flags, &command))
+        };
+        let (client, mongos) = ("10.0.0.1:1", "127.0.0.1:27017");
+
+        let upstreams = LoadBalancer::try_from_iter(["127.0.0.1:27017"].iter()).unwrap();
+        let config = MongoDBConfig {
+            mongos_endpoints: vec!["127.0.0.1:27017".to_string()],
+            ..Default::default()
+        }
+        .with_audit(true);
+        let proxy = MongoDBTcpProxy::new(Arc::new(upstreams), config).await.unwrap();
+        let mut pending = HashMap::new();
+        let client_id = ClientIdentifier::SocketAddr("10.0.0.1:1".parse().unwrap());
+
+        let find = DocumentBuilder::new()
+            .string("find", "orders")
+            .string("$db", "shop")
+            .build();
+        proxy.track_operation(&mut pending, &op_msg(1, 0, 0, find), client, &client_id, mongos);
+        assert_eq!(pending[&1].operation.namespace(), "shop.orders");
+        assert!(!pending[&1].answered);
+
+        // Unanswered writes are audited right away
+        let insert = DocumentBuilder::new()
+            .string("insert", "orders")
+            .string("$db", "shop")
+            .build();
+        proxy.track_operation(&mut pending, &op_msg(2, 0, 0b10, insert), client, &client_id, mongos);
+        assert_eq!(pending.len(), 1);
+
+        // An exhaust reply keeps the operation, audited once, for the next one
+        let batch = DocumentBuilder::new().int32("ok", 1).build();
+        proxy.track_reply(&mut pending, &op_msg(10, 1, 0b10, batch.clone()), client, &client_id, mongos);
+        assert!(pending[&10].answered);
+        proxy.track_reply(&mut pending, &op_msg(11, 10, 0, batch), client, &client_id, mongos);
+        assert!(pending.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_slow_operations_timed_to_first_reply() {
+        use crate::modes::mongodb::bson::DocumentBuilder;
+
+        let op_msg = |request_id: i32, response_to: i32, command: Vec<u8>| {
+            Bytes::from(test_support::op_msg(request_id, response_to, 0, &command))
+        };
+        let slow_count = |command| {
+            crate::metrics::global()
+                .counter_with_labels(
+                    "puerta_mongodb_slow_operations_total",
+                    "MongoDB operations answered after the slow operation threshold",
+                    &[("backend", "test-slow-replies:27017"), ("command", command)],
+                )
+                .get()
+        };
+
+        let upstreams = LoadBalancer::try_from_iter(["127.0.0.1:27017"].iter()).unwrap();
+        let config = MongoDBConfig {
+            mongos_endpoints: vec!["127.0.0.1:27017".to_string()],
+            ..Default::default()
+        }
+        .with_slow_operation_threshold_ms(Some(20));
+        let proxy = MongoDBTcpProxy::new(Arc::new(upstreams), config).await.unwrap();
+        let mut pending = HashMap::new();
+        let client_id = ClientIdentifier::SocketAddr("10.0.0.1:1".parse().unwrap());
+        let command = |name: &str| DocumentBuilder::new().string(name, "orders").string("$db", "shop").build();
+        let ok = DocumentBuilder::new().int32("ok", 1).build();
+
+        proxy.track_operation(&mut pending, &op_msg(1, 0, command("find")), "10.0.0.1:1", &client_id, "test-slow-replies:27017");
+        proxy.track_operation(&mut pending, &op_msg(2, 0, command("count")), "10.0.0.1:1", &client_id, "test-slow-replies:27017");
+        proxy.track_reply(&mut pending, &op_msg(10, 2, ok.clone()), "10.0.0.1:1", &client_id, "test-slow-replies:27017");
+        tokio::time::sleep(std::time::Duration::from_millis(30)).await;
+        proxy.track_reply(&mut pending, &op_msg(11, 1, ok), "10.0.0.1:1", &client_id, "test-slow-replies:27017");
+
+        assert!(pending.is_empty());
+        assert_eq!(slow_count("find"), 1);
+        assert_eq!(slow_count("count"), 0);
+    }
+
+    #[tokio::test]
+    async fn test_cursor_affinity_follows_replies() {
+        use crate::modes::mongodb::bson::DocumentBuilder;
+
+        let op_msg = |request_id: i32, response_to: i32, command: Vec<u8>| {
+            Bytes::from(test_support::op_msg(request_id, response_to, 0, &command))
+        };
+        let reply = |id: i64| {
+            DocumentBuilder::new()
+                .document("cursor", DocumentBuilder::new().int64("id", id).string("ns", "shop.orders").build())
+                .double("ok", 1.0)
+                .build()
+        };
+
+        let upstreams = LoadBalancer::try_from_iter(["127.0.0.1:27017"].iter()).unwrap();
+        let config = MongoDBConfig {
+            mongos_endpoints: vec!["127.0.0.1:27017".to_string()],
+            ..Default::default()
+        }
+        .with_zero_copy(true)
+        .with_cursor_affinity(true);
+        let proxy = MongoDBTcpProxy::new(Arc::new(upstreams), config).await.unwrap();
+        // Cursors are read from replies, so connections are never spliced
+        assert!(!proxy.relays_zero_copy());
+        let cursors = proxy.cursors.clone().unwrap();
+        let mut pending = HashMap::new();
+        let client_id = ClientIdentifier::SessionId("app".to_string());
+        let mongos = "10.0.1.1:27017";
+
+        let find = DocumentBuilder::new().string("find", "orders").string("$db", "shop").build();
+        proxy.track_operation(&mut pending, &op_msg(1, 0, find), "10.0.0.1:1", &client_id, mongos);
+        proxy.track_reply(&mut pending, &op_msg(10, 1, reply(77)), "10.0.0.1:1", &client_id, mongos);
+        assert_eq!(cursors.backend_of(&client_id).as_deref(), Some(mongos));
+
+        // The last batch closes the cursor
+        let get_more = DocumentBuilder::new()
+            .int64("getMore", 77)
+            .string("collection", "orders")
+            .string("$db", "shop")
+            .build();
+        proxy.track_operation(&mut pending, &op_msg(2, 0, get_more), "10.0.0.1:1", &client_id, mongos);
+        proxy.track_reply(&mut pending, &op_msg(11, 2, reply(0)), "10.0.0.1:1", &client_id, mongos);
+        assert_eq!(cursors.backend_of(&client_id), None);
+
+        // As does killing it
+        let aggregate = DocumentBuilder::new().string("aggregate", "orders").string("$db", "shop").build();
+        proxy.track_operation(&mut pending, &op_msg(3, 0, aggregate), "10.0.0.1:1", &client_id, mongos);
+        proxy.track_reply(&mut pending, &op_msg(12, 3, reply(78)), "10.0.0.1:1", &client_id, mongos);
+        assert_eq!(cursors.len(), 1);
+        let kill = DocumentBuilder::new()
+            .string("killCursors", "orders")
+            .array("cursors", DocumentBuilder::new().int64("0", 78).build())
+            .string("$db", "shop")
+            .build();
+        proxy.track_operation(&mut pending, &op_msg(4, 0, kill), "10.0.0.1:1", &client_id, mongos);
+        assert!(cursors.is_empty());
+    }
 }
@@ -0,0 +1,402 @@
+/// Logging subsystem: text or JSON application logs and a per-connection
+/// access log
+///
+/// Application logs go to stdout (or stderr when `logging.stdout` is off) in
+/// the configured format. When `logging.file` is set, one access record per
+/// client connection is appended to that file in the same format, along with
+/// one audit record per command of MongoDB proxies with `audit` enabled.
+use crate::config::LoggingConfig;
+use serde::Serialize;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+static ACCESS_LOG: OnceLock<AccessLog> = OnceLock::new();
+
+/// Output format for log lines
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+impl LogFormat {
+    /// Parse `logging.format`; anything but `json` is text
+    pub fn from_config(format: &str) -> Self {
+        match format {
+            "json" => LogFormat::Json,
+            _ => LogFormat::Text,
+        }
+    }
+}
+
+/// Summary of one proxied client connection
+#[derive(Debug, Clone, Serialize)]
+pub struct AccessRecord {
+    /// Proxy mode that served the connection (`mongodb` or `redis`)
+    pub mode: &'static str,
+    pub client_addr: String,
+    /// Upstream the connection was forwarded to
+    pub backend: String,
+    /// Bytes forwarded from the client to the backend
+    pub bytes_from_client: u64,
+    /// Bytes forwarded from the backend to the client
+    pub bytes_to_client: u64,
+    /// Operations or commands forwarded for the client
+    pub requests: u64,
+    pub duration_ms: u64,
+}
+
+impl AccessRecord {
+    pub fn new(mode: &'static str, client_addr: &str, backend: &str) -> Self {
+        Self {
+            mode,
+            client_addr: client_addr.to_string(),
+            backend: backend.to_string(),
+            bytes_from_client: 0,
+            bytes_to_client: 0,
+            requests: 0,
+            duration_ms: 0,
+        }
+    }
+
+    /// Set how long the connection was forwarded
+    pub fn with_duration(mut self, duration: Duration) -> Self {
+        self.duration_ms = duration.as_millis() as u64;
+        self
+    }
+
+    /// Render the record as a single line without trailing newline
+    pub fn format(&self, format: LogFormat, now: SystemTime) -> String {
+        let timestamp = rfc3339(now);
+        match format {
+            LogFormat::Json => {
+                let mut value = serde_json::to_value(self).unwrap_or_default();
+                if let Some(fields) = value.as_object_mut() {
+                    fields.insert("timestamp".to_string(), timestamp.into());
+                }
+                value.to_string()
+            }
+            LogFormat::Text => format!(
+                "{timestamp} {} {} -> {} requests={} bytes_in={} bytes_out={} duration_ms={}",
+                self.mode,
+                self.client_addr,
+                self.backend,
+                self.requests,
+                self.bytes_from_client,
+                self.bytes_to_client,
+                self.duration_ms
+            ),
+        }
+    }
+}
+
+/// One MongoDB command, named without its body
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditRecord {
+    pub mode: &'static str,
+    pub client_addr: String,
+    /// Mongos the command was forwarded to
+    pub backend: String,
+    pub command: String,
+    pub database: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub collection: Option<String>,
+    /// Time from forwarding the command to its reply, absent for commands
+    /// mongos does not answer
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<u64>,
+}
+
+impl AuditRecord {
+    /// Render the record as a single line without trailing newline
+    pub fn format(&self, format: LogFormat, now: SystemTime) -> String {
+        let timestamp = rfc3339(now);
+        match format {
+            LogFormat::Json => {
+                let mut value = serde_json::to_value(self).unwrap_or_default();
+                if let Some(fields) = value.as_object_mut() {
+                    fields.insert("timestamp".to_string(), timestamp.into());
+                }
+                value.to_string()
+            }
+            LogFormat::Text => {
+                let namespace = match &self.collection {
+                    Some(collection) => format!("{}.{collection}", self.database),
+                    None => self.database.clone(),
+                };
+                let duration = self
+                    .duration_ms
+                    .map_or_else(|| "-".to_string(), |duration| duration.to_string());
+                format!(
+                    "{timestamp} {} {} -> {} command={} ns={namespace} duration_ms={duration}",
+                    self.mode, self.client_addr, self.backend, self.command
+                )
+            }
+        }
+    }
+}
+
+/// Append-only access log file
+pub struct AccessLog {
+    format: LogFormat,
+    file: Mutex<File>,
+}
+
+impl AccessLog {
+    /// Open `path` for appending, creating it if needed
+    pub fn open<P: AsRef<Path>>(path: P, format: LogFormat) -> std::io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            format,
+            file: Mutex::new(file),
+        })
+    }
+
+    /// Append one record
+    pub fn write(&self, record: &AccessRecord) {
+        self.append(record.format(self.format, SystemTime::now()));
+    }
+
+    /// Append one audit record
+    pub fn write_audit(&self, record: &AuditRecord) {
+        self.append(record.format(self.format, SystemTime::now()));
+    }
+
+    fn append(&self, mut line: String) {
+        line.push('\n');
+        let mut file = self.file.lock().unwrap();
+        if let Err(e) = file.write_all(line.as_bytes()) {
+            log::warn!("Failed to write access log record: {e}");
+        }
+    }
+}
+
+/// Record a finished client connection in the access log, if one is
+/// configured
+pub fn access(record: &AccessRecord) {
+    if let Some(access_log) = ACCESS_LOG.get() {
+        access_log.write(record);
+    }
+}
+
+/// Record a forwarded command in the access log, if one is configured
+pub fn audit(record: &AuditRecord) {
+    if let Some(access_log) = ACCESS_LOG.get() {
+        access_log.write_audit(record);
+    }
+}
+
+/// Application logger writing text or JSON lines
+struct Logger {
+    format: LogFormat,
+    stdout: bool,
+}
+
+impl log::Log for Logger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let mut line = format_record(record, self.format, SystemTime::now());
+        line.push('\n');
+        // Logging must never take the proxy down, so write errors are ignored
+        if self.stdout {
+            let _ = std::io::stdout().lock().write_all(line.as_bytes());
+        } else {
+            let _ = std::io::stderr().lock().write_all(line.as_bytes());
+        }
+    }
+
+    fn flush(&self) {
+        let _ = std::io::stdout().flush();
+    }
+}
+
+/// Render an application log record as a single line
+fn format_record(record: &log::Record, format: LogFormat, now: SystemTime) -> String {
+    let timestamp = rfc3339(now);
+    match format {
+        LogFormat::Json => serde_json::json!({
+            "timestamp": timestamp,
+            "level": record.level().as_str(),
+            "target": record.target(),
+            "message": record.args().to_string(),
+        })
+        .to_string(),
+        LogFormat::Text => format!(
+            "[{timestamp} {:<5} {}] {}",
+            record.level(),
+            record.target(),
+            record.args()
+        ),
+    }
+}
+
+/// Install the application logger and open the access log
+///
+/// The format and access log file are fixed for the life of the process;
+/// only the level can change on configuration reload.
+pub fn init(config: &LoggingConfig) -> Result<(), Box<dyn std::error::Error>> {
+    let format = LogFormat::from_config(&config.format);
+
+    if let Some(path) = &config.file {
+        let access_log = AccessLog::open(path, format)
+            .map_err(|e| format!("Failed to open access log {path}: {e}"))?;
+        let _ = ACCESS_LOG.set(access_log);
+    }
+
+    log::set_boxed_logger(Box::new(Logger {
+        format,
+        stdout: config.stdout,
+    }))?;
+    log::set_max_level(config.level_filter());
+    Ok(())
+}
+
+/// Format a time as an RFC 3339 UTC timestamp with millisecond precision
+fn rfc3339(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (year, month, day) = civil_from_days((secs / 86_400) as i64);
+    let seconds_of_day = secs % 86_400;
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:03}Z",
+        seconds_of_day / 3600,
+        seconds_of_day % 3600 / 60,
+        seconds_of_day % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+/// Convert days since 1970-01-01 into a (year, month, day) civil date
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::NamedTempFile;
+
+    fn at(secs: u64, millis: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs) + Duration::from_millis(millis)
+    }
+
+    fn record() -> AccessRecord {
+        AccessRecord {
+            bytes_from_client: 120,
+            bytes_to_client: 4096,
+            requests: 3,
+            ..AccessRecord::new("mongodb", "10.0.0.1:50000", "127.0.0.1:27017")
+        }
+        .with_duration(Duration::from_millis(1500))
+    }
+
+    #[test]
+    fn test_rfc3339() {
+        assert_eq!(rfc3339(at(0, 0)), "1970-01-01T00:00:00.000Z");
+        assert_eq!(rfc3339(at(951_782_400, 5)), "2000-02-29T00:00:00.005Z");
+        assert_eq!(rfc3339(at(1_700_000_000, 123)), "2023-11-14T22:13:20.123Z");
+    }
+
+    #[test]
+    fn test_access_record_json() {
+        let line = record().format(LogFormat::Json, at(0, 0));
+        let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(value["mode"], "mongodb");
+        assert_eq!(value["client_addr"], "10.0.0.1:50000");
+        assert_eq!(value["backend"], "127.0.0.1:27017");
+        assert_eq!(value["bytes_from_client"], 120);
+        assert_eq!(value["bytes_to_client"], 4096);
+        assert_eq!(value["duration_ms"], 1500);
+        assert_eq!(value["timestamp"], "1970-01-01T00:00:00.000Z");
+    }
+
+    #[test]
+    fn test_access_record_text() {
+        let line = record().format(LogFormat::Text, at(0, 0));
+        assert_eq!(
+            line,
+            "1970-01-01T00:00:00.000Z mongodb 10.0.0.1:50000 -> 127.0.0.1:27017 requests=3 bytes_in=120 bytes_out=4096 duration_ms=1500"
+        );
+    }
+
+    #[test]
+    fn test_audit_record() {
+        let mut record = AuditRecord {
+            mode: "mongodb",
+            client_addr: "10.0.0.1:50000".to_string(),
+            backend: "127.0.0.1:27017".to_string(),
+            command: "find".to_string(),
+            database: "shop".to_string(),
+            collection: Some("orders".to_string()),
+            duration_ms: Some(12),
+        };
+        assert_eq!(
+            record.format(LogFormat::Text, at(0, 0)),
+            "1970-01-01T00:00:00.000Z mongodb 10.0.0.1:50000 -> 127.0.0.1:27017 command=find ns=shop.orders duration_ms=12"
+        );
+        let value: serde_json::Value = serde_json::from_str(&record.format(LogFormat::Json, at(0, 0))).unwrap();
+        assert_eq!(value["command"], "find");
+        assert_eq!(value["database"], "shop");
+        assert_eq!(value["collection"], "orders");
+        assert_eq!(value["duration_ms"], 12);
+
+        // Unanswered commands without a collection
+        record.command = "insert".to_string();
+        record.collection = None;
+        record.duration_ms = None;
+        assert!(record
+            .format(LogFormat::Text, at(0, 0))
+            .ends_with("command=insert ns=shop duration_ms=-"));
+        let value: serde_json::Value = serde_json::from_str(&record.format(LogFormat::Json, at(0, 0))).unwrap();
+        assert!(value.get("collection").is_none());
+        assert!(value.get("duration_ms").is_none());
+    }
+
+    #[test]
+    fn test_json_log_record() {
+        let line = format_record(
+            &log::Record::builder()
+                .args(format_args!("backend \"a\" down"))
+                .level(log::Level::Warn)
+                .target("puerta::health")
+                .build(),
+            LogFormat::Json,
+            at(0, 0),
+        );
+        let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(value["level"], "WARN");
+        assert_eq!(value["target"], "puerta::health");
+        assert_eq!(value["message"], "backend \"a\" down");
+    }
+
+    #[test]
+    fn test_access_log_appends_lines() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let access_log = AccessLog::open(temp_file.path(), LogFormat::Json).unwrap();
+        access_log.write(&record());
+        access_log.write(&AccessRecord::new("redis", "10.0.0.2:50001", "cluster"));
+
+        let contents = std::fs::read_to_string(temp_file.path()).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[1].contains("\"mode\":\"redis\""));
+    }
+}
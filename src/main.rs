@@ -1,9 +1,18 @@
 use clap::{Parser, Subcommand};
 use log::info;
-use puerta::config::Config;
+use puerta::config::reload::ConfigReloader;
+use puerta::config::{Config, Endpoint, ProxyConfig};
+use puerta::core::frontend::BufferConfig;
 use puerta::error::ConfigError;
-use puerta::{ProxyMode, Puerta, PuertaConfig};
-use std::path::PathBuf;
+use puerta::health::CheckOptions;
+use puerta::modes::mongodb::handler::MongoDBHandler;
+use puerta::modes::mongodb::MongoDBConfig;
+use puerta::modes::redis::handler::RedisHandler;
+use puerta::modes::redis::RedisConfig;
+use puerta::modes::ProxyModeHandler;
+use puerta::{ProxyInstance, Puerta, PuertaConfig};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 // Pingora framework imports
 use pingora_core::server::configuration::Opt;
@@ -60,6 +69,88 @@ enum Commands {
         /// Path to configuration file to validate
         #[arg(short, long)]
         config: PathBuf,
+        /// Also connect to every backend and run its health check
+        #[arg(long)]
+        probe: bool,
+    },
+    /// Show the status of a running puerta through its admin API
+    Status {
+        /// Configuration file whose [admin] listen_addr is queried
+        #[arg(short, long, default_value = "config/dev.toml")]
+        config: PathBuf,
+        /// Admin API address, instead of the one in the configuration file
+        #[arg(short, long)]
+        admin: Option<String>,
+        /// Print the status as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Stop new sessions to a backend, closing open ones after a timeout
+    Drain {
+        /// Backend address, or its id as shown by `puerta status`
+        backend: String,
+        /// Configuration file whose [admin] listen_addr is queried
+        #[arg(short, long, default_value = "config/dev.toml")]
+        config: PathBuf,
+        /// Admin API address, instead of the one in the configuration file
+        #[arg(short, long)]
+        admin: Option<String>,
+        /// Seconds before open sessions are closed (default: 300)
+        #[arg(short, long)]
+        timeout: Option<u64>,
+    },
+    /// Return a drained backend to service
+    Enable {
+        /// Backend address, or its id as shown by `puerta status`
+        backend: String,
+        /// Configuration file whose [admin] listen_addr is queried
+        #[arg(short, long, default_value = "config/dev.toml")]
+        config: PathBuf,
+        /// Admin API address, instead of the one in the configuration file
+        #[arg(short, long)]
+        admin: Option<String>,
+    },
+    /// List the MongoDB clients pinned to a mongos
+    Sessions {
+        /// Configuration file whose [admin] listen_addr is queried
+        #[arg(short, long, default_value = "config/dev.toml")]
+        config: PathBuf,
+        /// Admin API address, instead of the one in the configuration file
+        #[arg(short, long)]
+        admin: Option<String>,
+        /// Only list sessions pinned to this backend id
+        #[arg(short, long)]
+        backend: Option<String>,
+        /// Print the sessions as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Load test a proxy or backend with synthetic traffic
+    Bench {
+        /// Protocol to speak (mongodb or redis)
+        #[arg(short, long)]
+        mode: String,
+        /// Address to send traffic to
+        #[arg(short, long)]
+        target: String,
+        /// Connections sending requests in parallel
+        #[arg(short, long, default_value_t = 50)]
+        concurrency: usize,
+        /// Requests to send in total
+        #[arg(short = 'n', long, default_value_t = 100_000)]
+        requests: u64,
+        /// Command to send: ping, or get or set in Redis mode
+        #[arg(long)]
+        command: Option<String>,
+        /// Keys Redis GET and SET spread over
+        #[arg(long, default_value_t = 10_000)]
+        keyspace: u64,
+        /// Size in bytes of the values Redis SET writes
+        #[arg(long, default_value_t = 64)]
+        value_size: usize,
+        /// Replay the client messages of a capture file instead
+        #[arg(long)]
+        replay: Option<PathBuf>,
     },
     /// Show version information
     Version,
@@ -83,8 +174,32 @@ fn main() -> Result<(), String> {
         Commands::Config { mode, output } => {
             generate_config(mode, output)?;
         }
-        Commands::Validate { config } => {
-            validate_config(config)?;
+        Commands::Validate { config, probe } => {
+            validate_config(config, probe)?;
+        }
+        Commands::Status { config, admin, json } => {
+            show_status(config, admin, json)?;
+        }
+        Commands::Drain { backend, config, admin, timeout } => {
+            drain_backend(backend, config, admin, true, timeout)?;
+        }
+        Commands::Enable { backend, config, admin } => {
+            drain_backend(backend, config, admin, false, None)?;
+        }
+        Commands::Sessions { config, admin, backend, json } => {
+            show_sessions(config, admin, backend, json)?;
+        }
+        Commands::Bench { mode, target, replay: Some(path), .. } => {
+            run_replay(&path, &mode, &target)?;
+        }
+        Commands::Bench { mode, target, concurrency, requests, command, keyspace, value_size, replay: None } => {
+            let workload = puerta::bench::Workload::new(&mode, command.as_deref())?;
+            let config = puerta::bench::BenchConfig::new(workload, target)
+                .with_concurrency(concurrency)
+                .with_requests(requests)
+                .with_keyspace(keyspace)
+                .with_value_size(value_size);
+            run_bench(config)?;
         }
         Commands::Version => {
             show_version();
@@ -110,6 +225,15 @@ fn run_puerta(
     // Initialize logging
     init_logging(&config).map_err(|e| format!("Failed to initialize logging: {}", e))?;
 
+    // Export tracing spans when a [telemetry] block is configured
+    let _telemetry = match &config.telemetry {
+        Some(telemetry) => Some(
+            puerta::telemetry::init(telemetry)
+                .map_err(|e| format!("Failed to initialize telemetry: {}", e))?,
+        ),
+        None => None,
+    };
+
     info!(
         "Starting puerta v{} with Pingora framework",
         env!("CARGO_PKG_VERSION")
@@ -118,33 +242,46 @@ fn run_puerta(
     info!("Proxy mode: {:?}", config.proxy);
     info!("Listening on: {}", config.server.listen_addr);
 
+    // Configuration reloader, triggered by SIGHUP
+    let config_reloader = Arc::new(ConfigReloader::new(&config_path, config.clone()));
+
     // Create puerta configuration
     let puerta_config = PuertaConfig {
         listen_addr: config.server.listen_addr.clone(),
-        proxy_mode: match config.proxy {
-            puerta::config::ProxyConfig::MongoDB {
-                mongos_endpoints,
-                session_affinity,
-                ..
-            } => ProxyMode::MongoDB {
-                mongos_endpoints,
-                session_affinity_enabled: session_affinity,
-            },
-            puerta::config::ProxyConfig::Redis {
-                cluster_nodes,
-                slot_refresh_interval_sec,
-                ..
-            } => ProxyMode::Redis {
-                cluster_nodes,
-                slot_refresh_interval_ms: slot_refresh_interval_sec * 1000,
-            },
-        },
+        proxy_mode: proxy_mode(&config.proxy, &config),
         health_check_interval_ms: config.health.interval_sec * 1000,
         max_connections: config.server.max_connections,
+        connection_queue_timeout_ms: config.server.connection_queue_timeout_ms,
+        proxy_protocol: config.server.proxy_protocol,
+        tls: config.server.tls.clone(),
+        buffer_config: BufferConfig::new(
+            config.server.buffer_size,
+            config.server.write_high_watermark,
+        ),
+        connection_timeout_sec: config.server.connection_timeout_sec,
+        worker_threads: config.server.worker_threads,
+        dns_refresh_sec: config.server.dns_refresh_sec,
+        tuning: config.server.tuning.clone(),
     };
 
     // Create and initialize Puerta with Pingora
-    let mut puerta = Puerta::new(puerta_config);
+    let mut puerta = Puerta::new(puerta_config)
+        .with_config_reloader(config_reloader)
+        .with_admin_config(config.admin.clone())
+        .with_statsd_config(config.statsd.clone())
+        .with_acl_config(config.acl.clone())
+        .with_discovery_config(config.discovery.clone())
+        .with_chaos_config(config.chaos.clone())
+        .with_maintenance(config.maintenance.clone());
+    for instance in &config.proxies {
+        info!("Additional proxy {} listening on: {}", instance.name, instance.listen_addr);
+        puerta = puerta.with_proxy_instance(ProxyInstance {
+            name: instance.name.clone(),
+            listen_addr: instance.listen_addr.clone(),
+            proxy_mode: proxy_mode(&instance.proxy, &config),
+            cpu_affinity: instance.cpu_affinity.clone(),
+        });
+    }
 
     // Initialize Pingora server with daemon options
     let pingora_opt = Opt {
@@ -169,6 +306,163 @@ fn run_puerta(
     Ok(())
 }
 
+/// Handler serving a proxy section in its mode
+fn proxy_mode(proxy: &ProxyConfig, config: &Config) -> Box<dyn ProxyModeHandler> {
+    match proxy {
+        ProxyConfig::MongoDB { .. } => Box::new(MongoDBHandler::new(mongodb_config(proxy, config))),
+        ProxyConfig::Redis { .. } => Box::new(RedisHandler::new(redis_config(proxy, config))),
+    }
+}
+
+/// MongoDB mode settings of a MongoDB proxy section
+fn mongodb_config(proxy: &ProxyConfig, config: &Config) -> MongoDBConfig {
+    match proxy {
+        ProxyConfig::MongoDB {
+            mongos_endpoints,
+            session_affinity,
+            session_timeout_sec,
+            max_message_size,
+            tls,
+            balance_strategy,
+            client_identification,
+            read_preference_routes,
+            affinity_rules,
+            rebalance,
+            on_backend_unhealthy,
+            compressors,
+            spare_connections,
+            retry_attempts,
+            max_connections_per_backend,
+            backend_queue_timeout_ms,
+            large_payloads,
+            mirror,
+            capture,
+            zero_copy,
+            audit,
+            slow_operation_threshold_ms,
+            cursor_affinity,
+            transaction_pinning,
+            announce_addr,
+            ..
+        } => MongoDBConfig {
+            mongos_endpoints: Endpoint::addrs(mongos_endpoints),
+            session_affinity_enabled: *session_affinity,
+            session_timeout_sec: *session_timeout_sec,
+            max_message_size: *max_message_size,
+            upstream_tls: tls.clone(),
+            backend_tcp_keepalive: config.server.tuning.backend_tcp_keepalive.clone(),
+            endpoint_weights: Endpoint::weights(mongos_endpoints),
+            endpoint_health_checks: Endpoint::health_checks(mongos_endpoints),
+            balance_strategy: *balance_strategy,
+            client_identification: *client_identification,
+            health_failure_threshold: config.health.failure_threshold,
+            health_success_threshold: config.health.success_threshold,
+            passive_failure_threshold: config.health.passive_failure_threshold,
+            health_check_jitter_percent: config.health.jitter_percent,
+            outlier_detection: config.health.outlier_detection.clone(),
+            circuit_breaker: config.health.circuit_breaker.clone(),
+            health_notifications: config.health.notifications.clone(),
+            read_preference_routes: read_preference_routes.clone(),
+            affinity_rules: affinity_rules.clone(),
+            rebalance: rebalance.clone(),
+            on_backend_unhealthy: *on_backend_unhealthy,
+            compressors: compressors.clone(),
+            spare_connections: *spare_connections,
+            retry_attempts: *retry_attempts,
+            max_connections_per_backend: *max_connections_per_backend,
+            backend_queue_timeout_ms: *backend_queue_timeout_ms,
+            large_payloads: large_payloads.clone(),
+            mirror: mirror.clone(),
+            capture: capture.clone(),
+            zero_copy: *zero_copy,
+            audit: *audit,
+            slow_operation_threshold_ms: *slow_operation_threshold_ms,
+            cursor_affinity: *cursor_affinity,
+            transaction_pinning: *transaction_pinning,
+            announce_addr: announce_addr.clone(),
+            ..Default::default()
+        },
+        _ => unreachable!("mongodb_config called with a non-MongoDB proxy"),
+    }
+}
+
+/// Redis mode settings of a Redis proxy section
+fn redis_config(proxy: &ProxyConfig, config: &Config) -> RedisConfig {
+    match proxy {
+        ProxyConfig::Redis {
+            cluster_nodes,
+            slot_refresh_interval_sec,
+            max_redirects,
+            connection_timeout_ms,
+            pool_size,
+            pool_idle_timeout_sec,
+            pool_max_wait_ms,
+            pool_min_idle,
+            retry_attempts,
+            failover_timeout_ms,
+            tls,
+            auth,
+            client_auth,
+            read_from_replicas,
+            balance_strategy,
+            sharding,
+            topology_agreement,
+            slowlog_threshold_us,
+            slowlog_max_len,
+            fan_out_keyless_commands,
+            command_filter,
+            large_payloads,
+            mirror,
+            capture,
+            cluster_view,
+            snapshot,
+            max_command_size,
+            max_reply_size,
+            ..
+        } => RedisConfig {
+            cluster_nodes: Endpoint::addrs(cluster_nodes),
+            slot_refresh_interval_sec: *slot_refresh_interval_sec,
+            max_redirects: *max_redirects,
+            connection_timeout_ms: *connection_timeout_ms,
+            pool_size: *pool_size,
+            pool_idle_timeout_sec: *pool_idle_timeout_sec,
+            pool_max_wait_ms: *pool_max_wait_ms,
+            pool_min_idle: *pool_min_idle,
+            retry_attempts: *retry_attempts,
+            failover_timeout_ms: *failover_timeout_ms,
+            upstream_tls: tls.as_deref().cloned(),
+            backend_tcp_keepalive: config.server.tuning.backend_tcp_keepalive.clone(),
+            auth: auth.clone(),
+            client_auth: client_auth.clone(),
+            read_from_replicas: *read_from_replicas,
+            balance_strategy: *balance_strategy,
+            sharding: *sharding,
+            topology_agreement: *topology_agreement,
+            slowlog_threshold_us: *slowlog_threshold_us,
+            slowlog_max_len: *slowlog_max_len,
+            fan_out_keyless_commands: *fan_out_keyless_commands,
+            command_filter: command_filter.clone(),
+            large_payloads: large_payloads.clone(),
+            mirror: mirror.clone(),
+            capture: capture.clone(),
+            cluster_view: cluster_view.clone(),
+            snapshot: snapshot.clone(),
+            max_command_size: *max_command_size,
+            max_reply_size: *max_reply_size,
+            node_weights: Endpoint::weights(cluster_nodes),
+            health_check_interval_sec: config.health.interval_sec,
+            health_failure_threshold: config.health.failure_threshold,
+            health_success_threshold: config.health.success_threshold,
+            passive_failure_threshold: config.health.passive_failure_threshold,
+            outlier_detection: config.health.outlier_detection.clone(),
+            circuit_breaker: config.health.circuit_breaker.clone(),
+            health_notifications: config.health.notifications.clone(),
+            ..Default::default()
+        },
+        _ => unreachable!("redis_config called with a non-Redis proxy"),
+    }
+}
+
 fn generate_config(mode: String, output: PathBuf) -> Result<(), String> {
     println!("Generating {} configuration file: {:?}", mode, output);
 
@@ -182,15 +476,20 @@ fn generate_config(mode: String, output: PathBuf) -> Result<(), String> {
     Ok(())
 }
 
-fn validate_config(config_path: PathBuf) -> Result<(), String> {
+fn validate_config(config_path: PathBuf, probe: bool) -> Result<(), String> {
     println!("Validating configuration file: {:?}", config_path);
 
     match Config::load_from_file(&config_path) {
         Ok(config) => {
+            let probe_targets = probe.then(|| probe_targets(&config));
             println!("✓ Configuration file is valid");
             println!("  Proxy mode: {:?}", config.proxy);
             println!("  Listen address: {}", config.server.listen_addr);
             println!("  Max connections: {}", config.server.max_connections);
+            match &config.server.tls {
+                Some(tls) => println!("  TLS: enabled (cert: {}, key: {})", tls.cert_path, tls.key_path),
+                None => println!("  TLS: disabled"),
+            }
 
             match config.proxy {
                 puerta::config::ProxyConfig::MongoDB {
@@ -211,6 +510,21 @@ fn validate_config(config_path: PathBuf) -> Result<(), String> {
                     }
                 }
             }
+
+            for instance in &config.proxies {
+                let mode = match instance.proxy {
+                    ProxyConfig::MongoDB { .. } => "mongodb",
+                    ProxyConfig::Redis { .. } => "redis",
+                };
+                println!(
+                    "  Additional proxy {}: {} on {}",
+                    instance.name, mode, instance.listen_addr
+                );
+            }
+
+            if let Some(targets) = probe_targets {
+                probe_backends(targets)?;
+            }
         }
         Err(e) => {
             eprintln!("✗ Configuration file validation failed:");
@@ -227,6 +541,227 @@ fn validate_config(config_path: PathBuf) -> Result<(), String> {
     Ok(())
 }
 
+/// A proxy whose backends get probed: its name, mode, endpoints and how
+/// it reaches them
+type ProbeTarget = (String, &'static str, Vec<Endpoint>, CheckOptions);
+
+/// Backends to probe: the mode and endpoints of every proxy, by name
+fn probe_targets(config: &Config) -> Vec<ProbeTarget> {
+    let target = |name: String, proxy: &ProxyConfig| match proxy {
+        ProxyConfig::MongoDB {
+            mongos_endpoints, tls, ..
+        } => {
+            let options = CheckOptions {
+                upstream_tls: tls.clone(),
+                ..Default::default()
+            };
+            (name, "mongodb", mongos_endpoints.clone(), options)
+        }
+        ProxyConfig::Redis {
+            cluster_nodes,
+            tls,
+            auth,
+            sharding,
+            ..
+        } => {
+            let options = CheckOptions {
+                upstream_tls: tls.as_deref().cloned(),
+                redis_sharding: *sharding,
+                redis_auth: auth.clone(),
+            };
+            (name, "redis", cluster_nodes.clone(), options)
+        }
+    };
+    std::iter::once(target("Primary proxy".to_string(), &config.proxy))
+        .chain(
+            config
+                .proxies
+                .iter()
+                .map(|instance| target(format!("Proxy {}", instance.name), &instance.proxy)),
+        )
+        .collect()
+}
+
+/// Probe every backend, failing if any is unreachable or unhealthy
+fn probe_backends(targets: Vec<ProbeTarget>) -> Result<(), String> {
+    let runtime = tokio::runtime::Runtime::new().map_err(|e| format!("Failed to start runtime: {e}"))?;
+    let (mut probed, mut failed) = (0, 0);
+    for (name, mode, endpoints, options) in targets {
+        println!("{name} ({mode}) backends:");
+        for report in runtime.block_on(puerta::health::probe::probe_all(mode, &endpoints, &options)) {
+            probed += 1;
+            match (&report.role, &report.error) {
+                (Some(role), _) => println!(
+                    "  ✓ {}: {role} ({}ms)",
+                    report.endpoint,
+                    report.latency.as_millis()
+                ),
+                (None, Some(error)) if report.reachable => {
+                    failed += 1;
+                    println!("  ✗ {}: reachable, health check failed: {error}", report.endpoint);
+                }
+                (None, error) => {
+                    failed += 1;
+                    println!(
+                        "  ✗ {}: unreachable: {}",
+                        report.endpoint,
+                        error.as_deref().unwrap_or_default()
+                    );
+                }
+            }
+        }
+    }
+
+    if failed > 0 {
+        return Err(format!("{failed} of {probed} backends failed the probe"));
+    }
+    println!("✓ All {probed} backends passed the probe");
+    Ok(())
+}
+
+fn show_status(config_path: PathBuf, admin: Option<String>, json: bool) -> Result<(), String> {
+    let (admin_addr, token) = admin_target(&config_path, admin)?;
+    let status = admin_runtime()?.block_on(puerta::admin::status::fetch(&admin_addr, token.as_deref()))?;
+
+    if json {
+        let json = serde_json::to_string_pretty(&status).map_err(|e| e.to_string())?;
+        println!("{json}");
+    } else {
+        print!("{}", puerta::admin::status::render_table(&status));
+    }
+    Ok(())
+}
+
+fn show_sessions(
+    config_path: PathBuf,
+    admin: Option<String>,
+    backend: Option<String>,
+    json: bool,
+) -> Result<(), String> {
+    let (admin_addr, token) = admin_target(&config_path, admin)?;
+    let report = admin_runtime()?.block_on(puerta::admin::sessions::fetch(
+        &admin_addr,
+        token.as_deref(),
+        backend.as_deref(),
+    ))?;
+
+    if json {
+        let json = serde_json::to_string_pretty(&report).map_err(|e| e.to_string())?;
+        println!("{json}");
+    } else {
+        print!("{}", puerta::admin::sessions::render_table(&report));
+    }
+    Ok(())
+}
+
+/// Drain `backend`, closing its sessions after `timeout` seconds, or
+/// re-enable it
+fn drain_backend(
+    backend: String,
+    config_path: PathBuf,
+    admin: Option<String>,
+    drain: bool,
+    timeout: Option<u64>,
+) -> Result<(), String> {
+    let (admin_addr, token) = admin_target(&config_path, admin)?;
+    let runtime = admin_runtime()?;
+
+    // Backend ids are resolved to addresses through the status report
+    let addr = match backend.parse::<std::net::SocketAddr>() {
+        Ok(addr) => addr.to_string(),
+        Err(_) => {
+            let status = runtime.block_on(puerta::admin::status::fetch(&admin_addr, token.as_deref()))?;
+            status
+                .instances
+                .iter()
+                .flat_map(|instance| &instance.backends)
+                .find(|status| status.id == backend)
+                .map(|status| status.addr.clone())
+                .ok_or_else(|| format!("No backend {backend} at admin API {admin_addr}"))?
+        }
+    };
+
+    let path = match (drain, timeout) {
+        (true, Some(timeout_sec)) => format!("/backends/drain?addr={addr}&timeout_sec={timeout_sec}"),
+        (true, None) => format!("/backends/drain?addr={addr}"),
+        (false, _) => format!("/backends/enable?addr={addr}"),
+    };
+    let body = runtime.block_on(puerta::admin::request(&admin_addr, token.as_deref(), "POST", &path))?;
+    if drain {
+        println!(
+            "Draining backend {addr}, open sessions are closed in {}s",
+            body["timeout_sec"]
+        );
+    } else if body["was_draining"] == true {
+        println!("Re-enabled backend {addr}");
+    } else {
+        println!("Backend {addr} was not draining");
+    }
+    Ok(())
+}
+
+/// The admin API address given on the command line, or else the one in the
+/// configuration file, with the bearer token the configuration file sets
+fn admin_target(config_path: &Path, admin: Option<String>) -> Result<(String, Option<String>), String> {
+    if let Some(admin_addr) = admin {
+        // The configuration file is optional when the address is given
+        let token = Config::load_from_file(config_path)
+            .ok()
+            .and_then(|config| config.admin)
+            .and_then(|admin| admin.token);
+        return Ok((admin_addr, token));
+    }
+    let config = Config::load_from_file(config_path)
+        .map_err(|e| format!("Failed to load config from {:?}: {}", config_path, e))?;
+    let admin = config.admin.ok_or_else(|| {
+        format!("The admin API is not enabled in {:?}; add an [admin] block or pass --admin", config_path)
+    })?;
+    Ok((local_admin_addr(&admin.listen_addr), admin.token))
+}
+
+/// Runtime for admin API requests
+fn admin_runtime() -> Result<tokio::runtime::Runtime, String> {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .map_err(|e| format!("Failed to start runtime: {e}"))
+}
+
+/// Address to reach an admin API bound to `listen_addr`, using loopback for
+/// a wildcard bind
+fn local_admin_addr(listen_addr: &str) -> String {
+    match listen_addr.parse::<std::net::SocketAddr>() {
+        Ok(addr) if addr.ip().is_unspecified() => {
+            let loopback: std::net::IpAddr = if addr.is_ipv4() {
+                std::net::Ipv4Addr::LOCALHOST.into()
+            } else {
+                std::net::Ipv6Addr::LOCALHOST.into()
+            };
+            std::net::SocketAddr::new(loopback, addr.port()).to_string()
+        }
+        _ => listen_addr.to_string(),
+    }
+}
+
+fn run_bench(config: puerta::bench::BenchConfig) -> Result<(), String> {
+    println!(
+        "Sending {} {:?} requests to {} over {} connections",
+        config.requests, config.workload, config.target, config.concurrency
+    );
+    let runtime = tokio::runtime::Runtime::new().map_err(|e| format!("Failed to start runtime: {e}"))?;
+    let report = runtime.block_on(puerta::bench::run(config))?;
+    print!("{}", report.render());
+    Ok(())
+}
+
+fn run_replay(path: &Path, mode: &str, target: &str) -> Result<(), String> {
+    println!("Replaying {} against {target}", path.display());
+    let runtime = tokio::runtime::Runtime::new().map_err(|e| format!("Failed to start runtime: {e}"))?;
+    let report = runtime.block_on(puerta::bench::replay(path, mode, target))?;
+    print!("{}", report.render());
+    Ok(())
+}
+
 fn show_version() {
     println!("puerta v{}", env!("CARGO_PKG_VERSION"));
     println!("A high-performance load balancer for MongoDB Sharded Clusters and Redis Clusters");
@@ -246,17 +781,11 @@ fn show_version() {
 }
 
 fn init_logging(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
-    let log_level = match config.logging.level.as_str() {
-        "debug" => log::LevelFilter::Debug,
-        "info" => log::LevelFilter::Info,
-        "warn" => log::LevelFilter::Warn,
-        "error" => log::LevelFilter::Error,
-        _ => log::LevelFilter::Info,
-    };
+    let log_level = config.logging.level_filter();
 
-    env_logger::Builder::from_default_env()
-        .filter_level(log_level)
-        .init();
+    // The logger gates on the global max level, so the level can be changed
+    // on configuration reload
+    puerta::logging::init(&config.logging)?;
 
     info!("Logging initialized at level: {:?}", log_level);
     Ok(())
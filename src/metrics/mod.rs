@@ -0,0 +1,308 @@
+/// Process-wide metrics
+///
+/// Counters and gauges are registered by name (and optional labels) on first
+/// use and live for the life of the process. The registry renders in the
+/// Prometheus text exposition format for the admin API's `/metrics`, and
+/// `statsd` pushes it to a StatsD agent.
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+
+pub mod payload;
+pub mod statsd;
+
+/// Monotonically increasing count
+#[derive(Debug, Default)]
+pub struct Counter(AtomicU64);
+
+impl Counter {
+    pub fn inc(&self) {
+        self.add(1);
+    }
+
+    pub fn add(&self, n: u64) {
+        self.0.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Value that can go up and down
+#[derive(Debug, Default)]
+pub struct Gauge(AtomicI64);
+
+impl Gauge {
+    pub fn inc(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn dec(&self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub fn set(&self, value: i64) {
+        self.0.store(value, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> i64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Keeps a gauge incremented for as long as it is held
+pub struct GaugeGuard(Arc<Gauge>);
+
+impl GaugeGuard {
+    pub fn new(gauge: Arc<Gauge>) -> Self {
+        gauge.inc();
+        Self(gauge)
+    }
+}
+
+impl Drop for GaugeGuard {
+    fn drop(&mut self) {
+        self.0.dec();
+    }
+}
+
+#[derive(Clone)]
+enum Metric {
+    Counter(Arc<Counter>),
+    Gauge(Arc<Gauge>),
+}
+
+/// One labelled series of a family
+struct Series {
+    labels: Vec<(String, String)>,
+    metric: Metric,
+}
+
+/// All series sharing a metric name
+struct Family {
+    help: String,
+    /// Series keyed by their rendered label set, e.g. `{backend="a"}`
+    series: BTreeMap<String, Series>,
+}
+
+/// Current value of a series
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleValue {
+    Counter(u64),
+    Gauge(i64),
+}
+
+/// A series and its value at the time of a snapshot
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sample {
+    pub name: String,
+    pub labels: Vec<(String, String)>,
+    pub value: SampleValue,
+}
+
+/// Registry of named metrics
+#[derive(Default)]
+pub struct Registry {
+    families: Mutex<BTreeMap<String, Family>>,
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get or register an unlabelled counter
+    pub fn counter(&self, name: &str, help: &str) -> Arc<Counter> {
+        self.counter_with_labels(name, help, &[])
+    }
+
+    /// Get or register a counter with the given labels
+    pub fn counter_with_labels(
+        &self,
+        name: &str,
+        help: &str,
+        labels: &[(&str, &str)],
+    ) -> Arc<Counter> {
+        match self.get_or_register(name, help, labels, || Metric::Counter(Arc::default())) {
+            Metric::Counter(counter) => counter,
+            Metric::Gauge(_) => panic!("metric {name} is registered as a gauge"),
+        }
+    }
+
+    /// Get or register an unlabelled gauge
+    pub fn gauge(&self, name: &str, help: &str) -> Arc<Gauge> {
+        self.gauge_with_labels(name, help, &[])
+    }
+
+    /// Get or register a gauge with the given labels
+    pub fn gauge_with_labels(&self, name: &str, help: &str, labels: &[(&str, &str)]) -> Arc<Gauge> {
+        match self.get_or_register(name, help, labels, || Metric::Gauge(Arc::default())) {
+            Metric::Gauge(gauge) => gauge,
+            Metric::Counter(_) => panic!("metric {name} is registered as a counter"),
+        }
+    }
+
+    fn get_or_register(
+        &self,
+        name: &str,
+        help: &str,
+        labels: &[(&str, &str)],
+        new_metric: impl FnOnce() -> Metric,
+    ) -> Metric {
+        let mut families = self.families.lock().unwrap();
+        let family = families.entry(name.to_string()).or_insert_with(|| Family {
+            help: help.to_string(),
+            series: BTreeMap::new(),
+        });
+        family
+            .series
+            .entry(render_labels(labels))
+            .or_insert_with(|| Series {
+                labels: labels
+                    .iter()
+                    .map(|(name, value)| (name.to_string(), value.to_string()))
+                    .collect(),
+                metric: new_metric(),
+            })
+            .metric
+            .clone()
+    }
+
+    /// Every series with its current value, ordered by name and labels
+    pub fn snapshot(&self) -> Vec<Sample> {
+        let families = self.families.lock().unwrap();
+        families
+            .iter()
+            .flat_map(|(name, family)| {
+                family.series.values().map(move |series| Sample {
+                    name: name.clone(),
+                    labels: series.labels.clone(),
+                    value: match &series.metric {
+                        Metric::Counter(counter) => SampleValue::Counter(counter.get()),
+                        Metric::Gauge(gauge) => SampleValue::Gauge(gauge.get()),
+                    },
+                })
+            })
+            .collect()
+    }
+
+    /// Render every metric in the Prometheus text format
+    pub fn render_prometheus(&self) -> String {
+        let families = self.families.lock().unwrap();
+        let mut out = String::new();
+        for (name, family) in families.iter() {
+            let kind = match family.series.values().next().map(|series| &series.metric) {
+                Some(Metric::Counter(_)) => "counter",
+                Some(Metric::Gauge(_)) => "gauge",
+                None => continue,
+            };
+            let _ = writeln!(out, "# HELP {name} {}", family.help);
+            let _ = writeln!(out, "# TYPE {name} {kind}");
+            for (labels, series) in &family.series {
+                let _ = match &series.metric {
+                    Metric::Counter(counter) => writeln!(out, "{name}{labels} {}", counter.get()),
+                    Metric::Gauge(gauge) => writeln!(out, "{name}{labels} {}", gauge.get()),
+                };
+            }
+        }
+        out
+    }
+}
+
+/// Render labels as `{a="1",b="2"}`, or nothing without labels
+fn render_labels(labels: &[(&str, &str)]) -> String {
+    if labels.is_empty() {
+        return String::new();
+    }
+    let pairs: Vec<String> = labels
+        .iter()
+        .map(|(name, value)| {
+            let value = value
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n");
+            format!("{name}=\"{value}\"")
+        })
+        .collect();
+    format!("{{{}}}", pairs.join(","))
+}
+
+/// The process-wide registry
+pub fn global() -> &'static Registry {
+    static REGISTRY: OnceLock<Registry> = OnceLock::new();
+    REGISTRY.get_or_init(Registry::new)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counters_and_gauges_are_shared_by_name() {
+        let registry = Registry::new();
+        registry.counter("requests_total", "Requests").inc();
+        registry.counter("requests_total", "Requests").add(2);
+        assert_eq!(registry.counter("requests_total", "Requests").get(), 3);
+
+        let gauge = registry.gauge("active", "Active");
+        gauge.inc();
+        gauge.inc();
+        gauge.dec();
+        assert_eq!(registry.gauge("active", "Active").get(), 1);
+
+        let guard = GaugeGuard::new(Arc::clone(&gauge));
+        assert_eq!(gauge.get(), 2);
+        drop(guard);
+        assert_eq!(gauge.get(), 1);
+    }
+
+    #[test]
+    fn test_render_prometheus() {
+        let registry = Registry::new();
+        registry
+            .counter_with_labels("errors_total", "Errors", &[("backend", "10.0.0.1:27017")])
+            .inc();
+        registry
+            .counter_with_labels("errors_total", "Errors", &[("backend", "say \"hi\"")])
+            .add(2);
+        registry.gauge("active", "Active connections").set(4);
+
+        assert_eq!(
+            registry.render_prometheus(),
+            "# HELP active Active connections\n\
+             # TYPE active gauge\n\
+             active 4\n\
+             # HELP errors_total Errors\n\
+             # TYPE errors_total counter\n\
+             errors_total{backend=\"10.0.0.1:27017\"} 1\n\
+             errors_total{backend=\"say \\\"hi\\\"\"} 2\n"
+        );
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let registry = Registry::new();
+        registry
+            .counter_with_labels("errors_total", "Errors", &[("backend", "10.0.0.1:27017")])
+            .add(3);
+        registry.gauge("active", "Active connections").set(-1);
+
+        assert_eq!(
+            registry.snapshot(),
+            vec![
+                Sample {
+                    name: "active".to_string(),
+                    labels: Vec::new(),
+                    value: SampleValue::Gauge(-1),
+                },
+                Sample {
+                    name: "errors_total".to_string(),
+                    labels: vec![("backend".to_string(), "10.0.0.1:27017".to_string())],
+                    value: SampleValue::Counter(3),
+                },
+            ]
+        );
+    }
+}
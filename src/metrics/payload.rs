@@ -0,0 +1,244 @@
+/// Large reply detection
+///
+/// Replies at or above a configured size are logged, counted, and
+/// attributed to what produced them: the key in Redis mode, the operation
+/// in MongoDB mode. The largest offenders are kept in a bounded table the
+/// admin API reports, so big keys and unbounded queries can be found
+/// without scanning the backends.
+use super::Counter;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Bytes of a key or operation name kept per offender
+const MAX_NAME_LEN: usize = 128;
+
+/// A key or operation that produced large replies
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Offender {
+    /// Redis key or MongoDB operation, truncated
+    pub name: String,
+    /// Command of the most recent large reply
+    pub command: String,
+    /// Large replies seen
+    pub count: u64,
+    pub max_bytes: usize,
+    pub total_bytes: u64,
+    /// Unix time of the most recent large reply, in seconds
+    pub last_seen: u64,
+    pub last_client_addr: String,
+}
+
+/// Large reply tracker shared by every connection of a listener
+pub struct LargePayloads {
+    mode: &'static str,
+    threshold_bytes: usize,
+    max_offenders: usize,
+    offenders: Mutex<HashMap<String, Offender>>,
+    replies: Arc<Counter>,
+    bytes: Arc<Counter>,
+}
+
+impl LargePayloads {
+    /// Track replies of at least `threshold_bytes`, keeping the
+    /// `max_offenders` largest; `mode` names the proxy mode in metrics and
+    /// admin paths
+    pub fn new(mode: &'static str, threshold_bytes: usize, max_offenders: usize) -> Self {
+        let registry = super::global();
+        Self {
+            mode,
+            threshold_bytes,
+            max_offenders,
+            offenders: Mutex::new(HashMap::new()),
+            replies: registry.counter_with_labels(
+                "puerta_large_replies_total",
+                "Replies at or above the large payload threshold",
+                &[("mode", mode)],
+            ),
+            bytes: registry.counter_with_labels(
+                "puerta_large_reply_bytes_total",
+                "Bytes of replies at or above the large payload threshold",
+                &[("mode", mode)],
+            ),
+        }
+    }
+
+    pub fn threshold_bytes(&self) -> usize {
+        self.threshold_bytes
+    }
+
+    /// Record a reply of `size` bytes produced by `name` (a key or
+    /// operation) for `command`, returning whether it was large
+    pub fn record(&self, name: &str, command: &str, size: usize, client_addr: &str) -> bool {
+        if size < self.threshold_bytes {
+            return false;
+        }
+        let name = Self::truncated_name(name);
+        log::warn!(
+            "Large {} reply of {size} bytes for {command} {name} to client {client_addr}",
+            self.mode
+        );
+        self.replies.inc();
+        self.bytes.add(size as u64);
+
+        let last_seen = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let mut offenders = self.offenders.lock().unwrap();
+        if !offenders.contains_key(&name) && offenders.len() >= self.max_offenders {
+            // Make room by dropping the smallest offender, if this one is larger
+            let smallest = offenders
+                .values()
+                .min_by_key(|offender| offender.max_bytes)
+                .filter(|offender| offender.max_bytes < size)
+                .map(|offender| offender.name.clone());
+            match smallest {
+                Some(smallest) => {
+                    offenders.remove(&smallest);
+                }
+                None => return true,
+            }
+        }
+        let offender = offenders.entry(name.clone()).or_insert_with(|| Offender {
+            name,
+            command: String::new(),
+            count: 0,
+            max_bytes: 0,
+            total_bytes: 0,
+            last_seen: 0,
+            last_client_addr: String::new(),
+        });
+        offender.command = command.to_string();
+        offender.count += 1;
+        offender.max_bytes = offender.max_bytes.max(size);
+        offender.total_bytes += size as u64;
+        offender.last_seen = last_seen;
+        offender.last_client_addr = client_addr.to_string();
+        true
+    }
+
+    fn truncated_name(name: &str) -> String {
+        if name.len() <= MAX_NAME_LEN {
+            return name.to_string();
+        }
+        let mut end = MAX_NAME_LEN;
+        while !name.is_char_boundary(end) {
+            end -= 1;
+        }
+        format!("{}... ({} more bytes)", &name[..end], name.len() - end)
+    }
+
+    /// The `count` largest offenders, largest reply first
+    pub fn top(&self, count: usize) -> Vec<Offender> {
+        let mut offenders: Vec<Offender> =
+            self.offenders.lock().unwrap().values().cloned().collect();
+        offenders.sort_by(|a, b| {
+            b.max_bytes
+                .cmp(&a.max_bytes)
+                .then_with(|| a.name.cmp(&b.name))
+        });
+        offenders.truncate(count);
+        offenders
+    }
+
+    /// Forget every offender
+    pub fn reset(&self) {
+        self.offenders.lock().unwrap().clear();
+    }
+
+    /// Register the `/<mode>/large-replies` admin endpoints
+    pub fn register_admin_routes(
+        self: &Arc<Self>,
+        router: crate::admin::AdminRouter,
+    ) -> crate::admin::AdminRouter {
+        use crate::admin::AdminResponse;
+        use serde_json::json;
+
+        let path = format!("/{}/large-replies", self.mode);
+        let top = Arc::clone(self);
+        let reset = Arc::clone(self);
+        router
+            .route("GET", &path, move |request| {
+                let count = request.query_param("count").unwrap_or(10);
+                let body = json!({
+                    "threshold_bytes": top.threshold_bytes,
+                    "offenders": top.top(count),
+                });
+                async move { AdminResponse::ok(body) }
+            })
+            .route("POST", &format!("{path}/reset"), move |_| {
+                reset.reset();
+                async { AdminResponse::ok(json!({ "reset": true })) }
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_records_replies_over_threshold() {
+        let payloads = LargePayloads::new("redis", 1024, 8);
+        assert!(!payloads.record("small", "GET", 1023, "10.0.0.1:1"));
+        assert!(payloads.record("big", "GET", 4096, "10.0.0.1:1"));
+        assert!(payloads.record("big", "HGETALL", 2048, "10.0.0.2:1"));
+
+        let top = payloads.top(10);
+        assert_eq!(top.len(), 1);
+        assert_eq!(top[0].name, "big");
+        assert_eq!(top[0].command, "HGETALL");
+        assert_eq!(top[0].count, 2);
+        assert_eq!(top[0].max_bytes, 4096);
+        assert_eq!(top[0].total_bytes, 6144);
+        assert_eq!(top[0].last_client_addr, "10.0.0.2:1");
+
+        payloads.reset();
+        assert!(payloads.top(10).is_empty());
+    }
+
+    #[test]
+    fn test_keeps_largest_offenders() {
+        let payloads = LargePayloads::new("mongodb", 1, 2);
+        payloads.record("a", "find", 10, "c");
+        payloads.record("b", "find", 30, "c");
+        // Smaller than every tracked offender, so not kept
+        payloads.record("c", "find", 5, "c");
+        // Replaces the smallest
+        payloads.record("d", "find", 20, "c");
+
+        let names: Vec<String> = payloads.top(10).into_iter().map(|o| o.name).collect();
+        assert_eq!(names, vec!["b", "d"]);
+        assert_eq!(payloads.top(1)[0].name, "b");
+
+        let long = "k".repeat(200);
+        payloads.record(&long, "find", 100, "c");
+        assert_eq!(
+            payloads.top(1)[0].name,
+            format!("{}... (72 more bytes)", "k".repeat(128))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_admin_routes() {
+        let payloads = Arc::new(LargePayloads::new("redis", 1, 8));
+        payloads.record("user:1", "GET", 64, "10.0.0.1:1");
+        let router = payloads.register_admin_routes(crate::admin::AdminRouter::new());
+
+        let request = |method: &str, path: &str| crate::admin::AdminRequest {
+            method: method.to_string(),
+            path: path.to_string(),
+            ..Default::default()
+        };
+        let top = router.handle(request("GET", "/redis/large-replies")).await;
+        assert_eq!(top.body["threshold_bytes"], 1);
+        assert_eq!(top.body["offenders"][0]["name"], "user:1");
+
+        router
+            .handle(request("POST", "/redis/large-replies/reset"))
+            .await;
+        assert!(payloads.top(10).is_empty());
+    }
+}
@@ -0,0 +1,383 @@
+/// StatsD / DogStatsD push exporter
+///
+/// For agents that collect StatsD rather than scrape `/metrics`, the
+/// exporter sends the registry to a StatsD endpoint every flush interval:
+/// gauges at their current value and counters as their increase since the
+/// previous flush. In Redis mode it adds the p50, p95 and p99 latency of the
+/// commands completed during the interval, from the latency buckets. With
+/// `dogstatsd` labels travel as tags; plain StatsD has no tags, so label
+/// values are appended to the metric name instead.
+use super::{Registry, SampleValue};
+use crate::config::StatsdConfig;
+use crate::modes::redis::stats::{bucket_percentile, CommandStats, LatencyBuckets};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::UdpSocket;
+
+/// Largest datagram sent, small enough not to be fragmented on the
+/// common 1500-byte MTU
+pub const MAX_PACKET_BYTES: usize = 1432;
+
+/// Prefix of the registry's metric names, replaced by the configured prefix
+const REGISTRY_PREFIX: &str = "puerta_";
+
+/// Latency percentiles sent per Redis command
+const PERCENTILES: [(&str, f64); 3] = [("p50", 0.5), ("p95", 0.95), ("p99", 0.99)];
+
+/// Sends the metrics of a registry to a StatsD agent
+pub struct StatsdExporter<'a> {
+    config: StatsdConfig,
+    registry: &'a Registry,
+    command_stats: Option<Arc<CommandStats>>,
+    /// Counter values at the previous flush, by metric line prefix
+    counters: HashMap<String, u64>,
+    /// Latency buckets at the previous flush, per Redis command
+    latencies: HashMap<String, LatencyBuckets>,
+    socket: Option<UdpSocket>,
+}
+
+impl<'a> StatsdExporter<'a> {
+    pub fn new(config: StatsdConfig, registry: &'a Registry) -> Self {
+        Self {
+            config,
+            registry,
+            command_stats: None,
+            counters: HashMap::new(),
+            latencies: HashMap::new(),
+            socket: None,
+        }
+    }
+
+    /// Also send the latency percentiles of Redis commands
+    pub fn with_command_stats(mut self, command_stats: Arc<CommandStats>) -> Self {
+        self.command_stats = Some(command_stats);
+        self
+    }
+
+    /// Metric lines due at this flush, remembering the counters sent
+    pub fn flush_lines(&mut self) -> Vec<String> {
+        let mut lines = Vec::new();
+        for sample in self.registry.snapshot() {
+            let labels: Vec<(&str, &str)> = sample
+                .labels
+                .iter()
+                .map(|(name, value)| (name.as_str(), value.as_str()))
+                .collect();
+            let metric = self.metric(&sample.name, &labels);
+            match sample.value {
+                SampleValue::Gauge(value) => lines.push(self.line(&metric, value, "g")),
+                SampleValue::Counter(value) => {
+                    let previous = self.counters.insert(metric.clone(), value).unwrap_or(0);
+                    let delta = value.saturating_sub(previous);
+                    if delta > 0 {
+                        lines.push(self.line(&metric, delta, "c"));
+                    }
+                }
+            }
+        }
+
+        let latencies = match &self.command_stats {
+            Some(command_stats) => command_stats.latency_buckets(),
+            None => HashMap::new(),
+        };
+        let mut commands: Vec<_> = latencies.into_iter().collect();
+        commands.sort_by(|(a, _), (b, _)| a.cmp(b));
+        for (command, buckets) in commands {
+            let previous = self
+                .latencies
+                .insert(command.clone(), buckets)
+                .unwrap_or_default();
+            let mut interval = LatencyBuckets::default();
+            for (calls, (now, before)) in interval.iter_mut().zip(buckets.iter().zip(&previous)) {
+                *calls = now.saturating_sub(*before);
+            }
+            let calls: u64 = interval.iter().sum();
+            if calls == 0 {
+                continue;
+            }
+            let labels = [("command", command.as_str())];
+            let metric = self.metric("puerta_redis_commands_total", &labels);
+            lines.push(self.line(&metric, calls, "c"));
+            for (name, quantile) in PERCENTILES {
+                if let Some(latency_us) = bucket_percentile(&interval, quantile) {
+                    let name = format!("puerta_redis_command_latency_us_{name}");
+                    let metric = self.metric(&name, &labels);
+                    lines.push(self.line(&metric, latency_us, "g"));
+                }
+            }
+        }
+        lines
+    }
+
+    /// Send the metrics due at this flush
+    pub async fn flush(&mut self) -> std::io::Result<()> {
+        let lines = self.flush_lines();
+        if lines.is_empty() {
+            return Ok(());
+        }
+        let target = tokio::net::lookup_host(&self.config.address)
+            .await?
+            .next()
+            .ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    format!("{} did not resolve", self.config.address),
+                )
+            })?;
+        let socket = self.socket(target).await?;
+        for packet in packets(&lines) {
+            socket.send_to(packet.as_bytes(), target).await?;
+        }
+        Ok(())
+    }
+
+    /// A socket of the address family of `target`
+    async fn socket(&mut self, target: SocketAddr) -> std::io::Result<&UdpSocket> {
+        let matches = self.socket.as_ref().is_some_and(|socket| {
+            socket
+                .local_addr()
+                .is_ok_and(|local| local.is_ipv4() == target.is_ipv4())
+        });
+        if !matches {
+            let bind = if target.is_ipv4() {
+                "0.0.0.0:0"
+            } else {
+                "[::]:0"
+            };
+            self.socket = Some(UdpSocket::bind(bind).await?);
+        }
+        Ok(self.socket.as_ref().unwrap())
+    }
+
+    /// Metric name with the configured prefix, followed in plain StatsD by
+    /// the label values or in DogStatsD by the tags
+    fn metric(&self, name: &str, labels: &[(&str, &str)]) -> String {
+        let name = name.strip_prefix(REGISTRY_PREFIX).unwrap_or(name);
+        let mut metric = if self.config.prefix.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}.{}", self.config.prefix, name)
+        };
+        if !self.config.dogstatsd {
+            for (_, value) in labels {
+                metric.push('.');
+                metric.push_str(&sanitize(value, &['.', ':']));
+            }
+            return metric;
+        }
+
+        let mut constant: Vec<_> = self.config.tags.iter().collect();
+        constant.sort();
+        let tags: Vec<String> = labels
+            .iter()
+            .map(|(name, value)| (*name, *value))
+            .chain(
+                constant
+                    .iter()
+                    .map(|(name, value)| (name.as_str(), value.as_str())),
+            )
+            .map(|(name, value)| format!("{}:{}", sanitize(name, &[':']), sanitize(value, &[])))
+            .collect();
+        if !tags.is_empty() {
+            metric.push_str("|#");
+            metric.push_str(&tags.join(","));
+        }
+        metric
+    }
+
+    /// `name:value|kind`, with any DogStatsD tags after the kind
+    fn line(&self, metric: &str, value: impl std::fmt::Display, kind: &str) -> String {
+        match metric.split_once("|#") {
+            Some((name, tags)) => format!("{name}:{value}|{kind}|#{tags}"),
+            None => format!("{metric}:{value}|{kind}"),
+        }
+    }
+}
+
+impl StatsdExporter<'static> {
+    /// Flush every `flush_interval_sec` on a background thread
+    pub fn spawn(mut self) {
+        std::thread::spawn(move || {
+            let rt = crate::utils::background_runtime();
+            rt.block_on(async move {
+                let period = Duration::from_secs(self.config.flush_interval_sec);
+                log::info!(
+                    "Sending metrics to StatsD at {} every {}s",
+                    self.config.address,
+                    period.as_secs()
+                );
+                let mut interval =
+                    tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+                loop {
+                    interval.tick().await;
+                    if let Err(e) = self.flush().await {
+                        log::warn!(
+                            "Failed to send metrics to StatsD at {}: {e}",
+                            self.config.address
+                        );
+                    }
+                }
+            });
+        });
+    }
+}
+
+/// Replace the characters StatsD reserves, and `extra`, with `_`
+fn sanitize(value: &str, extra: &[char]) -> String {
+    value
+        .chars()
+        .map(|c| match c {
+            '|' | '@' | '#' | ',' | '\n' | ' ' => '_',
+            c if extra.contains(&c) => '_',
+            c => c,
+        })
+        .collect()
+}
+
+/// Newline-separated lines packed into datagrams of at most
+/// `MAX_PACKET_BYTES`
+fn packets(lines: &[String]) -> Vec<String> {
+    let mut packets = Vec::new();
+    let mut packet = String::new();
+    for line in lines {
+        if !packet.is_empty() && packet.len() + 1 + line.len() > MAX_PACKET_BYTES {
+            packets.push(std::mem::take(&mut packet));
+        }
+        if !packet.is_empty() {
+            packet.push('\n');
+        }
+        packet.push_str(line);
+    }
+    if !packet.is_empty() {
+        packets.push(packet);
+    }
+    packets
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modes::redis::RedisCommand;
+
+    fn get() -> RedisCommand {
+        RedisCommand {
+            command: "GET".to_string(),
+            args: Vec::new(),
+            key: None,
+            slot: None,
+            readonly: true,
+        }
+    }
+
+    #[test]
+    fn test_flush_lines_statsd() {
+        let registry = Registry::new();
+        registry.gauge("puerta_connections_active", "Active").set(3);
+        let transitions = registry.counter_with_labels(
+            "puerta_backend_health_transitions_total",
+            "Transitions",
+            &[("backend", "10.0.0.1:27017"), ("state", "unhealthy")],
+        );
+        transitions.add(2);
+
+        let mut exporter = StatsdExporter::new(StatsdConfig::default(), &registry);
+        assert_eq!(
+            exporter.flush_lines(),
+            vec![
+                "puerta.backend_health_transitions_total.10_0_0_1_27017.unhealthy:2|c",
+                "puerta.connections_active:3|g",
+            ]
+        );
+
+        // Counters are sent as their increase, and left out when unchanged
+        assert_eq!(
+            exporter.flush_lines(),
+            vec!["puerta.connections_active:3|g"]
+        );
+        transitions.inc();
+        assert_eq!(
+            exporter.flush_lines()[0],
+            "puerta.backend_health_transitions_total.10_0_0_1_27017.unhealthy:1|c"
+        );
+    }
+
+    #[test]
+    fn test_flush_lines_dogstatsd_with_latency() {
+        let registry = Registry::new();
+        registry
+            .counter_with_labels(
+                "puerta_connections_rejected_total",
+                "Rejected",
+                &[("listener", "a,b")],
+            )
+            .inc();
+        let command_stats = Arc::new(CommandStats::new(Duration::from_secs(1), 0));
+        command_stats.record(&get(), "10.0.0.5:50000", Duration::from_micros(80));
+        command_stats.record(&get(), "10.0.0.5:50000", Duration::from_micros(3_000));
+
+        let config = StatsdConfig {
+            prefix: "edge".to_string(),
+            dogstatsd: true,
+            tags: [("env".to_string(), "prod".to_string())]
+                .into_iter()
+                .collect(),
+            ..StatsdConfig::default()
+        };
+        let mut exporter =
+            StatsdExporter::new(config, &registry).with_command_stats(Arc::clone(&command_stats));
+        assert_eq!(
+            exporter.flush_lines(),
+            vec![
+                "edge.connections_rejected_total:1|c|#listener:a_b,env:prod",
+                "edge.redis_commands_total:2|c|#command:GET,env:prod",
+                "edge.redis_command_latency_us_p50:100|g|#command:GET,env:prod",
+                "edge.redis_command_latency_us_p95:5000|g|#command:GET,env:prod",
+                "edge.redis_command_latency_us_p99:5000|g|#command:GET,env:prod",
+            ]
+        );
+
+        // Percentiles cover only the commands completed since the last flush
+        command_stats.record(&get(), "10.0.0.5:50000", Duration::from_micros(90));
+        let lines = exporter.flush_lines();
+        assert!(lines.contains(&"edge.redis_commands_total:1|c|#command:GET,env:prod".to_string()));
+        assert!(lines.contains(
+            &"edge.redis_command_latency_us_p99:100|g|#command:GET,env:prod".to_string()
+        ));
+        assert!(exporter.flush_lines().is_empty());
+    }
+
+    #[test]
+    fn test_packets() {
+        let lines: Vec<String> = (0..100)
+            .map(|i| format!("puerta.metric_{i:03}:1|c"))
+            .collect();
+        let packets = packets(&lines);
+        assert!(packets.len() > 1);
+        assert!(packets
+            .iter()
+            .all(|packet| packet.len() <= MAX_PACKET_BYTES));
+        assert_eq!(packets.join("\n"), lines.join("\n"));
+    }
+
+    #[tokio::test]
+    async fn test_flush_sends_datagrams() {
+        let agent = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let registry = Registry::new();
+        registry.gauge("puerta_connections_active", "Active").set(7);
+        let config = StatsdConfig {
+            address: agent.local_addr().unwrap().to_string(),
+            ..StatsdConfig::default()
+        };
+        let mut exporter = StatsdExporter::new(config, &registry);
+        exporter.flush().await.unwrap();
+
+        let mut buf = [0u8; MAX_PACKET_BYTES];
+        let (len, _) = tokio::time::timeout(Duration::from_secs(5), agent.recv_from(&mut buf))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(&buf[..len], b"puerta.connections_active:7|g");
+    }
+}
@@ -1,12 +1,123 @@
 /// Different operational modes for puerta
+///
+/// Each protocol lives in its own module and plugs into [`crate::Puerta`]
+/// through a [`ProxyModeHandler`]: adding a protocol means adding a module
+/// with a handler carrying the settings of an instance in that mode.
 pub mod mongodb;
 pub mod redis;
 
+use crate::admin::explain::{ExplainSource, Explainer};
+use crate::admin::status::StatusReporter;
+use crate::admin::AdminRouter;
+use crate::core::frontend::ConnectionLimiter;
 use crate::core::Backend;
-use std::collections::HashMap;
+use crate::modes::redis::stats::CommandStats;
+use crate::{Puerta, ProxyInstance};
+use pingora_core::server::Server;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
+/// A protocol puerta proxies
+///
+/// A handler holds the mode settings of one instance and builds its Pingora
+/// services and the checker probing its backends. Routing happens inside
+/// those services, so it stays private to the mode.
+pub trait ProxyModeHandler: fmt::Debug + Send + Sync {
+    /// Protocol name for logging, e.g. "MongoDB"
+    fn name(&self) -> &'static str;
+
+    /// Name of the primary instance
+    fn service_name(&self) -> &'static str;
+
+    /// Check the mode settings, e.g. that backends are given
+    fn validate(&self) -> Result<(), String>;
+
+    /// Checker probing the backends of the instance
+    fn health_checker(&self) -> Box<dyn crate::health::HealthChecker>;
+
+    /// Add the services of `instance` to the server of `context`, along
+    /// with its status and admin routes
+    fn add_instance(
+        &self,
+        puerta: &Puerta,
+        instance: &ProxyInstance,
+        context: &mut InstanceContext<'_>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>>;
+
+    /// Copy of the handler, for cloning the instances holding it
+    fn clone_box(&self) -> Box<dyn ProxyModeHandler>;
+}
+
+impl Clone for Box<dyn ProxyModeHandler> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
+/// What instances of every mode share while the server is built
+pub struct InstanceContext<'a> {
+    pub server: &'a mut Server,
+    /// Whether the instance is the primary `[proxy]` one, which alone
+    /// follows discovery and configuration reloads
+    pub is_primary: bool,
+    pub connection_limiter: &'a ConnectionLimiter,
+    pub access_control: &'a Arc<crate::acl::AccessControl>,
+    pub status_reporter: &'a mut StatusReporter,
+    admin_router: AdminRouter,
+    registered: HashSet<&'static str>,
+    command_stats: Option<Arc<CommandStats>>,
+    explainer: Explainer,
+}
+
+impl<'a> InstanceContext<'a> {
+    pub fn new(
+        server: &'a mut Server,
+        connection_limiter: &'a ConnectionLimiter,
+        access_control: &'a Arc<crate::acl::AccessControl>,
+        status_reporter: &'a mut StatusReporter,
+        admin_router: AdminRouter,
+    ) -> Self {
+        Self {
+            server,
+            is_primary: false,
+            connection_limiter,
+            access_control,
+            status_reporter,
+            admin_router,
+            registered: HashSet::new(),
+            command_stats: None,
+            explainer: Explainer::new(),
+        }
+    }
+
+    /// Add admin routes with `register`, unless routes were already added
+    /// under `key`, so the admin API reports the first instance having them
+    pub fn register_admin_routes_once(&mut self, key: &'static str, register: impl FnOnce(AdminRouter) -> AdminRouter) {
+        if self.registered.insert(key) {
+            self.admin_router = register(std::mem::take(&mut self.admin_router));
+        }
+    }
+
+    /// Report `command_stats` to StatsD, unless an earlier instance's are
+    pub fn export_command_stats(&mut self, command_stats: Arc<CommandStats>) {
+        self.command_stats.get_or_insert(command_stats);
+    }
+
+    /// Explain the routing of an instance through `POST /explain`
+    pub fn add_explain_source(&mut self, source: ExplainSource) {
+        self.explainer.add_source(source);
+    }
+
+    /// The admin routes and command statistics gathered from the instances
+    pub fn finish(self) -> (AdminRouter, Option<Arc<CommandStats>>) {
+        let admin_router = Arc::new(self.explainer).register_admin_routes(self.admin_router);
+        (admin_router, self.command_stats)
+    }
+}
+
 /// Shared backend pool management
 pub type BackendPool = Arc<RwLock<HashMap<String, Backend>>>;
 
@@ -23,3 +134,31 @@ pub enum RoutingDecision {
         command: String,
     },
 }
+
+#[cfg(test)]
+mod tests {
+    use super::mongodb::handler::MongoDBHandler;
+    use super::mongodb::MongoDBConfig;
+    use super::redis::handler::RedisHandler;
+    use super::redis::RedisConfig;
+    use super::ProxyModeHandler;
+
+    #[test]
+    fn test_handlers_carry_their_mode() {
+        let mongodb: Box<dyn ProxyModeHandler> = Box::new(MongoDBHandler::new(MongoDBConfig {
+            mongos_endpoints: vec!["127.0.0.1:27017".to_string()],
+            ..Default::default()
+        }));
+        let redis: Box<dyn ProxyModeHandler> = Box::new(RedisHandler::new(RedisConfig {
+            cluster_nodes: vec!["127.0.0.1:7000".to_string()],
+            ..Default::default()
+        }));
+        assert_eq!(mongodb.name(), "MongoDB");
+        assert_eq!(mongodb.service_name(), "MongoDB TCP Proxy");
+        assert_eq!(redis.name(), "Redis");
+        assert_eq!(redis.service_name(), "Redis Cluster Proxy");
+        assert_eq!(redis.clone().name(), "Redis");
+        assert!(mongodb.validate().is_ok());
+        assert!(redis.validate().is_ok());
+    }
+}
@@ -5,7 +5,10 @@ use std::sync::Arc;
 use std::time::{Duration, SystemTime};
 use tokio::sync::RwLock;
 use sha2::{Sha256, Digest};
+use serde::{Deserialize, Serialize};
 use std::fmt;
+use super::bson::Document;
+use super::compression::hello_command;
 
 /// Client identification strategy for session affinity
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -57,9 +60,11 @@ pub struct AffinityManager {
 }
 
 /// Strategy for identifying clients
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum ClientIdentificationStrategy {
     /// Use socket address only (legacy, has NAT issues)
+    #[default]
     SocketAddressOnly,
     /// Use connection fingerprinting (NAT-friendly)
     ConnectionFingerprint,
@@ -69,6 +74,103 @@ pub enum ClientIdentificationStrategy {
     Adaptive,
 }
 
+impl From<SocketAddr> for ClientIdentifier {
+    fn from(client_addr: SocketAddr) -> Self {
+        ClientIdentifier::SocketAddr(client_addr)
+    }
+}
+
+/// Identify a client by `strategy` from its address and the hello handshake
+/// it opened the connection with, if seen
+///
+/// The fingerprint hashes the client IP with the driver, OS and application
+/// metadata of the handshake, so connections from one application instance
+/// share it whatever their source port. The session ID is the application
+/// name the driver was configured with (`appName`). The adaptive strategy
+/// uses the session ID, then the fingerprint, then the socket address,
+/// whichever is available first.
+pub fn identify_client(
+    strategy: ClientIdentificationStrategy,
+    client_addr: SocketAddr,
+    handshake: Option<&[u8]>,
+) -> ClientIdentifier {
+    match strategy {
+        ClientIdentificationStrategy::SocketAddressOnly => ClientIdentifier::SocketAddr(client_addr),
+        ClientIdentificationStrategy::ConnectionFingerprint => {
+            ClientIdentifier::ConnectionFingerprint(connection_fingerprint(client_addr, handshake))
+        }
+        ClientIdentificationStrategy::SessionId => match session_id(handshake) {
+            Some(session_id) => ClientIdentifier::SessionId(session_id),
+            None => ClientIdentifier::ConnectionFingerprint(connection_fingerprint(client_addr, handshake)),
+        },
+        ClientIdentificationStrategy::Adaptive => match (session_id(handshake), handshake) {
+            (Some(session_id), _) => ClientIdentifier::SessionId(session_id),
+            (None, Some(_)) => {
+                ClientIdentifier::ConnectionFingerprint(connection_fingerprint(client_addr, handshake))
+            }
+            (None, None) => ClientIdentifier::SocketAddr(client_addr),
+        },
+    }
+}
+
+/// Client metadata document of a hello handshake
+fn client_metadata(handshake: Option<&[u8]>) -> Option<Document<'_>> {
+    hello_command(handshake?)?.get_document("client")
+}
+
+/// Fingerprint of the client IP (not the port, which NAT changes) and the
+/// metadata of its handshake
+fn connection_fingerprint(client_addr: SocketAddr, handshake: Option<&[u8]>) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(client_addr.ip().to_string().as_bytes());
+    if let Some(metadata) = client_metadata(handshake) {
+        hasher.update(metadata.as_bytes());
+    }
+    // The first 8 bytes are plenty to tell clients apart
+    hex::encode(&hasher.finalize()[..8])
+}
+
+/// Application and driver a client names in its hello handshake
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClientMetadata {
+    /// Application name the driver was configured with (`appName`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub application: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub driver_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub driver_version: Option<String>,
+}
+
+impl ClientMetadata {
+    /// Metadata of a hello handshake, `None` for other messages and
+    /// handshakes naming neither application nor driver
+    pub fn from_handshake(handshake: &[u8]) -> Option<Self> {
+        let metadata = client_metadata(Some(handshake))?;
+        let field = |document: &str, key: &str| {
+            metadata
+                .get_document(document)
+                .and_then(|document| document.get_str(key))
+                .filter(|value| !value.is_empty())
+                .map(str::to_string)
+        };
+        let metadata = Self {
+            application: field("application", "name"),
+            driver_name: field("driver", "name"),
+            driver_version: field("driver", "version"),
+        };
+        (metadata != Self::default()).then_some(metadata)
+    }
+}
+
+/// Application name from the client metadata of a handshake
+fn session_id(handshake: Option<&[u8]>) -> Option<String> {
+    let name = client_metadata(handshake)?
+        .get_document("application")?
+        .get_str("name")?;
+    (!name.is_empty()).then(|| name.to_string())
+}
+
 impl AffinityManager {
     pub fn new(session_timeout: Duration) -> Self {
         Self {
@@ -88,66 +190,7 @@ impl AffinityManager {
     
     /// Generate client identifier based on the configured strategy
     pub fn generate_client_identifier(&self, client_addr: SocketAddr, connection_data: Option<&[u8]>) -> ClientIdentifier {
-        match &self.identification_strategy {
-            ClientIdentificationStrategy::SocketAddressOnly => {
-                ClientIdentifier::SocketAddr(client_addr)
-            }
-            ClientIdentificationStrategy::ConnectionFingerprint => {
-                let fingerprint = self.generate_connection_fingerprint(client_addr, connection_data);
-                ClientIdentifier::ConnectionFingerprint(fingerprint)
-            }
-            ClientIdentificationStrategy::SessionId => {
-                // Try to extract session ID from connection data, fallback to fingerprint
-                if let Some(session_id) = self.extract_session_id(connection_data) {
-                    ClientIdentifier::SessionId(session_id)
-                } else {
-                    let fingerprint = self.generate_connection_fingerprint(client_addr, connection_data);
-                    ClientIdentifier::ConnectionFingerprint(fingerprint)
-                }
-            }
-            ClientIdentificationStrategy::Adaptive => {
-                let fingerprint = self.generate_connection_fingerprint(client_addr, connection_data);
-                ClientIdentifier::Hybrid {
-                    socket_addr: client_addr,
-                    fingerprint,
-                }
-            }
-        }
-    }
-    
-    /// Generate connection fingerprint based on connection characteristics
-    fn generate_connection_fingerprint(&self, client_addr: SocketAddr, connection_data: Option<&[u8]>) -> String {
-        let mut hasher = Sha256::new();
-        
-        // Include IP address (but not port to handle NAT port changes)
-        hasher.update(client_addr.ip().to_string().as_bytes());
-        
-        // Include connection timing characteristics
-        hasher.update(SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)
-                     .unwrap_or_default().as_secs().to_string().as_bytes());
-        
-        // Include connection data characteristics if available
-        if let Some(data) = connection_data {
-            // Use first few bytes as connection signature (e.g., MongoDB handshake)
-            let signature_len = std::cmp::min(data.len(), 32);
-            hasher.update(&data[..signature_len]);
-        }
-        
-        // Create a shorter, more manageable fingerprint
-        let result = hasher.finalize();
-        hex::encode(&result[..8]) // Use first 8 bytes for shorter fingerprint
-    }
-    
-    /// Extract session ID from MongoDB connection data if available
-    fn extract_session_id(&self, connection_data: Option<&[u8]>) -> Option<String> {
-        // In a full implementation, this would parse MongoDB Wire Protocol
-        // to extract client session information from the handshake
-        // For now, return None to fallback to fingerprinting
-        connection_data.and_then(|_data| {
-            // TODO: Implement MongoDB Wire Protocol parsing to extract session ID
-            // This would look for client metadata in the initial handshake
-            None
-        })
+        identify_client(self.identification_strategy, client_addr, connection_data)
     }
 
     /// Get or assign backend for client with session affinity (enhanced with new identification strategies)
@@ -322,7 +365,7 @@ impl AffinityManager {
 }
 
 /// Statistics about session affinity
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct AffinityStatistics {
     pub total_sessions: usize,
     pub total_connections: u64,
@@ -365,6 +408,82 @@ mod tests {
         assert_eq!(session.unwrap().backend_id, "backend1");
     }
 
+    /// OP_MSG hello carrying client metadata, with `app_name` if given
+    fn hello(request_id: i32, app_name: Option<&str>) -> Vec<u8> {
+        use crate::modes::mongodb::bson::DocumentBuilder;
+
+        let driver = DocumentBuilder::new()
+            .string("name", "nodejs")
+            .string("version", "6.8.0")
+            .build();
+        let mut client = DocumentBuilder::new();
+        if let Some(app_name) = app_name {
+            client = client.document("application", DocumentBuilder::new().string("name", app_name).build());
+        }
+        let command = DocumentBuilder::new()
+            .int32("hello", 1)
+            .document("client", client.document("driver", driver).build())
+            .string("$db", "admin")
+            .build();
+        crate::modes::mongodb::test_support::op_msg(request_id, 0, 0, &command)
+    }
+
+    #[test]
+    fn test_identify_client() {
+        let client = |port| SocketAddr::new(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 7)), port);
+        let other_host = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 8)), 40000);
+        let named = hello(1, Some("orders"));
+        let unnamed = hello(2, None);
+
+        assert_eq!(
+            identify_client(ClientIdentificationStrategy::SocketAddressOnly, client(40000), Some(&named)),
+            ClientIdentifier::SocketAddr(client(40000))
+        );
+
+        // Stable across connections of a host, whatever the port and request id
+        let fingerprint = |addr, handshake: &[u8]| {
+            identify_client(ClientIdentificationStrategy::ConnectionFingerprint, addr, Some(handshake))
+        };
+        assert_eq!(fingerprint(client(40000), &named), fingerprint(client(40001), &hello(9, Some("orders"))));
+        assert_ne!(fingerprint(client(40000), &named), fingerprint(other_host, &named));
+        assert_ne!(fingerprint(client(40000), &named), fingerprint(client(40000), &unnamed));
+
+        assert_eq!(
+            identify_client(ClientIdentificationStrategy::SessionId, other_host, Some(&named)),
+            ClientIdentifier::SessionId("orders".to_string())
+        );
+        assert!(matches!(
+            identify_client(ClientIdentificationStrategy::SessionId, client(40000), Some(&unnamed)),
+            ClientIdentifier::ConnectionFingerprint(_)
+        ));
+
+        let adaptive = |handshake| identify_client(ClientIdentificationStrategy::Adaptive, client(40000), handshake);
+        assert_eq!(adaptive(Some(&named)), ClientIdentifier::SessionId("orders".to_string()));
+        assert!(matches!(adaptive(Some(&unnamed)), ClientIdentifier::ConnectionFingerprint(_)));
+        assert_eq!(adaptive(None), ClientIdentifier::SocketAddr(client(40000)));
+    }
+
+    #[test]
+    fn test_client_metadata() {
+        assert_eq!(
+            ClientMetadata::from_handshake(&hello(1, Some("orders"))),
+            Some(ClientMetadata {
+                application: Some("orders".to_string()),
+                driver_name: Some("nodejs".to_string()),
+                driver_version: Some("6.8.0".to_string()),
+            })
+        );
+        let unnamed = ClientMetadata::from_handshake(&hello(2, None)).unwrap();
+        assert_eq!(unnamed.application, None);
+        assert_eq!(unnamed.driver_name.as_deref(), Some("nodejs"));
+
+        // Only hello handshakes carry metadata
+        let mut ping = hello(3, Some("orders"));
+        let hello_key = ping.windows(6).position(|window| window == b"hello\0").unwrap();
+        ping[hello_key..hello_key + 5].copy_from_slice(b"pings");
+        assert_eq!(ClientMetadata::from_handshake(&ping), None);
+    }
+
     #[tokio::test]
     async fn test_session_cleanup() {
         let manager = AffinityManager::new(Duration::from_millis(10)); // Very short timeout
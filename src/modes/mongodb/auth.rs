@@ -0,0 +1,75 @@
+/// Recognizing MongoDB authentication traffic
+///
+/// Drivers authenticate with SASL conversations or the legacy
+/// `authenticate` command, optionally starting the conversation in the
+/// hello handshake with `speculativeAuthenticate`, and user management
+/// commands carry passwords too. Captures leave these messages out.
+use super::compression::{client_command, is_hello};
+
+/// Whether a client's message carries credentials: an authentication or
+/// user management command, or a hello with `speculativeAuthenticate`
+///
+/// Authentication is never compressed, so compressed messages carry none.
+pub fn carries_credentials(message: &[u8]) -> bool {
+    const CREDENTIAL_COMMANDS: &[&str] = &[
+        "saslStart",
+        "saslContinue",
+        "authenticate",
+        "getnonce",
+        "copydbSaslStart",
+        "createUser",
+        "updateUser",
+    ];
+    let Some(command) = client_command(message) else {
+        return false;
+    };
+    command.first_key().is_some_and(|name| CREDENTIAL_COMMANDS.contains(&name))
+        || (is_hello(&command) && command.get("speculativeAuthenticate").is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Compressor;
+    use crate::modes::mongodb::bson::DocumentBuilder;
+    use crate::modes::mongodb::compression::compress;
+    use crate::modes::mongodb::test_support::{message, op_msg};
+
+    #[test]
+    fn test_carries_credentials() {
+        let sasl = DocumentBuilder::new()
+            .int32("saslStart", 1)
+            .string("mechanism", "SCRAM-SHA-256")
+            .string("$db", "admin")
+            .build();
+        assert!(carries_credentials(&op_msg(7, 0, 0, &sasl)));
+        let create_user = DocumentBuilder::new()
+            .string("createUser", "app")
+            .string("pwd", "secret")
+            .build();
+        assert!(carries_credentials(&op_msg(7, 0, 0, &create_user)));
+
+        let speculative = DocumentBuilder::new()
+            .int32("hello", 1)
+            .document("speculativeAuthenticate", sasl.clone())
+            .build();
+        assert!(carries_credentials(&op_msg(7, 0, 0, &speculative)));
+        let hello = DocumentBuilder::new()
+            .int32("hello", 1)
+            .string_array("compression", &["zstd"])
+            .build();
+        assert!(!carries_credentials(&op_msg(7, 0, 0, &hello)));
+
+        // Also sent as a legacy OP_QUERY
+        let mut body = 0i32.to_le_bytes().to_vec();
+        body.extend_from_slice(b"admin.$cmd\0");
+        body.extend_from_slice(&0i32.to_le_bytes());
+        body.extend_from_slice(&(-1i32).to_le_bytes());
+        body.extend_from_slice(&speculative);
+        assert!(carries_credentials(&message(7, 0, 2004, &body)));
+
+        let find = DocumentBuilder::new().string("find", "users").string("$db", "app").build();
+        assert!(!carries_credentials(&op_msg(7, 0, 0, &find)));
+        assert!(!carries_credentials(&compress(&op_msg(7, 0, 0, &find), Compressor::Zlib).unwrap()));
+    }
+}
@@ -0,0 +1,295 @@
+/// Runtime changes to the mongos set
+///
+/// Orchestration tooling scales the mongos pool without restarts through
+/// the `POST /backends` and `DELETE /backends/{addr}` admin endpoints. A
+/// new mongos joins only once it passes a health check, and sessions pinned
+/// to a removed one are forgotten. Changes last until a configuration
+/// reload or a discovery update replaces the mongos set.
+use super::MongoDBProxy;
+use crate::admin::{AdminResponse, AdminRouter};
+use crate::config::Endpoint;
+use crate::{MongoDBTcpProxy, ReloadableDiscovery};
+use pingora_load_balancing::{selection::RoundRobin, LoadBalancer};
+use serde_json::json;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// Adds and removes the mongos of a MongoDB instance
+pub struct BackendAdmin {
+    load_balancer: Arc<LoadBalancer<RoundRobin>>,
+    mongodb_proxy: Arc<MongoDBProxy>,
+    discovery: ReloadableDiscovery,
+    /// Held through each change, which reads and replaces the whole set
+    changes: Mutex<()>,
+}
+
+impl BackendAdmin {
+    pub fn new(
+        load_balancer: Arc<LoadBalancer<RoundRobin>>,
+        mongodb_proxy: Arc<MongoDBProxy>,
+        discovery: ReloadableDiscovery,
+    ) -> Self {
+        Self {
+            load_balancer,
+            mongodb_proxy,
+            discovery,
+            changes: Mutex::new(()),
+        }
+    }
+
+    /// The current mongos set
+    pub fn endpoints(&self) -> Vec<Endpoint> {
+        self.discovery
+            .backends()
+            .iter()
+            .map(|backend| Endpoint::new(backend.addr.to_string(), backend.weight))
+            .collect()
+    }
+
+    /// Add `endpoint` to the mongos set once it passes a health check,
+    /// returning the new set
+    pub async fn add(&self, endpoint: Endpoint) -> Result<Vec<Endpoint>, AdminResponse> {
+        endpoint
+            .validate("mongos endpoint")
+            .map_err(|e| AdminResponse::error(400, e.to_string()))?;
+        let Ok(addr) = endpoint.addr.parse::<SocketAddr>() else {
+            return Err(AdminResponse::error(
+                400,
+                format!("mongos endpoint {} must be an IP address", endpoint.addr),
+            ));
+        };
+        if endpoint.health_check.is_some() {
+            return Err(AdminResponse::error(
+                400,
+                "per-endpoint health checks are only set in the configuration",
+            ));
+        }
+
+        let _changes = self.changes.lock().await;
+        let mut endpoints = self.endpoints();
+        if endpoints.iter().any(|existing| existing.addr == addr.to_string()) {
+            return Err(AdminResponse::error(409, format!("mongos {addr} is already a backend")));
+        }
+        let status = self.mongodb_proxy.probe(addr).await;
+        if !status.is_healthy() {
+            return Err(AdminResponse::error(
+                503,
+                format!("mongos {addr} failed its health check: {status}"),
+            ));
+        }
+
+        endpoints.push(Endpoint::new(addr.to_string(), endpoint.weight));
+        self.apply(&endpoints).await?;
+        log::info!("Added mongos {addr} through the admin API");
+        Ok(endpoints)
+    }
+
+    /// Remove the mongos with address or backend id `id` from the mongos
+    /// set, returning the new set
+    pub async fn remove(&self, id: &str) -> Result<Vec<Endpoint>, AdminResponse> {
+        let _changes = self.changes.lock().await;
+        let addr = match id.parse::<SocketAddr>() {
+            Ok(addr) => addr,
+            Err(_) => {
+                let backends = self.mongodb_proxy.get_backends();
+                let backends = backends.read().await;
+                backends
+                    .get(id)
+                    .map(|backend| backend.addr)
+                    .ok_or_else(|| AdminResponse::error(404, format!("no mongos {id}")))?
+            }
+        };
+
+        let mut endpoints = self.endpoints();
+        let count = endpoints.len();
+        endpoints.retain(|endpoint| endpoint.addr != addr.to_string());
+        if endpoints.len() == count {
+            return Err(AdminResponse::error(404, format!("mongos {addr} is not a backend")));
+        }
+        if endpoints.is_empty() {
+            return Err(AdminResponse::error(400, "cannot remove the last mongos"));
+        }
+
+        let invalidated = self.mongodb_proxy.invalidate_sessions(addr).await;
+        self.apply(&endpoints).await?;
+        log::info!("Removed mongos {addr} through the admin API, forgetting {invalidated} sessions");
+        Ok(endpoints)
+    }
+
+    async fn apply(&self, endpoints: &[Endpoint]) -> Result<(), AdminResponse> {
+        MongoDBTcpProxy::apply_endpoints(&self.load_balancer, &self.mongodb_proxy, &self.discovery, endpoints)
+            .await
+            .map_err(|e| AdminResponse::error(500, format!("Failed to apply mongos endpoints: {e}")))
+    }
+
+    fn backends_response(endpoints: &[Endpoint]) -> AdminResponse {
+        let backends: Vec<_> = endpoints
+            .iter()
+            .map(|endpoint| json!({ "addr": endpoint.addr, "weight": endpoint.weight }))
+            .collect();
+        AdminResponse::ok(json!({ "backends": backends }))
+    }
+
+    /// Register the `POST /backends` and `DELETE /backends/{addr}` admin
+    /// endpoints
+    ///
+    /// `POST /backends` takes the endpoint as JSON, either `"host:port"` or
+    /// `{"addr": "host:port", "weight": 2}`.
+    pub fn register_admin_routes(self: &Arc<Self>, router: AdminRouter) -> AdminRouter {
+        let add = Arc::clone(self);
+        let remove = Arc::clone(self);
+        router
+            .route("POST", "/backends", move |request| {
+                let admin = Arc::clone(&add);
+                async move {
+                    let endpoint = match serde_json::from_slice::<Endpoint>(&request.body) {
+                        Ok(endpoint) => endpoint,
+                        Err(e) => return AdminResponse::error(400, format!("invalid backend: {e}")),
+                    };
+                    match admin.add(endpoint).await {
+                        Ok(endpoints) => Self::backends_response(&endpoints),
+                        Err(response) => response,
+                    }
+                }
+            })
+            .route("DELETE", "/backends/{addr}", move |request| {
+                let admin = Arc::clone(&remove);
+                async move {
+                    match admin.remove(request.last_segment()).await {
+                        Ok(endpoints) => Self::backends_response(&endpoints),
+                        Err(response) => response,
+                    }
+                }
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::admin::AdminRequest;
+    use crate::modes::mongodb::bson::DocumentBuilder;
+    use crate::modes::mongodb::MongoDBConfig;
+    use pingora_load_balancing::Backends;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    /// A mongos answering every command with a successful hello reply
+    async fn fake_mongos() -> SocketAddr {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut header = [0u8; 16];
+                    while stream.read_exact(&mut header).await.is_ok() {
+                        let len = u32::from_le_bytes(header[..4].try_into().unwrap()) as usize;
+                        let mut request = vec![0u8; len - 16];
+                        stream.read_exact(&mut request).await.unwrap();
+
+                        let document = DocumentBuilder::new()
+                            .boolean("ismaster", true)
+                            .string("msg", "isdbgrid")
+                            .int32("maxWireVersion", 21)
+                            .int32("ok", 1)
+                            .build();
+                        let mut reply = ((36 + document.len()) as i32).to_le_bytes().to_vec();
+                        reply.extend_from_slice(&[0u8; 8]);
+                        reply.extend_from_slice(&1i32.to_le_bytes());
+                        reply.extend_from_slice(&[0u8; 20]);
+                        reply.extend_from_slice(&document);
+                        if stream.write_all(&reply).await.is_err() {
+                            return;
+                        }
+                    }
+                });
+            }
+        });
+        addr
+    }
+
+    async fn backend_admin() -> (Arc<BackendAdmin>, Arc<MongoDBProxy>) {
+        let discovery = ReloadableDiscovery::new(&[Endpoint::from("127.0.0.1:27017")]).unwrap();
+        let upstreams: LoadBalancer<RoundRobin> =
+            LoadBalancer::from_backends(Backends::new(Box::new(discovery.clone())));
+        upstreams.update().await.unwrap();
+        let config = MongoDBConfig {
+            mongos_endpoints: vec!["127.0.0.1:27017".to_string()],
+            ..Default::default()
+        };
+        let proxy = MongoDBTcpProxy::new(Arc::new(upstreams), config).await.unwrap();
+        (proxy.backend_admin(discovery), Arc::clone(&proxy.mongodb_proxy))
+    }
+
+    fn request(method: &str, path: &str, body: &str) -> AdminRequest {
+        AdminRequest {
+            method: method.to_string(),
+            path: path.to_string(),
+            body: body.as_bytes().to_vec(),
+            ..AdminRequest::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_add_and_remove_mongos() {
+        let (admin, mongodb_proxy) = backend_admin().await;
+        let router = admin.register_admin_routes(AdminRouter::new());
+        let mongos = fake_mongos().await;
+
+        let body = format!(r#"{{"addr": "{mongos}", "weight": 2}}"#);
+        let response = router.handle(request("POST", "/backends", &body)).await;
+        assert_eq!(response.status, 200, "{:?}", response.body);
+        assert_eq!(
+            response.body["backends"],
+            json!([
+                { "addr": "127.0.0.1:27017", "weight": 1 },
+                { "addr": mongos.to_string(), "weight": 2 },
+            ])
+        );
+        let backends = mongodb_proxy.get_backends();
+        let id = {
+            let backends = backends.read().await;
+            let backend = backends.values().find(|backend| backend.addr == mongos).unwrap();
+            assert_eq!(backend.weight, 2);
+            backend.id.clone()
+        };
+        let response = router.handle(request("POST", "/backends", &format!("\"{mongos}\""))).await;
+        assert_eq!(response.status, 409);
+
+        // Removed by address or by backend id
+        let response = router.handle(request("DELETE", &format!("/backends/{mongos}"), "")).await;
+        assert_eq!(response.body["backends"], json!([{ "addr": "127.0.0.1:27017", "weight": 1 }]));
+        assert_eq!(backends.read().await.len(), 1);
+        let response = router.handle(request("DELETE", &format!("/backends/{mongos}"), "")).await;
+        assert_eq!(response.status, 404);
+
+        router.handle(request("POST", "/backends", &format!("\"{mongos}\""))).await;
+        let response = router.handle(request("DELETE", &format!("/backends/{id}"), "")).await;
+        assert_eq!(response.status, 200);
+        assert_eq!(admin.endpoints(), vec![Endpoint::from("127.0.0.1:27017")]);
+
+        let response = router.handle(request("DELETE", "/backends/127.0.0.1:27017", "")).await;
+        assert_eq!(response.status, 400);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_or_unhealthy_mongos_refused() {
+        let (admin, _) = backend_admin().await;
+        let router = admin.register_admin_routes(AdminRouter::new());
+
+        for body in ["", "{\"weight\": 2}", "\"not an endpoint\"", "\"mongos.internal:27017\""] {
+            let response = router.handle(request("POST", "/backends", body)).await;
+            assert_eq!(response.status, 400, "{body}");
+        }
+        let body = r#"{"addr": "10.0.0.1:27017", "weight": 0}"#;
+        assert_eq!(router.handle(request("POST", "/backends", body)).await.status, 400);
+
+        // Nothing listens on a port just released
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let closed = listener.local_addr().unwrap();
+        drop(listener);
+        let response = router.handle(request("POST", "/backends", &format!("\"{closed}\""))).await;
+        assert_eq!(response.status, 503);
+        assert_eq!(admin.endpoints(), vec![Endpoint::from("127.0.0.1:27017")]);
+    }
+}
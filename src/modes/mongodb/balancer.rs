@@ -1,11 +1,20 @@
 /// Load balancing algorithms for MongoDB mongos instances
-use crate::core::Backend;
+use crate::core::latency::LatencyTracker;
+use crate::core::{Backend, BackendMetadata};
 use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
 
 /// Load balancing algorithm trait
 pub trait LoadBalancingAlgorithm: Send + Sync {
     /// Select a backend from the available healthy backends
     fn select_backend(&self, backends: &[Backend]) -> Option<usize>;
+
+    /// Select a backend for a request identified by `key`
+    ///
+    /// Algorithms that do not hash on a key ignore it.
+    fn select_backend_for_key(&self, backends: &[Backend], _key: &[u8]) -> Option<usize> {
+        self.select_backend(backends)
+    }
 }
 
 /// Round-robin load balancing algorithm
@@ -75,7 +84,10 @@ impl LoadBalancingAlgorithm for WeightedRoundRobin {
     }
 }
 
-/// Least connections algorithm
+/// Weighted least connections algorithm
+///
+/// Picks the backend with the fewest connections per unit of weight; ties go
+/// to the earliest backend.
 pub struct LeastConnections;
 
 impl LeastConnections {
@@ -86,13 +98,131 @@ impl LeastConnections {
 
 impl LoadBalancingAlgorithm for LeastConnections {
     fn select_backend(&self, backends: &[Backend]) -> Option<usize> {
-        if backends.is_empty() {
-            return None;
+        // Compare count_a / weight_a with count_b / weight_b without division
+        backends
+            .iter()
+            .enumerate()
+            .filter(|(_, backend)| backend.weight > 0)
+            .min_by(|(_, a), (_, b)| {
+                (connection_count(a) * b.weight).cmp(&(connection_count(b) * a.weight))
+            })
+            .map(|(index, _)| index)
+    }
+}
+
+/// Ketama points per unit of weight, as in libketama (40 digests x 4 points)
+const KETAMA_POINTS_PER_WEIGHT: usize = 160;
+
+/// Ketama consistent hashing
+///
+/// Each backend is placed on a hash ring at points derived from its address,
+/// so a key maps to the same backend across proxy restarts and only keys of
+/// added or removed backends move. The ring is rebuilt when the backend set
+/// changes.
+#[derive(Default)]
+pub struct ConsistentHash {
+    ring: RwLock<KetamaRing>,
+}
+
+#[derive(Default)]
+struct KetamaRing {
+    /// (address, weight) of the backends the ring was built for
+    members: Vec<(String, usize)>,
+    /// Sorted (point, member index) pairs
+    points: Vec<(u32, usize)>,
+}
+
+impl KetamaRing {
+    fn build(members: Vec<(String, usize)>) -> Self {
+        let mut points = Vec::new();
+        for (index, (addr, weight)) in members.iter().enumerate() {
+            for i in 0..(KETAMA_POINTS_PER_WEIGHT * weight / 4) {
+                let digest = md5::compute(format!("{addr}-{i}"));
+                for chunk in digest.chunks(4) {
+                    points.push((u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]), index));
+                }
+            }
         }
+        points.sort_unstable();
+        Self { members, points }
+    }
 
-        // For now, just return round-robin since we don't track connections yet
-        // TODO: Implement actual connection counting
-        Some(0)
+    fn lookup(&self, key: &[u8]) -> Option<usize> {
+        let digest = md5::compute(key);
+        let hash = u32::from_le_bytes([digest[0], digest[1], digest[2], digest[3]]);
+        let position = self.points.partition_point(|(point, _)| *point < hash);
+        self.points
+            .get(position)
+            .or_else(|| self.points.first())
+            .map(|(_, index)| *index)
+    }
+}
+
+impl ConsistentHash {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl LoadBalancingAlgorithm for ConsistentHash {
+    /// Without a key every request hashes alike and lands on one backend
+    fn select_backend(&self, backends: &[Backend]) -> Option<usize> {
+        self.select_backend_for_key(backends, &[])
+    }
+
+    fn select_backend_for_key(&self, backends: &[Backend], key: &[u8]) -> Option<usize> {
+        let members: Vec<(String, usize)> = backends
+            .iter()
+            .map(|backend| (backend.addr.to_string(), backend.weight))
+            .collect();
+
+        {
+            let ring = self.ring.read().unwrap();
+            if ring.members == members {
+                return ring.lookup(key);
+            }
+        }
+
+        let ring = KetamaRing::build(members);
+        let index = ring.lookup(key);
+        *self.ring.write().unwrap() = ring;
+        index
+    }
+}
+
+/// Latency-aware selection on peak-EWMA estimates
+///
+/// Picks the faster of two random backends by connect and first-byte
+/// latency per unit of weight, as recorded in the shared tracker.
+pub struct LatencyAware {
+    latencies: Arc<LatencyTracker>,
+}
+
+impl LatencyAware {
+    pub fn new(latencies: Arc<LatencyTracker>) -> Self {
+        Self { latencies }
+    }
+}
+
+impl LoadBalancingAlgorithm for LatencyAware {
+    fn select_backend(&self, backends: &[Backend]) -> Option<usize> {
+        let addrs: Vec<String> = backends.iter().map(|backend| backend.addr.to_string()).collect();
+        let candidates: Vec<(&str, usize)> = addrs
+            .iter()
+            .zip(backends)
+            .map(|(addr, backend)| (addr.as_str(), backend.weight))
+            .collect();
+        self.latencies.select(&candidates)
+    }
+}
+
+/// Current connection count of a backend
+fn connection_count(backend: &Backend) -> usize {
+    match &backend.metadata {
+        BackendMetadata::MongoDB {
+            connection_count, ..
+        } => *connection_count,
+        BackendMetadata::Redis { .. } => 0,
     }
 }
 
@@ -102,6 +232,10 @@ mod tests {
     use crate::core::Backend;
     use std::net::SocketAddr;
 
+    fn backend_at(addr: &str) -> Backend {
+        Backend::new_mongodb(addr.to_string(), addr.parse().unwrap())
+    }
+
     fn create_test_backend(id: &str, weight: usize) -> Backend {
         let mut backend = Backend::new_mongodb(
             id.to_string(),
@@ -111,6 +245,16 @@ mod tests {
         backend
     }
 
+    fn with_connections(mut backend: Backend, count: usize) -> Backend {
+        if let BackendMetadata::MongoDB {
+            connection_count, ..
+        } = &mut backend.metadata
+        {
+            *connection_count = count;
+        }
+        backend
+    }
+
     #[test]
     fn test_round_robin() {
         let rr = RoundRobin::new();
@@ -147,4 +291,92 @@ mod tests {
         assert_eq!(counts[0], 6);
         assert_eq!(counts[1], 2);
     }
+
+    #[test]
+    fn test_weighted_least_connections() {
+        let lc = LeastConnections::new();
+        assert_eq!(lc.select_backend(&[]), None);
+
+        // 4 connections at weight 3 beat 2 connections at weight 1
+        let backends = vec![
+            with_connections(create_test_backend("backend1", 1), 2),
+            with_connections(create_test_backend("backend2", 3), 4),
+        ];
+        assert_eq!(lc.select_backend(&backends), Some(1));
+
+        // Ties go to the first backend
+        let backends = vec![
+            with_connections(create_test_backend("backend1", 1), 1),
+            with_connections(create_test_backend("backend2", 2), 2),
+        ];
+        assert_eq!(lc.select_backend(&backends), Some(0));
+
+        // Zero-weight backends are never chosen
+        let backends = vec![
+            with_connections(create_test_backend("backend1", 0), 0),
+            with_connections(create_test_backend("backend2", 1), 5),
+        ];
+        assert_eq!(lc.select_backend(&backends), Some(1));
+    }
+
+    #[test]
+    fn test_latency_aware_prefers_faster_backend() {
+        let latencies = Arc::new(LatencyTracker::default());
+        let la = LatencyAware::new(Arc::clone(&latencies));
+        let backends = vec![backend_at("10.0.0.1:27017"), backend_at("10.0.0.2:27017")];
+        latencies.record("10.0.0.1:27017", std::time::Duration::from_millis(30));
+        latencies.record("10.0.0.2:27017", std::time::Duration::from_millis(2));
+        for _ in 0..10 {
+            assert_eq!(la.select_backend(&backends), Some(1));
+        }
+        assert_eq!(la.select_backend(&[]), None);
+    }
+
+    #[test]
+    fn test_consistent_hash_is_stable() {
+        let backends = vec![
+            backend_at("10.0.0.1:27017"),
+            backend_at("10.0.0.2:27017"),
+            backend_at("10.0.0.3:27017"),
+        ];
+        assert_eq!(ConsistentHash::new().select_backend_for_key(&[], b"key"), None);
+
+        // A fresh ring (as after a proxy restart) maps keys identically
+        let first = ConsistentHash::new();
+        let second = ConsistentHash::new();
+        let mut used = [false; 3];
+        for i in 0..100 {
+            let key = format!("192.168.1.{i}");
+            let index = first.select_backend_for_key(&backends, key.as_bytes()).unwrap();
+            assert_eq!(second.select_backend_for_key(&backends, key.as_bytes()), Some(index));
+            used[index] = true;
+        }
+        assert_eq!(used, [true; 3]);
+    }
+
+    #[test]
+    fn test_consistent_hash_minimal_remapping() {
+        let ch = ConsistentHash::new();
+        let backends = vec![
+            backend_at("10.0.0.1:27017"),
+            backend_at("10.0.0.2:27017"),
+            backend_at("10.0.0.3:27017"),
+        ];
+        let before: Vec<String> = (0..200)
+            .map(|i| {
+                let index = ch.select_backend_for_key(&backends, format!("10.1.0.{i}").as_bytes());
+                backends[index.unwrap()].id.clone()
+            })
+            .collect();
+
+        // Removing a backend only moves the keys it owned
+        let remaining = vec![backends[0].clone(), backends[2].clone()];
+        for (i, previous) in before.iter().enumerate() {
+            let index = ch.select_backend_for_key(&remaining, format!("10.1.0.{i}").as_bytes());
+            let now = &remaining[index.unwrap()].id;
+            if previous != "10.0.0.2:27017" {
+                assert_eq!(now, previous);
+            }
+        }
+    }
 }
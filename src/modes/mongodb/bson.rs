@@ -0,0 +1,354 @@
+/// Minimal BSON reader for inspecting commands
+///
+/// Only walks the top-level elements of a borrowed document and reads the
+/// scalar types commands use; nothing is decoded eagerly and unknown or
+/// malformed input simply yields `None`.
+// Element type tags
+const DOUBLE: u8 = 0x01;
+const STRING: u8 = 0x02;
+const DOCUMENT: u8 = 0x03;
+const ARRAY: u8 = 0x04;
+const BINARY: u8 = 0x05;
+const UNDEFINED: u8 = 0x06;
+const OBJECT_ID: u8 = 0x07;
+const BOOLEAN: u8 = 0x08;
+const DATETIME: u8 = 0x09;
+const NULL: u8 = 0x0A;
+const REGEX: u8 = 0x0B;
+const DB_POINTER: u8 = 0x0C;
+const JAVASCRIPT: u8 = 0x0D;
+const SYMBOL: u8 = 0x0E;
+const JAVASCRIPT_WITH_SCOPE: u8 = 0x0F;
+const INT32: u8 = 0x10;
+const TIMESTAMP: u8 = 0x11;
+const INT64: u8 = 0x12;
+const DECIMAL128: u8 = 0x13;
+const MIN_KEY: u8 = 0xFF;
+const MAX_KEY: u8 = 0x7F;
+
+/// A BSON value borrowed from its document
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value<'a> {
+    Double(f64),
+    String(&'a str),
+    Document(Document<'a>),
+    Array(Document<'a>),
+    Boolean(bool),
+    Int32(i32),
+    Int64(i64),
+    Null,
+    /// Any other type, as its raw bytes
+    Other(u8, &'a [u8]),
+}
+
+/// A borrowed BSON document, length prefix and terminator included
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Document<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Document<'a> {
+    /// Read the document at the start of `data`
+    pub fn from_bytes(data: &'a [u8]) -> Option<Self> {
+        let len = read_i32(data, 0)?;
+        if len < 5 || len as usize > data.len() || data[len as usize - 1] != 0 {
+            return None;
+        }
+        Some(Self {
+            data: &data[..len as usize],
+        })
+    }
+
+    /// The encoded document
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// Iterate over `(key, value)` pairs, stopping at the first malformed element
+    pub fn iter(&self) -> Elements<'a> {
+        Elements {
+            data: self.data,
+            offset: 4,
+        }
+    }
+
+    /// Iterate over `(key, type tag, encoded value)` triples, for copying
+    /// elements into another document
+    pub fn raw_iter(&self) -> RawElements<'a> {
+        RawElements(self.iter())
+    }
+
+    /// Value of the first element named `key`
+    pub fn get(&self, key: &str) -> Option<Value<'a>> {
+        self.iter().find(|(name, _)| *name == key).map(|(_, value)| value)
+    }
+
+    pub fn get_str(&self, key: &str) -> Option<&'a str> {
+        match self.get(key)? {
+            Value::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn get_document(&self, key: &str) -> Option<Document<'a>> {
+        match self.get(key)? {
+            Value::Document(document) => Some(document),
+            _ => None,
+        }
+    }
+
+    pub fn get_array(&self, key: &str) -> Option<Document<'a>> {
+        match self.get(key)? {
+            Value::Array(array) => Some(array),
+            _ => None,
+        }
+    }
+
+    /// Name of the first element, which for a command is the command name
+    pub fn first_key(&self) -> Option<&'a str> {
+        self.iter().next().map(|(name, _)| name)
+    }
+}
+
+/// Iterator over the elements of a document
+pub struct Elements<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Elements<'a> {
+    fn next_raw(&mut self) -> Option<(&'a str, u8, &'a [u8])> {
+        let tag = *self.data.get(self.offset)?;
+        if tag == 0 {
+            return None;
+        }
+        let (name, value_start) = read_cstring(self.data, self.offset + 1)?;
+        let value_len = value_len(tag, self.data, value_start)?;
+        let raw = self.data.get(value_start..value_start + value_len)?;
+        self.offset = value_start + value_len;
+        Some((name, tag, raw))
+    }
+}
+
+impl<'a> Iterator for Elements<'a> {
+    type Item = (&'a str, Value<'a>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (name, tag, raw) = self.next_raw()?;
+        let value = match tag {
+            DOUBLE => Value::Double(f64::from_le_bytes(raw.try_into().ok()?)),
+            STRING => Value::String(std::str::from_utf8(&raw[4..raw.len() - 1]).ok()?),
+            DOCUMENT => Value::Document(Document::from_bytes(raw)?),
+            ARRAY => Value::Array(Document::from_bytes(raw)?),
+            BOOLEAN => Value::Boolean(raw[0] != 0),
+            INT32 => Value::Int32(i32::from_le_bytes(raw.try_into().ok()?)),
+            INT64 => Value::Int64(i64::from_le_bytes(raw.try_into().ok()?)),
+            NULL => Value::Null,
+            other => Value::Other(other, raw),
+        };
+        Some((name, value))
+    }
+}
+
+/// Iterator over the undecoded elements of a document
+pub struct RawElements<'a>(Elements<'a>);
+
+impl<'a> Iterator for RawElements<'a> {
+    type Item = (&'a str, u8, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next_raw()
+    }
+}
+
+/// Encoded size of a value of type `tag` starting at `offset`
+fn value_len(tag: u8, data: &[u8], offset: usize) -> Option<usize> {
+    let len = match tag {
+        DOUBLE | DATETIME | TIMESTAMP | INT64 => 8,
+        STRING | JAVASCRIPT | SYMBOL => {
+            let len = read_i32(data, offset)?;
+            if len < 1 {
+                return None;
+            }
+            4 + len as usize
+        }
+        DOCUMENT | ARRAY | JAVASCRIPT_WITH_SCOPE => {
+            let len = read_i32(data, offset)?;
+            if len < 5 {
+                return None;
+            }
+            len as usize
+        }
+        BINARY => 5 + usize::try_from(read_i32(data, offset)?).ok()?,
+        UNDEFINED | NULL | MIN_KEY | MAX_KEY => 0,
+        OBJECT_ID => 12,
+        BOOLEAN => 1,
+        REGEX => {
+            let (_, options) = read_cstring(data, offset)?;
+            let (_, end) = read_cstring(data, options)?;
+            end - offset
+        }
+        DB_POINTER => 4 + usize::try_from(read_i32(data, offset)?).ok()? + 12,
+        INT32 => 4,
+        DECIMAL128 => 16,
+        _ => return None,
+    };
+    (offset + len <= data.len()).then_some(len)
+}
+
+fn read_i32(data: &[u8], offset: usize) -> Option<i32> {
+    let bytes = data.get(offset..offset + 4)?;
+    Some(i32::from_le_bytes(bytes.try_into().ok()?))
+}
+
+/// Read a NUL-terminated string, returning it and the offset after the NUL
+fn read_cstring(data: &[u8], offset: usize) -> Option<(&str, usize)> {
+    let len = data.get(offset..)?.iter().position(|&b| b == 0)?;
+    let value = std::str::from_utf8(&data[offset..offset + len]).ok()?;
+    Some((value, offset + len + 1))
+}
+
+/// Encoder for small documents such as test commands
+#[derive(Debug, Default)]
+pub struct DocumentBuilder {
+    body: Vec<u8>,
+}
+
+impl DocumentBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append an already encoded value of type `tag`
+    pub fn element(mut self, tag: u8, key: &str, value: &[u8]) -> Self {
+        self.body.push(tag);
+        self.body.extend_from_slice(key.as_bytes());
+        self.body.push(0);
+        self.body.extend_from_slice(value);
+        self
+    }
+
+    pub fn string(self, key: &str, value: &str) -> Self {
+        let mut encoded = ((value.len() + 1) as i32).to_le_bytes().to_vec();
+        encoded.extend_from_slice(value.as_bytes());
+        encoded.push(0);
+        self.element(STRING, key, &encoded)
+    }
+
+    pub fn double(self, key: &str, value: f64) -> Self {
+        self.element(DOUBLE, key, &value.to_le_bytes())
+    }
+
+    pub fn int32(self, key: &str, value: i32) -> Self {
+        self.element(INT32, key, &value.to_le_bytes())
+    }
+
+    pub fn int64(self, key: &str, value: i64) -> Self {
+        self.element(INT64, key, &value.to_le_bytes())
+    }
+
+    pub fn boolean(self, key: &str, value: bool) -> Self {
+        self.element(BOOLEAN, key, &[value as u8])
+    }
+
+    pub fn document(self, key: &str, value: Vec<u8>) -> Self {
+        self.element(DOCUMENT, key, &value)
+    }
+
+    /// Append an array, encoded as a document keyed by index
+    pub fn array(self, key: &str, value: Vec<u8>) -> Self {
+        self.element(ARRAY, key, &value)
+    }
+
+    /// Append an array of strings
+    pub fn string_array(self, key: &str, values: &[&str]) -> Self {
+        let array = values
+            .iter()
+            .enumerate()
+            .fold(DocumentBuilder::new(), |array, (index, value)| {
+                array.string(&index.to_string(), value)
+            })
+            .build();
+        self.element(ARRAY, key, &array)
+    }
+
+    /// Encode the document
+    pub fn build(self) -> Vec<u8> {
+        let len = (self.body.len() + 5) as i32;
+        let mut document = len.to_le_bytes().to_vec();
+        document.extend_from_slice(&self.body);
+        document.push(0);
+        document
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_elements() {
+        let data = DocumentBuilder::new()
+            .int32("find", 1)
+            .string("$db", "test")
+            .document(
+                "$readPreference",
+                DocumentBuilder::new().string("mode", "secondaryPreferred").build(),
+            )
+            .boolean("singleBatch", true)
+            .string_array("compression", &["snappy", "zstd"])
+            .build();
+        let document = Document::from_bytes(&data).unwrap();
+
+        assert_eq!(document.first_key(), Some("find"));
+        assert_eq!(document.get("find"), Some(Value::Int32(1)));
+        assert_eq!(document.get_str("$db"), Some("test"));
+        assert_eq!(
+            document
+                .get_document("$readPreference")
+                .and_then(|preference| preference.get_str("mode")),
+            Some("secondaryPreferred")
+        );
+        assert_eq!(document.get("singleBatch"), Some(Value::Boolean(true)));
+        let compression: Vec<Value> = document
+            .get_array("compression")
+            .unwrap()
+            .iter()
+            .map(|(_, value)| value)
+            .collect();
+        assert_eq!(compression, vec![Value::String("snappy"), Value::String("zstd")]);
+        assert_eq!(document.get("missing"), None);
+
+        // Raw elements copy into an identical document
+        let copy = document
+            .raw_iter()
+            .fold(DocumentBuilder::new(), |builder, (key, tag, value)| {
+                builder.element(tag, key, value)
+            })
+            .build();
+        assert_eq!(copy, data);
+    }
+
+    #[test]
+    fn test_skips_other_types_and_rejects_truncation() {
+        let mut data = DocumentBuilder::new().build();
+        // ObjectId then an int32, spliced in before the terminator
+        data.truncate(4);
+        data.push(OBJECT_ID);
+        data.extend_from_slice(b"_id\0");
+        data.extend_from_slice(&[7u8; 12]);
+        data.push(INT32);
+        data.extend_from_slice(b"n\0");
+        data.extend_from_slice(&5i32.to_le_bytes());
+        data.push(0);
+        let len = data.len() as i32;
+        data[..4].copy_from_slice(&len.to_le_bytes());
+
+        let document = Document::from_bytes(&data).unwrap();
+        assert_eq!(document.get("_id"), Some(Value::Other(OBJECT_ID, &[7u8; 12])));
+        assert_eq!(document.get("n"), Some(Value::Int32(5)));
+
+        assert!(Document::from_bytes(&data[..data.len() - 1]).is_none());
+        assert!(Document::from_bytes(&[1, 0]).is_none());
+    }
+}
@@ -0,0 +1,447 @@
+/// OP_COMPRESSED handling and compression negotiation control
+///
+/// Clients offer compressors in the `compression` field of their hello
+/// handshake and mongos answers with the ones it accepts. Rewriting that
+/// offer lets the proxy strip compression or restrict and reorder it, and
+/// decompressing OP_COMPRESSED messages lets the proxy inspect commands
+/// whatever was negotiated. Forwarded messages are never recompressed, so
+/// the proxy cannot force a compressor the client did not offer.
+use super::bson::{Document, DocumentBuilder, Value};
+use super::wire::{MsgHeader, OpCode, HEADER_LEN};
+use crate::config::Compressor;
+use bytes::Bytes;
+use std::io::Read;
+
+/// OP_COMPRESSED compressor ids
+const NOOP_ID: u8 = 0;
+const SNAPPY_ID: u8 = 1;
+const ZLIB_ID: u8 = 2;
+const ZSTD_ID: u8 = 3;
+
+/// Bytes between the header and the compressed data: original opcode,
+/// uncompressed size and compressor id
+const COMPRESSED_PREFIX_LEN: usize = 9;
+
+/// Decompression errors
+#[derive(Debug, thiserror::Error, PartialEq)]
+pub enum CompressionError {
+    #[error("Not an OP_COMPRESSED message")]
+    NotCompressed,
+    #[error("Truncated OP_COMPRESSED message")]
+    Truncated,
+    #[error("Unknown compressor id: {0}")]
+    UnknownCompressor(u8),
+    #[error("Uncompressed message of {size} bytes exceeds the maximum of {max} bytes")]
+    TooLarge { size: usize, max: usize },
+    #[error("Corrupt {compressor} data: {reason}")]
+    Corrupt {
+        compressor: &'static str,
+        reason: String,
+    },
+}
+
+#[cfg(test)]
+fn compressor_id(compressor: Compressor) -> u8 {
+    match compressor {
+        Compressor::Snappy => SNAPPY_ID,
+        Compressor::Zlib => ZLIB_ID,
+        Compressor::Zstd => ZSTD_ID,
+    }
+}
+
+/// Name of the compressor with `id`, for errors
+fn compressor_name(id: u8) -> &'static str {
+    match id {
+        NOOP_ID => "noop",
+        SNAPPY_ID => "snappy",
+        ZLIB_ID => "zlib",
+        ZSTD_ID => "zstd",
+        _ => "unknown",
+    }
+}
+
+fn header_bytes(message_length: usize, request_id: i32, response_to: i32, op_code: i32) -> Vec<u8> {
+    let mut header = Vec::with_capacity(HEADER_LEN);
+    header.extend_from_slice(&(message_length as i32).to_le_bytes());
+    header.extend_from_slice(&request_id.to_le_bytes());
+    header.extend_from_slice(&response_to.to_le_bytes());
+    header.extend_from_slice(&op_code.to_le_bytes());
+    header
+}
+
+fn read_i32(data: &[u8], offset: usize) -> Option<i32> {
+    Some(i32::from_le_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+/// Restore the original message wrapped in a complete OP_COMPRESSED message,
+/// refusing to inflate it past `max_message_size`
+pub fn decompress(message: &[u8], max_message_size: usize) -> Result<Bytes, CompressionError> {
+    let header = MsgHeader::parse(message).ok_or(CompressionError::Truncated)?;
+    if header.op_code != OpCode::Compressed {
+        return Err(CompressionError::NotCompressed);
+    }
+    let original_op_code = read_i32(message, HEADER_LEN).ok_or(CompressionError::Truncated)?;
+    let size = read_i32(message, HEADER_LEN + 4).ok_or(CompressionError::Truncated)?;
+    let id = *message
+        .get(HEADER_LEN + 8)
+        .ok_or(CompressionError::Truncated)?;
+    let end = (header.message_length as usize).min(message.len());
+    let data = message
+        .get(HEADER_LEN + COMPRESSED_PREFIX_LEN..end)
+        .ok_or(CompressionError::Truncated)?;
+
+    let size = usize::try_from(size).map_err(|_| CompressionError::Truncated)?;
+    if HEADER_LEN + size > max_message_size {
+        return Err(CompressionError::TooLarge {
+            size: HEADER_LEN + size,
+            max: max_message_size,
+        });
+    }
+
+    let corrupt = |reason: String| CompressionError::Corrupt {
+        compressor: compressor_name(id),
+        reason,
+    };
+    let body = match id {
+        NOOP_ID => data.to_vec(),
+        SNAPPY_ID => {
+            // Check the claimed length before snappy allocates for it
+            let len = snap::raw::decompress_len(data).map_err(|e| corrupt(e.to_string()))?;
+            if len != size {
+                return Err(corrupt(format!("claims {len} bytes, expected {size}")));
+            }
+            snap::raw::Decoder::new()
+                .decompress_vec(data)
+                .map_err(|e| corrupt(e.to_string()))?
+        }
+        ZLIB_ID => {
+            let mut body = Vec::with_capacity(size);
+            flate2::read::ZlibDecoder::new(data)
+                .take(size as u64 + 1)
+                .read_to_end(&mut body)
+                .map_err(|e| corrupt(e.to_string()))?;
+            body
+        }
+        ZSTD_ID => zstd::bulk::decompress(data, size).map_err(|e| corrupt(e.to_string()))?,
+        other => return Err(CompressionError::UnknownCompressor(other)),
+    };
+    if body.len() != size {
+        return Err(corrupt(format!(
+            "inflated to {} bytes, expected {size}",
+            body.len()
+        )));
+    }
+
+    let mut original = header_bytes(
+        HEADER_LEN + size,
+        header.request_id,
+        header.response_to,
+        original_op_code,
+    );
+    original.extend_from_slice(&body);
+    Ok(Bytes::from(original))
+}
+
+/// Wrap a complete message in OP_COMPRESSED using `compressor`, for tests
+/// of the messages compressing drivers send
+#[cfg(test)]
+pub(crate) fn compress(message: &[u8], compressor: Compressor) -> Result<Bytes, CompressionError> {
+    use std::io::Write;
+
+    let header = MsgHeader::parse(message).ok_or(CompressionError::Truncated)?;
+    let original_op_code = read_i32(message, 12).ok_or(CompressionError::Truncated)?;
+    let body = &message[HEADER_LEN..];
+    let failed = |reason: String| CompressionError::Corrupt {
+        compressor: compressor.name(),
+        reason,
+    };
+    let data = match compressor {
+        Compressor::Snappy => snap::raw::Encoder::new()
+            .compress_vec(body)
+            .map_err(|e| failed(e.to_string()))?,
+        Compressor::Zlib => {
+            let mut encoder =
+                flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(body).map_err(|e| failed(e.to_string()))?;
+            encoder.finish().map_err(|e| failed(e.to_string()))?
+        }
+        Compressor::Zstd => zstd::bulk::compress(body, 0).map_err(|e| failed(e.to_string()))?,
+    };
+
+    let mut compressed = header_bytes(
+        HEADER_LEN + COMPRESSED_PREFIX_LEN + data.len(),
+        header.request_id,
+        header.response_to,
+        2012,
+    );
+    compressed.extend_from_slice(&original_op_code.to_le_bytes());
+    compressed.extend_from_slice(&(body.len() as i32).to_le_bytes());
+    compressed.push(compressor_id(compressor));
+    compressed.extend_from_slice(&data);
+    Ok(Bytes::from(compressed))
+}
+
+/// Whether `command` is a hello handshake
+pub(super) fn is_hello(command: &Document) -> bool {
+    matches!(command.first_key(), Some("hello" | "isMaster" | "ismaster"))
+}
+
+/// The command a client's message carries, sent either as OP_MSG or as a
+/// legacy OP_QUERY on a `$cmd` collection
+pub(super) fn client_command(message: &[u8]) -> Option<Document<'_>> {
+    let header = MsgHeader::parse(message)?;
+    let end = (header.message_length as usize).min(message.len());
+    let message = &message[..end];
+    match header.op_code {
+        OpCode::Msg => super::wire::op_msg_body(message),
+        OpCode::Query => Some(legacy_command(message)?.1),
+        _ => None,
+    }
+}
+
+/// The hello command a client's message carries, sent either as OP_MSG or
+/// as a legacy OP_QUERY on `admin.$cmd`
+pub fn hello_command(message: &[u8]) -> Option<Document<'_>> {
+    client_command(message).filter(is_hello)
+}
+
+/// Offset and document of the command in an OP_QUERY on a `$cmd`
+/// collection
+fn legacy_command(message: &[u8]) -> Option<(usize, Document<'_>)> {
+    // flags, fullCollectionName, numberToSkip, numberToReturn, query
+    let name_end = HEADER_LEN
+        + 4
+        + message
+            .get(HEADER_LEN + 4..)?
+            .iter()
+            .position(|&b| b == 0)?;
+    if !message[HEADER_LEN + 4..name_end].ends_with(b".$cmd") {
+        return None;
+    }
+    let query_start = name_end + 1 + 8;
+    let command = Document::from_bytes(message.get(query_start..)?)?;
+    Some((query_start, command))
+}
+
+/// The hello command with its compressor offer limited to `allowed`, in the
+/// order of `allowed`, or `None` if the offer is already that
+fn rewrite_offer(command: &Document, allowed: &[Compressor]) -> Option<Vec<u8>> {
+    let offered: Vec<&str> = command
+        .get_array("compression")?
+        .iter()
+        .filter_map(|(_, value)| match value {
+            Value::String(name) => Some(name),
+            _ => None,
+        })
+        .collect();
+    let kept: Vec<&str> = allowed
+        .iter()
+        .map(|compressor| compressor.name())
+        .filter(|name| offered.contains(name))
+        .collect();
+    if kept == offered {
+        return None;
+    }
+
+    let rewritten = command.raw_iter().fold(
+        DocumentBuilder::new(),
+        |builder, (key, tag, value)| match key {
+            "compression" if kept.is_empty() => builder,
+            "compression" => builder.string_array(key, &kept),
+            _ => builder.element(tag, key, value),
+        },
+    );
+    Some(rewritten.build())
+}
+
+/// Apply the compression policy to a client's hello handshake
+///
+/// Hello is sent either as OP_MSG or as a legacy OP_QUERY on `admin.$cmd`;
+/// it is never compressed. Returns the rewritten message, or `None` if
+/// `message` is not a hello or its offer needs no change.
+pub fn rewrite_hello(message: &[u8], allowed: &[Compressor]) -> Option<Bytes> {
+    let header = MsgHeader::parse(message)?;
+    let end = (header.message_length as usize).min(message.len());
+    let message = &message[..end];
+    match header.op_code {
+        OpCode::Msg => {
+            // Look for a hello body before copying anything
+            let command = super::wire::op_msg_body(message)?;
+            if !is_hello(&command) {
+                return None;
+            }
+            let body = rewrite_offer(&command, allowed)?;
+            super::wire::with_op_msg_body(message, &body)
+        }
+        OpCode::Query => {
+            let (query_start, command) = legacy_command(message)?;
+            if !is_hello(&command) {
+                return None;
+            }
+            let body = rewrite_offer(&command, allowed)?;
+            let query_end = query_start + command.as_bytes().len();
+
+            let mut rewritten = header_bytes(
+                end - command.as_bytes().len() + body.len(),
+                header.request_id,
+                header.response_to,
+                2004,
+            );
+            rewritten.extend_from_slice(&message[HEADER_LEN..query_start]);
+            rewritten.extend_from_slice(&body);
+            // An optional returnFieldsSelector follows the query
+            rewritten.extend_from_slice(&message[query_end..]);
+            Some(Bytes::from(rewritten))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modes::mongodb::test_support::{message, op_msg};
+    use crate::modes::mongodb::wire;
+
+    fn hello(offer: &[&str]) -> Vec<u8> {
+        DocumentBuilder::new()
+            .int32("hello", 1)
+            .string_array("compression", offer)
+            .string("$db", "admin")
+            .build()
+    }
+
+    fn offer(message: &[u8]) -> Option<Vec<String>> {
+        let command = wire::op_msg_body(message)?;
+        Some(
+            command
+                .get_array("compression")?
+                .iter()
+                .filter_map(|(_, value)| match value {
+                    Value::String(name) => Some(name.to_string()),
+                    _ => None,
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_compress_round_trip() {
+        let original = op_msg(7, 0, 0, &DocumentBuilder::new().int32("ping", 1).build());
+        for compressor in [Compressor::Snappy, Compressor::Zlib, Compressor::Zstd] {
+            let compressed = compress(&original, compressor).unwrap();
+            let header = MsgHeader::parse(&compressed).unwrap();
+            assert_eq!(header.op_code, OpCode::Compressed);
+            assert_eq!(header.message_length as usize, compressed.len());
+            assert_eq!(header.request_id, 7);
+
+            let restored = decompress(&compressed, wire::DEFAULT_MAX_MESSAGE_SIZE).unwrap();
+            assert_eq!(&restored[..], &original[..]);
+            assert_eq!(
+                wire::op_msg_body(&restored).unwrap().first_key(),
+                Some("ping")
+            );
+        }
+    }
+
+    #[test]
+    fn test_decompress_rejects_bad_input() {
+        let original = op_msg(7, 0, 0, &DocumentBuilder::new().int32("ping", 1).build());
+        let compressed = compress(&original, Compressor::Zstd).unwrap();
+
+        assert_eq!(
+            decompress(&original, 1024).unwrap_err(),
+            CompressionError::NotCompressed
+        );
+        assert!(matches!(
+            decompress(&compressed, 20).unwrap_err(),
+            CompressionError::TooLarge { max: 20, .. }
+        ));
+
+        let mut unknown = compressed.to_vec();
+        unknown[HEADER_LEN + 8] = 9;
+        assert_eq!(
+            decompress(&unknown, 1024).unwrap_err(),
+            CompressionError::UnknownCompressor(9)
+        );
+
+        let mut corrupt = compress(&original, Compressor::Snappy).unwrap().to_vec();
+        corrupt.truncate(corrupt.len() - 3);
+        assert!(matches!(
+            decompress(&corrupt, 1024).unwrap_err(),
+            CompressionError::Corrupt {
+                compressor: "snappy",
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_rewrite_op_msg_hello() {
+        let request = op_msg(7, 0, wire::CHECKSUM_PRESENT, &hello(&["snappy", "zlib", "zstd"]));
+
+        // Restricting keeps the configured order of the offered compressors
+        let rewritten = rewrite_hello(&request, &[Compressor::Zstd, Compressor::Snappy]).unwrap();
+        let header = MsgHeader::parse(&rewritten).unwrap();
+        assert_eq!(header.message_length as usize, rewritten.len());
+        assert_eq!(header.request_id, 7);
+        assert_eq!(&rewritten[HEADER_LEN..HEADER_LEN + 4], &0u32.to_le_bytes());
+        assert_eq!(
+            offer(&rewritten),
+            Some(vec!["zstd".to_string(), "snappy".to_string()])
+        );
+        assert_eq!(
+            wire::op_msg_body(&rewritten).unwrap().get_str("$db"),
+            Some("admin")
+        );
+
+        // Stripping removes the field
+        let stripped = rewrite_hello(&request, &[]).unwrap();
+        assert!(wire::op_msg_body(&stripped)
+            .unwrap()
+            .get("compression")
+            .is_none());
+
+        // Offers already within policy and other commands pass untouched
+        let plain = op_msg(7, 0, 0, &hello(&["zstd"]));
+        assert!(rewrite_hello(&plain, &[Compressor::Zstd]).is_none());
+        let ping = op_msg(7, 0, 0, &DocumentBuilder::new().int32("ping", 1).build());
+        assert!(rewrite_hello(&ping, &[]).is_none());
+    }
+
+    #[test]
+    fn test_rewrite_legacy_hello() {
+        let command = DocumentBuilder::new()
+            .int32("isMaster", 1)
+            .string_array("compression", &["zlib", "snappy"])
+            .build();
+        let mut body = 0i32.to_le_bytes().to_vec();
+        body.extend_from_slice(b"admin.$cmd\0");
+        body.extend_from_slice(&0i32.to_le_bytes());
+        body.extend_from_slice(&(-1i32).to_le_bytes());
+        let query_start = HEADER_LEN + body.len();
+        body.extend_from_slice(&command);
+        let request = message(7, 0, 2004, &body);
+
+        let rewritten = rewrite_hello(&request, &[Compressor::Snappy]).unwrap();
+        let header = MsgHeader::parse(&rewritten).unwrap();
+        assert_eq!(header.op_code, OpCode::Query);
+        assert_eq!(header.message_length as usize, rewritten.len());
+        let query = Document::from_bytes(&rewritten[query_start..]).unwrap();
+        assert_eq!(query.first_key(), Some("isMaster"));
+        let offered: Vec<_> = query
+            .get_array("compression")
+            .unwrap()
+            .iter()
+            .map(|(_, value)| value)
+            .collect();
+        assert_eq!(offered, vec![Value::String("snappy")]);
+
+        // Queries on collections are not commands
+        let mut find = request.clone();
+        find[HEADER_LEN + 4..HEADER_LEN + 14].copy_from_slice(b"admin.user");
+        assert!(rewrite_hello(&find, &[]).is_none());
+    }
+}
@@ -0,0 +1,220 @@
+/// Cursor affinity
+///
+/// A cursor lives on the mongos that opened it: a `getMore` or
+/// `killCursors` reaching another mongos fails with CursorNotFound. Mongos
+/// are chosen per connection, so the cursors each client opens are recorded
+/// along with the mongos holding them, and a new connection of a client
+/// with open cursors goes to that mongos even once its session affinity
+/// entry expired or was forgotten. Cursors are forgotten when exhausted,
+/// killed, or idle past mongod's default cursor timeout.
+use super::affinity::ClientIdentifier;
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// mongod's default `cursorTimeoutMillis`, after which idle cursors are closed
+pub const CURSOR_IDLE_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Most cursors tracked at once
+pub const MAX_TRACKED_CURSORS: usize = 100_000;
+
+/// A cursor by the mongos holding it and its id there
+type CursorKey = (String, i64);
+
+#[derive(Debug)]
+struct Cursor {
+    client: ClientIdentifier,
+    last_used: Instant,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    cursors: HashMap<CursorKey, Cursor>,
+    by_client: HashMap<ClientIdentifier, HashSet<CursorKey>>,
+}
+
+impl State {
+    fn remove(&mut self, key: &CursorKey) {
+        let Some(cursor) = self.cursors.remove(key) else {
+            return;
+        };
+        if let Some(keys) = self.by_client.get_mut(&cursor.client) {
+            keys.remove(key);
+            if keys.is_empty() {
+                self.by_client.remove(&cursor.client);
+            }
+        }
+    }
+
+    fn remove_idle(&mut self, idle_timeout: Duration) {
+        let idle: Vec<CursorKey> = self
+            .cursors
+            .iter()
+            .filter(|(_, cursor)| cursor.last_used.elapsed() >= idle_timeout)
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in &idle {
+            self.remove(key);
+        }
+    }
+}
+
+/// Open cursors of every client of a listener, by mongos
+#[derive(Debug)]
+pub struct CursorAffinity {
+    idle_timeout: Duration,
+    max_cursors: usize,
+    state: Mutex<State>,
+}
+
+impl Default for CursorAffinity {
+    fn default() -> Self {
+        Self::new(CURSOR_IDLE_TIMEOUT, MAX_TRACKED_CURSORS)
+    }
+}
+
+impl CursorAffinity {
+    pub fn new(idle_timeout: Duration, max_cursors: usize) -> Self {
+        Self {
+            idle_timeout,
+            max_cursors,
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    /// Record cursor `id` returned by `backend` to `client`, or that it is
+    /// still open
+    pub fn opened(&self, client: &ClientIdentifier, backend: &str, id: i64) {
+        let key = (backend.to_string(), id);
+        let mut state = self.state.lock().unwrap();
+        if let Some(cursor) = state.cursors.get_mut(&key) {
+            cursor.last_used = Instant::now();
+            return;
+        }
+        if state.cursors.len() >= self.max_cursors {
+            state.remove_idle(self.idle_timeout);
+            if state.cursors.len() >= self.max_cursors {
+                log::debug!("Not tracking cursor {id} on mongos {backend}: {} cursors tracked", self.max_cursors);
+                return;
+            }
+        }
+        state.by_client.entry(client.clone()).or_default().insert(key.clone());
+        state.cursors.insert(
+            key,
+            Cursor {
+                client: client.clone(),
+                last_used: Instant::now(),
+            },
+        );
+    }
+
+    /// Keep cursor `id` on `backend` from going idle
+    pub fn used(&self, backend: &str, id: i64) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(cursor) = state.cursors.get_mut(&(backend.to_string(), id)) {
+            cursor.last_used = Instant::now();
+        }
+    }
+
+    /// Forget cursors exhausted or killed on `backend`
+    pub fn closed(&self, backend: &str, ids: &[i64]) {
+        let mut state = self.state.lock().unwrap();
+        for &id in ids {
+            state.remove(&(backend.to_string(), id));
+        }
+    }
+
+    /// The mongos holding the most recently used open cursor of `client`
+    pub fn backend_of(&self, client: &ClientIdentifier) -> Option<String> {
+        let mut state = self.state.lock().unwrap();
+        let keys: Vec<CursorKey> = state.by_client.get(client)?.iter().cloned().collect();
+        let mut newest: Option<(&CursorKey, Instant)> = None;
+        let mut idle = Vec::new();
+        for key in &keys {
+            let Some(cursor) = state.cursors.get(key) else {
+                continue;
+            };
+            if cursor.last_used.elapsed() >= self.idle_timeout {
+                idle.push(key.clone());
+            } else if newest.is_none_or(|(_, last_used)| cursor.last_used > last_used) {
+                newest = Some((key, cursor.last_used));
+            }
+        }
+        let backend = newest.map(|((backend, _), _)| backend.clone());
+        for key in &idle {
+            state.remove(key);
+        }
+        backend
+    }
+
+    /// Number of open cursors tracked
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().cursors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn client(name: &str) -> ClientIdentifier {
+        ClientIdentifier::SessionId(name.to_string())
+    }
+
+    #[test]
+    fn test_client_follows_its_open_cursors() {
+        let cursors = CursorAffinity::default();
+        assert_eq!(cursors.backend_of(&client("app")), None);
+
+        cursors.opened(&client("app"), "10.0.1.1:27017", 11);
+        cursors.opened(&client("app"), "10.0.1.2:27017", 12);
+        cursors.opened(&client("batch"), "10.0.1.3:27017", 13);
+        assert_eq!(cursors.len(), 3);
+        // The most recently used cursor wins
+        assert_eq!(cursors.backend_of(&client("app")).as_deref(), Some("10.0.1.2:27017"));
+        std::thread::sleep(Duration::from_millis(2));
+        cursors.used("10.0.1.1:27017", 11);
+        assert_eq!(cursors.backend_of(&client("app")).as_deref(), Some("10.0.1.1:27017"));
+
+        // Exhausted and killed cursors no longer pin the client
+        cursors.closed("10.0.1.1:27017", &[11]);
+        assert_eq!(cursors.backend_of(&client("app")).as_deref(), Some("10.0.1.2:27017"));
+        cursors.closed("10.0.1.2:27017", &[12, 99]);
+        assert_eq!(cursors.backend_of(&client("app")), None);
+        assert_eq!(cursors.backend_of(&client("batch")).as_deref(), Some("10.0.1.3:27017"));
+        assert_eq!(cursors.len(), 1);
+    }
+
+    #[test]
+    fn test_cursor_ids_are_per_mongos() {
+        let cursors = CursorAffinity::default();
+        cursors.opened(&client("app"), "10.0.1.1:27017", 7);
+        cursors.opened(&client("batch"), "10.0.1.2:27017", 7);
+        cursors.closed("10.0.1.1:27017", &[7]);
+        assert_eq!(cursors.backend_of(&client("app")), None);
+        assert_eq!(cursors.backend_of(&client("batch")).as_deref(), Some("10.0.1.2:27017"));
+    }
+
+    #[test]
+    fn test_idle_cursors_expire() {
+        let cursors = CursorAffinity::new(Duration::from_millis(20), 2);
+        cursors.opened(&client("app"), "10.0.1.1:27017", 1);
+        cursors.opened(&client("app"), "10.0.1.1:27017", 2);
+        // Full: a new cursor is not tracked until others go idle
+        cursors.opened(&client("batch"), "10.0.1.2:27017", 3);
+        assert_eq!(cursors.backend_of(&client("batch")), None);
+
+        std::thread::sleep(Duration::from_millis(30));
+        cursors.opened(&client("batch"), "10.0.1.2:27017", 3);
+        assert_eq!(cursors.len(), 1);
+        assert_eq!(cursors.backend_of(&client("batch")).as_deref(), Some("10.0.1.2:27017"));
+
+        std::thread::sleep(Duration::from_millis(30));
+        assert_eq!(cursors.backend_of(&client("batch")), None);
+        assert!(cursors.is_empty());
+    }
+}
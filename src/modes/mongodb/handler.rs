@@ -0,0 +1,200 @@
+/// Proxy mode handler for MongoDB sharded clusters
+///
+/// Each MongoDB instance is a Pingora TCP service balancing client
+/// connections across the mongos endpoints, with session affinity.
+use super::MongoDBConfig;
+use crate::config::Endpoint;
+use crate::modes::{InstanceContext, ProxyModeHandler};
+use crate::{MongoDBTcpProxy, ProxyInstance, Puerta, ReloadableDiscovery};
+use pingora_core::services::listening::Service;
+use pingora_load_balancing::{selection::RoundRobin, Backends, LoadBalancer};
+use std::error::Error;
+use std::sync::Arc;
+
+/// MongoDB mode of an instance
+#[derive(Debug, Clone, Default)]
+pub struct MongoDBHandler {
+    /// Mongos endpoints, session affinity and detailed settings
+    config: MongoDBConfig,
+}
+
+impl MongoDBHandler {
+    pub fn new(config: MongoDBConfig) -> Self {
+        Self { config }
+    }
+}
+
+impl ProxyModeHandler for MongoDBHandler {
+    fn name(&self) -> &'static str {
+        "MongoDB"
+    }
+
+    fn service_name(&self) -> &'static str {
+        "MongoDB TCP Proxy"
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.config.mongos_endpoints.is_empty() {
+            return Err("At least one mongos endpoint is required".to_string());
+        }
+        Ok(())
+    }
+
+    fn health_checker(&self) -> Box<dyn crate::health::HealthChecker> {
+        Box::new(crate::health::mongodb::MongoDBHealthChecker::new())
+    }
+
+    fn add_instance(
+        &self,
+        puerta: &Puerta,
+        instance: &ProxyInstance,
+        context: &mut InstanceContext<'_>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        log::info!(
+            "Starting {} in MongoDB TCP proxy mode using Pingora framework",
+            instance.name
+        );
+
+        let defaults = self.config.clone();
+        let session_affinity_enabled = defaults.session_affinity_enabled;
+
+        // With backend discovery the discovered mongos set replaces the static one
+        let static_endpoints: Vec<Endpoint> = defaults
+            .mongos_endpoints
+            .iter()
+            .map(|addr| Endpoint {
+                health_check: defaults.endpoint_health_checks.get(addr).cloned(),
+                ..Endpoint::new(addr.clone(), defaults.endpoint_weights.get(addr).copied().unwrap_or(1))
+            })
+            .collect();
+        let discovered = puerta.backend_discovery(static_endpoints.clone(), context.is_primary, |config| match &config.proxy {
+            crate::config::ProxyConfig::MongoDB {
+                mongos_endpoints, ..
+            } => Some(mongos_endpoints.clone()),
+            _ => None,
+        });
+        let initial_endpoints = match &discovered {
+            Some(receiver) => crate::discovery::DiscoveredEndpoint::endpoints(&receiver.borrow()),
+            None => static_endpoints,
+        };
+        let mongos_endpoints = Endpoint::addrs(&initial_endpoints);
+        let mut endpoint_health_checks = defaults.endpoint_health_checks;
+        endpoint_health_checks.extend(Endpoint::health_checks(&initial_endpoints));
+
+        let mongodb_config = MongoDBConfig::new(
+            mongos_endpoints.clone(),
+            session_affinity_enabled,
+            defaults.session_timeout_sec,
+            puerta.config.health_check_interval_ms / 1000,
+        )
+        .map_err(|e| format!("Invalid MongoDB configuration: {e}"))?
+        .with_max_message_size(defaults.max_message_size)
+        .with_upstream_tls(defaults.upstream_tls)
+        .with_backend_tcp_keepalive(defaults.backend_tcp_keepalive)
+        .with_endpoint_weights(Endpoint::weights(&initial_endpoints))
+        .with_endpoint_health_checks(endpoint_health_checks)
+        .with_balance_strategy(defaults.balance_strategy)
+        .with_client_identification(defaults.client_identification)
+        .with_health_thresholds(defaults.health_failure_threshold, defaults.health_success_threshold)
+        .with_passive_failure_threshold(defaults.passive_failure_threshold)
+        .with_health_check_jitter(defaults.health_check_jitter_percent)
+        .with_outlier_detection(defaults.outlier_detection)
+        .with_circuit_breaker(defaults.circuit_breaker)
+        .with_health_notifications(defaults.health_notifications)
+        .with_read_preference_routes(defaults.read_preference_routes)
+        .with_affinity_rules(defaults.affinity_rules)
+        .with_rebalance(defaults.rebalance)
+        .with_on_backend_unhealthy(defaults.on_backend_unhealthy)
+        .with_compressors(defaults.compressors)
+        .with_spare_connections(defaults.spare_connections)
+        .with_retry_attempts(defaults.retry_attempts)
+        .with_backend_connection_limit(defaults.max_connections_per_backend, defaults.backend_queue_timeout_ms)
+        .with_large_payloads(defaults.large_payloads)
+        .with_mirror(defaults.mirror)
+        .with_capture(defaults.capture)
+        // Splicing needs plaintext sockets to clients as well
+        .with_zero_copy(defaults.zero_copy && puerta.config.tls.is_none())
+        .with_audit(defaults.audit)
+        .with_slow_operation_threshold_ms(defaults.slow_operation_threshold_ms)
+        .with_cursor_affinity(defaults.cursor_affinity)
+        .with_transaction_pinning(defaults.transaction_pinning)
+        .with_announce_addr(defaults.announce_addr);
+
+        // Create Pingora load balancer with weighted mongos endpoints; the
+        // discovery backend set is replaced when the configuration is reloaded
+        let weighted_endpoints: Vec<Endpoint> = mongos_endpoints
+            .iter()
+            .map(|addr| Endpoint::new(addr.clone(), mongodb_config.weight_of(addr)))
+            .collect();
+        let discovery = ReloadableDiscovery::new(&weighted_endpoints)?;
+        let upstreams: LoadBalancer<RoundRobin> =
+            LoadBalancer::from_backends(Backends::new(Box::new(discovery.clone())));
+        futures::executor::block_on(upstreams.update())?;
+
+        // Backend health comes from MongoDBProxy's health checks, which
+        // enable and disable backends through the discovery
+        let load_balancer = Arc::new(upstreams);
+
+        // Create MongoDB TCP proxy service
+        let mongodb_proxy = futures::executor::block_on(MongoDBTcpProxy::new(load_balancer, mongodb_config))
+            .map_err(|e| format!("Failed to create MongoDB proxy: {e}"))?
+            .with_connection_limiter(context.connection_limiter.clone())
+            .with_proxy_protocol(puerta.config.proxy_protocol)
+            .with_access_control(Arc::clone(context.access_control))
+            .with_buffer_config(puerta.config.buffer_config)
+            .with_idle_timeout(puerta.config.idle_timeout())
+            .with_drains(Arc::clone(&puerta.drains))
+            .with_chaos(puerta.chaos("mongodb"))
+            .with_uring(puerta.uring());
+
+        // The admin API reports the first MongoDB instance's sessions and
+        // the first one tracking large replies, and changes its mongos set
+        let affinity_manager = mongodb_proxy.affinity_manager();
+        context.register_admin_routes_once("mongodb_sessions", |router| affinity_manager.register_admin_routes(router));
+        let backend_admin = mongodb_proxy.backend_admin(discovery.clone());
+        context.register_admin_routes_once("mongodb_backends", |router| backend_admin.register_admin_routes(router));
+        if let Some(large_payloads) = mongodb_proxy.large_payloads() {
+            context.register_admin_routes_once("mongodb_large_payloads", |router| {
+                large_payloads.register_admin_routes(router)
+            });
+        }
+        context.status_reporter.add_source(
+            mongodb_proxy.status_source(instance.name.clone(), instance.listen_addr.clone()),
+        );
+        context.add_explain_source(mongodb_proxy.explain_source(instance.name.clone()));
+        mongodb_proxy.spawn_warm_up();
+        mongodb_proxy.spawn_rebalancer();
+        mongodb_proxy.spawn_health_sync(discovery.clone());
+        if let Some(receiver) = discovered {
+            mongodb_proxy.spawn_discovery_watcher(receiver, discovery.clone());
+        }
+        if let (true, Some(reloader)) = (context.is_primary, &puerta.config_reloader) {
+            mongodb_proxy.spawn_reload_watcher(reloader.subscribe(), discovery);
+        }
+
+        // Create TCP listening service for MongoDB Wire Protocol
+        let tcp_service = Service::with_listeners(
+            instance.name.clone(),
+            puerta.config.listeners_for(&instance.listen_addr)?,
+            crate::core::cpu::Pinned::new(
+                mongodb_proxy,
+                crate::core::cpu::CpuPinning::new(instance.name.clone(), instance.cpu_affinity.clone()),
+            ),
+        );
+
+        // Add services to server
+        context.server.add_service(tcp_service);
+
+        log::info!(
+            "MongoDB TCP proxy {} listening on: {}",
+            instance.name,
+            instance.listen_addr
+        );
+        log::info!("Proxying to mongos endpoints: {mongos_endpoints:?}");
+        Ok(())
+    }
+
+    fn clone_box(&self) -> Box<dyn ProxyModeHandler> {
+        Box::new(self.clone())
+    }
+}
@@ -0,0 +1,166 @@
+/// Hello reply rewriting to advertise the proxy
+///
+/// Drivers discover the deployment from the hello (or legacy `isMaster`)
+/// replies of the servers they reach: replica set members list themselves
+/// in `me` and their peers in `hosts`, `passives` and `arbiters`, and
+/// drivers then connect to those addresses directly, bypassing the proxy.
+/// With an announced address, hello replies name the proxy as the only
+/// member instead, so drivers keep all traffic on the proxy.
+use super::bson::{Document, DocumentBuilder};
+use super::wire::{self, MsgHeader, OpCode, HEADER_LEN};
+use bytes::Bytes;
+
+/// OP_REPLY fields between the header and the first document: response
+/// flags, cursor id, starting from and number returned
+const REPLY_PREFIX_LEN: usize = 20;
+
+/// Whether `reply` answers a hello handshake
+fn is_hello_reply(reply: &Document) -> bool {
+    reply.get("maxWireVersion").is_some()
+        && (reply.get("isWritablePrimary").is_some() || reply.get("ismaster").is_some())
+}
+
+/// The hello reply naming `announce` as the only member, or `None` if it
+/// names no member
+fn announce_in(reply: &Document, announce: &str) -> Option<Vec<u8>> {
+    const MEMBER_FIELDS: &[&str] = &["me", "primary", "hosts", "passives", "arbiters"];
+    if !reply.iter().any(|(key, _)| MEMBER_FIELDS.contains(&key)) {
+        return None;
+    }
+    let rewritten = reply.raw_iter().fold(DocumentBuilder::new(), |builder, (key, tag, value)| match key {
+        "me" | "primary" => builder.string(key, announce),
+        "hosts" => builder.string_array(key, &[announce]),
+        "passives" | "arbiters" => builder,
+        _ => builder.element(tag, key, value),
+    });
+    Some(rewritten.build())
+}
+
+/// A mongos or mongod reply with the members a hello reply names replaced
+/// by `announce`
+///
+/// Replies come as OP_MSG or, to a legacy OP_QUERY handshake, as OP_REPLY.
+/// Returns `None` if `message` is not a hello reply naming members.
+pub fn rewrite_hello_reply(message: &[u8], announce: &str) -> Option<Bytes> {
+    let header = MsgHeader::parse(message)?;
+    let end = (header.message_length as usize).min(message.len());
+    let message = &message[..end];
+    match header.op_code {
+        OpCode::Msg => {
+            let reply = wire::op_msg_body(message)?;
+            if !is_hello_reply(&reply) {
+                return None;
+            }
+            wire::with_op_msg_body(message, &announce_in(&reply, announce)?)
+        }
+        OpCode::Reply => {
+            let documents_start = HEADER_LEN + REPLY_PREFIX_LEN;
+            let reply = Document::from_bytes(message.get(documents_start..)?)?;
+            if !is_hello_reply(&reply) {
+                return None;
+            }
+            let body = announce_in(&reply, announce)?;
+            let reply_end = documents_start + reply.as_bytes().len();
+
+            let mut rewritten = ((end - reply.as_bytes().len() + body.len()) as i32).to_le_bytes().to_vec();
+            rewritten.extend_from_slice(&message[4..documents_start]);
+            rewritten.extend_from_slice(&body);
+            rewritten.extend_from_slice(&message[reply_end..]);
+            Some(Bytes::from(rewritten))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modes::mongodb::bson::Value;
+    use crate::modes::mongodb::test_support::{message, op_msg};
+
+    fn replica_set_hello(primary_key: &str) -> Vec<u8> {
+        DocumentBuilder::new()
+            .boolean(primary_key, true)
+            .string_array("hosts", &["10.0.1.1:27017", "10.0.1.2:27017"])
+            .string_array("passives", &["10.0.1.3:27017"])
+            .string("setName", "rs0")
+            .string("primary", "10.0.1.1:27017")
+            .string("me", "10.0.1.1:27017")
+            .int32("maxWireVersion", 21)
+            .double("ok", 1.0)
+            .build()
+    }
+
+    fn strings(document: &Document, key: &str) -> Vec<String> {
+        document
+            .get_array(key)
+            .map(|array| {
+                array
+                    .iter()
+                    .filter_map(|(_, value)| match value {
+                        Value::String(value) => Some(value.to_string()),
+                        _ => None,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    fn assert_announced(reply: &Document) {
+        assert_eq!(reply.get_str("me"), Some("proxy.example.com:27017"));
+        assert_eq!(reply.get_str("primary"), Some("proxy.example.com:27017"));
+        assert_eq!(strings(reply, "hosts"), vec!["proxy.example.com:27017"]);
+        assert_eq!(reply.get("passives"), None);
+        // Everything else is kept
+        assert_eq!(reply.get_str("setName"), Some("rs0"));
+        assert_eq!(reply.get("maxWireVersion"), Some(Value::Int32(21)));
+    }
+
+    #[test]
+    fn test_rewrite_op_msg_hello_reply() {
+        let reply = op_msg(9, 7, wire::CHECKSUM_PRESENT, &replica_set_hello("isWritablePrimary"));
+
+        let rewritten = rewrite_hello_reply(&reply, "proxy.example.com:27017").unwrap();
+        let header = MsgHeader::parse(&rewritten).unwrap();
+        assert_eq!(header.message_length as usize, rewritten.len());
+        assert_eq!((header.request_id, header.response_to), (9, 7));
+        // The stale checksum is dropped
+        assert_eq!(rewritten[HEADER_LEN] as u32 & wire::CHECKSUM_PRESENT, 0);
+        assert_announced(&wire::op_msg_body(&rewritten).unwrap());
+    }
+
+    #[test]
+    fn test_rewrite_legacy_hello_reply() {
+        let mut body = 8i32.to_le_bytes().to_vec();
+        body.extend_from_slice(&0i64.to_le_bytes());
+        body.extend_from_slice(&0i32.to_le_bytes());
+        body.extend_from_slice(&1i32.to_le_bytes());
+        body.extend_from_slice(&replica_set_hello("ismaster"));
+        let reply = message(9, 7, 1, &body);
+
+        let rewritten = rewrite_hello_reply(&reply, "proxy.example.com:27017").unwrap();
+        let header = MsgHeader::parse(&rewritten).unwrap();
+        assert_eq!(header.message_length as usize, rewritten.len());
+        assert_eq!(header.op_code, OpCode::Reply);
+        assert_eq!(rewritten[HEADER_LEN..HEADER_LEN + REPLY_PREFIX_LEN], body[..REPLY_PREFIX_LEN]);
+        assert_announced(&Document::from_bytes(&rewritten[HEADER_LEN + REPLY_PREFIX_LEN..]).unwrap());
+    }
+
+    #[test]
+    fn test_other_replies_untouched() {
+        let reply = |document: Vec<u8>| op_msg(9, 7, 0, &document);
+        // mongos names no members
+        let mongos = DocumentBuilder::new()
+            .boolean("isWritablePrimary", true)
+            .string("msg", "isdbgrid")
+            .int32("maxWireVersion", 21)
+            .build();
+        assert_eq!(rewrite_hello_reply(&reply(mongos), "proxy.example.com:27017"), None);
+        // Nor is a reply that merely has a `hosts` field a hello reply
+        let find = DocumentBuilder::new()
+            .string_array("hosts", &["10.0.1.1:27017"])
+            .double("ok", 1.0)
+            .build();
+        assert_eq!(rewrite_hello_reply(&reply(find), "proxy.example.com:27017"), None);
+    }
+}
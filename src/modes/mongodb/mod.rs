@@ -1,4 +1,6 @@
 pub mod affinity;
+pub mod auth;
+pub mod backends;
 /// MongoDB Sharded Cluster mode implementation
 ///
 /// This mode provides session-aware load balancing across multiple mongos instances.
@@ -6,16 +8,51 @@ pub mod affinity;
 /// - Session affinity: ensures same client always connects to same mongos
 /// - TCP-level load balancing (no deep MongoDB protocol parsing needed)
 /// - Health checking of mongos instances
-/// - Weighted round-robin load balancing for new sessions
+/// - Weighted round-robin, least-connections or consistent-hash load balancing for new sessions
 pub mod balancer;
-
-use crate::core::Backend;
+pub mod bson;
+pub mod compression;
+pub mod cursors;
+pub mod handler;
+pub mod handshake;
+pub mod rebalance;
+pub mod slow;
+#[cfg(test)]
+pub(crate) mod test_support;
+pub mod transactions;
+pub mod wire;
+
+use crate::config::{
+    AffinityRule, BalanceStrategy, CaptureConfig, CircuitBreakerConfig, Compressor, EndpointHealthCheck,
+    HealthNotificationsConfig, LargePayloadConfig, MirrorConfig, OutlierDetectionConfig,
+    ReadPreferenceMode, ReadPreferenceRoute, RebalanceConfig, TcpKeepaliveConfig,
+    UnhealthyBackendAction, UpstreamTlsConfig,
+};
+use crate::admin::explain::ClientRoute;
+use crate::admin::sessions::{SessionInfo, SessionsReport};
+use crate::admin::status::BackendStatus;
+use crate::admin::{AdminResponse, AdminRouter};
+use crate::acl::Cidr;
+use crate::core::circuit_breaker::CircuitBreaker;
+use crate::core::latency::LatencyTracker;
+use crate::core::upstream::SpareConnections;
+use crate::core::{Backend, BackendMetadata};
+use crate::health::events::HealthEvents;
+use affinity::{AffinityStatistics, ClientIdentificationStrategy, ClientIdentifier, ClientMetadata};
+use balancer::{ConsistentHash, LatencyAware, LeastConnections, LoadBalancingAlgorithm, WeightedRoundRobin};
 use crate::modes::{BackendPool, RoutingDecision};
-use std::collections::HashMap;
-use std::net::SocketAddr;
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{watch, RwLock};
+
+/// Session timeout of an affinity manager built without configuration
+const DEFAULT_SESSION_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Longest time between session gauge updates
+const SESSION_METRICS_INTERVAL: Duration = Duration::from_secs(15);
 
 /// MongoDB mode configuration
 #[derive(Debug, Clone)]
@@ -24,6 +61,116 @@ pub struct MongoDBConfig {
     pub session_affinity_enabled: bool,
     pub session_timeout_sec: u64,
     pub health_check_interval_sec: u64,
+    /// Maximum wire protocol message size accepted in either direction
+    pub max_message_size: usize,
+    /// TLS settings for connections to mongos
+    pub upstream_tls: Option<UpstreamTlsConfig>,
+    /// How clients are told apart for session affinity
+    pub client_identification: ClientIdentificationStrategy,
+    /// Keepalive probes on connections to mongos
+    pub backend_tcp_keepalive: Option<TcpKeepaliveConfig>,
+    /// Load balancing weight per endpoint address; unlisted endpoints weigh 1
+    pub endpoint_weights: HashMap<String, usize>,
+    /// Health check settings per endpoint address; unlisted endpoints use the
+    /// defaults
+    pub endpoint_health_checks: HashMap<String, EndpointHealthCheck>,
+    /// How new sessions are spread across mongos instances
+    pub balance_strategy: BalanceStrategy,
+    /// Consecutive failed health checks before a mongos is marked unhealthy
+    pub health_failure_threshold: u32,
+    /// Consecutive passed health checks before a mongos is marked healthy again
+    pub health_success_threshold: u32,
+    /// Consecutive live-traffic failures before a mongos is ejected
+    pub passive_failure_threshold: u32,
+    /// Share of the interval, in percent, each round of health checks is
+    /// spread over
+    pub health_check_jitter_percent: u32,
+    /// Eject mongos instances whose error rate or latency stands out
+    pub outlier_detection: Option<OutlierDetectionConfig>,
+    /// Refuse sessions to mongos instances whose recent connections mostly failed
+    pub circuit_breaker: Option<CircuitBreakerConfig>,
+    /// Push mongos health state changes to a webhook
+    pub health_notifications: Option<HealthNotificationsConfig>,
+    /// Mongos subsets for clients using particular read preferences
+    pub read_preference_routes: Vec<ReadPreferenceRoute>,
+    /// Client networks pinned to dedicated mongos, ahead of session affinity
+    pub affinity_rules: Vec<AffinityRule>,
+    /// Move idle sessions off mongos holding more than their share
+    pub rebalance: Option<RebalanceConfig>,
+    /// Close or keep client connections to a mongos that turns unhealthy
+    pub on_backend_unhealthy: UnhealthyBackendAction,
+    /// Compressors clients may negotiate, in order of preference; `None`
+    /// leaves the hello handshake untouched
+    pub compressors: Option<Vec<Compressor>>,
+    /// Connections kept open to each healthy mongos ahead of new clients
+    pub spare_connections: usize,
+    /// Other mongos instances tried when connecting to the selected one fails
+    pub retry_attempts: u32,
+    /// Client connections each mongos takes at once, unlimited when unset
+    pub max_connections_per_backend: Option<usize>,
+    /// How long a connection waits for a mongos below its limit
+    pub backend_queue_timeout_ms: u64,
+    /// Log, count and report replies above a size threshold, per operation
+    pub large_payloads: Option<LargePayloadConfig>,
+    /// Copy a share of client connections to a shadow mongos
+    pub mirror: Option<MirrorConfig>,
+    /// Write client messages to capture files
+    pub capture: Option<CaptureConfig>,
+    /// Relay connections needing no message inspection with splice(2)
+    pub zero_copy: bool,
+    /// Write every command, without its body, to the access log
+    pub audit: bool,
+    /// Log and count operations answered after this many milliseconds
+    pub slow_operation_threshold_ms: Option<u64>,
+    /// Route new connections of clients with open cursors to their mongos
+    pub cursor_affinity: bool,
+    /// Route new connections of clients with an open transaction to its mongos
+    pub transaction_pinning: bool,
+    /// Address hello replies name as the only member
+    pub announce_addr: Option<String>,
+}
+
+impl Default for MongoDBConfig {
+    fn default() -> Self {
+        Self {
+            mongos_endpoints: Vec::new(),
+            session_affinity_enabled: true,
+            session_timeout_sec: 300,
+            health_check_interval_sec: 10,
+            max_message_size: wire::DEFAULT_MAX_MESSAGE_SIZE,
+            upstream_tls: None,
+            client_identification: ClientIdentificationStrategy::default(),
+            backend_tcp_keepalive: None,
+            endpoint_weights: HashMap::new(),
+            endpoint_health_checks: HashMap::new(),
+            balance_strategy: BalanceStrategy::default(),
+            health_failure_threshold: 1,
+            health_success_threshold: 1,
+            passive_failure_threshold: crate::health::DEFAULT_PASSIVE_FAILURE_THRESHOLD,
+            health_check_jitter_percent: 0,
+            outlier_detection: None,
+            circuit_breaker: None,
+            health_notifications: None,
+            read_preference_routes: Vec::new(),
+            affinity_rules: Vec::new(),
+            rebalance: None,
+            on_backend_unhealthy: UnhealthyBackendAction::default(),
+            compressors: None,
+            spare_connections: 0,
+            retry_attempts: 2,
+            max_connections_per_backend: None,
+            backend_queue_timeout_ms: 0,
+            large_payloads: None,
+            mirror: None,
+            capture: None,
+            zero_copy: false,
+            audit: false,
+            slow_operation_threshold_ms: None,
+            cursor_affinity: false,
+            transaction_pinning: false,
+            announce_addr: None,
+        }
+    }
 }
 
 impl MongoDBConfig {
@@ -68,9 +215,246 @@ impl MongoDBConfig {
             session_affinity_enabled,
             session_timeout_sec,
             health_check_interval_sec,
+            max_message_size: wire::DEFAULT_MAX_MESSAGE_SIZE,
+            upstream_tls: None,
+            client_identification: ClientIdentificationStrategy::default(),
+            backend_tcp_keepalive: None,
+            endpoint_weights: HashMap::new(),
+            endpoint_health_checks: HashMap::new(),
+            balance_strategy: BalanceStrategy::default(),
+            health_failure_threshold: 1,
+            health_success_threshold: 1,
+            passive_failure_threshold: crate::health::DEFAULT_PASSIVE_FAILURE_THRESHOLD,
+            health_check_jitter_percent: 0,
+            outlier_detection: None,
+            circuit_breaker: None,
+            health_notifications: None,
+            read_preference_routes: Vec::new(),
+            affinity_rules: Vec::new(),
+            rebalance: None,
+            on_backend_unhealthy: UnhealthyBackendAction::default(),
+            compressors: None,
+            spare_connections: 0,
+            retry_attempts: 2,
+            max_connections_per_backend: None,
+            backend_queue_timeout_ms: 0,
+            large_payloads: None,
+            mirror: None,
+            capture: None,
+            zero_copy: false,
+            audit: false,
+            slow_operation_threshold_ms: None,
+            cursor_affinity: false,
+            transaction_pinning: false,
+            announce_addr: None,
         })
     }
 
+    /// Set the maximum wire protocol message size
+    pub fn with_max_message_size(mut self, max_message_size: usize) -> Self {
+        self.max_message_size = max_message_size;
+        self
+    }
+
+    /// Connect to mongos over TLS
+    pub fn with_upstream_tls(mut self, tls: Option<UpstreamTlsConfig>) -> Self {
+        self.upstream_tls = tls;
+        self
+    }
+
+    /// Identify clients for session affinity by `strategy`
+    pub fn with_client_identification(mut self, strategy: ClientIdentificationStrategy) -> Self {
+        self.client_identification = strategy;
+        self
+    }
+
+    /// Probe idle connections to mongos
+    pub fn with_backend_tcp_keepalive(mut self, keepalive: Option<TcpKeepaliveConfig>) -> Self {
+        self.backend_tcp_keepalive = keepalive;
+        self
+    }
+
+    /// Set per-endpoint load balancing weights
+    pub fn with_endpoint_weights(mut self, weights: HashMap<String, usize>) -> Self {
+        self.endpoint_weights = weights;
+        self
+    }
+
+    /// Set per-endpoint health check intervals, timeouts and checker types
+    pub fn with_endpoint_health_checks(
+        mut self,
+        health_checks: HashMap<String, EndpointHealthCheck>,
+    ) -> Self {
+        self.endpoint_health_checks = health_checks;
+        self
+    }
+
+    /// Set the selection strategy for new sessions
+    pub fn with_balance_strategy(mut self, strategy: BalanceStrategy) -> Self {
+        self.balance_strategy = strategy;
+        self
+    }
+
+    /// Mark a mongos unhealthy after `failure_threshold` consecutive failed
+    /// health checks and healthy after `success_threshold` passed ones
+    pub fn with_health_thresholds(mut self, failure_threshold: u32, success_threshold: u32) -> Self {
+        self.health_failure_threshold = failure_threshold;
+        self.health_success_threshold = success_threshold;
+        self
+    }
+
+    /// Spread each round of health checks over `percent` of the interval,
+    /// staggered with random jitter
+    pub fn with_health_check_jitter(mut self, percent: u32) -> Self {
+        self.health_check_jitter_percent = percent;
+        self
+    }
+
+    /// Eject a mongos after this many consecutive live-traffic failures
+    pub fn with_passive_failure_threshold(mut self, threshold: u32) -> Self {
+        self.passive_failure_threshold = threshold;
+        self
+    }
+
+    /// Enable outlier detection on live traffic
+    pub fn with_outlier_detection(mut self, config: Option<OutlierDetectionConfig>) -> Self {
+        self.outlier_detection = config;
+        self
+    }
+
+    /// Open a circuit per mongos when its connections fail too often
+    pub fn with_circuit_breaker(mut self, config: Option<CircuitBreakerConfig>) -> Self {
+        self.circuit_breaker = config;
+        self
+    }
+
+    /// Send clients using the routed read preferences to those mongos subsets
+    pub fn with_read_preference_routes(mut self, routes: Vec<ReadPreferenceRoute>) -> Self {
+        self.read_preference_routes = routes;
+        self
+    }
+
+    /// Pin clients from the rules' networks to their mongos
+    pub fn with_affinity_rules(mut self, rules: Vec<AffinityRule>) -> Self {
+        self.affinity_rules = rules;
+        self
+    }
+
+    /// Move idle sessions off mongos holding more than their share
+    pub fn with_rebalance(mut self, rebalance: Option<RebalanceConfig>) -> Self {
+        self.rebalance = rebalance;
+        self
+    }
+
+    /// Close or keep the client connections of a mongos that turns unhealthy
+    pub fn with_on_backend_unhealthy(mut self, action: UnhealthyBackendAction) -> Self {
+        self.on_backend_unhealthy = action;
+        self
+    }
+
+    /// Limit the compressors clients offer mongos; an empty list strips
+    /// compression from the handshake
+    pub fn with_compressors(mut self, compressors: Option<Vec<Compressor>>) -> Self {
+        self.compressors = compressors;
+        self
+    }
+
+    /// Keep this many connections open to each healthy mongos so new
+    /// clients skip connection setup
+    pub fn with_spare_connections(mut self, spare_connections: usize) -> Self {
+        self.spare_connections = spare_connections;
+        self
+    }
+
+    /// Relay connections with splice(2) when no message needs inspecting
+    pub fn with_zero_copy(mut self, zero_copy: bool) -> Self {
+        self.zero_copy = zero_copy;
+        self
+    }
+
+    /// Record the name, database, collection and time of every command
+    pub fn with_audit(mut self, audit: bool) -> Self {
+        self.audit = audit;
+        self
+    }
+
+    /// Log and count operations mongos takes at least this long to answer
+    pub fn with_slow_operation_threshold_ms(mut self, slow_operation_threshold_ms: Option<u64>) -> Self {
+        self.slow_operation_threshold_ms = slow_operation_threshold_ms;
+        self
+    }
+
+    /// Send new connections of clients with open cursors to the mongos
+    /// holding them, even without a session affinity entry
+    pub fn with_cursor_affinity(mut self, cursor_affinity: bool) -> Self {
+        self.cursor_affinity = cursor_affinity;
+        self
+    }
+
+    /// Send new connections of clients with an open multi-document
+    /// transaction to the mongos running it, ahead of any other routing
+    pub fn with_transaction_pinning(mut self, transaction_pinning: bool) -> Self {
+        self.transaction_pinning = transaction_pinning;
+        self
+    }
+
+    /// Name `announce_addr` as the only member in hello replies, so drivers
+    /// never learn the servers' own addresses
+    pub fn with_announce_addr(mut self, announce_addr: Option<String>) -> Self {
+        self.announce_addr = announce_addr;
+        self
+    }
+
+    /// Try up to this many other mongos instances when connecting fails
+    pub fn with_retry_attempts(mut self, retry_attempts: u32) -> Self {
+        self.retry_attempts = retry_attempts;
+        self
+    }
+
+    /// Cap the client connections of each mongos, letting a connection
+    /// wait up to `queue_timeout_ms` when every mongos is at the cap
+    pub fn with_backend_connection_limit(
+        mut self,
+        max_connections_per_backend: Option<usize>,
+        queue_timeout_ms: u64,
+    ) -> Self {
+        self.max_connections_per_backend = max_connections_per_backend;
+        self.backend_queue_timeout_ms = queue_timeout_ms;
+        self
+    }
+
+    /// POST mongos health state changes to the configured webhook
+    pub fn with_health_notifications(
+        mut self,
+        health_notifications: Option<HealthNotificationsConfig>,
+    ) -> Self {
+        self.health_notifications = health_notifications;
+        self
+    }
+
+    /// Track the operations of replies above the large payload threshold
+    pub fn with_large_payloads(mut self, large_payloads: Option<LargePayloadConfig>) -> Self {
+        self.large_payloads = large_payloads;
+        self
+    }
+
+    /// Copy a share of client connections to a shadow mongos
+    pub fn with_mirror(mut self, mirror: Option<MirrorConfig>) -> Self {
+        self.mirror = mirror;
+        self
+    }
+
+    /// Write client messages to capture files
+    pub fn with_capture(mut self, capture: Option<CaptureConfig>) -> Self {
+        self.capture = capture;
+        self
+    }
+
+    /// Get the load balancing weight of an endpoint
+    pub fn weight_of(&self, endpoint: &str) -> usize {
+        self.endpoint_weights.get(endpoint).copied().unwrap_or(1)
+    }
+
     /// Get the number of mongos endpoints
     pub fn endpoint_count(&self) -> usize {
         self.mongos_endpoints.len()
@@ -87,10 +471,56 @@ impl MongoDBConfig {
 /// Session affinity manager
 /// Tracks which client should connect to which mongos instance
 pub struct SessionAffinityManager {
-    /// Maps client IP -> mongos backend ID
-    client_to_backend: Arc<RwLock<HashMap<SocketAddr, String>>>,
+    /// Maps client identifier -> mongos backend ID
+    client_to_backend: Arc<RwLock<HashMap<ClientIdentifier, AffinityEntry>>>,
     /// Round-robin counter for new sessions
     round_robin_counter: AtomicUsize,
+    /// Sessions without activity for this long are expired
+    session_timeout: Duration,
+}
+
+/// The mongos a client is pinned to
+#[derive(Debug, Clone)]
+struct AffinityEntry {
+    backend_id: String,
+    created: Instant,
+    last_activity: Instant,
+    /// Connections routed through the session
+    connections: u64,
+    /// Application and driver of the client's latest handshake
+    metadata: Option<ClientMetadata>,
+}
+
+impl AffinityEntry {
+    fn new(backend_id: String) -> Self {
+        let now = Instant::now();
+        Self {
+            backend_id,
+            created: now,
+            last_activity: now,
+            connections: 1,
+            metadata: None,
+        }
+    }
+
+    /// Record a new connection routed through the session
+    fn reuse(&mut self) -> String {
+        self.last_activity = Instant::now();
+        self.connections += 1;
+        self.backend_id.clone()
+    }
+
+    /// The session of `client` as reported by the admin API
+    fn info(&self, client: &ClientIdentifier) -> SessionInfo {
+        SessionInfo {
+            client: client.to_string(),
+            backend_id: self.backend_id.clone(),
+            age_secs: self.created.elapsed().as_secs(),
+            idle_secs: self.last_activity.elapsed().as_secs(),
+            connections: self.connections,
+            metadata: self.metadata.clone(),
+        }
+    }
 }
 
 impl Clone for SessionAffinityManager {
@@ -98,6 +528,7 @@ impl Clone for SessionAffinityManager {
         Self {
             client_to_backend: Arc::clone(&self.client_to_backend),
             round_robin_counter: AtomicUsize::new(self.round_robin_counter.load(Ordering::Relaxed)),
+            session_timeout: self.session_timeout,
         }
     }
 }
@@ -109,6 +540,25 @@ pub struct MongoDBProxy {
     backends: BackendPool,
     pub affinity_manager: SessionAffinityManager,
     health_manager: Option<Arc<crate::health::HealthCheckManager>>,
+    /// Health check interval, adjustable at runtime on configuration reload
+    health_check_interval_sec: Arc<AtomicU64>,
+    /// Selection of backends for new sessions
+    balancer: Arc<dyn LoadBalancingAlgorithm>,
+    /// Connect and first-byte latency estimates of mongos
+    latencies: Arc<LatencyTracker>,
+    /// Backends a discovery catalog reports as failing, kept unhealthy
+    /// regardless of active health checks
+    catalog_unhealthy: Arc<RwLock<HashSet<SocketAddr>>>,
+    /// Latest read preference seen from each client IP and when
+    read_preferences: Arc<Mutex<HashMap<IpAddr, (ReadPreferenceMode, Instant)>>>,
+    /// Static affinity rules, client networks parsed, in order
+    affinity_rules: Arc<Vec<(Vec<Cidr>, Vec<String>)>>,
+    /// Fail fast on mongos instances whose recent connections mostly failed
+    circuit_breaker: Option<Arc<CircuitBreaker>>,
+    /// Mongos currently found unhealthy, for the Pingora load balancer
+    health_updates: Arc<watch::Sender<HashSet<SocketAddr>>>,
+    /// Events, metrics and notifications for mongos health state changes
+    health_events: Arc<HealthEvents>,
 }
 
 impl SessionAffinityManager {
@@ -116,9 +566,16 @@ impl SessionAffinityManager {
         Self {
             client_to_backend: Arc::new(RwLock::new(HashMap::new())),
             round_robin_counter: AtomicUsize::new(0),
+            session_timeout: DEFAULT_SESSION_TIMEOUT,
         }
     }
 
+    /// Expire sessions without activity for `session_timeout`
+    pub fn with_session_timeout(mut self, session_timeout: Duration) -> Self {
+        self.session_timeout = session_timeout;
+        self
+    }
+
     /// Get the current number of active sessions
     pub async fn session_count(&self) -> usize {
         self.client_to_backend.read().await.len()
@@ -127,18 +584,13 @@ impl SessionAffinityManager {
     /// Get or assign backend for a client
     pub async fn get_backend_for_client(
         &self,
-        client_addr: SocketAddr,
+        client: impl Into<ClientIdentifier>,
         available_backends: &[String],
     ) -> Option<String> {
-        // First check if client has existing affinity
-        {
-            let affinity_map = self.client_to_backend.read().await;
-            if let Some(backend_id) = affinity_map.get(&client_addr) {
-                // Verify the backend is still available
-                if available_backends.contains(backend_id) {
-                    return Some(backend_id.clone());
-                }
-            }
+        let client = client.into();
+        // First check if client has existing affinity that is still available
+        if let Some(backend_id) = self.existing_backend(client.clone(), available_backends).await {
+            return Some(backend_id);
         }
 
         // No existing affinity or backend unavailable, assign new one
@@ -151,18 +603,15 @@ impl SessionAffinityManager {
         let backend_id = available_backends[index].clone();
 
         // Store the new affinity
-        {
-            let mut affinity_map = self.client_to_backend.write().await;
-            affinity_map.insert(client_addr, backend_id.clone());
-        }
+        self.assign_backend(client, backend_id.clone()).await;
 
         Some(backend_id)
     }
 
     /// Remove client affinity (when client disconnects)
-    pub async fn remove_client_affinity(&self, client_addr: SocketAddr) -> bool {
+    pub async fn remove_client_affinity(&self, client: impl Into<ClientIdentifier>) -> bool {
         let mut affinity_map = self.client_to_backend.write().await;
-        affinity_map.remove(&client_addr).is_some()
+        affinity_map.remove(&client.into()).is_some()
     }
 
     /// Clear all sessions (for testing or reset)
@@ -171,31 +620,332 @@ impl SessionAffinityManager {
         affinity_map.clear();
     }
 
-    /// Get all active client addresses
-    pub async fn get_active_clients(&self) -> Vec<SocketAddr> {
+    /// Get the existing backend of a client if it is still available,
+    /// counting the lookup as a new connection
+    pub async fn existing_backend(
+        &self,
+        client: impl Into<ClientIdentifier>,
+        available_backends: &[String],
+    ) -> Option<String> {
+        let mut affinity_map = self.client_to_backend.write().await;
+        let entry = affinity_map
+            .get_mut(&client.into())
+            .filter(|entry| available_backends.contains(&entry.backend_id))?;
+        Some(entry.reuse())
+    }
+
+    /// Get the backend a client is pinned to, whatever its state, counting
+    /// the lookup as a new connection
+    pub async fn backend_of(&self, client: &ClientIdentifier) -> Option<String> {
+        let mut affinity_map = self.client_to_backend.write().await;
+        affinity_map.get_mut(client).map(AffinityEntry::reuse)
+    }
+
+    /// Pin a client to a backend
+    pub async fn assign_backend(&self, client: impl Into<ClientIdentifier>, backend_id: String) {
+        let mut affinity_map = self.client_to_backend.write().await;
+        affinity_map.insert(client.into(), AffinityEntry::new(backend_id));
+    }
+
+    /// Record activity from a client, keeping its session from expiring
+    pub async fn touch(&self, client: &ClientIdentifier) {
+        if let Some(entry) = self.client_to_backend.write().await.get_mut(client) {
+            entry.last_activity = Instant::now();
+        }
+    }
+
+    /// Attach the application and driver a client's handshake names to its
+    /// session
+    pub async fn set_client_metadata(&self, client: &ClientIdentifier, metadata: ClientMetadata) {
+        if let Some(entry) = self.client_to_backend.write().await.get_mut(client) {
+            entry.metadata = Some(metadata);
+        }
+    }
+
+    /// Remove sessions idle past the session timeout, returning how many
+    pub async fn cleanup_expired_sessions(&self) -> usize {
+        let mut affinity_map = self.client_to_backend.write().await;
+        let before = affinity_map.len();
+        affinity_map.retain(|_, entry| entry.last_activity.elapsed() <= self.session_timeout);
+        before - affinity_map.len()
+    }
+
+    /// Forget every session pinned to a backend, returning how many
+    pub async fn remove_backend_sessions(&self, backend_id: &str) -> usize {
+        let mut affinity_map = self.client_to_backend.write().await;
+        let before = affinity_map.len();
+        affinity_map.retain(|_, entry| entry.backend_id != backend_id);
+        before - affinity_map.len()
+    }
+
+    /// Get all active client identifiers
+    pub async fn get_active_clients(&self) -> Vec<ClientIdentifier> {
         let affinity_map = self.client_to_backend.read().await;
         affinity_map.keys().cloned().collect()
     }
+
+    /// Session totals and their distribution across backends
+    pub async fn get_statistics(&self) -> AffinityStatistics {
+        let affinity_map = self.client_to_backend.read().await;
+        let mut statistics = AffinityStatistics {
+            total_sessions: affinity_map.len(),
+            total_connections: 0,
+            backend_distribution: Default::default(),
+        };
+        for entry in affinity_map.values() {
+            statistics.total_connections += entry.connections;
+            *statistics
+                .backend_distribution
+                .entry(entry.backend_id.clone())
+                .or_insert(0) += 1;
+        }
+        statistics
+    }
+
+    /// Every session, oldest first, only those pinned to `backend_id` if
+    /// given
+    pub async fn sessions(&self, backend_id: Option<&str>) -> Vec<SessionInfo> {
+        let affinity_map = self.client_to_backend.read().await;
+        let mut entries: Vec<_> = affinity_map
+            .iter()
+            .filter(|(_, entry)| backend_id.is_none_or(|backend_id| entry.backend_id == backend_id))
+            .collect();
+        entries.sort_by_key(|(_, entry)| entry.created);
+        entries.into_iter().map(|(client, entry)| entry.info(client)).collect()
+    }
+
+    /// Sessions of clients identified by a socket address from `client_ip`,
+    /// oldest first
+    pub async fn sessions_of_ip(&self, client_ip: IpAddr) -> Vec<SessionInfo> {
+        let affinity_map = self.client_to_backend.read().await;
+        let mut entries: Vec<_> = affinity_map
+            .iter()
+            .filter(|(client, _)| match client {
+                ClientIdentifier::SocketAddr(addr) | ClientIdentifier::Hybrid { socket_addr: addr, .. } => {
+                    addr.ip() == client_ip
+                }
+                _ => false,
+            })
+            .collect();
+        entries.sort_by_key(|(_, entry)| entry.created);
+        entries.into_iter().map(|(client, entry)| entry.info(client)).collect()
+    }
+
+    /// Set the session gauges of every backend with sessions, and zero
+    /// those in `published` left without any
+    pub async fn publish_metrics(&self, published: &mut HashSet<String>) {
+        let statistics = self.get_statistics().await;
+        let mut connections: HashMap<&str, u64> = HashMap::new();
+        let affinity_map = self.client_to_backend.read().await;
+        for entry in affinity_map.values() {
+            *connections.entry(entry.backend_id.as_str()).or_insert(0) += entry.connections;
+        }
+
+        let registry = crate::metrics::global();
+        published.extend(statistics.backend_distribution.keys().cloned());
+        for backend in published.iter() {
+            let labels = [("backend", backend.as_str())];
+            registry
+                .gauge_with_labels(
+                    "puerta_mongodb_affinity_sessions",
+                    "MongoDB clients pinned to the mongos",
+                    &labels,
+                )
+                .set(statistics.backend_distribution.get(backend).copied().unwrap_or(0) as i64);
+            registry
+                .gauge_with_labels(
+                    "puerta_mongodb_affinity_connections",
+                    "Connections routed through the sessions pinned to the mongos",
+                    &labels,
+                )
+                .set(connections.get(backend.as_str()).copied().unwrap_or(0) as i64);
+        }
+        published.retain(|backend| statistics.backend_distribution.contains_key(backend));
+    }
+
+    /// Register the `/mongodb/sessions` admin endpoint, taking an optional
+    /// `backend` id filter
+    pub fn register_admin_routes(&self, router: AdminRouter) -> AdminRouter {
+        let manager = self.clone();
+        router.route("GET", "/mongodb/sessions", move |request| {
+            let manager = manager.clone();
+            let backend = request.query.get("backend").cloned();
+            async move {
+                let report = SessionsReport {
+                    statistics: manager.get_statistics().await,
+                    sessions: manager.sessions(backend.as_deref()).await,
+                };
+                match serde_json::to_value(report) {
+                    Ok(body) => AdminResponse::ok(body),
+                    Err(e) => AdminResponse::error(500, e.to_string()),
+                }
+            }
+        })
+    }
 }
 
 impl MongoDBProxy {
     pub fn new(config: MongoDBConfig) -> Self {
+        let health_check_interval_sec = Arc::new(AtomicU64::new(config.health_check_interval_sec));
+        let latencies = Arc::new(LatencyTracker::default());
+        let balancer: Arc<dyn LoadBalancingAlgorithm> = match config.balance_strategy {
+            BalanceStrategy::RoundRobin => Arc::new(WeightedRoundRobin::new()),
+            BalanceStrategy::LeastConnections => Arc::new(LeastConnections::new()),
+            BalanceStrategy::ConsistentHash => Arc::new(ConsistentHash::new()),
+            BalanceStrategy::LatencyAware => Arc::new(LatencyAware::new(Arc::clone(&latencies))),
+        };
+        let circuit_breaker = config
+            .circuit_breaker
+            .clone()
+            .map(|config| Arc::new(CircuitBreaker::new(config)));
+        let health_events = Arc::new(HealthEvents::new(
+            "mongodb",
+            config.health_notifications.as_ref(),
+        ));
+        let affinity_manager = SessionAffinityManager::new()
+            .with_session_timeout(Duration::from_secs(config.session_timeout_sec));
+        // Client networks were checked when the configuration was validated
+        let affinity_rules = config
+            .affinity_rules
+            .iter()
+            .map(|rule| {
+                let clients = rule.clients.iter().filter_map(|c| c.parse().ok()).collect();
+                (clients, rule.endpoints.clone())
+            })
+            .collect();
         Self {
             config,
             backends: Arc::new(RwLock::new(HashMap::new())),
-            affinity_manager: SessionAffinityManager::new(),
+            affinity_manager,
             health_manager: None,
+            health_check_interval_sec,
+            balancer,
+            latencies,
+            catalog_unhealthy: Arc::new(RwLock::new(HashSet::new())),
+            read_preferences: Arc::new(Mutex::new(HashMap::new())),
+            affinity_rules: Arc::new(affinity_rules),
+            circuit_breaker,
+            health_updates: Arc::new(watch::channel(HashSet::new()).0),
+            health_events,
         }
     }
 
-    pub fn with_health_check(mut self) -> Self {
-        let health_checker = Box::new(crate::health::mongodb::MongoDBHealthChecker::new());
-        self.health_manager = Some(Arc::new(crate::health::HealthCheckManager::new(
-            health_checker,
-        )));
+    pub fn with_health_check(self) -> Self {
+        self.with_health_check_over(None)
+    }
+
+    /// Enable health checks, running them over a spare connection to the
+    /// mongos when `spares` holds one instead of connecting for each check
+    pub fn with_health_check_over(mut self, spares: Option<Arc<SpareConnections>>) -> Self {
+        let tls = self.config.upstream_tls.as_ref();
+        let health_checker = crate::health::mongodb::MongoDBHealthChecker::new().with_upstream_tls(tls);
+        let health_checker = match spares {
+            Some(spares) => health_checker.with_spare_connections(spares),
+            None => health_checker,
+        };
+        let check_options = crate::health::CheckOptions {
+            upstream_tls: self.config.upstream_tls.clone(),
+            ..Default::default()
+        };
+        self.health_manager = Some(Arc::new(
+            crate::health::HealthCheckManager::new(Box::new(health_checker))
+                .with_check_options(check_options)
+                .with_endpoint_overrides(Self::endpoint_overrides(&self.config.endpoint_health_checks))
+                .with_thresholds(
+                    self.config.health_failure_threshold,
+                    self.config.health_success_threshold,
+                )
+                .with_passive_failure_threshold(self.config.passive_failure_threshold)
+                .with_outlier_detection(self.config.outlier_detection.clone()),
+        ));
         self
     }
 
+    /// Report a connect failure or I/O error on live traffic to `addr`,
+    /// ejecting the backend once the passive failure threshold is reached
+    pub async fn report_failure(&self, addr: SocketAddr) {
+        if let Some(circuit_breaker) = &self.circuit_breaker {
+            circuit_breaker.record(addr, false);
+        }
+        let Some(health_manager) = &self.health_manager else {
+            return;
+        };
+        health_manager.record_outcome(addr, false, None);
+        if health_manager.record_passive_failure(addr) {
+            let mut backends = self.backends.write().await;
+            for backend in backends.values_mut().filter(|b| b.addr == addr) {
+                log::warn!(
+                    "Ejecting mongos {} ({addr}) after {} consecutive failures",
+                    backend.id,
+                    self.config.passive_failure_threshold
+                );
+                if backend.healthy {
+                    self.health_events.record(
+                        &backend.id,
+                        addr,
+                        false,
+                        &format!(
+                            "{} consecutive failures on live traffic",
+                            self.config.passive_failure_threshold
+                        ),
+                    );
+                }
+                backend.healthy = false;
+            }
+        }
+    }
+
+    /// Report a successful connection to `addr` that took `latency`
+    pub fn report_success(&self, addr: SocketAddr, latency: Duration) {
+        self.latencies.record(&addr.to_string(), latency);
+        if let Some(circuit_breaker) = &self.circuit_breaker {
+            circuit_breaker.record(addr, true);
+        }
+        if let Some(health_manager) = &self.health_manager {
+            health_manager.record_passive_success(addr);
+            health_manager.record_outcome(addr, true, Some(latency));
+        }
+    }
+
+    /// Report that `addr` answered the first message of a session after
+    /// `latency`
+    pub fn report_first_reply(&self, addr: SocketAddr, latency: Duration) {
+        self.latencies.record(&addr.to_string(), latency);
+    }
+
+    /// Whether `addr` is currently kept out of selection by passive health
+    /// checking or outlier detection
+    pub fn is_ejected(&self, addr: SocketAddr) -> bool {
+        self.health_manager
+            .as_ref()
+            .is_some_and(|health_manager| !health_manager.admits(addr))
+    }
+
+    /// Whether the circuit of `addr` is open, refusing new sessions
+    pub fn is_circuit_open(&self, addr: SocketAddr) -> bool {
+        self.circuit_breaker
+            .as_ref()
+            .is_some_and(|circuit_breaker| circuit_breaker.is_open(addr))
+    }
+
+    /// Take a session slot on the circuit of `addr`, which a half-open
+    /// circuit grants only to its probes
+    pub fn circuit_allows(&self, addr: SocketAddr) -> bool {
+        self.circuit_breaker
+            .as_ref()
+            .is_none_or(|circuit_breaker| circuit_breaker.allow(addr))
+    }
+
+    /// Health check a mongos not yet among the backends, as the periodic
+    /// checks would
+    pub async fn probe(&self, addr: SocketAddr) -> crate::health::HealthStatus {
+        let mut backend = Backend::new_mongodb(format!("probe-{addr}"), addr);
+        match &self.health_manager {
+            Some(health_manager) => health_manager.check_backend_health(&mut backend).await,
+            None => crate::health::HealthStatus::Unknown,
+        }
+    }
+
     /// Initialize backends from configuration
     pub async fn initialize_backends(&self) -> Result<(), Box<dyn std::error::Error>> {
         let mut backends = self.backends.write().await;
@@ -203,73 +953,518 @@ impl MongoDBProxy {
         for (index, endpoint) in self.config.mongos_endpoints.iter().enumerate() {
             let addr: SocketAddr = endpoint.parse()?;
             let backend_id = format!("mongos-{}", index);
-            let backend = Backend::new_mongodb(backend_id.clone(), addr);
+            let mut backend = Backend::new_mongodb(backend_id.clone(), addr);
+            backend.weight = self.config.weight_of(endpoint);
             backends.insert(backend_id, backend);
         }
 
         Ok(())
     }
 
+    /// Replace the backend set with a new list of mongos endpoints
+    ///
+    /// Backends whose address is still listed keep their id and health state,
+    /// so existing session affinity stays valid. Removed backends are dropped
+    /// and new endpoints get the first free `mongos-{index}` id.
+    pub async fn update_backends(&self, endpoints: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+        let mut addrs = Vec::with_capacity(endpoints.len());
+        for endpoint in endpoints {
+            addrs.push(endpoint.parse::<SocketAddr>()?);
+        }
+
+        let mut backends = self.backends.write().await;
+
+        backends.retain(|backend_id, backend| {
+            let keep = addrs.contains(&backend.addr);
+            if !keep {
+                log::info!("Removing mongos backend {backend_id} ({})", backend.addr);
+            }
+            keep
+        });
+
+        let mut next_index = 0;
+        for addr in addrs {
+            if backends.values().any(|backend| backend.addr == addr) {
+                continue;
+            }
+
+            while backends.contains_key(&format!("mongos-{}", next_index)) {
+                next_index += 1;
+            }
+            let backend_id = format!("mongos-{}", next_index);
+            log::info!("Adding mongos backend {backend_id} ({addr})");
+            backends.insert(backend_id.clone(), Backend::new_mongodb(backend_id, addr));
+        }
+
+        Ok(())
+    }
+
+    /// Apply new endpoint weights, keyed by address; unlisted backends weigh 1
+    pub async fn update_weights(&self, weights: &HashMap<String, usize>) {
+        let mut backends = self.backends.write().await;
+        for backend in backends.values_mut() {
+            backend.weight = weights.get(&backend.addr.to_string()).copied().unwrap_or(1);
+        }
+    }
+
+    /// Apply the health a discovery catalog reports for each backend
+    ///
+    /// Backends at `unhealthy` addresses are taken out of rotation at once and
+    /// stay out until the catalog reports them passing again; recovered
+    /// backends return once the next active health check succeeds.
+    pub async fn set_catalog_unhealthy(&self, unhealthy: HashSet<SocketAddr>) {
+        // Same lock order as the health check task: catalog, then backends
+        let mut catalog_unhealthy = self.catalog_unhealthy.write().await;
+        let mut backends = self.backends.write().await;
+        for backend in backends.values_mut() {
+            if unhealthy.contains(&backend.addr) && backend.healthy {
+                log::warn!(
+                    "Backend {} ({}) is failing in the discovery catalog",
+                    backend.id,
+                    backend.addr
+                );
+                self.health_events.record(
+                    &backend.id,
+                    backend.addr,
+                    false,
+                    "failing in the discovery catalog",
+                );
+                backend.healthy = false;
+            }
+        }
+        *catalog_unhealthy = unhealthy;
+        Self::publish_health(&self.health_updates, &backends, &catalog_unhealthy);
+    }
+
+    /// Subscribe to the set of mongos found unhealthy by health checks or
+    /// the discovery catalog
+    ///
+    /// Backends not checked yet are not part of the set. Passive ejection
+    /// and outlier detection are left out too; callers filter those per
+    /// selection with [`Self::is_ejected`].
+    pub fn health_updates(&self) -> watch::Receiver<HashSet<SocketAddr>> {
+        self.health_updates.subscribe()
+    }
+
+    /// Wait until the mongos at `addr` turns unhealthy, having been healthy
+    /// or unchecked when called
+    pub async fn became_unhealthy(&self, addr: SocketAddr) {
+        let mut receiver = self.health_updates.subscribe();
+        let mut was_unhealthy = receiver.borrow_and_update().contains(&addr);
+        // The sender lives as long as `self`
+        while receiver.changed().await.is_ok() {
+            let unhealthy = receiver.borrow_and_update().contains(&addr);
+            if unhealthy && !was_unhealthy {
+                return;
+            }
+            was_unhealthy = unhealthy;
+        }
+        std::future::pending().await
+    }
+
+    /// Forget the sessions pinned to the mongos at `addr`, so its clients
+    /// get another mongos on their next connection, returning how many
+    pub async fn invalidate_sessions(&self, addr: SocketAddr) -> usize {
+        let backend_id = {
+            let backends = self.backends.read().await;
+            backends
+                .values()
+                .find(|backend| backend.addr == addr)
+                .map(|backend| backend.id.clone())
+        };
+        match backend_id {
+            Some(backend_id) => self.affinity_manager.remove_backend_sessions(&backend_id).await,
+            None => 0,
+        }
+    }
+
+    /// Publish the unhealthy mongos set if it changed
+    fn publish_health(
+        sender: &watch::Sender<HashSet<SocketAddr>>,
+        backends: &HashMap<String, Backend>,
+        catalog_unhealthy: &HashSet<SocketAddr>,
+    ) {
+        let unhealthy: HashSet<SocketAddr> = backends
+            .values()
+            .filter(|backend| {
+                !backend.healthy
+                    && (backend.last_health_check.is_some()
+                        || catalog_unhealthy.contains(&backend.addr))
+            })
+            .map(|backend| backend.addr)
+            .collect();
+        sender.send_if_modified(|current| {
+            if *current == unhealthy {
+                return false;
+            }
+            *current = unhealthy;
+            true
+        });
+    }
+
+    /// Record a new client connection to the backend at `addr`
+    pub async fn connection_opened(&self, addr: SocketAddr) {
+        self.adjust_connection_count(addr, |count| count + 1).await;
+    }
+
+    /// Record a closed client connection to the backend at `addr`
+    pub async fn connection_closed(&self, addr: SocketAddr) {
+        self.adjust_connection_count(addr, |count| count.saturating_sub(1))
+            .await;
+    }
+
+    async fn adjust_connection_count(&self, addr: SocketAddr, adjust: impl Fn(usize) -> usize) {
+        let mut backends = self.backends.write().await;
+        for backend in backends.values_mut().filter(|b| b.addr == addr) {
+            if let BackendMetadata::MongoDB {
+                connection_count, ..
+            } = &mut backend.metadata
+            {
+                *connection_count = adjust(*connection_count);
+            }
+        }
+    }
+
+    /// Whether client commands need inspecting for their read preference
+    pub fn routes_read_preferences(&self) -> bool {
+        !self.config.read_preference_routes.is_empty()
+    }
+
+    /// Remember the read preference a client's commands use
+    pub fn record_read_preference(&self, client_ip: IpAddr, mode: ReadPreferenceMode) {
+        let now = Instant::now();
+        let mut read_preferences = self.read_preferences.lock().unwrap();
+        if read_preferences.insert(client_ip, (mode, now)).is_none() {
+            // Forget clients that have gone quiet whenever a new one appears
+            let timeout = Duration::from_secs(self.config.session_timeout_sec);
+            read_preferences.retain(|_, (_, seen)| now.duration_since(*seen) < timeout);
+        }
+    }
+
+    /// Route for the read preference last seen from `client_ip`, until it is
+    /// older than the session timeout
+    pub fn read_preference_route(&self, client_ip: IpAddr) -> Option<&ReadPreferenceRoute> {
+        let (mode, seen) = *self.read_preferences.lock().unwrap().get(&client_ip)?;
+        if seen.elapsed() >= Duration::from_secs(self.config.session_timeout_sec) {
+            return None;
+        }
+        self.config
+            .read_preference_routes
+            .iter()
+            .find(|route| route.modes.contains(&mode))
+    }
+
+    /// Whether a backend at `addr` may serve `client_ip` under its read
+    /// preference route
+    pub fn admits_read_preference(&self, client_ip: IpAddr, addr: SocketAddr) -> bool {
+        self.read_preference_route(client_ip)
+            .is_none_or(|route| route.endpoints.iter().any(|endpoint| endpoint == &addr.to_string()))
+    }
+
+    /// Mongos endpoints of the first static affinity rule matching `client_ip`
+    pub fn affinity_rule(&self, client_ip: IpAddr) -> Option<&[String]> {
+        self.affinity_rules
+            .iter()
+            .find(|(clients, _)| clients.iter().any(|cidr| cidr.contains(client_ip)))
+            .map(|(_, endpoints)| endpoints.as_slice())
+    }
+
+    /// Mongos a static affinity rule pins `client_ip` to: the first of the
+    /// rule's endpoints that is healthy and not one of `excluded`
+    pub async fn static_backend(&self, client_ip: IpAddr, excluded: &[SocketAddr]) -> Option<Backend> {
+        let endpoints = self.affinity_rule(client_ip)?;
+        let backend = {
+            let backends = self.backends.read().await;
+            endpoints.iter().find_map(|endpoint| {
+                backends
+                    .values()
+                    .find(|b| {
+                        b.healthy
+                            && &b.addr.to_string() == endpoint
+                            && !excluded.contains(&b.addr)
+                            && !self.is_ejected(b.addr)
+                            && !self.is_circuit_open(b.addr)
+                    })
+                    .cloned()
+            })
+        };
+        if backend.is_none() {
+            log::warn!("No healthy mongos on the affinity rule of {client_ip}, balancing as usual");
+        }
+        backend
+    }
+
+    /// How a new session from `client_ip` would pick its mongos, skipping
+    /// those in `excluded`, without selecting one
+    pub async fn explain_client(&self, client_ip: IpAddr, excluded: &[SocketAddr]) -> ClientRoute {
+        let affinity_rule = self.affinity_rule(client_ip).map(<[String]>::to_vec);
+        let backend = match affinity_rule {
+            Some(_) => self.static_backend(client_ip, excluded).await,
+            None => None,
+        };
+        let mongos: HashSet<String> = {
+            let backends = self.backends.read().await;
+            backends.values().map(|backend| backend.addr.to_string()).collect()
+        };
+        let connections = crate::core::connections::global()
+            .report(None)
+            .connections
+            .into_iter()
+            .filter(|connection| {
+                connection.mode == "mongodb"
+                    && mongos.contains(&connection.backend)
+                    && connection
+                        .client_addr
+                        .parse::<SocketAddr>()
+                        .is_ok_and(|addr| addr.ip() == client_ip)
+            })
+            .collect();
+
+        ClientRoute {
+            client_ip,
+            read_preference_route: self
+                .read_preference_route(client_ip)
+                .map(|route| route.endpoints.clone()),
+            affinity_rule,
+            balance_strategy: self.config.balance_strategy,
+            decision: if backend.is_some() { "affinity_rule" } else { "balanced" }.to_string(),
+            backend: backend.map(|backend| backend.addr.to_string()),
+            sessions: self.affinity_manager.sessions_of_ip(client_ip).await,
+            connections,
+        }
+    }
+
+    /// Pick a healthy backend for a new session using the configured strategy
+    ///
+    /// Consistent hashing is keyed on the client IP, so all connections from
+    /// one host land on the same mongos. Clients with a read preference route
+    /// are kept to its mongos while any of them is healthy.
+    pub async fn select_new_backend(&self, client_addr: SocketAddr) -> Option<Backend> {
+        self.select_new_backend_excluding(client_addr, &[]).await
+    }
+
+    /// Pick a backend for a new session as `select_new_backend` does, never
+    /// one of `excluded`, such as mongos a connect just failed on
+    pub async fn select_new_backend_excluding(
+        &self,
+        client_addr: SocketAddr,
+        excluded: &[SocketAddr],
+    ) -> Option<Backend> {
+        // Open circuits would refuse the session anyway
+        let mut healthy: Vec<Backend> = {
+            let backends = self.backends.read().await;
+            backends
+                .values()
+                .filter(|b| {
+                    b.healthy && !excluded.contains(&b.addr) && !self.is_circuit_open(b.addr)
+                })
+                .cloned()
+                .collect()
+        };
+        // Stable order so weighted round-robin cycles predictably
+        healthy.sort_by(|a, b| a.id.cmp(&b.id));
+
+        let routed: Vec<Backend> = healthy
+            .iter()
+            .filter(|b| self.admits_read_preference(client_addr.ip(), b.addr))
+            .cloned()
+            .collect();
+        if !routed.is_empty() {
+            healthy = routed;
+        } else if !healthy.is_empty() {
+            log::warn!(
+                "No healthy mongos on the read preference route of {}, using any",
+                client_addr.ip()
+            );
+        }
+
+        // Skip outliers unless that would leave nothing to select
+        let admitted: Vec<Backend> = healthy
+            .iter()
+            .filter(|b| !self.is_ejected(b.addr))
+            .cloned()
+            .collect();
+        if !admitted.is_empty() {
+            healthy = admitted;
+        }
+
+        let key = client_addr.ip().to_string();
+        let index = self.balancer.select_backend_for_key(&healthy, key.as_bytes())?;
+        Some(healthy.swap_remove(index))
+    }
+
+    /// Change the health check interval used by the background health check task
+    pub fn set_health_check_interval(&self, interval_sec: u64) {
+        if interval_sec > 0 {
+            self.health_check_interval_sec
+                .store(interval_sec, Ordering::Relaxed);
+        }
+    }
+
+    /// Change the consecutive health check results needed to change a
+    /// mongos' state
+    pub fn set_health_thresholds(&self, failure_threshold: u32, success_threshold: u32) {
+        if let Some(health_manager) = &self.health_manager {
+            health_manager.set_thresholds(failure_threshold, success_threshold);
+        }
+    }
+
+    /// Replace the per-endpoint health check settings
+    pub fn set_endpoint_health_checks(&self, health_checks: &HashMap<String, EndpointHealthCheck>) {
+        if let Some(health_manager) = &self.health_manager {
+            health_manager.set_endpoint_overrides(Self::endpoint_overrides(health_checks));
+        }
+    }
+
+    /// Key endpoint health check settings by socket address
+    fn endpoint_overrides(
+        health_checks: &HashMap<String, EndpointHealthCheck>,
+    ) -> HashMap<SocketAddr, EndpointHealthCheck> {
+        health_checks
+            .iter()
+            .filter_map(|(addr, health_check)| Some((addr.parse().ok()?, health_check.clone())))
+            .collect()
+    }
+
+    /// Period of the health check task: the configured interval, or the
+    /// shortest per-endpoint interval if that is shorter
+    fn health_check_tick(health_manager: &crate::health::HealthCheckManager, interval_sec: u64) -> Duration {
+        let interval = Duration::from_secs(interval_sec);
+        health_manager
+            .shortest_endpoint_interval()
+            .map_or(interval, |shortest| shortest.min(interval))
+    }
+
+    /// Get the current health check interval in seconds
+    pub fn health_check_interval(&self) -> u64 {
+        self.health_check_interval_sec.load(Ordering::Relaxed)
+    }
+
     /// Start health checking for all backends
     pub async fn start_health_checks(&self) -> Result<(), Box<dyn std::error::Error>> {
         if let Some(health_manager) = &self.health_manager {
             // Start health checks in a separate task to avoid runtime conflicts
             let health_manager = Arc::clone(health_manager);
             let backends = Arc::clone(&self.backends);
-            let config_interval = self.config.health_check_interval_sec;
+            let interval_sec = Arc::clone(&self.health_check_interval_sec);
+            let catalog_unhealthy = Arc::clone(&self.catalog_unhealthy);
+            let health_updates = Arc::clone(&self.health_updates);
+            let health_events = Arc::clone(&self.health_events);
+            let jitter_percent = self.config.health_check_jitter_percent;
             
             // Start health checks in a separate thread with its own runtime to avoid conflicts with Pingora
             std::thread::spawn(move || {
-                let rt = tokio::runtime::Runtime::new().unwrap();
+                let rt = crate::utils::background_runtime();
                 rt.block_on(async move {
                 log::info!("Starting MongoDB health check background task");
-                let mut interval = tokio::time::interval(
-                    tokio::time::Duration::from_secs(config_interval)
-                );
+                let mut current_interval = interval_sec.load(Ordering::Relaxed);
+                let mut current_tick = Self::health_check_tick(&health_manager, current_interval);
+                let mut interval = tokio::time::interval(current_tick);
+                // When each backend was last checked, for per-endpoint intervals
+                let mut last_checked: HashMap<SocketAddr, Instant> = HashMap::new();
                 
                 loop {
                     interval.tick().await;
+
+                    // Pick up interval changes from configuration reloads
+                    let configured_interval = interval_sec.load(Ordering::Relaxed);
+                    if configured_interval != current_interval {
+                        log::info!("MongoDB health check interval changed to {configured_interval}s");
+                        current_interval = configured_interval;
+                    }
+                    let tick = Self::health_check_tick(&health_manager, current_interval);
+                    if tick != current_tick {
+                        current_tick = tick;
+                        interval = tokio::time::interval(current_tick);
+                        interval.tick().await;
+                    }
                     
-                    // Get all backends to check
+                    // Get the backends due for a check; half a tick of slack
+                    // keeps timer jitter from delaying a check by a whole tick
+                    let now = Instant::now();
                     let backend_list = {
                         let backends = backends.read().await;
-                        backends.values().cloned().collect::<Vec<_>>()
+                        last_checked.retain(|addr, _| backends.values().any(|b| b.addr == *addr));
+                        backends
+                            .values()
+                            .filter(|backend| {
+                                let due_after = health_manager
+                                    .endpoint_interval(backend.addr)
+                                    .unwrap_or(Duration::from_secs(current_interval));
+                                last_checked.get(&backend.addr).is_none_or(|checked| {
+                                    now.duration_since(*checked) + current_tick / 2 >= due_after
+                                })
+                            })
+                            .cloned()
+                            .collect::<Vec<_>>()
                     };
+                    for backend in &backend_list {
+                        last_checked.insert(backend.addr, now);
+                    }
                     
                     if backend_list.is_empty() {
                         continue;
                     }
                     
-                    // Check health of all backends concurrently
-                    let health_checks: Vec<_> = backend_list.iter().map(|backend| {
+                    // Check health of all backends concurrently, staggered
+                    // over part of the tick so they do not all fire at once
+                    let spread = current_tick * jitter_percent / 100;
+                    let count = backend_list.len();
+                    let health_checks: Vec<_> = backend_list.iter().enumerate().map(|(index, backend)| {
                         let health_manager = Arc::clone(&health_manager);
                         let backend = backend.clone();
                         async move {
+                            tokio::time::sleep(crate::health::stagger_delay(index, count, spread)).await;
                             let mut backend_clone = backend.clone();
                             let status = health_manager.check_backend_health(&mut backend_clone).await;
-                            (backend.id.clone(), status)
+                            (backend_clone, status)
                         }
                     }).collect();
                     
                     let results = futures::future::join_all(health_checks).await;
                     
-                    // Update backend health status
+                    // Update backend health status; the discovery catalog can veto
                     {
+                        let catalog_unhealthy = catalog_unhealthy.read().await;
                         let mut backends_mut = backends.write().await;
-                        for (backend_id, status) in results {
+                        for (checked, status) in results {
+                            let backend_id = checked.id;
                             if let Some(backend) = backends_mut.get_mut(&backend_id) {
                                 let was_healthy = backend.healthy;
-                                backend.healthy = status.is_healthy();
+                                // The health manager applies the failure and success thresholds
+                                backend.last_health_check = checked.last_health_check;
+                                if let (
+                                    BackendMetadata::MongoDB { version, max_wire_version, .. },
+                                    BackendMetadata::MongoDB {
+                                        version: checked_version,
+                                        max_wire_version: checked_max_wire_version,
+                                        ..
+                                    },
+                                ) = (&mut backend.metadata, &checked.metadata)
+                                {
+                                    version.clone_from(checked_version);
+                                    *max_wire_version = *checked_max_wire_version;
+                                }
+                                backend.healthy = checked.healthy
+                                    && !catalog_unhealthy.contains(&backend.addr);
                                 
                                 if was_healthy != backend.healthy {
-                                    if backend.healthy {
+                                    let reason = if backend.healthy {
                                         log::info!("Backend {backend_id} is now healthy");
+                                        "health checks passed".to_string()
+                                    } else if checked.healthy {
+                                        log::warn!("Backend {backend_id} is now unhealthy: failing in the discovery catalog");
+                                        "failing in the discovery catalog".to_string()
                                     } else {
                                         log::warn!("Backend {backend_id} is now unhealthy: {status}");
-                                    }
+                                        status.to_string()
+                                    };
+                                    health_events.record(&backend_id, backend.addr, backend.healthy, &reason);
                                 }
                             }
                         }
+                        // Hand the same view to the Pingora load balancer
+                        Self::publish_health(&health_updates, &backends_mut, &catalog_unhealthy);
                     }
                 }
             })});
@@ -284,48 +1479,120 @@ impl MongoDBProxy {
 
     /// Route request to appropriate mongos based on session affinity
     pub async fn route_request(&self, client_addr: SocketAddr) -> RoutingDecision {
-        let backends = self.backends.read().await;
-
-        // Get healthy backends
-        let healthy_backends: Vec<String> = backends
-            .values()
-            .filter(|b| b.healthy)
-            .map(|b| b.id.clone())
-            .collect();
-
-        if healthy_backends.is_empty() {
-            return RoutingDecision::Error {
-                message: "No healthy mongos instances available".to_string(),
-            };
+        // Static affinity rules come before the client's session
+        if let Some(backend) = self.static_backend(client_addr.ip(), &[]).await {
+            if self.config.session_affinity_enabled {
+                self.affinity_manager
+                    .get_backend_for_client(client_addr, std::slice::from_ref(&backend.id))
+                    .await;
+            }
+            return RoutingDecision::Route { backend_id: backend.id };
         }
 
-        // Use session affinity if enabled
+        // Keep an existing session on its backend if affinity is enabled
         if self.config.session_affinity_enabled {
+            let healthy_ids: Vec<String> = {
+                let backends = self.backends.read().await;
+                backends
+                    .values()
+                    .filter(|b| b.healthy && self.admits_read_preference(client_addr.ip(), b.addr))
+                    .map(|b| b.id.clone())
+                    .collect()
+            };
             if let Some(backend_id) = self
                 .affinity_manager
-                .get_backend_for_client(client_addr, &healthy_backends)
+                .existing_backend(client_addr, &healthy_ids)
                 .await
             {
+                let addr = self.backends.read().await.get(&backend_id).map(|b| b.addr);
+                // Fail fast rather than move the session to another mongos
+                if let Some(addr) = addr.filter(|addr| !self.circuit_allows(*addr)) {
+                    return RoutingDecision::Error {
+                        message: format!("Circuit open for mongos {addr}"),
+                    };
+                }
                 return RoutingDecision::Route { backend_id };
             }
         }
 
-        // Fallback to simple round-robin for new sessions
-        let index = self
-            .affinity_manager
-            .round_robin_counter
-            .load(Ordering::Relaxed)
-            % healthy_backends.len();
-        let backend_id = healthy_backends[index].clone();
+        // New sessions are spread according to the balance strategy
+        let backend = match self.select_new_backend(client_addr).await {
+            Some(backend) => backend,
+            None => {
+                return RoutingDecision::Error {
+                    message: "No healthy mongos instances available".to_string(),
+                }
+            }
+        };
+        if !self.circuit_allows(backend.addr) {
+            return RoutingDecision::Error {
+                message: format!("Circuit open for mongos {}", backend.addr),
+            };
+        }
+        let backend_id = backend.id;
+
+        if self.config.session_affinity_enabled {
+            self.affinity_manager
+                .assign_backend(client_addr, backend_id.clone())
+                .await;
+        }
+
+        RoutingDecision::Route { backend_id }
+    }
+
+    /// Record traffic from a client so its session doesn't expire
+    pub async fn touch_session(&self, client: &ClientIdentifier) {
+        if self.config.session_affinity_enabled {
+            self.affinity_manager.touch(client).await;
+        }
+    }
+
+    /// Identify a client for session affinity from its address and, with a
+    /// strategy other than the socket address, its hello handshake
+    pub fn identify_client(&self, client_addr: SocketAddr, handshake: Option<&[u8]>) -> ClientIdentifier {
+        affinity::identify_client(self.config.client_identification, client_addr, handshake)
+    }
+
+    /// Whether clients are identified by their handshake, which must then
+    /// be read before a mongos is selected
+    pub fn identifies_by_handshake(&self) -> bool {
+        self.config.session_affinity_enabled
+            && self.config.client_identification != ClientIdentificationStrategy::SocketAddressOnly
+    }
 
-        RoutingDecision::Route { backend_id }
+    /// Expire idle sessions in the background, so affinities of clients
+    /// that went away without a clean disconnect don't pile up, and keep
+    /// the session gauges current
+    pub fn start_session_cleanup(&self) {
+        if !self.config.session_affinity_enabled {
+            return;
+        }
+        let affinity_manager = self.affinity_manager.clone();
+        let cleanup_interval = (affinity_manager.session_timeout / 4)
+            .clamp(Duration::from_secs(1), SESSION_METRICS_INTERVAL);
+
+        std::thread::spawn(move || {
+            let rt = crate::utils::background_runtime();
+            rt.block_on(async move {
+                let mut interval = tokio::time::interval(cleanup_interval);
+                let mut published = HashSet::new();
+                loop {
+                    interval.tick().await;
+                    let expired = affinity_manager.cleanup_expired_sessions().await;
+                    if expired > 0 {
+                        log::debug!("Expired {expired} idle MongoDB session affinities");
+                    }
+                    affinity_manager.publish_metrics(&mut published).await;
+                }
+            });
+        });
     }
 
     /// Handle client disconnection
-    pub async fn handle_client_disconnect(&self, client_addr: SocketAddr) -> bool {
+    pub async fn handle_client_disconnect(&self, client: impl Into<ClientIdentifier>) -> bool {
         if self.config.session_affinity_enabled {
             self.affinity_manager
-                .remove_client_affinity(client_addr)
+                .remove_client_affinity(client)
                 .await
         } else {
             false
@@ -346,6 +1613,30 @@ impl MongoDBProxy {
     pub fn get_affinity_manager(&self) -> &SessionAffinityManager {
         &self.affinity_manager
     }
+
+    /// Health and open client connections of every mongos, for the admin
+    /// status report
+    pub async fn backend_status(&self) -> Vec<BackendStatus> {
+        let backends = self.backends.read().await;
+        let mut status: Vec<BackendStatus> = backends
+            .values()
+            .map(|backend| BackendStatus {
+                id: backend.id.clone(),
+                addr: backend.addr.to_string(),
+                healthy: backend.healthy && !self.is_ejected(backend.addr),
+                draining: false,
+                sessions: match &backend.metadata {
+                    BackendMetadata::MongoDB {
+                        connection_count, ..
+                    } => Some(*connection_count),
+                    BackendMetadata::Redis { .. } => None,
+                },
+                slots: None,
+            })
+            .collect();
+        status.sort_by_key(|backend| backend.addr.clone());
+        status
+    }
 }
 
 #[cfg(test)]
@@ -541,6 +1832,68 @@ mod tests {
         assert_eq!(manager.session_count().await, 0);
     }
 
+    #[tokio::test]
+    async fn test_session_affinity_manager_expires_idle_sessions() {
+        let manager = SessionAffinityManager::new().with_session_timeout(Duration::from_millis(50));
+        let idle = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 12345);
+        let active = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 12346);
+        manager.assign_backend(idle, "mongos-0".to_string()).await;
+        manager.assign_backend(active, "mongos-1".to_string()).await;
+        assert_eq!(manager.cleanup_expired_sessions().await, 0);
+
+        tokio::time::sleep(Duration::from_millis(80)).await;
+        manager.touch(&active.into()).await;
+        assert_eq!(manager.cleanup_expired_sessions().await, 1);
+        assert_eq!(manager.get_active_clients().await, vec![active.into()]);
+
+        // The session timeout comes from the configuration
+        let config = MongoDBConfig::new(vec!["127.0.0.1:27017".to_string()], true, 30, 10).unwrap();
+        let proxy = MongoDBProxy::new(config);
+        assert_eq!(proxy.affinity_manager.session_timeout, Duration::from_secs(30));
+    }
+
+    #[tokio::test]
+    async fn test_session_affinity_statistics() {
+        let manager = SessionAffinityManager::new();
+        let first = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)), 40001);
+        let app = ClientIdentifier::SessionId("stats-app".to_string());
+        manager.assign_backend(first, "mongos-stats-0".to_string()).await;
+        manager.assign_backend(app.clone(), "mongos-stats-1".to_string()).await;
+        // A second connection of the same application
+        assert_eq!(manager.backend_of(&app).await.as_deref(), Some("mongos-stats-1"));
+
+        let statistics = manager.get_statistics().await;
+        assert_eq!(statistics.total_sessions, 2);
+        assert_eq!(statistics.total_connections, 3);
+        assert_eq!(statistics.backend_distribution["mongos-stats-1"], 1);
+
+        let sessions = manager.sessions(Some("mongos-stats-1")).await;
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].client, "session:stats-app");
+        assert_eq!(sessions[0].connections, 2);
+
+        let mut published = HashSet::new();
+        manager.publish_metrics(&mut published).await;
+        manager.remove_client_affinity(first).await;
+        manager.publish_metrics(&mut published).await;
+        let metrics = crate::metrics::global().render_prometheus();
+        assert!(metrics.contains("puerta_mongodb_affinity_sessions{backend=\"mongos-stats-0\"} 0"));
+        assert!(metrics.contains("puerta_mongodb_affinity_connections{backend=\"mongos-stats-1\"} 2"));
+        assert_eq!(published, HashSet::from(["mongos-stats-1".to_string()]));
+
+        let router = manager.register_admin_routes(AdminRouter::new());
+        let response = router
+            .handle(crate::admin::AdminRequest {
+                method: "GET".to_string(),
+                path: "/mongodb/sessions".to_string(),
+                ..Default::default()
+            })
+            .await;
+        let report: SessionsReport = serde_json::from_value(response.body).unwrap();
+        assert_eq!(report.statistics.total_sessions, 1);
+        assert_eq!(report.sessions[0].backend_id, "mongos-stats-1");
+    }
+
     #[tokio::test]
     async fn test_session_affinity_manager_get_active_clients() {
         let manager = SessionAffinityManager::new();
@@ -563,7 +1916,7 @@ mod tests {
         assert_eq!(active_clients.len(), 3);
 
         for &client_addr in &client_addrs {
-            assert!(active_clients.contains(&client_addr));
+            assert!(active_clients.contains(&client_addr.into()));
         }
     }
 
@@ -595,6 +1948,536 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_mongodb_proxy_route_request_by_weight() {
+        let mut weights = HashMap::new();
+        weights.insert("127.0.0.1:27017".to_string(), 3);
+        let config = MongoDBConfig::new(
+            vec!["127.0.0.1:27017".to_string(), "127.0.0.1:27018".to_string()],
+            true,
+            300,
+            10,
+        )
+        .unwrap()
+        .with_endpoint_weights(weights);
+
+        let proxy = MongoDBProxy::new(config);
+        proxy.initialize_backends().await.unwrap();
+        for backend in proxy.backends.write().await.values_mut() {
+            backend.healthy = true;
+        }
+
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for port in 0..8 {
+            let client_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 40000 + port);
+            match proxy.route_request(client_addr).await {
+                RoutingDecision::Route { backend_id } => *counts.entry(backend_id).or_default() += 1,
+                _ => panic!("Expected route decision"),
+            }
+        }
+        assert_eq!(counts["mongos-0"], 6);
+        assert_eq!(counts["mongos-1"], 2);
+
+        // Existing sessions stay on their backend
+        let client_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 40001);
+        let first = proxy.route_request(client_addr).await;
+        let second = proxy.route_request(client_addr).await;
+        assert_eq!(format!("{first:?}"), format!("{second:?}"));
+
+        // Reloaded weights apply to new sessions
+        proxy
+            .update_weights(&HashMap::from([("127.0.0.1:27018".to_string(), 3)]))
+            .await;
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for port in 0..8 {
+            let client_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 41000 + port);
+            match proxy.route_request(client_addr).await {
+                RoutingDecision::Route { backend_id } => *counts.entry(backend_id).or_default() += 1,
+                _ => panic!("Expected route decision"),
+            }
+        }
+        assert_eq!(counts["mongos-0"], 2);
+        assert_eq!(counts["mongos-1"], 6);
+        let third = proxy.route_request(client_addr).await;
+        assert_eq!(format!("{first:?}"), format!("{third:?}"));
+    }
+
+    #[tokio::test]
+    async fn test_mongodb_proxy_catalog_health() {
+        let config = MongoDBConfig::new(
+            vec!["127.0.0.1:27017".to_string(), "127.0.0.1:27018".to_string()],
+            false,
+            300,
+            10,
+        )
+        .unwrap();
+        let proxy = MongoDBProxy::new(config);
+        proxy.initialize_backends().await.unwrap();
+        for backend in proxy.backends.write().await.values_mut() {
+            backend.healthy = true;
+        }
+
+        let failing: SocketAddr = "127.0.0.1:27018".parse().unwrap();
+        proxy.set_catalog_unhealthy(HashSet::from([failing])).await;
+        let client: SocketAddr = "10.0.0.1:5000".parse().unwrap();
+        for _ in 0..4 {
+            assert_ne!(proxy.select_new_backend(client).await.unwrap().addr, failing);
+        }
+        assert!(proxy.catalog_unhealthy.read().await.contains(&failing));
+        assert!(proxy.health_events.is_unhealthy(failing));
+
+        proxy.set_catalog_unhealthy(HashSet::new()).await;
+        assert!(proxy.catalog_unhealthy.read().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_mongodb_proxy_publishes_health() {
+        let config = MongoDBConfig::new(
+            vec!["127.0.0.1:27017".to_string(), "127.0.0.1:27018".to_string()],
+            false,
+            300,
+            10,
+        )
+        .unwrap();
+        let proxy = MongoDBProxy::new(config);
+        proxy.initialize_backends().await.unwrap();
+        let mut updates = proxy.health_updates();
+
+        // Unchecked backends are not reported, catalog failures are
+        let failing: SocketAddr = "127.0.0.1:27018".parse().unwrap();
+        proxy.set_catalog_unhealthy(HashSet::from([failing])).await;
+        assert!(updates.has_changed().unwrap());
+        assert_eq!(*updates.borrow_and_update(), HashSet::from([failing]));
+
+        // Checked backends are reported by their health
+        let checked: SocketAddr = "127.0.0.1:27017".parse().unwrap();
+        for backend in proxy.backends.write().await.values_mut() {
+            backend.last_health_check = Some(std::time::SystemTime::now());
+            backend.healthy = backend.addr == failing;
+        }
+        proxy.set_catalog_unhealthy(HashSet::new()).await;
+        assert_eq!(*updates.borrow_and_update(), HashSet::from([checked]));
+
+        // Unchanged sets are not sent again
+        proxy.set_catalog_unhealthy(HashSet::new()).await;
+        assert!(!updates.has_changed().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_mongodb_proxy_least_connections() {
+        let config = MongoDBConfig::new(
+            vec!["127.0.0.1:27017".to_string(), "127.0.0.1:27018".to_string()],
+            false,
+            300,
+            10,
+        )
+        .unwrap()
+        .with_balance_strategy(BalanceStrategy::LeastConnections);
+
+        let proxy = MongoDBProxy::new(config);
+        proxy.initialize_backends().await.unwrap();
+        for backend in proxy.backends.write().await.values_mut() {
+            backend.healthy = true;
+        }
+
+        let busy: SocketAddr = "127.0.0.1:27017".parse().unwrap();
+        proxy.connection_opened(busy).await;
+        proxy.connection_opened(busy).await;
+
+        let client_addr: SocketAddr = "127.0.0.1:40000".parse().unwrap();
+        let selected = proxy.select_new_backend(client_addr).await.unwrap();
+        assert_eq!(selected.addr.to_string(), "127.0.0.1:27018");
+
+        // Closing connections brings the busy backend back into contention
+        proxy.connection_closed(busy).await;
+        proxy.connection_closed(busy).await;
+        proxy.connection_closed(busy).await;
+        let backends = proxy.backends.read().await;
+        let counts: Vec<usize> = backends
+            .values()
+            .map(|b| match b.metadata {
+                BackendMetadata::MongoDB {
+                    connection_count, ..
+                } => connection_count,
+                _ => unreachable!(),
+            })
+            .collect();
+        assert_eq!(counts, vec![0, 0]);
+    }
+
+    #[tokio::test]
+    async fn test_mongodb_proxy_affinity_rules() {
+        let config = MongoDBConfig::new(
+            vec!["127.0.0.1:27017".to_string(), "127.0.0.1:27018".to_string(), "127.0.0.1:27019".to_string()],
+            true,
+            300,
+            10,
+        )
+        .unwrap()
+        .with_affinity_rules(vec![AffinityRule {
+            clients: vec!["10.20.0.0/16".to_string()],
+            endpoints: vec!["127.0.0.1:27019".to_string(), "127.0.0.1:27018".to_string()],
+        }]);
+
+        let proxy = MongoDBProxy::new(config);
+        proxy.initialize_backends().await.unwrap();
+        for backend in proxy.backends.write().await.values_mut() {
+            backend.healthy = true;
+        }
+
+        let batch_ip = IpAddr::V4(Ipv4Addr::new(10, 20, 3, 4));
+        let other_ip = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 6));
+        assert!(proxy.affinity_rule(other_ip).is_none());
+
+        // The rule wins over an existing session
+        let batch_client = SocketAddr::new(batch_ip, 40000);
+        proxy
+            .affinity_manager
+            .assign_backend(batch_client, "mongos-0".to_string())
+            .await;
+        let RoutingDecision::Route { backend_id } = proxy.route_request(batch_client).await else {
+            panic!("Expected a route");
+        };
+        assert_eq!(backend_id, "mongos-2");
+
+        // The next endpoint of the rule takes over, then normal balancing
+        let failover: SocketAddr = "127.0.0.1:27019".parse().unwrap();
+        let selected = proxy.static_backend(batch_ip, &[failover]).await.unwrap();
+        assert_eq!(selected.addr.to_string(), "127.0.0.1:27018");
+        for backend in proxy.backends.write().await.values_mut() {
+            backend.healthy = backend.addr.to_string() == "127.0.0.1:27017";
+        }
+        assert!(proxy.static_backend(batch_ip, &[]).await.is_none());
+        let RoutingDecision::Route { backend_id } = proxy.route_request(batch_client).await else {
+            panic!("Expected a route");
+        };
+        assert_eq!(backend_id, "mongos-0");
+    }
+
+    #[tokio::test]
+    async fn test_explain_client() {
+        let config = MongoDBConfig::new(
+            vec!["127.0.0.1:27017".to_string(), "127.0.0.1:27018".to_string(), "127.0.0.1:27019".to_string()],
+            true,
+            300,
+            10,
+        )
+        .unwrap()
+        .with_affinity_rules(vec![AffinityRule {
+            clients: vec!["10.20.0.0/16".to_string()],
+            endpoints: vec!["127.0.0.1:27019".to_string(), "127.0.0.1:27018".to_string()],
+        }]);
+
+        let proxy = MongoDBProxy::new(config);
+        proxy.initialize_backends().await.unwrap();
+        for backend in proxy.backends.write().await.values_mut() {
+            backend.healthy = true;
+        }
+
+        let batch_ip = IpAddr::V4(Ipv4Addr::new(10, 20, 3, 4));
+        proxy
+            .affinity_manager
+            .assign_backend(SocketAddr::new(batch_ip, 40000), "mongos-0".to_string())
+            .await;
+        let route = proxy.explain_client(batch_ip, &[]).await;
+        assert_eq!(route.decision, "affinity_rule");
+        assert_eq!(route.backend.as_deref(), Some("127.0.0.1:27019"));
+        assert_eq!(
+            route.affinity_rule,
+            Some(vec!["127.0.0.1:27019".to_string(), "127.0.0.1:27018".to_string()])
+        );
+        assert_eq!(route.sessions.len(), 1);
+        assert_eq!(route.sessions[0].backend_id, "mongos-0");
+
+        // Excluded mongos are skipped as a failover would
+        let failover: SocketAddr = "127.0.0.1:27019".parse().unwrap();
+        let route = proxy.explain_client(batch_ip, &[failover]).await;
+        assert_eq!(route.backend.as_deref(), Some("127.0.0.1:27018"));
+
+        let route = proxy.explain_client(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 6)), &[]).await;
+        assert_eq!(route.decision, "balanced");
+        assert!(route.affinity_rule.is_none() && route.backend.is_none() && route.sessions.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_mongodb_proxy_unhealthy_backend_sessions() {
+        let config = MongoDBConfig::new(
+            vec!["127.0.0.1:27017".to_string(), "127.0.0.1:27018".to_string()],
+            true,
+            300,
+            10,
+        )
+        .unwrap();
+        let proxy = Arc::new(MongoDBProxy::new(config));
+        assert_eq!(proxy.get_config().on_backend_unhealthy, UnhealthyBackendAction::Close);
+        proxy.initialize_backends().await.unwrap();
+        for backend in proxy.backends.write().await.values_mut() {
+            backend.healthy = true;
+        }
+        for port in 40000..40003 {
+            let client = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 5)), port);
+            let backend_id = if port == 40000 { "mongos-1" } else { "mongos-0" };
+            proxy.affinity_manager.assign_backend(client, backend_id.to_string()).await;
+        }
+
+        let failing: SocketAddr = "127.0.0.1:27017".parse().unwrap();
+        let waiter = tokio::spawn({
+            let proxy = Arc::clone(&proxy);
+            async move { proxy.became_unhealthy(failing).await }
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!waiter.is_finished());
+
+        proxy.set_catalog_unhealthy(HashSet::from([failing])).await;
+        tokio::time::timeout(Duration::from_secs(5), waiter)
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(proxy.invalidate_sessions(failing).await, 2);
+        assert_eq!(proxy.affinity_manager.session_count().await, 1);
+        assert_eq!(proxy.invalidate_sessions("127.0.0.1:27019".parse().unwrap()).await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_mongodb_proxy_read_preference_routes() {
+        let config = MongoDBConfig::new(
+            vec![
+                "127.0.0.1:27017".to_string(),
+                "127.0.0.1:27018".to_string(),
+                "127.0.0.1:27019".to_string(),
+            ],
+            true,
+            300,
+            10,
+        )
+        .unwrap()
+        .with_read_preference_routes(vec![ReadPreferenceRoute {
+            modes: vec![ReadPreferenceMode::SecondaryPreferred],
+            endpoints: vec!["127.0.0.1:27018".to_string(), "127.0.0.1:27019".to_string()],
+        }]);
+
+        let proxy = MongoDBProxy::new(config);
+        assert!(proxy.routes_read_preferences());
+        proxy.initialize_backends().await.unwrap();
+        for backend in proxy.backends.write().await.values_mut() {
+            backend.healthy = true;
+        }
+
+        let analytics_ip = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 5));
+        let other_ip = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 6));
+        proxy.record_read_preference(analytics_ip, ReadPreferenceMode::SecondaryPreferred);
+        proxy.record_read_preference(other_ip, ReadPreferenceMode::Primary);
+        assert!(proxy.read_preference_route(analytics_ip).is_some());
+        assert!(proxy.read_preference_route(other_ip).is_none());
+
+        // Connections from the analytics client only reach the routed subset
+        for port in 40000..40006 {
+            let selected = proxy
+                .select_new_backend(SocketAddr::new(analytics_ip, port))
+                .await
+                .unwrap();
+            assert_ne!(selected.addr.to_string(), "127.0.0.1:27017");
+        }
+        let unrouted: HashSet<String> = futures::future::join_all(
+            (40000..40006).map(|port| proxy.select_new_backend(SocketAddr::new(other_ip, port))),
+        )
+        .await
+        .into_iter()
+        .map(|backend| backend.unwrap().addr.to_string())
+        .collect();
+        assert_eq!(unrouted.len(), 3);
+
+        // With the whole subset down, any healthy mongos is used
+        for backend in proxy.backends.write().await.values_mut() {
+            backend.healthy = backend.addr.to_string() == "127.0.0.1:27017";
+        }
+        let selected = proxy
+            .select_new_backend(SocketAddr::new(analytics_ip, 40010))
+            .await
+            .unwrap();
+        assert_eq!(selected.addr.to_string(), "127.0.0.1:27017");
+    }
+
+    #[tokio::test]
+    async fn test_mongodb_proxy_consistent_hash_by_client_ip() {
+        let endpoints = vec![
+            "127.0.0.1:27017".to_string(),
+            "127.0.0.1:27018".to_string(),
+            "127.0.0.1:27019".to_string(),
+        ];
+        let new_proxy = || async {
+            let config = MongoDBConfig::new(endpoints.clone(), false, 300, 10)
+                .unwrap()
+                .with_balance_strategy(BalanceStrategy::ConsistentHash);
+            let proxy = MongoDBProxy::new(config);
+            proxy.initialize_backends().await.unwrap();
+            for backend in proxy.backends.write().await.values_mut() {
+                backend.healthy = true;
+            }
+            proxy
+        };
+        let proxy = new_proxy().await;
+        let restarted = new_proxy().await;
+
+        for host in 1..20 {
+            let ip = IpAddr::V4(Ipv4Addr::new(10, 0, 0, host));
+            let first = proxy.route_request(SocketAddr::new(ip, 40000)).await;
+            // Other connections from the same host, even after a restart
+            let again = proxy.route_request(SocketAddr::new(ip, 40001)).await;
+            let after_restart = restarted.route_request(SocketAddr::new(ip, 40002)).await;
+            assert_eq!(format!("{first:?}"), format!("{again:?}"));
+            assert_eq!(format!("{first:?}"), format!("{after_restart:?}"));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_mongodb_proxy_passive_ejection() {
+        let config = MongoDBConfig::new(
+            vec!["127.0.0.1:27017".to_string(), "127.0.0.1:27018".to_string()],
+            false,
+            300,
+            10,
+        )
+        .unwrap()
+        .with_passive_failure_threshold(2);
+
+        let proxy = MongoDBProxy::new(config).with_health_check();
+        proxy.initialize_backends().await.unwrap();
+        for backend in proxy.backends.write().await.values_mut() {
+            backend.healthy = true;
+        }
+
+        let failing: SocketAddr = "127.0.0.1:27017".parse().unwrap();
+        proxy.report_failure(failing).await;
+        assert!(!proxy.is_ejected(failing));
+
+        proxy.report_failure(failing).await;
+        assert!(proxy.is_ejected(failing));
+        assert!(proxy.health_events.is_unhealthy(failing));
+
+        // New sessions avoid the ejected mongos
+        let client_addr: SocketAddr = "127.0.0.1:40000".parse().unwrap();
+        for _ in 0..4 {
+            let selected = proxy.select_new_backend(client_addr).await.unwrap();
+            assert_eq!(selected.addr.to_string(), "127.0.0.1:27018");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_mongodb_proxy_outlier_ejection() {
+        let config = MongoDBConfig::new(
+            vec!["127.0.0.1:27017".to_string(), "127.0.0.1:27018".to_string()],
+            false,
+            300,
+            10,
+        )
+        .unwrap()
+        .with_passive_failure_threshold(0)
+        .with_outlier_detection(Some(OutlierDetectionConfig {
+            min_requests: 5,
+            ..OutlierDetectionConfig::default()
+        }));
+
+        let proxy = MongoDBProxy::new(config).with_health_check();
+        proxy.initialize_backends().await.unwrap();
+        for backend in proxy.backends.write().await.values_mut() {
+            backend.healthy = true;
+        }
+
+        let fast: SocketAddr = "127.0.0.1:27018".parse().unwrap();
+        let slow: SocketAddr = "127.0.0.1:27017".parse().unwrap();
+        for _ in 0..10 {
+            proxy.report_success(fast, Duration::from_millis(2));
+            proxy.report_success(slow, Duration::from_millis(50));
+        }
+        assert!(proxy.is_ejected(slow));
+        assert!(!proxy.is_ejected(fast));
+
+        // The outlier stays marked healthy but receives no new sessions
+        let client_addr: SocketAddr = "127.0.0.1:40000".parse().unwrap();
+        for _ in 0..4 {
+            let selected = proxy.select_new_backend(client_addr).await.unwrap();
+            assert_eq!(selected.addr, fast);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_mongodb_proxy_circuit_breaker() {
+        let config = MongoDBConfig::new(
+            vec!["127.0.0.1:27017".to_string(), "127.0.0.1:27018".to_string()],
+            true,
+            300,
+            10,
+        )
+        .unwrap()
+        .with_circuit_breaker(Some(CircuitBreakerConfig {
+            min_requests: 4,
+            ..CircuitBreakerConfig::default()
+        }));
+
+        let proxy = MongoDBProxy::new(config);
+        proxy.initialize_backends().await.unwrap();
+        for backend in proxy.backends.write().await.values_mut() {
+            backend.healthy = true;
+        }
+
+        let client_addr: SocketAddr = "127.0.0.1:40000".parse().unwrap();
+        let RoutingDecision::Route { backend_id } = proxy.route_request(client_addr).await else {
+            panic!("Expected route decision");
+        };
+        let failing = proxy.backends.read().await[&backend_id].addr;
+        for _ in 0..4 {
+            proxy.report_failure(failing).await;
+        }
+        assert!(proxy.is_circuit_open(failing));
+
+        // The pinned session fails fast instead of moving
+        match proxy.route_request(client_addr).await {
+            RoutingDecision::Error { message } => {
+                assert_eq!(message, format!("Circuit open for mongos {failing}"));
+            }
+            _ => panic!("Expected error decision"),
+        }
+
+        // New sessions avoid the open circuit
+        let other_client: SocketAddr = "127.0.0.1:40001".parse().unwrap();
+        let selected = proxy.select_new_backend(other_client).await.unwrap();
+        assert_ne!(selected.addr, failing);
+    }
+
+    #[tokio::test]
+    async fn test_mongodb_proxy_select_excluding_failed_backends() {
+        let config = MongoDBConfig::new(
+            vec!["127.0.0.1:27017".to_string(), "127.0.0.1:27018".to_string()],
+            true,
+            300,
+            10,
+        )
+        .unwrap();
+        let proxy = MongoDBProxy::new(config);
+        proxy.initialize_backends().await.unwrap();
+        for backend in proxy.backends.write().await.values_mut() {
+            backend.healthy = true;
+        }
+
+        let client_addr: SocketAddr = "127.0.0.1:40000".parse().unwrap();
+        let failed: SocketAddr = "127.0.0.1:27017".parse().unwrap();
+        for _ in 0..4 {
+            let selected = proxy
+                .select_new_backend_excluding(client_addr, &[failed])
+                .await
+                .unwrap();
+            assert_ne!(selected.addr, failed);
+        }
+
+        let other: SocketAddr = "127.0.0.1:27018".parse().unwrap();
+        assert!(proxy
+            .select_new_backend_excluding(client_addr, &[failed, other])
+            .await
+            .is_none());
+    }
+
     #[tokio::test]
     async fn test_mongodb_proxy_handle_client_disconnect_affinity_enabled() {
         let config = MongoDBConfig::new(
@@ -611,7 +2494,7 @@ mod tests {
         // Add a session manually
         {
             let mut affinity_map = proxy.affinity_manager.client_to_backend.write().await;
-            affinity_map.insert(client_addr, "mongos-0".to_string());
+            affinity_map.insert(client_addr.into(), AffinityEntry::new("mongos-0".to_string()));
         }
 
         // Handle disconnect
@@ -674,4 +2557,70 @@ mod tests {
         let result = proxy.start_health_checks().await;
         assert!(result.is_ok());
     }
+
+    #[tokio::test]
+    async fn test_mongodb_proxy_update_backends() {
+        let config = MongoDBConfig::new(
+            vec!["127.0.0.1:27017".to_string(), "127.0.0.1:27018".to_string()],
+            true,
+            300,
+            10,
+        )
+        .unwrap();
+
+        let proxy = MongoDBProxy::new(config);
+        proxy.initialize_backends().await.unwrap();
+
+        // Drop 27017, keep 27018, add 27019
+        proxy
+            .update_backends(&["127.0.0.1:27018".to_string(), "127.0.0.1:27019".to_string()])
+            .await
+            .unwrap();
+
+        let backends = proxy.get_backends();
+        let backends = backends.read().await;
+        assert_eq!(backends.len(), 2);
+
+        // Existing backend keeps its id
+        assert_eq!(
+            backends.get("mongos-1").unwrap().addr,
+            "127.0.0.1:27018".parse::<SocketAddr>().unwrap()
+        );
+        // New backend reuses the freed id
+        assert_eq!(
+            backends.get("mongos-0").unwrap().addr,
+            "127.0.0.1:27019".parse::<SocketAddr>().unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_mongodb_proxy_update_backends_invalid_endpoint() {
+        let config =
+            MongoDBConfig::new(vec!["127.0.0.1:27017".to_string()], true, 300, 10).unwrap();
+
+        let proxy = MongoDBProxy::new(config);
+        proxy.initialize_backends().await.unwrap();
+
+        let result = proxy.update_backends(&["not-an-address".to_string()]).await;
+        assert!(result.is_err());
+
+        // Backend set is left untouched
+        assert_eq!(proxy.get_backends().read().await.len(), 1);
+    }
+
+    #[test]
+    fn test_mongodb_proxy_set_health_check_interval() {
+        let config =
+            MongoDBConfig::new(vec!["127.0.0.1:27017".to_string()], true, 300, 10).unwrap();
+
+        let proxy = MongoDBProxy::new(config);
+        assert_eq!(proxy.health_check_interval(), 10);
+
+        proxy.set_health_check_interval(30);
+        assert_eq!(proxy.health_check_interval(), 30);
+
+        // Zero is ignored
+        proxy.set_health_check_interval(0);
+        assert_eq!(proxy.health_check_interval(), 30);
+    }
 }
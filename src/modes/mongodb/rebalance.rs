@@ -0,0 +1,165 @@
+/// Rebalancing of session affinity across mongos
+///
+/// Sessions stay on the mongos they were first given, so the distribution
+/// drifts over long periods: a mongos that joined late or came back from
+/// maintenance gets only new clients while the others keep theirs. The
+/// rebalancer compares the sessions of each mongos, per unit of weight,
+/// with the average and moves idle sessions from the busiest mongos to the
+/// least busy until the skew is tolerable. Only the affinity entry moves:
+/// connections open to the old mongos are left alone, and the client
+/// reaches its new mongos when it next connects.
+use super::affinity::ClientIdentifier;
+use super::SessionAffinityManager;
+use crate::config::RebalanceConfig;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// A session pinned to another mongos
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionMove {
+    pub client: ClientIdentifier,
+    pub from: String,
+    pub to: String,
+}
+
+impl SessionAffinityManager {
+    /// Move sessions off the busiest of the backends in `weights`, keyed by
+    /// backend id, while it holds more than `max_skew` times the average
+    ///
+    /// Only sessions idle for `min_idle_sec` for which `movable` holds are
+    /// moved, the most recently active first as they are the likeliest to
+    /// reconnect, and at most `max_sessions` of them. A move never leaves
+    /// the target busier than the source was.
+    pub async fn rebalance(
+        &self,
+        weights: &HashMap<String, usize>,
+        config: &RebalanceConfig,
+        movable: impl Fn(&ClientIdentifier) -> bool,
+    ) -> Vec<SessionMove> {
+        let mut backends: Vec<(&String, f64)> = weights
+            .iter()
+            .filter(|(_, weight)| **weight > 0)
+            .map(|(backend_id, weight)| (backend_id, *weight as f64))
+            .collect();
+        backends.sort_by(|a, b| a.0.cmp(b.0));
+        if backends.len() < 2 {
+            return Vec::new();
+        }
+
+        let mut affinity_map = self.client_to_backend.write().await;
+        let mut sessions: Vec<usize> = backends
+            .iter()
+            .map(|(backend_id, _)| {
+                affinity_map
+                    .values()
+                    .filter(|entry| &entry.backend_id == *backend_id)
+                    .count()
+            })
+            .collect();
+        let total_weight: f64 = backends.iter().map(|(_, weight)| weight).sum();
+        let average = sessions.iter().sum::<usize>() as f64 / total_weight;
+        let load = |sessions: &[usize], index: usize| sessions[index] as f64 / backends[index].1;
+        let min_idle = Duration::from_secs(config.min_idle_sec);
+
+        let mut moves = Vec::new();
+        while moves.len() < config.max_sessions {
+            let by_load = |a: &usize, b: &usize| load(&sessions, *a).total_cmp(&load(&sessions, *b));
+            let (Some(busiest), Some(idlest)) =
+                ((0..backends.len()).max_by(by_load), (0..backends.len()).min_by(by_load))
+            else {
+                break;
+            };
+            let busiest_load = load(&sessions, busiest);
+            if busiest_load <= average * config.max_skew
+                || (sessions[idlest] + 1) as f64 / backends[idlest].1 >= busiest_load
+            {
+                break;
+            }
+
+            let from = backends[busiest].0;
+            let Some((client, entry)) = affinity_map
+                .iter_mut()
+                .filter(|(client, entry)| {
+                    &entry.backend_id == from && entry.last_activity.elapsed() >= min_idle && movable(client)
+                })
+                .max_by_key(|(_, entry)| entry.last_activity)
+            else {
+                break;
+            };
+            entry.backend_id = backends[idlest].0.clone();
+            sessions[busiest] -= 1;
+            sessions[idlest] += 1;
+            moves.push(SessionMove {
+                client: client.clone(),
+                from: from.clone(),
+                to: entry.backend_id.clone(),
+            });
+        }
+        moves
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(max_sessions: usize, min_idle_sec: u64) -> RebalanceConfig {
+        RebalanceConfig {
+            max_skew: 1.5,
+            max_sessions,
+            min_idle_sec,
+            interval_sec: 60,
+        }
+    }
+
+    async fn manager(sessions: usize) -> SessionAffinityManager {
+        let manager = SessionAffinityManager::new();
+        for i in 0..sessions {
+            manager
+                .assign_backend(ClientIdentifier::SessionId(format!("app-{i}")), "mongos-0".to_string())
+                .await;
+        }
+        manager
+    }
+
+    fn weighted(entries: &[(&str, usize)]) -> HashMap<String, usize> {
+        entries.iter().map(|(id, weight)| (id.to_string(), *weight)).collect()
+    }
+
+    #[tokio::test]
+    async fn test_rebalance_until_skew_is_tolerable() {
+        let manager = manager(10).await;
+        let weights = weighted(&[("mongos-0", 1), ("mongos-1", 1)]);
+
+        // 10 against 0 is twice the average; 7 against 3 is within 1.5 times
+        let moves = manager.rebalance(&weights, &config(10, 0), |_| true).await;
+        assert_eq!(moves.len(), 3);
+        assert!(moves.iter().all(|m| m.from == "mongos-0" && m.to == "mongos-1"));
+        let distribution = manager.get_statistics().await.backend_distribution;
+        assert_eq!(distribution.get("mongos-0"), Some(&7));
+        assert_eq!(distribution.get("mongos-1"), Some(&3));
+        assert!(manager.rebalance(&weights, &config(10, 0), |_| true).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_rebalance_limits() {
+        let manager = manager(8).await;
+        let weights = weighted(&[("mongos-0", 1), ("mongos-1", 3)]);
+
+        // Sessions active recently or not movable stay
+        assert!(manager.rebalance(&weights, &config(10, 3600), |_| true).await.is_empty());
+        assert!(manager.rebalance(&weights, &config(10, 0), |_| false).await.is_empty());
+
+        // At most max_sessions per round
+        assert_eq!(manager.rebalance(&weights, &config(2, 0), |_| true).await.len(), 2);
+
+        // Weight scales the share of sessions a mongos holds
+        assert_eq!(manager.rebalance(&weights, &config(10, 0), |_| true).await.len(), 3);
+        let distribution = manager.get_statistics().await.backend_distribution;
+        assert_eq!(distribution.get("mongos-1"), Some(&5));
+
+        // A single candidate has nowhere to move sessions to
+        let single = weighted(&[("mongos-0", 1)]);
+        assert!(manager.rebalance(&single, &config(10, 0), |_| true).await.is_empty());
+    }
+}
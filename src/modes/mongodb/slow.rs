@@ -0,0 +1,128 @@
+/// Slow operation detection
+///
+/// An operation is timed from forwarding its request to mongos until the
+/// first reply answering it, matched by request id. Operations taking at
+/// least the threshold are logged and counted per mongos and command, so a
+/// slow mongos or an expensive query shape shows up without profiling on
+/// mongod.
+use super::wire::Operation;
+use crate::metrics::Counter;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Distinct commands counted before the rest are counted as `other`
+const MAX_COMMANDS: usize = 64;
+
+/// Slow operation tracker shared by every connection of a listener
+pub struct SlowOperations {
+    threshold: Duration,
+    /// Counters by mongos and command
+    counters: Mutex<HashMap<(String, String), Arc<Counter>>>,
+}
+
+impl SlowOperations {
+    pub fn new(threshold: Duration) -> Self {
+        Self {
+            threshold,
+            counters: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn threshold(&self) -> Duration {
+        self.threshold
+    }
+
+    /// Record an operation `backend` answered after `elapsed`, returning
+    /// whether it was slow
+    pub fn record(&self, operation: &Operation, backend: &str, client_addr: &str, elapsed: Duration) -> bool {
+        if elapsed < self.threshold {
+            return false;
+        }
+        log::warn!(
+            "Slow MongoDB operation {} {} took {}ms on mongos {backend} for client {client_addr}",
+            operation.command,
+            operation.namespace(),
+            elapsed.as_millis()
+        );
+        self.counter(backend, &operation.command).inc();
+        true
+    }
+
+    /// Counter of slow `command` operations on `backend`
+    fn counter(&self, backend: &str, command: &str) -> Arc<Counter> {
+        let mut counters = self.counters.lock().unwrap();
+        if let Some(counter) = counters.get(&(backend.to_string(), command.to_string())) {
+            return Arc::clone(counter);
+        }
+        // Command names come from clients, so their number is capped
+        let commands: HashSet<&str> = counters.keys().map(|(_, command)| command.as_str()).collect();
+        let command = if commands.contains(command) || commands.len() < MAX_COMMANDS {
+            command
+        } else {
+            "other"
+        };
+        Arc::clone(
+            counters
+                .entry((backend.to_string(), command.to_string()))
+                .or_insert_with(|| {
+                    crate::metrics::global().counter_with_labels(
+                        "puerta_mongodb_slow_operations_total",
+                        "MongoDB operations answered after the slow operation threshold",
+                        &[("backend", backend), ("command", command)],
+                    )
+                }),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn operation(command: &str) -> Operation {
+        Operation {
+            command: command.to_string(),
+            database: "shop".to_string(),
+            collection: Some("orders".to_string()),
+        }
+    }
+
+    fn count(backend: &str, command: &str) -> u64 {
+        crate::metrics::global()
+            .counter_with_labels(
+                "puerta_mongodb_slow_operations_total",
+                "MongoDB operations answered after the slow operation threshold",
+                &[("backend", backend), ("command", command)],
+            )
+            .get()
+    }
+
+    #[test]
+    fn test_slow_operations_counted_per_backend_and_command() {
+        let slow = SlowOperations::new(Duration::from_millis(100));
+        let backend = "test-slow-operations:27017";
+
+        assert!(!slow.record(&operation("find"), backend, "10.0.0.1:1", Duration::from_millis(99)));
+        assert_eq!(count(backend, "find"), 0);
+        assert!(slow.record(&operation("find"), backend, "10.0.0.1:1", Duration::from_millis(100)));
+        assert!(slow.record(&operation("find"), backend, "10.0.0.1:1", Duration::from_secs(2)));
+        assert!(slow.record(&operation("aggregate"), backend, "10.0.0.1:1", Duration::from_secs(1)));
+        assert_eq!(count(backend, "find"), 2);
+        assert_eq!(count(backend, "aggregate"), 1);
+    }
+
+    #[test]
+    fn test_command_labels_are_capped() {
+        let slow = SlowOperations::new(Duration::ZERO);
+        let backend = "test-slow-operations-cap:27017";
+        for i in 0..MAX_COMMANDS + 3 {
+            slow.record(&operation(&format!("command{i}")), backend, "10.0.0.1:1", Duration::ZERO);
+        }
+        assert_eq!(count(backend, "command0"), 1);
+        assert_eq!(count(backend, "other"), 3);
+        // Commands counted before the cap keep their own label
+        slow.record(&operation("command1"), backend, "10.0.0.1:1", Duration::ZERO);
+        assert_eq!(count(backend, "command1"), 2);
+    }
+}
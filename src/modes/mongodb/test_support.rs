@@ -0,0 +1,25 @@
+//! Helpers shared by the MongoDB mode tests
+
+use super::wire::{CHECKSUM_PRESENT, HEADER_LEN};
+
+/// A wire message with `op_code` and `body`, answering `response_to`
+pub(crate) fn message(request_id: i32, response_to: i32, op_code: i32, body: &[u8]) -> Vec<u8> {
+    let mut data = ((HEADER_LEN + body.len()) as i32).to_le_bytes().to_vec();
+    data.extend_from_slice(&request_id.to_le_bytes());
+    data.extend_from_slice(&response_to.to_le_bytes());
+    data.extend_from_slice(&op_code.to_le_bytes());
+    data.extend_from_slice(body);
+    data
+}
+
+/// An OP_MSG with flag bits `flags` and `command` as its body section,
+/// followed by a placeholder checksum when `flags` announce one
+pub(crate) fn op_msg(request_id: i32, response_to: i32, flags: u32, command: &[u8]) -> Vec<u8> {
+    let mut body = flags.to_le_bytes().to_vec();
+    body.push(0);
+    body.extend_from_slice(command);
+    if flags & CHECKSUM_PRESENT != 0 {
+        body.extend_from_slice(&[0xAA; 4]);
+    }
+    message(request_id, response_to, 2013, &body)
+}
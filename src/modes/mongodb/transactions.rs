@@ -0,0 +1,194 @@
+/// Transaction pinning
+///
+/// Every statement of a multi-document transaction must reach the mongos
+/// that started it, but drivers send them over whichever pooled connection
+/// is free. The transactions each client starts are recorded along with
+/// their mongos, and while one is open, new connections of the client go to
+/// that mongos ahead of affinity rules, sessions, cursors and balancing,
+/// even when it is being drained. A transaction ends when committed or
+/// aborted, or once past mongod's default transaction lifetime.
+use super::affinity::ClientIdentifier;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// mongod's default `transactionLifetimeLimitSeconds`, after which open
+/// transactions are aborted
+pub const TRANSACTION_LIFETIME: Duration = Duration::from_secs(60);
+
+/// Most transactions tracked at once
+pub const MAX_TRACKED_TRANSACTIONS: usize = 100_000;
+
+#[derive(Debug)]
+struct Transaction {
+    backend: String,
+    txn_number: i64,
+    started: Instant,
+}
+
+/// Open transactions of every client of a listener, by session
+#[derive(Debug)]
+pub struct TransactionPinning {
+    lifetime: Duration,
+    max_transactions: usize,
+    /// Transactions by client, then by raw `lsid`
+    transactions: Mutex<HashMap<ClientIdentifier, HashMap<Vec<u8>, Transaction>>>,
+}
+
+impl Default for TransactionPinning {
+    fn default() -> Self {
+        Self::new(TRANSACTION_LIFETIME, MAX_TRACKED_TRANSACTIONS)
+    }
+}
+
+impl TransactionPinning {
+    pub fn new(lifetime: Duration, max_transactions: usize) -> Self {
+        Self {
+            lifetime,
+            max_transactions,
+            transactions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Record transaction `txn_number` of session `lsid` started by
+    /// `client` on `backend`, replacing the session's previous one
+    pub fn started(&self, client: &ClientIdentifier, lsid: &[u8], txn_number: i64, backend: &str) {
+        let mut transactions = self.transactions.lock().unwrap();
+        let tracked: usize = transactions.values().map(HashMap::len).sum();
+        if tracked >= self.max_transactions {
+            self.remove_expired(&mut transactions);
+            if transactions.values().map(HashMap::len).sum::<usize>() >= self.max_transactions {
+                log::debug!("Not pinning transaction {txn_number} to mongos {backend}: {} transactions tracked", self.max_transactions);
+                return;
+            }
+        }
+        transactions.entry(client.clone()).or_default().insert(
+            lsid.to_vec(),
+            Transaction {
+                backend: backend.to_string(),
+                txn_number,
+                started: Instant::now(),
+            },
+        );
+    }
+
+    /// Forget transaction `txn_number` of session `lsid`, committed or
+    /// aborted by `client`
+    pub fn ended(&self, client: &ClientIdentifier, lsid: &[u8], txn_number: i64) {
+        let mut transactions = self.transactions.lock().unwrap();
+        let Some(sessions) = transactions.get_mut(client) else {
+            return;
+        };
+        if sessions.get(lsid).is_some_and(|transaction| transaction.txn_number == txn_number) {
+            sessions.remove(lsid);
+        }
+        if sessions.is_empty() {
+            transactions.remove(client);
+        }
+    }
+
+    /// The mongos of transaction `txn_number` of session `lsid`, if open
+    pub fn backend_of_transaction(&self, client: &ClientIdentifier, lsid: &[u8], txn_number: i64) -> Option<String> {
+        let transactions = self.transactions.lock().unwrap();
+        transactions
+            .get(client)?
+            .get(lsid)
+            .filter(|transaction| transaction.txn_number == txn_number && transaction.started.elapsed() < self.lifetime)
+            .map(|transaction| transaction.backend.clone())
+    }
+
+    /// The mongos of the most recently started open transaction of `client`
+    pub fn backend_of(&self, client: &ClientIdentifier) -> Option<String> {
+        let mut transactions = self.transactions.lock().unwrap();
+        let sessions = transactions.get_mut(client)?;
+        sessions.retain(|_, transaction| transaction.started.elapsed() < self.lifetime);
+        let backend = sessions
+            .values()
+            .max_by_key(|transaction| transaction.started)
+            .map(|transaction| transaction.backend.clone());
+        if sessions.is_empty() {
+            transactions.remove(client);
+        }
+        backend
+    }
+
+    /// Number of open transactions tracked
+    pub fn len(&self) -> usize {
+        self.transactions.lock().unwrap().values().map(HashMap::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn remove_expired(&self, transactions: &mut HashMap<ClientIdentifier, HashMap<Vec<u8>, Transaction>>) {
+        for sessions in transactions.values_mut() {
+            sessions.retain(|_, transaction| transaction.started.elapsed() < self.lifetime);
+        }
+        transactions.retain(|_, sessions| !sessions.is_empty());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn client(name: &str) -> ClientIdentifier {
+        ClientIdentifier::SessionId(name.to_string())
+    }
+
+    #[test]
+    fn test_client_pinned_while_transaction_open() {
+        let transactions = TransactionPinning::default();
+        assert_eq!(transactions.backend_of(&client("app")), None);
+
+        transactions.started(&client("app"), b"session-1", 1, "10.0.1.1:27017");
+        std::thread::sleep(Duration::from_millis(2));
+        transactions.started(&client("app"), b"session-2", 1, "10.0.1.2:27017");
+        assert_eq!(transactions.len(), 2);
+        // The most recently started transaction wins
+        assert_eq!(transactions.backend_of(&client("app")).as_deref(), Some("10.0.1.2:27017"));
+        assert_eq!(
+            transactions.backend_of_transaction(&client("app"), b"session-1", 1).as_deref(),
+            Some("10.0.1.1:27017")
+        );
+
+        // Ending an older transaction number of the session changes nothing
+        transactions.ended(&client("app"), b"session-2", 0);
+        assert_eq!(transactions.backend_of(&client("app")).as_deref(), Some("10.0.1.2:27017"));
+        transactions.ended(&client("app"), b"session-2", 1);
+        assert_eq!(transactions.backend_of(&client("app")).as_deref(), Some("10.0.1.1:27017"));
+        transactions.ended(&client("app"), b"session-1", 1);
+        assert_eq!(transactions.backend_of(&client("app")), None);
+        assert!(transactions.is_empty());
+    }
+
+    #[test]
+    fn test_next_transaction_of_session_replaces_previous() {
+        let transactions = TransactionPinning::default();
+        transactions.started(&client("app"), b"session-1", 1, "10.0.1.1:27017");
+        transactions.started(&client("app"), b"session-1", 2, "10.0.1.2:27017");
+        assert_eq!(transactions.len(), 1);
+        assert_eq!(transactions.backend_of_transaction(&client("app"), b"session-1", 1), None);
+        assert_eq!(transactions.backend_of(&client("app")).as_deref(), Some("10.0.1.2:27017"));
+    }
+
+    #[test]
+    fn test_transactions_expire() {
+        let transactions = TransactionPinning::new(Duration::from_millis(20), 1);
+        transactions.started(&client("app"), b"session-1", 1, "10.0.1.1:27017");
+        // Full: a new transaction is not pinned until others expire
+        transactions.started(&client("batch"), b"session-2", 1, "10.0.1.2:27017");
+        assert_eq!(transactions.backend_of(&client("batch")), None);
+
+        std::thread::sleep(Duration::from_millis(30));
+        assert_eq!(transactions.backend_of_transaction(&client("app"), b"session-1", 1), None);
+        transactions.started(&client("batch"), b"session-2", 1, "10.0.1.2:27017");
+        assert_eq!(transactions.len(), 1);
+        assert_eq!(transactions.backend_of(&client("batch")).as_deref(), Some("10.0.1.2:27017"));
+
+        std::thread::sleep(Duration::from_millis(30));
+        assert_eq!(transactions.backend_of(&client("batch")), None);
+        assert!(transactions.is_empty());
+    }
+}
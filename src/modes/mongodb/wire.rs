@@ -0,0 +1,628 @@
+/// MongoDB wire protocol message framing
+///
+/// Every MongoDB message starts with a 16-byte header whose first field is
+/// the total message length (little-endian i32, header included). The framer
+/// buffers stream data and yields complete messages, which lets the proxy
+/// count operations and reject oversized messages without parsing bodies.
+use super::bson::{Document, DocumentBuilder, Value};
+use crate::config::ReadPreferenceMode;
+use bytes::{Bytes, BytesMut};
+
+/// Size of the standard message header
+pub const HEADER_LEN: usize = 16;
+
+/// Default maximum message size, matching mongod's `maxMessageSizeBytes`
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 48_000_000;
+
+/// OP_MSG flag bit announcing a trailing CRC-32C checksum
+pub const CHECKSUM_PRESENT: u32 = 1;
+
+/// MongoDB wire protocol opcodes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpCode {
+    Reply,
+    Update,
+    Insert,
+    Query,
+    GetMore,
+    Delete,
+    KillCursors,
+    Compressed,
+    Msg,
+    Unknown(i32),
+}
+
+impl From<i32> for OpCode {
+    fn from(code: i32) -> Self {
+        match code {
+            1 => OpCode::Reply,
+            2001 => OpCode::Update,
+            2002 => OpCode::Insert,
+            2004 => OpCode::Query,
+            2005 => OpCode::GetMore,
+            2006 => OpCode::Delete,
+            2007 => OpCode::KillCursors,
+            2012 => OpCode::Compressed,
+            2013 => OpCode::Msg,
+            other => OpCode::Unknown(other),
+        }
+    }
+}
+
+/// Standard message header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MsgHeader {
+    pub message_length: i32,
+    pub request_id: i32,
+    pub response_to: i32,
+    pub op_code: OpCode,
+}
+
+impl MsgHeader {
+    /// Parse a header from the start of `data`
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        if data.len() < HEADER_LEN {
+            return None;
+        }
+
+        let field = |offset: usize| {
+            i32::from_le_bytes([
+                data[offset],
+                data[offset + 1],
+                data[offset + 2],
+                data[offset + 3],
+            ])
+        };
+
+        Some(Self {
+            message_length: field(0),
+            request_id: field(4),
+            response_to: field(8),
+            op_code: OpCode::from(field(12)),
+        })
+    }
+}
+
+/// Framing errors
+#[derive(Debug, thiserror::Error, PartialEq)]
+pub enum WireError {
+    #[error("Invalid message length: {0}")]
+    InvalidLength(i32),
+    #[error("Message of {size} bytes exceeds the maximum of {max} bytes")]
+    MessageTooLarge { size: usize, max: usize },
+}
+
+/// Incremental framer that splits a byte stream into complete messages
+pub struct MessageFramer {
+    buf: BytesMut,
+    max_message_size: usize,
+}
+
+impl MessageFramer {
+    pub fn new(max_message_size: usize) -> Self {
+        Self {
+            buf: BytesMut::with_capacity(8192),
+            max_message_size,
+        }
+    }
+
+    /// Append data read from the stream
+    pub fn push(&mut self, data: &[u8]) {
+        self.buf.extend_from_slice(data);
+    }
+
+    /// Number of buffered bytes not yet returned as a message
+    pub fn buffered(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Header of the next message, complete or not
+    pub fn peek_header(&self) -> Option<MsgHeader> {
+        MsgHeader::parse(&self.buf)
+    }
+
+    /// Take the next complete message, if one is buffered
+    pub fn next_message(&mut self) -> Result<Option<(MsgHeader, Bytes)>, WireError> {
+        let header = match MsgHeader::parse(&self.buf) {
+            Some(header) => header,
+            None => return Ok(None),
+        };
+
+        if header.message_length < HEADER_LEN as i32 {
+            return Err(WireError::InvalidLength(header.message_length));
+        }
+
+        let size = header.message_length as usize;
+        if size > self.max_message_size {
+            return Err(WireError::MessageTooLarge {
+                size,
+                max: self.max_message_size,
+            });
+        }
+
+        if self.buf.len() < size {
+            // Reserve up front so large messages don't reallocate repeatedly
+            self.buf.reserve(size - self.buf.len());
+            return Ok(None);
+        }
+
+        Ok(Some((header, self.buf.split_to(size).freeze())))
+    }
+}
+
+/// Body document of a complete OP_MSG message
+///
+/// The body is the single kind 0 section; kind 1 document sequences around
+/// it are skipped.
+pub fn op_msg_body(message: &[u8]) -> Option<Document<'_>> {
+    let header = MsgHeader::parse(message)?;
+    if header.op_code != OpCode::Msg {
+        return None;
+    }
+    let end = (header.message_length as usize).min(message.len());
+    // Sections follow the flag bits
+    let mut offset = HEADER_LEN + 4;
+    while offset < end {
+        match message[offset] {
+            0 => return Document::from_bytes(message.get(offset + 1..end)?),
+            1 => {
+                let size = i32::from_le_bytes(message.get(offset + 1..offset + 5)?.try_into().ok()?);
+                if size < 4 {
+                    return None;
+                }
+                offset += 1 + size as usize;
+            }
+            _ => return None,
+        }
+    }
+    None
+}
+
+/// A complete OP_MSG message with its body section replaced by `body`
+///
+/// Document sequences are kept. A checksum would no longer match and is
+/// optional, so it is dropped.
+pub fn with_op_msg_body(message: &[u8], body: &[u8]) -> Option<Bytes> {
+    let header = MsgHeader::parse(message)?;
+    if header.op_code != OpCode::Msg {
+        return None;
+    }
+    let end = (header.message_length as usize).min(message.len());
+    let mut flags = u32::from_le_bytes(message.get(HEADER_LEN..HEADER_LEN + 4)?.try_into().ok()?);
+    let mut sections_end = end;
+    if flags & CHECKSUM_PRESENT != 0 {
+        flags &= !CHECKSUM_PRESENT;
+        sections_end = end.checked_sub(4)?;
+    }
+
+    let mut sections = Vec::with_capacity(end);
+    let mut offset = HEADER_LEN + 4;
+    while offset < sections_end {
+        let size = match message[offset] {
+            0 => {
+                sections.push(0);
+                sections.extend_from_slice(body);
+                Document::from_bytes(message.get(offset + 1..sections_end)?)?.as_bytes().len()
+            }
+            _ => {
+                let size = i32::from_le_bytes(message.get(offset + 1..offset + 5)?.try_into().ok()?);
+                let size = usize::try_from(size).ok().filter(|&size| size >= 4)?;
+                sections.extend_from_slice(message.get(offset..offset + 1 + size)?);
+                size
+            }
+        };
+        offset += 1 + size;
+    }
+
+    let mut rewritten = ((HEADER_LEN + 4 + sections.len()) as i32).to_le_bytes().to_vec();
+    rewritten.extend_from_slice(&header.request_id.to_le_bytes());
+    rewritten.extend_from_slice(&header.response_to.to_le_bytes());
+    rewritten.extend_from_slice(&2013i32.to_le_bytes());
+    rewritten.extend_from_slice(&flags.to_le_bytes());
+    rewritten.extend_from_slice(&sections);
+    Some(Bytes::from(rewritten))
+}
+
+/// OP_MSG reply failing request `response_to` with server error `code`
+pub fn error_reply(response_to: i32, code: i32, code_name: &str, errmsg: &str) -> Bytes {
+    let body = DocumentBuilder::new()
+        .double("ok", 0.0)
+        .string("errmsg", errmsg)
+        .int32("code", code)
+        .string("codeName", code_name)
+        .build();
+    let mut message = ((HEADER_LEN + 5 + body.len()) as i32).to_le_bytes().to_vec();
+    // Request id, response to and OP_MSG opcode
+    message.extend_from_slice(&0i32.to_le_bytes());
+    message.extend_from_slice(&response_to.to_le_bytes());
+    message.extend_from_slice(&2013i32.to_le_bytes());
+    // No flag bits, then the body section
+    message.extend_from_slice(&[0; 5]);
+    message.extend_from_slice(&body);
+    Bytes::from(message)
+}
+
+/// Mode of the `$readPreference` an OP_MSG command carries, if any
+pub fn read_preference(message: &[u8]) -> Option<ReadPreferenceMode> {
+    op_msg_body(message)?
+        .get_document("$readPreference")?
+        .get_str("mode")?
+        .parse()
+        .ok()
+}
+
+/// Whether an OP_MSG sets `moreToCome`: no reply follows a request that
+/// sets it, and another reply follows a reply that sets it
+pub fn more_to_come(message: &[u8]) -> bool {
+    match MsgHeader::parse(message) {
+        Some(header) if header.op_code == OpCode::Msg => message
+            .get(HEADER_LEN..HEADER_LEN + 4)
+            .is_some_and(|flags| flags[0] & 0b10 != 0),
+        _ => false,
+    }
+}
+
+/// What an OP_MSG command runs and on which collection, without its body
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Operation {
+    pub command: String,
+    pub database: String,
+    /// Absent for commands not naming a collection, e.g. `ping`
+    pub collection: Option<String>,
+}
+
+impl Operation {
+    /// `database.collection`, or just the database
+    pub fn namespace(&self) -> String {
+        match &self.collection {
+            Some(collection) => format!("{}.{collection}", self.database),
+            None => self.database.clone(),
+        }
+    }
+}
+
+/// Command name, database and collection of an OP_MSG command
+pub fn operation(message: &[u8]) -> Option<Operation> {
+    let body = op_msg_body(message)?;
+    let command = body.first_key()?;
+    let collection = match command {
+        "getMore" => body.get_str("collection"),
+        _ => body.get_str(command),
+    };
+    Some(Operation {
+        command: command.to_string(),
+        database: body.get_str("$db").unwrap_or_default().to_string(),
+        collection: collection.map(str::to_string),
+    })
+}
+
+/// Id of the cursor an OP_MSG reply returns in `cursor.id`; 0 once the
+/// cursor is exhausted
+pub fn cursor_id(message: &[u8]) -> Option<i64> {
+    match op_msg_body(message)?.get_document("cursor")?.get("id")? {
+        Value::Int64(id) => Some(id),
+        _ => None,
+    }
+}
+
+/// Cursor an OP_MSG `getMore` command reads from
+pub fn get_more_cursor(message: &[u8]) -> Option<i64> {
+    match op_msg_body(message)?.get("getMore")? {
+        Value::Int64(id) => Some(id),
+        _ => None,
+    }
+}
+
+/// Cursors an OP_MSG `killCursors` command closes
+pub fn killed_cursors(message: &[u8]) -> Vec<i64> {
+    let Some(body) = op_msg_body(message).filter(|body| body.first_key() == Some("killCursors")) else {
+        return Vec::new();
+    };
+    body.get_array("cursors")
+        .map(|cursors| {
+            cursors
+                .iter()
+                .filter_map(|(_, id)| match id {
+                    Value::Int64(id) => Some(id),
+                    _ => None,
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Where an OP_MSG command stands in a multi-document transaction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransactionStep {
+    /// First statement, with `startTransaction: true`
+    Start,
+    /// Any later statement
+    Continue,
+    Commit,
+    Abort,
+}
+
+/// A statement of a multi-document transaction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Transaction {
+    /// Raw `lsid` document of the session running the transaction
+    pub lsid: Vec<u8>,
+    pub txn_number: i64,
+    pub step: TransactionStep,
+}
+
+/// Transaction an OP_MSG command belongs to: it carries a session, a
+/// `txnNumber` and `autocommit: false`
+pub fn transaction(message: &[u8]) -> Option<Transaction> {
+    let body = op_msg_body(message)?;
+    if body.get("autocommit") != Some(Value::Boolean(false)) {
+        return None;
+    }
+    let Value::Int64(txn_number) = body.get("txnNumber")? else {
+        return None;
+    };
+    let lsid = body.get_document("lsid")?.as_bytes().to_vec();
+    let step = match body.first_key()? {
+        "commitTransaction" => TransactionStep::Commit,
+        "abortTransaction" => TransactionStep::Abort,
+        _ if body.get("startTransaction") == Some(Value::Boolean(true)) => TransactionStep::Start,
+        _ => TransactionStep::Continue,
+    };
+    Some(Transaction { lsid, txn_number, step })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modes::mongodb::bson::{DocumentBuilder, Value};
+    use crate::modes::mongodb::test_support::message;
+
+    #[test]
+    fn test_parse_header() {
+        let data = message(42, 0, 2013, b"body");
+        let header = MsgHeader::parse(&data).unwrap();
+        assert_eq!(header.message_length, 20);
+        assert_eq!(header.request_id, 42);
+        assert_eq!(header.response_to, 0);
+        assert_eq!(header.op_code, OpCode::Msg);
+
+        assert!(MsgHeader::parse(&data[..10]).is_none());
+    }
+
+    #[test]
+    fn test_framer_split_messages() {
+        let mut data = message(1, 0, 2013, b"first");
+        data.extend(message(2, 0, 2004, b"second"));
+
+        let mut framer = MessageFramer::new(DEFAULT_MAX_MESSAGE_SIZE);
+
+        // Feed a partial first message
+        framer.push(&data[..10]);
+        assert!(framer.next_message().unwrap().is_none());
+
+        framer.push(&data[10..]);
+        let (header, msg) = framer.next_message().unwrap().unwrap();
+        assert_eq!(header.request_id, 1);
+        assert_eq!(msg.len(), HEADER_LEN + 5);
+
+        let (header, _) = framer.next_message().unwrap().unwrap();
+        assert_eq!(header.op_code, OpCode::Query);
+        assert!(framer.next_message().unwrap().is_none());
+        assert_eq!(framer.buffered(), 0);
+    }
+
+    #[test]
+    fn test_framer_rejects_oversized_message() {
+        let mut framer = MessageFramer::new(32);
+        framer.push(&message(1, 0, 2013, &[0u8; 64]));
+
+        assert_eq!(
+            framer.next_message().unwrap_err(),
+            WireError::MessageTooLarge { size: 80, max: 32 }
+        );
+        // The header stays readable to answer the request
+        assert_eq!(framer.peek_header().unwrap().request_id, 1);
+    }
+
+    #[test]
+    fn test_error_reply() {
+        let reply = error_reply(7, 10334, "BSONObjectTooLarge", "too large");
+        let header = MsgHeader::parse(&reply).unwrap();
+        assert_eq!(header.message_length as usize, reply.len());
+        assert_eq!(header.response_to, 7);
+        assert_eq!(header.op_code, OpCode::Msg);
+        assert!(!more_to_come(&reply));
+
+        let body = op_msg_body(&reply).unwrap();
+        assert!(matches!(body.get("ok"), Some(Value::Double(ok)) if ok == 0.0));
+        assert!(matches!(body.get("code"), Some(Value::Int32(10334))));
+        assert_eq!(body.get_str("codeName"), Some("BSONObjectTooLarge"));
+        assert_eq!(body.get_str("errmsg"), Some("too large"));
+    }
+
+    #[test]
+    fn test_framer_rejects_invalid_length() {
+        let mut framer = MessageFramer::new(DEFAULT_MAX_MESSAGE_SIZE);
+        let mut data = message(1, 0, 2013, b"");
+        data[..4].copy_from_slice(&4i32.to_le_bytes());
+        framer.push(&data);
+
+        assert_eq!(framer.next_message().unwrap_err(), WireError::InvalidLength(4));
+    }
+
+    fn op_msg(sections: &[u8]) -> Vec<u8> {
+        let mut body = 0u32.to_le_bytes().to_vec();
+        body.extend_from_slice(sections);
+        message(1, 0, 2013, &body)
+    }
+
+    #[test]
+    fn test_op_msg_read_preference() {
+        let command = DocumentBuilder::new()
+            .string("find", "orders")
+            .string("$db", "shop")
+            .document(
+                "$readPreference",
+                DocumentBuilder::new().string("mode", "secondaryPreferred").build(),
+            )
+            .build();
+
+        // A document sequence before the body is skipped
+        let sequence = DocumentBuilder::new().int32("_id", 1).build();
+        let mut sections = vec![1u8];
+        sections.extend_from_slice(&((4 + 10 + sequence.len()) as i32).to_le_bytes());
+        sections.extend_from_slice(b"documents\0");
+        sections.extend_from_slice(&sequence);
+        sections.push(0);
+        sections.extend_from_slice(&command);
+
+        let data = op_msg(&sections);
+        assert_eq!(op_msg_body(&data).unwrap().first_key(), Some("find"));
+        assert_eq!(read_preference(&data), Some(ReadPreferenceMode::SecondaryPreferred));
+
+        let mut plain = vec![0u8];
+        plain.extend_from_slice(&DocumentBuilder::new().int32("ping", 1).build());
+        assert_eq!(read_preference(&op_msg(&plain)), None);
+
+        // Legacy opcodes and truncated sections are ignored
+        assert!(op_msg_body(&message(1, 0, 2004, &command)).is_none());
+        assert!(op_msg_body(&op_msg(&sections[..8])).is_none());
+    }
+
+    #[test]
+    fn test_op_msg_operation() {
+        let body = |command: Vec<u8>| {
+            let mut section = vec![0u8];
+            section.extend_from_slice(&command);
+            op_msg(&section)
+        };
+        let find = body(
+            DocumentBuilder::new()
+                .string("find", "orders")
+                .string("$db", "shop")
+                .build(),
+        );
+        let find = operation(&find).unwrap();
+        assert_eq!(find.command, "find");
+        assert_eq!(find.database, "shop");
+        assert_eq!(find.collection.as_deref(), Some("orders"));
+        assert_eq!(find.namespace(), "shop.orders");
+
+        let get_more = body(
+            DocumentBuilder::new()
+                .int32("getMore", 7)
+                .string("collection", "orders")
+                .string("$db", "shop")
+                .build(),
+        );
+        let get_more = operation(&get_more).unwrap();
+        assert_eq!(get_more.command, "getMore");
+        assert_eq!(get_more.namespace(), "shop.orders");
+
+        let ping = body(DocumentBuilder::new().int32("ping", 1).string("$db", "admin").build());
+        let ping_operation = operation(&ping).unwrap();
+        assert_eq!(ping_operation.collection, None);
+        assert_eq!(ping_operation.namespace(), "admin");
+        assert!(!more_to_come(&ping));
+
+        let mut exhaust = ping.clone();
+        exhaust[HEADER_LEN] = 0b10;
+        assert!(more_to_come(&exhaust));
+        assert_eq!(operation(&message(1, 0, 2004, b"query")), None);
+    }
+
+    #[test]
+    fn test_cursor_ids() {
+        let body = |command: Vec<u8>| {
+            let mut section = vec![0u8];
+            section.extend_from_slice(&command);
+            op_msg(&section)
+        };
+        let reply = |id: i64| {
+            body(
+                DocumentBuilder::new()
+                    .document("cursor", DocumentBuilder::new().int64("id", id).string("ns", "shop.orders").build())
+                    .double("ok", 1.0)
+                    .build(),
+            )
+        };
+        assert_eq!(cursor_id(&reply(8_000_000_001)), Some(8_000_000_001));
+        assert_eq!(cursor_id(&reply(0)), Some(0));
+        assert_eq!(cursor_id(&body(DocumentBuilder::new().double("ok", 1.0).build())), None);
+
+        let get_more = body(
+            DocumentBuilder::new()
+                .int64("getMore", 42)
+                .string("collection", "orders")
+                .string("$db", "shop")
+                .build(),
+        );
+        assert_eq!(get_more_cursor(&get_more), Some(42));
+        assert!(killed_cursors(&get_more).is_empty());
+
+        let cursors = DocumentBuilder::new().int64("0", 42).int64("1", 43).build();
+        let kill = body(
+            DocumentBuilder::new()
+                .string("killCursors", "orders")
+                .array("cursors", cursors)
+                .string("$db", "shop")
+                .build(),
+        );
+        assert_eq!(killed_cursors(&kill), vec![42, 43]);
+        assert_eq!(get_more_cursor(&kill), None);
+    }
+
+    #[test]
+    fn test_transaction_steps() {
+        let body = |command: Vec<u8>| {
+            let mut section = vec![0u8];
+            section.extend_from_slice(&command);
+            op_msg(&section)
+        };
+        // A UUID binary: length, subtype 4, then the 16 bytes
+        let mut uuid = vec![16, 0, 0, 0, 4];
+        uuid.extend_from_slice(&[7; 16]);
+        let lsid = DocumentBuilder::new().element(5, "id", &uuid).build();
+        let statement = |command: DocumentBuilder| {
+            body(
+                command
+                    .document("lsid", lsid.clone())
+                    .int64("txnNumber", 3)
+                    .boolean("autocommit", false)
+                    .string("$db", "shop")
+                    .build(),
+            )
+        };
+
+        let start = transaction(&statement(
+            DocumentBuilder::new().string("insert", "orders").boolean("startTransaction", true),
+        ))
+        .unwrap();
+        assert_eq!(start.step, TransactionStep::Start);
+        assert_eq!(start.txn_number, 3);
+        assert_eq!(start.lsid, lsid);
+        let update = transaction(&statement(DocumentBuilder::new().string("update", "orders"))).unwrap();
+        assert_eq!(update.step, TransactionStep::Continue);
+        let commit = transaction(&statement(DocumentBuilder::new().int32("commitTransaction", 1))).unwrap();
+        assert_eq!(commit.step, TransactionStep::Commit);
+        let abort = transaction(&statement(DocumentBuilder::new().int32("abortTransaction", 1))).unwrap();
+        assert_eq!(abort.step, TransactionStep::Abort);
+
+        // Retryable writes carry a txnNumber but no autocommit
+        let retryable = body(
+            DocumentBuilder::new()
+                .string("insert", "orders")
+                .document("lsid", lsid.clone())
+                .int64("txnNumber", 4)
+                .string("$db", "shop")
+                .build(),
+        );
+        assert_eq!(transaction(&retryable), None);
+    }
+
+    #[test]
+    fn test_opcode_unknown() {
+        assert_eq!(OpCode::from(9999), OpCode::Unknown(9999));
+        assert_eq!(OpCode::from(1), OpCode::Reply);
+    }
+}
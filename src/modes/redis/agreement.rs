@@ -0,0 +1,110 @@
+/// Agreement between cluster nodes on the topology
+///
+/// A node partitioned from the rest of the cluster keeps answering
+/// `CLUSTER NODES` with the slot map it last knew. Unless
+/// `topology_agreement` is `first`, every known node is asked and their
+/// answers are grouped into views assigning the same slots to the same
+/// masters. A view is then applied by its config epoch, which Redis bumps
+/// on every failover and slot move, or once enough nodes answer it.
+use super::slots::SlotMapping;
+use crate::config::TopologyAgreement;
+
+/// A slot map and the nodes answering it
+#[derive(Debug, Clone)]
+pub struct TopologyView {
+    pub mapping: SlotMapping,
+    /// Addresses of the nodes answering the view, in the order they were
+    /// asked
+    pub nodes: Vec<String>,
+}
+
+/// Group the slot maps answered by each node into views
+///
+/// A view keeps the first answer, replicas included, of its nodes.
+pub fn group_views(answers: Vec<(String, SlotMapping)>) -> Vec<TopologyView> {
+    let mut views: Vec<TopologyView> = Vec::new();
+    for (node, mapping) in answers {
+        match views.iter_mut().find(|view| view.mapping.same_slots(&mapping)) {
+            Some(view) => view.nodes.push(node),
+            None => views.push(TopologyView {
+                mapping,
+                nodes: vec![node],
+            }),
+        }
+    }
+    views
+}
+
+/// The view to apply under `agreement`, or why none may be
+pub fn choose_view(views: Vec<TopologyView>, agreement: TopologyAgreement) -> Result<TopologyView, String> {
+    let quorum = match agreement {
+        TopologyAgreement::First => return views.into_iter().next().ok_or_else(|| "no node answered".to_string()),
+        TopologyAgreement::HighestEpoch => 1,
+        TopologyAgreement::Quorum(quorum) => quorum,
+    };
+    let answered = views.iter().map(|view| view.nodes.len()).max().unwrap_or(0);
+    // Among views of the same epoch, the one most nodes answer
+    views
+        .into_iter()
+        .filter(|view| view.nodes.len() >= quorum)
+        .max_by_key(|view| (view.mapping.config_epoch(), view.nodes.len()))
+        .ok_or_else(|| format!("no slot map answered by {quorum} nodes, at most {answered} agree"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `CLUSTER NODES` of a three-master cluster at `epoch`, with 7003's
+    /// slots on `last_master`
+    fn answer(last_master: &str, epoch: u64) -> SlotMapping {
+        SlotMapping::from_cluster_nodes(&format!(
+            "\
+a 127.0.0.1:7001@17001 master - 0 0 1 connected 0-5460
+b 127.0.0.1:7002@17002 master - 0 0 2 connected 5461-10922
+c {last_master}@17003 master - 0 0 {epoch} connected 10923-16383
+"
+        ))
+    }
+
+    fn answers(entries: &[(&str, &str, u64)]) -> Vec<TopologyView> {
+        group_views(
+            entries
+                .iter()
+                .map(|(node, last_master, epoch)| (node.to_string(), answer(last_master, *epoch)))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_group_views() {
+        let views = answers(&[
+            ("127.0.0.1:7001", "127.0.0.1:7003", 3),
+            ("127.0.0.1:7002", "127.0.0.1:7004", 4),
+            ("127.0.0.1:7004", "127.0.0.1:7004", 4),
+        ]);
+        assert_eq!(views.len(), 2);
+        assert_eq!(views[0].nodes, ["127.0.0.1:7001"]);
+        assert_eq!(views[1].nodes, ["127.0.0.1:7002", "127.0.0.1:7004"]);
+    }
+
+    #[test]
+    fn test_choose_view() {
+        // A partitioned node still serving the slots failed over from it
+        let stale = ("127.0.0.1:7003", "127.0.0.1:7003", 3);
+        let current = [("127.0.0.1:7001", "127.0.0.1:7004", 4), ("127.0.0.1:7002", "127.0.0.1:7004", 4)];
+
+        let chosen = choose_view(answers(&[stale, current[0]]), TopologyAgreement::First).unwrap();
+        assert_eq!(chosen.nodes, ["127.0.0.1:7003"]);
+
+        let chosen = choose_view(answers(&[stale, current[0]]), TopologyAgreement::HighestEpoch).unwrap();
+        assert_eq!(chosen.mapping.get_backend_for_slot(16383), Some("127.0.0.1:7004".to_string()));
+
+        let chosen = choose_view(answers(&[stale, current[0], current[1]]), TopologyAgreement::Quorum(2)).unwrap();
+        assert_eq!(chosen.nodes, ["127.0.0.1:7001", "127.0.0.1:7002"]);
+
+        // Too few nodes agreeing keeps the current mapping
+        assert!(choose_view(answers(&[stale, current[0]]), TopologyAgreement::Quorum(2)).is_err());
+        assert!(choose_view(Vec::new(), TopologyAgreement::HighestEpoch).is_err());
+    }
+}
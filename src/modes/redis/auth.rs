@@ -0,0 +1,328 @@
+/// Redis authentication for upstream connections and proxy clients
+///
+/// Upstream connections are authenticated with `AUTH` as soon as they are
+/// opened, so pooled connections shared between clients all run as the
+/// configured user. Client `AUTH` and `HELLO` commands are answered by the
+/// proxy itself and never reach the cluster.
+use super::pool::send_expect_ok;
+use super::resp::{RespEncoder, RespParser, RespValue};
+use super::RedisCommand;
+use crate::config::RedisAuthConfig;
+use bytes::Bytes;
+use std::error::Error;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+/// Reply sent to clients that have not authenticated yet
+pub const NOAUTH_REPLY: &[u8] = b"-NOAUTH Authentication required.\r\n";
+
+/// Reply sent when client credentials do not match
+pub const WRONGPASS_REPLY: &[u8] =
+    b"-WRONGPASS invalid username-password pair or user is disabled.\r\n";
+
+/// Encode the `AUTH` command for the given credentials
+pub fn auth_command(auth: &RedisAuthConfig) -> Bytes {
+    let mut args = Vec::with_capacity(2);
+    if let Some(username) = &auth.username {
+        args.push(username.as_str());
+    }
+    args.push(auth.password.as_str());
+    RespEncoder::encode(&RespEncoder::create_command("AUTH", &args))
+}
+
+/// Authenticate a freshly opened upstream connection
+pub async fn authenticate<S>(
+    stream: &mut S,
+    auth: &RedisAuthConfig,
+) -> Result<(), Box<dyn Error + Send + Sync>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    send_expect_ok(stream, &auth_command(auth))
+        .await
+        .map_err(|e| format!("Upstream authentication failed: {e}").into())
+}
+
+/// Whether a command frame carries credentials: `AUTH`, `HELLO ... AUTH`,
+/// `MIGRATE ... AUTH`/`AUTH2`, `ACL SETUSER` or `CONFIG SET`
+///
+/// Frames that are not an array of bulk strings are assumed to carry some.
+pub fn carries_credentials(frame: &Bytes) -> bool {
+    let Some(parts) = RespParser::command_parts(frame) else {
+        return true;
+    };
+    let Some((name, args)) = parts.split_first() else {
+        return true;
+    };
+    let has_arg = |arg: &[u8]| args.iter().any(|a| a.eq_ignore_ascii_case(arg));
+    let first_arg = |arg: &[u8]| args.first().is_some_and(|a| a.eq_ignore_ascii_case(arg));
+    match name.to_ascii_uppercase().as_slice() {
+        b"AUTH" => true,
+        b"HELLO" | b"MIGRATE" => has_arg(b"AUTH") || has_arg(b"AUTH2"),
+        b"ACL" => first_arg(b"SETUSER"),
+        b"CONFIG" => first_arg(b"SET"),
+        _ => false,
+    }
+}
+
+/// Per-connection client authentication state
+#[derive(Debug)]
+pub struct ClientAuth<'a> {
+    required: Option<&'a RedisAuthConfig>,
+    authenticated: bool,
+}
+
+impl<'a> ClientAuth<'a> {
+    pub fn new(required: Option<&'a RedisAuthConfig>) -> Self {
+        Self {
+            required,
+            authenticated: required.is_none(),
+        }
+    }
+
+    /// Whether the client may run regular commands
+    pub fn is_authenticated(&self) -> bool {
+        self.authenticated
+    }
+
+    /// Answer `AUTH`/`HELLO` locally and block other commands until the
+    /// client has authenticated
+    ///
+    /// Returns the reply to send when the proxy handles the command itself,
+    /// or `None` when the command should be forwarded to the cluster.
+    pub fn intercept(&mut self, command: &RedisCommand) -> Option<Bytes> {
+        match command.command.as_str() {
+            "AUTH" => Some(self.handle_auth(&command.args)),
+            "HELLO" => Some(self.handle_hello(&command.args)),
+            _ if !self.authenticated => Some(Bytes::from_static(NOAUTH_REPLY)),
+            _ => None,
+        }
+    }
+
+    fn handle_auth(&mut self, args: &[Bytes]) -> Bytes {
+        let (username, password) = match args {
+            [password] => (None, password),
+            [username, password] => (Some(username), password),
+            _ => {
+                return Bytes::from_static(
+                    b"-ERR wrong number of arguments for 'auth' command\r\n",
+                )
+            }
+        };
+
+        if self.check(username, password) {
+            Bytes::from_static(b"+OK\r\n")
+        } else {
+            Bytes::from_static(WRONGPASS_REPLY)
+        }
+    }
+
+    /// `HELLO [protover [AUTH username password] [SETNAME clientname]]`
+    ///
+    /// Only RESP2 is spoken towards the cluster, so other protocol versions
+    /// are refused.
+    fn handle_hello(&mut self, args: &[Bytes]) -> Bytes {
+        if let Some(protover) = args.first() {
+            if protover.as_ref() != b"2" {
+                return Bytes::from_static(b"-NOPROTO unsupported protocol version\r\n");
+            }
+        }
+
+        let mut i = 1;
+        while i < args.len() {
+            if args[i].eq_ignore_ascii_case(b"AUTH") {
+                if i + 2 >= args.len() {
+                    return Bytes::from_static(b"-ERR Syntax error in HELLO option 'auth'\r\n");
+                }
+                if !self.check(Some(&args[i + 1]), &args[i + 2]) {
+                    return Bytes::from_static(WRONGPASS_REPLY);
+                }
+                i += 3;
+            } else {
+                // SETNAME and its value are accepted and ignored
+                i += 2;
+            }
+        }
+
+        if !self.authenticated {
+            return Bytes::from_static(NOAUTH_REPLY);
+        }
+
+        RespEncoder::encode(&RespValue::Array(Some(vec![
+            RespValue::BulkString(Some(Bytes::from_static(b"server"))),
+            RespValue::BulkString(Some(Bytes::from_static(b"puerta"))),
+            RespValue::BulkString(Some(Bytes::from_static(b"version"))),
+            RespValue::BulkString(Some(Bytes::from_static(env!("CARGO_PKG_VERSION").as_bytes()))),
+            RespValue::BulkString(Some(Bytes::from_static(b"proto"))),
+            RespValue::Integer(2),
+            RespValue::BulkString(Some(Bytes::from_static(b"mode"))),
+            RespValue::BulkString(Some(Bytes::from_static(b"cluster"))),
+        ])))
+    }
+
+    /// Verify client credentials, recording success
+    ///
+    /// Without configured client credentials any `AUTH` is accepted: the
+    /// proxy authenticates to the cluster on the client's behalf.
+    fn check(&mut self, username: Option<&Bytes>, password: &Bytes) -> bool {
+        let Some(required) = self.required else {
+            return true;
+        };
+
+        let expected_user = required.username.as_deref().unwrap_or("default");
+        let user_matches = match username {
+            Some(username) => username.as_ref() == expected_user.as_bytes(),
+            None => required.username.is_none(),
+        };
+
+        // Compared in constant time so response times do not leak the password
+        let password_matches = crate::utils::constant_time_eq(password, required.password.as_bytes());
+        if user_matches && password_matches {
+            self.authenticated = true;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modes::redis::test_support::command;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    fn credentials(username: Option<&str>) -> RedisAuthConfig {
+        RedisAuthConfig {
+            username: username.map(str::to_string),
+            password: "secret".to_string(),
+            password_file: None,
+        }
+    }
+
+    #[test]
+    fn test_auth_command_encoding() {
+        assert_eq!(
+            auth_command(&credentials(None)).as_ref(),
+            b"*2\r\n$4\r\nAUTH\r\n$6\r\nsecret\r\n"
+        );
+        assert_eq!(
+            auth_command(&credentials(Some("proxy"))).as_ref(),
+            b"*3\r\n$4\r\nAUTH\r\n$5\r\nproxy\r\n$6\r\nsecret\r\n"
+        );
+    }
+
+    #[test]
+    fn test_client_auth_required() {
+        let required = credentials(None);
+        let mut auth = ClientAuth::new(Some(&required));
+
+        assert_eq!(
+            auth.intercept(&command("GET", &["key"])).unwrap().as_ref(),
+            NOAUTH_REPLY
+        );
+        assert_eq!(
+            auth.intercept(&command("AUTH", &["wrong"])).unwrap().as_ref(),
+            WRONGPASS_REPLY
+        );
+        assert!(!auth.is_authenticated());
+
+        assert_eq!(
+            auth.intercept(&command("AUTH", &["secret"])).unwrap().as_ref(),
+            b"+OK\r\n"
+        );
+        assert!(auth.is_authenticated());
+        assert!(auth.intercept(&command("GET", &["key"])).is_none());
+    }
+
+    #[test]
+    fn test_client_auth_with_username() {
+        let required = credentials(Some("app"));
+        let mut auth = ClientAuth::new(Some(&required));
+
+        // Password-only AUTH targets the default user
+        auth.intercept(&command("AUTH", &["secret"]));
+        assert!(!auth.is_authenticated());
+
+        auth.intercept(&command("AUTH", &["app", "secret"]));
+        assert!(auth.is_authenticated());
+    }
+
+    #[test]
+    fn test_client_auth_not_required() {
+        let mut auth = ClientAuth::new(None);
+        assert!(auth.is_authenticated());
+        assert!(auth.intercept(&command("GET", &["key"])).is_none());
+        assert_eq!(
+            auth.intercept(&command("AUTH", &["anything"])).unwrap().as_ref(),
+            b"+OK\r\n"
+        );
+    }
+
+    #[test]
+    fn test_hello_auth() {
+        let required = credentials(None);
+        let mut auth = ClientAuth::new(Some(&required));
+
+        let reply = auth.intercept(&command("HELLO", &["3"])).unwrap();
+        assert!(reply.starts_with(b"-NOPROTO"));
+
+        let reply = auth.intercept(&command("HELLO", &["2"])).unwrap();
+        assert_eq!(reply.as_ref(), NOAUTH_REPLY);
+
+        let reply = auth
+            .intercept(&command("HELLO", &["2", "AUTH", "default", "secret", "SETNAME", "app"]))
+            .unwrap();
+        assert!(reply.starts_with(b"*8\r\n"));
+        assert!(auth.is_authenticated());
+    }
+
+    #[test]
+    fn test_carries_credentials() {
+        let frame = |parts: &[&str]| RespEncoder::encode(&RespEncoder::create_command(parts[0], &parts[1..]));
+        for credentials in [
+            &["AUTH", "secret"][..],
+            &["auth", "proxy", "secret"],
+            &["HELLO", "2", "AUTH", "default", "secret"],
+            &["MIGRATE", "10.0.0.2", "6379", "k", "0", "5000", "AUTH2", "proxy", "secret"],
+            &["ACL", "setuser", "app", "on", ">secret"],
+            &["CONFIG", "SET", "masterauth", "secret"],
+        ] {
+            assert!(carries_credentials(&frame(credentials)), "{credentials:?}");
+        }
+        for other in [
+            &["GET", "auth"][..],
+            &["HELLO", "2"],
+            &["MIGRATE", "10.0.0.2", "6379", "k", "0", "5000"],
+            &["ACL", "WHOAMI"],
+            &["CONFIG", "GET", "maxmemory"],
+        ] {
+            assert!(!carries_credentials(&frame(other)), "{other:?}");
+        }
+        assert!(carries_credentials(&Bytes::from_static(b"AUTH secret\r\n")));
+    }
+
+    #[tokio::test]
+    async fn test_authenticate_upstream() {
+        let (mut client, mut server) = tokio::io::duplex(256);
+        let server_task = tokio::spawn(async move {
+            let mut buf = [0u8; 256];
+            let n = server.read(&mut buf).await.unwrap();
+            server.write_all(b"+OK\r\n").await.unwrap();
+            buf[..n].to_vec()
+        });
+
+        authenticate(&mut client, &credentials(None)).await.unwrap();
+        assert_eq!(
+            server_task.await.unwrap(),
+            b"*2\r\n$4\r\nAUTH\r\n$6\r\nsecret\r\n".to_vec()
+        );
+
+        let (mut client, mut server) = tokio::io::duplex(256);
+        tokio::spawn(async move {
+            let mut buf = [0u8; 256];
+            let _ = server.read(&mut buf).await.unwrap();
+            server.write_all(WRONGPASS_REPLY).await.unwrap();
+        });
+        assert!(authenticate(&mut client, &credentials(None)).await.is_err());
+    }
+}
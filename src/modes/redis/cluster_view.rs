@@ -0,0 +1,248 @@
+/// Single-node cluster view answered by the proxy
+///
+/// Cluster-aware clients learn the node addresses from `CLUSTER SLOTS`,
+/// `CLUSTER SHARDS` or `CLUSTER NODES` and then connect to the nodes
+/// directly, bypassing the proxy. With the view enabled these commands, and
+/// `CLUSTER INFO` and `CLUSTER MYID`, are answered locally as if the proxy
+/// were the only master, serving every slot, so clients keep talking to the
+/// proxy and never see the real nodes.
+use super::resp::{RespEncoder, RespValue};
+use super::RedisCommand;
+use crate::config::ClusterViewConfig;
+use bytes::Bytes;
+use std::net::SocketAddr;
+
+/// Node ID the proxy reports for itself
+pub const PROXY_NODE_ID: &str = "70756572746170726f787970756572746170726f";
+
+/// Subcommands answered locally
+const SUBCOMMANDS: &[&str] = &["SLOTS", "SHARDS", "NODES", "INFO", "MYID"];
+
+/// Cluster view shared by every connection of a listener
+pub struct ClusterView {
+    /// Host and port clients are told to connect to, or the address each
+    /// client connected to when not configured
+    announce: Option<(String, u16)>,
+}
+
+impl ClusterView {
+    pub fn new(config: &ClusterViewConfig) -> Self {
+        Self {
+            announce: config.announce_addr.as_deref().and_then(split_host_port),
+        }
+    }
+
+    /// Host and port the proxy is announced at to a client connected to
+    /// `local_addr`
+    pub fn address(&self, local_addr: Option<SocketAddr>) -> Option<(String, u16)> {
+        match &self.announce {
+            Some((host, port)) => Some((host.clone(), *port)),
+            None => local_addr.map(|addr| (addr.ip().to_string(), addr.port())),
+        }
+    }
+
+    /// Local reply to a `CLUSTER` subcommand of the view for a client
+    /// connected to `local_addr`, or `None` to forward the command
+    ///
+    /// Without an announced address and a known local address the command
+    /// is forwarded.
+    pub fn intercept(
+        &self,
+        command: &RedisCommand,
+        local_addr: Option<SocketAddr>,
+    ) -> Option<Bytes> {
+        if command.command != "CLUSTER" {
+            return None;
+        }
+        let subcommand = String::from_utf8_lossy(command.args.first()?).to_ascii_uppercase();
+        if !SUBCOMMANDS.contains(&subcommand.as_str()) {
+            return None;
+        }
+        let (host, port) = self.address(local_addr)?;
+        if command.args.len() > 1 {
+            return Some(Bytes::from(format!(
+                "-ERR wrong number of arguments for 'cluster|{}' command\r\n",
+                subcommand.to_ascii_lowercase()
+            )));
+        }
+        let reply = match subcommand.as_str() {
+            "SLOTS" => slots_reply(&host, port),
+            "SHARDS" => shards_reply(&host, port),
+            "NODES" => bulk(&format!(
+                "{PROXY_NODE_ID} {host}:{port}@{port} myself,master - 0 0 1 connected 0-16383\n"
+            )),
+            "INFO" => bulk(&info()),
+            _ => bulk(PROXY_NODE_ID),
+        };
+        Some(RespEncoder::encode(&reply))
+    }
+}
+
+/// Host and port of a `host:port` address, IPv6 hosts in brackets
+pub fn split_host_port(addr: &str) -> Option<(String, u16)> {
+    let (host, port) = addr.rsplit_once(':')?;
+    let host = host
+        .strip_prefix('[')
+        .and_then(|host| host.strip_suffix(']'))
+        .unwrap_or(host);
+    if host.is_empty() {
+        return None;
+    }
+    Some((host.to_string(), port.parse().ok()?))
+}
+
+fn bulk(value: &str) -> RespValue {
+    RespValue::BulkString(Some(Bytes::copy_from_slice(value.as_bytes())))
+}
+
+fn array(elements: Vec<RespValue>) -> RespValue {
+    RespValue::Array(Some(elements))
+}
+
+/// One slot range served by the proxy
+fn slots_reply(host: &str, port: u16) -> RespValue {
+    let node = array(vec![
+        bulk(host),
+        RespValue::Integer(port.into()),
+        bulk(PROXY_NODE_ID),
+    ]);
+    array(vec![array(vec![
+        RespValue::Integer(0),
+        RespValue::Integer(16383),
+        node,
+    ])])
+}
+
+/// One shard of one master, in the RESP2 form of the reply
+fn shards_reply(host: &str, port: u16) -> RespValue {
+    let node = array(vec![
+        bulk("id"),
+        bulk(PROXY_NODE_ID),
+        bulk("port"),
+        RespValue::Integer(port.into()),
+        bulk("ip"),
+        bulk(host),
+        bulk("endpoint"),
+        bulk(host),
+        bulk("role"),
+        bulk("master"),
+        bulk("replication-offset"),
+        RespValue::Integer(0),
+        bulk("health"),
+        bulk("online"),
+    ]);
+    array(vec![array(vec![
+        bulk("slots"),
+        array(vec![RespValue::Integer(0), RespValue::Integer(16383)]),
+        bulk("nodes"),
+        array(vec![node]),
+    ])])
+}
+
+fn info() -> String {
+    [
+        "cluster_state:ok",
+        "cluster_slots_assigned:16384",
+        "cluster_slots_ok:16384",
+        "cluster_slots_pfail:0",
+        "cluster_slots_fail:0",
+        "cluster_known_nodes:1",
+        "cluster_size:1",
+        "cluster_current_epoch:1",
+        "cluster_my_epoch:1",
+        "",
+    ]
+    .join("\r\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modes::redis::resp::RespParser;
+    use bytes::BytesMut;
+
+    fn cluster(subcommand: &str) -> RedisCommand {
+        RedisCommand {
+            command: "CLUSTER".to_string(),
+            args: vec![Bytes::copy_from_slice(subcommand.as_bytes())],
+            key: None,
+            slot: None,
+            readonly: true,
+        }
+    }
+
+    fn parse(reply: &Bytes) -> RespValue {
+        RespParser::parse(&mut BytesMut::from(reply.as_ref()))
+            .unwrap()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_intercept() {
+        let view = ClusterView::new(&ClusterViewConfig {
+            announce_addr: Some("proxy.example.com:6380".to_string()),
+        });
+        let reply = view.intercept(&cluster("slots"), None).unwrap();
+        assert_eq!(
+            parse(&reply),
+            RespValue::Array(Some(vec![RespValue::Array(Some(vec![
+                RespValue::Integer(0),
+                RespValue::Integer(16383),
+                RespValue::Array(Some(vec![
+                    RespValue::BulkString(Some(Bytes::from_static(b"proxy.example.com"))),
+                    RespValue::Integer(6380),
+                    RespValue::BulkString(Some(Bytes::from_static(PROXY_NODE_ID.as_bytes()))),
+                ])),
+            ]))]))
+        );
+
+        let RespValue::Array(Some(shards)) =
+            parse(&view.intercept(&cluster("SHARDS"), None).unwrap())
+        else {
+            panic!("CLUSTER SHARDS reply is not an array");
+        };
+        assert_eq!(shards.len(), 1);
+
+        let reply = view.intercept(&cluster("NODES"), None).unwrap();
+        let expected = format!(
+            "{PROXY_NODE_ID} proxy.example.com:6380@6380 myself,master - 0 0 1 connected 0-16383\n"
+        );
+        assert_eq!(parse(&reply), bulk(&expected));
+
+        let reply = view.intercept(&cluster("INFO"), None).unwrap();
+        assert!(String::from_utf8_lossy(&reply).contains("cluster_known_nodes:1\r\n"));
+
+        // Other subcommands and commands reach the cluster
+        assert!(view.intercept(&cluster("KEYSLOT"), None).is_none());
+        let ping = RedisCommand {
+            command: "PING".to_string(),
+            ..cluster("SLOTS")
+        };
+        assert!(view.intercept(&ping, None).is_none());
+    }
+
+    #[test]
+    fn test_intercept_announces_local_address() {
+        let view = ClusterView::new(&ClusterViewConfig::default());
+        assert!(view.intercept(&cluster("SLOTS"), None).is_none());
+
+        let local_addr = "[::1]:7000".parse().unwrap();
+        let reply = view.intercept(&cluster("SLOTS"), Some(local_addr)).unwrap();
+        assert_eq!(parse(&reply), slots_reply("::1", 7000));
+    }
+
+    #[test]
+    fn test_split_host_port() {
+        assert_eq!(
+            split_host_port("10.0.0.1:6379"),
+            Some(("10.0.0.1".to_string(), 6379))
+        );
+        assert_eq!(
+            split_host_port("[::1]:6379"),
+            Some(("::1".to_string(), 6379))
+        );
+        assert_eq!(split_host_port("proxy"), None);
+        assert_eq!(split_host_port(":6379"), None);
+        assert_eq!(split_host_port("proxy:port"), None);
+    }
+}
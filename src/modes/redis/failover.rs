@@ -0,0 +1,282 @@
+/// Failover handling when a master is lost
+///
+/// Redis Cluster promotes a replica of a failed master, but the periodic
+/// topology refresh may only notice minutes later. A command whose master
+/// cannot be reached instead asks the refresh task for an immediate
+/// refresh and waits for it, then goes to the slot's owner in the new
+/// topology. Until the cluster has promoted a replica, the refresh is
+/// repeated every `FAILOVER_RETRY_INTERVAL` up to the failover timeout.
+///
+/// A resharding moves many slots at once, so MOVED replies for many
+/// distinct slots in a short window trigger one full refresh instead of
+/// patching the slot mapping slot by slot.
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::sync::{watch, Notify};
+
+/// Pause between topology refreshes while waiting for a replica promotion
+pub const FAILOVER_RETRY_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Distinct slots answered with MOVED within `MOVED_STORM_WINDOW` that
+/// mark a resharding rather than a single moved slot
+pub const MOVED_STORM_THRESHOLD: usize = 8;
+
+/// Span over which MOVED replies count toward a storm, and for which
+/// further ones are damped once it has triggered a refresh
+pub const MOVED_STORM_WINDOW: Duration = Duration::from_secs(1);
+
+/// What to do about a MOVED reply
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MovedAction {
+    /// Move the one slot in the slot mapping
+    Patch,
+    /// Refresh the whole topology, as many slots are moving
+    Refresh,
+    /// The ongoing storm already requested a refresh
+    Damped,
+}
+
+/// Recent MOVED replies, telling a resharding from a single moved slot
+pub struct MovedStorm {
+    threshold: usize,
+    window: Duration,
+    state: Mutex<MovedStormState>,
+}
+
+#[derive(Default)]
+struct MovedStormState {
+    /// Slots answered with MOVED, and when last
+    slots: HashMap<u16, Instant>,
+    /// When the last storm requested a refresh
+    refreshed_at: Option<Instant>,
+}
+
+impl MovedStorm {
+    pub fn new(threshold: usize, window: Duration) -> Self {
+        Self {
+            threshold,
+            window,
+            state: Mutex::default(),
+        }
+    }
+
+    /// Record a MOVED reply for `slot` and decide how to handle it
+    pub fn record(&self, slot: u16) -> MovedAction {
+        self.record_at(slot, Instant::now())
+    }
+
+    fn record_at(&self, slot: u16, now: Instant) -> MovedAction {
+        let mut state = self.state.lock().unwrap();
+        if state
+            .refreshed_at
+            .is_some_and(|refreshed_at| now.saturating_duration_since(refreshed_at) < self.window)
+        {
+            return MovedAction::Damped;
+        }
+        state
+            .slots
+            .retain(|_, seen| now.saturating_duration_since(*seen) < self.window);
+        state.slots.insert(slot, now);
+        if state.slots.len() < self.threshold {
+            return MovedAction::Patch;
+        }
+        state.slots.clear();
+        state.refreshed_at = Some(now);
+        MovedAction::Refresh
+    }
+}
+
+impl Default for MovedStorm {
+    fn default() -> Self {
+        Self::new(MOVED_STORM_THRESHOLD, MOVED_STORM_WINDOW)
+    }
+}
+
+/// Topology refreshes requested by commands, run by the refresh task
+pub struct TopologyRefresh {
+    requested: Notify,
+    /// Number of refreshes completed
+    completed: watch::Sender<u64>,
+}
+
+impl TopologyRefresh {
+    pub fn new() -> Self {
+        Self {
+            requested: Notify::new(),
+            completed: watch::channel(0).0,
+        }
+    }
+
+    /// Ask for a refresh without waiting for it
+    ///
+    /// Requests made while a refresh runs are coalesced into the next one.
+    pub fn request(&self) {
+        self.requested.notify_one();
+    }
+
+    /// Ask for a refresh and wait up to `timeout` for the refresh task to
+    /// complete one, returning whether it did
+    pub async fn refresh(&self, timeout: Duration) -> bool {
+        let mut completed = self.completed.subscribe();
+        completed.borrow_and_update();
+        self.request();
+        matches!(
+            tokio::time::timeout(timeout, completed.changed()).await,
+            Ok(Ok(()))
+        )
+    }
+
+    /// Wait for the next refresh request, for the refresh task
+    pub async fn requested(&self) {
+        self.requested.notified().await;
+    }
+
+    /// Wake the commands waiting on a refresh, whether or not it succeeded
+    pub fn complete(&self) {
+        self.completed.send_modify(|completed| *completed += 1);
+    }
+}
+
+impl Default for TopologyRefresh {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A node that could not be reached, or whose connection broke
+#[derive(Debug)]
+pub struct NodeLost {
+    /// Address of the node
+    pub node: String,
+    source: Box<dyn Error + Send + Sync>,
+    /// The command may have reached the node before it was lost
+    pub sent: bool,
+}
+
+impl NodeLost {
+    /// Boxed error for losing `node`
+    pub fn error(
+        node: impl Into<String>,
+        source: impl Into<Box<dyn Error + Send + Sync>>,
+        sent: bool,
+    ) -> Box<dyn Error + Send + Sync> {
+        Box::new(Self {
+            node: node.into(),
+            source: source.into(),
+            sent,
+        })
+    }
+
+    /// The node lost by `error`, if a command failing with it may be sent
+    /// again: it never reached the node, or it only reads
+    pub fn retryable<'a>(
+        error: &'a (dyn Error + Send + Sync + 'static),
+        readonly: bool,
+    ) -> Option<&'a str> {
+        error
+            .downcast_ref::<NodeLost>()
+            .filter(|lost| readonly || !lost.sent)
+            .map(|lost| lost.node.as_str())
+    }
+
+    /// Replace the message of `error`, keeping it a `NodeLost` if it was one
+    pub fn with_message(
+        error: Box<dyn Error + Send + Sync>,
+        message: String,
+    ) -> Box<dyn Error + Send + Sync> {
+        match error.downcast::<NodeLost>() {
+            Ok(lost) => Box::new(NodeLost {
+                source: message.into(),
+                ..*lost
+            }),
+            Err(_) => message.into(),
+        }
+    }
+}
+
+impl fmt::Display for NodeLost {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.source.fmt(f)
+    }
+}
+
+impl Error for NodeLost {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_refresh_waits_for_the_refresh_task() {
+        let refresh = Arc::new(TopologyRefresh::new());
+        assert!(!refresh.refresh(Duration::from_millis(20)).await);
+
+        let task = tokio::spawn({
+            let refresh = Arc::clone(&refresh);
+            async move {
+                loop {
+                    refresh.requested().await;
+                    refresh.complete();
+                }
+            }
+        });
+        assert!(refresh.refresh(Duration::from_secs(5)).await);
+        assert!(refresh.refresh(Duration::from_secs(5)).await);
+        task.abort();
+    }
+
+    #[test]
+    fn test_moved_storm_triggers_one_refresh() {
+        let storm = MovedStorm::new(3, Duration::from_secs(1));
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+
+        // The same slot moving again is not a storm
+        assert_eq!(storm.record_at(1, at(0)), MovedAction::Patch);
+        assert_eq!(storm.record_at(1, at(10)), MovedAction::Patch);
+        assert_eq!(storm.record_at(2, at(20)), MovedAction::Patch);
+        // Nor are slots moving further apart than the window
+        assert_eq!(storm.record_at(3, at(1500)), MovedAction::Patch);
+        assert_eq!(storm.record_at(4, at(1600)), MovedAction::Patch);
+
+        assert_eq!(storm.record_at(5, at(1700)), MovedAction::Refresh);
+        assert_eq!(storm.record_at(6, at(1800)), MovedAction::Damped);
+        assert_eq!(storm.record_at(7, at(2699)), MovedAction::Damped);
+        // Counting starts over once the window has passed
+        assert_eq!(storm.record_at(8, at(2700)), MovedAction::Patch);
+    }
+
+    #[test]
+    fn test_node_lost_retryable() {
+        let refused = NodeLost::error("10.0.0.1:7000", "connection refused", false);
+        let refused = NodeLost::with_message(
+            refused,
+            "upstream 10.0.0.1:7000 failed: connection refused".to_string(),
+        );
+        assert_eq!(
+            refused.to_string(),
+            "upstream 10.0.0.1:7000 failed: connection refused"
+        );
+        assert_eq!(
+            NodeLost::retryable(refused.as_ref(), false),
+            Some("10.0.0.1:7000")
+        );
+
+        // A write may have been applied before the connection broke
+        let broken = NodeLost::error("10.0.0.1:7000", "connection closed by Redis node", true);
+        assert_eq!(NodeLost::retryable(broken.as_ref(), false), None);
+        assert_eq!(
+            NodeLost::retryable(broken.as_ref(), true),
+            Some("10.0.0.1:7000")
+        );
+
+        let other: Box<dyn Error + Send + Sync> = "No cluster nodes available".into();
+        assert_eq!(NodeLost::retryable(other.as_ref(), true), None);
+        let other = NodeLost::with_message(other, "failed".to_string());
+        assert_eq!(NodeLost::retryable(other.as_ref(), true), None);
+    }
+}
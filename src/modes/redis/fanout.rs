@@ -0,0 +1,174 @@
+/// Cluster-wide keyless commands
+///
+/// Commands such as `DBSIZE` or `FLUSHALL` act on a single node's keyspace,
+/// so sending them to one node answers for a fraction of the cluster. The
+/// proxy runs them on every master and aggregates the replies.
+use super::resp::{RespEncoder, RespParser, RespValue};
+use bytes::{Bytes, BytesMut};
+
+/// How the replies of the masters are combined
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aggregate {
+    /// `DBSIZE`: sum of integer replies
+    Sum,
+    /// `FLUSHDB`/`FLUSHALL`: `+OK` once every master succeeded
+    AllOk,
+    /// `KEYS`: concatenation of array replies
+    Concat,
+    /// `INFO`: each master's text under a header naming it
+    Info,
+    /// The first master's reply once every master succeeded
+    First,
+}
+
+impl Aggregate {
+    /// Aggregation for a keyless command run on every master, if it is one
+    pub fn for_command(command: &str) -> Option<Self> {
+        match command {
+            "DBSIZE" => Some(Self::Sum),
+            "FLUSHDB" | "FLUSHALL" => Some(Self::AllOk),
+            "KEYS" => Some(Self::Concat),
+            "INFO" => Some(Self::Info),
+            _ => None,
+        }
+    }
+
+    /// Combine the masters' replies, given with their addresses
+    ///
+    /// The first error reply from any master is returned to the client as-is.
+    pub fn merge(self, replies: &[(String, Bytes)]) -> Bytes {
+        let mut parsed = Vec::with_capacity(replies.len());
+        for (node, reply) in replies {
+            match RespParser::parse(&mut BytesMut::from(reply.as_ref())) {
+                Ok(Some(RespValue::Error(_))) => return reply.clone(),
+                Ok(Some(value)) => parsed.push((node, value)),
+                _ => return error("invalid reply from cluster node"),
+            }
+        }
+
+        match self {
+            Self::AllOk => Bytes::from_static(b"+OK\r\n"),
+            Self::First => replies
+                .first()
+                .map(|(_, reply)| reply.clone())
+                .unwrap_or_else(|| error("no cluster nodes available")),
+            Self::Sum => {
+                let mut total = 0;
+                for (_, value) in parsed {
+                    match value {
+                        RespValue::Integer(n) => total += n,
+                        _ => return error("unexpected reply type from cluster node"),
+                    }
+                }
+                RespEncoder::encode(&RespValue::Integer(total))
+            }
+            Self::Concat => {
+                let mut elements = Vec::new();
+                for (_, value) in parsed {
+                    match value {
+                        RespValue::Array(Some(node_elements)) => elements.extend(node_elements),
+                        _ => return error("unexpected reply type from cluster node"),
+                    }
+                }
+                RespEncoder::encode(&RespValue::Array(Some(elements)))
+            }
+            Self::Info => {
+                let mut info = BytesMut::new();
+                for (node, value) in parsed {
+                    match value {
+                        RespValue::BulkString(Some(text)) => {
+                            info.extend_from_slice(format!("# Node {node}\r\n").as_bytes());
+                            info.extend_from_slice(&text);
+                            if !text.ends_with(b"\r\n") {
+                                info.extend_from_slice(b"\r\n");
+                            }
+                        }
+                        _ => return error("unexpected reply type from cluster node"),
+                    }
+                }
+                RespEncoder::encode(&RespValue::BulkString(Some(info.freeze())))
+            }
+        }
+    }
+}
+
+fn error(message: &str) -> Bytes {
+    Bytes::from(format!("-ERR {message}\r\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn replies(replies: &[&[u8]]) -> Vec<(String, Bytes)> {
+        replies
+            .iter()
+            .enumerate()
+            .map(|(i, reply)| (format!("node{i}:6379"), Bytes::copy_from_slice(reply)))
+            .collect()
+    }
+
+    #[test]
+    fn test_keyless_commands() {
+        assert_eq!(Aggregate::for_command("DBSIZE"), Some(Aggregate::Sum));
+        assert_eq!(Aggregate::for_command("FLUSHALL"), Some(Aggregate::AllOk));
+        assert_eq!(Aggregate::for_command("KEYS"), Some(Aggregate::Concat));
+        assert_eq!(Aggregate::for_command("PING"), None);
+    }
+
+    #[test]
+    fn test_merge_replies() {
+        assert_eq!(
+            Aggregate::Sum
+                .merge(&replies(&[b":3\r\n", b":4\r\n"]))
+                .as_ref(),
+            b":7\r\n"
+        );
+        assert_eq!(
+            Aggregate::AllOk
+                .merge(&replies(&[b"+OK\r\n", b"+OK\r\n"]))
+                .as_ref(),
+            b"+OK\r\n"
+        );
+        assert_eq!(
+            Aggregate::Concat
+                .merge(&replies(&[
+                    b"*1\r\n$1\r\na\r\n",
+                    b"*0\r\n",
+                    b"*1\r\n$1\r\nb\r\n"
+                ]))
+                .as_ref(),
+            b"*2\r\n$1\r\na\r\n$1\r\nb\r\n"
+        );
+        assert_eq!(
+            Aggregate::First
+                .merge(&replies(&[b"$3\r\nlib\r\n", b"$3\r\nlib\r\n"]))
+                .as_ref(),
+            b"$3\r\nlib\r\n"
+        );
+
+        let info =
+            Aggregate::Info.merge(&replies(&[b"$7\r\nrole:m\n\r\n", b"$8\r\nrole:m\r\n\r\n"]));
+        let mut buf = BytesMut::from(info.as_ref());
+        let Ok(Some(RespValue::BulkString(Some(text)))) = RespParser::parse(&mut buf) else {
+            panic!("Expected a bulk string");
+        };
+        assert_eq!(
+            text.as_ref(),
+            b"# Node node0:6379\r\nrole:m\n\r\n# Node node1:6379\r\nrole:m\r\n"
+        );
+    }
+
+    #[test]
+    fn test_merge_errors() {
+        assert_eq!(
+            Aggregate::Sum
+                .merge(&replies(&[b":3\r\n", b"-ERR busy\r\n"]))
+                .as_ref(),
+            b"-ERR busy\r\n"
+        );
+        assert!(Aggregate::Sum
+            .merge(&replies(&[b":3\r\n", b"+OK\r\n"]))
+            .starts_with(b"-ERR unexpected reply type"));
+    }
+}
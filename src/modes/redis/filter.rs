@@ -0,0 +1,87 @@
+/// Redis command allow and deny lists
+///
+/// Commands refused by the filter are answered by the proxy without reaching
+/// a node, so a listener shared by several tenants can keep dangerous or
+/// expensive commands such as `FLUSHALL`, `KEYS` or `CONFIG` away from the
+/// cluster.
+use crate::config::CommandFilterConfig;
+use crate::metrics::Counter;
+use bytes::Bytes;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+/// Reply to a command refused by the filter
+pub const DISABLED_REPLY: &[u8] = b"-ERR command disabled by proxy\r\n";
+
+/// Command filter shared by every connection of a listener
+pub struct CommandFilter {
+    /// Allowed command names in upper case; empty allows every command not denied
+    allow: HashSet<String>,
+    /// Denied command names in upper case
+    deny: HashSet<String>,
+    rejected: Arc<Counter>,
+}
+
+impl CommandFilter {
+    pub fn new(config: &CommandFilterConfig) -> Self {
+        let names = |commands: &[String]| {
+            commands
+                .iter()
+                .map(|command| command.to_ascii_uppercase())
+                .collect()
+        };
+        Self {
+            allow: names(&config.allow),
+            deny: names(&config.deny),
+            rejected: crate::metrics::global().counter(
+                "puerta_redis_commands_rejected_total",
+                "Redis commands refused by the command filter",
+            ),
+        }
+    }
+
+    pub fn is_allowed(&self, command: &str) -> bool {
+        !self.deny.contains(command) && (self.allow.is_empty() || self.allow.contains(command))
+    }
+
+    /// Reply for a refused command, counting it, or `None` when it is allowed
+    ///
+    /// `command` is the upper case command name.
+    pub fn check(&self, command: &str) -> Option<Bytes> {
+        if self.is_allowed(command) {
+            return None;
+        }
+        self.rejected.inc();
+        Some(Bytes::from_static(DISABLED_REPLY))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(allow: &[&str], deny: &[&str]) -> CommandFilter {
+        CommandFilter::new(&CommandFilterConfig {
+            allow: allow.iter().map(|command| command.to_string()).collect(),
+            deny: deny.iter().map(|command| command.to_string()).collect(),
+        })
+    }
+
+    #[test]
+    fn test_deny_list() {
+        let filter = filter(&[], &["flushall", "KEYS"]);
+        assert!(filter.is_allowed("GET"));
+        assert!(!filter.is_allowed("FLUSHALL"));
+        assert_eq!(filter.check("KEYS").as_deref(), Some(DISABLED_REPLY));
+        assert_eq!(filter.check("SET"), None);
+    }
+
+    #[test]
+    fn test_allow_list() {
+        let filter = filter(&["GET", "SET", "DEL"], &["DEL"]);
+        assert!(filter.is_allowed("GET"));
+        assert!(!filter.is_allowed("CONFIG"));
+        // Denied wins over allowed
+        assert!(!filter.is_allowed("DEL"));
+    }
+}
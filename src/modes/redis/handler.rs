@@ -0,0 +1,142 @@
+/// Proxy mode handler for Redis
+///
+/// Each Redis instance is a RESP proxy routing commands by hash slot across
+/// a Redis Cluster, or across standalone servers sharing the slots.
+use super::{RedisClusterProxy, RedisConfig};
+use crate::config::Endpoint;
+use crate::modes::{InstanceContext, ProxyModeHandler};
+use crate::{ProxyInstance, Puerta};
+use std::error::Error;
+use std::sync::Arc;
+
+/// Redis mode of an instance
+#[derive(Debug, Clone, Default)]
+pub struct RedisHandler {
+    /// Cluster nodes, slot refreshes and detailed settings
+    config: RedisConfig,
+}
+
+impl RedisHandler {
+    pub fn new(config: RedisConfig) -> Self {
+        Self { config }
+    }
+}
+
+impl ProxyModeHandler for RedisHandler {
+    fn name(&self) -> &'static str {
+        "Redis"
+    }
+
+    fn service_name(&self) -> &'static str {
+        "Redis Cluster Proxy"
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.config.cluster_nodes.is_empty() {
+            return Err("At least one Redis cluster node is required".to_string());
+        }
+        if self.config.slot_refresh_interval_sec == 0 {
+            return Err("Slot refresh interval must be greater than 0".to_string());
+        }
+        Ok(())
+    }
+
+    fn health_checker(&self) -> Box<dyn crate::health::HealthChecker> {
+        // Standalone servers refuse CLUSTER NODES
+        let config = &self.config;
+        Box::new(
+            crate::health::redis::RedisHealthChecker::new()
+                .with_cluster_check(config.sharding == crate::config::RedisSharding::Cluster)
+                .with_upstream_tls(config.upstream_tls.as_ref())
+                .with_auth(config.auth.as_ref()),
+        )
+    }
+
+    fn add_instance(
+        &self,
+        puerta: &Puerta,
+        instance: &ProxyInstance,
+        context: &mut InstanceContext<'_>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        log::info!(
+            "Starting {} in Redis mode using RCProxy architecture",
+            instance.name
+        );
+
+        // With backend discovery the healthy discovered seed nodes replace the
+        // static ones
+        let redis_defaults = self.config.clone();
+        let static_endpoints: Vec<Endpoint> = redis_defaults
+            .cluster_nodes
+            .iter()
+            .map(|addr| {
+                Endpoint::new(addr.clone(), redis_defaults.node_weights.get(addr).copied().unwrap_or(1))
+            })
+            .collect();
+        let discovered = puerta.backend_discovery(static_endpoints, context.is_primary, |config| match &config.proxy {
+            crate::config::ProxyConfig::Redis { cluster_nodes, .. } => Some(cluster_nodes.clone()),
+            _ => None,
+        });
+        let mut node_weights = redis_defaults.node_weights;
+        let cluster_nodes = match &discovered {
+            Some(receiver) => {
+                let endpoints =
+                    crate::discovery::DiscoveredEndpoint::healthy_endpoints(&receiver.borrow());
+                node_weights = Endpoint::weights(&endpoints);
+                Endpoint::addrs(&endpoints)
+            }
+            None => redis_defaults.cluster_nodes.clone(),
+        };
+
+        // Create Redis configuration
+        let redis_config = RedisConfig {
+            cluster_nodes,
+            node_weights,
+            ..redis_defaults
+        };
+
+        let mut redis_proxy = RedisClusterProxy::new(redis_config)
+            .with_name(instance.name.clone())
+            .with_health_check()
+            .with_listeners(
+                puerta.config.listeners_for(&instance.listen_addr)?,
+                instance.listen_addr.clone(),
+            )
+            .with_connection_limiter(context.connection_limiter.clone())
+            .with_proxy_protocol(puerta.config.proxy_protocol)
+            .with_access_control(Arc::clone(context.access_control))
+            .with_buffer_config(puerta.config.buffer_config)
+            .with_idle_timeout(puerta.config.idle_timeout())
+            .with_drains(Arc::clone(&puerta.drains))
+            .with_chaos(puerta.chaos("redis"))
+            .with_cpu_pinning(crate::core::cpu::CpuPinning::new(
+                instance.name.clone(),
+                instance.cpu_affinity.clone(),
+            ));
+        if let Some(receiver) = discovered {
+            redis_proxy = redis_proxy.with_discovery(receiver);
+        }
+        if let (true, Some(reloader)) = (context.is_primary, &puerta.config_reloader) {
+            redis_proxy = redis_proxy.with_reload(reloader.subscribe());
+        }
+        // The admin API and StatsD report the first Redis instance's statistics
+        let command_stats = redis_proxy.command_stats();
+        let large_payloads = redis_proxy.large_payloads();
+        context.register_admin_routes_once("redis", |router| {
+            let router = command_stats.register_admin_routes(router);
+            match large_payloads {
+                Some(large_payloads) => large_payloads.register_admin_routes(router),
+                None => router,
+            }
+        });
+        context.export_command_stats(command_stats);
+        context.status_reporter.add_source(redis_proxy.status_source(instance.listen_addr.clone()));
+        context.add_explain_source(redis_proxy.explain_source());
+        futures::executor::block_on(redis_proxy.add_to_server(context.server))?;
+        Ok(())
+    }
+
+    fn clone_box(&self) -> Box<dyn ProxyModeHandler> {
+        Box::new(self.clone())
+    }
+}
@@ -7,20 +7,86 @@
 /// - MOVED/ASK redirection handling
 /// - Cluster topology discovery and maintenance
 /// - Cross-slot operation detection and handling
+/// - MULTI/EXEC transactions pinned to the node owning their keys
+/// - Pub/Sub subscribers passed through a dedicated node connection
+/// - Script and function calls routed by their declared keys
+/// - SCAN across every master through a virtual cursor
+/// - Keyless cluster-wide commands fanned out to every master
+/// - Command allow and deny lists per listener
+/// - Large replies logged, counted and reported per key
+/// - Slots of a lost master re-routed to its promoted replica
+/// - Optional single-node cluster view hiding the real nodes from clients
+/// - Sharding across standalone servers without Redis Cluster
+/// - Warm restarts from a topology snapshot kept on disk
+pub mod agreement;
+pub mod auth;
+pub mod cluster_view;
+pub mod failover;
+pub mod fanout;
+pub mod filter;
+pub mod handler;
+pub mod pool;
 pub mod proxy;
+pub mod pubsub;
 pub mod redirect;
 pub mod resp;
+pub mod scan;
+pub mod scripting;
+pub mod sharding;
 pub mod slots;
-
-
+pub mod snapshot;
+pub mod split;
+pub mod stats;
+#[cfg(test)]
+pub(crate) mod test_support;
+pub mod transaction;
 
 use async_trait::async_trait;
-use bytes::Bytes;
-use std::collections::HashMap;
+use bytes::{Bytes, BytesMut};
+use crate::config::{
+    BalanceStrategy, CaptureConfig, ClusterViewConfig, CommandFilterConfig, LargePayloadConfig, MirrorConfig,
+    RedisAuthConfig, RedisSharding, SnapshotConfig, TcpKeepaliveConfig, TopologyAgreement,
+    UpstreamTlsConfig,
+};
+use crate::acl::AccessControl;
+use crate::core::chaos::{Chaos, Fault};
+use crate::core::circuit_breaker::CircuitBreaker;
+use crate::core::connections::ConnectionStats;
+use crate::core::drain::Drains;
+use crate::core::frontend::{read_idle, BufferConfig, ConnectionLimiter};
+use crate::core::latency::LatencyTracker;
+use crate::core::capture::Capture;
+use crate::core::mirror::Mirror;
+use crate::core::upstream;
+use crate::modes::RoutingDecision;
+use auth::ClientAuth;
+use cluster_view::ClusterView;
+use failover::{NodeLost, TopologyRefresh, FAILOVER_RETRY_INTERVAL};
+use fanout::Aggregate;
+use filter::CommandFilter;
+use pool::{ConnectionMode, ConnectionPool, PoolConfig, PooledConnection};
+use proxy::{ClusterRouter, NodeHealth};
+use pubsub::Subscriptions;
+use scripting::ScriptCache;
+use snapshot::TopologySnapshot;
+pub use slots::{SlotMapping, SlotMigration};
+use resp::{RespParseError, RespParser, RespValue};
+use split::SplitPlan;
+use stats::CommandStats;
+use crate::admin::explain::{ExplainSource, InstanceExplanation, KeyRoute, Route};
+use crate::admin::status::{
+    BackendStatus, InstanceStatus, SlotCoverageStatus, StatusSource, TOTAL_SLOTS,
+};
+use crate::health::events::HealthEvents;
+use crate::metrics::payload::LargePayloads;
+use crate::metrics::{Gauge, GaugeGuard};
+use transaction::{Transaction, TransactionStep};
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
 use std::sync::Arc;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::sync::RwLock;
+use std::time::Instant;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::{watch, RwLock};
 
 // Pingora framework imports
 use pingora::apps::ServerApp;
@@ -39,15 +105,131 @@ pub struct RedisConfig {
     pub slot_refresh_interval_sec: u64,
     pub max_redirects: u8,
     pub connection_timeout_ms: u64,
+    /// Maximum upstream connections per cluster node
+    pub pool_size: usize,
+    /// Idle pooled connections older than this are closed
+    pub pool_idle_timeout_sec: u64,
+    /// Maximum time to wait for a pooled connection
+    pub pool_max_wait_ms: u64,
+    /// Idle connections kept open to each node ahead of demand
+    pub pool_min_idle: usize,
+    /// Other nodes tried when connecting fails for a command any node can serve
+    pub retry_attempts: u32,
+    /// How long a command whose master was lost waits for a replica to take
+    /// over its slot; 0 fails it right away
+    pub failover_timeout_ms: u64,
+    /// TLS settings for connections to cluster nodes
+    pub upstream_tls: Option<UpstreamTlsConfig>,
+    /// Keepalive probes on connections to cluster nodes
+    pub backend_tcp_keepalive: Option<TcpKeepaliveConfig>,
+    /// Credentials used to authenticate to cluster nodes
+    pub auth: Option<RedisAuthConfig>,
+    /// Credentials clients must present to the proxy
+    pub client_auth: Option<RedisAuthConfig>,
+    /// Route read-only commands to replicas
+    pub read_from_replicas: bool,
+    /// How replicas and seed nodes are picked
+    pub balance_strategy: BalanceStrategy,
+    /// Whether the nodes form a cluster or are standalone servers sharded
+    /// by the proxy
+    pub sharding: RedisSharding,
+    /// How many nodes must agree on the topology before it is applied
+    pub topology_agreement: TopologyAgreement,
+    /// Weight per seed node for commands without a key; unlisted nodes weigh 1
+    pub node_weights: HashMap<String, usize>,
+    /// Seconds between active health checks of the nodes
+    pub health_check_interval_sec: u64,
+    /// Consecutive failed health checks before a node is ejected
+    pub health_failure_threshold: u32,
+    /// Consecutive passed health checks before an ejected node is admitted
+    pub health_success_threshold: u32,
+    /// Consecutive live-traffic failures before a node is ejected
+    pub passive_failure_threshold: u32,
+    /// Eject nodes whose error rate or latency stands out
+    pub outlier_detection: Option<crate::config::OutlierDetectionConfig>,
+    /// Fail fast on nodes whose recent requests mostly failed
+    pub circuit_breaker: Option<crate::config::CircuitBreakerConfig>,
+    /// Commands slower than this many microseconds enter the slow log
+    pub slowlog_threshold_us: u64,
+    /// Maximum number of slow log entries kept
+    pub slowlog_max_len: usize,
+    /// Run cluster-wide keyless commands on every master
+    pub fan_out_keyless_commands: bool,
+    /// Commands clients may or may not send
+    pub command_filter: Option<CommandFilterConfig>,
+    /// Log, count and report replies above a size threshold, per key
+    pub large_payloads: Option<LargePayloadConfig>,
+    /// Copy a share of client connections to a shadow node
+    pub mirror: Option<MirrorConfig>,
+    /// Write client commands to capture files
+    pub capture: Option<CaptureConfig>,
+    /// Answer CLUSTER topology commands as a single node
+    pub cluster_view: Option<ClusterViewConfig>,
+    /// Keep the cluster topology on disk for warm restarts
+    pub snapshot: Option<SnapshotConfig>,
+    /// Push node ejections and recoveries to a webhook
+    pub health_notifications: Option<crate::config::HealthNotificationsConfig>,
+    /// Largest command accepted from a client
+    pub max_command_size: usize,
+    /// Largest reply accepted from a node
+    pub max_reply_size: usize,
 }
 
-/// Redis slot mapping (16384 slots total)
-#[derive(Debug, Clone)]
-pub struct SlotMapping {
-    /// Maps slot number (0-16383) to backend ID
-    slot_to_backend: HashMap<u16, String>,
-    /// Maps backend ID to slot ranges for quick lookup
-    backend_to_slots: HashMap<String, Vec<(u16, u16)>>,
+impl Default for RedisConfig {
+    fn default() -> Self {
+        Self {
+            cluster_nodes: Vec::new(),
+            slot_refresh_interval_sec: 60,
+            max_redirects: 3,
+            connection_timeout_ms: 5000,
+            pool_size: 64,
+            pool_idle_timeout_sec: 300,
+            pool_max_wait_ms: 1000,
+            pool_min_idle: 0,
+            retry_attempts: 2,
+            failover_timeout_ms: 5000,
+            upstream_tls: None,
+            backend_tcp_keepalive: None,
+            auth: None,
+            client_auth: None,
+            read_from_replicas: false,
+            balance_strategy: BalanceStrategy::default(),
+            sharding: RedisSharding::default(),
+            topology_agreement: TopologyAgreement::default(),
+            node_weights: HashMap::new(),
+            health_check_interval_sec: 10,
+            health_failure_threshold: 1,
+            health_success_threshold: 1,
+            passive_failure_threshold: crate::health::DEFAULT_PASSIVE_FAILURE_THRESHOLD,
+            outlier_detection: None,
+            circuit_breaker: None,
+            slowlog_threshold_us: 10_000,
+            slowlog_max_len: 128,
+            fan_out_keyless_commands: true,
+            command_filter: None,
+            large_payloads: None,
+            mirror: None,
+            capture: None,
+            cluster_view: None,
+            snapshot: None,
+            health_notifications: None,
+            max_command_size: resp::DEFAULT_MAX_FRAME_SIZE,
+            max_reply_size: resp::DEFAULT_MAX_FRAME_SIZE,
+        }
+    }
+}
+
+impl RedisConfig {
+    /// Connection pool settings derived from this configuration
+    pub fn pool_config(&self) -> PoolConfig {
+        PoolConfig {
+            max_size: self.pool_size,
+            idle_timeout: std::time::Duration::from_secs(self.pool_idle_timeout_sec),
+            max_wait: std::time::Duration::from_millis(self.pool_max_wait_ms),
+            min_idle: self.pool_min_idle,
+            max_reply_size: self.max_reply_size,
+        }
+    }
 }
 
 /// Redis command representation
@@ -76,114 +258,523 @@ pub enum RedisResponse {
 /// Redis cluster proxy using Pingora TCP proxy for RESP protocol
 pub struct RedisClusterProxy {
     config: RedisConfig,
-    server: Server,
+    /// Name of the listening service
+    name: String,
     connector: TransportConnector,
     cluster_nodes: Arc<RwLock<HashMap<String, BasicPeer>>>,
+    node_weights: Arc<RwLock<HashMap<String, usize>>>,
     slot_mapping: Arc<RwLock<SlotMapping>>,
     health_manager: Option<Arc<crate::health::HealthCheckManager>>,
+    reload_receiver: Option<watch::Receiver<Arc<crate::config::Config>>>,
+    discovery_receiver: Option<watch::Receiver<Vec<crate::discovery::DiscoveredEndpoint>>>,
+    listeners: Option<(Listeners, String)>,
+    command_stats: Arc<CommandStats>,
+    large_payloads: Option<Arc<LargePayloads>>,
+    connection_limiter: Option<ConnectionLimiter>,
+    proxy_protocol: bool,
+    access_control: Option<Arc<AccessControl>>,
+    buffer_config: BufferConfig,
+    idle_timeout: Option<std::time::Duration>,
+    /// Client connections currently open
+    sessions: Arc<Gauge>,
+    /// Nodes taken out of keyless and replica routing for maintenance
+    drains: Option<Arc<Drains>>,
+    /// Topology refreshes requested when a node is lost
+    topology_refresh: Arc<TopologyRefresh>,
+    /// Faults injected into client commands for resilience testing
+    chaos: Option<Arc<Chaos>>,
+    /// Cores the worker threads are pinned to
+    cpu_pinning: Option<Arc<crate::core::cpu::CpuPinning>>,
+    /// Each node as last health checked, with what its checks recorded
+    node_health: Arc<RwLock<HashMap<std::net::SocketAddr, crate::core::Backend>>>,
+    health_events: Arc<HealthEvents>,
 }
 
-impl SlotMapping {
-    pub fn new() -> Self {
+impl RedisClusterProxy {
+    pub fn new(config: RedisConfig) -> Self {
         Self {
-            slot_to_backend: HashMap::new(),
-            backend_to_slots: HashMap::new(),
+            connector: upstream::new_connector(config.upstream_tls.as_ref()),
+            node_weights: Arc::new(RwLock::new(config.node_weights.clone())),
+            command_stats: Arc::new(CommandStats::new(
+                std::time::Duration::from_micros(config.slowlog_threshold_us),
+                config.slowlog_max_len,
+            )),
+            large_payloads: config.large_payloads.as_ref().map(|large_payloads| {
+                Arc::new(LargePayloads::new(
+                    "redis",
+                    large_payloads.threshold_bytes,
+                    large_payloads.top,
+                ))
+            }),
+            health_events: Arc::new(HealthEvents::new("redis", config.health_notifications.as_ref())),
+            config,
+            name: "Redis Cluster Proxy".to_string(),
+            cluster_nodes: Arc::new(RwLock::new(HashMap::new())),
+            slot_mapping: Arc::new(RwLock::new(SlotMapping::new())),
+            health_manager: None,
+            reload_receiver: None,
+            discovery_receiver: None,
+            listeners: None,
+            connection_limiter: None,
+            proxy_protocol: false,
+            access_control: None,
+            buffer_config: BufferConfig::default(),
+            idle_timeout: None,
+            sessions: Arc::default(),
+            drains: None,
+            topology_refresh: Arc::new(TopologyRefresh::new()),
+            chaos: None,
+            cpu_pinning: None,
+            node_health: Arc::default(),
         }
     }
 
-    /// Calculate Redis slot for a key using CRC16
-    pub fn calculate_slot(key: &str) -> u16 {
-        // Extract hash tag if present (text between first { and })
-        let hash_key = if let (Some(start), Some(end)) = (key.find('{'), key.find('}')) {
-            if end > start + 1 {
-                &key[start + 1..end]
-            } else {
-                key
-            }
-        } else {
-            key
-        };
+    /// Listen on the given listeners instead of the default plaintext 0.0.0.0:6379
+    pub fn with_listeners(mut self, listeners: Listeners, listen_addr: String) -> Self {
+        self.listeners = Some((listeners, listen_addr));
+        self
+    }
 
-        // Calculate CRC16 and mod 16384
-        crc16(hash_key.as_bytes()) % 16384
+    pub fn with_health_check(mut self) -> Self {
+        let health_checker = crate::health::redis::RedisHealthChecker::new()
+            .with_cluster_check(self.config.sharding == RedisSharding::Cluster)
+            .with_upstream_tls(self.config.upstream_tls.as_ref())
+            .with_auth(self.config.auth.as_ref());
+        self.health_manager = Some(Arc::new(
+            crate::health::HealthCheckManager::new(Box::new(health_checker))
+                .with_check_options(crate::health::CheckOptions {
+                    upstream_tls: self.config.upstream_tls.clone(),
+                    redis_sharding: self.config.sharding,
+                    redis_auth: self.config.auth.clone(),
+                })
+                .with_thresholds(self.config.health_failure_threshold, self.config.health_success_threshold)
+                .with_passive_failure_threshold(self.config.passive_failure_threshold)
+                .with_outlier_detection(self.config.outlier_detection.clone()),
+        ));
+        self
+    }
+
+    /// Keep draining nodes out of keyless commands and replica reads
+    pub fn with_drains(mut self, drains: Arc<Drains>) -> Self {
+        self.drains = Some(drains);
+        self
     }
 
-    /// Get backend ID for a given slot
-    pub fn get_backend_for_slot(&self, slot: u16) -> Option<String> {
-        self.slot_to_backend.get(&slot).cloned()
+    /// Delay, drop or redirect a share of client commands
+    pub fn with_chaos(mut self, chaos: Option<Arc<Chaos>>) -> Self {
+        self.chaos = chaos;
+        self
     }
 
-    /// Update slot mapping from cluster nodes
-    pub fn update_slot_mapping(&mut self, slot_ranges: HashMap<String, Vec<(u16, u16)>>) {
-        self.slot_to_backend.clear();
-        self.backend_to_slots = slot_ranges.clone();
+    /// Pin the worker threads serving clients to cores
+    pub fn with_cpu_pinning(mut self, cpu_pinning: Option<Arc<crate::core::cpu::CpuPinning>>) -> Self {
+        self.cpu_pinning = cpu_pinning;
+        self
+    }
 
-        for (backend_id, ranges) in slot_ranges {
-            for (start, end) in ranges {
-                for slot in start..=end {
-                    self.slot_to_backend.insert(slot, backend_id.clone());
+    /// Status of this proxy for the admin API: seed nodes and slot-serving
+    /// masters with their passive health, slot coverage and open clients
+    pub fn status_source(&self, listen_addr: String) -> StatusSource {
+        let name = self.name.clone();
+        let cluster_nodes = Arc::clone(&self.cluster_nodes);
+        let slot_mapping = Arc::clone(&self.slot_mapping);
+        let health_manager = self.health_manager.clone();
+        let drains = self.drains.clone();
+        let sessions = Arc::clone(&self.sessions);
+        Arc::new(move || {
+            let name = name.clone();
+            let listen_addr = listen_addr.clone();
+            let cluster_nodes = Arc::clone(&cluster_nodes);
+            let slot_mapping = Arc::clone(&slot_mapping);
+            let health_manager = health_manager.clone();
+            let drains = drains.clone();
+            let sessions = Arc::clone(&sessions);
+            Box::pin(async move {
+                let (covered, slot_counts) = {
+                    let slot_mapping = slot_mapping.read().await;
+                    (slot_mapping.covered_slots(), slot_mapping.slot_counts())
+                };
+                let mut nodes: Vec<String> = cluster_nodes.read().await.keys().cloned().collect();
+                nodes.extend(slot_counts.keys().cloned());
+                nodes.sort();
+                nodes.dedup();
+
+                let backends = nodes
+                    .into_iter()
+                    .map(|node| {
+                        let addr = node.parse().ok();
+                        BackendStatus {
+                            healthy: match (&health_manager, addr) {
+                                (Some(health_manager), Some(addr)) => health_manager.admits(addr),
+                                _ => true,
+                            },
+                            draining: match (&drains, addr) {
+                                (Some(drains), Some(addr)) => drains.is_draining(addr),
+                                _ => false,
+                            },
+                            sessions: None,
+                            slots: Some(slot_counts.get(&node).copied().unwrap_or_default()),
+                            id: node.clone(),
+                            addr: node,
+                        }
+                    })
+                    .collect();
+                InstanceStatus {
+                    name,
+                    mode: "redis".to_string(),
+                    listen_addr,
+                    active_sessions: usize::try_from(sessions.get()).unwrap_or_default(),
+                    backends,
+                    slot_coverage: Some(SlotCoverageStatus {
+                        covered,
+                        total: TOTAL_SLOTS,
+                    }),
                 }
-            }
-        }
+            })
+        })
     }
 
-    /// Check if all slots are covered
-    pub fn is_complete(&self) -> bool {
-        self.slot_to_backend.len() == 16384
+    /// Where this proxy routes the key of an explain request: its slot, the
+    /// master serving it and that master's replicas
+    pub fn explain_source(&self) -> ExplainSource {
+        let name = self.name.clone();
+        let slot_mapping = Arc::clone(&self.slot_mapping);
+        Arc::new(move |request| {
+            let name = name.clone();
+            let slot_mapping = Arc::clone(&slot_mapping);
+            Box::pin(async move {
+                let key = request.key?;
+                let slot = SlotMapping::calculate_slot(&key);
+                let slot_mapping = slot_mapping.read().await;
+                Some(InstanceExplanation {
+                    name,
+                    mode: "redis".to_string(),
+                    route: Route::Key(KeyRoute {
+                        hash_tag: SlotMapping::hash_tag(key.as_bytes())
+                            .map(|tag| String::from_utf8_lossy(tag).into_owned()),
+                        slot,
+                        node: slot_mapping.get_backend_for_slot(slot),
+                        replicas: slot_mapping.get_replicas_for_slot(slot).to_vec(),
+                        migration: slot_mapping.migration_of(slot).cloned(),
+                        key,
+                    }),
+                })
+            })
+        })
     }
-}
 
-impl RedisClusterProxy {
-    pub fn new(config: RedisConfig, server: Server) -> Self {
-        Self {
-            config,
-            server,
-            connector: TransportConnector::new(None),
-            cluster_nodes: Arc::new(RwLock::new(HashMap::new())),
-            slot_mapping: Arc::new(RwLock::new(SlotMapping::new())),
-            health_manager: None,
-        }
+    /// Name the listening service, e.g. after its `[[proxies]]` entry
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
     }
 
-    pub fn with_health_check(mut self) -> Self {
-        let health_checker = Box::new(crate::health::redis::RedisHealthChecker::new());
-        self.health_manager = Some(Arc::new(crate::health::HealthCheckManager::new(
-            health_checker,
-        )));
+    /// Command latency and slow log statistics, for the admin API
+    pub fn command_stats(&self) -> Arc<CommandStats> {
+        Arc::clone(&self.command_stats)
+    }
+
+    /// Large reply tracking, for the admin API, when enabled
+    pub fn large_payloads(&self) -> Option<Arc<LargePayloads>> {
+        self.large_payloads.clone()
+    }
+
+    /// Reject client connections past the limiter's `max_connections`
+    pub fn with_connection_limiter(mut self, connection_limiter: ConnectionLimiter) -> Self {
+        self.connection_limiter = Some(connection_limiter);
+        self
+    }
+
+    /// Expect a PROXY protocol header on every client connection
+    pub fn with_proxy_protocol(mut self, enabled: bool) -> Self {
+        self.proxy_protocol = enabled;
+        self
+    }
+
+    /// Reject clients outside the access control lists
+    pub fn with_access_control(mut self, access_control: Arc<AccessControl>) -> Self {
+        self.access_control = Some(access_control);
+        self
+    }
+
+    /// Read client data and batch commands with the given buffer settings
+    pub fn with_buffer_config(mut self, buffer_config: BufferConfig) -> Self {
+        self.buffer_config = buffer_config;
+        self
+    }
+
+    /// Close client connections that send nothing for `idle_timeout`
+    pub fn with_idle_timeout(mut self, idle_timeout: Option<std::time::Duration>) -> Self {
+        self.idle_timeout = idle_timeout;
         self
     }
 
+    /// Apply cluster node changes published by the configuration reloader
+    pub fn with_reload(mut self, receiver: watch::Receiver<Arc<crate::config::Config>>) -> Self {
+        self.reload_receiver = Some(receiver);
+        self
+    }
+
+    /// Apply seed node changes published by backend discovery
+    pub fn with_discovery(
+        mut self,
+        receiver: watch::Receiver<Vec<crate::discovery::DiscoveredEndpoint>>,
+    ) -> Self {
+        self.discovery_receiver = Some(receiver);
+        self
+    }
+
+    /// Replace the set of seed cluster nodes
+    pub async fn update_cluster_nodes(&self, nodes: &[String]) -> Result<(), Box<dyn Error + Send + Sync>> {
+        Self::apply_cluster_nodes(
+            &self.cluster_nodes,
+            nodes,
+            self.config.upstream_tls.as_ref(),
+            self.config.backend_tcp_keepalive.as_ref(),
+        )
+        .await
+    }
+
+    async fn apply_cluster_nodes(
+        cluster_nodes: &RwLock<HashMap<String, BasicPeer>>,
+        nodes: &[String],
+        tls: Option<&UpstreamTlsConfig>,
+        keepalive: Option<&TcpKeepaliveConfig>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        // Validate everything up front so a bad entry leaves the current set intact
+        for node in nodes {
+            node.parse::<std::net::SocketAddr>()
+                .map_err(|e| format!("Invalid Redis node {node}: {e}"))?;
+        }
+
+        let mut current = cluster_nodes.write().await;
+        current.retain(|endpoint, _| {
+            let keep = nodes.contains(endpoint);
+            if !keep {
+                log::info!("Removing Redis cluster node {endpoint}");
+            }
+            keep
+        });
+
+        for node in nodes {
+            if !current.contains_key(node) {
+                log::info!("Adding Redis cluster node {node}");
+                current.insert(node.clone(), upstream::new_peer(node, tls, keepalive));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Watch for configuration reloads and update the cluster nodes, and
+    /// the `shards` of standalone servers
+    fn spawn_reload_watcher(
+        mut receiver: watch::Receiver<Arc<crate::config::Config>>,
+        cluster_nodes: Arc<RwLock<HashMap<String, BasicPeer>>>,
+        node_weights: Arc<RwLock<HashMap<String, usize>>>,
+        shards: Option<Arc<RwLock<SlotMapping>>>,
+        tls: Option<UpstreamTlsConfig>,
+        keepalive: Option<TcpKeepaliveConfig>,
+    ) {
+        std::thread::spawn(move || {
+            let rt = crate::utils::background_runtime();
+            rt.block_on(async move {
+                while receiver.changed().await.is_ok() {
+                    let config = receiver.borrow_and_update().clone();
+                    // Backend discovery owns the seed nodes when configured
+                    if config.discovery.is_some() {
+                        continue;
+                    }
+                    if let crate::config::ProxyConfig::Redis { cluster_nodes: nodes, .. } =
+                        &config.proxy
+                    {
+                        // Hostnames stand for the nodes they currently resolve to
+                        let nodes = match crate::discovery::resolve_endpoints(nodes).await {
+                            Ok(nodes) => nodes,
+                            Err(e) => {
+                                log::error!("Failed to resolve reloaded Redis cluster nodes: {e}");
+                                continue;
+                            }
+                        };
+                        let addrs = crate::config::Endpoint::addrs(&nodes);
+                        if let Err(e) =
+                            Self::apply_cluster_nodes(&cluster_nodes, &addrs, tls.as_ref(), keepalive.as_ref())
+                                .await
+                        {
+                            log::error!("Failed to apply reloaded Redis cluster nodes: {e}");
+                            continue;
+                        }
+                        let weights = crate::config::Endpoint::weights(&nodes);
+                        if let Some(shards) = &shards {
+                            *shards.write().await = sharding::standalone_mapping(&addrs, &weights);
+                        }
+                        *node_weights.write().await = weights;
+                    }
+                }
+            });
+        });
+    }
+
+    /// Apply seed nodes found by backend discovery as they change, leaving
+    /// out nodes the catalog reports as failing, and the `shards` of
+    /// standalone servers
+    fn spawn_discovery_watcher(
+        mut receiver: watch::Receiver<Vec<crate::discovery::DiscoveredEndpoint>>,
+        cluster_nodes: Arc<RwLock<HashMap<String, BasicPeer>>>,
+        node_weights: Arc<RwLock<HashMap<String, usize>>>,
+        shards: Option<Arc<RwLock<SlotMapping>>>,
+        tls: Option<UpstreamTlsConfig>,
+        keepalive: Option<TcpKeepaliveConfig>,
+    ) {
+        std::thread::spawn(move || {
+            let rt = crate::utils::background_runtime();
+            rt.block_on(async move {
+                while receiver.changed().await.is_ok() {
+                    let endpoints = crate::discovery::DiscoveredEndpoint::healthy_endpoints(
+                        &receiver.borrow_and_update(),
+                    );
+                    let addrs = crate::config::Endpoint::addrs(&endpoints);
+                    if let Err(e) =
+                        Self::apply_cluster_nodes(&cluster_nodes, &addrs, tls.as_ref(), keepalive.as_ref()).await
+                    {
+                        log::error!("Failed to apply discovered Redis cluster nodes: {e}");
+                        continue;
+                    }
+                    let weights = crate::config::Endpoint::weights(&endpoints);
+                    if let Some(shards) = &shards {
+                        *shards.write().await = sharding::standalone_mapping(&addrs, &weights);
+                    }
+                    *node_weights.write().await = weights;
+                }
+            });
+        });
+    }
+
     /// Initialize cluster nodes from configuration
     pub async fn initialize_cluster_nodes(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
         let mut nodes = self.cluster_nodes.write().await;
 
         for endpoint in &self.config.cluster_nodes {
-            let peer = BasicPeer::new(endpoint);
+            let peer = upstream::new_peer(
+                endpoint,
+                self.config.upstream_tls.as_ref(),
+                self.config.backend_tcp_keepalive.as_ref(),
+            );
             nodes.insert(endpoint.clone(), peer);
         }
 
-        // Skip cluster topology discovery for now - configure fallback mapping directly
-        log::info!("Setting up Redis cluster with fallback configuration");
-        self.setup_fallback_mapping().await;
-
         Ok(())
     }
 
+    /// Discover the topology at startup, trying `attempts` times with a
+    /// backoff doubling from `backoff`
+    ///
+    /// When every attempt fails, all slots are mapped to the first
+    /// configured node that accepts connections, e.g. a node without
+    /// cluster support, until the periodic refresh succeeds. Fails when no
+    /// configured node is reachable.
+    pub async fn discover_initial_topology(
+        &self,
+        attempts: u32,
+        mut backoff: std::time::Duration,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if self.cluster_nodes.read().await.is_empty() {
+            log::warn!("No Redis seed nodes yet, topology discovery waits for backend discovery");
+            return Ok(());
+        }
+
+        for attempt in 1..=attempts {
+            match self.discover_cluster_topology().await {
+                Ok(()) => return Ok(()),
+                Err(e) if attempt < attempts => {
+                    log::warn!(
+                        "Initial topology discovery failed (attempt {attempt}/{attempts}), retrying in {}ms: {e}",
+                        backoff.as_millis()
+                    );
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(STARTUP_DISCOVERY_MAX_BACKOFF);
+                }
+                Err(e) => log::warn!("Initial topology discovery failed after {attempts} attempts: {e}"),
+            }
+        }
+
+        match self.first_reachable_node().await {
+            Some(node) => {
+                self.setup_fallback_mapping(&node).await;
+                Ok(())
+            }
+            None => Err(format!(
+                "No Redis cluster node reachable at startup, tried {}",
+                self.config.cluster_nodes.join(", ")
+            )
+            .into()),
+        }
+    }
+
+    /// First configured node accepting connections, in configuration order
+    async fn first_reachable_node(&self) -> Option<String> {
+        let timeout = std::time::Duration::from_millis(self.config.connection_timeout_ms);
+        let nodes = self.cluster_nodes.read().await.clone();
+        for addr in &self.config.cluster_nodes {
+            let Some(peer) = nodes.get(addr) else {
+                continue;
+            };
+            match tokio::time::timeout(timeout, self.connector.new_stream(peer)).await {
+                Ok(Ok(_)) => return Some(addr.clone()),
+                Ok(Err(e)) => log::warn!("Redis node {addr} is unreachable: {e}"),
+                Err(_) => log::warn!("Redis node {addr} is unreachable: connection timed out"),
+            }
+        }
+        None
+    }
+
     /// Setup fallback mapping for single node
-    async fn setup_fallback_mapping(&self) {
+    ///
+    /// All slots are mapped to `fallback_node` until the periodic topology
+    /// refresh succeeds.
+    async fn setup_fallback_mapping(&self, fallback_node: &str) {
         log::warn!("Setting up fallback single-node mapping");
         let mut mapping = self.slot_mapping.write().await;
         let mut slot_ranges = std::collections::HashMap::new();
-        slot_ranges.insert("127.0.0.1:7001".to_string(), vec![(0, 16383)]);
+        slot_ranges.insert(fallback_node.to_string(), vec![(0, 16383)]);
         mapping.update_slot_mapping(slot_ranges);
-        log::info!("Fallback mapping configured: all slots (0-16383) -> 127.0.0.1:7001");
+        log::info!("Fallback mapping configured: all slots (0-16383) -> {fallback_node}");
     }
 
     /// Discover cluster topology by querying CLUSTER NODES
     pub async fn discover_cluster_topology(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
-        let nodes = self.cluster_nodes.read().await;
+        Self::discover_topology(
+            &self.connector,
+            self.config.auth.as_ref(),
+            &self.cluster_nodes,
+            &self.slot_mapping,
+            self.config.topology_agreement,
+        )
+        .await
+    }
+
+    /// Query the known cluster nodes and swap in the topology `agreement`
+    /// chooses among their answers
+    async fn discover_topology(
+        connector: &TransportConnector,
+        auth: Option<&RedisAuthConfig>,
+        cluster_nodes: &RwLock<HashMap<String, BasicPeer>>,
+        slot_mapping: &RwLock<SlotMapping>,
+        agreement: TopologyAgreement,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        // Snapshot the peers so the node map is not locked while querying
+        let mut nodes: Vec<(String, BasicPeer)> = cluster_nodes
+            .read()
+            .await
+            .iter()
+            .map(|(addr, peer)| (addr.clone(), peer.clone()))
+            .collect();
         
         log::info!("Discovering cluster topology from {} nodes", nodes.len());
+        if agreement != TopologyAgreement::First {
+            nodes.sort_by(|a, b| a.0.cmp(&b.0));
+            return Self::discover_agreed_topology(connector, auth, &nodes, slot_mapping, agreement).await;
+        }
 
         for (addr, peer) in nodes.iter() {
             log::info!("Attempting to query cluster topology from: {}", addr);
@@ -191,13 +782,15 @@ impl RedisClusterProxy {
             // Add timeout to prevent hanging
             let query_result = tokio::time::timeout(
                 std::time::Duration::from_secs(10),
-                self.query_cluster_nodes(peer)
+                Self::query_cluster_nodes(connector, auth, peer)
             ).await;
             
             match query_result {
-                Ok(Ok(slot_mapping)) => {
-                    let mut mapping = self.slot_mapping.write().await;
-                    *mapping = slot_mapping;
+                Ok(Ok(new_mapping)) => {
+                    // Swap the whole mapping under the write lock so readers never
+                    // observe a partially updated table
+                    let mut mapping = slot_mapping.write().await;
+                    *mapping = new_mapping;
                     log::info!("Successfully discovered cluster topology from {}", addr);
                     return Ok(());
                 }
@@ -216,9 +809,68 @@ impl RedisClusterProxy {
         Err("Failed to discover cluster topology from any node".into())
     }
 
+    /// Query every node in `nodes` at once and swap in the view `agreement`
+    /// chooses among their answers
+    async fn discover_agreed_topology(
+        connector: &TransportConnector,
+        auth: Option<&RedisAuthConfig>,
+        nodes: &[(String, BasicPeer)],
+        slot_mapping: &RwLock<SlotMapping>,
+        agreement: TopologyAgreement,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let answers = futures::future::join_all(nodes.iter().map(|(addr, peer)| async move {
+            match tokio::time::timeout(
+                std::time::Duration::from_secs(10),
+                Self::query_cluster_nodes(connector, auth, peer),
+            )
+            .await
+            {
+                Ok(Ok(mapping)) => Some((addr.clone(), mapping)),
+                Ok(Err(e)) => {
+                    log::warn!("Failed to discover topology from {}: {}", addr, e);
+                    None
+                }
+                Err(_) => {
+                    log::warn!("Timeout querying cluster topology from {}", addr);
+                    None
+                }
+            }
+        }))
+        .await;
+
+        let views = agreement::group_views(answers.into_iter().flatten().collect());
+        if views.len() > 1 {
+            for view in &views {
+                log::warn!(
+                    "Cluster nodes disagree on the topology: {:?} answer config epoch {} with {} slots",
+                    view.nodes,
+                    view.mapping.config_epoch(),
+                    view.mapping.covered_slots()
+                );
+            }
+            crate::metrics::global()
+                .counter(
+                    "puerta_redis_topology_disagreements_total",
+                    "Topology discoveries whose cluster nodes answered different slot maps",
+                )
+                .inc();
+        }
+
+        let view = agreement::choose_view(views, agreement)
+            .map_err(|e| format!("Failed to agree on the cluster topology: {e}"))?;
+        log::info!(
+            "Applying cluster topology answered by {:?} at config epoch {}",
+            view.nodes,
+            view.mapping.config_epoch()
+        );
+        *slot_mapping.write().await = view.mapping;
+        Ok(())
+    }
+
     /// Query CLUSTER NODES from a specific peer
     async fn query_cluster_nodes(
-        &self,
+        connector: &TransportConnector,
+        auth: Option<&RedisAuthConfig>,
         peer: &BasicPeer,
     ) -> Result<SlotMapping, Box<dyn Error + Send + Sync>> {
         use tokio::io::{AsyncReadExt, AsyncWriteExt};
@@ -227,11 +879,15 @@ impl RedisClusterProxy {
         let connect_timeout = std::time::Duration::from_secs(3);
         let mut stream = tokio::time::timeout(
             connect_timeout,
-            self.connector.new_stream(peer)
+            connector.new_stream(peer)
         ).await
             .map_err(|_| "Connection timeout")?
             .map_err(|e| format!("Connection failed: {}", e))?;
 
+        if let Some(auth) = auth {
+            auth::authenticate(&mut stream, auth).await?;
+        }
+
         // Send CLUSTER NODES command in RESP format
         let cmd_bytes = b"*2\r\n$7\r\nCLUSTER\r\n$5\r\nNODES\r\n";
         stream.write_all(cmd_bytes).await?;
@@ -255,9 +911,16 @@ impl RedisClusterProxy {
                 break;
             }
             buffer.extend_from_slice(&temp_buf[..n]);
+
+            // Nodes without cluster support answer with an error
+            if buffer.starts_with(b"-") {
+                if let Some(end) = buffer.windows(2).position(|window| window == b"\r\n") {
+                    return Err(String::from_utf8_lossy(&buffer[1..end]).into_owned().into());
+                }
+            }
             
             // Check if we have a complete response
-            if let Some(end_pos) = self.find_resp_end(&buffer) {
+            if let Some(end_pos) = Self::find_resp_end(&buffer) {
                 buffer.truncate(end_pos);
                 break;
             }
@@ -269,16 +932,16 @@ impl RedisClusterProxy {
         }
 
         // Parse RESP response
-        let response_str = self.parse_cluster_nodes_response(&buffer)?;
+        let response_str = Self::parse_cluster_nodes_response(&buffer)?;
         
         // Parse cluster nodes output and create slot mapping
-        let slot_mapping = self.parse_cluster_nodes_output(&response_str)?;
+        let slot_mapping = SlotMapping::from_cluster_nodes(&response_str);
         
         Ok(slot_mapping)
     }
 
     /// Find the end of a RESP response in the buffer
-    fn find_resp_end(&self, buffer: &[u8]) -> Option<usize> {
+    fn find_resp_end(buffer: &[u8]) -> Option<usize> {
         // Look for bulk string format: $<length>\r\n<data>\r\n
         if buffer.len() < 4 || buffer[0] != b'$' {
             return None;
@@ -312,7 +975,7 @@ impl RedisClusterProxy {
     }
 
     /// Parse CLUSTER NODES RESP response
-    fn parse_cluster_nodes_response(&self, buffer: &[u8]) -> Result<String, Box<dyn Error + Send + Sync>> {
+    fn parse_cluster_nodes_response(buffer: &[u8]) -> Result<String, Box<dyn Error + Send + Sync>> {
         if buffer.len() < 4 || buffer[0] != b'$' {
             return Err("Invalid RESP bulk string format".into());
         }
@@ -344,79 +1007,363 @@ impl RedisClusterProxy {
         Ok(String::from_utf8_lossy(data).to_string())
     }
 
-    /// Parse CLUSTER NODES output and create slot mapping
-    fn parse_cluster_nodes_output(&self, cluster_nodes: &str) -> Result<SlotMapping, Box<dyn Error + Send + Sync>> {
-        let mut slot_mapping = SlotMapping::new();
-        let mut slot_ranges = HashMap::new();
+    /// Start a background task that periodically re-discovers the cluster
+    /// topology so slot migrations and failovers are picked up, and
+    /// re-discovers it right away when a command loses its node or many
+    /// slots are moving
+    pub fn start_topology_refresh(&self) {
+        let refresh_interval_sec = self.config.slot_refresh_interval_sec;
+        let periodic = refresh_interval_sec > 0;
+        if !periodic {
+            log::warn!("Slot refresh interval is 0, periodic topology refresh disabled");
+        }
 
-        for line in cluster_nodes.lines() {
-            if line.trim().is_empty() {
-                continue;
-            }
+        let cluster_nodes = Arc::clone(&self.cluster_nodes);
+        let slot_mapping = Arc::clone(&self.slot_mapping);
+        let topology_refresh = Arc::clone(&self.topology_refresh);
+        let tls = self.config.upstream_tls.clone();
+        let auth = self.config.auth.clone();
+        let agreement = self.config.topology_agreement;
+
+        // Run in a separate thread with its own runtime to avoid conflicts with Pingora
+        std::thread::spawn(move || {
+            let rt = crate::utils::background_runtime();
+            rt.block_on(async move {
+                if periodic {
+                    log::info!("Starting Redis topology refresh every {refresh_interval_sec}s");
+                }
+                let connector = upstream::new_connector(tls.as_ref());
+                let mut interval = tokio::time::interval(
+                    tokio::time::Duration::from_secs(refresh_interval_sec.max(1))
+                );
+                // The first tick completes immediately; startup already ran discovery
+                interval.tick().await;
+
+                loop {
+                    tokio::select! {
+                        _ = interval.tick(), if periodic => {}
+                        _ = topology_refresh.requested() => {
+                            log::info!("Refreshing Redis topology on request");
+                        }
+                    }
 
-            let parts: Vec<&str> = line.split_whitespace().collect();
-            if parts.len() < 8 {
-                continue; // Invalid line format
-            }
+                    if let Err(e) =
+                        Self::discover_topology(
+                            &connector,
+                            auth.as_ref(),
+                            &cluster_nodes,
+                            &slot_mapping,
+                            agreement,
+                        )
+                        .await
+                    {
+                        log::warn!("Topology refresh failed, keeping current slot mapping: {e}");
+                    }
+                    topology_refresh.complete();
+                }
+            });
+        });
+    }
 
-            let _node_id = parts[0];
-            let address = parts[1];
-            let flags = parts[2];
-            // parts[3] is master node id (for slaves)
-            // parts[4] is ping_sent
-            // parts[5] is pong_recv
-            // parts[6] is config_epoch
-            // parts[7] is link_state
-            // parts[8..] are slot ranges
+    /// Route by the snapshot configured in `snapshot`, ejecting the nodes
+    /// it found unhealthy, returning whether a recent enough one was found
+    async fn restore_snapshot(&self) -> bool {
+        let Some(config) = &self.config.snapshot else {
+            return false;
+        };
+        let max_age = (config.max_age_sec > 0).then(|| std::time::Duration::from_secs(config.max_age_sec));
+        let Some(snapshot) = TopologySnapshot::load(std::path::Path::new(&config.path), max_age) else {
+            return false;
+        };
 
-            // Only process master nodes (not slaves)
-            if flags.contains("slave") || flags.contains("fail") {
-                continue;
+        let mapping = snapshot.mapping();
+        if mapping.covered_slots() == 0 {
+            return false;
+        }
+        log::info!(
+            "Restored Redis topology from snapshot {} taken {}s ago: {} masters, {} slots",
+            config.path,
+            snapshot.age().as_secs(),
+            mapping.backends().len(),
+            mapping.covered_slots()
+        );
+        *self.slot_mapping.write().await = mapping;
+        if let Some(health_manager) = &self.health_manager {
+            for node in &snapshot.unhealthy {
+                if let Ok(addr) = node.parse() {
+                    health_manager.eject(addr);
+                }
             }
+        }
+        true
+    }
 
-            // Extract IP:port from address (remove @cluster_port if present)
-            let addr = if let Some(at_pos) = address.find('@') {
-                &address[..at_pos]
-            } else {
-                address
-            };
-
-            let mut ranges = Vec::new();
-
-            // Parse slot ranges (format: "0-5460" or "5461-10922" or single slots "16383")
-            for slot_info in &parts[8..] {
-                if slot_info.contains('-') {
-                    // Range format: "start-end"
-                    let range_parts: Vec<&str> = slot_info.split('-').collect();
-                    if range_parts.len() == 2 {
-                        if let (Ok(start), Ok(end)) = (range_parts[0].parse::<u16>(), range_parts[1].parse::<u16>()) {
-                            ranges.push((start, end));
+    /// Write the topology to the configured snapshot file every
+    /// `interval_sec`, with the nodes health checking ejected
+    fn start_snapshots(&self) {
+        let Some(config) = self.config.snapshot.clone() else {
+            return;
+        };
+        let slot_mapping = Arc::clone(&self.slot_mapping);
+        let health_manager = self.health_manager.clone();
+
+        std::thread::spawn(move || {
+            let rt = crate::utils::background_runtime();
+            rt.block_on(async move {
+                log::info!("Writing Redis topology snapshots to {} every {}s", config.path, config.interval_sec);
+                let mut interval = tokio::time::interval(std::time::Duration::from_secs(config.interval_sec));
+                loop {
+                    interval.tick().await;
+                    let snapshot = {
+                        let slot_mapping = slot_mapping.read().await;
+                        // Nothing worth keeping before the first discovery
+                        if slot_mapping.covered_slots() == 0 {
+                            continue;
                         }
+                        let mut nodes = slot_mapping.backends();
+                        nodes.extend(slot_mapping.replica_addrs().cloned());
+                        let unhealthy = nodes
+                            .into_iter()
+                            .filter(|node| {
+                                matches!(
+                                    (&health_manager, node.parse::<std::net::SocketAddr>()),
+                                    (Some(health_manager), Ok(addr)) if !health_manager.admits(addr)
+                                )
+                            })
+                            .collect();
+                        TopologySnapshot::capture(&slot_mapping, unhealthy)
+                    };
+                    if let Err(e) = snapshot.save(std::path::Path::new(&config.path)) {
+                        log::warn!("Failed to write Redis topology snapshot {}: {e}", config.path);
+                    }
+                }
+            });
+        });
+    }
+
+    /// Health check the seed nodes, masters and replicas every
+    /// `health_check_interval_sec` in the background
+    fn start_health_checks(&self) {
+        let Some(health_manager) = self.health_manager.clone() else {
+            return;
+        };
+        let cluster_nodes = Arc::clone(&self.cluster_nodes);
+        let slot_mapping = Arc::clone(&self.slot_mapping);
+        let node_health = Arc::clone(&self.node_health);
+        let health_events = Arc::clone(&self.health_events);
+        let interval_sec = self.config.health_check_interval_sec.max(1);
+
+        std::thread::spawn(move || {
+            let rt = crate::utils::background_runtime();
+            rt.block_on(async move {
+                log::info!("Health checking Redis nodes every {interval_sec}s");
+                let mut interval = tokio::time::interval(std::time::Duration::from_secs(interval_sec));
+                loop {
+                    interval.tick().await;
+                    let mut nodes: Vec<String> = cluster_nodes.read().await.keys().cloned().collect();
+                    {
+                        let slot_mapping = slot_mapping.read().await;
+                        nodes.extend(slot_mapping.backends());
+                        nodes.extend(slot_mapping.replica_addrs().cloned());
                     }
-                } else if let Ok(slot) = slot_info.parse::<u16>() {
-                    // Single slot
-                    ranges.push((slot, slot));
+                    Self::check_nodes(&health_manager, &node_health, &health_events, nodes).await;
                 }
+            });
+        });
+    }
+
+    /// Health check `nodes` concurrently, ejecting those failing their
+    /// checks and recording the role and slots they report
+    async fn check_nodes(
+        health_manager: &crate::health::HealthCheckManager,
+        node_health: &RwLock<HashMap<std::net::SocketAddr, crate::core::Backend>>,
+        health_events: &HealthEvents,
+        nodes: Vec<String>,
+    ) {
+        let mut backends: HashMap<std::net::SocketAddr, crate::core::Backend> = HashMap::new();
+        {
+            let node_health = node_health.read().await;
+            for node in nodes {
+                let Ok(addr) = node.parse::<std::net::SocketAddr>() else {
+                    continue;
+                };
+                let backend = node_health
+                    .get(&addr)
+                    .cloned()
+                    .unwrap_or_else(|| crate::core::Backend::new_redis(node, addr, String::new()));
+                backends.insert(addr, backend);
             }
+        }
 
-            if !ranges.is_empty() {
-                slot_ranges.insert(addr.to_string(), ranges);
+        let checks = backends.into_values().map(|mut backend| async move {
+            let was_healthy = backend.healthy;
+            let status = health_manager.check_backend_health(&mut backend).await;
+            (backend, was_healthy, status)
+        });
+        let mut checked = HashMap::new();
+        for (backend, was_healthy, status) in futures::future::join_all(checks).await {
+            if !backend.healthy {
+                // Stays out until it passes enough checks
+                health_manager.eject(backend.addr);
+            }
+            if was_healthy != backend.healthy {
+                let reason = if backend.healthy {
+                    log::info!("Redis node {} is now healthy", backend.id);
+                    "health checks passed".to_string()
+                } else {
+                    log::warn!("Redis node {} is now unhealthy: {status}", backend.id);
+                    status.to_string()
+                };
+                health_events.record(&backend.id, backend.addr, backend.healthy, &reason);
             }
+            checked.insert(backend.addr, backend);
         }
+        // Nodes that left the topology are forgotten
+        *node_health.write().await = checked;
+    }
 
-        slot_mapping.update_slot_mapping(slot_ranges);
-        Ok(slot_mapping)
+    /// Each node as last health checked: whether it passes and the node
+    /// ID, role and slots its `CLUSTER NODES` reply reported
+    pub async fn checked_nodes(&self) -> Vec<crate::core::Backend> {
+        self.node_health.read().await.values().cloned().collect()
+    }
+
+    /// Keep `pool_min_idle` idle connections open to every master, and to
+    /// replicas when they serve reads, skipping nodes ejected by health
+    /// checking
+    ///
+    /// Nodes joining the cluster or recovering are warmed up on the next
+    /// pass, before clients need them.
+    fn start_warm_up(
+        pool: &ConnectionPool,
+        slot_mapping: Arc<RwLock<SlotMapping>>,
+        health_manager: Option<Arc<crate::health::HealthCheckManager>>,
+        config: &RedisConfig,
+    ) {
+        let tls = config.upstream_tls.clone();
+        let keepalive = config.backend_tcp_keepalive.clone();
+        let read_from_replicas = config.read_from_replicas;
+        // Connections are opened on the warm-up runtime into the shared pool
+        let pool = pool.shared_with(upstream::new_connector(tls.as_ref()));
+
+        std::thread::spawn(move || {
+            let rt = crate::utils::background_runtime();
+            rt.block_on(async move {
+                let mut interval = tokio::time::interval(WARM_UP_INTERVAL);
+                loop {
+                    interval.tick().await;
+                    let nodes = {
+                        let slot_mapping = slot_mapping.read().await;
+                        let mut nodes: Vec<_> = slot_mapping
+                            .backends()
+                            .into_iter()
+                            .map(|node| (node, ConnectionMode::ReadWrite))
+                            .collect();
+                        if read_from_replicas {
+                            nodes.extend(
+                                slot_mapping
+                                    .replica_addrs()
+                                    .map(|replica| (replica.clone(), ConnectionMode::ReadOnly)),
+                            );
+                        }
+                        nodes
+                    };
+                    Self::warm_up_nodes(
+                        &pool,
+                        &nodes,
+                        health_manager.as_deref(),
+                        tls.as_ref(),
+                        keepalive.as_ref(),
+                    )
+                    .await;
+                }
+            });
+        });
+    }
+
+    /// Top up the idle connections of each admitted node in `nodes`, in
+    /// the mode given with it
+    async fn warm_up_nodes(
+        pool: &ConnectionPool,
+        nodes: &[(String, ConnectionMode)],
+        health_manager: Option<&crate::health::HealthCheckManager>,
+        tls: Option<&UpstreamTlsConfig>,
+        keepalive: Option<&TcpKeepaliveConfig>,
+    ) {
+        for (node, mode) in nodes {
+            let admitted = match (health_manager, node.parse::<std::net::SocketAddr>()) {
+                (Some(health_manager), Ok(addr)) => health_manager.admits(addr),
+                _ => true,
+            };
+            if !admitted {
+                continue;
+            }
+            if let Err(e) = pool.warm_up(&upstream::new_peer(node, tls, keepalive), *mode).await {
+                log::warn!("Failed to warm up connections to Redis node {node}: {e}");
+            }
+        }
     }
 
     /// Run the Redis cluster proxy
-    pub async fn run_redis_proxy(self) -> Result<(), Box<dyn Error + Send + Sync>> {
-        log::info!("Starting Redis Cluster proxy using Pingora framework");
+    pub async fn run_redis_proxy(self, mut server: Server) -> Result<(), Box<dyn Error + Send + Sync>> {
+        server.bootstrap();
+        self.add_to_server(&mut server).await?;
+
+        // Run the server
+        server.run_forever();
+    }
+
+    /// Discover the cluster topology, start the background refresh and
+    /// watchers, and add the RESP listening service to `server`
+    pub async fn add_to_server(self, server: &mut Server) -> Result<(), Box<dyn Error + Send + Sync>> {
+        log::info!("Starting Redis Cluster proxy {} using Pingora framework", self.name);
 
         // Initialize cluster nodes and topology
         self.initialize_cluster_nodes().await?;
+        let shards = match self.config.sharding {
+            RedisSharding::Cluster => {
+                if self.restore_snapshot().await {
+                    // Discovery replaces the snapshot in the background
+                    self.topology_refresh.request();
+                } else {
+                    self.discover_initial_topology(STARTUP_DISCOVERY_ATTEMPTS, STARTUP_DISCOVERY_BACKOFF)
+                        .await?;
+                }
+                self.start_topology_refresh();
+                self.start_snapshots();
+                None
+            }
+            RedisSharding::Standalone => {
+                *self.slot_mapping.write().await =
+                    sharding::standalone_mapping(&self.config.cluster_nodes, &self.config.node_weights);
+                log::info!("Sharding keys across standalone Redis servers: {:?}", self.config.cluster_nodes);
+                Some(Arc::clone(&self.slot_mapping))
+            }
+        };
+        self.start_health_checks();
+
+        if let Some(receiver) = self.discovery_receiver.clone() {
+            Self::spawn_discovery_watcher(
+                receiver,
+                self.cluster_nodes.clone(),
+                self.node_weights.clone(),
+                shards.clone(),
+                self.config.upstream_tls.clone(),
+                self.config.backend_tcp_keepalive.clone(),
+            );
+        }
+        if let Some(receiver) = self.reload_receiver.clone() {
+            Self::spawn_reload_watcher(
+                receiver,
+                self.cluster_nodes.clone(),
+                self.node_weights.clone(),
+                shards.clone(),
+                self.config.upstream_tls.clone(),
+                self.config.backend_tcp_keepalive.clone(),
+            );
+        }
 
-        let mut server = self.server;
-        server.bootstrap();
+        let capture = Capture::from_config(self.config.capture.as_ref(), "redis", auth::carries_credentials)?;
 
         // Create Redis protocol proxy app
         let redis_app = RedisProtocolApp::new(
@@ -424,22 +1371,66 @@ impl RedisClusterProxy {
             self.cluster_nodes.clone(),
             self.slot_mapping.clone(),
             self.config.max_redirects,
-        );
+        )
+        .with_pool_config(self.config.pool_config())
+        .with_upstream_tls(self.config.upstream_tls.clone())
+        .with_backend_tcp_keepalive(self.config.backend_tcp_keepalive.clone())
+        .with_auth(self.config.auth.clone(), self.config.client_auth.clone())
+        .with_read_from_replicas(self.config.read_from_replicas)
+        .with_latency_aware(self.config.balance_strategy == BalanceStrategy::LatencyAware)
+        .with_fan_out_keyless_commands(self.config.fan_out_keyless_commands)
+        .with_max_command_size(self.config.max_command_size)
+        .with_command_filter(self.config.command_filter.as_ref().map(CommandFilter::new))
+        .with_mirror(Mirror::from_config(self.config.mirror.as_ref(), "redis"))
+        .with_capture(capture)
+        .with_cluster_view(self.config.cluster_view.as_ref().map(ClusterView::new))
+        .with_circuit_breaker(self.config.circuit_breaker.clone().map(CircuitBreaker::new))
+        .with_retry_attempts(self.config.retry_attempts)
+        .with_node_weights(self.node_weights.clone())
+        .with_health_manager(self.health_manager.clone())
+        .with_command_stats(self.command_stats.clone())
+        .with_large_payloads(self.large_payloads.clone())
+        .with_sessions(Arc::clone(&self.sessions))
+        .with_drains(self.drains.clone())
+        .with_health_events(Some(Arc::clone(&self.health_events)))
+        .with_connection_limiter(self.connection_limiter)
+        .with_proxy_protocol(self.proxy_protocol)
+        .with_access_control(self.access_control)
+        .with_buffer_config(self.buffer_config)
+        .with_idle_timeout(self.idle_timeout)
+        .with_chaos(self.chaos);
+        // Standalone servers have no replicas to take over their slots
+        let redis_app = match self.config.sharding {
+            RedisSharding::Cluster => redis_app.with_failover(
+                Arc::clone(&self.topology_refresh),
+                std::time::Duration::from_millis(self.config.failover_timeout_ms),
+            ),
+            RedisSharding::Standalone => redis_app,
+        };
+        if self.config.pool_min_idle > 0 {
+            Self::start_warm_up(
+                redis_app.pool(),
+                self.slot_mapping.clone(),
+                self.health_manager.clone(),
+                &self.config,
+            );
+        }
 
         // Create TCP listening service for Redis RESP protocol
+        let (listeners, listen_addr) = self
+            .listeners
+            .unwrap_or_else(|| (Listeners::tcp("0.0.0.0:6379"), "0.0.0.0:6379".to_string())); // Default Redis port
         let tcp_service = Service::with_listeners(
-            "Redis Cluster Proxy".to_string(),
-            Listeners::tcp("0.0.0.0:6379"), // Default Redis port
-            redis_app,
+            self.name.clone(),
+            listeners,
+            crate::core::cpu::Pinned::new(redis_app, self.cpu_pinning),
         );
 
         server.add_service(tcp_service);
 
-        log::info!("Redis Cluster proxy listening on: 0.0.0.0:6379");
+        log::info!("Redis Cluster proxy {} listening on: {listen_addr}", self.name);
         log::info!("Proxying to cluster nodes: {:?}", self.config.cluster_nodes);
-
-        // Run the server
-        server.run_forever();
+        Ok(())
     }
 
     /// Get cluster nodes for management
@@ -458,12 +1449,102 @@ impl RedisClusterProxy {
     }
 }
 
+/// Most client commands framed and executed as one pipelined batch
+const MAX_PIPELINE_DEPTH: usize = 512;
+
+/// Time between passes topping up idle connections to every node
+const WARM_UP_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Topology discovery attempts at startup before falling back or failing
+const STARTUP_DISCOVERY_ATTEMPTS: u32 = 5;
+
+/// Wait after the first failed startup discovery, doubled after each attempt
+const STARTUP_DISCOVERY_BACKOFF: std::time::Duration = std::time::Duration::from_millis(500);
+
+/// Longest wait between startup discovery attempts
+const STARTUP_DISCOVERY_MAX_BACKOFF: std::time::Duration = std::time::Duration::from_secs(4);
+
+/// A framed client command awaiting its reply
+enum PendingCommand {
+    /// Answered by the proxy itself
+    Answered(Bytes),
+    /// Forwarded unchanged to the node owning its slot
+    Forward { command: RedisCommand, raw: Bytes },
+    /// Fanned out across slots
+    Split { command: RedisCommand, plan: SplitPlan },
+    /// Part of a MULTI/EXEC transaction or WATCH that needs the cluster
+    Transaction(TransactionStep),
+    /// Handled by the proxy across nodes: scripts and functions, and SCAN
+    Cluster { command: RedisCommand, raw: Bytes },
+}
+
+/// Commands wrapped around a queued transaction
+const MULTI_COMMAND: &[u8] = b"*1\r\n$5\r\nMULTI\r\n";
+const EXEC_COMMAND: &[u8] = b"*1\r\n$4\r\nEXEC\r\n";
+const UNWATCH_COMMAND: &[u8] = b"*1\r\n$7\r\nUNWATCH\r\n";
+/// Sent ahead of a command redirected by ASK
+const ASKING_COMMAND: &[u8] = b"*1\r\n$6\r\nASKING\r\n";
+
 /// Redis Protocol App using Pingora for RESP protocol handling
 pub struct RedisProtocolApp {
-    connector: TransportConnector,
+    pool: ConnectionPool,
     cluster_nodes: Arc<RwLock<HashMap<String, BasicPeer>>>,
     slot_mapping: Arc<RwLock<SlotMapping>>,
-    max_redirects: u8,
+    /// Picks the node for each command and decides how redirects are followed
+    router: ClusterRouter,
+    upstream_tls: Option<UpstreamTlsConfig>,
+    backend_tcp_keepalive: Option<TcpKeepaliveConfig>,
+    client_auth: Option<RedisAuthConfig>,
+    /// Passive health checking of nodes from live traffic
+    health_manager: Option<Arc<crate::health::HealthCheckManager>>,
+    /// Per-command latency and slow log
+    command_stats: Option<Arc<CommandStats>>,
+    /// Replies above the large payload threshold, per key
+    large_payloads: Option<Arc<LargePayloads>>,
+    /// Events, metrics and notifications for node ejections and recoveries
+    health_events: Option<Arc<HealthEvents>>,
+    /// Client connections currently open
+    sessions: Arc<Gauge>,
+    /// Nodes being drained for maintenance
+    drains: Option<Arc<Drains>>,
+    /// Cap on concurrent client connections
+    connection_limiter: Option<ConnectionLimiter>,
+    /// Read the client address from a PROXY protocol header
+    proxy_protocol: bool,
+    /// Client IP allow and deny lists
+    access_control: Option<Arc<AccessControl>>,
+    /// Client read size and the cap on command bytes per batch
+    buffer_config: BufferConfig,
+    /// Close client connections without traffic for this long
+    idle_timeout: Option<std::time::Duration>,
+    /// Scripts loaded through the proxy, for replaying on other nodes
+    scripts: ScriptCache,
+    /// Run cluster-wide keyless commands on every master
+    fan_out_keyless_commands: bool,
+    /// Commands refused without contacting a node
+    command_filter: Option<CommandFilter>,
+    /// CLUSTER topology commands answered as a single node
+    cluster_view: Option<ClusterView>,
+    /// Fail fast on nodes whose recent requests mostly failed
+    circuit_breaker: Option<CircuitBreaker>,
+    /// Other nodes tried when connecting fails for a command any node can serve
+    retry_attempts: u32,
+    /// Topology refreshes requested when a command's master is lost
+    topology_refresh: Option<Arc<TopologyRefresh>>,
+    /// How long a command whose master was lost waits for its slot to move
+    failover_timeout: std::time::Duration,
+    /// Faults injected into client commands for resilience testing
+    chaos: Option<Arc<Chaos>>,
+    /// Copies of a share of client connections sent to a shadow node
+    mirror: Option<Arc<Mirror>>,
+    /// Client commands written to capture files
+    capture: Option<Arc<Capture>>,
+    /// Largest command accepted from a client
+    max_command_size: usize,
+    /// Importing nodes being connected to ahead of ASK redirects
+    prefetching: Arc<std::sync::Mutex<HashSet<String>>>,
+    /// Node latency estimates for latency-aware routing
+    latencies: Option<Arc<LatencyTracker>>,
 }
 
 impl RedisProtocolApp {
@@ -473,47 +1554,379 @@ impl RedisProtocolApp {
         slot_mapping: Arc<RwLock<SlotMapping>>,
         max_redirects: u8,
     ) -> Self {
+        let router = ClusterRouter::new(Arc::clone(&cluster_nodes), Arc::clone(&slot_mapping), max_redirects);
         Self {
-            connector,
+            pool: ConnectionPool::new(connector, PoolConfig::default()),
             cluster_nodes,
             slot_mapping,
-            max_redirects,
+            router,
+            upstream_tls: None,
+            backend_tcp_keepalive: None,
+            client_auth: None,
+            health_manager: None,
+            command_stats: None,
+            large_payloads: None,
+            health_events: None,
+            sessions: Arc::default(),
+            drains: None,
+            connection_limiter: None,
+            proxy_protocol: false,
+            access_control: None,
+            buffer_config: BufferConfig::default(),
+            idle_timeout: None,
+            scripts: ScriptCache::new(),
+            fan_out_keyless_commands: true,
+            command_filter: None,
+            cluster_view: None,
+            circuit_breaker: None,
+            retry_attempts: 0,
+            topology_refresh: None,
+            failover_timeout: std::time::Duration::ZERO,
+            chaos: None,
+            mirror: None,
+            capture: None,
+            max_command_size: resp::DEFAULT_MAX_FRAME_SIZE,
+            prefetching: Arc::default(),
+            latencies: None,
         }
     }
 
-    /// Parse Redis command from raw data using complete RESP parser
-    pub async fn parse_redis_command(
-        &self,
-        data: &[u8],
-    ) -> Result<RedisCommand, Box<dyn Error + Send + Sync>> {
-        use crate::modes::redis::resp::{RespParser, RespValue};
-        use bytes::Bytes;
-        
-        let mut buf = bytes::BytesMut::from(data);
-        
-        match RespParser::parse(&mut buf)? {
-            Some(RespValue::Array(Some(elements))) => {
-                if elements.is_empty() {
-                    return Err("Empty command array".into());
-                }
-                
-                // First element should be the command
-                let command = match &elements[0] {
-                    RespValue::BulkString(Some(cmd_bytes)) => {
-                        String::from_utf8_lossy(cmd_bytes).to_uppercase()
-                    }
-                    RespValue::SimpleString(cmd_str) => cmd_str.to_uppercase(),
-                    _ => return Err("Invalid command format".into()),
-                };
-                
-                // Extract arguments
-                let mut args = Vec::new();
-                let mut key: Option<String> = None;
-                
-                for (i, element) in elements.iter().skip(1).enumerate() {
-                    match element {
-                        RespValue::BulkString(Some(arg_bytes)) => {
-                            let arg_str = String::from_utf8_lossy(arg_bytes).to_string();
+    /// Reject client connections past the limiter's `max_connections`
+    pub fn with_connection_limiter(mut self, connection_limiter: Option<ConnectionLimiter>) -> Self {
+        self.connection_limiter = connection_limiter;
+        self
+    }
+
+    /// Expect a PROXY protocol header and log its client address
+    pub fn with_proxy_protocol(mut self, enabled: bool) -> Self {
+        self.proxy_protocol = enabled;
+        self
+    }
+
+    /// Reject clients outside the access control lists
+    pub fn with_access_control(mut self, access_control: Option<Arc<AccessControl>>) -> Self {
+        self.access_control = access_control;
+        self
+    }
+
+    /// Read client data and batch commands with the given buffer settings
+    pub fn with_buffer_config(mut self, buffer_config: BufferConfig) -> Self {
+        self.buffer_config = buffer_config;
+        self
+    }
+
+    /// Close client connections that send nothing for `idle_timeout`
+    pub fn with_idle_timeout(mut self, idle_timeout: Option<std::time::Duration>) -> Self {
+        self.idle_timeout = idle_timeout;
+        self
+    }
+
+    /// Record the latency of every forwarded command
+    pub fn with_command_stats(mut self, command_stats: Arc<CommandStats>) -> Self {
+        self.command_stats = Some(command_stats);
+        self
+    }
+
+    /// Track the keys of replies above the large payload threshold
+    pub fn with_large_payloads(mut self, large_payloads: Option<Arc<LargePayloads>>) -> Self {
+        self.large_payloads = large_payloads;
+        self
+    }
+
+    /// Count open client connections on `sessions`
+    pub fn with_sessions(mut self, sessions: Arc<Gauge>) -> Self {
+        self.sessions = sessions;
+        self
+    }
+
+    /// Route keyless commands and replica reads away from draining nodes
+    pub fn with_drains(mut self, drains: Option<Arc<Drains>>) -> Self {
+        self.drains = drains;
+        self
+    }
+
+    /// Record node ejections and recoveries as health events
+    pub fn with_health_events(mut self, health_events: Option<Arc<HealthEvents>>) -> Self {
+        self.health_events = health_events;
+        self
+    }
+
+    /// Count connection failures per node and avoid ejected nodes where
+    /// another node can serve the command
+    pub fn with_health_manager(
+        mut self,
+        health_manager: Option<Arc<crate::health::HealthCheckManager>>,
+    ) -> Self {
+        self.health_manager = health_manager;
+        self
+    }
+
+    /// Whether `addr` is currently kept out of routing by passive health
+    /// checking or outlier detection
+    fn is_ejected(&self, addr: &str) -> bool {
+        match (&self.health_manager, addr.parse::<std::net::SocketAddr>()) {
+            (Some(health_manager), Ok(addr)) => !health_manager.admits(addr),
+            _ => false,
+        }
+    }
+
+    /// Whether `addr` is being drained for maintenance
+    fn is_draining(&self, addr: &str) -> bool {
+        match (&self.drains, addr.parse::<std::net::SocketAddr>()) {
+            (Some(drains), Ok(addr)) => drains.is_draining(addr),
+            _ => false,
+        }
+    }
+
+    /// Whether the circuit of `addr` is open, refusing commands
+    fn is_circuit_open(&self, addr: &str) -> bool {
+        match (&self.circuit_breaker, addr.parse::<std::net::SocketAddr>()) {
+            (Some(circuit_breaker), Ok(addr)) => circuit_breaker.is_open(addr),
+            _ => false,
+        }
+    }
+
+    /// Fail a command routed to `peer` while its circuit is open
+    fn check_circuit(&self, peer: BasicPeer) -> Result<BasicPeer, Box<dyn Error + Send + Sync>> {
+        let node_addr = peer.address().to_string();
+        match (&self.circuit_breaker, node_addr.parse::<std::net::SocketAddr>()) {
+            (Some(circuit_breaker), Ok(addr)) if !circuit_breaker.allow(addr) => {
+                let message = format!("Circuit open for node {node_addr}");
+                Err(NodeLost::error(node_addr, message, false))
+            }
+            _ => Ok(peer),
+        }
+    }
+
+    /// Feed the outcome of a node exchange, started at `started`, into
+    /// the circuit breaker, passive health checking and outlier detection
+    fn report_result<T, E>(&self, peer: &BasicPeer, started: Instant, result: &Result<T, E>) {
+        let Ok(addr) = peer.address().to_string().parse::<std::net::SocketAddr>() else {
+            return;
+        };
+        if let Some(circuit_breaker) = &self.circuit_breaker {
+            circuit_breaker.record(addr, result.is_ok());
+        }
+        if let (Some(latencies), Ok(_)) = (&self.latencies, result) {
+            latencies.record(&addr.to_string(), started.elapsed());
+        }
+        let Some(health_manager) = &self.health_manager else {
+            return;
+        };
+        match result {
+            Ok(_) => {
+                health_manager.record_passive_success(addr);
+                health_manager.record_outcome(addr, true, Some(started.elapsed()));
+                if let Some(health_events) = &self.health_events {
+                    if health_events.is_unhealthy(addr) {
+                        let node = addr.to_string();
+                        health_events.record(&node, addr, true, "request succeeded after ejection");
+                    }
+                }
+            }
+            Err(_) => {
+                if health_manager.record_passive_failure(addr) {
+                    log::warn!("Ejecting Redis node {addr} after consecutive failures");
+                    if let Some(health_events) = &self.health_events {
+                        let node = addr.to_string();
+                        health_events.record(&node, addr, false, "consecutive failures on live traffic");
+                    }
+                }
+                health_manager.record_outcome(addr, false, None);
+            }
+        }
+    }
+
+    /// Spread commands without a key over the seed nodes by weight
+    pub fn with_node_weights(mut self, weights: Arc<RwLock<HashMap<String, usize>>>) -> Self {
+        self.router = self.router.with_node_weights(weights);
+        self
+    }
+
+    /// Run DBSIZE, FLUSHDB, FLUSHALL, KEYS and INFO on every master
+    pub fn with_fan_out_keyless_commands(mut self, enabled: bool) -> Self {
+        self.fan_out_keyless_commands = enabled;
+        self
+    }
+
+    /// Close client connections sending a command over `max_command_size`
+    /// bytes with a protocol error, before the command is buffered
+    pub fn with_max_command_size(mut self, max_command_size: usize) -> Self {
+        self.max_command_size = max_command_size;
+        self
+    }
+
+    /// Copy a share of client connections to a shadow node
+    pub fn with_mirror(mut self, mirror: Option<Mirror>) -> Self {
+        self.mirror = mirror.map(Arc::new);
+        self
+    }
+
+    /// Write client commands to capture files
+    pub fn with_capture(mut self, capture: Option<Capture>) -> Self {
+        self.capture = capture.map(Arc::new);
+        self
+    }
+
+    /// Refuse commands with `-ERR command disabled by proxy`
+    pub fn with_command_filter(mut self, command_filter: Option<CommandFilter>) -> Self {
+        self.command_filter = command_filter;
+        self
+    }
+
+    /// Answer `CLUSTER SLOTS`, `SHARDS`, `NODES`, `INFO` and `MYID` as a
+    /// single node serving every slot
+    pub fn with_cluster_view(mut self, cluster_view: Option<ClusterView>) -> Self {
+        self.cluster_view = cluster_view;
+        self
+    }
+
+    /// Delay, drop or redirect a share of client commands
+    pub fn with_chaos(mut self, chaos: Option<Arc<Chaos>>) -> Self {
+        self.chaos = chaos;
+        self
+    }
+
+    /// Try up to this many other nodes when connecting fails for a command
+    /// any node can serve
+    pub fn with_retry_attempts(mut self, retry_attempts: u32) -> Self {
+        self.retry_attempts = retry_attempts;
+        self
+    }
+
+    /// Refresh the topology through `topology_refresh` when a command's
+    /// master is lost, and wait up to `timeout` for a replica to take over
+    pub fn with_failover(mut self, topology_refresh: Arc<TopologyRefresh>, timeout: std::time::Duration) -> Self {
+        self.router = self.router.with_topology_refresh(Arc::clone(&topology_refresh));
+        self.topology_refresh = Some(topology_refresh);
+        self.failover_timeout = timeout;
+        self
+    }
+
+    /// Refuse commands for a node while its circuit is open
+    pub fn with_circuit_breaker(mut self, circuit_breaker: Option<CircuitBreaker>) -> Self {
+        self.circuit_breaker = circuit_breaker;
+        self
+    }
+
+    /// Send read-only commands to replicas, over upstream connections to
+    /// them with `READONLY` enabled
+    pub fn with_read_from_replicas(mut self, enabled: bool) -> Self {
+        self.router = self.router.with_read_from_replicas(enabled);
+        self
+    }
+
+    /// Pick replicas and seed nodes by the latency of recent requests to
+    /// them rather than in turn
+    pub fn with_latency_aware(mut self, enabled: bool) -> Self {
+        let latencies = enabled.then(|| Arc::new(LatencyTracker::default()));
+        self.router = self.router.with_latencies(latencies.clone());
+        self.latencies = latencies;
+        self
+    }
+
+    /// Authenticate upstream connections with `auth` and require clients to
+    /// authenticate with `client_auth`
+    pub fn with_auth(
+        mut self,
+        auth: Option<RedisAuthConfig>,
+        client_auth: Option<RedisAuthConfig>,
+    ) -> Self {
+        self.pool = self.pool.with_auth(auth);
+        self.client_auth = client_auth;
+        self
+    }
+
+    /// Connect to nodes learned from topology or redirects over TLS
+    pub fn with_upstream_tls(mut self, tls: Option<UpstreamTlsConfig>) -> Self {
+        self.upstream_tls = tls;
+        self
+    }
+
+    /// Probe idle connections to nodes learned from topology or redirects
+    pub fn with_backend_tcp_keepalive(mut self, keepalive: Option<TcpKeepaliveConfig>) -> Self {
+        self.backend_tcp_keepalive = keepalive;
+        self
+    }
+
+    /// Use the given upstream connection pool settings
+    pub fn with_pool_config(mut self, config: PoolConfig) -> Self {
+        self.pool = self.pool.with_config(config);
+        self
+    }
+
+    /// Get the upstream connection pool
+    pub fn pool(&self) -> &ConnectionPool {
+        &self.pool
+    }
+
+    /// Get the maximum number of redirects followed per command
+    pub fn max_redirects(&self) -> u8 {
+        self.router.max_redirects()
+    }
+
+    /// Parse Redis command from raw data using complete RESP parser
+    pub async fn parse_redis_command(
+        &self,
+        data: &[u8],
+    ) -> Result<RedisCommand, Box<dyn Error + Send + Sync>> {
+        let mut buf = bytes::BytesMut::from(data);
+
+        match RespParser::parse(&mut buf)? {
+            Some(value) => Self::command_from_value(value),
+            None => Err("Incomplete command data".into()),
+        }
+    }
+
+    /// Build a `RedisCommand` from the name and arguments of a command frame
+    ///
+    /// The arguments stay slices of the client's frame, so values are not
+    /// copied, and the slot is computed from the raw key bytes.
+    pub fn command_from_parts(parts: Vec<Bytes>) -> Result<RedisCommand, Box<dyn Error + Send + Sync>> {
+        let mut parts = parts.into_iter();
+        let name = parts.next().ok_or("Empty command array")?;
+        let command = String::from_utf8_lossy(&name).to_uppercase();
+        let args: Vec<Bytes> = parts.collect();
+
+        let key_arg = args.first().filter(|_| Self::command_has_key(&command));
+        let slot = key_arg.map(|key| SlotMapping::calculate_slot_bytes(key));
+        let key = key_arg.map(|key| String::from_utf8_lossy(key).into_owned());
+        let readonly = Self::is_readonly_command(&command);
+
+        Ok(RedisCommand {
+            command,
+            args,
+            key,
+            slot,
+            readonly,
+        })
+    }
+
+    /// Build a `RedisCommand` from a parsed RESP value
+    pub fn command_from_value(value: RespValue) -> Result<RedisCommand, Box<dyn Error + Send + Sync>> {
+        match value {
+            RespValue::Array(Some(elements)) => {
+                if elements.is_empty() {
+                    return Err("Empty command array".into());
+                }
+                
+                // First element should be the command
+                let command = match &elements[0] {
+                    RespValue::BulkString(Some(cmd_bytes)) => {
+                        String::from_utf8_lossy(cmd_bytes).to_uppercase()
+                    }
+                    RespValue::SimpleString(cmd_str) => cmd_str.to_uppercase(),
+                    _ => return Err("Invalid command format".into()),
+                };
+                
+                // Extract arguments
+                let mut args = Vec::new();
+                let mut key: Option<String> = None;
+                
+                for (i, element) in elements.iter().skip(1).enumerate() {
+                    match element {
+                        RespValue::BulkString(Some(arg_bytes)) => {
+                            let arg_str = String::from_utf8_lossy(arg_bytes).to_string();
                             args.push(Bytes::from(arg_str.clone()));
                             
                             // First argument is typically the key for most commands
@@ -548,18 +1961,25 @@ impl RedisProtocolApp {
                     readonly,
                 })
             }
-            Some(RespValue::Array(None)) => {
+            RespValue::Array(None) => {
                 Err("NULL command array".into())
             }
-            Some(_) => {
+            _ => {
                 Err("Expected array command".into())
             }
-            None => {
-                Err("Incomplete command data".into())
-            }
         }
     }
-    
+
+    /// Split one complete RESP value off the front of `buf`
+    ///
+    /// Returns the parsed value together with its raw bytes so the original
+    /// encoding can be forwarded untouched. The buffer is left as-is when it
+    /// does not yet hold a complete value. Loops reading a connection keep a
+    /// `RespParser` instead, so partial values are not scanned again.
+    pub fn take_frame(buf: &mut BytesMut) -> Result<Option<(RespValue, Bytes)>, RespParseError> {
+        RespParser::new().decode_frame(buf)
+    }
+
     /// Check if a command typically has a key as first argument
     fn command_has_key(command: &str) -> bool {
         matches!(
@@ -603,554 +2023,3212 @@ impl RedisProtocolApp {
                 | "ZRANGEBYSCORE"
                 | "ZRANK"
                 | "ZSCORE"
+                | "EVAL_RO"
+                | "EVALSHA_RO"
+                | "FCALL_RO"
         )
     }
 
     /// Route command to appropriate cluster node
+    #[tracing::instrument(
+        name = "redis.route_command",
+        skip_all,
+        fields(command = %command.command, slot = ?command.slot)
+    )]
     pub async fn route_command(
         &self,
         command: &RedisCommand,
     ) -> Result<BasicPeer, Box<dyn Error + Send + Sync>> {
-        if let Some(slot) = command.slot {
-            let slot_mapping = self.slot_mapping.read().await;
-            if let Some(node_addr) = slot_mapping.get_backend_for_slot(slot) {
-                let nodes = self.cluster_nodes.read().await;
-                if let Some(peer) = nodes.get(&node_addr) {
-                    return Ok(peer.clone());
-                }
-            }
-        }
+        let decision = self.router.route_command(command, self).await;
+        self.peer_for_decision(decision).await
+    }
 
-        // Fallback to first available node
-        let nodes = self.cluster_nodes.read().await;
-        if let Some((_, peer)) = nodes.iter().next() {
-            Ok(peer.clone())
-        } else {
-            Err("No cluster nodes available".into())
-        }
+    /// Pick a node for a command without a key by weighted round-robin,
+    /// never one of `excluded`
+    async fn select_keyless_node(
+        &self,
+        excluded: &[String],
+    ) -> Result<BasicPeer, Box<dyn Error + Send + Sync>> {
+        let decision = self.router.select_keyless_node(excluded, self).await;
+        self.peer_for_decision(decision).await
     }
 
-    /// Forward Redis RESP protocol data with redirection handling
-    async fn forward_redis_data(&self, mut client_stream: Stream, mut redis_stream: Stream) {
-        use crate::modes::redis::redirect::{RedirectParser, RedirectionContext};
+    /// The peer a routing decision names, failing while its circuit is open
+    async fn peer_for_decision(
+        &self,
+        decision: RoutingDecision,
+    ) -> Result<BasicPeer, Box<dyn Error + Send + Sync>> {
+        match decision {
+            RoutingDecision::Route { backend_id } => self.check_circuit(self.peer_for_address(&backend_id).await),
+            RoutingDecision::Error { message } => Err(message.into()),
+            RoutingDecision::Redirect { new_address, command } => {
+                Err(format!("{command} redirected to {new_address}").into())
+            }
+        }
+    }
 
-        let mut client_buf = [0; 8192];
-        let mut redis_buf = [0; 8192];
-        let _redirect_context = RedirectionContext::new(0, self.max_redirects);
+    /// Forward Redis RESP protocol data, routing each command to the node
+    /// that owns its slot
+    ///
+    /// Every complete command buffered from the client is framed and run as
+    /// one pipelined batch: commands for the same node share a pooled
+    /// connection and their replies are matched back to them in order, so a
+    /// MOVED or ASK reply retries only the command it answers. Replies are
+    /// written back in the order the commands arrived.
+    ///
+    /// A batch holds at most `MAX_PIPELINE_DEPTH` commands and about the
+    /// high watermark in command bytes, and the client is not read again
+    /// until its replies are written, so a slow client throttles what is
+    /// sent to the cluster. With an idle timeout the connection is closed
+    /// once the client sends nothing for that long while no command is in
+    /// flight. An access record is written once the client disconnects.
+    async fn forward_redis_data(&self, mut client_stream: Stream, client_addr: &str, initial: &[u8]) {
+        let started = Instant::now();
+        // Commands already received are handled before the first read
+        let mut client_buf = BytesMut::with_capacity(self.buffer_config.buffer_size);
+        client_buf.extend_from_slice(initial);
+        // Picks up partial commands where the previous read left them
+        let mut client_parser = RespParser::new().with_max_frame_size(self.max_command_size);
+        let mut read_buf = vec![0u8; self.buffer_config.buffer_size];
+        let mut client_auth = ClientAuth::new(self.client_auth.as_ref());
+        let mut transaction = Transaction::new();
+        // Longest delay injected into the current batch by chaos testing
+        let mut chaos_delay = std::time::Duration::ZERO;
+        let mut mirror = self.mirror.as_ref().and_then(|mirror| mirror.session());
+        // Commands are routed per slot, so the record names the cluster
+        let mut access = crate::logging::AccessRecord::new("redis", client_addr, "cluster");
+        access.bytes_from_client += initial.len() as u64;
+        let connection = crate::core::connections::global().register("redis", client_addr, "cluster");
+        // Announced by the cluster view when no address is configured
+        let local_addr = client_stream
+            .get_socket_digest()
+            .and_then(|digest| digest.local_addr().and_then(|addr| addr.as_inet()).copied());
+
+        'connection: loop {
+            connection.update(access.bytes_from_client, access.bytes_to_client, access.requests);
+            // Frame every complete command currently buffered
+            let mut batch = Vec::new();
+            let mut batch_bytes = 0;
+            let mut protocol_error = None;
+            let mut subscribe = None;
+            let mut chaos_reset = false;
+            while batch.len() < MAX_PIPELINE_DEPTH && batch_bytes < self.buffer_config.high_watermark {
+                let raw_command = match client_parser.split_frame(&mut client_buf) {
+                    Ok(Some(raw)) => raw,
+                    Ok(None) => break,
+                    Err(e) => {
+                        log::warn!("Protocol error from client {}: {}", client_addr, e);
+                        protocol_error = Some(e);
+                        break;
+                    }
+                };
 
-        loop {
-            tokio::select! {
-                // Client -> Redis
-                result = client_stream.read(&mut client_buf) => {
-                    match result {
-                        Ok(0) => {
-                            log::debug!("Client connection closed");
-                            break;
-                        }
-                        Ok(n) => {
-                            // Forward client data to Redis
-                            if let Err(e) = redis_stream.write_all(&client_buf[0..n]).await {
-                                log::error!("Failed to write to Redis: {}", e);
-                                break;
-                            }
-                            if let Err(e) = redis_stream.flush().await {
-                                log::error!("Failed to flush to Redis: {}", e);
-                                break;
-                            }
-                        }
+                // Commands come as arrays of bulk strings, which are routed
+                // from slices of the frame; anything else is fully parsed
+                let (split_plan, command) = match RespParser::command_parts(&raw_command) {
+                    Some(parts) => (SplitPlan::from_parts(&parts), Self::command_from_parts(parts)),
+                    None => match RespParser::parse_frame(&raw_command) {
+                        Ok(value) => (None, Self::command_from_value(value)),
                         Err(e) => {
-                            log::error!("Failed to read from client: {}", e);
+                            log::warn!("Protocol error from client {}: {}", client_addr, e);
+                            protocol_error = Some(e);
                             break;
                         }
+                    },
+                };
+                access.requests += 1;
+                batch_bytes += raw_command.len();
+                let mut command = match command {
+                    Ok(command) => command,
+                    Err(e) => {
+                        batch.push(PendingCommand::Answered(Bytes::from(format!("-ERR {e}\r\n"))));
+                        continue;
+                    }
+                };
+                if let Some(reply) = client_auth.intercept(&command) {
+                    batch.push(PendingCommand::Answered(reply));
+                    continue;
+                }
+                if let Some(reply) = self.filter_command(&command) {
+                    transaction.reject();
+                    batch.push(PendingCommand::Answered(reply));
+                    continue;
+                }
+                if let Some(reply) = self
+                    .cluster_view
+                    .as_ref()
+                    .filter(|_| !transaction.in_multi())
+                    .and_then(|cluster_view| cluster_view.intercept(&command, local_addr))
+                {
+                    batch.push(PendingCommand::Answered(reply));
+                    continue;
+                }
+                match scripting::script_slot(&command) {
+                    Some(Ok(slot)) => command.slot = slot,
+                    Some(Err(reply)) => {
+                        batch.push(PendingCommand::Answered(reply));
+                        continue;
                     }
+                    None => {}
                 }
-                // Redis -> Client
-                result = redis_stream.read(&mut redis_buf) => {
-                    match result {
-                        Ok(0) => {
-                            log::debug!("Redis connection closed");
+                if let Some(chaos) = self.chaos.as_ref().filter(|_| !transaction.in_multi()) {
+                    // Clients are redirected to the address they reached the proxy on
+                    let redirect = command.slot.zip(match &self.cluster_view {
+                        Some(cluster_view) => cluster_view.address(local_addr),
+                        None => local_addr.map(|addr| (addr.ip().to_string(), addr.port())),
+                    });
+                    match (chaos.fault(redirect.is_some()), redirect) {
+                        (Some(Fault::Delay(delay)), _) => chaos_delay = chaos_delay.max(delay),
+                        (Some(Fault::Reset), _) => {
+                            chaos_reset = true;
                             break;
                         }
-                        Ok(n) => {
-                            // Check for Redis redirections in the response
-                            let response_data = &redis_buf[0..n];
-
-                            // Parse potential redirections using RCProxy-style parsing
-                            if let Some(redirect) = RedirectParser::parse_redirect_raw(response_data) {
-                                log::info!("Detected redirection: {:?}", redirect);
-
-                                // Handle the redirection with full implementation
-                                match redirect {
-                                    crate::modes::redis::redirect::RedirectType::Moved { slot, address } => {
-                                        log::warn!("MOVED redirection detected for slot {} to {}", slot, address);
-                                        
-                                        // Update slot mapping for MOVED redirections
-                                        if let Err(e) = self.handle_moved_redirect(slot, &address).await {
-                                            log::error!("Failed to handle MOVED redirect: {}", e);
-                                        }
-                                        
-                                        // Forward the MOVED response to client so they can handle it
-                                        // In a full proxy, we might retry the command automatically
-                                    }
-                                    crate::modes::redis::redirect::RedirectType::Ask { slot, address } => {
-                                        log::warn!("ASK redirection detected for slot {} to {}", slot, address);
-                                        
-                                        // Handle ASK redirection by connecting to target node and sending ASKING
-                                        if let Err(e) = self.handle_ask_redirect(slot, &address, &client_buf[0..n]).await {
-                                            log::error!("Failed to handle ASK redirect: {}", e);
-                                            // Forward the ASK response to client as fallback
-                                        } else {
-                                            // ASK redirect was handled successfully, don't forward the ASK response
-                                            continue;
-                                        }
+                        (Some(Fault::Moved), Some((slot, (host, port)))) => {
+                            batch.push(PendingCommand::Answered(Bytes::from(format!(
+                                "-MOVED {slot} {host}:{port}\r\n"
+                            ))));
+                            continue;
+                        }
+                        _ => {}
+                    }
+                }
+                // Only commands the nodes would see reach the shadow
+                if let Some(mirror) = &mut mirror {
+                    mirror.send(&raw_command);
+                }
+                if let Some(capture) = &self.capture {
+                    capture.record(connection.id, &raw_command);
+                }
+                match transaction.intercept(&command, split_plan.as_ref(), &raw_command) {
+                    Some(TransactionStep::Reply(reply)) => batch.push(PendingCommand::Answered(reply)),
+                    Some(step) => batch.push(PendingCommand::Transaction(step)),
+                    // Everything after a subscribe waits for subscriber mode
+                    None if pubsub::is_subscribe(&command.command) => {
+                        subscribe = Some((command, raw_command));
+                        break;
+                    }
+                    None if self.is_cluster_command(&command.command) => {
+                        batch.push(PendingCommand::Cluster {
+                            command,
+                            raw: raw_command,
+                        })
+                    }
+                    None => batch.push(match split_plan {
+                        Some(plan) => PendingCommand::Split { command, plan },
+                        None => PendingCommand::Forward {
+                            command,
+                            raw: raw_command,
+                        },
+                    }),
+                }
+            }
+
+            if chaos_reset {
+                log::info!("Chaos: closing Redis client {}", client_addr);
+                break;
+            }
+            if !chaos_delay.is_zero() {
+                tokio::time::sleep(std::mem::take(&mut chaos_delay)).await;
+            }
+            let full = batch.len() == MAX_PIPELINE_DEPTH || batch_bytes >= self.buffer_config.high_watermark;
+            if !batch.is_empty() {
+                let mut replies = BytesMut::new();
+                for reply in self.execute_batch(batch, &mut transaction, client_addr).await {
+                    replies.extend_from_slice(&reply);
+                }
+                if let Err(e) = Self::write_to_client(&mut client_stream, &replies).await {
+                    log::error!("Failed to write to client {}: {}", client_addr, e);
+                    break 'connection;
+                }
+                access.bytes_to_client += replies.len() as u64;
+            }
+
+            if let Some(e) = protocol_error {
+                let _ = Self::write_to_client(
+                    &mut client_stream,
+                    format!("-ERR Protocol error: {e}\r\n").as_bytes(),
+                )
+                .await;
+                break;
+            }
+            // The subscribe command was a complete frame, so `client_parser`
+            // holds no partial state while the subscriber reads `client_buf`
+            if let Some((command, raw)) = subscribe {
+                if self
+                    .run_subscriber(&mut client_stream, &mut client_buf, command, &raw, &connection, &mut access)
+                    .await
+                {
+                    continue;
+                }
+                break;
+            }
+            // A full batch may leave more commands buffered
+            if full {
+                continue;
+            }
+
+            let n = match read_idle(&mut client_stream, &mut read_buf, self.idle_timeout).await {
+                Ok(None) => {
+                    log::info!(
+                        "Closing Redis client {} after {}s without traffic",
+                        client_addr,
+                        self.idle_timeout.unwrap_or_default().as_secs()
+                    );
+                    break;
+                }
+                Ok(Some(0)) => {
+                    log::debug!("Client {} connection closed", client_addr);
+                    break;
+                }
+                Ok(Some(n)) => n,
+                Err(e) => {
+                    log::error!("Failed to read from client {}: {}", client_addr, e);
+                    break;
+                }
+            };
+            client_buf.extend_from_slice(&read_buf[..n]);
+            access.bytes_from_client += n as u64;
+        }
+
+        connection.update(access.bytes_from_client, access.bytes_to_client, access.requests);
+        crate::logging::access(&access.with_duration(started.elapsed()));
+    }
+
+    /// Pass a subscribed client through a dedicated connection to one node
+    ///
+    /// `SSUBSCRIBE` goes to the node owning its channels' slot and other
+    /// subscribes to any node. Client commands are forwarded one at a time,
+    /// each once the previous one is answered, with no idle timeout. Returns
+    /// whether the client left subscriber mode with its connection open; any
+    /// commands it sent after that are left in `client_buf`.
+    async fn run_subscriber<S>(
+        &self,
+        client_stream: &mut S,
+        client_buf: &mut BytesMut,
+        command: RedisCommand,
+        raw_command: &[u8],
+        connection: &ConnectionStats,
+        access: &mut crate::logging::AccessRecord,
+    ) -> bool
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let client_addr = connection.client_addr.as_str();
+        let slot = if command.command == "SSUBSCRIBE" {
+            let slots: Vec<u16> = command
+                .args
+                .iter()
+                .map(|channel| SlotMapping::calculate_slot_bytes(channel))
+                .collect();
+            if slots.windows(2).any(|pair| pair[0] != pair[1]) {
+                return Self::reply_to_subscriber(client_stream, transaction::CROSSSLOT_REPLY)
+                    .await;
+            }
+            slots.first().copied()
+        } else {
+            None
+        };
+        let route = RedisCommand {
+            slot,
+            ..command.clone()
+        };
+        let connected = match self.route_command(&route).await {
+            Ok(peer) => {
+                let mode = self.router.connection_mode(&peer.address().to_string()).await;
+                self.pool.connect(&peer, mode).await
+            }
+            Err(e) => Err(e),
+        };
+        let mut upstream = match connected {
+            Ok(stream) => stream,
+            Err(e) => {
+                log::error!("Failed to open subscriber connection for client {}: {}", client_addr, e);
+                let reply = format!("-ERR {e}\r\n");
+                return Self::reply_to_subscriber(client_stream, reply.as_bytes()).await;
+            }
+        };
+        log::debug!("Client {} entered subscriber mode", client_addr);
+
+        let mut subscriptions = Subscriptions::new();
+        let mut next = Some((command, Bytes::copy_from_slice(raw_command)));
+        let mut client_parser = RespParser::new().with_max_frame_size(self.max_command_size);
+        let mut upstream_buf = BytesMut::new();
+        let mut upstream_parser = RespParser::new().with_max_frame_size(self.pool.config().max_reply_size);
+        let mut client_read = vec![0u8; self.buffer_config.buffer_size];
+        let mut upstream_read = vec![0u8; self.buffer_config.buffer_size];
+        loop {
+            connection.update(access.bytes_from_client, access.bytes_to_client, access.requests);
+            // Forward the next command once the previous one is fully answered
+            while !subscriptions.awaiting_replies() {
+                if next.is_none() {
+                    if subscriptions.is_empty() {
+                        log::debug!("Client {} left subscriber mode", client_addr);
+                        return true;
+                    }
+                    next = match client_parser.decode_frame(client_buf) {
+                        Ok(Some((value, raw))) => match Self::command_from_value(value) {
+                            Ok(command) => {
+                                access.requests += 1;
+                                if let Some(reply) = self.filter_command(&command) {
+                                    if client_stream.write_all(&reply).await.is_err() {
+                                        return false;
                                     }
+                                    continue;
                                 }
+                                Some((command, raw))
                             }
-
-                            // Forward response to client
-                            if let Err(e) = client_stream.write_all(response_data).await {
-                                log::error!("Failed to write to client: {}", e);
-                                break;
-                            }
-                            if let Err(e) = client_stream.flush().await {
-                                log::error!("Failed to flush to client: {}", e);
-                                break;
+                            Err(e) => {
+                                let reply = format!("-ERR {e}\r\n");
+                                if client_stream.write_all(reply.as_bytes()).await.is_err() {
+                                    return false;
+                                }
+                                continue;
                             }
-                        }
+                        },
+                        Ok(None) => break,
                         Err(e) => {
-                            log::error!("Failed to read from Redis: {}", e);
-                            break;
+                            log::warn!("Protocol error from client {}: {}", client_addr, e);
+                            let reply = format!("-ERR Protocol error: {e}\r\n");
+                            let _ = client_stream.write_all(reply.as_bytes()).await;
+                            return false;
                         }
+                    };
+                }
+                if let Some((command, raw)) = next.take() {
+                    subscriptions.expect(&command);
+                    if let Err(e) = upstream.write_all(&raw).await {
+                        log::error!("Failed to forward to subscriber connection for {}: {}", client_addr, e);
+                        return false;
                     }
                 }
             }
-        }
-    }
+            if let Err(e) = upstream.flush().await {
+                log::error!("Failed to forward to subscriber connection for {}: {}", client_addr, e);
+                return false;
+            }
+            if let Err(e) = client_stream.flush().await {
+                log::error!("Failed to write to client {}: {}", client_addr, e);
+                return false;
+            }
+
+            tokio::select! {
+                read = client_stream.read(&mut client_read) => match read {
+                    Ok(0) => {
+                        log::debug!("Client {} connection closed", client_addr);
+                        return false;
+                    }
+                    Ok(n) => {
+                        client_buf.extend_from_slice(&client_read[..n]);
+                        access.bytes_from_client += n as u64;
+                    }
+                    Err(e) => {
+                        log::error!("Failed to read from client {}: {}", client_addr, e);
+                        return false;
+                    }
+                },
+                read = upstream.read(&mut upstream_read) => {
+                    let n = match read {
+                        Ok(0) | Err(_) => {
+                            log::warn!("Subscriber connection for client {} closed by Redis node", client_addr);
+                            return false;
+                        }
+                        Ok(n) => n,
+                    };
+                    upstream_buf.extend_from_slice(&upstream_read[..n]);
+                    let mut replies = BytesMut::new();
+                    loop {
+                        match upstream_parser.decode_frame(&mut upstream_buf) {
+                            Ok(Some((value, raw))) => {
+                                subscriptions.observe(&value);
+                                replies.extend_from_slice(&raw);
+                            }
+                            Ok(None) => break,
+                            Err(e) => {
+                                log::error!("Invalid reply on subscriber connection for {}: {}", client_addr, e);
+                                return false;
+                            }
+                        }
+                    }
+                    if let Err(e) = Self::write_all_flush(client_stream, &replies).await {
+                        log::error!("Failed to write to client {}: {}", client_addr, e);
+                        return false;
+                    }
+                    access.bytes_to_client += replies.len() as u64;
+                }
+            }
+        }
+    }
+
+    /// Answer a subscribe that could not enter subscriber mode, returning
+    /// whether the client connection is still usable
+    async fn reply_to_subscriber<S>(client_stream: &mut S, reply: &[u8]) -> bool
+    where
+        S: AsyncWrite + Unpin,
+    {
+        Self::write_all_flush(client_stream, reply).await.is_ok()
+    }
+
+    async fn write_all_flush<S>(stream: &mut S, data: &[u8]) -> std::io::Result<()>
+    where
+        S: AsyncWrite + Unpin,
+    {
+        stream.write_all(data).await?;
+        stream.flush().await
+    }
+
+    /// Execute a batch of client commands and return their replies in order
+    ///
+    /// Consecutive forwarded commands are pipelined together. Split commands
+    /// and transaction steps use connections of their own, so they wait for
+    /// everything before them and run before anything after them.
+    async fn execute_batch(
+        &self,
+        batch: Vec<PendingCommand>,
+        transaction: &mut Transaction,
+        client_addr: &str,
+    ) -> Vec<Bytes> {
+        let mut replies = Vec::with_capacity(batch.len());
+        let mut run: Vec<(RedisCommand, Bytes)> = Vec::new();
+        // Replies answered locally inside the current run, by batch position
+        let mut answered: Vec<(usize, Bytes)> = Vec::new();
+
+        for pending in batch {
+            match pending {
+                PendingCommand::Answered(reply) => answered.push((run.len(), reply)),
+                PendingCommand::Forward { command, raw } => run.push((command, raw)),
+                PendingCommand::Split { command, plan } => {
+                    self.flush_run(&mut run, &mut answered, &mut replies, client_addr).await;
+                    let command_started = Instant::now();
+                    let result = self.execute_split(&plan).await;
+                    let reply = Self::reply_or_error(result, client_addr);
+                    self.record_command(&command, &reply, client_addr, command_started);
+                    replies.push(reply);
+                }
+                PendingCommand::Cluster { command, raw } => {
+                    self.flush_run(&mut run, &mut answered, &mut replies, client_addr).await;
+                    let command_started = Instant::now();
+                    let result = self.execute_cluster_command(&command, &raw).await;
+                    let reply = Self::reply_or_error(result, client_addr);
+                    self.record_command(&command, &reply, client_addr, command_started);
+                    replies.push(reply);
+                }
+                PendingCommand::Transaction(step) => {
+                    self.flush_run(&mut run, &mut answered, &mut replies, client_addr).await;
+                    let reply = self.execute_transaction_step(step, transaction, client_addr).await;
+                    replies.push(reply);
+                }
+            }
+        }
+        self.flush_run(&mut run, &mut answered, &mut replies, client_addr).await;
+        replies
+    }
+
+    /// Run a transaction step against the cluster and return its reply
+    async fn execute_transaction_step(
+        &self,
+        step: TransactionStep,
+        transaction: &mut Transaction,
+        client_addr: &str,
+    ) -> Bytes {
+        match step {
+            TransactionStep::Reply(reply) => reply,
+            TransactionStep::Watch { raw, slot } => {
+                let result = self.watch(transaction, &raw, slot).await;
+                if !matches!(&result, Ok(reply) if reply.starts_with(b"+OK")) {
+                    transaction.fail_watch();
+                }
+                Self::reply_or_error(result, client_addr)
+            }
+            TransactionStep::Exec { commands, slot } => {
+                let pinned = transaction.take_pinned();
+                if transaction.take_watch_failed() {
+                    return Bytes::from_static(transaction::EXECABORT_REPLY);
+                }
+                let result = self.execute_transaction(&commands, slot, pinned).await;
+                Self::reply_or_error(result, client_addr)
+            }
+            TransactionStep::Release { reply } => {
+                transaction.take_watch_failed();
+                if let Some(mut conn) = transaction.take_pinned() {
+                    // A connection still watching keys must not be shared
+                    if Self::roundtrip(&mut conn, UNWATCH_COMMAND).await.is_ok() {
+                        self.pool.release(conn);
+                    }
+                }
+                reply
+            }
+        }
+    }
+
+    /// Check out a connection to the node owning `slot`, or any node when
+    /// there is no slot
+    async fn acquire_for_slot(
+        &self,
+        slot: Option<u16>,
+    ) -> Result<PooledConnection, Box<dyn Error + Send + Sync>> {
+        let route = RedisCommand {
+            command: "EXEC".to_string(),
+            args: Vec::new(),
+            key: None,
+            slot,
+            readonly: false,
+        };
+        let peer = self.route_command(&route).await?;
+        let mode = self.router.connection_mode(&peer.address().to_string()).await;
+        let started = Instant::now();
+        let conn = self.pool.acquire_in(&peer, mode).await;
+        self.report_result(&peer, started, &conn);
+        conn
+    }
+
+    /// Send `WATCH` on the transaction's pinned connection, pinning a
+    /// connection to the node owning `slot` first if there is none
+    ///
+    /// A MOVED reply to the first `WATCH` is followed like any other command.
+    async fn watch(
+        &self,
+        transaction: &mut Transaction,
+        raw_command: &[u8],
+        slot: u16,
+    ) -> Result<Bytes, Box<dyn Error + Send + Sync>> {
+        use crate::modes::redis::redirect::{RedirectParser, RedirectType};
+
+        let mut redirects = 0u8;
+        loop {
+            let pinned = transaction.take_pinned();
+            let was_pinned = pinned.is_some();
+            let mut conn = match pinned {
+                Some(conn) => conn,
+                None => self.acquire_for_slot(Some(slot)).await?,
+            };
+            // On error the connection is dropped rather than returned to the pool
+            let reply = Self::roundtrip(&mut conn, raw_command).await?;
+            if reply.starts_with(b"+OK") {
+                transaction.pin(conn);
+                return Ok(reply);
+            }
+
+            match RedirectParser::parse_redirect_raw(&reply) {
+                Some(RedirectType::Moved { slot, address })
+                    if !was_pinned && redirects < self.router.max_redirects() =>
+                {
+                    self.pool.release(conn);
+                    redirects += 1;
+                    self.handle_moved_redirect(slot, &address).await?;
+                }
+                _ if was_pinned => {
+                    // Keys watched earlier are still watched on this connection
+                    transaction.pin(conn);
+                    return Ok(reply);
+                }
+                _ => {
+                    self.pool.release(conn);
+                    return Ok(reply);
+                }
+            }
+        }
+    }
+
+    /// Run queued commands as `MULTI` ... `EXEC` on one connection and
+    /// return the `EXEC` reply
+    ///
+    /// The transaction runs on `pinned` when keys are watched, otherwise on a
+    /// pooled connection to the node owning `slot`. Without watched keys, a
+    /// transaction aborted because its slot moved is retried on the new
+    /// owner, up to `max_redirects` times.
+    async fn execute_transaction(
+        &self,
+        commands: &[(RedisCommand, Bytes)],
+        slot: Option<u16>,
+        mut pinned: Option<PooledConnection>,
+    ) -> Result<Bytes, Box<dyn Error + Send + Sync>> {
+        use crate::modes::redis::redirect::{RedirectParser, RedirectType};
+
+        let mut pipeline = BytesMut::from(MULTI_COMMAND);
+        for (_, raw) in commands {
+            pipeline.extend_from_slice(raw);
+        }
+        pipeline.extend_from_slice(EXEC_COMMAND);
+
+        let mut redirects = 0u8;
+        loop {
+            let watched = pinned.is_some();
+            let mut conn = match pinned.take() {
+                Some(conn) => conn,
+                None => self.acquire_for_slot(slot).await?,
+            };
+            log::trace!(
+                "Running transaction of {} commands on {}",
+                commands.len(),
+                conn.node_addr()
+            );
+
+            // On error the connection is dropped rather than returned to the pool
+            conn.stream.write_all(&pipeline).await?;
+            conn.stream.flush().await?;
+            let mut replies = Vec::with_capacity(commands.len() + 2);
+            while replies.len() < commands.len() + 2 {
+                replies.push(Self::read_reply(&mut conn).await?);
+            }
+            self.pool.release(conn);
+
+            let exec_reply = replies.pop().unwrap_or_default();
+            if exec_reply.starts_with(b"-EXECABORT") && !watched && redirects < self.router.max_redirects() {
+                let moved = replies.iter().find_map(|reply| match RedirectParser::parse_redirect_raw(reply) {
+                    Some(RedirectType::Moved { slot, address }) => Some((slot, address)),
+                    _ => None,
+                });
+                if let Some((moved_slot, address)) = moved {
+                    log::warn!("Transaction slot {moved_slot} moved to {address}, retrying");
+                    redirects += 1;
+                    self.handle_moved_redirect(moved_slot, &address).await?;
+                    continue;
+                }
+            }
+            return Ok(exec_reply);
+        }
+    }
+
+    /// Pipeline the pending run of forwarded commands, interleaving the
+    /// replies answered locally at their original positions
+    async fn flush_run(
+        &self,
+        run: &mut Vec<(RedisCommand, Bytes)>,
+        answered: &mut Vec<(usize, Bytes)>,
+        replies: &mut Vec<Bytes>,
+        client_addr: &str,
+    ) {
+        let commands = std::mem::take(run);
+        let mut answered = std::mem::take(answered).into_iter().peekable();
+        let run_started = Instant::now();
+        let results = if commands.is_empty() {
+            Vec::new()
+        } else {
+            self.execute_pipeline(&commands).await
+        };
+
+        for (index, ((command, _), result)) in commands.iter().zip(results).enumerate() {
+            while let Some((_, reply)) = answered.next_if(|(position, _)| *position == index) {
+                replies.push(reply);
+            }
+            let reply = Self::reply_or_error(result, client_addr);
+            self.record_command(command, &reply, client_addr, run_started);
+            replies.push(reply);
+        }
+        replies.extend(answered.map(|(_, reply)| reply));
+    }
+
+    fn record_command(&self, command: &RedisCommand, reply: &[u8], client_addr: &str, started: Instant) {
+        if let Some(command_stats) = &self.command_stats {
+            command_stats.record(command, client_addr, started.elapsed());
+        }
+        if let Some(large_payloads) = &self.large_payloads {
+            // Keyless replies are attributed to their command
+            let name = command.key.as_deref().unwrap_or(&command.command);
+            large_payloads.record(name, &command.command, reply.len(), client_addr);
+        }
+    }
+
+    fn reply_or_error(result: Result<Bytes, Box<dyn Error + Send + Sync>>, client_addr: &str) -> Bytes {
+        match result {
+            Ok(reply) => reply,
+            Err(e) => {
+                log::error!("Failed to execute command for client {}: {}", client_addr, e);
+                Bytes::from(format!("-ERR {e}\r\n"))
+            }
+        }
+    }
+
+    /// Run several commands as one pipeline and return a result per command
+    ///
+    /// Commands are grouped by node; each group is written in one go over a
+    /// single pooled connection and exactly as many replies are read back, so
+    /// reply `i` of a group always answers its command `i`. Redirected
+    /// replies are then retried one command at a time, in client order.
+    async fn execute_pipeline(
+        &self,
+        commands: &[(RedisCommand, Bytes)],
+    ) -> Vec<Result<Bytes, Box<dyn Error + Send + Sync>>> {
+        let mut results: Vec<Option<Result<Bytes, Box<dyn Error + Send + Sync>>>> =
+            (0..commands.len()).map(|_| None).collect();
+
+        // Group commands by node, keeping each node's commands in client order
+        let mut groups: Vec<(BasicPeer, Vec<usize>)> = Vec::new();
+        for (index, (command, _)) in commands.iter().enumerate() {
+            match self.route_command(command).await {
+                Ok(peer) => {
+                    let node_addr = peer.address().to_string();
+                    match groups
+                        .iter_mut()
+                        .find(|(group_peer, _)| group_peer.address().to_string() == node_addr)
+                    {
+                        Some((_, indices)) => indices.push(index),
+                        None => groups.push((peer, vec![index])),
+                    }
+                }
+                Err(e) => results[index] = Some(Err(e)),
+            }
+        }
+
+        let group_replies = futures::future::join_all(groups.iter().map(|(peer, indices)| async move {
+            log::trace!("Pipelining {} commands to {}", indices.len(), peer.address());
+            let raw_commands: Vec<&[u8]> = indices.iter().map(|&i| commands[i].1.as_ref()).collect();
+            // Commands without a slot can move to another node if connecting fails
+            let failover = indices.iter().all(|&i| commands[i].0.slot.is_none());
+            self.send_pipeline_to_node(peer, &raw_commands, failover).await
+        }))
+        .await;
+        for ((_, indices), replies) in groups.iter().zip(group_replies) {
+            for (&index, reply) in indices.iter().zip(replies) {
+                results[index] = Some(reply);
+            }
+        }
+
+        let mut resolved = Vec::with_capacity(commands.len());
+        for ((command, raw), result) in commands.iter().zip(results) {
+            let result = result.unwrap_or_else(|| Err("no reply from Redis node".into()));
+            let result = match result {
+                Err(e) => self.fail_over(command, raw, e).await,
+                reply => reply,
+            };
+            resolved.push(match result {
+                Ok(reply) => self.follow_redirects(command, raw, reply).await,
+                Err(e) => Err(e),
+            });
+        }
+        resolved
+    }
+
+    /// Route a single command to its node and return the node's reply
+    async fn execute_command(
+        &self,
+        command: &RedisCommand,
+        raw_command: &[u8],
+    ) -> Result<Bytes, Box<dyn Error + Send + Sync>> {
+        let reply = match self.send_routed(command, raw_command).await {
+            Err(e) => self.fail_over(command, raw_command, e).await?,
+            reply => reply?,
+        };
+        self.follow_redirects(command, raw_command, reply).await
+    }
+
+    /// Route a command to its node and return the node's reply, without
+    /// following redirects
+    async fn send_routed(
+        &self,
+        command: &RedisCommand,
+        raw_command: &[u8],
+    ) -> Result<Bytes, Box<dyn Error + Send + Sync>> {
+        let peer = self.route_command(command).await?;
+        let node_addr = peer.address().to_string();
+        log::trace!("Routing {} (slot {:?}) to {}", command.command, command.slot, node_addr);
+        if let Some(slot) = command.slot {
+            self.prefetch_migration_destination(slot).await;
+        }
+
+        self.send_to_node_with(&peer, raw_command, command.slot.is_none())
+            .await
+            .map_err(|e| {
+                let message = format!("upstream {node_addr} failed: {e}");
+                NodeLost::with_message(e, message)
+            })
+    }
+
+    /// Open a connection to the importing node of a migrating slot in the
+    /// background, so an ASK redirect for a moved key finds one ready
+    async fn prefetch_migration_destination(&self, slot: u16) {
+        let Some(destination) = self
+            .slot_mapping
+            .read()
+            .await
+            .migration_of(slot)
+            .map(|migration| migration.destination.clone())
+        else {
+            return;
+        };
+        if self.pool.idle_count(&destination) > 0 || !self.prefetching.lock().unwrap().insert(destination.clone()) {
+            return;
+        }
+
+        let peer = self.peer_for_address(&destination).await;
+        let pool = self.pool.shared_with(upstream::new_connector(self.upstream_tls.as_ref()));
+        let prefetching = Arc::clone(&self.prefetching);
+        tokio::spawn(async move {
+            match pool.prefetch(&peer).await {
+                Ok(true) => log::debug!("Prefetched a connection to {destination}, importing slot {slot}"),
+                Ok(false) => {}
+                Err(e) => log::debug!("Failed to prefetch a connection to {destination}: {e}"),
+            }
+            prefetching.lock().unwrap().remove(&destination);
+        });
+    }
+
+    /// Send a command whose master was lost again once the topology has
+    /// been refreshed, until a replica has taken over its slot or the
+    /// failover timeout has passed
+    ///
+    /// Only commands that never reached the node, or that only read, are
+    /// sent again; anything else fails with `error`.
+    async fn fail_over(
+        &self,
+        command: &RedisCommand,
+        raw_command: &[u8],
+        mut error: Box<dyn Error + Send + Sync>,
+    ) -> Result<Bytes, Box<dyn Error + Send + Sync>> {
+        let Some(topology_refresh) = &self.topology_refresh else {
+            return Err(error);
+        };
+        let Some(slot) = command.slot else {
+            return Err(error);
+        };
+        let Some(lost_node) = NodeLost::retryable(error.as_ref(), command.readonly) else {
+            return Err(error);
+        };
+        let mut lost_node = lost_node.to_string();
+        if self.failover_timeout.is_zero() {
+            topology_refresh.request();
+            return Err(error);
+        }
+
+        log::warn!("Redis node {lost_node} serving slot {slot} lost, refreshing cluster topology");
+        let deadline = Instant::now() + self.failover_timeout;
+        loop {
+            // A refresh for another command may already have moved the slot
+            if self.routes_to(command, &lost_node).await {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if remaining.is_zero() || !topology_refresh.refresh(remaining).await {
+                    return Err(error);
+                }
+                if self.routes_to(command, &lost_node).await {
+                    // No replica promoted yet
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    tokio::time::sleep(FAILOVER_RETRY_INTERVAL.min(remaining)).await;
+                    continue;
+                }
+            }
+
+            match self.send_routed(command, raw_command).await {
+                Err(e) => match NodeLost::retryable(e.as_ref(), command.readonly) {
+                    Some(node) => {
+                        lost_node = node.to_string();
+                        error = e;
+                    }
+                    None => return Err(e),
+                },
+                reply => {
+                    log::info!("{} for slot {slot} re-routed after losing {lost_node}", command.command);
+                    return reply;
+                }
+            }
+        }
+    }
+
+    /// Whether `command` is still routed to `node`, or cannot be routed
+    async fn routes_to(&self, command: &RedisCommand, node: &str) -> bool {
+        match self.route_command(command).await {
+            Ok(peer) => peer.address().to_string() == node,
+            Err(_) => true,
+        }
+    }
+
+    /// Retry a command while its node answers with MOVED or ASK, up to
+    /// `max_redirects` times, and return the first other reply
+    async fn follow_redirects(
+        &self,
+        command: &RedisCommand,
+        raw_command: &[u8],
+        mut reply: Bytes,
+    ) -> Result<Bytes, Box<dyn Error + Send + Sync>> {
+        use crate::modes::redis::redirect::{RedirectParser, RedirectType};
+
+        let mut redirects = 0u8;
+        loop {
+            let Some(redirect) = RedirectParser::parse_redirect_raw(&reply) else {
+                return Ok(reply);
+            };
+
+            let node_addr = match self.router.handle_redirection(command, &redirect, redirects).await {
+                RoutingDecision::Route { backend_id } => backend_id,
+                RoutingDecision::Redirect { .. } => return Ok(reply),
+                RoutingDecision::Error { message } => return Err(message.into()),
+            };
+            redirects += 1;
+
+            // Retry transparently against the node named in the redirect
+            let asking = match redirect {
+                RedirectType::Moved { slot, address } => {
+                    if let Err(e) = self.handle_moved_redirect(slot, &address).await {
+                        log::error!("Failed to handle MOVED redirect: {}", e);
+                    }
+                    false
+                }
+                RedirectType::Ask { .. } => true,
+            };
+            let peer = self.peer_for_address(&node_addr).await;
+            log::trace!("Retrying {} (slot {:?}) on {}", command.command, command.slot, node_addr);
+            let result = if asking {
+                self.send_asking_to_node(&peer, raw_command).await
+            } else {
+                self.send_to_node(&peer, raw_command).await
+            };
+            reply = result.map_err(|e| format!("upstream {node_addr} failed: {e}"))?;
+        }
+    }
+
+    /// Peer for a node address, reusing the configured peer when there is one
+    async fn peer_for_address(&self, address: &str) -> BasicPeer {
+        match self.cluster_nodes.read().await.get(address) {
+            Some(peer) => peer.clone(),
+            None => self.new_peer(address),
+        }
+    }
+
+    /// Peer for a node missing from the configured ones
+    fn new_peer(&self, address: &str) -> BasicPeer {
+        upstream::new_peer(
+            address,
+            self.upstream_tls.as_ref(),
+            self.backend_tcp_keepalive.as_ref(),
+        )
+    }
+
+    /// Reply for a command refused by the command filter
+    fn filter_command(&self, command: &RedisCommand) -> Option<Bytes> {
+        let reply = self.command_filter.as_ref()?.check(&command.command)?;
+        log::debug!("Refused {} by the command filter", command.command);
+        Some(reply)
+    }
+
+    /// Whether a command is handled by [`Self::execute_cluster_command`]
+    fn is_cluster_command(&self, command: &str) -> bool {
+        scripting::is_evalsha(command)
+            || matches!(command, "SCRIPT" | "FUNCTION" | "SCAN")
+            || (self.fan_out_keyless_commands && Aggregate::for_command(command).is_some())
+    }
+
+    async fn execute_cluster_command(
+        &self,
+        command: &RedisCommand,
+        raw_command: &[u8],
+    ) -> Result<Bytes, Box<dyn Error + Send + Sync>> {
+        if let Some(aggregate) = Aggregate::for_command(&command.command)
+            .filter(|_| self.fan_out_keyless_commands)
+        {
+            return self.fan_out(command, raw_command, aggregate).await;
+        }
+        match command.command.as_str() {
+            "SCAN" => self.execute_scan(command).await,
+            _ => self.execute_script(command, raw_command).await,
+        }
+    }
+
+    /// Run one step of a SCAN over all masters, resuming from the master
+    /// and position encoded in the client's cursor
+    async fn execute_scan(&self, command: &RedisCommand) -> Result<Bytes, Box<dyn Error + Send + Sync>> {
+        let backends = self.slot_mapping.read().await.backends();
+        if backends.is_empty() {
+            return Err("no masters known to SCAN".into());
+        }
+        if backends.len() > scan::MAX_NODES {
+            return Err(format!("SCAN supports at most {} masters", scan::MAX_NODES).into());
+        }
+        let Some(cursor) = command.args.first() else {
+            return Ok(Bytes::from_static(b"-ERR wrong number of arguments for 'scan' command\r\n"));
+        };
+        let (index, node_cursor) = match scan::decode_cursor(cursor, backends.len()) {
+            Ok(position) => position,
+            Err(reply) => return Ok(reply),
+        };
+
+        let peer = self.peer_for_address(&backends[index]).await;
+        log::trace!("Scanning {} from cursor {}", backends[index], node_cursor);
+        let reply = self
+            .send_to_node(&peer, &scan::node_command(command, node_cursor))
+            .await
+            .map_err(|e| format!("upstream {} failed: {e}", backends[index]))?;
+        Ok(scan::client_reply(&reply, index, backends.len())?)
+    }
+
+    /// Run `EVALSHA`, `SCRIPT` or `FUNCTION`
+    ///
+    /// `SCRIPT LOAD` runs on one node and its script is remembered.
+    /// `SCRIPT FLUSH` and the `FUNCTION` subcommands changing libraries run on
+    /// every master, since calls may be routed to any of them.
+    async fn execute_script(
+        &self,
+        command: &RedisCommand,
+        raw_command: &[u8],
+    ) -> Result<Bytes, Box<dyn Error + Send + Sync>> {
+        let subcommand = command
+            .args
+            .first()
+            .map(|subcommand| String::from_utf8_lossy(subcommand).to_uppercase())
+            .unwrap_or_default();
+        match (command.command.as_str(), subcommand.as_str()) {
+            ("EVALSHA" | "EVALSHA_RO", _) => self.execute_evalsha(command, raw_command).await,
+            ("SCRIPT", "LOAD") => {
+                let peer = self.route_command(command).await?;
+                let reply = self.send_to_node(&peer, raw_command).await?;
+                let mut buf = BytesMut::from(reply.as_ref());
+                if let (Ok(Some(RespValue::BulkString(Some(sha)))), Some(script)) =
+                    (RespParser::parse(&mut buf), command.args.get(1))
+                {
+                    let sha = String::from_utf8_lossy(&sha);
+                    self.scripts
+                        .record_load(&peer.address().to_string(), &sha, script.clone());
+                }
+                Ok(reply)
+            }
+            ("SCRIPT", "FLUSH") => {
+                self.scripts.clear();
+                self.fan_out(command, raw_command, Aggregate::AllOk).await
+            }
+            ("FUNCTION", "LOAD" | "DELETE" | "FLUSH" | "RESTORE") => {
+                self.fan_out(command, raw_command, Aggregate::First).await
+            }
+            _ => self.execute_command(command, raw_command).await,
+        }
+    }
+
+    /// Run `EVALSHA` on the node owning its keys, first loading the script
+    /// there if it was loaded through the proxy but not on that node
+    ///
+    /// A `NOSCRIPT` reply for a known script reloads it and retries once.
+    async fn execute_evalsha(
+        &self,
+        command: &RedisCommand,
+        raw_command: &[u8],
+    ) -> Result<Bytes, Box<dyn Error + Send + Sync>> {
+        let sha = command
+            .args
+            .first()
+            .map(|sha| String::from_utf8_lossy(sha).to_string())
+            .unwrap_or_default();
+        let Some(script) = self.scripts.script(&sha) else {
+            return self.execute_command(command, raw_command).await;
+        };
+
+        let peer = self.route_command(command).await?;
+        let node_addr = peer.address().to_string();
+        if !self.scripts.is_loaded(&node_addr, &sha) {
+            self.load_script(&peer, &sha, &script).await?;
+        }
+        let reply = self.execute_command(command, raw_command).await?;
+        if !reply.starts_with(b"-NOSCRIPT") {
+            return Ok(reply);
+        }
+
+        // The node lost the script, or the slot moved to a node without it
+        let peer = self.route_command(command).await?;
+        log::info!("Reloading script {} on {} after NOSCRIPT", sha, peer.address());
+        self.scripts.mark_missing(&node_addr, &sha);
+        self.load_script(&peer, &sha, &script).await?;
+        self.execute_command(command, raw_command).await
+    }
+
+    /// Load a script on a node and record it there
+    async fn load_script(
+        &self,
+        peer: &BasicPeer,
+        sha: &str,
+        script: &[u8],
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let reply = self
+            .send_to_node(peer, &scripting::script_load_command(script))
+            .await?;
+        if reply.starts_with(b"-") {
+            return Err(format!(
+                "SCRIPT LOAD failed on {}: {}",
+                peer.address(),
+                String::from_utf8_lossy(&reply).trim_end()
+            )
+            .into());
+        }
+        self.scripts.mark_loaded(&peer.address().to_string(), sha);
+        Ok(())
+    }
+
+    /// Run a command on every master concurrently and aggregate the replies
+    ///
+    /// Before the slot map is known the command runs on a single node.
+    async fn fan_out(
+        &self,
+        command: &RedisCommand,
+        raw_command: &[u8],
+        aggregate: Aggregate,
+    ) -> Result<Bytes, Box<dyn Error + Send + Sync>> {
+        let backends = self.slot_mapping.read().await.backends();
+        if backends.is_empty() {
+            return self.execute_command(command, raw_command).await;
+        }
+        log::trace!("Fanning {} out to {} masters", command.command, backends.len());
+
+        let replies = futures::future::try_join_all(backends.into_iter().map(|backend| async move {
+            let peer = self.peer_for_address(&backend).await;
+            let reply = self
+                .send_to_node(&peer, raw_command)
+                .await
+                .map_err(|e| format!("upstream {backend} failed: {e}"))?;
+            Ok::<_, Box<dyn Error + Send + Sync>>((backend, reply))
+        }))
+        .await?;
+        Ok(aggregate.merge(&replies))
+    }
+
+    /// Fan a cross-slot command out as per-slot sub-commands and merge the replies
+    async fn execute_split(&self, plan: &SplitPlan) -> Result<Bytes, Box<dyn Error + Send + Sync>> {
+        log::trace!(
+            "Splitting {} across {} slots",
+            plan.command,
+            plan.sub_commands.len()
+        );
+
+        let replies = futures::future::try_join_all(plan.sub_commands.iter().map(|sub| {
+            let command = RedisCommand {
+                command: plan.command.clone(),
+                args: Vec::new(),
+                key: None,
+                slot: Some(sub.slot),
+                readonly: plan.readonly,
+            };
+            async move { self.execute_command(&command, &sub.raw).await }
+        }))
+        .await?;
+
+        Ok(plan.merge(&replies))
+    }
+
+    /// Send a raw command to a node over a pooled connection and read back
+    /// one complete reply
+    async fn send_to_node(
+        &self,
+        peer: &BasicPeer,
+        raw_command: &[u8],
+    ) -> Result<Bytes, Box<dyn Error + Send + Sync>> {
+        self.send_to_node_with(peer, raw_command, false).await
+    }
+
+    /// Send a raw command as `send_to_node` does; with `failover`, a
+    /// connect failure moves the command to another node
+    async fn send_to_node_with(
+        &self,
+        peer: &BasicPeer,
+        raw_command: &[u8],
+        failover: bool,
+    ) -> Result<Bytes, Box<dyn Error + Send + Sync>> {
+        let started = Instant::now();
+        let (peer, connected) = self.acquire(peer, failover).await;
+        let sent = connected.is_ok();
+        let result = async {
+            let mut conn = connected?;
+            // On error the connection is dropped rather than returned to the pool
+            let reply = Self::roundtrip(&mut conn, raw_command).await?;
+            self.pool.release(conn);
+            Ok::<_, Box<dyn Error + Send + Sync>>(reply)
+        }
+        .await;
+        self.report_result(&peer, started, &Self::node_outcome(&result));
+        result.map_err(|e| {
+            if Self::reply_too_large(e.as_ref()) {
+                e
+            } else {
+                NodeLost::error(peer.address().to_string(), e, sent)
+            }
+        })
+    }
+
+    /// Take a pooled connection to `peer`, returned with the node it is to
+    ///
+    /// With `failover`, for commands any node can serve, a failed connect
+    /// is retried on other nodes up to `retry_attempts` times. Failures
+    /// before the last are reported here.
+    async fn acquire(
+        &self,
+        peer: &BasicPeer,
+        failover: bool,
+    ) -> (BasicPeer, Result<PooledConnection, Box<dyn Error + Send + Sync>>) {
+        let mut peer = peer.clone();
+        let mut failed = Vec::new();
+        loop {
+            let mode = self.router.connection_mode(&peer.address().to_string()).await;
+            let started = Instant::now();
+            let connected = self.pool.acquire_in(&peer, mode).await;
+            if connected.is_ok() || !failover || failed.len() >= self.retry_attempts as usize {
+                return (peer, connected);
+            }
+            failed.push(peer.address().to_string());
+            let Ok(next) = self.select_keyless_node(&failed).await else {
+                return (peer, connected);
+            };
+            self.report_result(&peer, started, &connected);
+            log::warn!(
+                "Connecting to Redis node {} failed, retrying on {}",
+                peer.address(),
+                next.address()
+            );
+            peer = next;
+        }
+    }
+
+    /// Write several raw commands to a node over one pooled connection and
+    /// read back one reply per command, in order
+    ///
+    /// When the connection fails part-way, commands already answered keep
+    /// their replies and the rest get the error.
+    ///
+    /// With `failover`, a connect failure moves the commands to another node.
+    async fn send_pipeline_to_node(
+        &self,
+        peer: &BasicPeer,
+        raw_commands: &[&[u8]],
+        failover: bool,
+    ) -> Vec<Result<Bytes, Box<dyn Error + Send + Sync>>> {
+        let started = Instant::now();
+        let (peer, connected) = self.acquire(peer, failover).await;
+        let sent = connected.is_ok();
+        let mut replies = Vec::with_capacity(raw_commands.len());
+        let result = async {
+            let mut conn = connected?;
+            conn.stream.write_all(&raw_commands.concat()).await?;
+            conn.stream.flush().await?;
+            while replies.len() < raw_commands.len() {
+                replies.push(Self::read_reply(&mut conn).await?);
+            }
+            // On error the connection is dropped rather than returned to the pool
+            self.pool.release(conn);
+            Ok::<_, Box<dyn Error + Send + Sync>>(())
+        }
+        .await;
+        self.report_result(&peer, started, &Self::node_outcome(&result));
+
+        let answered = replies.len();
+        let mut results: Vec<Result<Bytes, Box<dyn Error + Send + Sync>>> =
+            replies.into_iter().map(Ok).collect();
+        if let Err(e) = result {
+            let node_addr = peer.address();
+            // After an oversized reply the node is fine, only the connection
+            // carrying the remaining replies was dropped
+            let node_lost = !Self::reply_too_large(e.as_ref());
+            results.extend(
+                (answered..raw_commands.len()).map(|_| {
+                    let message = format!("upstream {node_addr} failed: {e}");
+                    if node_lost {
+                        Err(NodeLost::error(node_addr.to_string(), message, sent))
+                    } else {
+                        Err(message.into())
+                    }
+                }),
+            );
+        }
+        results
+    }
+
+    async fn roundtrip(
+        conn: &mut PooledConnection,
+        raw_command: &[u8],
+    ) -> Result<Bytes, Box<dyn Error + Send + Sync>> {
+        conn.stream.write_all(raw_command).await?;
+        conn.stream.flush().await?;
+        Self::read_reply(conn).await
+    }
+
+    /// Read one complete RESP reply from an upstream connection
+    async fn read_reply(conn: &mut PooledConnection) -> Result<Bytes, Box<dyn Error + Send + Sync>> {
+        let mut read_buf = [0u8; 8192];
+        loop {
+            if let Some(raw_reply) = conn.parser.split_frame(&mut conn.buf)? {
+                return Ok(raw_reply);
+            }
+
+            let n = conn.stream.read(&mut read_buf).await?;
+            if n == 0 {
+                return Err("connection closed by Redis node".into());
+            }
+            conn.buf.extend_from_slice(&read_buf[..n]);
+        }
+    }
+
+    /// Whether `error` is a reply over the pool's `max_reply_size`: the node
+    /// answered, so it is neither a node failure nor worth retrying elsewhere
+    fn reply_too_large(error: &(dyn Error + Send + Sync + 'static)) -> bool {
+        matches!(
+            error.downcast_ref::<RespParseError>(),
+            Some(RespParseError::FrameTooLarge { .. })
+        )
+    }
+
+    /// `result` as reported to node health, where oversized replies count
+    /// as answered
+    fn node_outcome<T>(result: &Result<T, Box<dyn Error + Send + Sync>>) -> Result<(), ()> {
+        match result {
+            Err(e) if !Self::reply_too_large(e.as_ref()) => Err(()),
+            _ => Ok(()),
+        }
+    }
+
+    async fn write_to_client(client_stream: &mut Stream, data: &[u8]) -> std::io::Result<()> {
+        client_stream.write_all(data).await?;
+        client_stream.flush().await
+    }
+
+    /// Handle MOVED redirection by updating slot mapping, or by refreshing
+    /// the whole topology when many slots are moving
+    #[tracing::instrument(name = "redis.moved_redirect", skip(self))]
+    async fn handle_moved_redirect(&self, slot: u16, new_address: &str) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if !self.router.record_moved(slot, new_address).await {
+            return Ok(());
+        }
 
-    /// Handle MOVED redirection by updating slot mapping
-    async fn handle_moved_redirect(&self, slot: u16, new_address: &str) -> Result<(), Box<dyn Error + Send + Sync>> {
-        log::info!("Updating slot mapping: slot {} moved to {}", slot, new_address);
-        
-        // Update the slot mapping
-        let mut slot_mapping = self.slot_mapping.write().await;
-        let mut slot_ranges = HashMap::new();
-        slot_ranges.insert(new_address.to_string(), vec![(slot, slot)]);
-        slot_mapping.update_slot_mapping(slot_ranges);
-        
         // Also update cluster nodes if this is a new node
         let mut cluster_nodes = self.cluster_nodes.write().await;
         if !cluster_nodes.contains_key(new_address) {
-            let peer = BasicPeer::new(new_address);
+            let peer = self.new_peer(new_address);
             cluster_nodes.insert(new_address.to_string(), peer);
             log::info!("Added new cluster node: {}", new_address);
         }
-        
-        Ok(())
+        
+        Ok(())
+    }
+    
+    /// Send a command to the importing node of an ASK redirect, preceded by
+    /// `ASKING` on the same pooled connection
+    ///
+    /// Both are written at once and their replies read back together, so
+    /// the redirect costs one round trip.
+    #[tracing::instrument(name = "redis.ask_redirect", skip_all, fields(node = %peer.address()))]
+    async fn send_asking_to_node(
+        &self,
+        peer: &BasicPeer,
+        raw_command: &[u8],
+    ) -> Result<Bytes, Box<dyn Error + Send + Sync>> {
+        let mut replies = self
+            .send_pipeline_to_node(peer, &[ASKING_COMMAND, raw_command], false)
+            .await
+            .into_iter();
+        let (Some(asking_reply), Some(reply)) = (replies.next(), replies.next()) else {
+            return Err("missing reply to ASKING".into());
+        };
+        let asking_reply = asking_reply?;
+        if !asking_reply.starts_with(b"+OK") {
+            return Err(format!("ASKING command failed: {}", String::from_utf8_lossy(&asking_reply)).into());
+        }
+        reply
+    }
+}
+
+impl NodeHealth for RedisProtocolApp {
+    fn avoid(&self, node: &str) -> bool {
+        self.is_ejected(node) || self.is_circuit_open(node) || self.is_draining(node)
+    }
+}
+
+#[async_trait]
+impl ServerApp for RedisProtocolApp {
+    #[tracing::instrument(
+        name = "redis.connection",
+        skip_all,
+        fields(client.addr = tracing::field::Empty)
+    )]
+    async fn process_new(
+        self: &Arc<Self>,
+        mut client_stream: Stream,
+        _shutdown: &ShutdownWatch,
+    ) -> Option<Stream> {
+        // Get client address for logging and potential future use
+        let mut client_addr = match client_stream
+            .get_socket_digest()
+            .and_then(|digest| digest.peer_addr().cloned())
+        {
+            Some(addr) => addr.to_string(),
+            None => {
+                log::warn!("Could not get client address, using fallback identifier");
+                format!("unknown-{:x}", std::ptr::addr_of!(client_stream) as usize)
+            }
+        };
+
+        // Held until the connection ends; past the limit the client gets the
+        // same error Redis sends at maxclients
+        let _permit = match &self.connection_limiter {
+            Some(limiter) => match limiter.acquire().await {
+                Some(permit) => Some(permit),
+                None => {
+                    log::warn!(
+                        "Rejecting Redis client {client_addr}: max_connections ({}) reached",
+                        limiter.max_connections()
+                    );
+                    let _ = client_stream
+                        .write_all(b"-ERR max number of clients reached\r\n")
+                        .await;
+                    return None;
+                }
+            },
+            None => None,
+        };
+        let _session = GaugeGuard::new(Arc::clone(&self.sessions));
+
+        // Behind another load balancer the real client comes from the PROXY header
+        let mut initial = Vec::new();
+        if self.proxy_protocol {
+            match crate::core::proxy_protocol::read_header(&mut client_stream).await {
+                Ok((header, remaining)) => {
+                    if let Some(source) = header.source() {
+                        log::debug!("PROXY header from {client_addr}: client is {source}");
+                        client_addr = source.to_string();
+                    }
+                    initial = remaining;
+                }
+                Err(e) => {
+                    log::warn!("Rejecting Redis client {client_addr}: {e}");
+                    return None;
+                }
+            }
+        }
+
+        if let Some(access_control) = &self.access_control {
+            if !access_control.check(&client_addr) {
+                log::warn!("Rejecting Redis client {client_addr}: denied by access control lists");
+                return None;
+            }
+        }
+
+        log::info!("New Redis client connection from: {}", client_addr);
+        tracing::Span::current().record("client.addr", client_addr.as_str());
+
+        // Each command is routed to the node owning its slot
+        self.forward_redis_data(client_stream, &client_addr, &initial).await;
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::admin::explain::ExplainRequest;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_slot_calculation() {
+        // Test vectors - need to verify these match actual Redis implementation
+        assert_eq!(SlotMapping::calculate_slot("123456789"), 12739);
+        assert_eq!(SlotMapping::calculate_slot("foo"), 44950 % 16384);
+        assert_eq!(SlotMapping::calculate_slot("bar"), 5061);
+
+        // Test hash tags
+        assert_eq!(
+            SlotMapping::calculate_slot("foo{hash_tag}bar"),
+            SlotMapping::calculate_slot("hash_tag")
+        );
+        assert_eq!(
+            SlotMapping::calculate_slot("{user1000}.following"),
+            SlotMapping::calculate_slot("user1000")
+        );
+    }
+
+    #[test]
+    fn test_crc16() {
+        // Test CRC16 calculation
+        // These values should match Redis cluster slot calculation
+        assert_eq!(crate::utils::crc16(b"123456789"), 12739);
+        assert_eq!(crate::utils::crc16(b"foo"), 44950);
+    }
+
+    #[test]
+    fn test_redis_config_creation() {
+        let config = RedisConfig {
+            cluster_nodes: vec!["127.0.0.1:6379".to_string(), "127.0.0.1:6380".to_string()],
+            slot_refresh_interval_sec: 30,
+            max_redirects: 3,
+            connection_timeout_ms: 5000,
+            ..Default::default()
+        };
+
+        assert_eq!(config.cluster_nodes.len(), 2);
+        assert_eq!(config.slot_refresh_interval_sec, 30);
+        assert_eq!(config.max_redirects, 3);
+        assert_eq!(config.connection_timeout_ms, 5000);
+    }
+
+    #[test]
+    fn test_slot_mapping_creation() {
+        let mapping = SlotMapping::new();
+        assert!(!mapping.is_complete());
+        assert_eq!(mapping.get_backend_for_slot(0), None);
+    }
+
+    #[test]
+    fn test_slot_mapping_update() {
+        let mut mapping = SlotMapping::new();
+
+        let mut slot_ranges = HashMap::new();
+        slot_ranges.insert("node1".to_string(), vec![(0, 5460)]);
+        slot_ranges.insert("node2".to_string(), vec![(5461, 10922)]);
+        slot_ranges.insert("node3".to_string(), vec![(10923, 16383)]);
+
+        mapping.update_slot_mapping(slot_ranges);
+
+        assert!(mapping.is_complete());
+        assert_eq!(mapping.get_backend_for_slot(0), Some("node1".to_string()));
+        assert_eq!(
+            mapping.get_backend_for_slot(5461),
+            Some("node2".to_string())
+        );
+        assert_eq!(
+            mapping.get_backend_for_slot(16383),
+            Some("node3".to_string())
+        );
+    }
+
+    #[test]
+    fn test_slot_mapping_partial_coverage() {
+        let mut mapping = SlotMapping::new();
+
+        let mut slot_ranges = HashMap::new();
+        slot_ranges.insert("node1".to_string(), vec![(0, 1000), (2000, 2099)]);
+
+        mapping.update_slot_mapping(slot_ranges);
+
+        assert!(!mapping.is_complete());
+        assert_eq!(mapping.covered_slots(), 1101);
+        assert_eq!(mapping.slot_counts().get("node1"), Some(&1101));
+        assert_eq!(mapping.get_backend_for_slot(0), Some("node1".to_string()));
+        assert_eq!(mapping.get_backend_for_slot(1001), None);
+    }
+
+    #[test]
+    fn test_redis_command_creation() {
+        let command = RedisCommand {
+            command: "GET".to_string(),
+            args: vec![],
+            key: Some("mykey".to_string()),
+            slot: Some(SlotMapping::calculate_slot("mykey")),
+            readonly: true,
+        };
+
+        assert_eq!(command.command, "GET");
+        assert_eq!(command.key, Some("mykey".to_string()));
+        assert!(command.readonly);
+        assert!(command.slot.is_some());
+    }
+
+    #[test]
+    fn test_redis_response_variants() {
+        let data_response = RedisResponse::Data(bytes::Bytes::from("OK"));
+        let moved_response = RedisResponse::Moved {
+            slot: 1234,
+            new_address: "127.0.0.1:6379".to_string(),
+        };
+        let ask_response = RedisResponse::Ask {
+            slot: 5678,
+            new_address: "127.0.0.1:6380".to_string(),
+        };
+        let error_response = RedisResponse::Error("ERR something went wrong".to_string());
+
+        match data_response {
+            RedisResponse::Data(_) => {}
+            _ => panic!("Expected Data response"),
+        }
+
+        match moved_response {
+            RedisResponse::Moved { slot, new_address } => {
+                assert_eq!(slot, 1234);
+                assert_eq!(new_address, "127.0.0.1:6379");
+            }
+            _ => panic!("Expected Moved response"),
+        }
+
+        match ask_response {
+            RedisResponse::Ask { slot, new_address } => {
+                assert_eq!(slot, 5678);
+                assert_eq!(new_address, "127.0.0.1:6380");
+            }
+            _ => panic!("Expected Ask response"),
+        }
+
+        match error_response {
+            RedisResponse::Error(msg) => {
+                assert_eq!(msg, "ERR something went wrong");
+            }
+            _ => panic!("Expected Error response"),
+        }
+    }
+
+    #[test]
+    fn test_slot_calculation_edge_cases() {
+        // Empty string
+        let empty_slot = SlotMapping::calculate_slot("");
+        assert!(empty_slot < 16384);
+
+        // Single character
+        let single_char_slot = SlotMapping::calculate_slot("a");
+        assert!(single_char_slot < 16384);
+
+        // Very long key
+        let long_key = "a".repeat(1000);
+        let long_key_slot = SlotMapping::calculate_slot(&long_key);
+        assert!(long_key_slot < 16384);
+
+        // Special characters
+        let special_key = "key-with:special@characters#";
+        let special_slot = SlotMapping::calculate_slot(special_key);
+        assert!(special_slot < 16384);
+    }
+
+    #[test]
+    fn test_hash_tag_extraction() {
+        // No hash tag
+        assert_eq!(
+            SlotMapping::calculate_slot("simple_key"),
+            SlotMapping::calculate_slot("simple_key")
+        );
+
+        // Hash tag at the beginning
+        let key1 = "{tag}key";
+        let tag_only = "tag";
+        assert_eq!(
+            SlotMapping::calculate_slot(key1),
+            SlotMapping::calculate_slot(tag_only)
+        );
+
+        // Hash tag in the middle
+        let key2 = "prefix{tag}suffix";
+        assert_eq!(
+            SlotMapping::calculate_slot(key2),
+            SlotMapping::calculate_slot(tag_only)
+        );
+
+        // Multiple hash tags (should use first one)
+        let key3 = "prefix{tag1}middle{tag2}suffix";
+        assert_eq!(
+            SlotMapping::calculate_slot(key3),
+            SlotMapping::calculate_slot("tag1")
+        );
+
+        // Empty hash tag (should use full key)
+        let key4 = "prefix{}suffix";
+        assert_eq!(
+            SlotMapping::calculate_slot(key4),
+            SlotMapping::calculate_slot(key4)
+        );
+
+        // Invalid hash tag (no closing brace)
+        let key5 = "prefix{tag_suffix";
+        assert_eq!(
+            SlotMapping::calculate_slot(key5),
+            SlotMapping::calculate_slot(key5)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_redis_cluster_proxy_creation() {
+        let config = RedisConfig {
+            cluster_nodes: vec!["127.0.0.1:6379".to_string()],
+            slot_refresh_interval_sec: 30,
+            max_redirects: 3,
+            connection_timeout_ms: 5000,
+            ..Default::default()
+        };
+
+        let proxy = RedisClusterProxy::new(config);
+
+        assert_eq!(proxy.get_config().cluster_nodes.len(), 1);
+        assert_eq!(proxy.get_config().max_redirects, 3);
+    }
+
+    #[tokio::test]
+    async fn test_redis_protocol_app_creation() {
+        use pingora_core::connectors::TransportConnector;
+
+        let connector = TransportConnector::new(None);
+        let cluster_nodes = Arc::new(RwLock::new(HashMap::new()));
+        let slot_mapping = Arc::new(RwLock::new(SlotMapping::new()));
+
+        let _app = RedisProtocolApp::new(connector, cluster_nodes, slot_mapping, 3);
+
+        // Just test creation succeeds
+        assert!(true);
+    }
+
+    #[test]
+    fn test_slot_mapping_comprehensive_coverage() {
+        let mut mapping = SlotMapping::new();
+
+        // Create a full mapping covering all 16384 slots
+        let mut slot_ranges = HashMap::new();
+        let slots_per_node = 16384 / 3;
+
+        slot_ranges.insert("node1".to_string(), vec![(0, slots_per_node - 1)]);
+        slot_ranges.insert(
+            "node2".to_string(),
+            vec![(slots_per_node, 2 * slots_per_node - 1)],
+        );
+        slot_ranges.insert("node3".to_string(), vec![(2 * slots_per_node, 16383)]);
+
+        mapping.update_slot_mapping(slot_ranges);
+
+        assert!(mapping.is_complete());
+
+        // Test boundary conditions
+        assert_eq!(mapping.get_backend_for_slot(0), Some("node1".to_string()));
+        assert_eq!(
+            mapping.get_backend_for_slot(slots_per_node - 1),
+            Some("node1".to_string())
+        );
+        assert_eq!(
+            mapping.get_backend_for_slot(slots_per_node),
+            Some("node2".to_string())
+        );
+        assert_eq!(
+            mapping.get_backend_for_slot(16383),
+            Some("node3".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_redis_cluster_proxy_with_health_check() {
+        let config = RedisConfig {
+            cluster_nodes: vec!["127.0.0.1:6379".to_string()],
+            slot_refresh_interval_sec: 30,
+            max_redirects: 3,
+            connection_timeout_ms: 5000,
+            ..Default::default()
+        };
+
+        let proxy = RedisClusterProxy::new(config).with_health_check();
+        assert!(proxy.health_manager.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_active_health_checks() {
+        // A master answering PING and CLUSTER NODES, and a node that is down
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let master = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut buf = [0u8; 1024];
+                    while let Ok(n) = socket.read(&mut buf).await {
+                        let reply = match &buf[..n] {
+                            [] => break,
+                            b"*1\r\n$4\r\nPING\r\n" => "+PONG\r\n".to_string(),
+                            _ => {
+                                let nodes = format!(
+                                    "e7d1eecce10fd6bb5eb35b9f99a514335d9ba9ca {master}@17000 myself,master - 0 0 1 connected 0-16383\n"
+                                );
+                                format!("${}\r\n{nodes}\r\n", nodes.len())
+                            }
+                        };
+                        socket.write_all(reply.as_bytes()).await.unwrap();
+                    }
+                });
+            }
+        });
+        let down = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap();
+
+        let proxy = RedisClusterProxy::new(RedisConfig::default()).with_health_check();
+        let health_manager = proxy.health_manager.clone().unwrap();
+        RedisClusterProxy::check_nodes(
+            &health_manager,
+            &proxy.node_health,
+            &proxy.health_events,
+            vec![master.to_string(), down.to_string()],
+        )
+        .await;
+
+        assert!(health_manager.admits(master));
+        assert!(!health_manager.admits(down));
+        let checked = proxy.checked_nodes().await;
+        let master = checked.iter().find(|backend| backend.addr == master).unwrap();
+        match &master.metadata {
+            crate::core::BackendMetadata::Redis {
+                node_id,
+                slot_ranges,
+                is_master,
+                ..
+            } => {
+                assert_eq!(node_id, "e7d1eecce10fd6bb5eb35b9f99a514335d9ba9ca");
+                assert_eq!(slot_ranges, &[(0, 16383)]);
+                assert!(is_master);
+            }
+            _ => panic!("Expected Redis metadata"),
+        }
+        assert!(!checked.iter().find(|backend| backend.addr == down).unwrap().healthy);
+    }
+
+    #[test]
+    fn test_command_from_parts_matches_full_parse() {
+        use crate::modes::redis::resp::RespEncoder;
+
+        for command in [
+            RespEncoder::create_command("set", &["{user}:1", "value"]),
+            RespEncoder::create_command("GET", &["key"]),
+            RespEncoder::create_command("MGET", &["a", "b"]),
+            RespEncoder::create_command("PING", &[]),
+        ] {
+            let frame = RespEncoder::encode(&command);
+            let parts = RespParser::command_parts(&frame).unwrap();
+            let fast = RedisProtocolApp::command_from_parts(parts).unwrap();
+            let full = RedisProtocolApp::command_from_value(command).unwrap();
+            assert_eq!(fast.command, full.command);
+            assert_eq!(fast.args, full.args);
+            assert_eq!(fast.key, full.key);
+            assert_eq!(fast.slot, full.slot);
+            assert_eq!(fast.readonly, full.readonly);
+        }
+        assert!(RedisProtocolApp::command_from_parts(Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_command_from_parts_binary_key() {
+        let key = Bytes::from_static(b"\xff\xfe{tag}");
+        let command =
+            RedisProtocolApp::command_from_parts(vec![Bytes::from("GET"), key.clone()]).unwrap();
+        assert_eq!(command.args, vec![key.clone()]);
+        assert_eq!(command.slot, Some(SlotMapping::calculate_slot_bytes(&key)));
+        assert_eq!(command.slot, Some(SlotMapping::calculate_slot("tag")));
+    }
+
+    #[test]
+    fn test_take_frame_complete_and_partial() {
+        let mut buf = BytesMut::from("*2\r\n$3\r\nGET\r\n$3\r\nfoo\r\n*1\r\n$4\r\nPI");
+
+        let (value, raw) = RedisProtocolApp::take_frame(&mut buf).unwrap().unwrap();
+        assert_eq!(&raw[..], b"*2\r\n$3\r\nGET\r\n$3\r\nfoo\r\n");
+        let command = RedisProtocolApp::command_from_value(value).unwrap();
+        assert_eq!(command.command, "GET");
+        assert_eq!(command.key, Some("foo".to_string()));
+        assert_eq!(command.slot, Some(SlotMapping::calculate_slot("foo")));
+
+        // Second command is incomplete and must be left in the buffer
+        assert!(RedisProtocolApp::take_frame(&mut buf).unwrap().is_none());
+        assert_eq!(&buf[..], b"*1\r\n$4\r\nPI");
+
+        buf.extend_from_slice(b"NG\r\n");
+        let (value, _) = RedisProtocolApp::take_frame(&mut buf).unwrap().unwrap();
+        let command = RedisProtocolApp::command_from_value(value).unwrap();
+        assert_eq!(command.command, "PING");
+        assert!(command.slot.is_none());
+        assert!(buf.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_route_command_by_slot() {
+        let connector = TransportConnector::new(None);
+        let cluster_nodes = Arc::new(RwLock::new(HashMap::new()));
+        cluster_nodes
+            .write()
+            .await
+            .insert("127.0.0.1:7001".to_string(), BasicPeer::new("127.0.0.1:7001"));
+
+        let mut slot_ranges = HashMap::new();
+        slot_ranges.insert("127.0.0.1:7001".to_string(), vec![(0, 8191)]);
+        slot_ranges.insert("127.0.0.1:7002".to_string(), vec![(8192, 16383)]);
+        let mut mapping = SlotMapping::new();
+        mapping.update_slot_mapping(slot_ranges);
+        let slot_mapping = Arc::new(RwLock::new(mapping));
+
+        let app = RedisProtocolApp::new(connector, cluster_nodes, slot_mapping, 3);
+
+        let low = RedisCommand {
+            command: "GET".to_string(),
+            args: vec![],
+            key: None,
+            slot: Some(100),
+            readonly: true,
+        };
+        let peer = app.route_command(&low).await.unwrap();
+        assert_eq!(peer.address().to_string(), "127.0.0.1:7001");
+
+        // Node only known from the slot mapping is still routable
+        let high = RedisCommand {
+            slot: Some(10000),
+            ..low
+        };
+        let peer = app.route_command(&high).await.unwrap();
+        assert_eq!(peer.address().to_string(), "127.0.0.1:7002");
+    }
+
+    #[tokio::test]
+    async fn test_open_circuit_fails_commands_for_node() {
+        let cluster_nodes = Arc::new(RwLock::new(HashMap::new()));
+        let mut slot_ranges = HashMap::new();
+        slot_ranges.insert("127.0.0.1:7001".to_string(), vec![(0, 16383)]);
+        let mut mapping = SlotMapping::new();
+        mapping.update_slot_mapping(slot_ranges);
+
+        let app = RedisProtocolApp::new(
+            TransportConnector::new(None),
+            cluster_nodes,
+            Arc::new(RwLock::new(mapping)),
+            3,
+        )
+        .with_circuit_breaker(Some(CircuitBreaker::new(crate::config::CircuitBreakerConfig {
+            min_requests: 2,
+            ..Default::default()
+        })));
+
+        let command = RedisCommand {
+            command: "GET".to_string(),
+            args: vec![],
+            key: None,
+            slot: Some(100),
+            readonly: true,
+        };
+        let peer = app.route_command(&command).await.unwrap();
+        let failed: Result<(), &str> = Err("connection refused");
+        app.report_result(&peer, Instant::now(), &failed);
+        app.report_result(&peer, Instant::now(), &failed);
+
+        let err = app.route_command(&command).await.unwrap_err();
+        assert_eq!(err.to_string(), "Circuit open for node 127.0.0.1:7001");
+    }
+
+    /// Fake cluster node answering every read with `reply(own address)`
+    async fn fake_node(reply: impl FnOnce(&str) -> String) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let reply = reply(&addr);
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let reply = reply.clone();
+                tokio::spawn(async move {
+                    let mut buf = [0u8; 1024];
+                    while let Ok(n) = socket.read(&mut buf).await {
+                        if n == 0 || socket.write_all(reply.as_bytes()).await.is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+        });
+        addr
+    }
+
+    #[tokio::test]
+    async fn test_moved_is_retried_transparently() {
+        let target = fake_node(|_| "+OK\r\n".to_string()).await;
+        let stale = fake_node(|_| format!("-MOVED 100 {target}\r\n")).await;
+
+        let mut slot_ranges = HashMap::new();
+        slot_ranges.insert(stale.clone(), vec![(0, 16383)]);
+        let mut mapping = SlotMapping::new();
+        mapping.update_slot_mapping(slot_ranges);
+        let mapping = Arc::new(RwLock::new(mapping));
+
+        let command = RedisCommand {
+            command: "SET".to_string(),
+            args: vec![],
+            key: None,
+            slot: Some(100),
+            readonly: false,
+        };
+        let raw = b"*3\r\n$3\r\nSET\r\n$1\r\na\r\n$1\r\n1\r\n";
+
+        let app = RedisProtocolApp::new(
+            TransportConnector::new(None),
+            Arc::new(RwLock::new(HashMap::new())),
+            Arc::clone(&mapping),
+            3,
+        );
+        let reply = app.execute_command(&command, raw).await.unwrap();
+        assert_eq!(reply.as_ref(), b"+OK\r\n");
+
+        // With no redirects allowed the MOVED error reaches the client
+        let mut slot_ranges = HashMap::new();
+        slot_ranges.insert(stale.clone(), vec![(0, 16383)]);
+        mapping.write().await.update_slot_mapping(slot_ranges);
+        let app = RedisProtocolApp::new(
+            TransportConnector::new(None),
+            Arc::new(RwLock::new(HashMap::new())),
+            mapping,
+            0,
+        );
+        let reply = app.execute_command(&command, raw).await.unwrap();
+        assert!(reply.starts_with(b"-MOVED 100"));
+    }
+
+    #[tokio::test]
+    async fn test_ask_pipelines_asking_over_a_pooled_connection() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        // Importing node answering ASKING and GET, counting its connections
+        // and the reads that did not carry both commands
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let target = listener.local_addr().unwrap().to_string();
+        let connections = Arc::new(AtomicUsize::new(0));
+        let partial_reads = Arc::new(AtomicUsize::new(0));
+        tokio::spawn({
+            let connections = Arc::clone(&connections);
+            let partial_reads = Arc::clone(&partial_reads);
+            async move {
+                while let Ok((mut socket, _)) = listener.accept().await {
+                    connections.fetch_add(1, Ordering::SeqCst);
+                    let partial_reads = Arc::clone(&partial_reads);
+                    tokio::spawn(async move {
+                        let mut buf = [0u8; 1024];
+                        while let Ok(n) = socket.read(&mut buf).await {
+                            if n == 0 {
+                                break;
+                            }
+                            let data = String::from_utf8_lossy(&buf[..n]).to_string();
+                            if !(data.starts_with("*1\r\n$6\r\nASKING\r\n") && data.contains("GET")) {
+                                partial_reads.fetch_add(1, Ordering::SeqCst);
+                            }
+                            if socket.write_all(b"+OK\r\n$3\r\nbar\r\n").await.is_err() {
+                                break;
+                            }
+                        }
+                    });
+                }
+            }
+        });
+        let source = fake_node(|_| format!("-ASK 100 {target}\r\n")).await;
+
+        let mut mapping = SlotMapping::new();
+        mapping.assign_slots(&source, 0, 16383);
+        let app = RedisProtocolApp::new(
+            TransportConnector::new(None),
+            Arc::new(RwLock::new(HashMap::new())),
+            Arc::new(RwLock::new(mapping)),
+            3,
+        );
+        let command = RedisCommand {
+            command: "GET".to_string(),
+            args: vec![],
+            key: None,
+            slot: Some(100),
+            readonly: true,
+        };
+        for _ in 0..3 {
+            let reply = app
+                .execute_command(&command, b"*2\r\n$3\r\nGET\r\n$3\r\nfoo\r\n")
+                .await
+                .unwrap();
+            assert_eq!(reply.as_ref(), b"$3\r\nbar\r\n");
+        }
+        assert_eq!(connections.load(Ordering::SeqCst), 1);
+        assert_eq!(partial_reads.load(Ordering::SeqCst), 0);
     }
-    
-    /// Handle ASK redirection by connecting to target node and sending ASKING command
-    async fn handle_ask_redirect(&self, slot: u16, target_address: &str, original_command: &[u8]) -> Result<(), Box<dyn Error + Send + Sync>> {
-        use tokio::io::{AsyncReadExt, AsyncWriteExt};
-        
-        log::info!("Handling ASK redirect: slot {} to {}", slot, target_address);
-        
-        // Create connection to target node
-        let target_peer = BasicPeer::new(target_address);
-        let mut target_stream = self.connector.new_stream(&target_peer).await?;
-        
-        // Send ASKING command first
-        let asking_cmd = b"*1\r\n$6\r\nASKING\r\n";
-        target_stream.write_all(asking_cmd).await?;
-        target_stream.flush().await?;
-        
-        // Read ASKING response (should be +OK)
-        let mut asking_response = [0u8; 64];
-        let n = target_stream.read(&mut asking_response).await?;
-        let asking_resp_str = String::from_utf8_lossy(&asking_response[..n]);
-        
-        if !asking_resp_str.starts_with("+OK") {
-            return Err(format!("ASKING command failed: {}", asking_resp_str).into());
+
+    #[tokio::test]
+    async fn test_moved_storm_refreshes_topology_once() {
+        let mut slot_ranges = HashMap::new();
+        slot_ranges.insert("127.0.0.1:7001".to_string(), vec![(0, 16383)]);
+        let mut mapping = SlotMapping::new();
+        mapping.update_slot_mapping(slot_ranges);
+        let mapping = Arc::new(RwLock::new(mapping));
+        let topology_refresh = Arc::new(TopologyRefresh::new());
+        let app = RedisProtocolApp::new(
+            TransportConnector::new(None),
+            Arc::new(RwLock::new(HashMap::new())),
+            Arc::clone(&mapping),
+            3,
+        )
+        .with_failover(Arc::clone(&topology_refresh), std::time::Duration::ZERO);
+
+        let storm = failover::MOVED_STORM_THRESHOLD as u16;
+        for slot in 0..storm - 1 {
+            app.handle_moved_redirect(slot, "127.0.0.1:7002").await.unwrap();
         }
-        
-        // Send the original command
-        target_stream.write_all(original_command).await?;
-        target_stream.flush().await?;
-        
-        // Read and forward the response
-        let mut response_buf = [0u8; 8192];
-        let _response_len = target_stream.read(&mut response_buf).await?;
-        
-        log::info!("ASK redirect completed successfully for slot {} to {}", slot, target_address);
-        
-        // Note: In a full implementation, we would forward this response back to the client
-        // For now, we just log success
-        
-        Ok(())
+        let requested = || tokio::time::timeout(std::time::Duration::from_millis(50), topology_refresh.requested());
+        assert!(requested().await.is_err());
+
+        for slot in storm - 1..storm + 10 {
+            app.handle_moved_redirect(slot, "127.0.0.1:7002").await.unwrap();
+        }
+        assert!(requested().await.is_ok());
+        assert!(requested().await.is_err());
+        // The slots moved in the storm are left to the refresh
+        let mapping = mapping.read().await;
+        assert_eq!(mapping.get_backend_for_slot(0).as_deref(), Some("127.0.0.1:7002"));
+        assert_eq!(mapping.get_backend_for_slot(storm).as_deref(), Some("127.0.0.1:7001"));
     }
-}
 
-#[async_trait]
-impl ServerApp for RedisProtocolApp {
-    async fn process_new(
-        self: &Arc<Self>,
-        client_stream: Stream,
-        _shutdown: &ShutdownWatch,
-    ) -> Option<Stream> {
-        // Get client address for logging and potential future use
-        let client_addr = match client_stream
-            .get_socket_digest()
-            .and_then(|digest| digest.peer_addr().cloned())
-        {
-            Some(addr) => addr.to_string(),
-            None => {
-                log::warn!("Could not get client address, using fallback identifier");
-                format!("unknown-{:x}", std::ptr::addr_of!(client_stream) as usize)
+    #[tokio::test]
+    async fn test_concurrent_moved_redirects_keep_other_slots() {
+        let mut mapping = SlotMapping::new();
+        mapping.update_slot_mapping(HashMap::from([("127.0.0.1:7001".to_string(), vec![(0, 16383)])]));
+        let mapping = Arc::new(RwLock::new(mapping));
+        let app = Arc::new(RedisProtocolApp::new(
+            TransportConnector::new(None),
+            Arc::new(RwLock::new(HashMap::new())),
+            Arc::clone(&mapping),
+            3,
+        ));
+
+        let tasks: Vec<_> = (0..64u16)
+            .map(|i| {
+                let app = Arc::clone(&app);
+                tokio::spawn(async move {
+                    let node = if i % 2 == 0 { "127.0.0.1:7002" } else { "127.0.0.1:7003" };
+                    app.handle_moved_redirect(i * 100, node).await.unwrap();
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        let mapping = mapping.read().await;
+        assert!(mapping.is_complete());
+        let counts = mapping.slot_counts();
+        assert_eq!(counts["127.0.0.1:7001"], 16384 - 64);
+        assert_eq!(counts["127.0.0.1:7002"], 32);
+        assert_eq!(counts["127.0.0.1:7003"], 32);
+        assert_eq!(mapping.get_backend_for_slot(200).as_deref(), Some("127.0.0.1:7002"));
+        assert_eq!(mapping.get_backend_for_slot(300).as_deref(), Some("127.0.0.1:7003"));
+        assert_eq!(mapping.get_backend_for_slot(301).as_deref(), Some("127.0.0.1:7001"));
+    }
+
+    #[tokio::test]
+    async fn test_lost_master_fails_over_to_promoted_replica() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let lost = listener.local_addr().unwrap().to_string();
+        drop(listener);
+        let promoted = fake_node(|_| "+OK\r\n".to_string()).await;
+
+        let mut slot_ranges = HashMap::new();
+        slot_ranges.insert(lost.clone(), vec![(0, 16383)]);
+        let mut mapping = SlotMapping::new();
+        mapping.update_slot_mapping(slot_ranges);
+        let mapping = Arc::new(RwLock::new(mapping));
+
+        // Stands in for the refresh task finding the promoted replica
+        let topology_refresh = Arc::new(TopologyRefresh::new());
+        let refresh_task = tokio::spawn({
+            let topology_refresh = Arc::clone(&topology_refresh);
+            let mapping = Arc::clone(&mapping);
+            let promoted = promoted.clone();
+            async move {
+                loop {
+                    topology_refresh.requested().await;
+                    let mut slot_ranges = HashMap::new();
+                    slot_ranges.insert(promoted.clone(), vec![(0, 16383)]);
+                    mapping.write().await.update_slot_mapping(slot_ranges);
+                    topology_refresh.complete();
+                }
             }
+        });
+
+        let command = RedisCommand {
+            command: "SET".to_string(),
+            args: vec![],
+            key: None,
+            slot: Some(100),
+            readonly: false,
         };
+        let raw = b"*3\r\n$3\r\nSET\r\n$1\r\na\r\n$1\r\n1\r\n";
+
+        // Without waiting, the command fails but the refresh is requested
+        let app = RedisProtocolApp::new(
+            TransportConnector::new(None),
+            Arc::new(RwLock::new(HashMap::new())),
+            Arc::clone(&mapping),
+            3,
+        )
+        .with_failover(Arc::clone(&topology_refresh), std::time::Duration::ZERO);
+        let err = app.execute_command(&command, raw).await.unwrap_err();
+        assert!(err.to_string().starts_with(&format!("upstream {lost} failed")));
+        for _ in 0..100 {
+            if app.route_command(&command).await.unwrap().address().to_string() == promoted {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert_eq!(app.route_command(&command).await.unwrap().address().to_string(), promoted);
 
-        log::info!("New Redis client connection from: {}", client_addr);
+        // Single commands and pipelined ones are re-routed once the slots move
+        let mut slot_ranges = HashMap::new();
+        slot_ranges.insert(lost.clone(), vec![(0, 16383)]);
+        mapping.write().await.update_slot_mapping(slot_ranges);
+        let app = app.with_failover(Arc::clone(&topology_refresh), std::time::Duration::from_secs(5));
+        let reply = app.execute_command(&command, raw).await.unwrap();
+        assert_eq!(reply.as_ref(), b"+OK\r\n");
 
-        // Route to configured master node (fixed for single-node setup)
-        let nodes = self.cluster_nodes.read().await;
-        let redis_peer = match nodes.get("127.0.0.1:7001") {
-            Some(peer) => peer.clone(),
-            None => {
-                // Fallback to first available node if 127.0.0.1:7001 not found
-                match nodes.iter().next() {
-                    Some((addr, peer)) => {
-                        log::warn!("127.0.0.1:7001 not found, using fallback: {}", addr);
-                        peer.clone()
-                    },
-                    None => {
-                        log::error!("No Redis cluster nodes available");
-                        return None;
+        let mut slot_ranges = HashMap::new();
+        slot_ranges.insert(lost.clone(), vec![(0, 16383)]);
+        mapping.write().await.update_slot_mapping(slot_ranges);
+        let commands = vec![
+            (command.clone(), Bytes::from_static(raw)),
+            (command.clone(), Bytes::from_static(raw)),
+        ];
+        for reply in app.execute_pipeline(&commands).await {
+            assert_eq!(reply.unwrap().as_ref(), b"+OK\r\n");
+        }
+        refresh_task.abort();
+    }
+
+    /// Fake cluster node answering each pipelined command with
+    /// `reply(own address, command)`
+    async fn pipeline_node(
+        reply: impl Fn(&str, &RedisCommand) -> String + Send + Sync + 'static,
+    ) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let reply = Arc::new(reply);
+        let node_addr = addr.clone();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let reply = Arc::clone(&reply);
+                let node_addr = node_addr.clone();
+                tokio::spawn(async move {
+                    let mut buf = BytesMut::new();
+                    let mut read_buf = [0u8; 1024];
+                    while let Ok(n) = socket.read(&mut read_buf).await {
+                        if n == 0 {
+                            break;
+                        }
+                        buf.extend_from_slice(&read_buf[..n]);
+                        let mut out = String::new();
+                        for value in RespParser::parse_commands(&mut buf).unwrap() {
+                            let command = RedisProtocolApp::command_from_value(value).unwrap();
+                            out.push_str(&reply(&node_addr, &command));
+                        }
+                        if socket.write_all(out.as_bytes()).await.is_err() {
+                            break;
+                        }
                     }
-                }
+                });
             }
+        });
+        addr
+    }
+
+    #[tokio::test]
+    async fn test_pipelined_replies_match_their_commands() {
+        use crate::modes::redis::resp::RespEncoder;
+
+        let owner = pipeline_node(|addr, command| {
+            format!("+{}@{}\r\n", command.key.clone().unwrap_or_default(), addr)
+        })
+        .await;
+        let owner_for_stale = owner.clone();
+        // Answers for the keys it owns and redirects `moved` to the owner
+        let stale = pipeline_node(move |addr, command| match command.key.as_deref() {
+            Some("moved") => format!("-MOVED {} {}\r\n", command.slot.unwrap(), owner_for_stale),
+            key => format!("+{}@{}\r\n", key.unwrap_or_default(), addr),
+        })
+        .await;
+
+        // `b` lives on the owner, every other slot on the stale node
+        let slot_b = SlotMapping::calculate_slot("b");
+        assert_ne!(SlotMapping::calculate_slot("a"), slot_b);
+        assert_ne!(SlotMapping::calculate_slot("moved"), slot_b);
+        let mut slot_ranges = HashMap::new();
+        slot_ranges.insert(stale.clone(), vec![(0, slot_b - 1), (slot_b + 1, 16383)]);
+        slot_ranges.insert(owner.clone(), vec![(slot_b, slot_b)]);
+        let mut mapping = SlotMapping::new();
+        mapping.update_slot_mapping(slot_ranges);
+
+        let app = RedisProtocolApp::new(
+            TransportConnector::new(None),
+            Arc::new(RwLock::new(HashMap::new())),
+            Arc::new(RwLock::new(mapping)),
+            3,
+        );
+        let forward = |key: &str| {
+            let raw = RespEncoder::encode(&RespEncoder::create_command("GET", &[key]));
+            let mut buf = BytesMut::from(raw.as_ref());
+            let command =
+                RedisProtocolApp::command_from_value(RespParser::parse(&mut buf).unwrap().unwrap())
+                    .unwrap();
+            PendingCommand::Forward { command, raw }
         };
 
-        // Connect to Redis node
-        let redis_stream = match self.connector.new_stream(&redis_peer).await {
-            Ok(stream) => stream,
-            Err(e) => {
-                log::error!(
-                    "Failed to connect to Redis node {}: {}",
-                    redis_peer.address(),
-                    e
-                );
-                return None;
+        let batch = vec![
+            forward("a"),
+            PendingCommand::Answered(Bytes::from_static(b"+local\r\n")),
+            forward("moved"),
+            forward("b"),
+            forward("a"),
+            PendingCommand::Answered(Bytes::from_static(b"+last\r\n")),
+        ];
+        let replies = app
+            .execute_batch(batch, &mut Transaction::new(), "127.0.0.1:1")
+            .await;
+
+        // Only the redirected command is retried; the others keep their replies
+        assert_eq!(
+            replies,
+            vec![
+                Bytes::from(format!("+a@{stale}\r\n")),
+                Bytes::from_static(b"+local\r\n"),
+                Bytes::from(format!("+moved@{owner}\r\n")),
+                Bytes::from(format!("+b@{owner}\r\n")),
+                Bytes::from(format!("+a@{stale}\r\n")),
+                Bytes::from_static(b"+last\r\n"),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_standalone_servers_share_keys() {
+        // Each server answers with the keys it was asked for and its address
+        let reply = |addr: &str, command: &RedisCommand| {
+            let values: Vec<String> = command
+                .args
+                .iter()
+                .map(|key| format!("{}@{addr}", String::from_utf8_lossy(key)))
+                .collect();
+            let bulk: String = values.iter().map(|value| format!("${}\r\n{value}\r\n", value.len())).collect();
+            format!("*{}\r\n{bulk}", values.len())
+        };
+        let first = pipeline_node(reply).await;
+        let second = pipeline_node(reply).await;
+        let mapping = sharding::standalone_mapping(&[first.clone(), second.clone()], &HashMap::new());
+        let app = RedisProtocolApp::new(
+            TransportConnector::new(None),
+            Arc::new(RwLock::new(HashMap::new())),
+            Arc::new(RwLock::new(mapping)),
+            3,
+        );
+
+        // The first server holds slots 0-8191, the second the rest
+        let key_on = |low: bool| {
+            (0..)
+                .map(|i| format!("key{i}"))
+                .find(|key| (SlotMapping::calculate_slot(key) < 8192) == low)
+                .unwrap()
+        };
+        let (a, b) = (key_on(true), key_on(false));
+        let parts = ["MGET", &b, &a].map(|part| Bytes::from(part.to_string()));
+        let plan = SplitPlan::from_parts(&parts).unwrap();
+        assert_eq!(plan.sub_commands.len(), 2);
+
+        let merged = app.execute_split(&plan).await.unwrap();
+        let (b_value, a_value) = (format!("{b}@{second}"), format!("{a}@{first}"));
+        assert_eq!(
+            merged,
+            Bytes::from(format!(
+                "*2\r\n${}\r\n{b_value}\r\n${}\r\n{a_value}\r\n",
+                b_value.len(),
+                a_value.len()
+            ))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_transaction_runs_on_slot_owner() {
+        use crate::modes::redis::resp::RespEncoder;
+
+        let node = pipeline_node(|addr, command| match command.command.as_str() {
+            "MULTI" | "WATCH" | "UNWATCH" => "+OK\r\n".to_string(),
+            "EXEC" => "*2\r\n+OK\r\n+OK\r\n".to_string(),
+            _ if command.key.as_deref() == Some("x") => format!("+x@{addr}\r\n"),
+            _ => "+QUEUED\r\n".to_string(),
+        })
+        .await;
+        let mut slot_ranges = HashMap::new();
+        slot_ranges.insert(node.clone(), vec![(0, 16383)]);
+        let mut mapping = SlotMapping::new();
+        mapping.update_slot_mapping(slot_ranges);
+        let app = RedisProtocolApp::new(
+            TransportConnector::new(None),
+            Arc::new(RwLock::new(HashMap::new())),
+            Arc::new(RwLock::new(mapping)),
+            3,
+        );
+
+        let mut transaction = Transaction::new();
+        let mut pending = |name: &str, args: &[&str]| {
+            let raw = RespEncoder::encode(&RespEncoder::create_command(name, args));
+            let mut buf = BytesMut::from(raw.as_ref());
+            let value = RespParser::parse(&mut buf).unwrap().unwrap();
+            let plan = SplitPlan::from_value(&value);
+            let command = RedisProtocolApp::command_from_value(value).unwrap();
+            match transaction.intercept(&command, plan.as_ref(), &raw) {
+                Some(TransactionStep::Reply(reply)) => PendingCommand::Answered(reply),
+                Some(step) => PendingCommand::Transaction(step),
+                None => PendingCommand::Forward { command, raw },
             }
         };
+        let batch = vec![
+            pending("WATCH", &["{u}a"]),
+            pending("MULTI", &[]),
+            pending("SET", &["{u}a", "1"]),
+            pending("SET", &["{u}b", "2"]),
+            pending("EXEC", &[]),
+            pending("GET", &["x"]),
+            pending("MULTI", &[]),
+            pending("SET", &["a", "1"]),
+            pending("SET", &["b", "1"]),
+            pending("EXEC", &[]),
+        ];
+        let replies = app
+            .execute_batch(batch, &mut transaction, "127.0.0.1:1")
+            .await;
 
-        log::info!(
-            "Established connection to Redis node: {}",
-            redis_peer.address()
+        assert_eq!(
+            replies,
+            vec![
+                Bytes::from_static(b"+OK\r\n"),
+                Bytes::from_static(b"+OK\r\n"),
+                Bytes::from_static(b"+QUEUED\r\n"),
+                Bytes::from_static(b"+QUEUED\r\n"),
+                Bytes::from_static(b"*2\r\n+OK\r\n+OK\r\n"),
+                Bytes::from(format!("+x@{node}\r\n")),
+                Bytes::from_static(b"+OK\r\n"),
+                Bytes::from_static(b"+QUEUED\r\n"),
+                Bytes::from_static(transaction::CROSSSLOT_REPLY),
+                Bytes::from_static(transaction::EXECABORT_REPLY),
+            ]
         );
+    }
 
-        // Forward Redis RESP protocol data bidirectionally
-        self.forward_redis_data(client_stream, redis_stream).await;
+    #[tokio::test]
+    async fn test_subscriber_mode_round_trip() {
+        use crate::modes::redis::resp::RespEncoder;
+
+        const SUBSCRIBED: &str = "*3\r\n$9\r\nsubscribe\r\n$2\r\nch\r\n:1\r\n";
+        const MESSAGE: &str = "*3\r\n$7\r\nmessage\r\n$2\r\nch\r\n$5\r\nhello\r\n";
+        const UNSUBSCRIBED: &str = "*3\r\n$11\r\nunsubscribe\r\n$2\r\nch\r\n:0\r\n";
+        let node = pipeline_node(|_, command| match command.command.as_str() {
+            "SUBSCRIBE" => format!("{SUBSCRIBED}{MESSAGE}"),
+            "UNSUBSCRIBE" => UNSUBSCRIBED.to_string(),
+            _ => "-ERR unexpected\r\n".to_string(),
+        })
+        .await;
+        let app = RedisProtocolApp::new(
+            TransportConnector::new(None),
+            Arc::new(RwLock::new(HashMap::from([(
+                node.clone(),
+                upstream::new_peer(&node, None, None),
+            )]))),
+            Arc::new(RwLock::new(SlotMapping::new())),
+            3,
+        );
 
-        None
+        let encode = |name: &str, args: &[&str]| RespEncoder::encode(&RespEncoder::create_command(name, args));
+        let raw = encode("SUBSCRIBE", &["ch"]);
+        let mut buf = BytesMut::from(raw.as_ref());
+        let command =
+            RedisProtocolApp::command_from_value(RespParser::parse(&mut buf).unwrap().unwrap()).unwrap();
+        // The client unsubscribes and then pipelines a regular command
+        let mut client_buf = BytesMut::from(encode("UNSUBSCRIBE", &[]).as_ref());
+        client_buf.extend_from_slice(&encode("GET", &["x"]));
+
+        let (mut client, mut proxy_side) = tokio::io::duplex(4096);
+        let mut access = crate::logging::AccessRecord::new("redis", "127.0.0.1:1", "cluster");
+        let connection = crate::core::connections::global().register("redis", "127.0.0.1:1", "cluster");
+        let still_open = app
+            .run_subscriber(&mut proxy_side, &mut client_buf, command, &raw, &connection, &mut access)
+            .await;
+        assert!(still_open);
+        // Back in request/response mode, the GET is left for regular routing
+        assert_eq!(client_buf.as_ref(), encode("GET", &["x"]).as_ref());
+
+        drop(proxy_side);
+        let mut received = String::new();
+        client.read_to_string(&mut received).await.unwrap();
+        assert_eq!(received, format!("{SUBSCRIBED}{MESSAGE}{UNSUBSCRIBED}"));
     }
-}
 
-/// Simple CRC16 implementation for Redis slot calculation
-fn crc16(data: &[u8]) -> u16 {
-    crate::utils::crc16(data)
-}
+    #[tokio::test]
+    async fn test_evalsha_reloads_script_on_owner() {
+        use crate::modes::redis::resp::RespEncoder;
+        use std::collections::HashSet;
+        use std::sync::Mutex;
+
+        const SHA: &str = "e0e1f9fabfc9d4800c877a703b823ac0578ff8db";
+        // Node keeping its own script cache, which the test can flush
+        let script_node = || async {
+            let loaded = Arc::new(Mutex::new(HashSet::new()));
+            let scripts = Arc::clone(&loaded);
+            let addr = pipeline_node(move |_, command| match command.command.as_str() {
+                "SCRIPT" => {
+                    scripts.lock().unwrap().insert(command.args[1].clone());
+                    format!("${}\r\n{SHA}\r\n", SHA.len())
+                }
+                "EVALSHA" if !scripts.lock().unwrap().is_empty() => ":1\r\n".to_string(),
+                _ => "-NOSCRIPT No matching script. Please use EVAL.\r\n".to_string(),
+            })
+            .await;
+            (addr, loaded)
+        };
+        let (first, _) = script_node().await;
+        let (owner, owner_scripts) = script_node().await;
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use pingora_core::server::Server;
-    use std::collections::HashMap;
+        let slot = SlotMapping::calculate_slot("k");
+        let mut slot_ranges = HashMap::new();
+        slot_ranges.insert(first.clone(), vec![(0, slot - 1), (slot + 1, 16383)]);
+        slot_ranges.insert(owner.clone(), vec![(slot, slot)]);
+        let mut mapping = SlotMapping::new();
+        mapping.update_slot_mapping(slot_ranges);
+        let cluster_nodes = HashMap::from([(first.clone(), upstream::new_peer(&first, None, None))]);
+        let app = RedisProtocolApp::new(
+            TransportConnector::new(None),
+            Arc::new(RwLock::new(cluster_nodes)),
+            Arc::new(RwLock::new(mapping)),
+            3,
+        );
+        let command = |name: &str, args: &[&str]| {
+            let raw = RespEncoder::encode(&RespEncoder::create_command(name, args));
+            let mut buf = BytesMut::from(raw.as_ref());
+            let mut command =
+                RedisProtocolApp::command_from_value(RespParser::parse(&mut buf).unwrap().unwrap())
+                    .unwrap();
+            if let Some(Ok(slot)) = scripting::script_slot(&command) {
+                command.slot = slot;
+            }
+            (command, raw)
+        };
 
-    #[test]
-    fn test_slot_calculation() {
-        // Test vectors - need to verify these match actual Redis implementation
-        assert_eq!(SlotMapping::calculate_slot("123456789"), 12739);
-        assert_eq!(SlotMapping::calculate_slot("foo"), 44950 % 16384);
-        assert_eq!(SlotMapping::calculate_slot("bar"), 5061);
+        // Loaded on the keyless node only
+        let (load, raw) = command("SCRIPT", &["LOAD", "return 1"]);
+        let reply = app.execute_script(&load, &raw).await.unwrap();
+        assert_eq!(reply.as_ref(), format!("$40\r\n{SHA}\r\n").as_bytes());
+        assert!(app.scripts.is_loaded(&first, SHA));
+
+        // The owner of the keys gets the script before the first EVALSHA
+        let (evalsha, raw) = command("EVALSHA", &[SHA, "1", "{k}x"]);
+        assert_eq!(evalsha.slot, Some(slot));
+        let reply = app.execute_script(&evalsha, &raw).await.unwrap();
+        assert_eq!(reply.as_ref(), b":1\r\n");
+        assert!(app.scripts.is_loaded(&owner, SHA));
+
+        // A node that lost the script answers NOSCRIPT and gets it again
+        owner_scripts.lock().unwrap().clear();
+        let reply = app.execute_script(&evalsha, &raw).await.unwrap();
+        assert_eq!(reply.as_ref(), b":1\r\n");
+        assert_eq!(owner_scripts.lock().unwrap().len(), 1);
+    }
 
-        // Test hash tags
+    #[tokio::test]
+    async fn test_scan_walks_every_master() {
+        use crate::modes::redis::resp::RespEncoder;
+
+        // Each node holds two keys, returned over two SCAN calls
+        let scan_node = || {
+            pipeline_node(|addr, command| {
+                let (next, key) = match command.args[0].as_ref() {
+                    b"0" => ("5", "first"),
+                    _ => ("0", "second"),
+                };
+                format!("*2\r\n$1\r\n{next}\r\n*1\r\n${}\r\n{key}@{addr}\r\n", key.len() + 1 + addr.len())
+            })
+        };
+        let a = scan_node().await;
+        let b = scan_node().await;
+        let mut slot_ranges = HashMap::new();
+        slot_ranges.insert(a.clone(), vec![(0, 8191)]);
+        slot_ranges.insert(b.clone(), vec![(8192, 16383)]);
+        let mut mapping = SlotMapping::new();
+        mapping.update_slot_mapping(slot_ranges);
+        let app = RedisProtocolApp::new(
+            TransportConnector::new(None),
+            Arc::new(RwLock::new(HashMap::new())),
+            Arc::new(RwLock::new(mapping)),
+            3,
+        );
+
+        let mut masters = [a, b];
+        masters.sort();
+        let mut cursor = "0".to_string();
+        let mut keys = Vec::new();
+        for _ in 0..4 {
+            let raw = RespEncoder::encode(&RespEncoder::create_command("SCAN", &[&cursor, "COUNT", "10"]));
+            let mut buf = BytesMut::from(raw.as_ref());
+            let command =
+                RedisProtocolApp::command_from_value(RespParser::parse(&mut buf).unwrap().unwrap())
+                    .unwrap();
+            let reply = app.execute_cluster_command(&command, &raw).await.unwrap();
+            let mut buf = BytesMut::from(reply.as_ref());
+            let Some(RespValue::Array(Some(elements))) = RespParser::parse(&mut buf).unwrap() else {
+                panic!("unexpected SCAN reply");
+            };
+            let RespValue::BulkString(Some(next)) = &elements[0] else {
+                panic!("unexpected cursor");
+            };
+            cursor = String::from_utf8(next.to_vec()).unwrap();
+            if let RespValue::Array(Some(batch)) = &elements[1] {
+                for key in batch {
+                    if let RespValue::BulkString(Some(key)) = key {
+                        keys.push(String::from_utf8(key.to_vec()).unwrap());
+                    }
+                }
+            }
+        }
+
+        assert_eq!(cursor, "0");
         assert_eq!(
-            SlotMapping::calculate_slot("foo{hash_tag}bar"),
-            SlotMapping::calculate_slot("hash_tag")
+            keys,
+            vec![
+                format!("first@{}", masters[0]),
+                format!("second@{}", masters[0]),
+                format!("first@{}", masters[1]),
+                format!("second@{}", masters[1]),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_keyless_commands_fan_out_to_masters() {
+        let a = pipeline_node(|_, _| ":3\r\n".to_string()).await;
+        let b = pipeline_node(|_, _| ":4\r\n".to_string()).await;
+        let mut slot_ranges = HashMap::new();
+        slot_ranges.insert(a.clone(), vec![(0, 8191)]);
+        slot_ranges.insert(b.clone(), vec![(8192, 16383)]);
+        let mut mapping = SlotMapping::new();
+        mapping.update_slot_mapping(slot_ranges);
+        let app = RedisProtocolApp::new(
+            TransportConnector::new(None),
+            Arc::new(RwLock::new(HashMap::from([(a.clone(), upstream::new_peer(&a, None, None))]))),
+            Arc::new(RwLock::new(mapping)),
+            3,
         );
+
+        let raw = b"*1\r\n$6\r\nDBSIZE\r\n";
+        let command = RedisCommand {
+            command: "DBSIZE".to_string(),
+            args: vec![],
+            key: None,
+            slot: None,
+            readonly: false,
+        };
+        assert!(app.is_cluster_command("DBSIZE"));
+        let reply = app.execute_cluster_command(&command, raw).await.unwrap();
+        assert_eq!(reply.as_ref(), b":7\r\n");
+
+        // Disabled, the command goes to a single node like other keyless commands
+        let app = app.with_fan_out_keyless_commands(false);
+        assert!(!app.is_cluster_command("DBSIZE"));
+        let reply = app.execute_command(&command, raw).await.unwrap();
+        assert_eq!(reply.as_ref(), b":3\r\n");
+    }
+
+    #[test]
+    fn test_command_filter_refuses_commands() {
+        let app = RedisProtocolApp::new(
+            TransportConnector::new(None),
+            Arc::new(RwLock::new(HashMap::new())),
+            Arc::new(RwLock::new(SlotMapping::new())),
+            3,
+        )
+        .with_command_filter(Some(CommandFilter::new(&CommandFilterConfig {
+            allow: vec![],
+            deny: vec!["flushall".to_string()],
+        })));
+        let command = |name: &str| RedisCommand {
+            command: name.to_string(),
+            args: vec![],
+            key: None,
+            slot: None,
+            readonly: false,
+        };
+
         assert_eq!(
-            SlotMapping::calculate_slot("{user1000}.following"),
-            SlotMapping::calculate_slot("user1000")
+            app.filter_command(&command("FLUSHALL")).as_deref(),
+            Some(filter::DISABLED_REPLY)
         );
+        assert_eq!(app.filter_command(&command("GET")), None);
     }
 
     #[test]
-    fn test_crc16() {
-        // Test CRC16 calculation
-        // These values should match Redis cluster slot calculation
-        assert_eq!(crc16(b"123456789"), 12739);
-        assert_eq!(crc16(b"foo"), 44950);
+    fn test_large_replies_recorded_per_key() {
+        let large_payloads = Arc::new(LargePayloads::new("redis", 16, 8));
+        let app = RedisProtocolApp::new(
+            TransportConnector::new(None),
+            Arc::new(RwLock::new(HashMap::new())),
+            Arc::new(RwLock::new(SlotMapping::new())),
+            3,
+        )
+        .with_large_payloads(Some(Arc::clone(&large_payloads)));
+        let command = |name: &str, key: Option<&str>| RedisCommand {
+            command: name.to_string(),
+            args: vec![],
+            key: key.map(str::to_string),
+            slot: None,
+            readonly: false,
+        };
+
+        let big = format!("${}\r\n{}\r\n", 32, "v".repeat(32));
+        app.record_command(&command("GET", Some("user:1")), big.as_bytes(), "c", Instant::now());
+        app.record_command(&command("GET", Some("user:2")), b"$1\r\nv\r\n", "c", Instant::now());
+        app.record_command(&command("KEYS", None), big.as_bytes(), "c", Instant::now());
+
+        let names: Vec<String> = large_payloads
+            .top(10)
+            .into_iter()
+            .map(|offender| offender.name)
+            .collect();
+        assert_eq!(names, vec!["KEYS", "user:1"]);
     }
 
-    #[test]
-    fn test_redis_config_creation() {
-        let config = RedisConfig {
-            cluster_nodes: vec!["127.0.0.1:6379".to_string(), "127.0.0.1:6380".to_string()],
-            slot_refresh_interval_sec: 30,
-            max_redirects: 3,
-            connection_timeout_ms: 5000,
-        };
+    #[tokio::test]
+    async fn test_pipeline_failure_keeps_earlier_replies() {
+        // Answers the first command of each read and then hangs up
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut buf = [0u8; 1024];
+                if socket.read(&mut buf).await.unwrap_or(0) > 0 {
+                    let _ = socket.write_all(b"+OK\r\n").await;
+                }
+            }
+        });
 
-        assert_eq!(config.cluster_nodes.len(), 2);
-        assert_eq!(config.slot_refresh_interval_sec, 30);
-        assert_eq!(config.max_redirects, 3);
-        assert_eq!(config.connection_timeout_ms, 5000);
+        let app = RedisProtocolApp::new(
+            TransportConnector::new(None),
+            Arc::new(RwLock::new(HashMap::new())),
+            Arc::new(RwLock::new(SlotMapping::new())),
+            3,
+        );
+        let peer = upstream::new_peer(&addr, None, None);
+        let raw: &[u8] = b"*1\r\n$4\r\nPING\r\n";
+        let replies = app.send_pipeline_to_node(&peer, &[raw, raw, raw], false).await;
+        assert_eq!(replies.len(), 3);
+        assert_eq!(replies[0].as_ref().unwrap().as_ref(), b"+OK\r\n");
+        assert!(replies[1..].iter().all(|reply| reply.is_err()));
     }
 
-    #[test]
-    fn test_slot_mapping_creation() {
-        let mapping = SlotMapping::new();
-        assert!(!mapping.is_complete());
-        assert_eq!(mapping.get_backend_for_slot(0), None);
+    #[tokio::test]
+    async fn test_oversized_reply_fails_command_not_node() {
+        let node = pipeline_node(|_, _| format!("$64\r\n{}\r\n", "v".repeat(64))).await;
+        let app = RedisProtocolApp::new(
+            TransportConnector::new(None),
+            Arc::new(RwLock::new(HashMap::new())),
+            Arc::new(RwLock::new(SlotMapping::new())),
+            3,
+        )
+        .with_pool_config(PoolConfig {
+            max_reply_size: 32,
+            ..PoolConfig::default()
+        });
+        let peer = upstream::new_peer(&node, None, None);
+        let raw: &[u8] = b"*2\r\n$3\r\nGET\r\n$1\r\nk\r\n";
+
+        let error = app.send_to_node(&peer, raw).await.unwrap_err();
+        assert!(error.to_string().contains("maximum of 32 bytes"), "{error}");
+        // Readonly commands are not retried on another node for it
+        assert!(NodeLost::retryable(error.as_ref(), true).is_none());
+
+        let replies = app.send_pipeline_to_node(&peer, &[raw, raw], false).await;
+        assert!(replies.iter().all(|reply| reply
+            .as_ref()
+            .is_err_and(|e| NodeLost::retryable(e.as_ref(), true).is_none())));
     }
 
-    #[test]
-    fn test_slot_mapping_update() {
-        let mut mapping = SlotMapping::new();
+    #[tokio::test]
+    async fn test_redirect_loop_is_bounded() {
+        // A node that keeps redirecting to itself
+        let looping = fake_node(|addr| format!("-MOVED 100 {addr}\r\n")).await;
 
         let mut slot_ranges = HashMap::new();
-        slot_ranges.insert("node1".to_string(), vec![(0, 5460)]);
-        slot_ranges.insert("node2".to_string(), vec![(5461, 10922)]);
-        slot_ranges.insert("node3".to_string(), vec![(10923, 16383)]);
-
+        slot_ranges.insert(looping.clone(), vec![(0, 16383)]);
+        let mut mapping = SlotMapping::new();
         mapping.update_slot_mapping(slot_ranges);
 
-        assert!(mapping.is_complete());
-        assert_eq!(mapping.get_backend_for_slot(0), Some("node1".to_string()));
-        assert_eq!(
-            mapping.get_backend_for_slot(5461),
-            Some("node2".to_string())
-        );
-        assert_eq!(
-            mapping.get_backend_for_slot(16383),
-            Some("node3".to_string())
+        let app = RedisProtocolApp::new(
+            TransportConnector::new(None),
+            Arc::new(RwLock::new(HashMap::new())),
+            Arc::new(RwLock::new(mapping)),
+            2,
         );
+        let command = RedisCommand {
+            command: "GET".to_string(),
+            args: vec![],
+            key: None,
+            slot: Some(100),
+            readonly: true,
+        };
+        // Retries stop after max_redirects and the last redirect is returned
+        let reply = app
+            .execute_command(&command, b"*2\r\n$3\r\nGET\r\n$1\r\na\r\n")
+            .await
+            .unwrap();
+        assert_eq!(reply, Bytes::from(format!("-MOVED 100 {looping}\r\n")));
     }
 
-    #[test]
-    fn test_slot_mapping_partial_coverage() {
-        let mut mapping = SlotMapping::new();
-
+    #[tokio::test]
+    async fn test_route_readonly_command_to_replicas() {
         let mut slot_ranges = HashMap::new();
-        slot_ranges.insert("node1".to_string(), vec![(0, 1000)]);
-
+        slot_ranges.insert("127.0.0.1:7001".to_string(), vec![(0, 16383)]);
+        let mut mapping = SlotMapping::new();
         mapping.update_slot_mapping(slot_ranges);
+        let mut replicas = HashMap::new();
+        replicas.insert(
+            "127.0.0.1:7001".to_string(),
+            vec!["127.0.0.1:7004".to_string(), "127.0.0.1:7005".to_string()],
+        );
+        mapping.set_replicas(replicas);
 
-        assert!(!mapping.is_complete());
-        assert_eq!(mapping.get_backend_for_slot(0), Some("node1".to_string()));
-        assert_eq!(mapping.get_backend_for_slot(1001), None);
-    }
+        let app = RedisProtocolApp::new(
+            TransportConnector::new(None),
+            Arc::new(RwLock::new(HashMap::new())),
+            Arc::new(RwLock::new(mapping)),
+            3,
+        )
+        .with_read_from_replicas(true);
 
-    #[test]
-    fn test_redis_command_creation() {
-        let command = RedisCommand {
+        let read = RedisCommand {
             command: "GET".to_string(),
             args: vec![],
-            key: Some("mykey".to_string()),
-            slot: Some(SlotMapping::calculate_slot("mykey")),
+            key: None,
+            slot: Some(100),
             readonly: true,
         };
-
-        assert_eq!(command.command, "GET");
-        assert_eq!(command.key, Some("mykey".to_string()));
-        assert!(command.readonly);
-        assert!(command.slot.is_some());
+        let first = app.route_command(&read).await.unwrap().address().to_string();
+        let second = app.route_command(&read).await.unwrap().address().to_string();
+        assert_ne!(first, second);
+        assert!(["127.0.0.1:7004", "127.0.0.1:7005"].contains(&first.as_str()));
+
+        // Writes always go to the master
+        let write = RedisCommand {
+            command: "SET".to_string(),
+            readonly: false,
+            ..read
+        };
+        let peer = app.route_command(&write).await.unwrap();
+        assert_eq!(peer.address().to_string(), "127.0.0.1:7001");
     }
 
-    #[test]
-    fn test_redis_response_variants() {
-        let data_response = RedisResponse::Data(bytes::Bytes::from("OK"));
-        let moved_response = RedisResponse::Moved {
-            slot: 1234,
-            new_address: "127.0.0.1:6379".to_string(),
+    #[tokio::test]
+    async fn test_migrating_slot_read_from_source_with_destination_prefetched() {
+        let source = fake_node(|_| "+OK\r\n".to_string()).await;
+        let destination = fake_node(|_| "+OK\r\n".to_string()).await;
+
+        let mut slot_ranges = HashMap::new();
+        slot_ranges.insert(source.clone(), vec![(0, 16383)]);
+        let mut mapping = SlotMapping::new();
+        mapping.update_slot_mapping(slot_ranges);
+        mapping.set_replicas(HashMap::from([(source.clone(), vec!["127.0.0.1:7004".to_string()])]));
+        mapping.set_migrations(HashMap::from([(
+            100,
+            SlotMigration {
+                source: source.clone(),
+                destination: destination.clone(),
+            },
+        )]));
+
+        let app = RedisProtocolApp::new(
+            TransportConnector::new(None),
+            Arc::new(RwLock::new(HashMap::new())),
+            Arc::new(RwLock::new(mapping)),
+            3,
+        )
+        .with_read_from_replicas(true);
+        let read = RedisCommand {
+            command: "GET".to_string(),
+            args: vec![],
+            key: None,
+            slot: Some(100),
+            readonly: true,
         };
-        let ask_response = RedisResponse::Ask {
-            slot: 5678,
-            new_address: "127.0.0.1:6380".to_string(),
+        assert_eq!(app.route_command(&read).await.unwrap().address().to_string(), source);
+        let other = RedisCommand {
+            slot: Some(101),
+            ..read.clone()
         };
-        let error_response = RedisResponse::Error("ERR something went wrong".to_string());
+        assert_eq!(app.route_command(&other).await.unwrap().address().to_string(), "127.0.0.1:7004");
 
-        match data_response {
-            RedisResponse::Data(_) => {}
-            _ => panic!("Expected Data response"),
+        let reply = app.execute_command(&read, b"*2\r\n$3\r\nGET\r\n$1\r\na\r\n").await.unwrap();
+        assert_eq!(reply.as_ref(), b"+OK\r\n");
+        for _ in 0..100 {
+            if app.pool.idle_count(&destination) == 1 && app.prefetching.lock().unwrap().is_empty() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
         }
+        assert_eq!(app.pool.idle_count(&destination), 1);
+        assert!(app.prefetching.lock().unwrap().is_empty());
+    }
 
-        match moved_response {
-            RedisResponse::Moved { slot, new_address } => {
-                assert_eq!(slot, 1234);
-                assert_eq!(new_address, "127.0.0.1:6379");
+    #[tokio::test]
+    async fn test_warm_up_skips_ejected_nodes() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let live_addr = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let mut held = Vec::new();
+            while let Ok((socket, _)) = listener.accept().await {
+                held.push(socket);
             }
-            _ => panic!("Expected Moved response"),
+        });
+        let ejected_addr = "127.0.0.1:7003".to_string();
+
+        let health_manager = crate::health::HealthCheckManager::new(Box::new(
+            crate::health::redis::RedisHealthChecker::new(),
+        ))
+        .with_passive_failure_threshold(1);
+        health_manager.record_passive_failure(ejected_addr.parse().unwrap());
+
+        let pool = ConnectionPool::new(
+            TransportConnector::new(None),
+            PoolConfig {
+                min_idle: 2,
+                ..Default::default()
+            },
+        );
+        RedisClusterProxy::warm_up_nodes(
+            &pool,
+            &[
+                (live_addr.clone(), ConnectionMode::ReadWrite),
+                (ejected_addr.clone(), ConnectionMode::ReadWrite),
+            ],
+            Some(&health_manager),
+            None,
+            None,
+        )
+        .await;
+        assert_eq!(pool.idle_count(&live_addr), 2);
+        assert_eq!(pool.idle_count(&ejected_addr), 0);
+    }
+
+    #[tokio::test]
+    async fn test_failing_node_is_ejected_from_keyless_routing() {
+        // Reserve a port with nothing listening on it
+        let closed = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let dead_addr = closed.local_addr().unwrap().to_string();
+        drop(closed);
+        let live_addr = "127.0.0.1:7002".to_string();
+
+        let mut nodes = HashMap::new();
+        for addr in [&dead_addr, &live_addr] {
+            nodes.insert(addr.clone(), BasicPeer::new(addr));
         }
+        let health_manager = crate::health::HealthCheckManager::new(Box::new(
+            crate::health::redis::RedisHealthChecker::new(),
+        ))
+        .with_passive_failure_threshold(1);
+
+        let app = RedisProtocolApp::new(
+            TransportConnector::new(None),
+            Arc::new(RwLock::new(nodes)),
+            Arc::new(RwLock::new(SlotMapping::new())),
+            3,
+        )
+        .with_health_manager(Some(Arc::new(health_manager)));
 
-        match ask_response {
-            RedisResponse::Ask { slot, new_address } => {
-                assert_eq!(slot, 5678);
-                assert_eq!(new_address, "127.0.0.1:6380");
-            }
-            _ => panic!("Expected Ask response"),
+        let dead_peer = BasicPeer::new(&dead_addr);
+        assert!(app.send_to_node(&dead_peer, b"*1\r\n$4\r\nPING\r\n").await.is_err());
+        assert!(app.is_ejected(&dead_addr));
+
+        let ping = RedisCommand {
+            command: "PING".to_string(),
+            args: vec![],
+            key: None,
+            slot: None,
+            readonly: true,
+        };
+        for _ in 0..4 {
+            let peer = app.route_command(&ping).await.unwrap();
+            assert_eq!(peer.address().to_string(), live_addr);
         }
+    }
 
-        match error_response {
-            RedisResponse::Error(msg) => {
-                assert_eq!(msg, "ERR something went wrong");
+    #[tokio::test]
+    async fn test_keyless_command_retried_on_another_node() {
+        let closed = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let dead_addr = closed.local_addr().unwrap().to_string();
+        drop(closed);
+        let live_addr = fake_node(|_| "+PONG\r\n".to_string()).await;
+
+        let mut nodes = HashMap::new();
+        for addr in [&dead_addr, &live_addr] {
+            nodes.insert(addr.clone(), BasicPeer::new(addr));
+        }
+        let app = RedisProtocolApp::new(
+            TransportConnector::new(None),
+            Arc::new(RwLock::new(nodes)),
+            Arc::new(RwLock::new(SlotMapping::new())),
+            3,
+        );
+        let dead_peer = BasicPeer::new(&dead_addr);
+        let raw = b"*1\r\n$4\r\nPING\r\n".as_slice();
+
+        // Without retries the connect failure reaches the client
+        assert!(app.send_pipeline_to_node(&dead_peer, &[raw], true).await[0].is_err());
+
+        let app = app.with_retry_attempts(1);
+        let replies = app.send_pipeline_to_node(&dead_peer, &[raw], true).await;
+        assert_eq!(replies[0].as_deref().unwrap(), b"+PONG\r\n");
+
+        // Commands for a slot stay on their node
+        assert!(app.send_pipeline_to_node(&dead_peer, &[raw], false).await[0].is_err());
+    }
+
+    #[tokio::test]
+    async fn test_keyless_commands_follow_node_weights() {
+        let mut nodes = HashMap::new();
+        for addr in ["127.0.0.1:7001", "127.0.0.1:7002"] {
+            nodes.insert(addr.to_string(), BasicPeer::new(addr));
+        }
+        let mut weights = HashMap::new();
+        weights.insert("127.0.0.1:7001".to_string(), 3);
+
+        let app = RedisProtocolApp::new(
+            TransportConnector::new(None),
+            Arc::new(RwLock::new(nodes)),
+            Arc::new(RwLock::new(SlotMapping::new())),
+            3,
+        )
+        .with_node_weights(Arc::new(RwLock::new(weights)));
+
+        let ping = RedisCommand {
+            command: "PING".to_string(),
+            args: vec![],
+            key: None,
+            slot: None,
+            readonly: true,
+        };
+        let mut heavy = 0;
+        for _ in 0..8 {
+            let peer = app.route_command(&ping).await.unwrap();
+            if peer.address().to_string() == "127.0.0.1:7001" {
+                heavy += 1;
             }
-            _ => panic!("Expected Error response"),
         }
+        assert_eq!(heavy, 6);
     }
 
     #[test]
-    fn test_slot_calculation_edge_cases() {
-        // Empty string
-        let empty_slot = SlotMapping::calculate_slot("");
-        assert!(empty_slot < 16384);
-
-        // Single character
-        let single_char_slot = SlotMapping::calculate_slot("a");
-        assert!(single_char_slot < 16384);
+    fn test_find_resp_end() {
+        let complete = b"$5\r\nhello\r\n";
+        assert_eq!(RedisClusterProxy::find_resp_end(complete), Some(complete.len()));
 
-        // Very long key
-        let long_key = "a".repeat(1000);
-        let long_key_slot = SlotMapping::calculate_slot(&long_key);
-        assert!(long_key_slot < 16384);
+        let partial = b"$5\r\nhel";
+        assert_eq!(RedisClusterProxy::find_resp_end(partial), None);
 
-        // Special characters
-        let special_key = "key-with:special@characters#";
-        let special_slot = SlotMapping::calculate_slot(special_key);
-        assert!(special_slot < 16384);
+        let body = RedisClusterProxy::parse_cluster_nodes_response(complete).unwrap();
+        assert_eq!(body, "hello");
     }
 
-    #[test]
-    fn test_hash_tag_extraction() {
-        // No hash tag
-        assert_eq!(
-            SlotMapping::calculate_slot("simple_key"),
-            SlotMapping::calculate_slot("simple_key")
-        );
+    #[tokio::test]
+    async fn test_initial_discovery_falls_back_to_first_reachable_node() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let closed = listener.local_addr().unwrap().to_string();
+        drop(listener);
+        // Answers CLUSTER NODES with an error, like a node without cluster support
+        let standalone = fake_node(|_| "-ERR This instance has cluster support disabled\r\n".to_string()).await;
 
-        // Hash tag at the beginning
-        let key1 = "{tag}key";
-        let tag_only = "tag";
-        assert_eq!(
-            SlotMapping::calculate_slot(key1),
-            SlotMapping::calculate_slot(tag_only)
-        );
+        let config = RedisConfig {
+            cluster_nodes: vec![closed.clone(), standalone.clone()],
+            ..Default::default()
+        };
+        let proxy = RedisClusterProxy::new(config);
+        proxy.initialize_cluster_nodes().await.unwrap();
+        proxy
+            .discover_initial_topology(2, std::time::Duration::from_millis(10))
+            .await
+            .unwrap();
+
+        let mapping = proxy.get_slot_mapping();
+        let mapping = mapping.read().await;
+        assert!(mapping.is_complete());
+        assert_eq!(mapping.get_backend_for_slot(100), Some(standalone));
+    }
 
-        // Hash tag in the middle
-        let key2 = "prefix{tag}suffix";
-        assert_eq!(
-            SlotMapping::calculate_slot(key2),
-            SlotMapping::calculate_slot(tag_only)
-        );
+    #[tokio::test]
+    async fn test_initial_discovery_fails_without_reachable_node() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let closed = listener.local_addr().unwrap().to_string();
+        drop(listener);
 
-        // Multiple hash tags (should use first one)
-        let key3 = "prefix{tag1}middle{tag2}suffix";
+        let config = RedisConfig {
+            cluster_nodes: vec![closed.clone()],
+            ..Default::default()
+        };
+        let proxy = RedisClusterProxy::new(config);
+        proxy.initialize_cluster_nodes().await.unwrap();
+        let err = proxy
+            .discover_initial_topology(2, std::time::Duration::from_millis(10))
+            .await
+            .unwrap_err();
         assert_eq!(
-            SlotMapping::calculate_slot(key3),
-            SlotMapping::calculate_slot("tag1")
+            err.to_string(),
+            format!("No Redis cluster node reachable at startup, tried {closed}")
         );
+        assert!(!proxy.get_slot_mapping().read().await.is_complete());
+
+        // Seed nodes supplied later by backend discovery
+        let proxy = RedisClusterProxy::new(RedisConfig::default());
+        proxy.initialize_cluster_nodes().await.unwrap();
+        proxy
+            .discover_initial_topology(2, std::time::Duration::from_millis(10))
+            .await
+            .unwrap();
+    }
 
-        // Empty hash tag (should use full key)
-        let key4 = "prefix{}suffix";
-        assert_eq!(
-            SlotMapping::calculate_slot(key4),
-            SlotMapping::calculate_slot(key4)
+    #[tokio::test]
+    async fn test_explain_key() {
+        let proxy = RedisClusterProxy::new(RedisConfig::default()).with_name("cache");
+        *proxy.get_slot_mapping().write().await = SlotMapping::from_cluster_nodes(
+            "\
+07c37dfeb235213a872192d90877d0cd55635b91 127.0.0.1:7002@17002 master - 0 1426238317239 2 connected 5461-10922
+67ed2db8d677e59ec4a4cefb06858cf2a1a89fa1 127.0.0.1:7001@17001 myself,master - 0 0 1 connected 0-5460
+292f8b365bb7edb5e285caf0b7e6ddc7265d2f4f 127.0.0.1:7003@17003 master - 0 1426238316232 3 connected 10923-16383
+6ec23923021cf3ffec47632106199cb7f496ce01 127.0.0.1:7005@17005 slave 67ed2db8d677e59ec4a4cefb06858cf2a1a89fa1 0 1426238316232 5 connected
+",
         );
+        let explain = proxy.explain_source();
+        let request = |key: &str| ExplainRequest {
+            key: Some(key.to_string()),
+            client_ip: None,
+        };
 
-        // Invalid hash tag (no closing brace)
-        let key5 = "prefix{tag_suffix";
+        let explanation = explain(request("{user1000}.following")).await.unwrap();
+        assert_eq!(explanation.name, "cache");
         assert_eq!(
-            SlotMapping::calculate_slot(key5),
-            SlotMapping::calculate_slot(key5)
+            explanation.route,
+            Route::Key(KeyRoute {
+                key: "{user1000}.following".to_string(),
+                hash_tag: Some("user1000".to_string()),
+                slot: 3443,
+                node: Some("127.0.0.1:7001".to_string()),
+                replicas: vec!["127.0.0.1:7005".to_string()],
+                migration: None,
+            })
         );
+        let Route::Key(route) = explain(request("foo")).await.unwrap().route else {
+            panic!("Expected a key route");
+        };
+        assert_eq!((route.hash_tag, route.slot, route.node), (None, 12182, Some("127.0.0.1:7003".to_string())));
+
+        assert!(explain(ExplainRequest::default()).await.is_none());
     }
 
     #[tokio::test]
-    async fn test_redis_cluster_proxy_creation() {
+    async fn test_redis_cluster_proxy_update_cluster_nodes() {
         let config = RedisConfig {
-            cluster_nodes: vec!["127.0.0.1:6379".to_string()],
+            cluster_nodes: vec!["127.0.0.1:7001".to_string(), "127.0.0.1:7002".to_string()],
             slot_refresh_interval_sec: 30,
             max_redirects: 3,
             connection_timeout_ms: 5000,
+            ..Default::default()
         };
 
-        let server = Server::new(None).unwrap();
-        let proxy = RedisClusterProxy::new(config, server);
-
-        assert_eq!(proxy.get_config().cluster_nodes.len(), 1);
-        assert_eq!(proxy.get_config().max_redirects, 3);
-    }
-
-    #[tokio::test]
-    async fn test_redis_protocol_app_creation() {
-        use pingora_core::connectors::TransportConnector;
-
-        let connector = TransportConnector::new(None);
-        let cluster_nodes = Arc::new(RwLock::new(HashMap::new()));
-        let slot_mapping = Arc::new(RwLock::new(SlotMapping::new()));
-
-        let _app = RedisProtocolApp::new(connector, cluster_nodes, slot_mapping, 3);
-
-        // Just test creation succeeds
-        assert!(true);
-    }
-
-    #[test]
-    fn test_slot_mapping_comprehensive_coverage() {
-        let mut mapping = SlotMapping::new();
-
-        // Create a full mapping covering all 16384 slots
-        let mut slot_ranges = HashMap::new();
-        let slots_per_node = 16384 / 3;
-
-        slot_ranges.insert("node1".to_string(), vec![(0, slots_per_node - 1)]);
-        slot_ranges.insert(
-            "node2".to_string(),
-            vec![(slots_per_node, 2 * slots_per_node - 1)],
-        );
-        slot_ranges.insert("node3".to_string(), vec![(2 * slots_per_node, 16383)]);
-
-        mapping.update_slot_mapping(slot_ranges);
+        let proxy = RedisClusterProxy::new(config);
+        proxy.initialize_cluster_nodes().await.unwrap();
 
-        assert!(mapping.is_complete());
+        proxy
+            .update_cluster_nodes(&["127.0.0.1:7002".to_string(), "127.0.0.1:7003".to_string()])
+            .await
+            .unwrap();
 
-        // Test boundary conditions
-        assert_eq!(mapping.get_backend_for_slot(0), Some("node1".to_string()));
-        assert_eq!(
-            mapping.get_backend_for_slot(slots_per_node - 1),
-            Some("node1".to_string())
-        );
-        assert_eq!(
-            mapping.get_backend_for_slot(slots_per_node),
-            Some("node2".to_string())
-        );
-        assert_eq!(
-            mapping.get_backend_for_slot(16383),
-            Some("node3".to_string())
-        );
+        let nodes = proxy.get_cluster_nodes();
+        let nodes = nodes.read().await;
+        assert_eq!(nodes.len(), 2);
+        assert!(!nodes.contains_key("127.0.0.1:7001"));
+        assert!(nodes.contains_key("127.0.0.1:7002"));
+        assert!(nodes.contains_key("127.0.0.1:7003"));
     }
 
     #[tokio::test]
-    async fn test_redis_cluster_proxy_with_health_check() {
+    async fn test_redis_cluster_proxy_update_cluster_nodes_invalid() {
         let config = RedisConfig {
-            cluster_nodes: vec!["127.0.0.1:6379".to_string()],
+            cluster_nodes: vec!["127.0.0.1:7001".to_string()],
             slot_refresh_interval_sec: 30,
             max_redirects: 3,
             connection_timeout_ms: 5000,
+            ..Default::default()
         };
 
-        let server = Server::new(None).unwrap();
-        let proxy = RedisClusterProxy::new(config, server).with_health_check();
-        assert!(proxy.health_manager.is_some());
+        let proxy = RedisClusterProxy::new(config);
+        proxy.initialize_cluster_nodes().await.unwrap();
+
+        let result = proxy.update_cluster_nodes(&["redis-host".to_string()]).await;
+        assert!(result.is_err());
+        assert_eq!(proxy.get_cluster_nodes().read().await.len(), 1);
     }
 
     #[test]
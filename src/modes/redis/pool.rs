@@ -0,0 +1,592 @@
+/// Upstream connection pooling for Redis cluster nodes
+///
+/// Client connections check out a backend connection per command and return
+/// it once the reply has been read, so many clients share a small set of
+/// persistent connections to each node.
+use bytes::BytesMut;
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use super::resp::{self, RespParser, RespValue};
+use crate::config::RedisAuthConfig;
+
+use pingora_core::connectors::TransportConnector;
+use pingora_core::protocols::Stream;
+use pingora_core::upstreams::peer::{BasicPeer, Peer};
+
+/// Connection pool settings
+#[derive(Debug, Clone)]
+pub struct PoolConfig {
+    /// Maximum number of connections per node (idle + in use)
+    pub max_size: usize,
+    /// Idle connections older than this are closed instead of reused
+    pub idle_timeout: Duration,
+    /// Maximum time to wait for a free connection before failing
+    pub max_wait: Duration,
+    /// Idle connections kept open per node ahead of demand
+    pub min_idle: usize,
+    /// Largest reply read from a node, in bytes
+    pub max_reply_size: usize,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            max_size: 64,
+            idle_timeout: Duration::from_secs(300),
+            max_wait: Duration::from_millis(1000),
+            min_idle: 0,
+            max_reply_size: resp::DEFAULT_MAX_FRAME_SIZE,
+        }
+    }
+}
+
+/// Whether a connection has `READONLY` enabled
+///
+/// Replicas only serve reads to connections in `ReadOnly` mode. The mode
+/// of each pooled connection is tracked, so a connection is switched only
+/// when no idle one in the wanted mode is left.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConnectionMode {
+    #[default]
+    ReadWrite,
+    ReadOnly,
+}
+
+impl ConnectionMode {
+    /// The command putting a connection in this mode
+    fn command(self) -> &'static [u8] {
+        match self {
+            ConnectionMode::ReadWrite => READWRITE_COMMAND,
+            ConnectionMode::ReadOnly => READONLY_COMMAND,
+        }
+    }
+}
+
+/// An idle connection, its mode and when it was returned
+type IdleConnection = (Stream, ConnectionMode, Instant);
+
+/// Per-node pool state
+struct NodePool {
+    idle: Mutex<Vec<IdleConnection>>,
+    permits: Arc<Semaphore>,
+}
+
+/// A connection checked out of the pool
+///
+/// Return it with [`ConnectionPool::release`] once the reply has been fully
+/// read. Dropping it instead closes the connection and frees its slot.
+pub struct PooledConnection {
+    pub stream: Stream,
+    /// Read buffer for replies on this connection
+    pub buf: BytesMut,
+    /// Decoder for the replies in `buf`, enforcing `max_reply_size`
+    pub parser: RespParser,
+    node_addr: String,
+    mode: ConnectionMode,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl PooledConnection {
+    /// Address of the node this connection belongs to
+    pub fn node_addr(&self) -> &str {
+        &self.node_addr
+    }
+
+    /// Whether this connection has `READONLY` enabled
+    pub fn mode(&self) -> ConnectionMode {
+        self.mode
+    }
+}
+
+/// Pool of upstream connections keyed by node address
+pub struct ConnectionPool {
+    connector: TransportConnector,
+    config: PoolConfig,
+    auth: Option<RedisAuthConfig>,
+    nodes: Arc<Mutex<HashMap<String, Arc<NodePool>>>>,
+}
+
+/// `READONLY` enables reads from replicas on a connection; it is a no-op on masters
+const READONLY_COMMAND: &[u8] = b"*1\r\n$8\r\nREADONLY\r\n";
+
+/// `READWRITE` turns `READONLY` off again
+const READWRITE_COMMAND: &[u8] = b"*1\r\n$9\r\nREADWRITE\r\n";
+
+/// Send a connection setup command and require a `+OK`-style simple string reply
+pub(crate) async fn send_expect_ok<S>(
+    stream: &mut S,
+    command: &[u8],
+) -> Result<(), Box<dyn Error + Send + Sync>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    stream.write_all(command).await?;
+    stream.flush().await?;
+
+    let mut buf = BytesMut::with_capacity(128);
+    let mut read_buf = [0u8; 512];
+    loop {
+        if let Some(reply) = RespParser::parse(&mut buf)? {
+            return match reply {
+                RespValue::SimpleString(_) => Ok(()),
+                RespValue::Error(e) => Err(e.into()),
+                other => Err(format!("Unexpected reply: {other:?}").into()),
+            };
+        }
+
+        let n = stream.read(&mut read_buf).await?;
+        if n == 0 {
+            return Err("Connection closed during setup".into());
+        }
+        buf.extend_from_slice(&read_buf[..n]);
+    }
+}
+
+impl ConnectionPool {
+    pub fn new(connector: TransportConnector, config: PoolConfig) -> Self {
+        Self {
+            connector,
+            config,
+            auth: None,
+            nodes: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// A pool sharing this pool's connections that opens new ones through
+    /// `connector`, for use from another runtime
+    pub fn shared_with(&self, connector: TransportConnector) -> Self {
+        Self {
+            connector,
+            config: self.config.clone(),
+            auth: self.auth.clone(),
+            nodes: Arc::clone(&self.nodes),
+        }
+    }
+
+    /// Replace the pool settings, keeping the connector
+    pub fn with_config(mut self, config: PoolConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Authenticate new connections with these credentials
+    pub fn with_auth(mut self, auth: Option<RedisAuthConfig>) -> Self {
+        self.auth = auth;
+        self
+    }
+
+    /// Get the pool configuration
+    pub fn config(&self) -> &PoolConfig {
+        &self.config
+    }
+
+    fn node_pool(&self, node_addr: &str) -> Arc<NodePool> {
+        let mut nodes = self.nodes.lock().unwrap();
+        Arc::clone(nodes.entry(node_addr.to_string()).or_insert_with(|| {
+            Arc::new(NodePool {
+                idle: Mutex::new(Vec::new()),
+                permits: Arc::new(Semaphore::new(self.config.max_size)),
+            })
+        }))
+    }
+
+    /// Check out a connection to `peer`, reusing an idle one when possible
+    pub async fn acquire(
+        &self,
+        peer: &BasicPeer,
+    ) -> Result<PooledConnection, Box<dyn Error + Send + Sync>> {
+        self.acquire_in(peer, ConnectionMode::ReadWrite).await
+    }
+
+    /// Check out a connection to `peer` in `mode`
+    ///
+    /// Idle connections already in `mode` are preferred; failing that, an
+    /// idle connection in the other mode is switched before opening a new one.
+    pub async fn acquire_in(
+        &self,
+        peer: &BasicPeer,
+        mode: ConnectionMode,
+    ) -> Result<PooledConnection, Box<dyn Error + Send + Sync>> {
+        let node_addr = peer.address().to_string();
+        let node = self.node_pool(&node_addr);
+
+        let permit = tokio::time::timeout(
+            self.config.max_wait,
+            Arc::clone(&node.permits).acquire_owned(),
+        )
+        .await
+        .map_err(|_| format!("Timed out waiting for a connection to {node_addr}"))?
+        .map_err(|e| format!("Connection pool for {node_addr} closed: {e}"))?;
+
+        let reusable = {
+            let mut idle = node.idle.lock().unwrap();
+            let count = idle.len();
+            idle.retain(|(_, _, since)| since.elapsed() < self.config.idle_timeout);
+            if idle.len() < count {
+                log::debug!("Closing {} idle connections to {node_addr}", count - idle.len());
+            }
+            match idle.iter().rposition(|(_, idle_mode, _)| *idle_mode == mode) {
+                Some(position) => Some(idle.remove(position)),
+                None => idle.pop(),
+            }
+        };
+
+        let stream = match reusable {
+            Some((stream, idle_mode, _)) if idle_mode == mode => stream,
+            Some((mut stream, _, _)) => match send_expect_ok(&mut stream, mode.command()).await {
+                Ok(()) => {
+                    log::debug!("Switched a pooled connection to {node_addr} to {mode:?}");
+                    stream
+                }
+                Err(e) => {
+                    log::debug!("Failed to switch a pooled connection to {node_addr} to {mode:?}: {e}");
+                    self.connect(peer, mode).await?
+                }
+            },
+            None => {
+                let stream = self.connect(peer, mode).await?;
+                log::debug!("Opened new pooled connection to Redis node: {node_addr}");
+                stream
+            }
+        };
+
+        Ok(PooledConnection {
+            stream,
+            buf: BytesMut::new(),
+            parser: RespParser::new().with_max_frame_size(self.config.max_reply_size),
+            node_addr,
+            mode,
+            _permit: permit,
+        })
+    }
+
+    /// Open a connection to `peer` in `mode` outside the pool and its size
+    /// limit, for connections that stay with one client such as subscribers
+    pub async fn connect(
+        &self,
+        peer: &BasicPeer,
+        mode: ConnectionMode,
+    ) -> Result<Stream, Box<dyn Error + Send + Sync>> {
+        let mut stream = self.connector.new_stream(peer).await?;
+        if let Some(auth) = &self.auth {
+            super::auth::authenticate(&mut stream, auth).await?;
+        }
+        if mode == ConnectionMode::ReadOnly {
+            send_expect_ok(&mut stream, READONLY_COMMAND)
+                .await
+                .map_err(|e| format!("READONLY failed on {}: {e}", peer.address()))?;
+        }
+        Ok(stream)
+    }
+
+    /// Open connections to `peer` in `mode` until `min_idle` of them are
+    /// idle, after closing expired idle ones, and return how many were opened
+    pub async fn warm_up(
+        &self,
+        peer: &BasicPeer,
+        mode: ConnectionMode,
+    ) -> Result<usize, Box<dyn Error + Send + Sync>> {
+        let node_addr = peer.address().to_string();
+        let node = self.node_pool(&node_addr);
+        node.idle
+            .lock()
+            .unwrap()
+            .retain(|(_, _, since)| since.elapsed() < self.config.idle_timeout);
+
+        let mut opened = 0;
+        while node.idle.lock().unwrap().len() < self.config.min_idle {
+            let stream = self.connect(peer, mode).await?;
+            node.idle.lock().unwrap().push((stream, mode, Instant::now()));
+            opened += 1;
+        }
+        if opened > 0 {
+            log::debug!("Warmed up {opened} connections to Redis node: {node_addr}");
+        }
+        Ok(opened)
+    }
+
+    /// Open one idle connection to `peer` unless it already has one, and
+    /// return whether one was opened
+    pub async fn prefetch(&self, peer: &BasicPeer) -> Result<bool, Box<dyn Error + Send + Sync>> {
+        let node = self.node_pool(&peer.address().to_string());
+        if !node.idle.lock().unwrap().is_empty() {
+            return Ok(false);
+        }
+        let stream = self.connect(peer, ConnectionMode::ReadWrite).await?;
+        node.idle
+            .lock()
+            .unwrap()
+            .push((stream, ConnectionMode::ReadWrite, Instant::now()));
+        Ok(true)
+    }
+
+    /// Return a healthy connection to the pool for reuse
+    pub fn release(&self, conn: PooledConnection) {
+        // Unread bytes mean the connection is out of sync with its replies
+        if !conn.buf.is_empty() {
+            log::warn!(
+                "Discarding connection to {} with {} unread bytes",
+                conn.node_addr,
+                conn.buf.len()
+            );
+            return;
+        }
+
+        let node = self.node_pool(&conn.node_addr);
+        node.idle.lock().unwrap().push((conn.stream, conn.mode, Instant::now()));
+    }
+
+    /// Number of idle connections held for a node
+    pub fn idle_count(&self, node_addr: &str) -> usize {
+        self.nodes
+            .lock()
+            .unwrap()
+            .get(node_addr)
+            .map(|node| node.idle.lock().unwrap().len())
+            .unwrap_or(0)
+    }
+
+    /// Number of connections to a node that are currently checked out
+    pub fn active_count(&self, node_addr: &str) -> usize {
+        self.nodes
+            .lock()
+            .unwrap()
+            .get(node_addr)
+            .map(|node| self.config.max_size - node.permits.available_permits())
+            .unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    async fn local_listener() -> (TcpListener, String) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        (listener, addr)
+    }
+
+    fn accept_forever(listener: TcpListener) {
+        tokio::spawn(async move {
+            let mut held = Vec::new();
+            while let Ok((socket, _)) = listener.accept().await {
+                held.push(socket);
+            }
+        });
+    }
+
+    #[test]
+    fn test_pool_config_default() {
+        let config = PoolConfig::default();
+        assert_eq!(config.max_size, 64);
+        assert_eq!(config.idle_timeout, Duration::from_secs(300));
+        assert_eq!(config.max_wait, Duration::from_millis(1000));
+        assert_eq!(config.min_idle, 0);
+    }
+
+    #[tokio::test]
+    async fn test_pool_warm_up_keeps_min_idle() {
+        let (listener, addr) = local_listener().await;
+        accept_forever(listener);
+
+        let config = PoolConfig {
+            min_idle: 2,
+            ..Default::default()
+        };
+        let pool = ConnectionPool::new(TransportConnector::new(None), config);
+        let peer = BasicPeer::new(&addr);
+
+        // A pool sharing the connections warms them up for the other
+        let warmer = pool.shared_with(TransportConnector::new(None));
+        assert_eq!(warmer.warm_up(&peer, ConnectionMode::ReadWrite).await.unwrap(), 2);
+        assert_eq!(pool.idle_count(&addr), 2);
+
+        let conn = pool.acquire(&peer).await.unwrap();
+        assert_eq!(warmer.warm_up(&peer, ConnectionMode::ReadWrite).await.unwrap(), 1);
+        pool.release(conn);
+        assert_eq!(pool.idle_count(&addr), 3);
+        assert_eq!(warmer.warm_up(&peer, ConnectionMode::ReadWrite).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_pool_reuses_released_connection() {
+        let (listener, addr) = local_listener().await;
+        accept_forever(listener);
+
+        let pool = ConnectionPool::new(TransportConnector::new(None), PoolConfig::default());
+        let peer = BasicPeer::new(&addr);
+
+        let conn = pool.acquire(&peer).await.unwrap();
+        assert_eq!(pool.active_count(&addr), 1);
+        pool.release(conn);
+        assert_eq!(pool.idle_count(&addr), 1);
+        assert_eq!(pool.active_count(&addr), 0);
+
+        let _conn = pool.acquire(&peer).await.unwrap();
+        assert_eq!(pool.idle_count(&addr), 0);
+    }
+
+    #[tokio::test]
+    async fn test_pool_authenticates_new_connections() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let (listener, addr) = local_listener().await;
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 256];
+            let n = socket.read(&mut buf).await.unwrap();
+            socket.write_all(b"+OK\r\n").await.unwrap();
+            buf[..n].to_vec()
+        });
+
+        let pool = ConnectionPool::new(TransportConnector::new(None), PoolConfig::default())
+            .with_auth(Some(RedisAuthConfig {
+                username: None,
+                password: "secret".to_string(),
+                password_file: None,
+            }));
+        let peer = BasicPeer::new(&addr);
+
+        let conn = pool.acquire(&peer).await.unwrap();
+        assert!(conn.buf.is_empty());
+        assert_eq!(
+            server.await.unwrap(),
+            b"*2\r\n$4\r\nAUTH\r\n$6\r\nsecret\r\n".to_vec()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_pool_readonly_connections() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let (listener, addr) = local_listener().await;
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 256];
+            let n = socket.read(&mut buf).await.unwrap();
+            socket.write_all(b"+OK\r\n").await.unwrap();
+            buf[..n].to_vec()
+        });
+
+        let pool = ConnectionPool::new(TransportConnector::new(None), PoolConfig::default());
+        let conn = pool
+            .acquire_in(&BasicPeer::new(&addr), ConnectionMode::ReadOnly)
+            .await
+            .unwrap();
+        assert_eq!(conn.mode(), ConnectionMode::ReadOnly);
+        assert_eq!(server.await.unwrap(), READONLY_COMMAND.to_vec());
+    }
+
+    #[tokio::test]
+    async fn test_pool_tracks_connection_modes() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        // Record every command each connection receives, answering +OK
+        let (listener, addr) = local_listener().await;
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&received);
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let recorded = Arc::clone(&recorded);
+                tokio::spawn(async move {
+                    let mut buf = [0u8; 256];
+                    while let Ok(n) = socket.read(&mut buf).await {
+                        if n == 0 {
+                            break;
+                        }
+                        recorded.lock().unwrap().push(buf[..n].to_vec());
+                        if socket.write_all(b"+OK\r\n").await.is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+        });
+
+        let pool = ConnectionPool::new(TransportConnector::new(None), PoolConfig::default());
+        let peer = BasicPeer::new(&addr);
+
+        // A released connection keeps its mode, so READONLY is sent once
+        let conn = pool.acquire_in(&peer, ConnectionMode::ReadOnly).await.unwrap();
+        pool.release(conn);
+        let conn = pool.acquire_in(&peer, ConnectionMode::ReadOnly).await.unwrap();
+        assert_eq!(*received.lock().unwrap(), vec![READONLY_COMMAND.to_vec()]);
+
+        // With a connection in each mode idle, each mode gets its own
+        let write = pool.acquire(&peer).await.unwrap();
+        assert_eq!(write.mode(), ConnectionMode::ReadWrite);
+        pool.release(write);
+        pool.release(conn);
+        let conn = pool.acquire(&peer).await.unwrap();
+        assert_eq!(conn.mode(), ConnectionMode::ReadWrite);
+        pool.release(conn);
+        assert_eq!(received.lock().unwrap().len(), 1);
+
+        // Short of idle connections in a mode, one in the other mode is switched
+        let read = pool.acquire_in(&peer, ConnectionMode::ReadOnly).await.unwrap();
+        let write = pool.acquire_in(&peer, ConnectionMode::ReadOnly).await.unwrap();
+        assert_eq!(write.mode(), ConnectionMode::ReadOnly);
+        assert_eq!(pool.idle_count(&addr), 0);
+        pool.release(read);
+        pool.release(write);
+        let conn = pool.acquire(&peer).await.unwrap();
+        assert_eq!(conn.mode(), ConnectionMode::ReadWrite);
+        assert_eq!(
+            *received.lock().unwrap(),
+            vec![
+                READONLY_COMMAND.to_vec(),
+                READONLY_COMMAND.to_vec(),
+                READWRITE_COMMAND.to_vec(),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_pool_max_wait_when_exhausted() {
+        let (listener, addr) = local_listener().await;
+        accept_forever(listener);
+
+        let config = PoolConfig {
+            max_size: 1,
+            max_wait: Duration::from_millis(50),
+            ..Default::default()
+        };
+        let pool = ConnectionPool::new(TransportConnector::new(None), config);
+        let peer = BasicPeer::new(&addr);
+
+        let _held = pool.acquire(&peer).await.unwrap();
+        assert!(pool.acquire(&peer).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_pool_discards_expired_and_dirty_connections() {
+        let (listener, addr) = local_listener().await;
+        accept_forever(listener);
+
+        let config = PoolConfig {
+            idle_timeout: Duration::from_millis(0),
+            ..Default::default()
+        };
+        let pool = ConnectionPool::new(TransportConnector::new(None), config);
+        let peer = BasicPeer::new(&addr);
+
+        // Expired idle connection is not handed out again
+        let conn = pool.acquire(&peer).await.unwrap();
+        pool.release(conn);
+        assert_eq!(pool.idle_count(&addr), 1);
+        let mut conn = pool.acquire(&peer).await.unwrap();
+        assert_eq!(pool.idle_count(&addr), 0);
+
+        // Connection with unread data is dropped on release
+        conn.buf.extend_from_slice(b"+OK\r\n");
+        pool.release(conn);
+        assert_eq!(pool.idle_count(&addr), 0);
+    }
+}
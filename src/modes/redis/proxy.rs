@@ -1,254 +1,374 @@
-/// Redis Cluster proxy implementation
-use super::{RedisCommand, RedisResponse, SlotMapping};
-use crate::core::Backend;
+/// Routing decisions for Redis Cluster commands
+///
+/// [`ClusterRouter`] decides which node serves each command: the master
+/// owning its slot, a replica for reads when enabled, or a seed node by
+/// weighted round-robin for commands without a key. It also decides how
+/// MOVED and ASK replies are followed and keeps the slot mapping current
+/// on MOVED. With latency estimates, replicas and seed nodes are picked by
+/// latency rather than in turn. The decisions are [`RoutingDecision`]s naming nodes by
+/// address; connecting to them is left to the caller.
+use super::failover::{MovedAction, MovedStorm, TopologyRefresh};
+use super::pool::ConnectionMode;
+use super::redirect::RedirectType;
+use super::{RedisCommand, SlotMapping};
+use crate::core::latency::LatencyTracker;
 use crate::modes::RoutingDecision;
+use pingora_core::upstreams::peer::{BasicPeer, Peer};
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
-/// Redis cluster proxy handler
-pub struct RedisClusterProxy {
-    backends: Arc<RwLock<HashMap<String, Backend>>>,
+/// The state of nodes, as far as routing is concerned
+pub trait NodeHealth: Sync {
+    /// Whether `node` should be passed over when another node can serve:
+    /// it is ejected by health checking, behind an open circuit or draining
+    fn avoid(&self, node: &str) -> bool;
+}
+
+impl<F: Fn(&str) -> bool + Sync> NodeHealth for F {
+    fn avoid(&self, node: &str) -> bool {
+        self(node)
+    }
+}
+
+/// Routes Redis commands to cluster nodes
+pub struct ClusterRouter {
+    /// Seed nodes, serving commands without a key
+    cluster_nodes: Arc<RwLock<HashMap<String, BasicPeer>>>,
+    /// Weights of seed nodes for commands without a key
+    node_weights: Arc<RwLock<HashMap<String, usize>>>,
     slot_mapping: Arc<RwLock<SlotMapping>>,
     max_redirects: u8,
+    read_from_replicas: bool,
+    /// Round-robin position across replicas
+    replica_cursor: AtomicUsize,
+    /// Weighted round-robin position for commands without a key
+    keyless_cursor: AtomicUsize,
+    /// Recent MOVED replies, refreshing the topology during a resharding
+    moved_storm: MovedStorm,
+    /// Topology refreshes requested by a storm of MOVED replies
+    topology_refresh: Option<Arc<TopologyRefresh>>,
+    /// Latency estimates by node address, picking replicas and seed nodes
+    /// instead of round-robin
+    latencies: Option<Arc<LatencyTracker>>,
 }
 
-impl RedisClusterProxy {
-    pub fn new(max_redirects: u8) -> Self {
+impl ClusterRouter {
+    pub fn new(
+        cluster_nodes: Arc<RwLock<HashMap<String, BasicPeer>>>,
+        slot_mapping: Arc<RwLock<SlotMapping>>,
+        max_redirects: u8,
+    ) -> Self {
         Self {
-            backends: Arc::new(RwLock::new(HashMap::new())),
-            slot_mapping: Arc::new(RwLock::new(SlotMapping::new())),
+            cluster_nodes,
+            node_weights: Arc::new(RwLock::new(HashMap::new())),
+            slot_mapping,
             max_redirects,
+            read_from_replicas: false,
+            replica_cursor: AtomicUsize::new(0),
+            keyless_cursor: AtomicUsize::new(0),
+            moved_storm: MovedStorm::default(),
+            topology_refresh: None,
+            latencies: None,
         }
     }
 
-    /// Route a Redis command to the appropriate node
-    pub async fn route_command(&self, command: &RedisCommand) -> RoutingDecision {
-        // Handle keyless commands
-        if command.key.is_none() {
-            return self.route_keyless_command(command).await;
-        }
+    /// Spread commands without a key over the seed nodes by these weights;
+    /// unlisted nodes weigh 1
+    pub fn with_node_weights(mut self, weights: Arc<RwLock<HashMap<String, usize>>>) -> Self {
+        self.node_weights = weights;
+        self
+    }
+
+    /// Route read-only commands to the replicas of the slot's master
+    pub fn with_read_from_replicas(mut self, enabled: bool) -> Self {
+        self.read_from_replicas = enabled;
+        self
+    }
 
-        // Calculate slot for the key
-        let slot = command.slot.unwrap_or_else(|| {
-            let key = command.key.as_ref().unwrap();
-            SlotMapping::calculate_slot(key)
-        });
-
-        // Find backend for this slot
-        let slot_mapping = self.slot_mapping.read().await;
-        match slot_mapping.get_backend_for_slot(slot) {
-            Some(backend_id) => {
-                // Verify backend is healthy
-                let backends = self.backends.read().await;
-                if let Some(backend) = backends.get(&backend_id) {
-                    if backend.healthy {
-                        RoutingDecision::Route { backend_id }
-                    } else {
-                        RoutingDecision::Error {
-                            message: format!(
-                                "Backend {} for slot {} is unhealthy",
-                                backend_id, slot
-                            ),
+    /// Request a topology refresh instead of patching the slot mapping
+    /// when MOVED replies arrive for many slots
+    pub fn with_topology_refresh(mut self, topology_refresh: Arc<TopologyRefresh>) -> Self {
+        self.topology_refresh = Some(topology_refresh);
+        self
+    }
+
+    /// Pick replicas and seed nodes for commands without a key by these
+    /// latency estimates, weighted, instead of in turn
+    pub fn with_latencies(mut self, latencies: Option<Arc<LatencyTracker>>) -> Self {
+        self.latencies = latencies;
+        self
+    }
+
+    /// Redirects followed for a command before the last one is passed to
+    /// the client
+    pub fn max_redirects(&self) -> u8 {
+        self.max_redirects
+    }
+
+    /// Route a command to the node serving its slot, or to a seed node
+    /// when it has no key or its slot has no known master
+    pub async fn route_command(&self, command: &RedisCommand, health: &dyn NodeHealth) -> RoutingDecision {
+        if let Some(slot) = command.slot {
+            let slot_mapping = self.slot_mapping.read().await;
+
+            // Replicas of a migrating slot's source lack the keys already
+            // moved, which only the source redirects with ASK
+            if self.read_from_replicas && command.readonly && slot_mapping.migration_of(slot).is_none() {
+                let replicas: Vec<&String> = slot_mapping
+                    .get_replicas_for_slot(slot)
+                    .iter()
+                    .filter(|replica| !health.avoid(replica))
+                    .collect();
+                if !replicas.is_empty() {
+                    let index = match &self.latencies {
+                        Some(latencies) => {
+                            let candidates: Vec<(&str, usize)> =
+                                replicas.iter().map(|replica| (replica.as_str(), 1)).collect();
+                            latencies.select(&candidates).unwrap_or(0)
                         }
-                    }
-                } else {
-                    RoutingDecision::Error {
-                        message: format!("Backend {} for slot {} not found", backend_id, slot),
-                    }
+                        None => self.replica_cursor.fetch_add(1, Ordering::Relaxed) % replicas.len(),
+                    };
+                    return RoutingDecision::Route {
+                        backend_id: replicas[index].clone(),
+                    };
+                }
+            }
+
+            // Only the owning master can serve the slot, even while avoided
+            if let Some(node_addr) = slot_mapping.get_backend_for_slot(slot) {
+                // Nodes learned from topology discovery rather than
+                // configuration are named by IP address
+                if self.cluster_nodes.read().await.contains_key(&node_addr)
+                    || node_addr.parse::<std::net::SocketAddr>().is_ok()
+                {
+                    return RoutingDecision::Route { backend_id: node_addr };
                 }
             }
-            None => RoutingDecision::Error {
-                message: format!("No backend found for slot {}", slot),
-            },
         }
+
+        self.select_keyless_node(&[], health).await
     }
 
-    /// Route commands that don't have keys (like PING, INFO, etc.)
-    async fn route_keyless_command(&self, _command: &RedisCommand) -> RoutingDecision {
-        let backends = self.backends.read().await;
-        let healthy_backends: Vec<String> = backends
-            .values()
-            .filter(|b| b.healthy)
-            .map(|b| b.id.clone())
-            .collect();
+    /// Pick a seed node for a command without a key by weighted
+    /// round-robin or latency, never one of `excluded`
+    ///
+    /// Avoided nodes are only picked when no other node is left.
+    pub async fn select_keyless_node(&self, excluded: &[String], health: &dyn NodeHealth) -> RoutingDecision {
+        let nodes = self.cluster_nodes.read().await;
+        let weights = self.node_weights.read().await;
 
-        if healthy_backends.is_empty() {
-            RoutingDecision::Error {
-                message: "No healthy Redis nodes available".to_string(),
+        let allowed: Vec<&String> = nodes.keys().filter(|addr| !excluded.contains(addr)).collect();
+        let mut candidates: Vec<&String> = allowed.iter().copied().filter(|addr| !health.avoid(addr)).collect();
+        if candidates.is_empty() {
+            candidates = allowed;
+        }
+        candidates.sort();
+        let weight_of = |addr: &String| weights.get(addr).copied().unwrap_or(1);
+
+        if let Some(latencies) = &self.latencies {
+            // Latencies are recorded by the address connected to
+            let addrs: Vec<String> = candidates.iter().map(|addr| nodes[*addr].address().to_string()).collect();
+            let weighted: Vec<(&str, usize)> = addrs
+                .iter()
+                .zip(&candidates)
+                .map(|(addr, node)| (addr.as_str(), weight_of(node)))
+                .collect();
+            if let Some(index) = latencies.select(&weighted).filter(|&index| weighted[index].1 > 0) {
+                return RoutingDecision::Route {
+                    backend_id: candidates[index].clone(),
+                };
             }
-        } else {
-            // Route to first healthy backend
-            // TODO: Implement better selection algorithm
-            RoutingDecision::Route {
-                backend_id: healthy_backends[0].clone(),
+        }
+
+        let total_weight: usize = candidates.iter().map(|addr| weight_of(addr)).sum();
+        if total_weight > 0 {
+            let mut position = self.keyless_cursor.fetch_add(1, Ordering::Relaxed) % total_weight;
+            for addr in candidates {
+                let weight = weight_of(addr);
+                if position < weight {
+                    return RoutingDecision::Route {
+                        backend_id: addr.clone(),
+                    };
+                }
+                position -= weight;
             }
         }
+
+        RoutingDecision::Error {
+            message: "No healthy Redis nodes available".to_string(),
+        }
+    }
+
+    /// The mode of connections to `node`: `READONLY` for replicas serving
+    /// reads, so they answer reads instead of redirecting to their master
+    pub async fn connection_mode(&self, node: &str) -> ConnectionMode {
+        if self.read_from_replicas && self.slot_mapping.read().await.replica_addrs().any(|replica| replica == node) {
+            ConnectionMode::ReadOnly
+        } else {
+            ConnectionMode::ReadWrite
+        }
     }
 
-    /// Handle MOVED/ASK redirection responses
+    /// Decide how to follow a MOVED or ASK reply to `command`, after
+    /// `redirect_count` redirects already followed
+    ///
+    /// The node named by the redirect is retried until `max_redirects`
+    /// redirects; after that the redirect goes to the client.
     pub async fn handle_redirection(
         &self,
-        _original_command: &RedisCommand,
-        response: &RedisResponse,
+        command: &RedisCommand,
+        redirect: &RedirectType,
         redirect_count: u8,
     ) -> RoutingDecision {
+        let (slot, address) = match redirect {
+            RedirectType::Moved { slot, address } | RedirectType::Ask { slot, address } => (*slot, address),
+        };
         if redirect_count >= self.max_redirects {
-            return RoutingDecision::Error {
-                message: format!("Too many redirects ({})", redirect_count),
+            log::warn!(
+                "{} still redirected after {} retries, forwarding redirect to client",
+                command.command,
+                redirect_count
+            );
+            return RoutingDecision::Redirect {
+                new_address: address.clone(),
+                command: command.command.clone(),
             };
         }
 
-        match response {
-            RedisResponse::Moved { slot, new_address } => {
-                // MOVED means the slot has permanently moved
-                // Update our slot mapping
-                self.update_slot_mapping(*slot, new_address.clone()).await;
-
-                // Find backend for the new address
-                if let Some(backend_id) = self.find_backend_by_address(new_address).await {
-                    RoutingDecision::Route { backend_id }
-                } else {
-                    RoutingDecision::Error {
-                        message: format!(
-                            "Backend not found for redirected address: {}",
-                            new_address
-                        ),
-                    }
-                }
+        match redirect {
+            RedirectType::Moved { .. } => {
+                log::warn!("MOVED redirection detected for slot {} to {}", slot, address);
             }
-            RedisResponse::Ask {
-                slot: _,
-                new_address,
-            } => {
-                // ASK is temporary - don't update slot mapping
-                if let Some(backend_id) = self.find_backend_by_address(new_address).await {
-                    RoutingDecision::Route { backend_id }
+            RedirectType::Ask { .. } => {
+                let expected = self
+                    .slot_mapping
+                    .read()
+                    .await
+                    .migration_of(slot)
+                    .is_some_and(|migration| &migration.destination == address);
+                if expected {
+                    log::debug!("ASK redirection for migrating slot {} to {}", slot, address);
                 } else {
-                    RoutingDecision::Error {
-                        message: format!("Backend not found for ASK address: {}", new_address),
-                    }
+                    log::warn!("ASK redirection detected for slot {} to {}", slot, address);
                 }
             }
-            _ => RoutingDecision::Error {
-                message: "Invalid redirection response".to_string(),
-            },
         }
-    }
-
-    /// Update slot mapping for a MOVED response
-    async fn update_slot_mapping(&self, slot: u16, new_address: String) {
-        // Find the backend ID for this address
-        if let Some(backend_id) = self.find_backend_by_address(&new_address).await {
-            let _slot_mapping = self.slot_mapping.write().await;
-            // Update the slot mapping
-            // TODO: Implement proper slot mapping update
-            tracing::info!(
-                "Slot {} moved to backend {} ({})",
-                slot,
-                backend_id,
-                new_address
-            );
+        RoutingDecision::Route {
+            backend_id: address.clone(),
         }
     }
 
-    /// Find backend by address
-    async fn find_backend_by_address(&self, address: &str) -> Option<String> {
-        let backends = self.backends.read().await;
-        for (backend_id, backend) in backends.iter() {
-            if backend.addr.to_string() == address {
-                return Some(backend_id.clone());
+    /// Record that `slot` moved to `new_address`, returning whether the
+    /// slot mapping was patched
+    ///
+    /// During a storm of MOVED replies the topology is refreshed as a
+    /// whole instead.
+    pub async fn record_moved(&self, slot: u16, new_address: &str) -> bool {
+        if let Some(topology_refresh) = &self.topology_refresh {
+            match self.moved_storm.record(slot) {
+                MovedAction::Patch => {}
+                MovedAction::Refresh => {
+                    log::warn!("MOVED replies for many slots, refreshing the cluster topology for a resharding");
+                    topology_refresh.request();
+                    return false;
+                }
+                MovedAction::Damped => return false,
             }
         }
-        None
-    }
 
-    /// Add a backend to the proxy
-    pub async fn add_backend(&self, backend: Backend) {
-        let mut backends = self.backends.write().await;
-        backends.insert(backend.id.clone(), backend);
+        log::info!("Updating slot mapping: slot {} moved to {}", slot, new_address);
+        // Move only this slot; the others keep their owners
+        let mut slot_mapping = self.slot_mapping.write().await;
+        slot_mapping.assign_slots(new_address, slot, slot);
+        slot_mapping.finish_migration(slot);
+        true
     }
+}
 
-    /// Remove a backend from the proxy
-    pub async fn remove_backend(&self, backend_id: &str) -> Option<Backend> {
-        let mut backends = self.backends.write().await;
-        backends.remove(backend_id)
-    }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::upstream;
 
-    /// Update the cluster topology from CLUSTER NODES response
-    pub async fn update_cluster_topology(&self, cluster_nodes_response: &str) {
-        // Parse CLUSTER NODES response and update slot mapping
-        // TODO: Implement cluster nodes parsing
-        tracing::debug!("Updating cluster topology: {}", cluster_nodes_response);
+    fn router(nodes: &[&str]) -> ClusterRouter {
+        let cluster_nodes = nodes
+            .iter()
+            .map(|node| (node.to_string(), upstream::new_peer(node, None, None)))
+            .collect();
+        ClusterRouter::new(
+            Arc::new(RwLock::new(cluster_nodes)),
+            Arc::new(RwLock::new(SlotMapping::new())),
+            3,
+        )
     }
 
-    /// Get backend pool reference
-    pub fn get_backends(&self) -> Arc<RwLock<HashMap<String, Backend>>> {
-        Arc::clone(&self.backends)
+    fn command(name: &str, slot: Option<u16>, readonly: bool) -> RedisCommand {
+        RedisCommand {
+            command: name.to_string(),
+            args: vec![],
+            key: None,
+            slot,
+            readonly,
+        }
     }
 
-    /// Get slot mapping reference
-    pub fn get_slot_mapping(&self) -> Arc<RwLock<SlotMapping>> {
-        Arc::clone(&self.slot_mapping)
+    fn routed_to(decision: RoutingDecision) -> String {
+        match decision {
+            RoutingDecision::Route { backend_id } => backend_id,
+            other => panic!("Expected route decision, got {other:?}"),
+        }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::core::Backend;
-    use std::net::SocketAddr;
+    const HEALTHY: &dyn NodeHealth = &|_: &str| false;
 
     #[tokio::test]
     async fn test_keyless_command_routing() {
-        let proxy = RedisClusterProxy::new(3);
+        let router = router(&["127.0.0.1:6379", "127.0.0.1:6380"]);
+        let ping = command("PING", None, true);
 
-        // Add a healthy backend
-        let backend = Backend::new_redis(
-            "redis-1".to_string(),
-            "127.0.0.1:6379".parse::<SocketAddr>().unwrap(),
-            "node-1".to_string(),
-        );
-        proxy.add_backend(backend).await;
+        // Avoided nodes are passed over while another one is left
+        let avoid_6380 = |node: &str| node == "127.0.0.1:6380";
+        for _ in 0..4 {
+            let decision = router.route_command(&ping, &avoid_6380).await;
+            assert_eq!(routed_to(decision), "127.0.0.1:6379");
+        }
+        let decision = router.select_keyless_node(&["127.0.0.1:6379".to_string()], &avoid_6380).await;
+        assert_eq!(routed_to(decision), "127.0.0.1:6380");
+    }
 
-        // Mark backend as healthy
+    #[tokio::test]
+    async fn test_latency_aware_routing() {
+        let latencies = Arc::new(LatencyTracker::default());
+        let router = router(&["127.0.0.1:6379", "127.0.0.1:6380"])
+            .with_read_from_replicas(true)
+            .with_latencies(Some(Arc::clone(&latencies)));
         {
-            let mut backends = proxy.backends.write().await;
-            if let Some(backend) = backends.get_mut("redis-1") {
-                backend.healthy = true;
-            }
+            let mut slot_mapping = router.slot_mapping.write().await;
+            slot_mapping.assign_slots("127.0.0.1:7001", 0, 16383);
+            slot_mapping.set_replicas(HashMap::from([(
+                "127.0.0.1:7001".to_string(),
+                vec!["127.0.0.1:7004".to_string(), "127.0.0.1:7005".to_string()],
+            )]));
+        }
+        for (node, millis) in [("127.0.0.1:6379", 20), ("127.0.0.1:6380", 1), ("127.0.0.1:7004", 1), ("127.0.0.1:7005", 20)] {
+            latencies.record(node, std::time::Duration::from_millis(millis));
         }
 
-        let command = RedisCommand {
-            command: "PING".to_string(),
-            args: vec![],
-            key: None,
-            slot: None,
-            readonly: true,
-        };
-
-        let result = proxy.route_command(&command).await;
-        match result {
-            RoutingDecision::Route { backend_id } => {
-                assert_eq!(backend_id, "redis-1");
-            }
-            _ => panic!("Expected route decision"),
+        for _ in 0..10 {
+            let decision = router.route_command(&command("PING", None, true), HEALTHY).await;
+            assert_eq!(routed_to(decision), "127.0.0.1:6380");
+            let decision = router.route_command(&command("GET", Some(100), true), HEALTHY).await;
+            assert_eq!(routed_to(decision), "127.0.0.1:7004");
         }
     }
 
     #[tokio::test]
     async fn test_no_healthy_backends() {
-        let proxy = RedisClusterProxy::new(3);
-
-        let command = RedisCommand {
-            command: "PING".to_string(),
-            args: vec![],
-            key: None,
-            slot: None,
-            readonly: true,
-        };
+        let router = router(&[]);
 
-        let result = proxy.route_command(&command).await;
+        let result = router.route_command(&command("PING", None, true), HEALTHY).await;
         match result {
             RoutingDecision::Error { message } => {
                 assert!(message.contains("No healthy Redis nodes"));
@@ -256,4 +376,61 @@ mod tests {
             _ => panic!("Expected error decision"),
         }
     }
+
+    #[tokio::test]
+    async fn test_slot_routing() {
+        let router = router(&["127.0.0.1:7001"]).with_read_from_replicas(true);
+        {
+            let mut slot_mapping = router.slot_mapping.write().await;
+            slot_mapping.assign_slots("127.0.0.1:7001", 0, 8191);
+            slot_mapping.assign_slots("127.0.0.1:7002", 8192, 16383);
+            slot_mapping.set_replicas(HashMap::from([(
+                "127.0.0.1:7002".to_string(),
+                vec!["127.0.0.1:7005".to_string()],
+            )]));
+        }
+
+        let decision = router.route_command(&command("SET", Some(100), false), HEALTHY).await;
+        assert_eq!(routed_to(decision), "127.0.0.1:7001");
+        let decision = router.route_command(&command("GET", Some(9000), true), HEALTHY).await;
+        assert_eq!(routed_to(decision), "127.0.0.1:7005");
+        // An avoided replica leaves reads to the master, which serves the
+        // slot even when avoided itself
+        let decision = router.route_command(&command("GET", Some(9000), true), &|_: &str| true).await;
+        assert_eq!(routed_to(decision), "127.0.0.1:7002");
+
+        assert_eq!(router.connection_mode("127.0.0.1:7005").await, ConnectionMode::ReadOnly);
+        assert_eq!(router.connection_mode("127.0.0.1:7002").await, ConnectionMode::ReadWrite);
+        let router = router.with_read_from_replicas(false);
+        assert_eq!(router.connection_mode("127.0.0.1:7005").await, ConnectionMode::ReadWrite);
+    }
+
+    #[tokio::test]
+    async fn test_redirection_decisions() {
+        let router = router(&["127.0.0.1:7001"]);
+        let get = command("GET", Some(100), true);
+        let moved = RedirectType::Moved {
+            slot: 100,
+            address: "127.0.0.1:7002".to_string(),
+        };
+        let ask = RedirectType::Ask {
+            slot: 100,
+            address: "127.0.0.1:7003".to_string(),
+        };
+
+        assert_eq!(routed_to(router.handle_redirection(&get, &moved, 0).await), "127.0.0.1:7002");
+        assert_eq!(routed_to(router.handle_redirection(&get, &ask, 2).await), "127.0.0.1:7003");
+        match router.handle_redirection(&get, &ask, 3).await {
+            RoutingDecision::Redirect { new_address, command } => {
+                assert_eq!(new_address, "127.0.0.1:7003");
+                assert_eq!(command, "GET");
+            }
+            other => panic!("Expected redirect decision, got {other:?}"),
+        }
+
+        // MOVED patches the one slot
+        assert!(router.record_moved(100, "127.0.0.1:7002").await);
+        let decision = router.route_command(&get, HEALTHY).await;
+        assert_eq!(routed_to(decision), "127.0.0.1:7002");
+    }
 }
@@ -0,0 +1,191 @@
+/// Redis Pub/Sub subscriber mode
+///
+/// Once a client subscribes, the node pushes messages at any time and replies
+/// no longer pair up with commands, so the connection cannot share pooled
+/// connections or per-command routing. The proxy instead passes traffic
+/// through a dedicated upstream connection until the client's last
+/// subscription is gone, tracking subscriptions from the node's
+/// confirmations.
+use super::resp::RespValue;
+use super::RedisCommand;
+use bytes::Bytes;
+use std::collections::HashSet;
+
+/// Whether `command` switches a connection into subscriber mode
+pub fn is_subscribe(command: &str) -> bool {
+    matches!(command, "SUBSCRIBE" | "PSUBSCRIBE" | "SSUBSCRIBE")
+}
+
+/// Subscriptions of a client in subscriber mode
+#[derive(Debug, Default)]
+pub struct Subscriptions {
+    channels: HashSet<Bytes>,
+    patterns: HashSet<Bytes>,
+    shard_channels: HashSet<Bytes>,
+    /// Replies the node still owes the last forwarded command
+    pending: usize,
+}
+
+impl Subscriptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Expect the replies the node sends for `command`
+    ///
+    /// Subscribe and unsubscribe commands are answered once per channel, and
+    /// an unsubscribe without channels once per current subscription.
+    pub fn expect(&mut self, command: &RedisCommand) {
+        let current = match command.command.as_str() {
+            "UNSUBSCRIBE" => self.channels.len(),
+            "PUNSUBSCRIBE" => self.patterns.len(),
+            "SUNSUBSCRIBE" => self.shard_channels.len(),
+            _ => 0,
+        };
+        self.pending = match command.command.as_str() {
+            "SUBSCRIBE" | "PSUBSCRIBE" | "SSUBSCRIBE" => command.args.len(),
+            "UNSUBSCRIBE" | "PUNSUBSCRIBE" | "SUNSUBSCRIBE" if command.args.is_empty() => current,
+            "UNSUBSCRIBE" | "PUNSUBSCRIBE" | "SUNSUBSCRIBE" => command.args.len(),
+            _ => 1,
+        }
+        .max(1);
+    }
+
+    /// Whether the last forwarded command is not fully answered yet
+    pub fn awaiting_replies(&self) -> bool {
+        self.pending > 0
+    }
+
+    /// Whether the client has no subscriptions left
+    pub fn is_empty(&self) -> bool {
+        self.channels.is_empty() && self.patterns.is_empty() && self.shard_channels.is_empty()
+    }
+
+    /// Track a reply or message sent by the node
+    pub fn observe(&mut self, reply: &RespValue) {
+        match reply {
+            RespValue::Array(Some(elements)) => {
+                let kind = match elements.first() {
+                    Some(RespValue::BulkString(Some(kind))) => kind.to_ascii_lowercase(),
+                    _ => Vec::new(),
+                };
+                let channel = match elements.get(1) {
+                    Some(RespValue::BulkString(Some(channel))) => Some(channel.clone()),
+                    _ => None,
+                };
+                match (kind.as_slice(), channel) {
+                    (b"message" | b"pmessage" | b"smessage", _) => return,
+                    (b"subscribe", Some(channel)) => {
+                        self.channels.insert(channel);
+                    }
+                    (b"psubscribe", Some(pattern)) => {
+                        self.patterns.insert(pattern);
+                    }
+                    (b"ssubscribe", Some(channel)) => {
+                        self.shard_channels.insert(channel);
+                    }
+                    (b"unsubscribe", Some(channel)) => {
+                        self.channels.remove(&channel);
+                    }
+                    (b"punsubscribe", Some(pattern)) => {
+                        self.patterns.remove(&pattern);
+                    }
+                    (b"sunsubscribe", Some(channel)) => {
+                        self.shard_channels.remove(&channel);
+                    }
+                    _ => {}
+                }
+            }
+            RespValue::SimpleString(reply) if reply.eq_ignore_ascii_case("RESET") => {
+                self.channels.clear();
+                self.patterns.clear();
+                self.shard_channels.clear();
+            }
+            // A rejected command is answered with a single error
+            RespValue::Error(_) => {
+                self.pending = 0;
+                return;
+            }
+            _ => {}
+        }
+        self.pending = self.pending.saturating_sub(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modes::redis::test_support::command;
+
+    fn push(kind: &str, channel: Option<&str>, count: i64) -> RespValue {
+        RespValue::Array(Some(vec![
+            RespValue::BulkString(Some(Bytes::from(kind.to_string()))),
+            RespValue::BulkString(channel.map(|channel| Bytes::from(channel.to_string()))),
+            RespValue::Integer(count),
+        ]))
+    }
+
+    #[test]
+    fn test_tracks_subscriptions() {
+        assert!(is_subscribe("SSUBSCRIBE"));
+        assert!(!is_subscribe("PUBLISH"));
+
+        let mut subscriptions = Subscriptions::new();
+        subscriptions.expect(&command("SUBSCRIBE", &["a", "b"]));
+        subscriptions.observe(&push("subscribe", Some("a"), 1));
+        assert!(subscriptions.awaiting_replies());
+        // Messages are not replies
+        subscriptions.observe(&RespValue::Array(Some(vec![
+            RespValue::BulkString(Some(Bytes::from_static(b"message"))),
+            RespValue::BulkString(Some(Bytes::from_static(b"a"))),
+            RespValue::BulkString(Some(Bytes::from_static(b"hello"))),
+        ])));
+        assert!(subscriptions.awaiting_replies());
+        subscriptions.observe(&push("subscribe", Some("b"), 2));
+        assert!(!subscriptions.awaiting_replies());
+
+        subscriptions.expect(&command("PSUBSCRIBE", &["news.*"]));
+        subscriptions.observe(&push("psubscribe", Some("news.*"), 3));
+
+        // Without channels, one reply per channel subscription
+        subscriptions.expect(&command("UNSUBSCRIBE", &[]));
+        subscriptions.observe(&push("unsubscribe", Some("a"), 2));
+        assert!(subscriptions.awaiting_replies());
+        subscriptions.observe(&push("unsubscribe", Some("b"), 1));
+        assert!(!subscriptions.awaiting_replies());
+        assert!(!subscriptions.is_empty());
+
+        subscriptions.expect(&command("PING", &[]));
+        subscriptions.observe(&RespValue::Array(Some(vec![
+            RespValue::BulkString(Some(Bytes::from_static(b"pong"))),
+            RespValue::BulkString(Some(Bytes::new())),
+        ])));
+        assert!(!subscriptions.awaiting_replies());
+
+        subscriptions.expect(&command("PUNSUBSCRIBE", &[]));
+        subscriptions.observe(&push("punsubscribe", Some("news.*"), 0));
+        assert!(subscriptions.is_empty());
+    }
+
+    #[test]
+    fn test_errors_and_reset() {
+        let mut subscriptions = Subscriptions::new();
+        subscriptions.expect(&command("SSUBSCRIBE", &["a", "b"]));
+        subscriptions.observe(&RespValue::Error("CROSSSLOT".to_string()));
+        assert!(!subscriptions.awaiting_replies());
+        assert!(subscriptions.is_empty());
+
+        subscriptions.expect(&command("SSUBSCRIBE", &["{a}1", "{a}2"]));
+        subscriptions.observe(&push("ssubscribe", Some("{a}1"), 1));
+        subscriptions.observe(&push("ssubscribe", Some("{a}2"), 2));
+        subscriptions.expect(&command("RESET", &[]));
+        subscriptions.observe(&RespValue::SimpleString("RESET".to_string()));
+        assert!(!subscriptions.awaiting_replies());
+        assert!(subscriptions.is_empty());
+
+        // Unsubscribing with no subscriptions still gets one reply
+        subscriptions.expect(&command("UNSUBSCRIBE", &[]));
+        subscriptions.observe(&push("unsubscribe", None, 0));
+        assert!(!subscriptions.awaiting_replies());
+    }
+}
@@ -1,6 +1,7 @@
 /// Redis RESP (Redis Serialization Protocol) parsing and generation
-use bytes::{Buf, BufMut, Bytes, BytesMut};
+use bytes::{BufMut, Bytes, BytesMut};
 use std::str;
+use tokio_util::codec::Decoder;
 
 /// RESP data types
 #[derive(Debug, Clone, PartialEq)]
@@ -17,8 +18,29 @@ pub enum RespValue {
     Array(Option<Vec<RespValue>>), // None represents NULL array
 }
 
-/// RESP parser for reading Redis protocol messages
-pub struct RespParser;
+/// Incremental RESP decoder
+///
+/// The decoder remembers how far it has scanned the value at the front of
+/// the buffer and which of its arrays are still open, so each read only
+/// scans the bytes that arrived since. Nothing is consumed until a whole
+/// value is buffered. Keep one decoder per buffer, e.g. per client
+/// connection, and only take bytes off the buffer through it;
+/// `RespParser::parse` decodes a single value with a fresh decoder.
+#[derive(Debug, Default)]
+pub struct RespParser {
+    /// Bytes of the value at the front of the buffer scanned so far
+    scanned: usize,
+    /// Elements still expected by each open array, innermost last
+    open_arrays: Vec<u64>,
+    /// Largest value accepted, in encoded bytes
+    max_frame_size: Option<usize>,
+}
+
+/// Deepest array nesting accepted before a value is rejected
+pub const MAX_NESTING_DEPTH: usize = 128;
+
+/// Default limit on one command or reply, Redis's own limit on a bulk string
+pub const DEFAULT_MAX_FRAME_SIZE: usize = 512 * 1024 * 1024;
 
 /// RESP encoder for writing Redis protocol messages
 pub struct RespEncoder;
@@ -34,168 +56,266 @@ pub enum RespParseError {
     InvalidUtf8(#[from] str::Utf8Error),
     #[error("Invalid integer: {0}")]
     InvalidInteger(#[from] std::num::ParseIntError),
+    #[error("Value of at least {size} bytes exceeds the maximum of {max} bytes")]
+    FrameTooLarge { size: usize, max: usize },
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
 }
 
 impl RespParser {
-    /// Parse a RESP value from bytes
-    pub fn parse(buf: &mut BytesMut) -> Result<Option<RespValue>, RespParseError> {
-        if buf.is_empty() {
-            return Ok(None);
-        }
+    pub fn new() -> Self {
+        Self::default()
+    }
 
-        let start = buf.as_ref();
-        let first_byte = start[0];
+    /// Reject values longer than `max` bytes as soon as their length is
+    /// known, instead of buffering them
+    pub fn with_max_frame_size(mut self, max: usize) -> Self {
+        self.max_frame_size = Some(max);
+        self
+    }
 
-        match first_byte {
-            b'+' => Self::parse_simple_string(buf),
-            b'-' => Self::parse_error(buf),
-            b':' => Self::parse_integer(buf),
-            b'$' => Self::parse_bulk_string(buf),
-            b'*' => Self::parse_array(buf),
-            _ => Err(RespParseError::InvalidFormat(format!(
-                "Unknown RESP type: {}",
-                first_byte as char
-            ))),
-        }
+    /// Parse a RESP value from bytes
+    ///
+    /// Nothing is consumed until a whole value is buffered, so `Ok(None)`
+    /// leaves `buf` as it was for the next read to extend.
+    pub fn parse(buf: &mut BytesMut) -> Result<Option<RespValue>, RespParseError> {
+        Self::new().decode(buf)
     }
 
     /// Parse multiple commands from a buffer
     pub fn parse_commands(buf: &mut BytesMut) -> Result<Vec<RespValue>, RespParseError> {
+        let mut parser = Self::new();
         let mut commands = Vec::new();
-
-        while !buf.is_empty() {
-            match Self::parse(buf)? {
-                Some(command) => commands.push(command),
-                None => break, // Incomplete data
-            }
+        while let Some(command) = parser.decode(buf)? {
+            commands.push(command);
         }
-
         Ok(commands)
     }
 
-    fn parse_simple_string(buf: &mut BytesMut) -> Result<Option<RespValue>, RespParseError> {
-        if let Some(line) = Self::read_line(buf)? {
-            let content = str::from_utf8(&line[1..])?.to_string(); // Skip '+'
-            Ok(Some(RespValue::SimpleString(content)))
-        } else {
-            Ok(None)
+    /// Split the next complete value off `buf`
+    ///
+    /// Returns the parsed value together with its raw bytes so the original
+    /// encoding can be forwarded untouched.
+    pub fn decode_frame(
+        &mut self,
+        buf: &mut BytesMut,
+    ) -> Result<Option<(RespValue, Bytes)>, RespParseError> {
+        match self.split_frame(buf)? {
+            Some(raw) => Ok(Some((Self::parse_frame(&raw)?, raw))),
+            None => Ok(None),
         }
     }
 
-    fn parse_error(buf: &mut BytesMut) -> Result<Option<RespValue>, RespParseError> {
-        if let Some(line) = Self::read_line(buf)? {
-            let content = str::from_utf8(&line[1..])?.to_string(); // Skip '-'
-            Ok(Some(RespValue::Error(content)))
-        } else {
-            Ok(None)
-        }
+    /// Split the raw bytes of the next complete value off `buf` without
+    /// parsing it
+    ///
+    /// Only the framing is checked; `parse_frame` or `command_parts` look
+    /// inside the frame when needed.
+    pub fn split_frame(&mut self, buf: &mut BytesMut) -> Result<Option<Bytes>, RespParseError> {
+        let len = match self.scan(buf) {
+            Ok(Some(len)) => len,
+            Ok(None) => return Ok(None),
+            Err(e) => {
+                self.scanned = 0;
+                self.open_arrays.clear();
+                return Err(e);
+            }
+        };
+        self.scanned = 0;
+        Ok(Some(buf.split_to(len).freeze()))
     }
 
-    fn parse_integer(buf: &mut BytesMut) -> Result<Option<RespValue>, RespParseError> {
-        if let Some(line) = Self::read_line(buf)? {
-            let content = str::from_utf8(&line[1..])?; // Skip ':'
-            let value: i64 = content.parse()?;
-            Ok(Some(RespValue::Integer(value)))
-        } else {
-            Ok(None)
-        }
+    /// Parse a frame returned by `split_frame`
+    pub fn parse_frame(frame: &Bytes) -> Result<RespValue, RespParseError> {
+        Self::value_at(frame, &mut 0)
     }
 
-    fn parse_bulk_string(buf: &mut BytesMut) -> Result<Option<RespValue>, RespParseError> {
-        if let Some(size_line) = Self::read_line(buf)? {
-            let size_str = str::from_utf8(&size_line[1..])?; // Skip '$'
-            let size: i64 = size_str.parse()?;
-
-            if size == -1 {
-                // NULL bulk string
-                return Ok(Some(RespValue::BulkString(None)));
+    /// Elements of a frame holding an array of bulk strings, the form
+    /// clients send commands in, as slices of the frame
+    ///
+    /// Returns `None` for any other value, which needs `parse_frame`.
+    pub fn command_parts(frame: &Bytes) -> Option<Vec<Bytes>> {
+        let header_len = find_crlf(frame)?;
+        if frame[0] != b'*' {
+            return None;
+        }
+        let count: usize = str::from_utf8(&frame[1..header_len]).ok()?.parse().ok()?;
+
+        let mut parts = Vec::with_capacity(count.min(frame.len() / 4));
+        let mut pos = header_len + 2;
+        for _ in 0..count {
+            let line_len = find_crlf(frame.get(pos..)?)?;
+            if frame[pos] != b'$' {
+                return None;
             }
-
-            if size < 0 {
-                return Err(RespParseError::InvalidFormat(
-                    "Invalid bulk string size".to_string(),
-                ));
+            let size: usize = str::from_utf8(&frame[pos + 1..pos + line_len])
+                .ok()?
+                .parse()
+                .ok()?;
+            let start = pos + line_len + 2;
+            let end = start.checked_add(size)?;
+            if frame.get(end..end.checked_add(2)?)? != b"\r\n" {
+                return None;
             }
+            parts.push(frame.slice(start..end));
+            pos = end + 2;
+        }
+        (pos == frame.len()).then_some(parts)
+    }
 
-            let size = size as usize;
-
-            // Check if we have enough data for the string + \r\n
-            if buf.len() < size + 2 {
-                // Not enough data, put back the size line
-                let mut restored = BytesMut::new();
-                restored.put_u8(b'$');
-                restored.extend_from_slice(&size_line[1..]);
-                restored.put_slice(b"\r\n");
-                restored.extend_from_slice(buf);
-                *buf = restored;
+    /// Scan the bytes of the value at the front of `buf` that arrived since
+    /// the last call, returning its length once it is complete
+    fn scan(&mut self, buf: &[u8]) -> Result<Option<usize>, RespParseError> {
+        while self.scanned < buf.len() {
+            let start = self.scanned;
+            let first_byte = buf[start];
+            if !matches!(first_byte, b'+' | b'-' | b':' | b'$' | b'*') {
+                return Err(RespParseError::InvalidFormat(format!(
+                    "Unknown RESP type: {}",
+                    first_byte as char
+                )));
+            }
+            let Some(line_len) = find_crlf(&buf[start..]) else {
+                // The line, and so the value, is longer than what is buffered
+                self.check_size(buf.len().saturating_add(1))?;
                 return Ok(None);
+            };
+            let line = &buf[start + 1..start + line_len];
+            let mut end = start + line_len + 2;
+            self.check_size(end)?;
+
+            match first_byte {
+                b'$' => {
+                    let size: i64 = str::from_utf8(line)?.parse()?;
+                    // Negative sizes are NULL or rejected when parsed
+                    if let Ok(size) = usize::try_from(size) {
+                        let bulk_end = end.saturating_add(size).saturating_add(2);
+                        self.check_size(bulk_end)?;
+                        if bulk_end > buf.len() {
+                            return Ok(None);
+                        }
+                        end = bulk_end;
+                    }
+                }
+                b'*' => {
+                    let size: i64 = str::from_utf8(line)?.parse()?;
+                    if size > 0 {
+                        if self.open_arrays.len() >= MAX_NESTING_DEPTH {
+                            return Err(RespParseError::InvalidFormat(
+                                "Arrays nested too deeply".to_string(),
+                            ));
+                        }
+                        self.open_arrays.push(size as u64);
+                        self.scanned = end;
+                        continue;
+                    }
+                }
+                _ => {}
             }
-
-            let content = buf.split_to(size);
-
-            // Consume \r\n
-            if buf.len() < 2 || buf[0] != b'\r' || buf[1] != b'\n' {
-                return Err(RespParseError::InvalidFormat(
-                    "Missing \\r\\n after bulk string".to_string(),
-                ));
+            self.scanned = end;
+
+            // The element may be the last one of the innermost open arrays
+            loop {
+                match self.open_arrays.last_mut() {
+                    None => return Ok(Some(end)),
+                    Some(remaining) if *remaining > 1 => {
+                        *remaining -= 1;
+                        break;
+                    }
+                    Some(_) => {
+                        self.open_arrays.pop();
+                    }
+                }
             }
-            buf.advance(2);
-
-            Ok(Some(RespValue::BulkString(Some(content.freeze()))))
-        } else {
-            Ok(None)
         }
+        Ok(None)
     }
 
-    fn parse_array(buf: &mut BytesMut) -> Result<Option<RespValue>, RespParseError> {
-        if let Some(size_line) = Self::read_line(buf)? {
-            let size_str = str::from_utf8(&size_line[1..])?; // Skip '*'
-            let size: i64 = size_str.parse()?;
+    /// Fail if a value of at least `size` bytes is over the limit
+    fn check_size(&self, size: usize) -> Result<(), RespParseError> {
+        match self.max_frame_size {
+            Some(max) if size > max => Err(RespParseError::FrameTooLarge { size, max }),
+            _ => Ok(()),
+        }
+    }
 
-            if size == -1 {
-                // NULL array
-                return Ok(Some(RespValue::Array(None)));
-            }
+    /// Parse the value at `pos` of a complete frame, advancing `pos` past it
+    fn value_at(frame: &Bytes, pos: &mut usize) -> Result<RespValue, RespParseError> {
+        let start = *pos;
+        let line_len = find_crlf(&frame[start..]).ok_or(RespParseError::Incomplete)?;
+        let line = &frame[start + 1..start + line_len];
+        *pos = start + line_len + 2;
+
+        match frame[start] {
+            b'+' => Ok(RespValue::SimpleString(str::from_utf8(line)?.to_string())),
+            b'-' => Ok(RespValue::Error(str::from_utf8(line)?.to_string())),
+            b':' => Ok(RespValue::Integer(str::from_utf8(line)?.parse()?)),
+            b'$' => {
+                let size: i64 = str::from_utf8(line)?.parse()?;
+                if size == -1 {
+                    // NULL bulk string
+                    return Ok(RespValue::BulkString(None));
+                }
+                if size < 0 {
+                    return Err(RespParseError::InvalidFormat(
+                        "Invalid bulk string size".to_string(),
+                    ));
+                }
 
-            if size < 0 {
-                return Err(RespParseError::InvalidFormat(
-                    "Invalid array size".to_string(),
-                ));
+                // The scan made sure the string and its \r\n are buffered
+                let content_start = *pos;
+                let content_end = content_start + size as usize;
+                if &frame[content_end..content_end + 2] != b"\r\n" {
+                    return Err(RespParseError::InvalidFormat(
+                        "Missing \\r\\n after bulk string".to_string(),
+                    ));
+                }
+                *pos = content_end + 2;
+                Ok(RespValue::BulkString(Some(
+                    frame.slice(content_start..content_end),
+                )))
             }
+            b'*' => {
+                let size: i64 = str::from_utf8(line)?.parse()?;
+                if size == -1 {
+                    // NULL array
+                    return Ok(RespValue::Array(None));
+                }
+                if size < 0 {
+                    return Err(RespParseError::InvalidFormat(
+                        "Invalid array size".to_string(),
+                    ));
+                }
 
-            let size = size as usize;
-            let mut elements = Vec::with_capacity(size);
-
-            for _ in 0..size {
-                match Self::parse(buf)? {
-                    Some(element) => elements.push(element),
-                    None => {
-                        // Not enough data for complete array
-                        // TODO: Handle partial array parsing properly
-                        return Ok(None);
-                    }
+                // Bounded by the frame, which holds every element
+                let mut elements = Vec::with_capacity(size as usize);
+                for _ in 0..size {
+                    elements.push(Self::value_at(frame, pos)?);
                 }
+                Ok(RespValue::Array(Some(elements)))
             }
-
-            Ok(Some(RespValue::Array(Some(elements))))
-        } else {
-            Ok(None)
+            first_byte => Err(RespParseError::InvalidFormat(format!(
+                "Unknown RESP type: {}",
+                first_byte as char
+            ))),
         }
     }
+}
 
-    /// Read a line ending with \r\n
-    fn read_line(buf: &mut BytesMut) -> Result<Option<Vec<u8>>, RespParseError> {
-        for i in 0..buf.len() - 1 {
-            if buf[i] == b'\r' && buf[i + 1] == b'\n' {
-                let line = buf.split_to(i + 2);
-                let line_content = line[..line.len() - 2].to_vec(); // Remove \r\n
-                return Ok(Some(line_content));
-            }
-        }
-        Ok(None) // No complete line found
+impl Decoder for RespParser {
+    type Item = RespValue;
+    type Error = RespParseError;
+
+    fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<RespValue>, RespParseError> {
+        Ok(self.decode_frame(buf)?.map(|(value, _)| value))
     }
 }
 
+/// Offset of the first \r\n in `buf`
+fn find_crlf(buf: &[u8]) -> Option<usize> {
+    buf.windows(2).position(|pair| pair == b"\r\n")
+}
+
 impl RespEncoder {
     /// Encode a RESP value to bytes
     pub fn encode(value: &RespValue) -> Bytes {
@@ -341,4 +461,274 @@ mod tests {
         let result = RespParser::parse(&mut buf).unwrap();
         assert!(result.is_none()); // Should return None for incomplete data
     }
+
+    #[test]
+    fn test_partial_array_is_kept() {
+        let mut buf = BytesMut::from("*2\r\n$5\r\nhello\r\n$5\r\nwor");
+        assert!(RespParser::parse(&mut buf).unwrap().is_none());
+        assert_eq!(buf, BytesMut::from("*2\r\n$5\r\nhello\r\n$5\r\nwor"));
+
+        buf.extend_from_slice(b"ld\r\n");
+        let command = RespParser::parse(&mut buf).unwrap().unwrap();
+        assert_eq!(command, RespEncoder::create_command("hello", &["world"]));
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn test_decoder_keeps_state_between_reads() {
+        let input = b"*2\r\n$3\r\nGET\r\n*1\r\n:1\r\n+OK\r\n";
+        let mut parser = RespParser::new();
+        let mut buf = BytesMut::new();
+        let mut frames = Vec::new();
+        for &byte in input {
+            buf.put_u8(byte);
+            if let Some(frame) = parser.decode_frame(&mut buf).unwrap() {
+                frames.push(frame);
+            }
+        }
+
+        assert_eq!(frames.len(), 2);
+        assert_eq!(&frames[0].1[..], b"*2\r\n$3\r\nGET\r\n*1\r\n:1\r\n");
+        assert_eq!(
+            frames[0].0,
+            RespValue::Array(Some(vec![
+                RespValue::BulkString(Some(Bytes::from("GET"))),
+                RespValue::Array(Some(vec![RespValue::Integer(1)])),
+            ]))
+        );
+        assert_eq!(frames[1].0, RespValue::SimpleString("OK".to_string()));
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn test_command_parts_are_frame_slices() {
+        let mut buf = BytesMut::from("*3\r\n$3\r\nSET\r\n$3\r\nkey\r\n$5\r\nvalue\r\n+OK\r\n");
+        let frame = RespParser::new().split_frame(&mut buf).unwrap().unwrap();
+        assert_eq!(buf, BytesMut::from("+OK\r\n"));
+
+        let parts = RespParser::command_parts(&frame).unwrap();
+        assert_eq!(
+            parts,
+            vec![Bytes::from("SET"), Bytes::from("key"), Bytes::from("value")]
+        );
+        let frame_range = frame.as_ptr_range();
+        assert!(parts
+            .iter()
+            .all(|part| frame_range.contains(&part.as_ptr())));
+    }
+
+    #[test]
+    fn test_command_parts_of_other_values() {
+        for input in [
+            "+OK\r\n",
+            "*-1\r\n",
+            "*2\r\n$3\r\nGET\r\n:1\r\n",
+            "*2\r\n$3\r\nGET\r\n$-1\r\n",
+            "*1\r\n*1\r\n$3\r\nGET\r\n",
+            "*1\r\n$3\r\nGETXX",
+        ] {
+            let frame = Bytes::from(input);
+            assert_eq!(RespParser::command_parts(&frame), None, "{input:?}");
+        }
+        assert_eq!(
+            RespParser::command_parts(&Bytes::from("*0\r\n")),
+            Some(vec![])
+        );
+    }
+
+    #[test]
+    fn test_max_frame_size() {
+        let command = RespEncoder::encode(&RespEncoder::create_command("SET", &["key", "value"]));
+        let mut parser = RespParser::new().with_max_frame_size(command.len());
+        let mut buf = BytesMut::from(&command[..]);
+        assert!(parser.decode(&mut buf).unwrap().is_some());
+
+        // A bulk string is rejected from its length alone
+        let mut buf = BytesMut::from("*2\r\n$3\r\nGET\r\n$1048576\r\nabc");
+        assert!(matches!(
+            parser.decode(&mut buf),
+            Err(RespParseError::FrameTooLarge { max, .. }) if max == command.len()
+        ));
+
+        // So is a line that never ends
+        let mut parser = RespParser::new().with_max_frame_size(16);
+        let mut buf = BytesMut::from("+");
+        buf.extend_from_slice(&[b'a'; 16]);
+        assert!(matches!(
+            parser.decode(&mut buf),
+            Err(RespParseError::FrameTooLarge { size: 18, max: 16 })
+        ));
+
+        // The limit applies to the whole value, not each element
+        let mut buf = BytesMut::from("*4\r\n:1\r\n:2\r\n:3\r\n:4\r\n");
+        assert!(matches!(
+            parser.decode(&mut buf),
+            Err(RespParseError::FrameTooLarge { size: 20, max: 16 })
+        ));
+    }
+
+    #[test]
+    fn test_empty_buffer() {
+        let mut buf = BytesMut::new();
+        assert!(RespParser::parse(&mut buf).unwrap().is_none());
+        assert!(RespParser::parse_commands(&mut buf).unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_framed_read() {
+        use futures::StreamExt;
+        use tokio_util::codec::FramedRead;
+
+        let input: &[u8] = b"+PONG\r\n$5\r\nhello\r\n*-1\r\n";
+        let values: Vec<RespValue> = FramedRead::new(input, RespParser::new())
+            .map(Result::unwrap)
+            .collect()
+            .await;
+        assert_eq!(
+            values,
+            vec![
+                RespValue::SimpleString("PONG".to_string()),
+                RespValue::BulkString(Some(Bytes::from("hello"))),
+                RespValue::Array(None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_malformed_lengths() {
+        for input in [
+            "$9223372036854775807\r\n",
+            "*9223372036854775807\r\n",
+            "$99999999999999999999\r\n",
+            "$-2\r\n",
+            "*-2\r\n",
+            "$\r\n",
+            "$1x\r\na\r\n",
+        ] {
+            let mut buf = BytesMut::from(input);
+            let result = RespParser::parse(&mut buf);
+            assert!(
+                !matches!(result, Ok(Some(_))),
+                "{input:?} parsed as {result:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_nesting_limit() {
+        let mut buf = BytesMut::from("*1\r\n".repeat(MAX_NESTING_DEPTH + 1).as_str());
+        buf.extend_from_slice(b":1\r\n");
+        assert!(matches!(
+            RespParser::parse(&mut buf),
+            Err(RespParseError::InvalidFormat(_))
+        ));
+
+        let mut buf = BytesMut::from("*1\r\n".repeat(MAX_NESTING_DEPTH).as_str());
+        buf.extend_from_slice(b":1\r\n");
+        assert!(RespParser::parse(&mut buf).unwrap().is_some());
+    }
+
+    mod properties {
+        use super::*;
+        use proptest::collection::vec;
+        use proptest::prelude::*;
+
+        /// Any RESP value, with bulk strings full of CR and LF bytes
+        fn value() -> impl Strategy<Value = RespValue> {
+            let bulk = vec(prop_oneof![Just(b'\r'), Just(b'\n'), any::<u8>()], 0..32);
+            let leaf = prop_oneof![
+                "[^\r\n]*".prop_map(RespValue::SimpleString),
+                "[^\r\n]*".prop_map(RespValue::Error),
+                any::<i64>().prop_map(RespValue::Integer),
+                proptest::option::of(bulk)
+                    .prop_map(|bytes| RespValue::BulkString(bytes.map(Bytes::from))),
+                Just(RespValue::Array(None)),
+            ];
+            leaf.prop_recursive(4, 64, 8, |inner| {
+                vec(inner, 0..8).prop_map(|elements| RespValue::Array(Some(elements)))
+            })
+        }
+
+        proptest! {
+            #[test]
+            fn test_round_trip(value in value()) {
+                let mut buf = BytesMut::from(&RespEncoder::encode(&value)[..]);
+                prop_assert_eq!(RespParser::parse(&mut buf).unwrap(), Some(value));
+                prop_assert!(buf.is_empty());
+            }
+
+            #[test]
+            fn test_pipelined_round_trip(values in vec(value(), 0..8)) {
+                let mut buf = BytesMut::new();
+                for value in &values {
+                    RespEncoder::encode_into(&mut buf, value);
+                }
+                prop_assert_eq!(RespParser::parse_commands(&mut buf).unwrap(), values);
+                prop_assert!(buf.is_empty());
+            }
+
+            #[test]
+            fn test_split_reads(value in value(), split in any::<prop::sample::Index>()) {
+                let encoded = RespEncoder::encode(&value);
+                let split = split.index(encoded.len());
+                let mut buf = BytesMut::from(&encoded[..split]);
+                prop_assert_eq!(RespParser::parse(&mut buf).unwrap(), None);
+                prop_assert_eq!(&buf[..], &encoded[..split]);
+
+                buf.extend_from_slice(&encoded[split..]);
+                prop_assert_eq!(RespParser::parse(&mut buf).unwrap(), Some(value));
+                prop_assert!(buf.is_empty());
+            }
+
+            #[test]
+            fn test_command_parts(parts in vec(vec(any::<u8>(), 0..32), 0..8)) {
+                let parts: Vec<Bytes> = parts.into_iter().map(Bytes::from).collect();
+                let command = RespValue::Array(Some(
+                    parts.iter().cloned().map(|part| RespValue::BulkString(Some(part))).collect(),
+                ));
+                let frame = RespEncoder::encode(&command);
+                prop_assert_eq!(RespParser::command_parts(&frame), Some(parts));
+                prop_assert_eq!(RespParser::parse_frame(&frame).unwrap(), command);
+            }
+
+            #[test]
+            fn test_chunked_decoding(values in vec(value(), 1..8), chunk in 1usize..16) {
+                let mut encoded = BytesMut::new();
+                for value in &values {
+                    RespEncoder::encode_into(&mut encoded, value);
+                }
+                let mut parser = RespParser::new();
+                let mut buf = BytesMut::new();
+                let mut decoded = Vec::new();
+                for piece in encoded.chunks(chunk) {
+                    buf.extend_from_slice(piece);
+                    while let Some(value) = parser.decode(&mut buf).unwrap() {
+                        decoded.push(value);
+                    }
+                }
+                prop_assert_eq!(decoded, values);
+                prop_assert!(buf.is_empty());
+            }
+
+            #[test]
+            fn test_malformed_length_prefixes(
+                kind in prop_oneof![Just("$"), Just("*")],
+                length in "-?[0-9]{0,24}|[^\r\n]{0,8}",
+                rest in vec(any::<u8>(), 0..64),
+            ) {
+                let mut buf = BytesMut::from(format!("{kind}{length}\r\n").as_str());
+                buf.extend_from_slice(&rest);
+                let before = buf.clone();
+                if let Ok(None) = RespParser::parse(&mut buf) {
+                    prop_assert_eq!(buf, before);
+                }
+            }
+
+            #[test]
+            fn test_arbitrary_bytes(bytes in vec(any::<u8>(), 0..256)) {
+                let mut buf = BytesMut::from(&bytes[..]);
+                let _ = RespParser::parse_commands(&mut buf);
+            }
+        }
+    }
 }
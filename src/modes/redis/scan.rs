@@ -0,0 +1,163 @@
+/// Proxy-side SCAN across cluster masters
+///
+/// Each master has its own keyspace and cursor, so the proxy walks the
+/// masters one after another and hands out a virtual cursor combining the
+/// master's position in the sorted master list with that master's cursor.
+/// A scan spanning a topology change may skip or repeat keys, as SCAN allows.
+use super::resp::{RespEncoder, RespParser, RespValue};
+use super::RedisCommand;
+use bytes::{Bytes, BytesMut};
+
+/// Low bits of a virtual cursor holding the master index
+const NODE_BITS: u32 = 10;
+
+/// Largest number of masters a virtual cursor can address
+pub const MAX_NODES: usize = 1 << NODE_BITS;
+
+const INVALID_CURSOR_REPLY: &[u8] = b"-ERR invalid cursor\r\n";
+
+/// Split a client cursor into the master index and that master's cursor
+pub fn decode_cursor(cursor: &[u8], nodes: usize) -> Result<(usize, u64), Bytes> {
+    let cursor: u64 = std::str::from_utf8(cursor)
+        .ok()
+        .and_then(|cursor| cursor.parse().ok())
+        .ok_or_else(|| Bytes::from_static(INVALID_CURSOR_REPLY))?;
+    let index = (cursor & (MAX_NODES as u64 - 1)) as usize;
+    if index >= nodes {
+        return Err(Bytes::from_static(INVALID_CURSOR_REPLY));
+    }
+    Ok((index, cursor >> NODE_BITS))
+}
+
+/// Virtual cursor for `node_cursor` on master `index`, or `None` when the
+/// master's cursor is too large to fit
+pub fn encode_cursor(index: usize, node_cursor: u64) -> Option<u64> {
+    if node_cursor.leading_zeros() < NODE_BITS {
+        return None;
+    }
+    Some(node_cursor << NODE_BITS | index as u64)
+}
+
+/// The client's `SCAN` with its cursor replaced by the master's
+pub fn node_command(command: &RedisCommand, node_cursor: u64) -> Bytes {
+    let mut elements = vec![
+        RespValue::BulkString(Some(Bytes::from_static(b"SCAN"))),
+        RespValue::BulkString(Some(Bytes::from(node_cursor.to_string()))),
+    ];
+    elements.extend(
+        command
+            .args
+            .iter()
+            .skip(1)
+            .map(|arg| RespValue::BulkString(Some(arg.clone()))),
+    );
+    RespEncoder::encode(&RespValue::Array(Some(elements)))
+}
+
+/// Rewrite a master's `SCAN` reply for the client, moving on to the next
+/// master once this one is exhausted
+pub fn client_reply(node_reply: &[u8], index: usize, nodes: usize) -> Result<Bytes, String> {
+    let mut buf = BytesMut::from(node_reply);
+    let (node_cursor, keys) = match RespParser::parse(&mut buf) {
+        Ok(Some(RespValue::Array(Some(mut elements)))) if elements.len() == 2 => {
+            let keys = elements.pop();
+            match elements.pop() {
+                Some(RespValue::BulkString(Some(cursor))) => (cursor, keys),
+                _ => return Err("unexpected SCAN reply".to_string()),
+            }
+        }
+        // Errors are passed through as they are
+        Ok(Some(RespValue::Error(_))) => return Ok(Bytes::copy_from_slice(node_reply)),
+        _ => return Err("unexpected SCAN reply".to_string()),
+    };
+    let node_cursor: u64 = std::str::from_utf8(&node_cursor)
+        .ok()
+        .and_then(|cursor| cursor.parse().ok())
+        .ok_or("invalid cursor in SCAN reply")?;
+
+    let cursor = match node_cursor {
+        0 if index + 1 < nodes => index as u64 + 1,
+        0 => 0,
+        _ => encode_cursor(index, node_cursor).ok_or("SCAN cursor too large to virtualize")?,
+    };
+    let reply = RespValue::Array(Some(vec![
+        RespValue::BulkString(Some(Bytes::from(cursor.to_string()))),
+        keys.unwrap_or(RespValue::Array(Some(Vec::new()))),
+    ]));
+    Ok(RespEncoder::encode(&reply))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scan_reply(cursor: &str, keys: &[&str]) -> Bytes {
+        RespEncoder::encode(&RespValue::Array(Some(vec![
+            RespValue::BulkString(Some(Bytes::from(cursor.to_string()))),
+            RespValue::Array(Some(
+                keys.iter()
+                    .map(|key| RespValue::BulkString(Some(Bytes::from(key.to_string()))))
+                    .collect(),
+            )),
+        ])))
+    }
+
+    #[test]
+    fn test_cursor_round_trip() {
+        assert_eq!(decode_cursor(b"0", 3), Ok((0, 0)));
+        let cursor = encode_cursor(2, 1234).unwrap();
+        assert_eq!(
+            decode_cursor(cursor.to_string().as_bytes(), 3),
+            Ok((2, 1234))
+        );
+
+        assert!(decode_cursor(b"2", 2).is_err());
+        assert!(decode_cursor(b"abc", 2).is_err());
+        assert_eq!(encode_cursor(0, u64::MAX), None);
+    }
+
+    #[test]
+    fn test_client_reply_walks_masters() {
+        // Mid-scan on the first master keeps the master index
+        let reply = client_reply(&scan_reply("17", &["a", "b"]), 0, 2).unwrap();
+        assert_eq!(
+            reply,
+            scan_reply(&encode_cursor(0, 17).unwrap().to_string(), &["a", "b"])
+        );
+
+        // A finished master moves on to the next one
+        let reply = client_reply(&scan_reply("0", &["c"]), 0, 2).unwrap();
+        assert_eq!(reply, scan_reply("1", &["c"]));
+
+        // The last master ends the scan
+        let reply = client_reply(&scan_reply("0", &[]), 1, 2).unwrap();
+        assert_eq!(reply, scan_reply("0", &[]));
+
+        assert_eq!(
+            client_reply(b"-ERR syntax error\r\n", 0, 2)
+                .unwrap()
+                .as_ref(),
+            b"-ERR syntax error\r\n"
+        );
+        assert!(client_reply(b":1\r\n", 0, 2).is_err());
+    }
+
+    #[test]
+    fn test_node_command_keeps_options() {
+        let command = RedisCommand {
+            command: "SCAN".to_string(),
+            args: vec![
+                Bytes::from_static(b"2049"),
+                Bytes::from_static(b"MATCH"),
+                Bytes::from_static(b"user:*"),
+            ],
+            key: None,
+            slot: None,
+            readonly: false,
+        };
+        assert_eq!(
+            node_command(&command, 2).as_ref(),
+            b"*4\r\n$4\r\nSCAN\r\n$1\r\n2\r\n$5\r\nMATCH\r\n$6\r\nuser:*\r\n"
+        );
+    }
+}
@@ -0,0 +1,191 @@
+/// Redis scripting and functions over per-slot routing
+///
+/// `EVAL`, `EVALSHA` and `FCALL` declare their keys with a `numkeys`
+/// argument, so they are routed by those keys rather than by their first
+/// argument. Scripts are cached per node by the nodes themselves; the proxy
+/// remembers the scripts clients load with `SCRIPT LOAD` and which nodes
+/// have them, so an `EVALSHA` routed to another node still finds its script.
+use super::transaction::CROSSSLOT_REPLY;
+use super::{RedisCommand, SlotMapping};
+use bytes::{BufMut, Bytes, BytesMut};
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+
+/// Whether `command` runs a script or function by SHA1 digest
+pub fn is_evalsha(command: &str) -> bool {
+    matches!(command, "EVALSHA" | "EVALSHA_RO")
+}
+
+/// Slot of the keys a script or function call declares
+///
+/// Returns `None` for other commands, and otherwise the slot (`None` when no
+/// keys are declared) or the error reply for an invalid key list.
+pub fn script_slot(command: &RedisCommand) -> Option<Result<Option<u16>, Bytes>> {
+    if !matches!(
+        command.command.as_str(),
+        "EVAL" | "EVAL_RO" | "EVALSHA" | "EVALSHA_RO" | "FCALL" | "FCALL_RO"
+    ) {
+        return None;
+    }
+    let numkeys = command
+        .args
+        .get(1)
+        .and_then(|numkeys| std::str::from_utf8(numkeys).ok()?.parse::<usize>().ok());
+    let keys = match numkeys {
+        Some(numkeys) if command.args.len() >= 2 + numkeys => &command.args[2..2 + numkeys],
+        Some(_) => {
+            return Some(Err(Bytes::from_static(
+                b"-ERR Number of keys can't be greater than number of args\r\n",
+            )))
+        }
+        None => {
+            return Some(Err(Bytes::from_static(
+                b"-ERR value is not an integer or out of range\r\n",
+            )))
+        }
+    };
+
+    let mut slots = keys
+        .iter()
+        .map(|key| SlotMapping::calculate_slot_bytes(key));
+    let slot = slots.next();
+    if slots.any(|other| Some(other) != slot) {
+        return Some(Err(Bytes::from_static(CROSSSLOT_REPLY)));
+    }
+    Some(Ok(slot))
+}
+
+/// Encode `SCRIPT LOAD <script>`
+pub fn script_load_command(script: &[u8]) -> Bytes {
+    let mut raw = BytesMut::with_capacity(script.len() + 40);
+    raw.put_slice(b"*3\r\n$6\r\nSCRIPT\r\n$4\r\nLOAD\r\n");
+    raw.put_slice(format!("${}\r\n", script.len()).as_bytes());
+    raw.put_slice(script);
+    raw.put_slice(b"\r\n");
+    raw.freeze()
+}
+
+/// Scripts loaded through the proxy and the nodes known to have them
+#[derive(Debug, Default)]
+pub struct ScriptCache {
+    /// Script bodies by lowercase SHA1 digest
+    scripts: Mutex<HashMap<String, Bytes>>,
+    /// Digests loaded on each node
+    loaded: Mutex<HashMap<String, HashSet<String>>>,
+}
+
+impl ScriptCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Remember a script `node` loaded under `sha`
+    pub fn record_load(&self, node: &str, sha: &str, script: Bytes) {
+        let sha = sha.to_ascii_lowercase();
+        self.scripts.lock().unwrap().insert(sha.clone(), script);
+        self.mark_loaded(node, &sha);
+    }
+
+    /// Body of the script with digest `sha`, if it was loaded through the proxy
+    pub fn script(&self, sha: &str) -> Option<Bytes> {
+        self.scripts
+            .lock()
+            .unwrap()
+            .get(&sha.to_ascii_lowercase())
+            .cloned()
+    }
+
+    pub fn mark_loaded(&self, node: &str, sha: &str) {
+        self.loaded
+            .lock()
+            .unwrap()
+            .entry(node.to_string())
+            .or_default()
+            .insert(sha.to_ascii_lowercase());
+    }
+
+    /// Forget that `node` has the script, after it answered `NOSCRIPT`
+    pub fn mark_missing(&self, node: &str, sha: &str) {
+        if let Some(loaded) = self.loaded.lock().unwrap().get_mut(node) {
+            loaded.remove(&sha.to_ascii_lowercase());
+        }
+    }
+
+    pub fn is_loaded(&self, node: &str, sha: &str) -> bool {
+        self.loaded
+            .lock()
+            .unwrap()
+            .get(node)
+            .is_some_and(|loaded| loaded.contains(&sha.to_ascii_lowercase()))
+    }
+
+    /// Forget every script, after `SCRIPT FLUSH`
+    pub fn clear(&self) {
+        self.scripts.lock().unwrap().clear();
+        self.loaded.lock().unwrap().clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modes::redis::test_support::command;
+
+    #[test]
+    fn test_script_slot() {
+        assert_eq!(script_slot(&command("GET", &["a"])), None);
+        assert_eq!(
+            script_slot(&command("EVAL", &["return 1", "0"])),
+            Some(Ok(None))
+        );
+        assert_eq!(
+            script_slot(&command("EVALSHA", &["abc", "2", "{u}a", "{u}b", "arg"])),
+            Some(Ok(Some(SlotMapping::calculate_slot("u"))))
+        );
+        assert_eq!(
+            script_slot(&command("FCALL", &["f", "1", "a", "b"])),
+            Some(Ok(Some(SlotMapping::calculate_slot("a"))))
+        );
+
+        assert_eq!(
+            script_slot(&command("EVAL", &["return 1", "2", "a", "b"])),
+            Some(Err(Bytes::from_static(CROSSSLOT_REPLY)))
+        );
+        assert!(matches!(
+            script_slot(&command("EVAL", &["return 1", "3", "a"])),
+            Some(Err(reply)) if reply.starts_with(b"-ERR Number of keys")
+        ));
+        assert!(matches!(
+            script_slot(&command("EVAL", &["return 1", "x"])),
+            Some(Err(reply)) if reply.starts_with(b"-ERR value is not an integer")
+        ));
+    }
+
+    #[test]
+    fn test_script_cache() {
+        let cache = ScriptCache::new();
+        let sha = "E0E1F9FABFC9D4800C877A703B823AC0578FF8DB";
+        cache.record_load("node-a:6379", sha, Bytes::from_static(b"return 1"));
+
+        assert_eq!(
+            cache.script(&sha.to_lowercase()),
+            Some(Bytes::from_static(b"return 1"))
+        );
+        assert!(cache.is_loaded("node-a:6379", sha));
+        assert!(!cache.is_loaded("node-b:6379", sha));
+
+        cache.mark_loaded("node-b:6379", sha);
+        cache.mark_missing("node-a:6379", sha);
+        assert!(!cache.is_loaded("node-a:6379", sha));
+        assert!(cache.is_loaded("node-b:6379", sha));
+
+        cache.clear();
+        assert_eq!(cache.script(sha), None);
+        assert!(!cache.is_loaded("node-b:6379", sha));
+
+        assert_eq!(
+            script_load_command(b"return 1").as_ref(),
+            b"*3\r\n$6\r\nSCRIPT\r\n$4\r\nLOAD\r\n$8\r\nreturn 1\r\n"
+        );
+    }
+}
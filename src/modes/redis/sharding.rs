@@ -0,0 +1,76 @@
+/// Sharding across standalone Redis servers
+///
+/// Without Redis Cluster, keys can still be spread over a static list of
+/// independent servers, as twemproxy does. Each key maps to one of the
+/// 16384 slots by the CRC16 of its hash tag, exactly as in a cluster, and
+/// the slots are split into contiguous ranges, one per server in list order
+/// and sized by its weight. Routing, multi-key command splitting and
+/// fan-out then work as in cluster mode, without redirects or topology
+/// refreshes. Adding, removing or reordering servers moves keys between
+/// them.
+use super::SlotMapping;
+use crate::admin::status::TOTAL_SLOTS;
+use std::collections::{HashMap, HashSet};
+
+/// Slot mapping sharing the slots among `servers` in proportion to their
+/// weight; unlisted servers weigh 1
+pub fn standalone_mapping(servers: &[String], weights: &HashMap<String, usize>) -> SlotMapping {
+    let weight_of = |server: &String| weights.get(server).copied().unwrap_or(1).max(1);
+    let mut seen = HashSet::new();
+    let servers: Vec<&String> = servers.iter().filter(|server| seen.insert(*server)).collect();
+    let total: usize = servers.iter().copied().map(weight_of).sum();
+    let mut slot_ranges = HashMap::new();
+    let mut start = 0;
+    let mut cumulative = 0;
+    for server in servers {
+        cumulative += weight_of(server);
+        let end = TOTAL_SLOTS * cumulative / total;
+        if end > start {
+            slot_ranges.insert(server.clone(), vec![(start as u16, (end - 1) as u16)]);
+        }
+        start = end;
+    }
+
+    let mut mapping = SlotMapping::new();
+    mapping.update_slot_mapping(slot_ranges);
+    mapping
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn servers(addrs: &[&str]) -> Vec<String> {
+        addrs.iter().map(|addr| addr.to_string()).collect()
+    }
+
+    #[test]
+    fn test_slots_split_evenly_in_list_order() {
+        let servers = servers(&["10.0.0.1:6379", "10.0.0.2:6379", "10.0.0.3:6379"]);
+        let mapping = standalone_mapping(&servers, &HashMap::new());
+        assert!(mapping.is_complete());
+        assert_eq!(mapping.get_backend_for_slot(0).as_deref(), Some("10.0.0.1:6379"));
+        assert_eq!(mapping.get_backend_for_slot(5460).as_deref(), Some("10.0.0.1:6379"));
+        assert_eq!(mapping.get_backend_for_slot(5461).as_deref(), Some("10.0.0.2:6379"));
+        assert_eq!(mapping.get_backend_for_slot(16383).as_deref(), Some("10.0.0.3:6379"));
+        assert_eq!(mapping.slot_counts().values().sum::<usize>(), TOTAL_SLOTS);
+
+        // Keys sharing a hash tag land on the same server
+        let server_of = |key: &str| mapping.get_backend_for_slot(SlotMapping::calculate_slot(key));
+        assert_eq!(server_of("{user:1}:name"), server_of("{user:1}:email"));
+    }
+
+    #[test]
+    fn test_slots_follow_weights() {
+        let servers = servers(&["10.0.0.1:6379", "10.0.0.2:6379", "10.0.0.1:6379"]);
+        let weights = HashMap::from([("10.0.0.2:6379".to_string(), 3)]);
+        let mapping = standalone_mapping(&servers, &weights);
+        assert!(mapping.is_complete());
+        let counts = mapping.slot_counts();
+        // Listing a server twice does not give it more slots
+        assert_eq!(counts["10.0.0.1:6379"], 4096);
+        assert_eq!(counts["10.0.0.2:6379"], 12288);
+
+        assert!(!standalone_mapping(&[], &weights).is_complete());
+    }
+}
@@ -1,118 +1,144 @@
-/// Redis slot management and mapping
+/// Redis cluster slot mapping
+///
+/// Every key belongs to one of 16384 slots, the CRC16 of its hash tag
+/// modulo 16384, and each slot is served by one master. The mapping is
+/// built from `CLUSTER NODES` output, with the replicas of each master and
+/// the slots being migrated, and is kept current by patching single slots
+/// on MOVED redirects between topology refreshes.
+use crate::admin::status::TOTAL_SLOTS;
+use crate::utils::crc16;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
-/// Redis cluster slot mapping (0-16383)
+/// Redis cluster slot mapping (16384 slots total)
 #[derive(Debug, Clone)]
-pub struct SlotMap {
-    /// Maps slot number to backend ID
+pub struct SlotMapping {
+    /// Maps slot number (0-16383) to backend ID
     slot_to_backend: HashMap<u16, String>,
-    /// Maps backend ID to their slot ranges
-    backend_to_slots: HashMap<String, Vec<SlotRange>>,
+    /// Maps backend ID to slot ranges for quick lookup
+    backend_to_slots: HashMap<String, Vec<(u16, u16)>>,
+    /// Maps master backend ID to its replica addresses
+    replicas: HashMap<String, Vec<String>>,
+    /// Slots being resharded from one master to another
+    migrations: HashMap<u16, SlotMigration>,
+    /// Highest config epoch of the masters, newer slot maps having higher
+    config_epoch: u64,
 }
 
-/// Represents a range of slots assigned to a backend
-#[derive(Debug, Clone, PartialEq)]
-pub struct SlotRange {
-    pub start: u16,
-    pub end: u16,
+/// A slot moving between masters during resharding
+///
+/// The source master keeps serving the slot's remaining keys and answers
+/// ASK for those already moved to the destination. `CLUSTER NODES` shows
+/// the migration only on the line of the node answering it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SlotMigration {
+    pub source: String,
+    pub destination: String,
 }
 
-impl SlotRange {
-    pub fn new(start: u16, end: u16) -> Self {
-        Self { start, end }
-    }
-
-    pub fn contains(&self, slot: u16) -> bool {
-        slot >= self.start && slot <= self.end
-    }
-
-    pub fn size(&self) -> u16 {
-        self.end - self.start + 1
-    }
-}
-
-impl SlotMap {
+impl SlotMapping {
     pub fn new() -> Self {
         Self {
             slot_to_backend: HashMap::new(),
             backend_to_slots: HashMap::new(),
+            replicas: HashMap::new(),
+            migrations: HashMap::new(),
+            config_epoch: 0,
         }
     }
 
-    /// Assign a range of slots to a backend
-    pub fn assign_slots(&mut self, backend_id: String, slot_range: SlotRange) {
-        // Remove any existing assignments for these slots
-        for slot in slot_range.start..=slot_range.end {
-            if let Some(old_backend) = self.slot_to_backend.remove(&slot) {
-                // Remove slot from old backend's ranges
-                if let Some(ranges) = self.backend_to_slots.get_mut(&old_backend) {
-                    ranges.retain(|range| !range.contains(slot));
-                    if ranges.is_empty() {
-                        self.backend_to_slots.remove(&old_backend);
-                    }
-                }
-            }
-            self.slot_to_backend.insert(slot, backend_id.clone());
-        }
+    /// Calculate Redis slot for a key using CRC16
+    pub fn calculate_slot(key: &str) -> u16 {
+        Self::calculate_slot_bytes(key.as_bytes())
+    }
 
-        // Add range to backend's slot list
-        self.backend_to_slots
-            .entry(backend_id)
-            .or_insert_with(Vec::new)
-            .push(slot_range);
+    /// Calculate the slot of a binary key
+    pub fn calculate_slot_bytes(key: &[u8]) -> u16 {
+        // Calculate CRC16 and mod 16384
+        crc16(Self::hash_tag(key).unwrap_or(key)) % 16384
     }
 
-    /// Get backend ID for a specific slot
-    pub fn get_backend_for_slot(&self, slot: u16) -> Option<&String> {
-        self.slot_to_backend.get(&slot)
+    /// The hash tag of a key: the text between its first `{` and the next
+    /// `}`, when not empty
+    pub fn hash_tag(key: &[u8]) -> Option<&[u8]> {
+        let start = key.iter().position(|&b| b == b'{')?;
+        let len = key[start + 1..].iter().position(|&b| b == b'}')?;
+        (len > 0).then(|| &key[start + 1..start + 1 + len])
     }
 
-    /// Get all slot ranges for a backend
-    pub fn get_slots_for_backend(&self, backend_id: &str) -> Option<&Vec<SlotRange>> {
-        self.backend_to_slots.get(backend_id)
+    /// Get backend ID for a given slot
+    pub fn get_backend_for_slot(&self, slot: u16) -> Option<String> {
+        self.slot_to_backend.get(&slot).cloned()
     }
 
-    /// Remove all slots assigned to a backend
-    pub fn remove_backend(&mut self, backend_id: &str) {
-        if let Some(ranges) = self.backend_to_slots.remove(backend_id) {
-            for range in ranges {
-                for slot in range.start..=range.end {
-                    self.slot_to_backend.remove(&slot);
+    /// Update slot mapping from cluster nodes
+    pub fn update_slot_mapping(&mut self, slot_ranges: HashMap<String, Vec<(u16, u16)>>) {
+        self.slot_to_backend.clear();
+        self.backend_to_slots = slot_ranges.clone();
+
+        for (backend_id, ranges) in slot_ranges {
+            for (start, end) in ranges {
+                for slot in start..=end {
+                    self.slot_to_backend.insert(slot, backend_id.clone());
                 }
             }
         }
     }
 
-    /// Check if all slots (0-16383) are assigned
-    pub fn is_complete(&self) -> bool {
-        self.slot_to_backend.len() == 16384
-    }
-
-    /// Get coverage statistics
-    pub fn get_coverage(&self) -> SlotCoverage {
-        let assigned_slots = self.slot_to_backend.len();
-        let total_slots = 16384u16;
-        let coverage_percentage = (assigned_slots as f64 / total_slots as f64) * 100.0;
-
-        let mut backend_stats = HashMap::new();
-        for (backend_id, ranges) in &self.backend_to_slots {
-            let slot_count: u16 = ranges.iter().map(|r| r.size()).sum();
-            backend_stats.insert(backend_id.clone(), slot_count);
+    /// Assign slots `start..=end` to `backend_id`, leaving every other
+    /// slot with its current backend
+    pub fn assign_slots(&mut self, backend_id: &str, start: u16, end: u16) {
+        for ranges in self.backend_to_slots.values_mut() {
+            *ranges = ranges
+                .iter()
+                .flat_map(|&(from, to)| {
+                    if to < start || from > end {
+                        return vec![(from, to)];
+                    }
+                    let mut kept = Vec::new();
+                    if from < start {
+                        kept.push((from, start - 1));
+                    }
+                    if to > end {
+                        kept.push((end + 1, to));
+                    }
+                    kept
+                })
+                .collect();
+        }
+        self.backend_to_slots.retain(|_, ranges| !ranges.is_empty());
+
+        let ranges = self.backend_to_slots.entry(backend_id.to_string()).or_default();
+        ranges.push((start, end));
+        ranges.sort_unstable();
+        // Merge ranges that now touch
+        let mut merged: Vec<(u16, u16)> = Vec::with_capacity(ranges.len());
+        for &(from, to) in ranges.iter() {
+            match merged.last_mut() {
+                Some(last) if u32::from(from) <= u32::from(last.1) + 1 => last.1 = last.1.max(to),
+                _ => merged.push((from, to)),
+            }
         }
+        *ranges = merged;
 
-        SlotCoverage {
-            assigned_slots: assigned_slots as u16,
-            total_slots,
-            coverage_percentage,
-            backend_distribution: backend_stats,
+        for slot in start..=end {
+            self.slot_to_backend.insert(slot, backend_id.to_string());
         }
     }
 
-    /// Parse CLUSTER NODES response to update slot mapping
-    pub fn update_from_cluster_nodes(&mut self, cluster_nodes: &str) -> Result<(), SlotParseError> {
-        // Clear existing mappings
-        self.slot_to_backend.clear();
-        self.backend_to_slots.clear();
+    /// Slot mapping described by `CLUSTER NODES` output
+    ///
+    /// Failed nodes and malformed lines are skipped.
+    pub fn from_cluster_nodes(cluster_nodes: &str) -> Self {
+        let mut slot_mapping = Self::new();
+        let mut slot_ranges = HashMap::new();
+        // Node IDs of masters, and replicas keyed by their master's node ID
+        let mut master_addrs: HashMap<&str, String> = HashMap::new();
+        let mut replicas_by_master_id: HashMap<&str, Vec<String>> = HashMap::new();
+        // Migrating and importing slots with this node's address and the
+        // node ID of the other end
+        let mut migrating: Vec<(u16, String, &str)> = Vec::new();
+        let mut importing: Vec<(u16, String, &str)> = Vec::new();
 
         for line in cluster_nodes.lines() {
             if line.trim().is_empty() {
@@ -121,83 +147,238 @@ impl SlotMap {
 
             let parts: Vec<&str> = line.split_whitespace().collect();
             if parts.len() < 8 {
-                continue; // Invalid line
+                continue; // Invalid line format
             }
 
-            let _node_id = parts[0];
-            let address_port = parts[1];
+            let node_id = parts[0];
+            let address = parts[1];
             let flags = parts[2];
-
-            // Skip replica nodes and failed nodes
-            if flags.contains("slave") || flags.contains("fail") {
+            let master_id = parts[3];
+            // parts[4] is ping_sent
+            // parts[5] is pong_recv
+            // parts[6] is config_epoch
+            // parts[7] is link_state
+            // parts[8..] are slot ranges
+
+            if flags.contains("fail") {
                 continue;
             }
 
-            // Extract address for backend lookup
-            let address = if let Some(at_pos) = address_port.find('@') {
-                &address_port[..at_pos]
+            // Extract IP:port from address (remove @cluster_port if present)
+            let addr = if let Some(at_pos) = address.find('@') {
+                &address[..at_pos]
             } else {
-                address_port
+                address
             };
 
-            // Parse slot ranges (from index 8 onwards)
-            for slot_spec in &parts[8..] {
-                if slot_spec.contains('-') {
-                    // Range like "0-5460"
-                    let range_parts: Vec<&str> = slot_spec.split('-').collect();
+            // Replicas serve no slots of their own; remember them for reads
+            if flags.contains("slave") {
+                if parts[7] == "connected" {
+                    replicas_by_master_id
+                        .entry(master_id)
+                        .or_default()
+                        .push(addr.to_string());
+                }
+                continue;
+            }
+            master_addrs.insert(node_id, addr.to_string());
+            if let Ok(config_epoch) = parts[6].parse::<u64>() {
+                slot_mapping.config_epoch = slot_mapping.config_epoch.max(config_epoch);
+            }
+
+            let mut ranges = Vec::new();
+
+            // Parse slot ranges (format: "0-5460" or "5461-10922" or single slots "16383"),
+            // and migrations ("[77->-<destination id>]" or "[93-<-<source id>]")
+            for slot_info in &parts[8..] {
+                if let Some(marker) = slot_info.strip_prefix('[').and_then(|info| info.strip_suffix(']')) {
+                    if let Some((slot, destination_id)) = marker.split_once("->-") {
+                        if let Ok(slot) = slot.parse::<u16>() {
+                            migrating.push((slot, addr.to_string(), destination_id));
+                        }
+                    } else if let Some((slot, source_id)) = marker.split_once("-<-") {
+                        if let Ok(slot) = slot.parse::<u16>() {
+                            importing.push((slot, addr.to_string(), source_id));
+                        }
+                    }
+                } else if slot_info.contains('-') {
+                    // Range format: "start-end"
+                    let range_parts: Vec<&str> = slot_info.split('-').collect();
                     if range_parts.len() == 2 {
-                        if let (Ok(start), Ok(end)) =
-                            (range_parts[0].parse::<u16>(), range_parts[1].parse::<u16>())
-                        {
-                            self.assign_slots(address.to_string(), SlotRange::new(start, end));
+                        if let (Ok(start), Ok(end)) = (range_parts[0].parse::<u16>(), range_parts[1].parse::<u16>()) {
+                            ranges.push((start, end));
                         }
                     }
-                } else if let Ok(slot) = slot_spec.parse::<u16>() {
+                } else if let Ok(slot) = slot_info.parse::<u16>() {
                     // Single slot
-                    self.assign_slots(address.to_string(), SlotRange::new(slot, slot));
+                    ranges.push((slot, slot));
                 }
             }
+
+            if !ranges.is_empty() {
+                slot_ranges.insert(addr.to_string(), ranges);
+            }
         }
 
-        Ok(())
+        slot_mapping.update_slot_mapping(slot_ranges);
+        slot_mapping.set_replicas(
+            replicas_by_master_id
+                .into_iter()
+                .filter_map(|(master_id, replicas)| {
+                    master_addrs.get(master_id).map(|master| (master.clone(), replicas))
+                })
+                .collect(),
+        );
+        let mut migrations = HashMap::new();
+        for (slot, source, destination_id) in migrating {
+            if let Some(destination) = master_addrs.get(destination_id) {
+                let destination = destination.clone();
+                migrations.insert(slot, SlotMigration { source, destination });
+            }
+        }
+        for (slot, destination, source_id) in importing {
+            if let Some(source) = master_addrs.get(source_id) {
+                let source = source.clone();
+                migrations.insert(slot, SlotMigration { source, destination });
+            }
+        }
+        slot_mapping.set_migrations(migrations);
+        slot_mapping
     }
 
-    /// Get all backends that have slots assigned
-    pub fn get_active_backends(&self) -> Vec<String> {
-        self.backend_to_slots.keys().cloned().collect()
+    /// Addresses of the masters serving slots
+    pub fn backends(&self) -> Vec<String> {
+        let mut backends: Vec<String> = self.backend_to_slots.keys().cloned().collect();
+        backends.sort();
+        backends
     }
 
-    /// Find which slots are missing (not assigned to any backend)
-    pub fn get_missing_slots(&self) -> Vec<u16> {
-        let mut missing = Vec::new();
-        for slot in 0..16384u16 {
-            if !self.slot_to_backend.contains_key(&slot) {
-                missing.push(slot);
+    /// Highest config epoch of the masters, 0 when unknown
+    pub fn config_epoch(&self) -> u64 {
+        self.config_epoch
+    }
+
+    /// Whether both mappings assign every slot to the same master
+    pub fn same_slots(&self, other: &SlotMapping) -> bool {
+        self.slot_to_backend == other.slot_to_backend
+    }
+
+    /// Check if all slots are covered
+    pub fn is_complete(&self) -> bool {
+        self.slot_to_backend.len() == 16384
+    }
+
+    /// Number of slots assigned to a master
+    pub fn covered_slots(&self) -> usize {
+        self.slot_to_backend.len()
+    }
+
+    /// Number of slots each master serves
+    pub fn slot_counts(&self) -> HashMap<String, usize> {
+        self.backend_to_slots
+            .iter()
+            .map(|(backend, ranges)| {
+                let slots = ranges
+                    .iter()
+                    .map(|(start, end)| usize::from(end.saturating_sub(*start)) + 1)
+                    .sum();
+                (backend.clone(), slots)
+            })
+            .collect()
+    }
+
+    /// Slot ranges served by a backend
+    pub fn get_slots_for_backend(&self, backend_id: &str) -> &[(u16, u16)] {
+        self.backend_to_slots
+            .get(backend_id)
+            .map(Vec::as_slice)
+            .unwrap_or(&[])
+    }
+
+    /// Unassign every slot of a backend
+    pub fn remove_backend(&mut self, backend_id: &str) {
+        if let Some(ranges) = self.backend_to_slots.remove(backend_id) {
+            for (start, end) in ranges {
+                for slot in start..=end {
+                    self.slot_to_backend.remove(&slot);
+                }
             }
         }
-        missing
+    }
+
+    /// Slots no backend serves, in order
+    pub fn get_missing_slots(&self) -> Vec<u16> {
+        (0..TOTAL_SLOTS as u16)
+            .filter(|slot| !self.slot_to_backend.contains_key(slot))
+            .collect()
+    }
+
+    /// How many slots are served, and by which backends
+    pub fn get_coverage(&self) -> SlotCoverage {
+        let assigned_slots = self.covered_slots();
+        SlotCoverage {
+            assigned_slots,
+            total_slots: TOTAL_SLOTS,
+            coverage_percentage: assigned_slots as f64 / TOTAL_SLOTS as f64 * 100.0,
+            backend_distribution: self.slot_counts(),
+        }
+    }
+
+    /// Replace the replica sets of the masters
+    pub fn set_replicas(&mut self, replicas: HashMap<String, Vec<String>>) {
+        self.replicas = replicas;
+    }
+
+    /// Replica addresses of each master
+    pub fn replicas(&self) -> &HashMap<String, Vec<String>> {
+        &self.replicas
+    }
+
+    /// Addresses of the replicas of every master
+    pub fn replica_addrs(&self) -> impl Iterator<Item = &String> {
+        self.replicas.values().flatten()
+    }
+
+    /// Get the replicas of the master serving a slot
+    pub fn get_replicas_for_slot(&self, slot: u16) -> &[String] {
+        self.slot_to_backend
+            .get(&slot)
+            .and_then(|master| self.replicas.get(master))
+            .map(Vec::as_slice)
+            .unwrap_or(&[])
+    }
+
+    /// Replace the slots being migrated
+    pub fn set_migrations(&mut self, migrations: HashMap<u16, SlotMigration>) {
+        self.migrations = migrations;
+    }
+
+    /// Slots being migrated
+    pub fn migrations(&self) -> &HashMap<u16, SlotMigration> {
+        &self.migrations
+    }
+
+    /// The migration a slot is part of, if it is being resharded
+    pub fn migration_of(&self, slot: u16) -> Option<&SlotMigration> {
+        self.migrations.get(&slot)
+    }
+
+    /// Forget the migration of a slot once it has moved
+    pub fn finish_migration(&mut self, slot: u16) {
+        self.migrations.remove(&slot);
     }
 }
 
 /// Statistics about slot coverage
 #[derive(Debug, Clone)]
 pub struct SlotCoverage {
-    pub assigned_slots: u16,
-    pub total_slots: u16,
+    pub assigned_slots: usize,
+    pub total_slots: usize,
     pub coverage_percentage: f64,
-    pub backend_distribution: HashMap<String, u16>,
-}
-
-/// Error type for slot parsing
-#[derive(Debug, thiserror::Error)]
-pub enum SlotParseError {
-    #[error("Invalid cluster nodes format")]
-    InvalidFormat,
-    #[error("Invalid slot range: {0}")]
-    InvalidRange(String),
+    pub backend_distribution: HashMap<String, usize>,
 }
 
-impl Default for SlotMap {
+impl Default for SlotMapping {
     fn default() -> Self {
         Self::new()
     }
@@ -209,44 +390,40 @@ mod tests {
 
     #[test]
     fn test_slot_assignment() {
-        let mut slot_map = SlotMap::new();
+        let mut mapping = SlotMapping::new();
 
         // Assign slots 0-100 to backend1
-        slot_map.assign_slots("backend1".to_string(), SlotRange::new(0, 100));
+        mapping.assign_slots("backend1", 0, 100);
 
         // Check slot assignment
-        assert_eq!(
-            slot_map.get_backend_for_slot(0),
-            Some(&"backend1".to_string())
-        );
-        assert_eq!(
-            slot_map.get_backend_for_slot(50),
-            Some(&"backend1".to_string())
-        );
-        assert_eq!(
-            slot_map.get_backend_for_slot(100),
-            Some(&"backend1".to_string())
-        );
-        assert_eq!(slot_map.get_backend_for_slot(101), None);
+        assert_eq!(mapping.get_backend_for_slot(0), Some("backend1".to_string()));
+        assert_eq!(mapping.get_backend_for_slot(50), Some("backend1".to_string()));
+        assert_eq!(mapping.get_backend_for_slot(100), Some("backend1".to_string()));
+        assert_eq!(mapping.get_backend_for_slot(101), None);
 
         // Check backend slots
-        let ranges = slot_map.get_slots_for_backend("backend1").unwrap();
-        assert_eq!(ranges.len(), 1);
-        assert_eq!(ranges[0], SlotRange::new(0, 100));
+        assert_eq!(mapping.get_slots_for_backend("backend1"), [(0, 100)]);
+        assert_eq!(mapping.get_missing_slots().len(), 16384 - 101);
+        assert_eq!(mapping.get_missing_slots()[0], 101);
+
+        mapping.remove_backend("backend1");
+        assert_eq!(mapping.get_backend_for_slot(0), None);
+        assert!(mapping.backends().is_empty());
     }
 
     #[test]
     fn test_slot_coverage() {
-        let mut slot_map = SlotMap::new();
+        let mut mapping = SlotMapping::new();
 
         // Assign first half to backend1, second half to backend2
-        slot_map.assign_slots("backend1".to_string(), SlotRange::new(0, 8191));
-        slot_map.assign_slots("backend2".to_string(), SlotRange::new(8192, 16383));
+        mapping.assign_slots("backend1", 0, 8191);
+        mapping.assign_slots("backend2", 8192, 16383);
 
-        let coverage = slot_map.get_coverage();
+        let coverage = mapping.get_coverage();
         assert_eq!(coverage.assigned_slots, 16384);
         assert_eq!(coverage.coverage_percentage, 100.0);
-        assert!(slot_map.is_complete());
+        assert!(mapping.is_complete());
+        assert!(mapping.get_missing_slots().is_empty());
 
         assert_eq!(coverage.backend_distribution.get("backend1"), Some(&8192));
         assert_eq!(coverage.backend_distribution.get("backend2"), Some(&8192));
@@ -254,8 +431,6 @@ mod tests {
 
     #[test]
     fn test_cluster_nodes_parsing() {
-        let mut slot_map = SlotMap::new();
-
         let cluster_nodes = r#"
 07c37dfeb235213a872192d90877d0cd55635b91 127.0.0.1:30004@31004 slave e7d1eecce10fd6bb5eb35b9f99a514335d9ba9ca 0 1426238317239 4 connected
 67ed2db8d677e59ec4a4cefb06858cf2a1a89fa1 127.0.0.1:30002@31002 master - 0 1426238316232 2 connected 5461-10922
@@ -265,37 +440,104 @@ mod tests {
 e7d1eecce10fd6bb5eb35b9f99a514335d9ba9ca 127.0.0.1:30001@31001 myself,master - 0 0 1 connected 0-5460
 "#;
 
-        slot_map.update_from_cluster_nodes(cluster_nodes).unwrap();
+        let mapping = SlotMapping::from_cluster_nodes(cluster_nodes);
 
         // Verify slot assignments
+        assert_eq!(mapping.get_backend_for_slot(0), Some("127.0.0.1:30001".to_string()));
+        assert_eq!(mapping.get_backend_for_slot(5460), Some("127.0.0.1:30001".to_string()));
+        assert_eq!(mapping.get_backend_for_slot(5461), Some("127.0.0.1:30002".to_string()));
+        assert_eq!(mapping.get_backend_for_slot(10922), Some("127.0.0.1:30002".to_string()));
+        assert_eq!(mapping.get_backend_for_slot(10923), Some("127.0.0.1:30003".to_string()));
+        assert_eq!(mapping.get_backend_for_slot(16383), Some("127.0.0.1:30003".to_string()));
+
+        assert!(mapping.is_complete());
+
+        let coverage = mapping.get_coverage();
+        assert_eq!(coverage.assigned_slots, 16384);
+
+        // Replicas are tracked for their master even when listed before it
+        assert_eq!(mapping.get_replicas_for_slot(0), ["127.0.0.1:30004".to_string()]);
+        let mut replicas: Vec<&String> = mapping.replica_addrs().collect();
+        replicas.sort();
+        assert_eq!(replicas, ["127.0.0.1:30004", "127.0.0.1:30005", "127.0.0.1:30006"]);
+    }
+
+    #[test]
+    fn test_parse_cluster_nodes_output() {
+        let output = "\
+07c37dfeb235213a872192d90877d0cd55635b91 127.0.0.1:7002@17002 master - 0 1426238317239 2 connected 5461-10922
+67ed2db8d677e59ec4a4cefb06858cf2a1a89fa1 127.0.0.1:7001@17001 myself,master - 0 0 1 connected 0-5460
+292f8b365bb7edb5e285caf0b7e6ddc7265d2f4f 127.0.0.1:7003@17003 master - 0 1426238316232 3 connected 10923-16383
+6ec23923021cf3ffec47632106199cb7f496ce01 127.0.0.1:7005@17005 slave 67ed2db8d677e59ec4a4cefb06858cf2a1a89fa1 0 1426238316232 5 connected
+";
+
+        let mapping = SlotMapping::from_cluster_nodes(output);
+        assert!(mapping.is_complete());
+        assert_eq!(mapping.get_backend_for_slot(0), Some("127.0.0.1:7001".to_string()));
+        assert_eq!(mapping.get_backend_for_slot(5461), Some("127.0.0.1:7002".to_string()));
+        assert_eq!(mapping.get_backend_for_slot(16383), Some("127.0.0.1:7003".to_string()));
+        assert_eq!(mapping.config_epoch(), 3);
+
+        // The replica of 7001 is tracked but owns no slots
+        assert_eq!(mapping.get_replicas_for_slot(0), ["127.0.0.1:7005".to_string()]);
+        assert!(mapping.get_replicas_for_slot(5461).is_empty());
+    }
+
+    #[test]
+    fn test_parse_slot_migrations() {
+        let output = "\
+07c37dfeb235213a872192d90877d0cd55635b91 127.0.0.1:7002@17002 master - 0 1426238317239 2 connected 5461-10922
+67ed2db8d677e59ec4a4cefb06858cf2a1a89fa1 127.0.0.1:7001@17001 myself,master - 0 0 1 connected 0-5460 [77->-07c37dfeb235213a872192d90877d0cd55635b91] [6000-<-07c37dfeb235213a872192d90877d0cd55635b91] [90->-0000000000000000000000000000000000000000]
+292f8b365bb7edb5e285caf0b7e6ddc7265d2f4f 127.0.0.1:7003@17003 master - 0 1426238316232 3 connected 10923-16383
+";
+
+        let mapping = SlotMapping::from_cluster_nodes(output);
+        assert!(mapping.is_complete());
+        // Migrating slots stay with their source until they have moved
+        assert_eq!(mapping.get_backend_for_slot(77), Some("127.0.0.1:7001".to_string()));
         assert_eq!(
-            slot_map.get_backend_for_slot(0),
-            Some(&"127.0.0.1:30001".to_string())
-        );
-        assert_eq!(
-            slot_map.get_backend_for_slot(5460),
-            Some(&"127.0.0.1:30001".to_string())
-        );
-        assert_eq!(
-            slot_map.get_backend_for_slot(5461),
-            Some(&"127.0.0.1:30002".to_string())
-        );
-        assert_eq!(
-            slot_map.get_backend_for_slot(10922),
-            Some(&"127.0.0.1:30002".to_string())
-        );
-        assert_eq!(
-            slot_map.get_backend_for_slot(10923),
-            Some(&"127.0.0.1:30003".to_string())
+            mapping.migration_of(77),
+            Some(&SlotMigration {
+                source: "127.0.0.1:7001".to_string(),
+                destination: "127.0.0.1:7002".to_string(),
+            })
         );
         assert_eq!(
-            slot_map.get_backend_for_slot(16383),
-            Some(&"127.0.0.1:30003".to_string())
+            mapping.migration_of(6000),
+            Some(&SlotMigration {
+                source: "127.0.0.1:7002".to_string(),
+                destination: "127.0.0.1:7001".to_string(),
+            })
         );
+        // Unknown nodes and other slots have no migration
+        assert_eq!(mapping.migration_of(90), None);
+        assert_eq!(mapping.migration_of(78), None);
+    }
 
-        assert!(slot_map.is_complete());
-
-        let coverage = slot_map.get_coverage();
-        assert_eq!(coverage.assigned_slots, 16384);
+    #[test]
+    fn test_assign_slots_keeps_other_slots() {
+        let mut mapping = SlotMapping::new();
+        mapping.update_slot_mapping(HashMap::from([
+            ("127.0.0.1:7001".to_string(), vec![(0, 8191)]),
+            ("127.0.0.1:7002".to_string(), vec![(8192, 16383)]),
+        ]));
+
+        mapping.assign_slots("127.0.0.1:7002", 100, 100);
+        assert!(mapping.is_complete());
+        assert_eq!(mapping.get_backend_for_slot(99).as_deref(), Some("127.0.0.1:7001"));
+        assert_eq!(mapping.get_backend_for_slot(100).as_deref(), Some("127.0.0.1:7002"));
+        assert_eq!(mapping.get_backend_for_slot(101).as_deref(), Some("127.0.0.1:7001"));
+        assert_eq!(mapping.get_slots_for_backend("127.0.0.1:7001"), [(0, 99), (101, 8191)]);
+
+        // A master giving up its last slots is no longer listed, and
+        // adjacent ranges are merged
+        mapping.assign_slots("127.0.0.1:7003", 8192, 16383);
+        mapping.assign_slots("127.0.0.1:7003", 8191, 8191);
+        assert_eq!(mapping.backends(), vec!["127.0.0.1:7001", "127.0.0.1:7002", "127.0.0.1:7003"]);
+        assert_eq!(mapping.get_slots_for_backend("127.0.0.1:7003"), [(8191, 16383)]);
+        mapping.assign_slots("127.0.0.1:7001", 100, 100);
+        assert_eq!(mapping.backends(), vec!["127.0.0.1:7001", "127.0.0.1:7003"]);
+        assert_eq!(mapping.get_slots_for_backend("127.0.0.1:7001"), [(0, 8190)]);
+        assert_eq!(mapping.slot_counts().values().sum::<usize>(), 16384);
     }
 }
@@ -0,0 +1,170 @@
+/// Topology snapshots for warm restarts
+///
+/// After a restart the proxy knows no slot until discovery has asked a
+/// seed node for `CLUSTER NODES`, and never learns one while every seed is
+/// down. With `snapshot` set, the slot mapping and the nodes ejected by
+/// health checking are written to a file every `interval_sec`. On startup
+/// a recent enough snapshot is loaded and served from right away, while
+/// discovery runs in the background and replaces it once it succeeds.
+use super::slots::{SlotMapping, SlotMigration};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The cluster topology as last known, written to disk
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TopologySnapshot {
+    /// When the snapshot was taken, in milliseconds since the Unix epoch
+    pub taken_at_ms: u64,
+    /// Slot ranges served by each master
+    pub slots: HashMap<String, Vec<(u16, u16)>>,
+    /// Replica addresses of each master
+    pub replicas: HashMap<String, Vec<String>>,
+    /// Slots being resharded
+    pub migrations: HashMap<u16, SlotMigration>,
+    /// Nodes ejected by health checking
+    pub unhealthy: Vec<String>,
+}
+
+impl TopologySnapshot {
+    /// Snapshot `mapping` now, with the `unhealthy` nodes
+    pub fn capture(mapping: &SlotMapping, unhealthy: Vec<String>) -> Self {
+        Self {
+            taken_at_ms: now_ms(),
+            slots: mapping
+                .backends()
+                .into_iter()
+                .map(|backend| {
+                    let ranges = mapping.get_slots_for_backend(&backend).to_vec();
+                    (backend, ranges)
+                })
+                .collect(),
+            replicas: mapping.replicas().clone(),
+            migrations: mapping.migrations().clone(),
+            unhealthy,
+        }
+    }
+
+    /// The slot mapping the snapshot was taken of
+    pub fn mapping(&self) -> SlotMapping {
+        let mut mapping = SlotMapping::new();
+        mapping.update_slot_mapping(self.slots.clone());
+        mapping.set_replicas(self.replicas.clone());
+        mapping.set_migrations(self.migrations.clone());
+        mapping
+    }
+
+    /// Time elapsed since the snapshot was taken
+    pub fn age(&self) -> Duration {
+        Duration::from_millis(now_ms().saturating_sub(self.taken_at_ms))
+    }
+
+    /// Write the snapshot to `path`, replacing the previous one at once so
+    /// a crash never leaves a truncated file
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let mut tmp = PathBuf::from(path);
+        tmp.as_mut_os_string().push(".tmp");
+        std::fs::write(&tmp, serde_json::to_vec(self)?)?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    /// Read the snapshot at `path`, `None` when there is none, it cannot be
+    /// read or it is older than `max_age`
+    pub fn load(path: &Path, max_age: Option<Duration>) -> Option<Self> {
+        let contents = match std::fs::read(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return None,
+            Err(e) => {
+                log::warn!("Failed to read Redis topology snapshot {}: {e}", path.display());
+                return None;
+            }
+        };
+        let snapshot: Self = match serde_json::from_slice(&contents) {
+            Ok(snapshot) => snapshot,
+            Err(e) => {
+                log::warn!("Ignoring invalid Redis topology snapshot {}: {e}", path.display());
+                return None;
+            }
+        };
+        if max_age.is_some_and(|max_age| snapshot.age() > max_age) {
+            log::info!(
+                "Ignoring Redis topology snapshot {} taken {}s ago",
+                path.display(),
+                snapshot.age().as_secs()
+            );
+            return None;
+        }
+        Some(snapshot)
+    }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("puerta-snapshot-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join("topology.json")
+    }
+
+    fn mapping() -> SlotMapping {
+        let mut mapping = SlotMapping::new();
+        mapping.update_slot_mapping(HashMap::from([
+            ("10.0.0.1:7000".to_string(), vec![(0, 8191)]),
+            ("10.0.0.2:7000".to_string(), vec![(8192, 16383)]),
+        ]));
+        mapping.set_replicas(HashMap::from([(
+            "10.0.0.1:7000".to_string(),
+            vec!["10.0.0.3:7000".to_string()],
+        )]));
+        mapping.set_migrations(HashMap::from([(
+            8000,
+            SlotMigration {
+                source: "10.0.0.1:7000".to_string(),
+                destination: "10.0.0.2:7000".to_string(),
+            },
+        )]));
+        mapping
+    }
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let path = snapshot_path("round-trip");
+        assert!(TopologySnapshot::load(&path, None).is_none());
+
+        let snapshot = TopologySnapshot::capture(&mapping(), vec!["10.0.0.2:7000".to_string()]);
+        snapshot.save(&path).unwrap();
+        let loaded = TopologySnapshot::load(&path, Some(Duration::from_secs(60))).unwrap();
+        assert_eq!(loaded, snapshot);
+
+        let restored = loaded.mapping();
+        assert!(restored.is_complete());
+        assert_eq!(restored.get_backend_for_slot(100), Some("10.0.0.1:7000".to_string()));
+        assert_eq!(restored.get_backend_for_slot(9000), Some("10.0.0.2:7000".to_string()));
+        assert_eq!(restored.get_replicas_for_slot(100), ["10.0.0.3:7000".to_string()]);
+        assert_eq!(restored.migration_of(8000).unwrap().destination, "10.0.0.2:7000");
+    }
+
+    #[test]
+    fn test_stale_or_invalid_snapshot_is_ignored() {
+        let path = snapshot_path("stale");
+        let mut snapshot = TopologySnapshot::capture(&mapping(), Vec::new());
+        snapshot.taken_at_ms -= 120_000;
+        snapshot.save(&path).unwrap();
+        assert!(TopologySnapshot::load(&path, Some(Duration::from_secs(60))).is_none());
+        assert!(TopologySnapshot::load(&path, None).is_some());
+
+        std::fs::write(&path, b"{\"taken_at_ms\":").unwrap();
+        assert!(TopologySnapshot::load(&path, None).is_none());
+    }
+}
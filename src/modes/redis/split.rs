@@ -0,0 +1,243 @@
+/// Cross-slot multi-key command splitting
+///
+/// Redis Cluster rejects multi-key commands whose keys hash to different
+/// slots with `CROSSSLOT`. Like rcproxy and predixy, the proxy splits such
+/// commands into one sub-command per slot, sends them concurrently and merges
+/// the replies back into a single reply in the client's original key order.
+use super::resp::{RespEncoder, RespParser, RespValue};
+use super::SlotMapping;
+use bytes::{Bytes, BytesMut};
+use std::collections::BTreeMap;
+
+/// How the replies of the sub-commands are merged
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergeKind {
+    /// `MGET`: array of values in key order
+    Values,
+    /// `MSET`: `+OK` once every sub-command succeeded
+    Ok,
+    /// `DEL`/`UNLINK`/`EXISTS`/`TOUCH`: sum of integer replies
+    Sum,
+}
+
+/// One per-slot sub-command
+#[derive(Debug, Clone)]
+pub struct SubCommand {
+    pub slot: u16,
+    /// RESP encoded sub-command
+    pub raw: Bytes,
+    /// Index of each key in the original command
+    pub positions: Vec<usize>,
+}
+
+/// A multi-key command split by slot
+#[derive(Debug, Clone)]
+pub struct SplitPlan {
+    pub command: String,
+    pub kind: MergeKind,
+    pub readonly: bool,
+    pub key_count: usize,
+    pub sub_commands: Vec<SubCommand>,
+}
+
+impl SplitPlan {
+    /// Split `value` if it is a supported multi-key command whose keys span
+    /// more than one slot
+    ///
+    /// Returns `None` for everything else, including malformed commands, which
+    /// are left for the node to answer.
+    pub fn from_value(value: &RespValue) -> Option<Self> {
+        let RespValue::Array(Some(elements)) = value else {
+            return None;
+        };
+        let mut parts = Vec::with_capacity(elements.len());
+        for element in elements {
+            match element {
+                RespValue::BulkString(Some(data)) => parts.push(data.clone()),
+                _ => return None,
+            }
+        }
+        Self::from_parts(&parts)
+    }
+
+    /// Split a command given as its name and arguments, see `from_value`
+    pub fn from_parts(parts: &[Bytes]) -> Option<Self> {
+        let (name, args) = parts.split_first()?;
+        let command = String::from_utf8_lossy(name).to_uppercase();
+
+        let (kind, readonly, step) = match command.as_str() {
+            "MGET" => (MergeKind::Values, true, 1),
+            "EXISTS" => (MergeKind::Sum, true, 1),
+            "DEL" | "UNLINK" | "TOUCH" => (MergeKind::Sum, false, 1),
+            "MSET" => (MergeKind::Ok, false, 2),
+            _ => return None,
+        };
+        if args.is_empty() || args.len() % step != 0 {
+            return None;
+        }
+
+        // Group the key (and value) arguments by slot, keeping key order
+        let mut groups: BTreeMap<u16, (Vec<Bytes>, Vec<usize>)> = BTreeMap::new();
+        for (position, chunk) in args.chunks(step).enumerate() {
+            let slot = SlotMapping::calculate_slot_bytes(&chunk[0]);
+            let (group_args, positions) = groups.entry(slot).or_default();
+            group_args.extend(chunk.iter().cloned());
+            positions.push(position);
+        }
+        if groups.len() < 2 {
+            return None;
+        }
+
+        let sub_commands = groups
+            .into_iter()
+            .map(|(slot, (group_args, positions))| {
+                let mut elements = Vec::with_capacity(group_args.len() + 1);
+                elements.push(RespValue::BulkString(Some(name.clone())));
+                elements.extend(group_args.into_iter().map(|arg| RespValue::BulkString(Some(arg))));
+                SubCommand {
+                    slot,
+                    raw: RespEncoder::encode(&RespValue::Array(Some(elements))),
+                    positions,
+                }
+            })
+            .collect();
+
+        Some(Self {
+            command,
+            kind,
+            readonly,
+            key_count: args.len() / step,
+            sub_commands,
+        })
+    }
+
+    /// Merge the sub-command replies, given in `sub_commands` order
+    ///
+    /// The first error reply from any node is returned to the client as-is.
+    pub fn merge(&self, replies: &[Bytes]) -> Bytes {
+        let mut parsed = Vec::with_capacity(replies.len());
+        for reply in replies {
+            match RespParser::parse(&mut BytesMut::from(reply.as_ref())) {
+                Ok(Some(RespValue::Error(_))) => return reply.clone(),
+                Ok(Some(value)) => parsed.push(value),
+                _ => return Self::error("invalid reply from cluster node"),
+            }
+        }
+
+        match self.kind {
+            MergeKind::Ok => Bytes::from_static(b"+OK\r\n"),
+            MergeKind::Sum => {
+                let mut total = 0;
+                for value in parsed {
+                    match value {
+                        RespValue::Integer(n) => total += n,
+                        _ => return Self::error("unexpected reply type from cluster node"),
+                    }
+                }
+                RespEncoder::encode(&RespValue::Integer(total))
+            }
+            MergeKind::Values => {
+                let mut values = vec![RespValue::BulkString(None); self.key_count];
+                for (sub_command, value) in self.sub_commands.iter().zip(parsed) {
+                    match value {
+                        RespValue::Array(Some(elements))
+                            if elements.len() == sub_command.positions.len() =>
+                        {
+                            for (position, element) in sub_command.positions.iter().zip(elements) {
+                                values[*position] = element;
+                            }
+                        }
+                        _ => return Self::error("unexpected reply type from cluster node"),
+                    }
+                }
+                RespEncoder::encode(&RespValue::Array(Some(values)))
+            }
+        }
+    }
+
+    fn error(message: &str) -> Bytes {
+        Bytes::from(format!("-ERR {message}\r\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(args: &[&str]) -> RespValue {
+        let (name, rest) = args.split_first().unwrap();
+        RespEncoder::create_command(name, rest)
+    }
+
+    #[test]
+    fn test_single_slot_not_split() {
+        assert!(SplitPlan::from_value(&command(&["MGET", "{user}:a", "{user}:b"])).is_none());
+        assert!(SplitPlan::from_value(&command(&["GET", "a"])).is_none());
+        // Odd number of MSET arguments is left for the node to reject
+        assert!(SplitPlan::from_value(&command(&["MSET", "a", "1", "b"])).is_none());
+    }
+
+    #[test]
+    fn test_split_mget_and_merge_in_order() {
+        let plan = SplitPlan::from_value(&command(&["MGET", "foo", "bar", "{foo}x"])).unwrap();
+        assert_eq!(plan.kind, MergeKind::Values);
+        assert!(plan.readonly);
+        assert_eq!(plan.key_count, 3);
+        assert_eq!(plan.sub_commands.len(), 2);
+
+        let foo_group = plan
+            .sub_commands
+            .iter()
+            .find(|sub| sub.positions == vec![0, 2])
+            .unwrap();
+        assert_eq!(
+            foo_group.raw.as_ref(),
+            b"*3\r\n$4\r\nMGET\r\n$3\r\nfoo\r\n$6\r\n{foo}x\r\n"
+        );
+
+        let replies: Vec<Bytes> = plan
+            .sub_commands
+            .iter()
+            .map(|sub| {
+                if sub.positions == vec![0, 2] {
+                    Bytes::from_static(b"*2\r\n$1\r\n1\r\n$-1\r\n")
+                } else {
+                    Bytes::from_static(b"*1\r\n$1\r\n2\r\n")
+                }
+            })
+            .collect();
+        assert_eq!(
+            plan.merge(&replies).as_ref(),
+            b"*3\r\n$1\r\n1\r\n$1\r\n2\r\n$-1\r\n"
+        );
+    }
+
+    #[test]
+    fn test_split_mset_keeps_values_with_keys() {
+        let plan = SplitPlan::from_value(&command(&["MSET", "foo", "1", "bar", "2"])).unwrap();
+        assert_eq!(plan.kind, MergeKind::Ok);
+        assert_eq!(plan.key_count, 2);
+        for sub in &plan.sub_commands {
+            assert!(sub.raw.starts_with(b"*3\r\n$4\r\nMSET\r\n"));
+        }
+
+        let ok = vec![Bytes::from_static(b"+OK\r\n"); 2];
+        assert_eq!(plan.merge(&ok).as_ref(), b"+OK\r\n");
+
+        let failed = vec![
+            Bytes::from_static(b"+OK\r\n"),
+            Bytes::from_static(b"-OOM command not allowed\r\n"),
+        ];
+        assert_eq!(plan.merge(&failed).as_ref(), b"-OOM command not allowed\r\n");
+    }
+
+    #[test]
+    fn test_split_del_sums_counts() {
+        let plan = SplitPlan::from_value(&command(&["DEL", "foo", "bar"])).unwrap();
+        assert_eq!(plan.kind, MergeKind::Sum);
+        assert!(!plan.readonly);
+
+        let replies = vec![Bytes::from_static(b":1\r\n"), Bytes::from_static(b":0\r\n")];
+        assert_eq!(plan.merge(&replies).as_ref(), b":1\r\n");
+    }
+}
@@ -0,0 +1,361 @@
+/// Per-command latency histograms and a proxy-level slow log
+///
+/// Every command forwarded to the cluster is timed from the moment it is
+/// parsed until its reply is ready for the client. Latencies are bucketed
+/// per command, and commands slower than the configured threshold are kept
+/// in a bounded slow log, like Redis `SLOWLOG` but measured at the proxy.
+use super::RedisCommand;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Upper bounds of the latency buckets in microseconds; a final bucket
+/// counts everything slower
+pub const LATENCY_BUCKETS_US: [u64; 13] = [
+    100, 250, 500, 1_000, 2_500, 5_000, 10_000, 25_000, 50_000, 100_000, 250_000, 500_000,
+    1_000_000,
+];
+
+/// Call counts per latency bucket, the last one unbounded
+pub type LatencyBuckets = [u64; LATENCY_BUCKETS_US.len() + 1];
+
+/// Upper bound of the bucket holding the `quantile` of the calls counted
+/// in `buckets`, or `None` without calls
+///
+/// Calls slower than the last bound are reported at that bound.
+pub fn bucket_percentile(buckets: &LatencyBuckets, quantile: f64) -> Option<u64> {
+    let count: u64 = buckets.iter().sum();
+    if count == 0 {
+        return None;
+    }
+    let rank = ((count as f64 * quantile).ceil() as u64).clamp(1, count);
+    let mut seen = 0;
+    let bucket = buckets
+        .iter()
+        .position(|&calls| {
+            seen += calls;
+            seen >= rank
+        })
+        .unwrap_or(LATENCY_BUCKETS_US.len());
+    Some(LATENCY_BUCKETS_US[bucket.min(LATENCY_BUCKETS_US.len() - 1)])
+}
+
+/// Distinct commands tracked before the rest are counted as `OTHER`
+const MAX_COMMAND_FAMILIES: usize = 256;
+
+/// Arguments kept per slow log entry, as in Redis
+const SLOWLOG_MAX_ARGS: usize = 32;
+
+/// Bytes kept per slow log argument, as in Redis
+const SLOWLOG_MAX_ARG_LEN: usize = 128;
+
+/// Latency histogram of one command
+#[derive(Debug, Clone, Default)]
+struct Histogram {
+    buckets: LatencyBuckets,
+    count: u64,
+    total_us: u64,
+    max_us: u64,
+}
+
+impl Histogram {
+    fn record(&mut self, latency_us: u64) {
+        let bucket = LATENCY_BUCKETS_US
+            .iter()
+            .position(|&bound| latency_us <= bound)
+            .unwrap_or(LATENCY_BUCKETS_US.len());
+        self.buckets[bucket] += 1;
+        self.count += 1;
+        self.total_us += latency_us;
+        self.max_us = self.max_us.max(latency_us);
+    }
+
+    fn to_json(&self) -> Value {
+        let buckets: serde_json::Map<String, Value> = LATENCY_BUCKETS_US
+            .iter()
+            .map(|bound| bound.to_string())
+            .chain(std::iter::once("+inf".to_string()))
+            .zip(self.buckets.iter())
+            .map(|(bound, count)| (bound, json!(count)))
+            .collect();
+        json!({
+            "calls": self.count,
+            "total_us": self.total_us,
+            "avg_us": self.total_us.checked_div(self.count).unwrap_or(0),
+            "max_us": self.max_us,
+            "buckets_us": buckets,
+        })
+    }
+}
+
+/// A command that exceeded the slow log threshold
+#[derive(Debug, Clone, Serialize)]
+pub struct SlowlogEntry {
+    pub id: u64,
+    /// Unix time the command completed, in seconds
+    pub timestamp: u64,
+    pub duration_us: u64,
+    /// Command name followed by its (truncated) arguments
+    pub command: Vec<String>,
+    pub client_addr: String,
+}
+
+struct StatsState {
+    commands: HashMap<String, Histogram>,
+    slowlog: VecDeque<SlowlogEntry>,
+    next_slowlog_id: u64,
+}
+
+/// Command latency statistics shared by all client connections
+pub struct CommandStats {
+    slowlog_threshold: Duration,
+    slowlog_max_len: usize,
+    state: Mutex<StatsState>,
+}
+
+impl CommandStats {
+    /// Keep up to `slowlog_max_len` commands slower than `slowlog_threshold`
+    pub fn new(slowlog_threshold: Duration, slowlog_max_len: usize) -> Self {
+        Self {
+            slowlog_threshold,
+            slowlog_max_len,
+            state: Mutex::new(StatsState {
+                commands: HashMap::new(),
+                slowlog: VecDeque::new(),
+                next_slowlog_id: 0,
+            }),
+        }
+    }
+
+    /// Record the round-trip time of a command
+    pub fn record(&self, command: &RedisCommand, client_addr: &str, elapsed: Duration) {
+        let latency_us = elapsed.as_micros() as u64;
+        let mut state = self.state.lock().unwrap();
+
+        let family = if state.commands.len() < MAX_COMMAND_FAMILIES
+            || state.commands.contains_key(&command.command)
+        {
+            command.command.as_str()
+        } else {
+            "OTHER"
+        };
+        match state.commands.get_mut(family) {
+            Some(histogram) => histogram.record(latency_us),
+            None => {
+                let mut histogram = Histogram::default();
+                histogram.record(latency_us);
+                state.commands.insert(family.to_string(), histogram);
+            }
+        }
+
+        if elapsed < self.slowlog_threshold || self.slowlog_max_len == 0 {
+            return;
+        }
+
+        let id = state.next_slowlog_id;
+        state.next_slowlog_id += 1;
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        state.slowlog.push_front(SlowlogEntry {
+            id,
+            timestamp,
+            duration_us: latency_us,
+            command: Self::slowlog_args(command),
+            client_addr: client_addr.to_string(),
+        });
+        state.slowlog.truncate(self.slowlog_max_len);
+    }
+
+    /// The command and arguments as stored in the slow log
+    fn slowlog_args(command: &RedisCommand) -> Vec<String> {
+        // Past the limit the last slot says how many arguments were dropped
+        let truncated = command.args.len() + 1 > SLOWLOG_MAX_ARGS;
+        let kept = if truncated {
+            SLOWLOG_MAX_ARGS - 2
+        } else {
+            command.args.len()
+        };
+
+        let mut args = vec![command.command.clone()];
+        for arg in &command.args[..kept] {
+            let arg = String::from_utf8_lossy(arg);
+            if arg.len() > SLOWLOG_MAX_ARG_LEN {
+                let mut end = SLOWLOG_MAX_ARG_LEN;
+                while !arg.is_char_boundary(end) {
+                    end -= 1;
+                }
+                args.push(format!("{}... ({} more bytes)", &arg[..end], arg.len() - end));
+            } else {
+                args.push(arg.into_owned());
+            }
+        }
+        if truncated {
+            args.push(format!("... ({} more arguments)", command.args.len() - kept));
+        }
+        args
+    }
+
+    /// The newest `count` slow log entries, newest first
+    pub fn slowlog(&self, count: usize) -> Vec<SlowlogEntry> {
+        let state = self.state.lock().unwrap();
+        state.slowlog.iter().take(count).cloned().collect()
+    }
+
+    /// Number of entries in the slow log
+    pub fn slowlog_len(&self) -> usize {
+        self.state.lock().unwrap().slowlog.len()
+    }
+
+    /// Empty the slow log
+    pub fn reset_slowlog(&self) {
+        self.state.lock().unwrap().slowlog.clear();
+    }
+
+    /// Latency histograms per command as JSON
+    pub fn latency_json(&self) -> Value {
+        let state = self.state.lock().unwrap();
+        let commands: serde_json::Map<String, Value> = state
+            .commands
+            .iter()
+            .map(|(name, histogram)| (name.clone(), histogram.to_json()))
+            .collect();
+        Value::Object(commands)
+    }
+
+    /// Call counts per latency bucket of every command since startup
+    pub fn latency_buckets(&self) -> HashMap<String, LatencyBuckets> {
+        let state = self.state.lock().unwrap();
+        state
+            .commands
+            .iter()
+            .map(|(name, histogram)| (name.clone(), histogram.buckets))
+            .collect()
+    }
+
+    /// Register the `/redis/latency` and `/redis/slowlog` admin endpoints
+    pub fn register_admin_routes(
+        self: &std::sync::Arc<Self>,
+        router: crate::admin::AdminRouter,
+    ) -> crate::admin::AdminRouter {
+        use crate::admin::AdminResponse;
+
+        let latency = std::sync::Arc::clone(self);
+        let slowlog = std::sync::Arc::clone(self);
+        let reset = std::sync::Arc::clone(self);
+        router
+            .route("GET", "/redis/latency", move |_| {
+                let body = latency.latency_json();
+                async move { AdminResponse::ok(body) }
+            })
+            .route("GET", "/redis/slowlog", move |request| {
+                // Like SLOWLOG GET, the newest 10 entries unless asked otherwise
+                let count = request.query_param("count").unwrap_or(10);
+                let body = json!({
+                    "len": slowlog.slowlog_len(),
+                    "entries": slowlog.slowlog(count),
+                });
+                async move { AdminResponse::ok(body) }
+            })
+            .route("POST", "/redis/slowlog/reset", move |_| {
+                reset.reset_slowlog();
+                async { AdminResponse::ok(json!({ "reset": true })) }
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modes::redis::test_support::command;
+
+    #[test]
+    fn test_latency_histograms_per_command() {
+        let stats = CommandStats::new(Duration::from_millis(10), 128);
+        stats.record(&command("GET", &["a"]), "10.0.0.1:1", Duration::from_micros(80));
+        stats.record(&command("GET", &["b"]), "10.0.0.1:1", Duration::from_micros(900));
+        stats.record(&command("SET", &["a", "1"]), "10.0.0.1:1", Duration::from_secs(2));
+
+        let latency = stats.latency_json();
+        assert_eq!(latency["GET"]["calls"], 2);
+        assert_eq!(latency["GET"]["buckets_us"]["100"], 1);
+        assert_eq!(latency["GET"]["buckets_us"]["1000"], 1);
+        assert_eq!(latency["GET"]["max_us"], 900);
+        assert_eq!(latency["SET"]["buckets_us"]["+inf"], 1);
+    }
+
+    #[test]
+    fn test_bucket_percentile() {
+        let stats = CommandStats::new(Duration::from_millis(10), 128);
+        for _ in 0..98 {
+            stats.record(&command("GET", &["a"]), "10.0.0.1:1", Duration::from_micros(80));
+        }
+        stats.record(&command("GET", &["a"]), "10.0.0.1:1", Duration::from_micros(3_000));
+        stats.record(&command("GET", &["a"]), "10.0.0.1:1", Duration::from_secs(2));
+
+        let buckets = stats.latency_buckets()["GET"];
+        assert_eq!(bucket_percentile(&buckets, 0.5), Some(100));
+        assert_eq!(bucket_percentile(&buckets, 0.99), Some(5_000));
+        assert_eq!(bucket_percentile(&buckets, 1.0), Some(1_000_000));
+        assert_eq!(bucket_percentile(&LatencyBuckets::default(), 0.5), None);
+    }
+
+    #[test]
+    fn test_slowlog_threshold_and_length() {
+        let stats = CommandStats::new(Duration::from_millis(10), 2);
+        stats.record(&command("GET", &["fast"]), "10.0.0.1:1", Duration::from_millis(1));
+        for key in ["a", "b", "c"] {
+            stats.record(&command("GET", &[key]), "10.0.0.1:1", Duration::from_millis(20));
+        }
+
+        // Only slow commands are kept, newest first, bounded by the max length
+        let entries = stats.slowlog(10);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].command, vec!["GET", "c"]);
+        assert_eq!(entries[0].id, 2);
+        assert_eq!(entries[1].command, vec!["GET", "b"]);
+        assert_eq!(entries[0].duration_us, 20_000);
+
+        stats.reset_slowlog();
+        assert_eq!(stats.slowlog_len(), 0);
+    }
+
+    #[test]
+    fn test_slowlog_truncates_arguments() {
+        let stats = CommandStats::new(Duration::ZERO, 8);
+        let long_value = "x".repeat(200);
+        let many_args: Vec<&str> = std::iter::once(long_value.as_str())
+            .chain(std::iter::repeat_n("k", 49))
+            .collect();
+        stats.record(&command("MSET", &many_args), "10.0.0.1:1", Duration::from_millis(1));
+
+        let entry = &stats.slowlog(1)[0];
+        assert_eq!(entry.command.len(), SLOWLOG_MAX_ARGS);
+        assert_eq!(entry.command[1], format!("{}... (72 more bytes)", "x".repeat(128)));
+        assert_eq!(entry.command[31], "... (20 more arguments)");
+    }
+
+    #[tokio::test]
+    async fn test_admin_routes() {
+        let stats = std::sync::Arc::new(CommandStats::new(Duration::ZERO, 8));
+        stats.record(&command("GET", &["a"]), "10.0.0.1:1", Duration::from_millis(1));
+        let router = stats.register_admin_routes(crate::admin::AdminRouter::new());
+
+        let request = |method: &str, path: &str| crate::admin::AdminRequest {
+            method: method.to_string(),
+            path: path.to_string(),
+            ..Default::default()
+        };
+        let slowlog = router.handle(request("GET", "/redis/slowlog")).await;
+        assert_eq!(slowlog.body["len"], 1);
+        assert_eq!(slowlog.body["entries"][0]["client_addr"], "10.0.0.1:1");
+
+        router.handle(request("POST", "/redis/slowlog/reset")).await;
+        let latency = router.handle(request("GET", "/redis/latency")).await;
+        assert_eq!(latency.body["GET"]["calls"], 1);
+        assert_eq!(stats.slowlog_len(), 0);
+    }
+}
@@ -0,0 +1,15 @@
+//! Helpers shared by the Redis mode tests
+
+use super::RedisCommand;
+use bytes::Bytes;
+
+/// A command with string arguments and no extracted key
+pub(crate) fn command(name: &str, args: &[&str]) -> RedisCommand {
+    RedisCommand {
+        command: name.to_string(),
+        args: args.iter().map(|arg| Bytes::from(arg.to_string())).collect(),
+        key: None,
+        slot: None,
+        readonly: false,
+    }
+}
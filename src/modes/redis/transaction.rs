@@ -0,0 +1,364 @@
+/// Redis transactions over per-slot routing
+///
+/// A transaction only works on a single connection to the node owning its
+/// keys, while regular commands share pooled connections across nodes. The
+/// proxy therefore queues the commands between `MULTI` and `EXEC` itself,
+/// answering `+QUEUED`, and replays them as one `MULTI` ... `EXEC` pipeline on
+/// one connection. `WATCH` pins a dedicated connection to the watched keys'
+/// node, which the transaction then runs on, until `EXEC`, `DISCARD` or
+/// `UNWATCH`. Every key of a transaction, watched keys included, must hash
+/// to one slot.
+use super::pool::PooledConnection;
+use super::split::SplitPlan;
+use super::{RedisCommand, SlotMapping};
+use bytes::Bytes;
+
+/// Reply to a transaction command whose keys span several slots
+pub const CROSSSLOT_REPLY: &[u8] = b"-CROSSSLOT Keys in request don't hash to the same slot\r\n";
+
+/// Reply to `EXEC` after a command was rejected while queuing
+pub const EXECABORT_REPLY: &[u8] =
+    b"-EXECABORT Transaction discarded because of previous errors.\r\n";
+
+const OK_REPLY: &[u8] = b"+OK\r\n";
+const QUEUED_REPLY: &[u8] = b"+QUEUED\r\n";
+
+/// What the proxy does with a command seen by [`Transaction::intercept`]
+#[derive(Debug)]
+pub enum TransactionStep {
+    /// Answered by the proxy itself
+    Reply(Bytes),
+    /// Send `WATCH` on the pinned connection to the node owning `slot`
+    Watch { raw: Bytes, slot: u16 },
+    /// Run the queued commands as one transaction on the node owning `slot`,
+    /// or any node when no command has a key
+    Exec {
+        commands: Vec<(RedisCommand, Bytes)>,
+        slot: Option<u16>,
+    },
+    /// Let go of the pinned connection, if any, and answer `reply`
+    Release { reply: Bytes },
+}
+
+/// Per-connection transaction state
+#[derive(Default)]
+pub struct Transaction {
+    /// Commands queued since `MULTI`, `None` outside a transaction
+    queued: Option<Vec<(RedisCommand, Bytes)>>,
+    /// Slot all keys seen since `WATCH` or `MULTI` hash to
+    slot: Option<u16>,
+    /// A command was rejected while queuing, so `EXEC` aborts
+    aborted: bool,
+    /// Keys are being watched
+    watching: bool,
+    /// Connection carrying the `WATCH` state
+    pinned: Option<PooledConnection>,
+    /// A `WATCH` failed upstream, so the transaction must not run unwatched
+    watch_failed: bool,
+}
+
+impl Transaction {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether commands are being queued
+    pub fn in_multi(&self) -> bool {
+        self.queued.is_some()
+    }
+
+    /// Handle transaction commands and queue commands sent after `MULTI`
+    ///
+    /// Returns `None` for commands that should be routed as usual.
+    pub fn intercept(
+        &mut self,
+        command: &RedisCommand,
+        split_plan: Option<&SplitPlan>,
+        raw: &Bytes,
+    ) -> Option<TransactionStep> {
+        let in_multi = self.in_multi();
+        let step = match (command.command.as_str(), in_multi) {
+            ("MULTI", false) => {
+                self.queued = Some(Vec::new());
+                TransactionStep::Reply(Bytes::from_static(OK_REPLY))
+            }
+            ("MULTI", true) => Self::error("MULTI calls can not be nested"),
+            ("EXEC", false) => Self::error("EXEC without MULTI"),
+            ("DISCARD", false) => Self::error("DISCARD without MULTI"),
+            ("EXEC", true) => {
+                let commands = self.queued.take().unwrap_or_default();
+                let slot = self.slot;
+                let aborted = self.aborted;
+                self.reset();
+                if aborted {
+                    TransactionStep::Release {
+                        reply: Bytes::from_static(EXECABORT_REPLY),
+                    }
+                } else {
+                    TransactionStep::Exec { commands, slot }
+                }
+            }
+            ("DISCARD", true) => {
+                self.reset();
+                TransactionStep::Release {
+                    reply: Bytes::from_static(OK_REPLY),
+                }
+            }
+            ("WATCH", true) => Self::error("WATCH inside MULTI is not allowed"),
+            ("WATCH", false) => {
+                let Some((first, rest)) = command.args.split_first() else {
+                    return Some(Self::error("wrong number of arguments for 'watch' command"));
+                };
+                let slot = SlotMapping::calculate_slot_bytes(first);
+                if rest
+                    .iter()
+                    .any(|key| SlotMapping::calculate_slot_bytes(key) != slot)
+                    || self.slot.is_some_and(|watched| watched != slot)
+                {
+                    return Some(TransactionStep::Reply(Bytes::from_static(CROSSSLOT_REPLY)));
+                }
+                self.slot = Some(slot);
+                self.watching = true;
+                TransactionStep::Watch {
+                    raw: raw.clone(),
+                    slot,
+                }
+            }
+            ("UNWATCH", false) => {
+                let watching = self.watching;
+                self.reset();
+                if watching {
+                    TransactionStep::Release {
+                        reply: Bytes::from_static(OK_REPLY),
+                    }
+                } else {
+                    TransactionStep::Reply(Bytes::from_static(OK_REPLY))
+                }
+            }
+            (_, true) => self.queue(command, split_plan, raw),
+            (_, false) => return None,
+        };
+        Some(step)
+    }
+
+    fn queue(
+        &mut self,
+        command: &RedisCommand,
+        split_plan: Option<&SplitPlan>,
+        raw: &Bytes,
+    ) -> TransactionStep {
+        let slot = Self::key_slot(command);
+        let conflicting = split_plan.is_some()
+            || matches!((self.slot, slot), (Some(current), Some(slot)) if current != slot);
+        if conflicting {
+            self.aborted = true;
+            return TransactionStep::Reply(Bytes::from_static(CROSSSLOT_REPLY));
+        }
+
+        self.slot = self.slot.or(slot);
+        if let Some(queued) = &mut self.queued {
+            queued.push((command.clone(), raw.clone()));
+        }
+        TransactionStep::Reply(Bytes::from_static(QUEUED_REPLY))
+    }
+
+    /// Slot of a command's keys; multi-key commands left unsplit have all
+    /// their keys in the slot of the first
+    fn key_slot(command: &RedisCommand) -> Option<u16> {
+        match command.command.as_str() {
+            "MGET" | "MSET" | "DEL" | "UNLINK" | "EXISTS" | "TOUCH" => command
+                .args
+                .first()
+                .map(|key| SlotMapping::calculate_slot_bytes(key)),
+            _ => command.slot,
+        }
+    }
+
+    fn error(message: &str) -> TransactionStep {
+        TransactionStep::Reply(Bytes::from(format!("-ERR {message}\r\n")))
+    }
+
+    fn reset(&mut self) {
+        self.queued = None;
+        self.slot = None;
+        self.aborted = false;
+        self.watching = false;
+    }
+
+    /// Abort the open transaction, if any, after a command was refused
+    /// before it could be queued
+    pub fn reject(&mut self) {
+        if self.in_multi() {
+            self.aborted = true;
+        }
+    }
+
+    /// Keep the connection `WATCH` was sent on for the transaction
+    pub fn pin(&mut self, conn: PooledConnection) {
+        self.pinned = Some(conn);
+    }
+
+    /// Take the pinned connection
+    pub fn take_pinned(&mut self) -> Option<PooledConnection> {
+        self.pinned.take()
+    }
+
+    /// Record that `WATCH` failed upstream
+    pub fn fail_watch(&mut self) {
+        self.watch_failed = true;
+    }
+
+    /// Whether a `WATCH` failed since the last `EXEC`, `DISCARD` or
+    /// `UNWATCH`, clearing the flag
+    pub fn take_watch_failed(&mut self) -> bool {
+        std::mem::take(&mut self.watch_failed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modes::redis::test_support::command;
+
+    /// Intercept `name` with `args`, keyed on its first argument
+    fn intercept(
+        transaction: &mut Transaction,
+        name: &str,
+        args: &[&str],
+    ) -> Option<TransactionStep> {
+        let raw = super::super::resp::RespEncoder::encode(
+            &super::super::resp::RespEncoder::create_command(name, args),
+        );
+        let key = args.first().map(|key| key.to_string());
+        let command = RedisCommand {
+            slot: key.as_deref().map(SlotMapping::calculate_slot),
+            key,
+            ..command(name, args)
+        };
+        transaction.intercept(&command, None, &raw)
+    }
+
+    fn reply(step: Option<TransactionStep>) -> Bytes {
+        match step {
+            Some(TransactionStep::Reply(reply)) | Some(TransactionStep::Release { reply }) => reply,
+            other => panic!("Expected a reply, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_queue_and_exec() {
+        let mut transaction = Transaction::new();
+        assert!(intercept(&mut transaction, "GET", &["a"]).is_none());
+
+        assert_eq!(
+            reply(intercept(&mut transaction, "MULTI", &[])).as_ref(),
+            OK_REPLY
+        );
+        assert_eq!(
+            reply(intercept(&mut transaction, "SET", &["{user}:name", "x"])).as_ref(),
+            QUEUED_REPLY
+        );
+        assert_eq!(
+            reply(intercept(&mut transaction, "INCR", &["{user}:visits"])).as_ref(),
+            QUEUED_REPLY
+        );
+        assert_eq!(
+            reply(intercept(&mut transaction, "PING", &[])).as_ref(),
+            QUEUED_REPLY
+        );
+
+        match intercept(&mut transaction, "EXEC", &[]) {
+            Some(TransactionStep::Exec { commands, slot }) => {
+                assert_eq!(commands.len(), 3);
+                assert_eq!(slot, Some(SlotMapping::calculate_slot("user")));
+            }
+            other => panic!("Expected EXEC, got {other:?}"),
+        }
+        assert!(!transaction.in_multi());
+        assert!(intercept(&mut transaction, "GET", &["b"]).is_none());
+    }
+
+    #[test]
+    fn test_cross_slot_aborts_transaction() {
+        let mut transaction = Transaction::new();
+        intercept(&mut transaction, "MULTI", &[]);
+        intercept(&mut transaction, "SET", &["a", "1"]);
+        assert_ne!(
+            SlotMapping::calculate_slot("a"),
+            SlotMapping::calculate_slot("b")
+        );
+        assert_eq!(
+            reply(intercept(&mut transaction, "SET", &["b", "1"])).as_ref(),
+            CROSSSLOT_REPLY
+        );
+        assert_eq!(
+            reply(intercept(&mut transaction, "EXEC", &[])).as_ref(),
+            EXECABORT_REPLY
+        );
+
+        // Watched keys must share the transaction's slot
+        assert!(matches!(
+            intercept(&mut transaction, "WATCH", &["a"]),
+            Some(TransactionStep::Watch { .. })
+        ));
+        intercept(&mut transaction, "MULTI", &[]);
+        assert_eq!(
+            reply(intercept(&mut transaction, "GET", &["b"])).as_ref(),
+            CROSSSLOT_REPLY
+        );
+        assert_eq!(
+            reply(intercept(&mut transaction, "WATCH", &["a"])).as_ref(),
+            b"-ERR WATCH inside MULTI is not allowed\r\n"
+        );
+        assert!(matches!(
+            intercept(&mut transaction, "DISCARD", &[]),
+            Some(TransactionStep::Release { .. })
+        ));
+        assert_eq!(
+            reply(intercept(&mut transaction, "WATCH", &["a", "b"])).as_ref(),
+            CROSSSLOT_REPLY
+        );
+    }
+
+    #[test]
+    fn test_rejected_command_aborts_transaction() {
+        let mut transaction = Transaction::new();
+        // Outside a transaction there is nothing to abort
+        transaction.reject();
+        intercept(&mut transaction, "MULTI", &[]);
+        intercept(&mut transaction, "SET", &["a", "1"]);
+        transaction.reject();
+        assert_eq!(
+            reply(intercept(&mut transaction, "EXEC", &[])).as_ref(),
+            EXECABORT_REPLY
+        );
+    }
+
+    #[test]
+    fn test_misplaced_transaction_commands() {
+        let mut transaction = Transaction::new();
+        assert_eq!(
+            reply(intercept(&mut transaction, "EXEC", &[])).as_ref(),
+            b"-ERR EXEC without MULTI\r\n"
+        );
+        assert_eq!(
+            reply(intercept(&mut transaction, "DISCARD", &[])).as_ref(),
+            b"-ERR DISCARD without MULTI\r\n"
+        );
+        assert_eq!(
+            reply(intercept(&mut transaction, "UNWATCH", &[])).as_ref(),
+            OK_REPLY
+        );
+
+        intercept(&mut transaction, "MULTI", &[]);
+        assert_eq!(
+            reply(intercept(&mut transaction, "MULTI", &[])).as_ref(),
+            b"-ERR MULTI calls can not be nested\r\n"
+        );
+        // A nested MULTI does not abort the transaction
+        assert!(matches!(
+            intercept(&mut transaction, "EXEC", &[]),
+            Some(TransactionStep::Exec { .. })
+        ));
+    }
+}
@@ -0,0 +1,77 @@
+/// OpenTelemetry tracing export
+///
+/// Proxy code records spans with the `tracing` crate: connection acceptance,
+/// backend selection, health checks and Redis redirect handling. When built
+/// with the `telemetry` feature and a `[telemetry]` block is configured, those
+/// spans are exported over OTLP/HTTP. Otherwise no subscriber is installed and
+/// the spans are close to free.
+use crate::config::TelemetryConfig;
+
+/// Keeps the exporter running; pending spans are flushed when dropped
+pub struct TelemetryGuard {
+    #[cfg(feature = "telemetry")]
+    provider: opentelemetry_sdk::trace::SdkTracerProvider,
+}
+
+impl Drop for TelemetryGuard {
+    fn drop(&mut self) {
+        #[cfg(feature = "telemetry")]
+        if let Err(e) = self.provider.shutdown() {
+            log::warn!("Failed to flush telemetry spans: {e}");
+        }
+    }
+}
+
+/// Install the OTLP exporter as the global `tracing` subscriber
+#[cfg(feature = "telemetry")]
+pub fn init(config: &TelemetryConfig) -> Result<TelemetryGuard, Box<dyn std::error::Error>> {
+    use opentelemetry::trace::TracerProvider as _;
+    use opentelemetry::KeyValue;
+    use opentelemetry_otlp::WithExportConfig;
+    use opentelemetry_sdk::trace::{Sampler, SdkTracerProvider};
+    use opentelemetry_sdk::Resource;
+    use tracing_subscriber::layer::SubscriberExt;
+    use tracing_subscriber::util::SubscriberInitExt;
+
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_http()
+        .with_endpoint(&config.otlp_endpoint)
+        .build()?;
+
+    let resource = Resource::builder()
+        .with_service_name(config.service_name.clone())
+        .with_attributes(
+            config
+                .resource_attributes
+                .iter()
+                .map(|(key, value)| KeyValue::new(key.clone(), value.clone())),
+        )
+        .build();
+
+    let provider = SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(
+            config.sample_ratio,
+        ))))
+        .with_resource(resource)
+        .build();
+
+    tracing_subscriber::registry()
+        .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("puerta")))
+        .try_init()?;
+    opentelemetry::global::set_tracer_provider(provider.clone());
+
+    log::info!(
+        "Exporting traces to {} as service {}",
+        config.otlp_endpoint,
+        config.service_name
+    );
+    Ok(TelemetryGuard { provider })
+}
+
+/// Without the `telemetry` feature spans cannot be exported
+#[cfg(not(feature = "telemetry"))]
+pub fn init(_config: &TelemetryConfig) -> Result<TelemetryGuard, Box<dyn std::error::Error>> {
+    log::warn!("[telemetry] is configured but puerta was built without the `telemetry` feature");
+    Ok(TelemetryGuard {})
+}
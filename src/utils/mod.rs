@@ -38,6 +38,26 @@ pub fn extract_hash_tag(key: &str) -> &str {
     key
 }
 
+/// Compare secrets in a time that does not depend on where they differ
+///
+/// Both are hashed first, so the comparison does not stop at the end of
+/// the shorter one either.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    use sha2::{Digest, Sha256};
+    let (a, b) = (Sha256::digest(a), Sha256::digest(b));
+    let diff = a.iter().zip(b.iter()).fold(0u8, |diff, (x, y)| diff | (x ^ y));
+    std::hint::black_box(diff) == 0
+}
+
+/// A single-threaded runtime for a background loop running on a thread of
+/// its own, so each loop does not start a worker per core
+pub fn background_runtime() -> tokio::runtime::Runtime {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .expect("failed to start a background runtime")
+}
+
 /// Parse socket address with error handling
 pub fn parse_socket_addr(addr: &str) -> Result<std::net::SocketAddr, std::net::AddrParseError> {
     addr.parse()
@@ -92,6 +112,14 @@ mod tests {
         assert_eq!(extract_hash_tag("{user1000}.following"), "user1000");
     }
 
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secret2"));
+        assert!(constant_time_eq(b"", b""));
+    }
+
     #[test]
     fn test_format_duration() {
         use std::time::Duration;
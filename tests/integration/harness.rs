@@ -0,0 +1,310 @@
+/// Fixtures and clients shared by the integration tests
+///
+/// `fixtures()` brings up the Redis Cluster and MongoDB sharded cluster of
+/// `docker-compose.yml` once per test run and waits until both are ready.
+/// `Proxy` runs the `puerta` binary against them with a generated
+/// configuration, and the small Redis, MongoDB and admin API clients talk
+/// to it the way applications do.
+use bytes::{Bytes, BytesMut};
+use puerta::config::{AdminConfig, Config, ProxyConfig};
+use puerta::modes::mongodb::bson::{DocumentBuilder, Value};
+use puerta::modes::mongodb::wire::{op_msg_body, MessageFramer};
+use puerta::modes::redis::resp::{RespEncoder, RespParser, RespValue};
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// Redis Cluster nodes of the fixtures, masters and replicas
+pub const REDIS_NODES: [&str; 6] = [
+    "127.0.0.1:7101",
+    "127.0.0.1:7102",
+    "127.0.0.1:7103",
+    "127.0.0.1:7104",
+    "127.0.0.1:7105",
+    "127.0.0.1:7106",
+];
+
+/// Mongos of the fixtures
+pub const MONGOS: [&str; 2] = ["127.0.0.1:27117", "127.0.0.1:27118"];
+
+/// Compose project the fixtures run under
+const PROJECT: &str = "puerta-it";
+
+/// How long the fixtures and proxies get to become ready
+const READY_TIMEOUT: Duration = Duration::from_secs(180);
+
+/// Largest MongoDB reply accepted
+const MAX_REPLY_SIZE: usize = 48 * 1024 * 1024;
+
+fn compose_file() -> String {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/integration/docker-compose.yml")
+        .display()
+        .to_string()
+}
+
+/// Run `docker compose` on the fixtures with `args`
+pub fn compose(args: &[&str]) {
+    let status = Command::new("docker")
+        .args(["compose", "-f", &compose_file(), "-p", PROJECT])
+        .args(args)
+        .status()
+        .expect("failed to run docker compose");
+    assert!(status.success(), "docker compose {args:?} failed: {status}");
+}
+
+/// Start the fixtures, unless `PUERTA_IT_EXTERNAL` says they already run,
+/// and wait until the cluster and the shard are ready
+pub async fn fixtures() {
+    static STARTED: OnceLock<()> = OnceLock::new();
+    STARTED.get_or_init(|| {
+        if std::env::var_os("PUERTA_IT_EXTERNAL").is_none() {
+            compose(&["up", "-d"]);
+        }
+    });
+
+    wait_for("the Redis Cluster", || async {
+        let mut node = RedisClient::connect(REDIS_NODES[0]).await.ok()?;
+        let info = node.command(&["CLUSTER", "INFO"]).await.ok()?;
+        text(&info).contains("cluster_state:ok").then_some(())
+    })
+    .await;
+    for mongos in MONGOS {
+        wait_for("the MongoDB shard", || async move {
+            let mut client = MongoClient::connect(mongos).await.ok()?;
+            let reply = client
+                .command(DocumentBuilder::new().int32("listShards", 1).string("$db", "admin"))
+                .await
+                .ok()?;
+            let body = op_msg_body(&reply)?;
+            let shards = body.get_array("shards")?;
+            shards.iter().next().map(|_| ())
+        })
+        .await;
+    }
+}
+
+/// Hold while a test changes the fixtures or depends on their state,
+/// e.g. migrates a slot or stops a mongos
+pub async fn exclusive() -> tokio::sync::MutexGuard<'static, ()> {
+    static LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+    LOCK.lock().await
+}
+
+/// Poll `probe` until it returns `Some`, panicking after `READY_TIMEOUT`
+pub async fn wait_for<T, F, Fut>(what: &str, mut probe: F) -> T
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Option<T>>,
+{
+    let deadline = Instant::now() + READY_TIMEOUT;
+    loop {
+        if let Some(value) = probe().await {
+            return value;
+        }
+        assert!(Instant::now() < deadline, "timed out waiting for {what}");
+        tokio::time::sleep(Duration::from_millis(500)).await;
+    }
+}
+
+/// A free local port, for the proxy and its admin API
+fn free_port() -> u16 {
+    std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+/// The `puerta` binary serving one proxy, killed when dropped
+pub struct Proxy {
+    pub addr: String,
+    pub admin_addr: String,
+    child: Child,
+    _dir: tempfile::TempDir,
+}
+
+impl Proxy {
+    /// Run puerta with the `[proxy]` section `proxy` and fast health checks,
+    /// and wait until it accepts clients
+    pub async fn start(proxy: &str) -> Self {
+        let dir = tempfile::tempdir().unwrap();
+        let addr = format!("127.0.0.1:{}", free_port());
+        let admin_addr = format!("127.0.0.1:{}", free_port());
+
+        let mut config = Config::default();
+        config.server.listen_addr = addr.clone();
+        config.proxy = toml::from_str::<ProxyConfig>(proxy).expect("invalid [proxy] section");
+        config.health.interval_sec = 2;
+        config.health.timeout_sec = 1;
+        config.health.failure_threshold = 1;
+        config.health.success_threshold = 1;
+        config.admin = Some(AdminConfig {
+            listen_addr: admin_addr.clone(),
+            token: None,
+            token_file: None,
+        });
+        config.validate().expect("invalid proxy configuration");
+        let config_path = dir.path().join("puerta.toml");
+        std::fs::write(&config_path, toml::to_string(&config).unwrap()).unwrap();
+
+        let child = Command::new(env!("CARGO_BIN_EXE_puerta"))
+            .arg("run")
+            .arg("--config")
+            .arg(&config_path)
+            .arg("--pid-file")
+            .arg(dir.path().join("puerta.pid"))
+            .arg("--upgrade-sock")
+            .arg(dir.path().join("upgrade.sock"))
+            .stdout(Stdio::null())
+            .spawn()
+            .expect("failed to start puerta");
+        let proxy = Self {
+            addr,
+            admin_addr,
+            child,
+            _dir: dir,
+        };
+        wait_for("puerta to listen", || async {
+            TcpStream::connect(&proxy.addr).await.ok().map(|_| ())
+        })
+        .await;
+        proxy
+    }
+
+    /// Body of `GET path` on the admin API, for the text endpoints
+    pub async fn admin_get(&self, path: &str) -> String {
+        let mut stream = TcpStream::connect(&self.admin_addr).await.unwrap();
+        let request = format!("GET {path} HTTP/1.1\r\nHost: puerta\r\nConnection: close\r\n\r\n");
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap_or((&response, ""));
+        assert!(head.starts_with("HTTP/1.1 200"), "GET {path} failed: {head}");
+        body.to_string()
+    }
+
+    /// `GET path` on the admin API as JSON
+    pub async fn admin_json(&self, path: &str) -> serde_json::Value {
+        puerta::admin::request(&self.admin_addr, None, "GET", path)
+            .await
+            .unwrap_or_else(|e| panic!("GET {path} failed: {e}"))
+    }
+}
+
+impl Drop for Proxy {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Text of a simple string, bulk string or error reply
+pub fn text(reply: &RespValue) -> String {
+    match reply {
+        RespValue::SimpleString(text) | RespValue::Error(text) => text.clone(),
+        RespValue::BulkString(Some(bytes)) => String::from_utf8_lossy(bytes).into_owned(),
+        other => format!("{other:?}"),
+    }
+}
+
+/// A RESP connection to the proxy or a node
+pub struct RedisClient {
+    stream: TcpStream,
+    buf: BytesMut,
+}
+
+impl RedisClient {
+    pub async fn connect(addr: &str) -> std::io::Result<Self> {
+        Ok(Self {
+            stream: TcpStream::connect(addr).await?,
+            buf: BytesMut::new(),
+        })
+    }
+
+    /// Send one command and read its reply
+    pub async fn command(&mut self, args: &[&str]) -> std::io::Result<RespValue> {
+        let command = RespValue::Array(Some(
+            args.iter()
+                .map(|arg| RespValue::BulkString(Some(Bytes::copy_from_slice(arg.as_bytes()))))
+                .collect(),
+        ));
+        self.stream.write_all(&RespEncoder::encode(&command)).await?;
+        loop {
+            match RespParser::parse(&mut self.buf) {
+                Ok(Some(reply)) => return Ok(reply),
+                Ok(None) => {}
+                Err(e) => return Err(std::io::Error::other(e.to_string())),
+            }
+            if self.stream.read_buf(&mut self.buf).await? == 0 {
+                return Err(std::io::ErrorKind::UnexpectedEof.into());
+            }
+        }
+    }
+}
+
+/// An OP_MSG connection to the proxy or a mongos
+pub struct MongoClient {
+    stream: TcpStream,
+    framer: MessageFramer,
+    request_id: i32,
+}
+
+impl MongoClient {
+    pub async fn connect(addr: &str) -> std::io::Result<Self> {
+        Ok(Self {
+            stream: TcpStream::connect(addr).await?,
+            framer: MessageFramer::new(MAX_REPLY_SIZE),
+            request_id: 0,
+        })
+    }
+
+    /// Address the proxy sees the client connecting from
+    pub fn local_addr(&self) -> String {
+        self.stream.local_addr().unwrap().to_string()
+    }
+
+    /// Send one command and read its reply message
+    pub async fn command(&mut self, body: DocumentBuilder) -> std::io::Result<Bytes> {
+        let body = body.build();
+        self.request_id += 1;
+        let mut message = ((21 + body.len()) as i32).to_le_bytes().to_vec();
+        // Request id, response to and OP_MSG opcode
+        message.extend_from_slice(&self.request_id.to_le_bytes());
+        message.extend_from_slice(&0i32.to_le_bytes());
+        message.extend_from_slice(&2013i32.to_le_bytes());
+        // No flag bits, then the body section
+        message.extend_from_slice(&[0; 5]);
+        message.extend_from_slice(&body);
+        self.stream.write_all(&message).await?;
+
+        let mut chunk = [0u8; 16 * 1024];
+        loop {
+            if let Some((_header, reply)) = self
+                .framer
+                .next_message()
+                .map_err(|e| std::io::Error::other(e.to_string()))?
+            {
+                return Ok(reply);
+            }
+            let n = self.stream.read(&mut chunk).await?;
+            if n == 0 {
+                return Err(std::io::ErrorKind::UnexpectedEof.into());
+            }
+            self.framer.push(&chunk[..n]);
+        }
+    }
+}
+
+/// Whether a MongoDB reply reports `ok: 1`
+pub fn mongo_ok(reply: &[u8]) -> bool {
+    match op_msg_body(reply).and_then(|body| body.get("ok")) {
+        Some(Value::Double(ok)) => ok == 1.0,
+        Some(Value::Int32(ok)) => ok == 1,
+        Some(Value::Int64(ok)) => ok == 1,
+        _ => false,
+    }
+}
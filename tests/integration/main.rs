@@ -0,0 +1,7 @@
+/// End-to-end tests against a real Redis Cluster and MongoDB sharded cluster
+///
+/// Built only with the `integration` feature. The fixtures are started
+/// with docker compose from `docker-compose.yml`; see `tests/README.md`.
+mod harness;
+mod mongodb;
+mod redis;
@@ -0,0 +1,124 @@
+/// MongoDB sharded cluster flows through the proxy: operations reach the
+/// shard, connections of one application stay on one mongos, and mongos
+/// health transitions show up in the admin API
+use crate::harness::{compose, exclusive, fixtures, mongo_ok, wait_for, MongoClient, Proxy, MONGOS};
+use puerta::core::connections::ConnectionsReport;
+use puerta::modes::mongodb::bson::{DocumentBuilder, Value};
+use puerta::modes::mongodb::wire::op_msg_body;
+
+async fn start_proxy() -> Proxy {
+    let mongos: Vec<String> = MONGOS.iter().map(|mongos| format!("\"{mongos}\"")).collect();
+    Proxy::start(&format!(
+        "mode = \"mongodb\"\nmongos_endpoints = [{}]\nsession_affinity = true\nsession_timeout_sec = 300\nclient_identification = \"session_id\"\n",
+        mongos.join(", ")
+    ))
+    .await
+}
+
+/// Connect as application `app_name`, sending the driver handshake
+async fn connect(proxy: &Proxy, app_name: &str) -> MongoClient {
+    let mut client = MongoClient::connect(&proxy.addr).await.unwrap();
+    let application = DocumentBuilder::new().string("name", app_name).build();
+    let hello = DocumentBuilder::new()
+        .int32("hello", 1)
+        .document("client", DocumentBuilder::new().document("application", application).build())
+        .string("$db", "admin");
+    assert!(mongo_ok(&client.command(hello).await.unwrap()));
+    client
+}
+
+#[tokio::test]
+async fn test_operations_reach_the_shard() {
+    fixtures().await;
+    let _guard = exclusive().await;
+    let proxy = start_proxy().await;
+    let mut client = connect(&proxy, "it-operations").await;
+
+    let document = DocumentBuilder::new().string("name", "puerta").build();
+    let insert = DocumentBuilder::new()
+        .string("insert", "it_operations")
+        .element(0x04, "documents", &DocumentBuilder::new().document("0", document).build())
+        .string("$db", "puerta_it");
+    assert!(mongo_ok(&client.command(insert).await.unwrap()));
+
+    let count = DocumentBuilder::new()
+        .string("count", "it_operations")
+        .string("$db", "puerta_it");
+    let reply = client.command(count).await.unwrap();
+    let counted = match op_msg_body(&reply).and_then(|body| body.get("n")) {
+        Some(Value::Int32(n)) => n as i64,
+        Some(Value::Int64(n)) => n,
+        other => panic!("unexpected count reply: {other:?}"),
+    };
+    assert!(counted >= 1);
+}
+
+#[tokio::test]
+async fn test_application_connections_share_a_mongos() {
+    fixtures().await;
+    let _guard = exclusive().await;
+    let proxy = start_proxy().await;
+
+    let mut clients = Vec::new();
+    for _ in 0..4 {
+        clients.push(connect(&proxy, "it-affinity").await);
+    }
+    let ping = || DocumentBuilder::new().int32("ping", 1).string("$db", "admin");
+    for client in &mut clients {
+        assert!(mongo_ok(&client.command(ping()).await.unwrap()));
+    }
+
+    let report: ConnectionsReport =
+        serde_json::from_value(proxy.admin_json("/connections").await).unwrap();
+    let addrs: Vec<String> = clients.iter().map(MongoClient::local_addr).collect();
+    let backends: std::collections::HashSet<String> = report
+        .connections
+        .iter()
+        .filter(|connection| addrs.contains(&connection.client_addr))
+        .map(|connection| connection.backend.clone())
+        .collect();
+    assert_eq!(report.connections.len(), clients.len());
+    assert_eq!(backends.len(), 1, "connections spread over {backends:?}");
+}
+
+#[tokio::test]
+async fn test_health_transitions_follow_a_stopped_mongos() {
+    fixtures().await;
+    let _guard = exclusive().await;
+    let proxy = start_proxy().await;
+
+    let mongos_healthy = |healthy: bool| {
+        let proxy = &proxy;
+        move || async move {
+            let status = puerta::admin::status::fetch(&proxy.admin_addr, None).await.ok()?;
+            let backend = status.instances[0]
+                .backends
+                .iter()
+                .find(|backend| backend.addr == MONGOS[1])?
+                .clone();
+            (backend.healthy == healthy).then_some(())
+        }
+    };
+    wait_for("the mongos to be healthy", mongos_healthy(true)).await;
+
+    compose(&["stop", "mongos-2"]);
+    wait_for("the stopped mongos to turn unhealthy", mongos_healthy(false)).await;
+    // New clients go to the remaining mongos meanwhile
+    let mut client = connect(&proxy, "it-health").await;
+    let ping = DocumentBuilder::new().int32("ping", 1).string("$db", "admin");
+    assert!(mongo_ok(&client.command(ping).await.unwrap()));
+
+    compose(&["start", "mongos-2"]);
+    wait_for("the restarted mongos to turn healthy", mongos_healthy(true)).await;
+
+    let metrics = proxy.admin_get("/metrics").await;
+    for state in ["unhealthy", "healthy"] {
+        assert!(
+            metrics.lines().any(|line| {
+                line.starts_with("puerta_backend_health_transitions_total{mode=\"mongodb\"")
+                    && line.contains(&format!("state=\"{state}\""))
+            }),
+            "no {state} transition in:\n{metrics}"
+        );
+    }
+}
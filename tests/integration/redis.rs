@@ -0,0 +1,155 @@
+/// Redis Cluster flows through the proxy: slot routing, cross-slot
+/// commands and `MOVED` handling while a slot migrates
+use crate::harness::{exclusive, fixtures, text, Proxy, RedisClient, REDIS_NODES};
+use puerta::modes::redis::resp::RespValue;
+
+async fn start_proxy() -> Proxy {
+    let nodes: Vec<String> = REDIS_NODES.iter().map(|node| format!("\"{node}\"")).collect();
+    Proxy::start(&format!(
+        "mode = \"redis\"\ncluster_nodes = [{}]\nslot_refresh_interval_sec = 60\nmax_redirects = 3\nconnection_timeout_ms = 5000\n",
+        nodes.join(", ")
+    ))
+    .await
+}
+
+/// A master of the cluster and the slots it serves
+struct Master {
+    addr: String,
+    id: String,
+    slots: Vec<(i64, i64)>,
+}
+
+/// Masters as reported by `CLUSTER SLOTS`
+async fn masters() -> Vec<Master> {
+    let mut node = RedisClient::connect(REDIS_NODES[0]).await.unwrap();
+    let RespValue::Array(Some(ranges)) = node.command(&["CLUSTER", "SLOTS"]).await.unwrap() else {
+        panic!("CLUSTER SLOTS did not return an array");
+    };
+    let mut masters: Vec<Master> = Vec::new();
+    for range in ranges {
+        let RespValue::Array(Some(range)) = range else {
+            continue;
+        };
+        let (RespValue::Integer(start), RespValue::Integer(end), RespValue::Array(Some(master))) =
+            (&range[0], &range[1], &range[2])
+        else {
+            continue;
+        };
+        let RespValue::Integer(port) = master[1] else {
+            continue;
+        };
+        let addr = format!("{}:{port}", text(&master[0]));
+        let id = text(&master[2]);
+        match masters.iter_mut().find(|known| known.id == id) {
+            Some(known) => known.slots.push((*start, *end)),
+            None => masters.push(Master {
+                addr,
+                id,
+                slots: vec![(*start, *end)],
+            }),
+        }
+    }
+    masters
+}
+
+async fn key_slot(key: &str) -> i64 {
+    let mut node = RedisClient::connect(REDIS_NODES[0]).await.unwrap();
+    match node.command(&["CLUSTER", "KEYSLOT", key]).await.unwrap() {
+        RespValue::Integer(slot) => slot,
+        other => panic!("unexpected CLUSTER KEYSLOT reply: {other:?}"),
+    }
+}
+
+fn owner(masters: &[Master], slot: i64) -> &Master {
+    masters
+        .iter()
+        .find(|master| master.slots.iter().any(|&(start, end)| (start..=end).contains(&slot)))
+        .unwrap_or_else(|| panic!("slot {slot} is not served"))
+}
+
+#[tokio::test]
+async fn test_commands_are_routed_by_slot() {
+    fixtures().await;
+    let _guard = exclusive().await;
+    let proxy = start_proxy().await;
+    let mut client = RedisClient::connect(&proxy.addr).await.unwrap();
+
+    let keys: Vec<String> = (0..100).map(|i| format!("it:route:{i}")).collect();
+    for key in &keys {
+        let reply = client.command(&["SET", key, &format!("value-{key}")]).await.unwrap();
+        assert_eq!(text(&reply), "OK");
+    }
+
+    // Every key lives on the master owning its slot, spread over the masters
+    let masters = masters().await;
+    let mut used = std::collections::HashSet::new();
+    for key in &keys {
+        let master = owner(&masters, key_slot(key).await);
+        let mut node = RedisClient::connect(&master.addr).await.unwrap();
+        assert_eq!(text(&node.command(&["GET", key]).await.unwrap()), format!("value-{key}"));
+        used.insert(master.id.clone());
+    }
+    assert!(used.len() > 1, "all keys landed on one master");
+
+    // Cross-slot commands are split per slot and merged in order
+    let mut args = vec!["MGET"];
+    args.extend(keys[..10].iter().map(String::as_str));
+    let RespValue::Array(Some(values)) = client.command(&args).await.unwrap() else {
+        panic!("MGET did not return an array");
+    };
+    let values: Vec<String> = values.iter().map(text).collect();
+    let expected: Vec<String> = keys[..10].iter().map(|key| format!("value-{key}")).collect();
+    assert_eq!(values, expected);
+}
+
+#[tokio::test]
+async fn test_commands_follow_a_migrated_slot() {
+    fixtures().await;
+    let _guard = exclusive().await;
+    let proxy = start_proxy().await;
+    let mut client = RedisClient::connect(&proxy.addr).await.unwrap();
+
+    let key = "{it:moved}:a";
+    assert_eq!(text(&client.command(&["SET", key, "before"]).await.unwrap()), "OK");
+
+    // Move the slot to another master behind the proxy's back
+    let slot = key_slot(key).await;
+    let masters = masters().await;
+    let source = owner(&masters, slot);
+    let target = masters.iter().find(|master| master.id != source.id).unwrap();
+    let (target_host, target_port) = target.addr.rsplit_once(':').unwrap();
+    let slot = slot.to_string();
+    let mut source_node = RedisClient::connect(&source.addr).await.unwrap();
+    let mut target_node = RedisClient::connect(&target.addr).await.unwrap();
+    let reply = target_node
+        .command(&["CLUSTER", "SETSLOT", &slot, "IMPORTING", &source.id])
+        .await
+        .unwrap();
+    assert_eq!(text(&reply), "OK");
+    let reply = source_node
+        .command(&["CLUSTER", "SETSLOT", &slot, "MIGRATING", &target.id])
+        .await
+        .unwrap();
+    assert_eq!(text(&reply), "OK");
+    let reply = source_node
+        .command(&["MIGRATE", target_host, target_port, "", "0", "5000", "KEYS", key])
+        .await
+        .unwrap();
+    assert_eq!(text(&reply), "OK");
+    // The new owner learns first, as redis-cli --cluster does
+    let others = masters.iter().filter(|master| master.id != source.id && master.id != target.id);
+    for master in [target, source].into_iter().chain(others) {
+        let mut node = RedisClient::connect(&master.addr).await.unwrap();
+        let reply = node
+            .command(&["CLUSTER", "SETSLOT", &slot, "NODE", &target.id])
+            .await
+            .unwrap();
+        assert_eq!(text(&reply), "OK");
+    }
+
+    // The proxy follows the MOVED redirect on the same client connection
+    assert_eq!(text(&client.command(&["GET", key]).await.unwrap()), "before");
+    let reply = client.command(&["SET", "{it:moved}:b", "after"]).await.unwrap();
+    assert_eq!(text(&reply), "OK");
+    assert_eq!(text(&target_node.command(&["GET", "{it:moved}:b"]).await.unwrap()), "after");
+}